@@ -0,0 +1,96 @@
+//! Compares the memory held per record by `Vec<YPBankRecord>` and `CompactRecords`.
+//!
+//! Run with `cargo bench --bench compact_record`.
+
+use parser::{CompactRecords, DescriptionPool, TransactionStatus, TransactionType, YPBankRecord};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const RECORDS: usize = 1_000_000;
+const DISTINCT_DESCRIPTIONS: usize = 100;
+
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn description(index: usize) -> String {
+    format!("ATM WITHDRAWAL #{}", index % DISTINCT_DESCRIPTIONS)
+}
+
+fn record(index: usize, description: impl Into<std::sync::Arc<str>>) -> YPBankRecord {
+    YPBankRecord::new(
+        index as u64,
+        TransactionType::Withdrawal,
+        index as u64 + 1,
+        0,
+        -(index as i64),
+        1633036860000 + index as u64,
+        TransactionStatus::Success,
+        description,
+    )
+}
+
+/// Runs `build` and reports the heap bytes held by its result, per record.
+fn measure<T>(name: &str, build: impl FnOnce() -> T) -> T {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let started = Instant::now();
+    let value = build();
+    let elapsed = started.elapsed();
+    let held = ALLOCATED.load(Ordering::Relaxed) - before;
+
+    println!(
+        "{:<32} {:>6.1} bytes/record {:>8.1?}",
+        name,
+        held as f64 / RECORDS as f64,
+        elapsed
+    );
+    value
+}
+
+fn main() {
+    println!(
+        "{} records, {} distinct descriptions",
+        RECORDS, DISTINCT_DESCRIPTIONS
+    );
+
+    let records = measure("Vec<YPBankRecord>", || {
+        (0..RECORDS)
+            .map(|index| record(index, description(index)))
+            .collect::<Vec<_>>()
+    });
+    drop(records);
+
+    let pool = DescriptionPool::new();
+    let pooled = measure("Vec<YPBankRecord> + pool", || {
+        (0..RECORDS)
+            .map(|index| record(index, pool.intern(&description(index))))
+            .collect::<Vec<_>>()
+    });
+
+    let compact = measure("CompactRecords", || {
+        CompactRecords::try_from(pooled.as_slice()).expect("Should convert")
+    });
+    drop(pooled);
+    drop(pool);
+
+    let restored = measure("CompactRecords -> YPBankRecord", || {
+        Vec::<YPBankRecord>::from(&compact)
+    });
+    assert_eq!(restored.len(), compact.len());
+}
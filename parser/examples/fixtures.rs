@@ -0,0 +1,28 @@
+//! Writes the example files used by the documentation to a directory.
+//!
+//! ```bash
+//! cargo run --example fixtures -- /tmp/ypbank
+//! ```
+
+use parser::fixtures;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    if let Err(err) = std::fs::create_dir_all(&dir)
+        .map_err(Into::into)
+        .and_then(|_| fixtures::write_to(&dir))
+    {
+        eprintln!("Failed to write fixtures: [{}] {err}", err.code());
+        return ExitCode::FAILURE;
+    }
+    for (name, _) in fixtures::FILES {
+        println!("{}", dir.join(name).display());
+    }
+    ExitCode::SUCCESS
+}
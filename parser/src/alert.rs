@@ -0,0 +1,431 @@
+use crate::calendar::format_utc;
+use crate::common::TransactionStatus;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::rule_expr::RuleExpr;
+use crate::spec::push_json_str;
+use crate::stats::Window;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+const USER_AMOUNT: &str = "user_amount";
+
+/// A threshold on the records of a sliding time window.
+///
+/// Rules are written as `<measure> > <limit> in <window>`, where the measure is a status
+/// (the number of records with it), `user_amount` (the sum of absolute amounts a user
+/// sends or receives) or `count(<expression>)` (the number of records matching a
+/// [`RuleExpr`]), e.g. `FAILURE > 10 in 5m`, `user_amount > 100000 in 1h` or
+/// `count(type == TRANSFER && amount > 10000) > 3 in 1h`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertRule {
+    /// More than `limit` records with `status`.
+    StatusCount {
+        status: TransactionStatus,
+        limit: u64,
+        window: Window,
+    },
+    /// A user with absolute amounts summing to more than `limit`. User `0`, the bank side
+    /// of deposits and withdrawals, is not checked.
+    UserAmount { limit: u128, window: Window },
+    /// More than `limit` records matching `expr`.
+    MatchCount {
+        expr: RuleExpr,
+        limit: u64,
+        window: Window,
+    },
+}
+
+impl AlertRule {
+    fn window(&self) -> Window {
+        match self {
+            AlertRule::StatusCount { window, .. }
+            | AlertRule::UserAmount { window, .. }
+            | AlertRule::MatchCount { window, .. } => *window,
+        }
+    }
+
+    fn limit(&self) -> u128 {
+        match self {
+            AlertRule::StatusCount { limit, .. } | AlertRule::MatchCount { limit, .. } => {
+                *limit as u128
+            }
+            AlertRule::UserAmount { limit, .. } => *limit,
+        }
+    }
+}
+
+impl fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertRule::StatusCount {
+                status,
+                limit,
+                window,
+            } => write!(f, "{} > {} in {}", status.as_str(), limit, window),
+            AlertRule::UserAmount { limit, window } => {
+                write!(f, "{} > {} in {}", USER_AMOUNT, limit, window)
+            }
+            AlertRule::MatchCount {
+                expr,
+                limit,
+                window,
+            } => write!(f, "count({}) > {} in {}", expr, limit, window),
+        }
+    }
+}
+
+impl FromStr for AlertRule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::InvalidRow(s.to_string());
+        // Split from the end, as expressions may hold both.
+        let (condition, window) = s.rsplit_once(" in ").ok_or_else(invalid)?;
+        let (measure, limit) = condition.rsplit_once('>').ok_or_else(invalid)?;
+        let (measure, limit) = (measure.trim(), limit.trim());
+        let window = Window::from_str(window)?;
+
+        if let Some(expr) = measure
+            .strip_prefix("count(")
+            .and_then(|measure| measure.strip_suffix(')'))
+        {
+            return Ok(AlertRule::MatchCount {
+                expr: expr.parse()?,
+                limit: limit.parse().map_err(|_| invalid())?,
+                window,
+            });
+        }
+
+        if measure.eq_ignore_ascii_case(USER_AMOUNT) {
+            return Ok(AlertRule::UserAmount {
+                limit: limit.parse().map_err(|_| invalid())?,
+                window,
+            });
+        }
+        Ok(AlertRule::StatusCount {
+            status: TransactionStatus::from_str(measure)?,
+            limit: limit.parse().map_err(|_| invalid())?,
+            window,
+        })
+    }
+}
+
+/// A rule that went over its limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub rule: AlertRule,
+    /// User over the limit of an [`AlertRule::UserAmount`] rule.
+    pub user_id: Option<u64>,
+    /// Count or amount in the window when the limit was passed.
+    pub value: u128,
+    /// Timestamp of the record that passed the limit.
+    pub ts: u64,
+}
+
+impl Alert {
+    /// Renders the alert as a JSON object, e.g.
+    /// `{"rule":"FAILURE > 10 in 5m","user_id":null,"value":11,"ts":1633036860000}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"rule\":");
+        push_json_str(&mut json, &self.rule.to_string());
+        let user_id = self
+            .user_id
+            .map_or("null".to_string(), |user_id| user_id.to_string());
+        json.push_str(&format!(
+            ",\"user_id\":{},\"value\":{},\"ts\":{}}}",
+            user_id, self.value, self.ts
+        ));
+        json
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", format_utc(self.ts, true), self.rule)?;
+        if let Some(user_id) = self.user_id {
+            write!(f, " for user {}", user_id)?;
+        }
+        write!(f, " ({})", self.value)
+    }
+}
+
+/// Sums of one rule over a sliding window, per key.
+#[derive(Debug, Clone, Default)]
+struct Sliding {
+    /// Timestamp, key and value of the records in the window, in arrival order.
+    events: VecDeque<(u64, u64, u128)>,
+    /// Sum of the window and whether the limit has been passed, per key.
+    sums: HashMap<u64, (u128, bool)>,
+}
+
+impl Sliding {
+    /// Adds a value, returning the sum of the key when it just went over `limit`.
+    fn push(&mut self, ts: u64, key: u64, value: u128, limit: u128) -> Option<u128> {
+        self.events.push_back((ts, key, value));
+        let (sum, over) = self.sums.entry(key).or_default();
+        *sum += value;
+        if *sum > limit && !*over {
+            *over = true;
+            return Some(*sum);
+        }
+        None
+    }
+
+    /// Drops the values older than `window` before `now`, re-arming keys back within
+    /// `limit`.
+    fn evict(&mut self, now: u64, window: Window, limit: u128) {
+        while let Some(&(ts, key, value)) = self.events.front()
+            && ts.saturating_add(window.millis()) <= now
+        {
+            self.events.pop_front();
+            if let Some((sum, over)) = self.sums.get_mut(&key) {
+                *sum -= value;
+                if *sum == 0 {
+                    self.sums.remove(&key);
+                } else if *sum <= limit {
+                    *over = false;
+                }
+            }
+        }
+    }
+}
+
+/// Checks [`AlertRule`]s against a record stream, reporting each rule once when it goes
+/// over its limit and again only after it went back within it.
+///
+/// Windows slide with the latest timestamp seen, so records are expected roughly in
+/// timestamp order. Only records within the windows are held. What an alert triggers is
+/// left to the caller.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Alerts, TransactionStatus, TransactionType, YPBankRecord};
+///
+/// let failure = |ts| {
+///     YPBankRecord::new(1, TransactionType::Deposit, 0, 2, 100, ts, TransactionStatus::Failure, "")
+/// };
+///
+/// let mut alerts = Alerts::from_text("FAILURE > 1 in 5m").unwrap();
+/// assert!(alerts.push(&failure(0)).is_empty());
+/// assert_eq!(alerts.push(&failure(60_000))[0].value, 2);
+/// // Still over the limit, not reported again.
+/// assert!(alerts.push(&failure(120_000)).is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Alerts {
+    rules: Vec<(AlertRule, Sliding)>,
+    now: u64,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| (rule, Sliding::default()))
+                .collect(),
+            now: 0,
+        }
+    }
+
+    /// Reads rules, one per line; empty lines and `#` comments are skipped.
+    pub fn from_text(s: &str) -> Result<Self, ParseError> {
+        let rules = s
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty())
+            .map(AlertRule::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(rules))
+    }
+
+    /// Reads rules from a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &AlertRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// Adds a record, returning the rules it took over their limit.
+    pub fn push(&mut self, record: &YPBankRecord) -> Vec<Alert> {
+        self.now = self.now.max(record.ts);
+        let mut alerts = Vec::new();
+        for (rule, sliding) in &mut self.rules {
+            let rule = &*rule;
+            let (window, limit) = (rule.window(), rule.limit());
+            sliding.evict(self.now, window, limit);
+            if record.ts.saturating_add(window.millis()) <= self.now {
+                continue;
+            }
+
+            let mut alert = |user_id: Option<u64>, value: u128| {
+                if let Some(value) = sliding.push(record.ts, user_id.unwrap_or(0), value, limit) {
+                    alerts.push(Alert {
+                        rule: rule.clone(),
+                        user_id,
+                        value,
+                        ts: record.ts,
+                    });
+                }
+            };
+            match rule {
+                AlertRule::StatusCount { status, .. } if record.status == *status => alert(None, 1),
+                AlertRule::StatusCount { .. } => {}
+                AlertRule::MatchCount { expr, .. } if expr.matches(record) => alert(None, 1),
+                AlertRule::MatchCount { .. } => {}
+                AlertRule::UserAmount { .. } => {
+                    for user_id in [record.from_user_id, record.to_user_id] {
+                        if user_id != 0 {
+                            alert(Some(user_id), record.amount.unsigned_abs() as u128);
+                        }
+                    }
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod alert_tests {
+    use super::*;
+    use crate::common::TransactionType;
+
+    fn record(from: u64, to: u64, amount: i64, ts: u64, status: TransactionStatus) -> YPBankRecord {
+        YPBankRecord::new(
+            1,
+            TransactionType::Transfer,
+            from,
+            to,
+            amount,
+            ts,
+            status,
+            "",
+        )
+    }
+
+    #[test]
+    fn test_rule_from_str() {
+        let rule: AlertRule = "failure > 10 in 5m".parse().expect("Should parse");
+        assert_eq!(
+            rule,
+            AlertRule::StatusCount {
+                status: TransactionStatus::Failure,
+                limit: 10,
+                window: Window::minutes(5).expect("Should build"),
+            }
+        );
+        assert_eq!(rule.to_string(), "FAILURE > 10 in 5m");
+        assert_eq!(
+            "user_amount > 100000 in 1h"
+                .parse::<AlertRule>()
+                .map(|rule| rule.to_string()),
+            Ok("user_amount > 100000 in 1h".to_string())
+        );
+        for invalid in [
+            "FAILURE > 10",
+            "FAILURE 10 in 5m",
+            "LOST > 1 in 1m",
+            "FAILURE > -1 in 1m",
+            "count(amount >) > 1 in 1m",
+        ] {
+            assert!(invalid.parse::<AlertRule>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_status_count_rearms() {
+        let mut alerts =
+            Alerts::from_text("# Failures\nFAILURE > 1 in 1m\n\n").expect("Should parse");
+        let mut fired = Vec::new();
+        for (ts, status) in [
+            (0, TransactionStatus::Failure),
+            (10_000, TransactionStatus::Success),
+            (20_000, TransactionStatus::Failure),
+            (30_000, TransactionStatus::Failure),
+            // Only the record at 30s is left in the window, which went back within the limit.
+            (85_000, TransactionStatus::Failure),
+            (200_000, TransactionStatus::Failure),
+            (210_000, TransactionStatus::Failure),
+        ] {
+            fired.extend(
+                alerts
+                    .push(&record(1, 2, 5, ts, status))
+                    .iter()
+                    .map(|alert| (alert.ts, alert.value)),
+            );
+        }
+        assert_eq!(fired, vec![(20_000, 2), (85_000, 2), (210_000, 2)]);
+    }
+
+    #[test]
+    fn test_match_count() {
+        let source = "count(amount > 100 && description == \"in cash\") > 1 in 1m";
+        let mut alerts = Alerts::from_text(source).expect("Should parse");
+        assert_eq!(
+            alerts
+                .rules()
+                .map(|rule| rule.to_string())
+                .collect::<Vec<_>>(),
+            vec![source]
+        );
+        let mut large = record(1, 2, 500, 0, TransactionStatus::Success);
+        large.description = "in cash".into();
+        let small = record(1, 2, 5, 0, TransactionStatus::Success);
+
+        assert!(alerts.push(&large).is_empty());
+        assert!(alerts.push(&small).is_empty());
+        let fired = alerts.push(&large);
+        assert_eq!(
+            fired.iter().map(|alert| alert.value).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_user_amount() {
+        let mut alerts = Alerts::from_text("user_amount > 100 in 1h").expect("Should parse");
+        assert!(
+            alerts
+                .push(&record(1, 2, 60, 0, TransactionStatus::Success))
+                .is_empty()
+        );
+        // A late record older than the window is left out.
+        assert!(
+            alerts
+                .push(&record(0, 3, 1000, 10_000_000, TransactionStatus::Success))
+                .iter()
+                .all(|alert| alert.user_id == Some(3))
+        );
+        assert!(
+            alerts
+                .push(&record(1, 4, 50, 0, TransactionStatus::Success))
+                .is_empty()
+        );
+
+        let alert = alerts.push(&record(5, 3, -1, 10_000_001, TransactionStatus::Success));
+        assert!(alert.is_empty());
+        let alert = alerts.push(&record(5, 6, -101, 10_000_002, TransactionStatus::Success));
+        assert_eq!(
+            alert
+                .iter()
+                .map(|alert| (alert.user_id, alert.value))
+                .collect::<Vec<_>>(),
+            vec![(Some(5), 102), (Some(6), 101)]
+        );
+        assert_eq!(
+            alert[1].to_string(),
+            "1970-01-01T02:46: user_amount > 100 in 1h for user 6 (101)"
+        );
+        assert_eq!(
+            alert[1].to_json(),
+            "{\"rule\":\"user_amount > 100 in 1h\",\"user_id\":6,\"value\":101,\"ts\":10000002}"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::error::ParseError;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Replaces the file at `path` with the output of `write`, so readers never see it half-written.
+///
+/// The output goes to a temporary file in the same directory, is synced to disk and
+/// renamed over `path`. With `backup` set, the previous file is copied there first.
+/// On failure the temporary file is removed and `path` is left as it was.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::Write;
+///
+/// parser::write_atomic("out/report.txt".as_ref(), None, |w| {
+///     w.write_all(b"done\n")?;
+///     Ok(())
+/// })
+/// .unwrap();
+/// ```
+pub fn write_atomic(
+    path: &Path,
+    backup: Option<&Path>,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), ParseError>,
+) -> Result<(), ParseError> {
+    let tmp_path = tmp_path(path)?;
+    let result = write_synced(&tmp_path, write).and_then(|_| {
+        if let Some(backup) = backup
+            && path.exists()
+        {
+            std::fs::copy(path, backup)?;
+        }
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+        return result;
+    }
+
+    sync_dir(path)
+}
+
+fn tmp_path(path: &Path) -> Result<PathBuf, ParseError> {
+    let Some(name) = path.file_name() else {
+        return Err(ParseError::IOError(format!(
+            "{} is not a file path",
+            path.display()
+        )));
+    };
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    Ok(path.with_file_name(tmp_name))
+}
+
+fn write_synced(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), ParseError>,
+) -> Result<(), ParseError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    Ok(())
+}
+
+/// Makes the rename durable; directories cannot be opened for syncing on every platform.
+fn sync_dir(path: &Path) -> Result<(), ParseError> {
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+#[cfg(test)]
+mod write_atomic_tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("atomic_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        dir
+    }
+
+    #[test]
+    fn test_write_atomic_with_backup() {
+        let dir = test_dir("backup");
+        let path = dir.join("records.csv");
+        let backup = dir.join("records.csv.bak");
+        std::fs::write(&path, "old").expect("Should write file");
+
+        write_atomic(&path, Some(&backup), |w| Ok(w.write_all(b"new")?)).expect("Should write");
+
+        let content = std::fs::read_to_string(&path).expect("Should read file");
+        let backup_content = std::fs::read_to_string(&backup).expect("Should read backup");
+        let files = std::fs::read_dir(&dir).expect("Should list dir").count();
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+        assert_eq!((content.as_str(), backup_content.as_str()), ("new", "old"));
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn test_write_atomic_failure_keeps_file() {
+        let dir = test_dir("failure");
+        let path = dir.join("records.csv");
+        std::fs::write(&path, "old").expect("Should write file");
+
+        let result = write_atomic(&path, None, |w| {
+            w.write_all(b"partial")?;
+            Err(ParseError::InconsistentRecord("interrupted".to_string()))
+        });
+
+        let content = std::fs::read_to_string(&path).expect("Should read file");
+        let files = std::fs::read_dir(&dir).expect("Should list dir").count();
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+        assert!(result.is_err());
+        assert_eq!((content.as_str(), files), ("old", 1));
+    }
+}
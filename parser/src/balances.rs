@@ -0,0 +1,375 @@
+use crate::common::{
+    TransactionStatus, TransactionType, ValueParser, read_i64_from_bytes, read_u64_from_bytes,
+};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+const CSV_HEADER: &str = "USER_ID,BALANCE,LAST_TS";
+const BIN_MAGIC: [u8; 4] = *b"YPBL";
+
+/// Balance of a user and the timestamp of the last transaction that changed it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub user_id: u64,
+    pub balance: i64,
+    pub last_ts: u64,
+}
+
+/// Format of a balances snapshot.
+///
+/// CSV snapshots start with a `USER_ID,BALANCE,LAST_TS` header. Binary snapshots start
+/// with the magic `YPBL` and a big-endian `u64` count, followed by one 24-byte entry of
+/// big-endian `user_id`, `balance` and `last_ts` per user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceFormat {
+    Csv,
+    Bin,
+}
+
+impl std::str::FromStr for BalanceFormat {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(BalanceFormat::Csv),
+            "binary" => Ok(BalanceFormat::Bin),
+            _ => Err(ParseError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
+/// Balances of all users, built by replaying a transaction log.
+///
+/// Only successful transactions change balances: deposits credit the receiver,
+/// withdrawals debit the sender and transfers do both. User `0`, the bank side of
+/// deposits and withdrawals, has no balance. A snapshot can be extended with later
+/// transactions instead of replaying the whole log again.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{BalanceFormat, Balances, TransactionStatus, TransactionType, YPBankRecord};
+///
+/// let record = |transaction_type, from, to, amount, ts| {
+///     YPBankRecord::new(1, transaction_type, from, to, amount, ts, TransactionStatus::Success, "")
+/// };
+///
+/// let mut balances = Balances::new();
+/// balances.apply(&record(TransactionType::Deposit, 0, 7, 500, 10)).unwrap();
+/// balances.apply(&record(TransactionType::Transfer, 7, 8, 200, 20)).unwrap();
+///
+/// let mut snapshot = Vec::new();
+/// balances.write_to(&mut snapshot, BalanceFormat::Csv).unwrap();
+/// assert_eq!(snapshot, b"USER_ID,BALANCE,LAST_TS\n7,300,20\n8,200,20\n");
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Balances {
+    balances: BTreeMap<u64, Balance>,
+}
+
+impl Balances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a transaction, failing with [`ParseError::Overflow`] when a balance
+    /// does not fit into `i64`.
+    pub fn apply(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        if record.status != TransactionStatus::Success {
+            return Ok(());
+        }
+
+        let (debited, credited) = match record.transaction_type {
+            TransactionType::Deposit => (None, Some(record.to_user_id)),
+            TransactionType::Withdrawal => (Some(record.from_user_id), None),
+            TransactionType::Transfer => (Some(record.from_user_id), Some(record.to_user_id)),
+        };
+        if let Some(user_id) = debited {
+            self.change(user_id, record.ts, |balance| {
+                balance.checked_sub(record.amount)
+            })?;
+        }
+        if let Some(user_id) = credited {
+            self.change(user_id, record.ts, |balance| {
+                balance.checked_add(record.amount)
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, user_id: u64) -> Option<&Balance> {
+        self.balances.get(&user_id)
+    }
+
+    /// Iterates over the balances ordered by user id.
+    pub fn iter(&self) -> impl Iterator<Item = &Balance> {
+        self.balances.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.balances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty()
+    }
+
+    /// Reads a snapshot written by [`Balances::write_to`].
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_read<R: std::io::Read>(
+        r: &mut R,
+        format: BalanceFormat,
+    ) -> Result<Self, ParseError> {
+        let mut buf_reader = std::io::BufReader::new(r);
+        let entries = match format {
+            BalanceFormat::Csv => Self::read_csv(&mut buf_reader)?,
+            BalanceFormat::Bin => Self::read_bin(&mut buf_reader)?,
+        };
+
+        let mut balances = Self::new();
+        for entry in entries {
+            if balances.balances.insert(entry.user_id, entry).is_some() {
+                return Err(ParseError::InconsistentRecord(format!(
+                    "duplicate balance for user id {}",
+                    entry.user_id
+                )));
+            }
+        }
+        Ok(balances)
+    }
+
+    pub fn write_to<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        format: BalanceFormat,
+    ) -> Result<(), ParseError> {
+        match format {
+            BalanceFormat::Csv => {
+                writeln!(w, "{}", CSV_HEADER)?;
+                for entry in self.iter() {
+                    writeln!(w, "{},{},{}", entry.user_id, entry.balance, entry.last_ts)?;
+                }
+            }
+            BalanceFormat::Bin => {
+                w.write_all(&BIN_MAGIC)?;
+                w.write_all(&(self.len() as u64).to_be_bytes())?;
+                for entry in self.iter() {
+                    w.write_all(&entry.user_id.to_be_bytes())?;
+                    w.write_all(&entry.balance.to_be_bytes())?;
+                    w.write_all(&entry.last_ts.to_be_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn change(
+        &mut self,
+        user_id: u64,
+        ts: u64,
+        change: impl FnOnce(i64) -> Option<i64>,
+    ) -> Result<(), ParseError> {
+        if user_id == 0 {
+            return Ok(());
+        }
+        let entry = self.balances.entry(user_id).or_insert(Balance {
+            user_id,
+            ..Balance::default()
+        });
+        entry.balance = change(entry.balance)
+            .ok_or_else(|| ParseError::Overflow(format!("balance of user id {}", user_id)))?;
+        entry.last_ts = entry.last_ts.max(ts);
+        Ok(())
+    }
+
+    fn read_csv<R: BufRead>(r: &mut R) -> Result<Vec<Balance>, ParseError> {
+        let mut lines = r.lines();
+        let header = lines.next().ok_or(ParseError::UnexpectedEOF)??;
+        if header.trim_end() != CSV_HEADER {
+            return Err(ParseError::InvalidCsvHeader(header));
+        }
+
+        let values = ValueParser::default();
+        let mut entries = Vec::new();
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.trim_end().split(',').collect();
+            let [user_id, balance, last_ts] = fields[..] else {
+                return Err(ParseError::InvalidRow(line));
+            };
+            entries.push(Balance {
+                user_id: values.number("USER_ID", user_id)?,
+                balance: values.number("BALANCE", balance)?,
+                last_ts: values.number("LAST_TS", last_ts)?,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn read_bin<R: BufRead>(r: &mut R) -> Result<Vec<Balance>, ParseError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != BIN_MAGIC {
+            return Err(ParseError::InvalidMagic(
+                String::from_utf8_lossy(&magic).to_string(),
+            ));
+        }
+
+        let count = read_u64_from_bytes(r)?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(Balance {
+                user_id: read_u64_from_bytes(r)?,
+                balance: read_i64_from_bytes(r)?,
+                last_ts: read_u64_from_bytes(r)?,
+            });
+        }
+        if !r.fill_buf()?.is_empty() {
+            return Err(ParseError::InconsistentRecord(format!(
+                "data after {} balances",
+                count
+            )));
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod balances_tests {
+    use super::*;
+
+    fn record(
+        transaction_type: TransactionType,
+        from: u64,
+        to: u64,
+        amount: i64,
+        ts: u64,
+        status: TransactionStatus,
+    ) -> YPBankRecord {
+        YPBankRecord::new(1, transaction_type, from, to, amount, ts, status, "")
+    }
+
+    fn balances() -> Balances {
+        let mut balances = Balances::new();
+        for record in [
+            record(
+                TransactionType::Deposit,
+                0,
+                1,
+                1000,
+                10,
+                TransactionStatus::Success,
+            ),
+            record(
+                TransactionType::Transfer,
+                1,
+                2,
+                300,
+                20,
+                TransactionStatus::Success,
+            ),
+            record(
+                TransactionType::Withdrawal,
+                2,
+                0,
+                50,
+                30,
+                TransactionStatus::Success,
+            ),
+            record(
+                TransactionType::Transfer,
+                1,
+                2,
+                999,
+                40,
+                TransactionStatus::Failure,
+            ),
+            record(
+                TransactionType::Deposit,
+                0,
+                3,
+                5,
+                50,
+                TransactionStatus::Pending,
+            ),
+        ] {
+            balances.apply(&record).expect("Should apply");
+        }
+        balances
+    }
+
+    #[test]
+    fn test_apply() {
+        let balances = balances();
+
+        assert_eq!(
+            balances.iter().copied().collect::<Vec<_>>(),
+            vec![
+                Balance {
+                    user_id: 1,
+                    balance: 700,
+                    last_ts: 20
+                },
+                Balance {
+                    user_id: 2,
+                    balance: 250,
+                    last_ts: 30
+                },
+            ]
+        );
+        assert!(balances.get(3).is_none());
+    }
+
+    #[test]
+    fn test_apply_overflow() {
+        let mut balances = Balances::new();
+        let deposit = record(
+            TransactionType::Deposit,
+            0,
+            1,
+            i64::MAX,
+            10,
+            TransactionStatus::Success,
+        );
+        balances.apply(&deposit).expect("Should apply");
+
+        assert_eq!(
+            balances.apply(&deposit),
+            Err(ParseError::Overflow("balance of user id 1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_write_and_read_round_trip() {
+        for format in [BalanceFormat::Csv, BalanceFormat::Bin] {
+            let mut data = Vec::new();
+            balances()
+                .write_to(&mut data, format)
+                .expect("Should write");
+
+            let read = Balances::from_read(&mut data.as_slice(), format).expect("Should read");
+            assert_eq!(read, balances());
+        }
+    }
+
+    #[test]
+    fn test_from_read_errors() {
+        assert!(matches!(
+            Balances::from_read(&mut b"YPBN".as_slice(), BalanceFormat::Bin),
+            Err(ParseError::InvalidMagic(_))
+        ));
+        assert!(matches!(
+            Balances::from_read(&mut b"USER_ID,AMOUNT\n".as_slice(), BalanceFormat::Csv),
+            Err(ParseError::InvalidCsvHeader(_))
+        ));
+        assert!(matches!(
+            Balances::from_read(
+                &mut b"USER_ID,BALANCE,LAST_TS\n1,5,1\n1,6,2\n".as_slice(),
+                BalanceFormat::Csv
+            ),
+            Err(ParseError::InconsistentRecord(_))
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::common::{Format, ValueParser, read_u32_from_bytes, read_u64_from_bytes};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::io::{BufRead, Read};
+
+const BATCH_ID: &str = "BATCH_ID";
+const CREATED_TS: &str = "CREATED_TS";
+const SOURCE_SYSTEM: &str = "SOURCE_SYSTEM";
+const COMMENT_PREFIX: u8 = b'#';
+const BIN_MAGIC: [u8; 4] = *b"YPBH";
+
+/// Metadata exporters attach to a file of records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchMetadata {
+    pub batch_id: String,
+    /// Creation time in milliseconds since the epoch.
+    pub created_ts: u64,
+    pub source_system: String,
+}
+
+/// Records of a file together with the metadata of its batch envelope, if it has one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Batch {
+    pub metadata: Option<BatchMetadata>,
+    pub records: Vec<YPBankRecord>,
+}
+
+/// Fails for formats without a batch envelope encoding.
+pub(crate) fn check_supported(format: Format) -> Result<(), ParseError> {
+    match format {
+        Format::Csv | Format::Txt | Format::Bin => Ok(()),
+        _ => Err(ParseError::UnsupportedOperation(format!(
+            "batch envelopes are not supported for {} files",
+            format.as_str()
+        ))),
+    }
+}
+
+/// Reads the envelope at the start of `r`, leaving `r` at the first byte after it.
+///
+/// CSV and TXT envelopes are leading `# KEY: value` comment lines, binary envelopes a
+/// `YPBH` header. Other leading comments are skipped; a file without an envelope
+/// returns `None`.
+pub(crate) fn read_envelope<R: BufRead>(
+    r: &mut R,
+    format: Format,
+) -> Result<Option<BatchMetadata>, ParseError> {
+    check_supported(format)?;
+    match format {
+        Format::Bin => read_bin_envelope(r),
+        _ => read_comment_envelope(r),
+    }
+}
+
+pub(crate) fn write_envelope<W: std::io::Write>(
+    w: &mut W,
+    format: Format,
+    metadata: &BatchMetadata,
+) -> Result<(), ParseError> {
+    check_supported(format)?;
+    for (field, value) in [
+        (BATCH_ID, &metadata.batch_id),
+        (SOURCE_SYSTEM, &metadata.source_system),
+    ] {
+        if value.contains(['\r', '\n']) {
+            return Err(ParseError::InvalidRawValue {
+                field: field.to_string(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    match format {
+        Format::Bin => {
+            w.write_all(&BIN_MAGIC)?;
+            write_bin_string(w, &metadata.batch_id)?;
+            w.write_all(&metadata.created_ts.to_be_bytes())?;
+            write_bin_string(w, &metadata.source_system)?;
+        }
+        _ => {
+            writeln!(w, "# {}: {}", BATCH_ID, metadata.batch_id)?;
+            writeln!(w, "# {}: {}", CREATED_TS, metadata.created_ts)?;
+            writeln!(w, "# {}: {}", SOURCE_SYSTEM, metadata.source_system)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_comment_envelope<R: BufRead>(r: &mut R) -> Result<Option<BatchMetadata>, ParseError> {
+    let (mut batch_id, mut created_ts, mut source_system) = (None, None, None);
+    let mut line = String::new();
+    while r.fill_buf()?.first() == Some(&COMMENT_PREFIX) {
+        line.clear();
+        r.read_line(&mut line)?;
+        let Some((key, value)) = line[1..].split_once(':') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim().to_uppercase().as_str() {
+            BATCH_ID => batch_id = Some(value),
+            CREATED_TS => created_ts = Some(ValueParser::default().number(CREATED_TS, &value)?),
+            SOURCE_SYSTEM => source_system = Some(value),
+            _ => {}
+        }
+    }
+
+    match (batch_id, created_ts, source_system) {
+        (None, None, None) => Ok(None),
+        (Some(batch_id), Some(created_ts), Some(source_system)) => Ok(Some(BatchMetadata {
+            batch_id,
+            created_ts,
+            source_system,
+        })),
+        (None, ..) => Err(ParseError::FieldNotFound(BATCH_ID.to_string())),
+        (_, None, _) => Err(ParseError::FieldNotFound(CREATED_TS.to_string())),
+        (.., None) => Err(ParseError::FieldNotFound(SOURCE_SYSTEM.to_string())),
+    }
+}
+
+fn read_bin_envelope<R: BufRead>(r: &mut R) -> Result<Option<BatchMetadata>, ParseError> {
+    if !r.fill_buf()?.starts_with(&BIN_MAGIC) {
+        return Ok(None);
+    }
+    r.consume(BIN_MAGIC.len());
+
+    Ok(Some(BatchMetadata {
+        batch_id: read_bin_string(r, BATCH_ID)?,
+        created_ts: read_u64_from_bytes(r)?,
+        source_system: read_bin_string(r, SOURCE_SYSTEM)?,
+    }))
+}
+
+fn read_bin_string<R: BufRead>(r: &mut R, field: &str) -> Result<String, ParseError> {
+    let len = read_u32_from_bytes(r)?;
+    let mut bytes = Vec::new();
+    r.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(ParseError::UnexpectedEOF);
+    }
+    String::from_utf8(bytes).map_err(|err| ParseError::InvalidRawValue {
+        field: field.to_string(),
+        value: String::from_utf8_lossy(err.as_bytes()).to_string(),
+    })
+}
+
+fn write_bin_string<W: std::io::Write>(w: &mut W, value: &str) -> Result<(), ParseError> {
+    let len = u32::try_from(value.len())
+        .map_err(|_| ParseError::Overflow(format!("string of {} bytes", value.len())))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(value.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod batch_tests {
+    use super::*;
+    use crate::CommonParser;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn batch() -> Batch {
+        Batch {
+            metadata: Some(BatchMetadata {
+                batch_id: "2021-10-01/7".to_string(),
+                created_ts: 1633046400000,
+                source_system: "core-banking".to_string(),
+            }),
+            records: vec![YPBankRecord::new(
+                1,
+                TransactionType::Deposit,
+                0,
+                2,
+                100,
+                1633036860000,
+                TransactionStatus::Success,
+                "Salary",
+            )],
+        }
+    }
+
+    #[test]
+    fn test_write_and_read_round_trip() {
+        for format in [Format::Csv, Format::Txt, Format::Bin] {
+            let parser = CommonParser::new(format);
+            let mut data = Vec::new();
+            parser
+                .write_batch(&mut data, &batch())
+                .expect("Should write batch");
+
+            let read = parser
+                .read_batch(&mut data.as_slice())
+                .expect("Should read batch");
+            assert_eq!(read, batch(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_read_exporter_comments() {
+        let data = "# Exported by core-banking\n\
+                    # batch_id: 2021-10-01/7\n\
+                    # created_ts: 1633046400000\n\
+                    # source_system: core-banking\n\
+                    TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                    1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n";
+
+        let read = CommonParser::new(Format::Csv)
+            .read_batch(&mut data.as_bytes())
+            .expect("Should read batch");
+        assert_eq!(read, batch());
+    }
+
+    #[test]
+    fn test_read_without_envelope_and_errors() {
+        let mut data = Vec::new();
+        let parser = CommonParser::new(Format::Bin);
+        parser
+            .write_to(&mut data, &batch().records)
+            .expect("Should write records");
+        assert_eq!(
+            parser.read_batch(&mut data.as_slice()),
+            Ok(Batch {
+                metadata: None,
+                records: batch().records
+            })
+        );
+
+        assert_eq!(
+            CommonParser::new(Format::Txt).read_batch(&mut "# BATCH_ID: 7\n".as_bytes()),
+            Err(ParseError::FieldNotFound(CREATED_TS.to_string()))
+        );
+        assert!(matches!(
+            CommonParser::new(Format::Toml).write_batch(&mut Vec::new(), &batch()),
+            Err(ParseError::UnsupportedOperation(_))
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{BalanceFormat, Balances, CommonParser, Format, ParseError};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(long)]
+    input: PathBuf,
+
+    #[arg(long)]
+    format: String,
+
+    /// Snapshot format (csv, binary)
+    #[arg(long, default_value = "binary")]
+    output_format: String,
+
+    /// Write the snapshot to FILE instead of stdout
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Snapshot in the output format to start from, for inputs holding only later transactions
+    #[arg(long, value_name = "FILE")]
+    base: Option<PathBuf>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+fn compact<R: std::io::Read>(
+    input: R,
+    format: Format,
+    mut balances: Balances,
+) -> Result<Balances, ParseError> {
+    for record in CommonParser::new(format).iter_read(input)? {
+        balances.apply(&record?)?;
+    }
+    Ok(balances)
+}
+
+fn run(args: &Args, format: Format, output_format: BalanceFormat) -> Result<usize, ParseError> {
+    let base = match &args.base {
+        Some(path) => Balances::from_read(&mut File::open(path)?, output_format)?,
+        None => Balances::new(),
+    };
+
+    let balances = compact(File::open(&args.input)?, format, base)?;
+
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    balances.write_to(&mut output, output_format)?;
+    output.flush()?;
+    Ok(balances.len())
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match Format::from_str(&args.format) {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid format {}: [{}] {err}",
+                args.format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+    let output_format = match BalanceFormat::from_str(&args.output_format) {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid output format {}: [{}] {err}",
+                args.output_format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+
+    match run(&args, format, output_format) {
+        Ok(users) => {
+            cli::summary(format!("Wrote balances of {users} users"));
+            Status::Success.into()
+        }
+        Err(err) => {
+            cli::error(format!(
+                "Failed to compact {}: [{}] {err}",
+                args.input.display(),
+                err.code()
+            ));
+            Status::Failure.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TransactionStatus, TransactionType, YPBankRecord};
+
+    fn csv_log(records: &[YPBankRecord]) -> Vec<u8> {
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records.to_vec())
+            .expect("Should write records");
+        data
+    }
+
+    #[test]
+    fn test_compact_from_base() {
+        let record = |id, transaction_type, from, to, amount, ts| {
+            YPBankRecord::new(
+                id,
+                transaction_type,
+                from,
+                to,
+                amount,
+                ts,
+                TransactionStatus::Success,
+                "Payment",
+            )
+        };
+        let january = csv_log(&[record(1, TransactionType::Deposit, 0, 7, 500, 10)]);
+        let february = csv_log(&[record(2, TransactionType::Transfer, 7, 8, 200, 20)]);
+
+        let base =
+            compact(january.as_slice(), Format::Csv, Balances::new()).expect("Should compact");
+        let mut snapshot = Vec::new();
+        base.write_to(&mut snapshot, BalanceFormat::Bin)
+            .expect("Should write snapshot");
+        let base = Balances::from_read(&mut snapshot.as_slice(), BalanceFormat::Bin)
+            .expect("Should read snapshot");
+        let balances = compact(february.as_slice(), Format::Csv, base).expect("Should compact");
+
+        let mut output = Vec::new();
+        balances
+            .write_to(&mut output, BalanceFormat::Csv)
+            .expect("Should write snapshot");
+        assert_eq!(
+            String::from_utf8(output).expect("Should be UTF-8"),
+            "USER_ID,BALANCE,LAST_TS\n7,300,20\n8,200,20\n"
+        );
+    }
+}
@@ -1,6 +1,14 @@
-use clap::Parser;
-use parser::{CommonParser, Format, ParseError};
+use clap::{CommandFactory, Parser, ValueEnum};
+use parser::cli::{self, Status, Verbosity, VerbosityArgs};
+use parser::{
+    ById, CommonParser, Coverage, ErrorCode, Exact, Format, ParseError, RecordEq, Semantic,
+    YPBankRecord, coverage,
+};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::ExitCode;
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -11,11 +19,71 @@ struct Args {
     #[arg(long)]
     format1: String,
 
-    #[arg(long)]
-    file2: String,
+    /// Second file; can be repeated to check that every record of the first file appears
+    /// exactly once across all of them, in any order
+    #[arg(long, required = true)]
+    file2: Vec<String>,
 
+    /// Format of every second file
     #[arg(long)]
     format2: String,
+
+    /// Which fields must match for two records to be identical
+    #[arg(long, value_enum, default_value_t = Equality::Exact)]
+    equality: Equality,
+
+    /// Compare again whenever either file changes, printing only differences not printed
+    /// before, until interrupted
+    #[arg(long)]
+    watch: bool,
+
+    /// Seconds between checks of the files for changes
+    #[arg(long, value_name = "SECONDS", default_value = "2", requires = "watch", value_parser = interval)]
+    interval: Duration,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// Parses a positive number of seconds that fits a `Duration`.
+fn interval(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>().map(Duration::try_from_secs_f64) {
+        Ok(Ok(interval)) if !interval.is_zero() => Ok(interval),
+        _ => Err(format!("{} is not a positive number of seconds", s)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Equality {
+    /// All fields
+    Exact,
+    /// All fields but the description
+    Semantic,
+    /// Only the transaction id
+    ById,
+}
+
+impl Equality {
+    fn record_eq(self) -> &'static dyn RecordEq {
+        match self {
+            Equality::Exact => &Exact,
+            Equality::Semantic => &Semantic,
+            Equality::ById => &ById,
+        }
+    }
+
+    /// Runs [`coverage`] with the record key of this equality.
+    fn coverage(
+        self,
+        baseline: &[YPBankRecord],
+        set: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<Coverage, ParseError> {
+        match self {
+            Equality::Exact => coverage(baseline, set, &Exact),
+            Equality::Semantic => coverage(baseline, set, &Semantic),
+            Equality::ById => coverage(baseline, set, &ById),
+        }
+    }
 }
 
 impl Args {
@@ -28,75 +96,317 @@ impl Args {
     }
 }
 
-fn run_logic<R: std::io::Read>(file1: &mut R, format1: Format, file2: &mut R, format2: Format) {
+fn run_logic<R: std::io::Read>(
+    file1: &mut R,
+    format1: Format,
+    file2: &mut R,
+    format2: Format,
+    eq: &dyn RecordEq,
+) -> Status {
     let parser1 = CommonParser::new(format1);
     let parser2 = CommonParser::new(format2);
     let records1 = match parser1.from_read(file1) {
         Ok(records) => records,
         Err(err) => {
-            println!("Failed to read first file: {err}");
-            return;
+            cli::error(format!("Failed to read first file: [{}] {err}", err.code()));
+            return Status::Failure;
         }
     };
     let records2 = match parser2.from_read(file2) {
         Ok(records) => records,
         Err(err) => {
-            println!("Failed to read second file: {err}");
-            return;
+            cli::error(format!(
+                "Failed to read second file: [{}] {err}",
+                err.code()
+            ));
+            return Status::Failure;
         }
     };
 
     if records1.len() != records2.len() {
-        println!("Files have different number of transactions");
-        return;
+        cli::summary("Files have different number of transactions");
+        return Status::Mismatch;
+    }
+
+    let mut different = (1..)
+        .zip(records1.iter().zip(records2.iter()))
+        .filter(|(_, (record1, record2))| !eq.same(record1, record2));
+    let Some((_, (record1, record2))) = different.next() else {
+        cli::summary("All transactions are identical");
+        return Status::Success;
+    };
+
+    cli::summary("Found different transactions");
+    println!("Record 1: {:?}", record1);
+    println!("Record 2: {:?}", record2);
+    if cli::verbosity() == Verbosity::Verbose {
+        for (position, (record1, record2)) in different {
+            cli::warning(format!(
+                "Transaction {position} differs: {:?} != {:?}",
+                record1, record2
+            ));
+        }
+    }
+    Status::Mismatch
+}
+
+/// Checks that every record of `baseline` appears exactly once across `set`, printing
+/// the records that don't and those that are not in the baseline.
+fn set_logic<R: std::io::Read>(
+    baseline: &mut R,
+    baseline_format: Format,
+    set: Vec<R>,
+    set_format: Format,
+    equality: Equality,
+) -> Status {
+    let baseline = match CommonParser::new(baseline_format).from_read(baseline) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read first file: [{}] {err}", err.code()));
+            return Status::Failure;
+        }
+    };
+    let set_parser = CommonParser::new(set_format);
+    let mut streams = Vec::with_capacity(set.len());
+    for (position, file) in (1..).zip(set) {
+        match set_parser.iter_read(file) {
+            Ok(records) => streams.push(records),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to read second file {position}: [{}] {err}",
+                    err.code()
+                ));
+                return Status::Failure;
+            }
+        }
     }
 
-    for (record1, record2) in records1.iter().zip(records2.iter()) {
-        if record1 != record2 {
-            println!("Found different transactions");
-            println!("Record 1: {:?}", record1);
-            println!("Record 2: {:?}", record2);
-            return;
+    let coverage = match equality.coverage(&baseline, streams.into_iter().flatten()) {
+        Ok(coverage) => coverage,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to read second files: [{}] {err}",
+                err.code()
+            ));
+            return Status::Failure;
         }
+    };
+    if coverage.is_exact() {
+        cli::summary("Every transaction appears exactly once across the files");
+        return Status::Success;
     }
 
-    println!("All transactions are identical");
+    cli::summary(format!(
+        "Found {} missing, {} duplicated and {} unexpected transactions",
+        coverage.missing.len(),
+        coverage.duplicated.len(),
+        coverage.unexpected.len()
+    ));
+    for record in &coverage.missing {
+        println!("Missing: {:?}", record);
+    }
+    for (record, count) in &coverage.duplicated {
+        println!("Found {} times: {:?}", count, record);
+    }
+    for record in &coverage.unexpected {
+        println!("Unexpected: {:?}", record);
+    }
+    Status::Mismatch
 }
 
-fn main() {
+/// A pair of records that differ, at a position counted from 1.
+type Difference = (u64, YPBankRecord, YPBankRecord);
+
+/// Differences of the last run of a watch by position, so that each is printed once.
+///
+/// Positions whose records match again are dropped, so at most one difference per
+/// compared position is kept, never more than the records a run reads.
+#[derive(Default)]
+struct Watch {
+    reported: HashMap<u64, (YPBankRecord, YPBankRecord)>,
+}
+
+impl Watch {
+    /// Compares the records both files have so far, returning the differences not
+    /// returned by the previous run. Records only one file has yet are not compared, as
+    /// the other file may still be catching up.
+    fn update(
+        &mut self,
+        records1: &[YPBankRecord],
+        records2: &[YPBankRecord],
+        eq: &dyn RecordEq,
+    ) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        for (position, (record1, record2)) in (1..).zip(records1.iter().zip(records2)) {
+            if eq.same(record1, record2) {
+                self.reported.remove(&position);
+                continue;
+            }
+            let pair = (record1.clone(), record2.clone());
+            if self.reported.get(&position) != Some(&pair) {
+                differences.push((position, pair.0.clone(), pair.1.clone()));
+                self.reported.insert(position, pair);
+            }
+        }
+        differences
+    }
+}
+
+/// Returns what tells whether the file at `path` changed: its modification time and length.
+fn stamp(path: &str) -> std::io::Result<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+/// Reads the records of a file that may still be written to, up to the first record
+/// that fails, e.g. because it is only partly written.
+fn read_complete(path: &str, format: Format) -> Result<Vec<YPBankRecord>, ParseError> {
+    let mut records = Vec::new();
+    for record in CommonParser::new(format).iter_read(std::fs::File::open(path)?)? {
+        match record {
+            Ok(record) => records.push(record),
+            Err(err) => {
+                cli::warning(format!("Stopped reading {path} at: [{}] {err}", err.code()));
+                break;
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Compares both files every time either changes, printing the new differences, until
+/// a file can no longer be read.
+fn watch(
+    file1: &str,
+    format1: Format,
+    file2: &str,
+    format2: Format,
+    eq: &dyn RecordEq,
+    interval: Duration,
+) -> Status {
+    let mut watch = Watch::default();
+    let mut last_stamps = None;
+    loop {
+        let compared = (|| {
+            let stamps = (stamp(file1)?, stamp(file2)?);
+            if last_stamps == Some(stamps) {
+                return Ok(());
+            }
+            last_stamps = Some(stamps);
+
+            let records1 = read_complete(file1, format1)?;
+            let records2 = read_complete(file2, format2)?;
+            let differences = watch.update(&records1, &records2, eq);
+            let mut stdout = std::io::stdout();
+            for (position, record1, record2) in &differences {
+                writeln!(
+                    stdout,
+                    "Transaction {position} differs: {:?} != {:?}",
+                    record1, record2
+                )?;
+            }
+            stdout.flush()?;
+            cli::summary(format!(
+                "Compared {} transactions, found {} new differences",
+                records1.len().min(records2.len()),
+                differences.len()
+            ));
+            Ok::<_, ParseError>(())
+        })();
+        if let Err(err) = compared {
+            cli::error(format!("Failed to compare files: [{}] {err}", err.code()));
+            return Status::Failure;
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
     let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
 
     let format1 = match args.format1() {
         Ok(format) => format,
         Err(err) => {
-            println!("Invalid format for first file: {err}");
-            return;
+            cli::error(format!(
+                "Invalid format for first file: [{}] {err}",
+                err.code()
+            ));
+            return Status::Usage.into();
         }
     };
     let format2 = match args.format2() {
         Ok(format) => format,
         Err(err) => {
-            println!("Invalid format for second file: {err}");
-            return;
+            cli::error(format!(
+                "Invalid format for second file: [{}] {err}",
+                err.code()
+            ));
+            return Status::Usage.into();
         }
     };
 
+    if args.watch {
+        if args.file2.len() > 1 {
+            cli::error("--watch compares two files; --file2 cannot be repeated");
+            return Status::Usage.into();
+        }
+        return watch(
+            &args.file1,
+            format1,
+            &args.file2[0],
+            format2,
+            args.equality.record_eq(),
+            args.interval,
+        )
+        .into();
+    }
+
     let mut file1 = match std::fs::File::open(&args.file1) {
         Ok(file) => file,
         Err(err) => {
-            println!("Failed to open first file {}: {err}", args.file1);
-            return;
+            cli::error(format!(
+                "Failed to open first file {}: [{}] {err}",
+                args.file1,
+                ErrorCode::Io
+            ));
+            return Status::Failure.into();
         }
     };
-    let mut file2 = match std::fs::File::open(&args.file2) {
-        Ok(file) => file,
-        Err(err) => {
-            println!("Failed to open second file {}: {err}", args.file2);
-            return;
+    let mut files2 = Vec::with_capacity(args.file2.len());
+    for path in &args.file2 {
+        match std::fs::File::open(path) {
+            Ok(file) => files2.push(file),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to open second file {}: [{}] {err}",
+                    path,
+                    ErrorCode::Io
+                ));
+                return Status::Failure.into();
+            }
         }
-    };
+    }
 
-    run_logic(&mut file1, format1, &mut file2, format2);
+    if files2.len() > 1 {
+        return set_logic(&mut file1, format1, files2, format2, args.equality).into();
+    }
+    let mut file2 = files2.swap_remove(0);
+    run_logic(
+        &mut file1,
+        format1,
+        &mut file2,
+        format2,
+        args.equality.record_eq(),
+    )
+    .into()
 }
 
 #[cfg(test)]
@@ -160,7 +470,7 @@ mod tests {
             data.extend_from_slice(&record.ts.to_be_bytes());
             data.push(record.status.as_int());
             data.extend_from_slice(&desc_len.to_be_bytes());
-            data.extend_from_slice(&record.description.as_bytes());
+            data.extend_from_slice(record.description.as_bytes());
         }
         data
     }
@@ -188,7 +498,10 @@ mod tests {
         let mut file1 = Cursor::new(csv_data.clone());
         let mut file2 = Cursor::new(csv_data);
 
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv, &Exact),
+            Status::Success
+        );
     }
 
     #[test]
@@ -206,7 +519,10 @@ mod tests {
         let mut file1 = Cursor::new(csv_data);
         let mut file2 = Cursor::new(txt_data);
 
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Txt);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Txt, &Exact),
+            Status::Success
+        );
     }
 
     #[test]
@@ -221,7 +537,10 @@ mod tests {
         let mut file1 = Cursor::new(csv_data1);
         let mut file2 = Cursor::new(csv_data2);
 
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv, &Exact),
+            Status::Mismatch
+        );
     }
 
     #[test]
@@ -247,7 +566,27 @@ mod tests {
         let mut file1 = Cursor::new(csv_data1);
         let mut file2 = Cursor::new(csv_data2);
 
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv, &Exact),
+            Status::Mismatch
+        );
+    }
+
+    #[test]
+    fn test_semantic_equality_ignores_descriptions() {
+        let record1 = create_test_record(1000000000000000, 100);
+        let mut record2 = create_test_record(1000000000000000, 100);
+        record2.description = "\"Renamed\"".into();
+        assert!(Equality::Semantic.record_eq().same(&record1, &record2));
+        assert!(!Equality::Exact.record_eq().same(&record1, &record2));
+
+        let mut file1 = Cursor::new(create_csv_data(vec![record1]));
+        let mut file2 = Cursor::new(create_csv_data(vec![record2]));
+
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv, &Semantic),
+            Status::Success
+        );
     }
 
     #[test]
@@ -258,7 +597,127 @@ mod tests {
         let mut file1 = Cursor::new(csv_data.clone());
         let mut file2 = Cursor::new(csv_data);
 
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Csv, &Exact),
+            Status::Success
+        );
+    }
+
+    #[test]
+    fn test_baseline_against_set() {
+        let records = (0..6)
+            .map(|id| create_test_record(1000000000000000 + id, 100 * id as i64))
+            .collect::<Vec<_>>();
+        let baseline = create_csv_data(records.clone());
+        let hourly = |records: &[YPBankRecord]| Cursor::new(create_bin_data(records.to_vec()));
+
+        let set = vec![
+            hourly(&records[4..]),
+            hourly(&records[..2]),
+            hourly(&records[2..4]),
+        ];
+        assert_eq!(
+            set_logic(
+                &mut Cursor::new(baseline.clone()),
+                Format::Csv,
+                set,
+                Format::Bin,
+                Equality::Exact
+            ),
+            Status::Success
+        );
+
+        let mut changed = records[3].clone();
+        changed.description = "\"Renamed\"".into();
+        let overlapping = || {
+            vec![
+                hourly(&records[..3]),
+                hourly(&[records[2].clone(), changed.clone()]),
+                hourly(&records[4..]),
+            ]
+        };
+        assert_eq!(
+            set_logic(
+                &mut Cursor::new(baseline.clone()),
+                Format::Csv,
+                overlapping(),
+                Format::Bin,
+                Equality::Exact
+            ),
+            Status::Mismatch
+        );
+        // The changed description still matches semantically, leaving the overlap.
+        let coverage = Equality::Semantic.coverage(
+            &records,
+            overlapping().into_iter().flat_map(|file| {
+                CommonParser::new(Format::Bin)
+                    .iter_read(file)
+                    .expect("Should start")
+            }),
+        );
+        assert_eq!(
+            coverage.map(|coverage| (coverage.missing, coverage.duplicated.len())),
+            Ok((vec![], 1))
+        );
+    }
+
+    #[test]
+    fn test_watch_reports_new_differences() {
+        let records = (0..4)
+            .map(|id| create_test_record(1000000000000000 + id, 100))
+            .collect::<Vec<_>>();
+        let mut changed = records.clone();
+        changed[1].amount = 200;
+        let mut watch = Watch::default();
+
+        // The second file lags behind: only the records both have are compared.
+        let differences = watch.update(&records, &changed[..2], &Exact);
+        assert_eq!(
+            differences,
+            vec![(2, records[1].clone(), changed[1].clone())]
+        );
+        assert!(watch.update(&records, &changed[..3], &Exact).is_empty());
+
+        changed[3].amount = 300;
+        let differences = watch.update(&records, &changed, &Exact);
+        assert_eq!(
+            differences
+                .iter()
+                .map(|(position, _, _)| *position)
+                .collect::<Vec<_>>(),
+            vec![4]
+        );
+        // A difference that changes again is new.
+        changed[1].amount = 250;
+        assert_eq!(watch.update(&records, &changed, &Exact).len(), 1);
+        assert!(watch.update(&records, &changed, &ById).is_empty());
+        assert!(watch.reported.is_empty());
+    }
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(interval("0.5"), Ok(Duration::from_millis(500)));
+        for invalid in ["0", "-1", "1e20", "inf", "NaN", "soon"] {
+            assert!(interval(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_read_complete_stops_at_partial_record() {
+        let path = std::env::temp_dir().join(format!("comparer_{}.bin", std::process::id()));
+        let mut data = create_bin_data(vec![
+            create_test_record(1000000000000000, 100),
+            create_test_record(1000000000000001, 200),
+        ]);
+        data.truncate(data.len() - 5);
+        std::fs::write(&path, &data).expect("Should write file");
+
+        let records = read_complete(path.to_str().expect("Should be UTF-8"), Format::Bin);
+        let stamped = stamp(path.to_str().expect("Should be UTF-8"));
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!(records.map(|records| records.len()), Ok(1));
+        assert_eq!(stamped.map(|(_, len)| len).ok(), Some(data.len() as u64));
     }
 
     #[test]
@@ -279,16 +738,25 @@ mod tests {
         // CSV and TXT
         let mut file1 = Cursor::new(csv_data.clone());
         let mut file2 = Cursor::new(txt_data.clone());
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Txt);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Txt, &Exact),
+            Status::Success
+        );
 
         // TXT and BIN
         let mut file1 = Cursor::new(txt_data);
         let mut file2 = Cursor::new(bin_data.clone());
-        run_logic(&mut file1, Format::Txt, &mut file2, Format::Bin);
+        assert_eq!(
+            run_logic(&mut file1, Format::Txt, &mut file2, Format::Bin, &Exact),
+            Status::Success
+        );
 
         // CSV and BIN
         let mut file1 = Cursor::new(csv_data);
         let mut file2 = Cursor::new(bin_data);
-        run_logic(&mut file1, Format::Csv, &mut file2, Format::Bin);
+        assert_eq!(
+            run_logic(&mut file1, Format::Csv, &mut file2, Format::Bin, &Exact),
+            Status::Success
+        );
     }
 }
@@ -0,0 +1,126 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::conformance::{self, Codec};
+use parser::{CommonParser, Format, ParseError};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Formats checked when none is given: every format records can be read back from.
+const FORMATS: &[Format] = &[
+    Format::Csv,
+    Format::Txt,
+    Format::Bin,
+    Format::Toml,
+    #[cfg(feature = "msgpack")]
+    Format::MsgPack,
+    #[cfg(feature = "cbor")]
+    Format::Cbor,
+];
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Format to check; may be repeated. All readable formats when omitted
+    #[arg(long = "format")]
+    formats: Vec<String>,
+
+    /// Seed of the first round; taken from the clock when omitted
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Rounds of random records per format
+    #[arg(long, default_value_t = 100)]
+    rounds: u64,
+
+    /// Records generated per round
+    #[arg(long, default_value_t = 50)]
+    records: usize,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// Checks `codec` against its golden file, then with random records from `seed` on.
+/// Errors of the fuzzing rounds name the seed to repeat them with.
+fn check(codec: &CommonParser, seed: u64, rounds: u64, records: usize) -> Result<(), String> {
+    match conformance::run(codec) {
+        Ok(()) | Err(ParseError::UnsupportedOperation(_)) => {}
+        Err(err) => return Err(format!("golden file: [{}] {err}", err.code())),
+    }
+    conformance::fuzz(&[codec as &dyn Codec], seed, rounds, records)
+        .map_err(|(seed, err)| format!("seed {}: [{}] {err}", seed, err.code()))
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let formats = match args.formats.is_empty() {
+        true => Ok(FORMATS.to_vec()),
+        false => args
+            .formats
+            .iter()
+            .map(|format| Format::from_str(format))
+            .collect(),
+    };
+    let formats = match formats {
+        Ok(formats) => formats,
+        Err(err) => {
+            cli::error(format!("Invalid format: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    if let Some(format) = formats.iter().find(|format| !format.is_readable()) {
+        cli::error(format!("Format {} cannot be read back", format.as_str()));
+        return Status::Usage.into();
+    }
+
+    let seed = args.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos() as u64)
+    });
+    let mut failed = 0;
+    for format in &formats {
+        match check(&CommonParser::new(*format), seed, args.rounds, args.records) {
+            Ok(()) => println!("{}: ok", format.as_str()),
+            Err(err) => {
+                println!("{}: {}", format.as_str(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    cli::summary(format!(
+        "Checked {} formats with {} rounds of {} records from seed {}, {} failed",
+        formats.len(),
+        args.rounds,
+        args.records,
+        seed,
+        failed
+    ));
+    match failed {
+        0 => Status::Success.into(),
+        _ => Status::Mismatch.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        for format in FORMATS {
+            assert_eq!(check(&CommonParser::new(*format), 0, 5, 20), Ok(()));
+        }
+    }
+}
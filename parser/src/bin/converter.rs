@@ -1,90 +1,1586 @@
-use clap::Parser;
-use parser::{CommonParser, Format, ParseError};
+use clap::{CommandFactory, Parser};
+#[cfg(feature = "http-sink")]
+use parser::HttpSink;
+#[cfg(feature = "wasm-rules")]
+use parser::WasmRule;
+use parser::cli::{self, Status, TimestampArgs, VerbosityArgs};
+use parser::{
+    AccountMap, ColumnStats, CommentLog, CommonParser, CsvHeader, ErrorCode, ExchangeRates, Format,
+    LedgerWriter, ParseError, ParseOptions, PgTable, Pipeline, RecordFilter, RedactionProfile,
+    Redactor, RuleExpr, SchemaMap, SeenIds, Sha256, SortKey, Stage, TimestampUnit, Tombstones,
+    Transformer, TxtComments, UserSide, YPBankRecord, final_states, merge_sorted, write_atomic,
+};
+#[cfg(feature = "http-source")]
+use parser::{HttpBody, HttpSource};
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
+    /// Input file, or http:// or https:// URL with the http-source feature; can be repeated
+    /// together with --merge-sorted-by or --dedup-ids
+    #[arg(long, required = true)]
+    input: Vec<String>,
+
+    /// Header sent with requests for URL inputs, as `Name: value`; can be repeated
+    #[cfg(feature = "http-source")]
+    #[arg(long = "input-header", value_name = "HEADER")]
+    input_headers: Vec<String>,
+
+    /// PEM file with certificates trusted for https:// inputs besides the public roots,
+    /// e.g. of an internal certificate authority
+    #[cfg(feature = "http-source")]
+    #[arg(long, value_name = "FILE")]
+    input_ca_cert: Option<PathBuf>,
+
+    /// Format of the inputs; inferred from the first input's extension or content when omitted
     #[arg(long)]
-    input: String,
+    input_format: Option<String>,
+
+    #[cfg_attr(
+        not(feature = "http-sink"),
+        arg(long, required_unless_present = "ledger")
+    )]
+    #[cfg_attr(
+        feature = "http-sink",
+        arg(long, required_unless_present_any = ["output_url", "ledger"])
+    )]
+    output_format: Option<String>,
+
+    /// File with the CREATE TABLE statement of the table pgcopy output is loaded into,
+    /// whose columns are written in table order
+    #[arg(long, value_name = "FILE", value_parser = |path: &str| PgTable::from_path(path))]
+    pg_table: Option<PgTable>,
+
+    /// Unit of input timestamps (ms, s, or auto for seconds below 10^11 and milliseconds
+    /// above)
+    #[arg(long, default_value = "ms")]
+    ts_unit: String,
+
+    /// Unit of output timestamps (ms, s)
+    #[arg(long, default_value = "ms")]
+    output_ts_unit: String,
 
+    /// Read CSV inputs without a header line, with columns in the YPBank order
     #[arg(long)]
-    input_format: String,
+    no_input_header: bool,
 
+    /// Write CSV output without a header line, e.g. to append it to an existing file
+    #[arg(long, conflicts_with = "output_header")]
+    no_output_header: bool,
+
+    /// Header line written before CSV output instead of the YPBank header
+    #[arg(long, value_name = "HEADER")]
+    output_header: Option<String>,
+
+    /// Handling of TXT comment lines: skip, warn (report them with their line numbers) or
+    /// reject
+    #[arg(long, default_value = "skip")]
+    txt_comments: String,
+
+    /// TOML file with a [rates] table of exchange rates by date; amounts of filtered
+    /// records are converted with the rate of their UTC day before transforms
+    #[arg(long, value_name = "FILE")]
+    exchange_rates: Option<PathBuf>,
+
+    /// TOML file with value transforms applied to every record before redaction
+    #[arg(long, value_name = "FILE")]
+    transform: Option<PathBuf>,
+
+    /// Redaction profile applied to every record (pii, amounts, descriptions); can be repeated
     #[arg(long)]
-    output_format: String,
+    redact: Vec<String>,
+
+    /// TOML file with a [filter] table of conditions records must meet to be kept
+    #[arg(long, value_name = "FILE")]
+    filter: Option<PathBuf>,
+
+    /// Keep only records matching this expression, e.g. "amount > 1000 && status == PENDING";
+    /// can be repeated
+    #[arg(long = "where", value_name = "EXPR", value_parser = |expr: &str| RuleExpr::from_str(expr))]
+    where_exprs: Vec<RuleExpr>,
+
+    /// Refuse input records matching this expression as invalid; can be repeated
+    #[arg(long = "reject-if", value_name = "EXPR", value_parser = |expr: &str| RuleExpr::from_str(expr))]
+    reject_if: Vec<RuleExpr>,
+
+    /// Keep only records of this user id; can be repeated
+    #[arg(long = "user", value_name = "ID")]
+    users: Vec<u64>,
+
+    /// User ids matched by --user (either, from, to)
+    #[arg(long, default_value = "either", requires = "users")]
+    user_side: String,
+
+    /// Merge inputs that are each sorted by this key (id, ts, amount) into one sorted output
+    #[arg(long)]
+    merge_sorted_by: Option<String>,
+
+    /// TOML file mapping foreign CSV columns and values to YPBank fields
+    #[arg(long, value_name = "FILE")]
+    schema_map: Option<PathBuf>,
+
+    /// File with ids of records to leave out, one per line
+    #[arg(long, value_name = "FILE")]
+    tombstones: Option<PathBuf>,
+
+    /// WebAssembly module with a validation rule every input record must pass; can be
+    /// repeated
+    #[cfg(feature = "wasm-rules")]
+    #[arg(long = "wasm-rule", value_name = "FILE")]
+    wasm_rules: Vec<PathBuf>,
+
+    /// Write the output to DIR/<content hash>.<format> instead of stdout, skipping the
+    /// conversion when that file already exists
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+
+    /// Write the output to FILE instead of stdout
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Write the column statistics of the output to FILE.stats, for tools reading them
+    /// instead of the output
+    #[arg(long, requires = "output", conflicts_with_all = ["checkpoint", "ledger"])]
+    stats_sidecar: bool,
+
+    /// Write a JSON manifest of the conversion to FILE once it completes: the inputs and
+    /// outputs with their hashes, the filters applied, record counts and timing
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "output",
+        conflicts_with_all = ["checkpoint", "ledger"]
+    )]
+    manifest: Option<PathBuf>,
+
+    /// Save progress to FILE while converting and resume from it after an interruption
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "output",
+        conflicts_with = "merge_sorted_by"
+    )]
+    checkpoint: Option<PathBuf>,
+
+    /// Number of input records between checkpoints
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1..))]
+    checkpoint_every: u64,
+
+    /// Go on after records that fail to parse, writing them to FILE in the input format
+    /// and their errors to FILE.reasons
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["merge_sorted_by", "checkpoint", "output_dir"]
+    )]
+    collect_errors: Option<PathBuf>,
+
+    /// Keep only the latest state of every transaction id, e.g. its SUCCESS record after
+    /// a PENDING one, before filtering and transforming records
+    #[arg(long, conflicts_with_all = ["checkpoint", "collect_errors"])]
+    final_only: bool,
+
+    /// Keep only the first record of every transaction id across all inputs, which are
+    /// read one after another
+    #[arg(
+        long,
+        conflicts_with_all = ["merge_sorted_by", "checkpoint", "collect_errors", "final_only"]
+    )]
+    dedup_ids: bool,
+
+    /// Number of ids --dedup-ids keeps in memory; further ids are spilled to temporary
+    /// files
+    #[arg(
+        long,
+        value_name = "IDS",
+        default_value_t = 10_000_000,
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "dedup_ids"
+    )]
+    dedup_memory: u64,
+
+    /// Number of threads filtering, transforming and serializing records while the input
+    /// is read; the output keeps the input order
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with_all = ["merge_sorted_by", "checkpoint", "collect_errors", "ledger"]
+    )]
+    threads: u16,
+
+    /// Write double-entry ledger lines as CSV instead of records, booking them to the
+    /// accounts of this TOML account map
+    #[arg(
+        long,
+        value_name = "FILE",
+        value_parser = |path: &str| AccountMap::from_path(path),
+        conflicts_with_all = ["output_format", "output_dir", "checkpoint", "collect_errors", "merge_sorted_by", "dedup_ids"]
+    )]
+    ledger: Option<AccountMap>,
+
+    /// POST the records as JSON arrays to this http:// URL instead of writing them
+    #[cfg(feature = "http-sink")]
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output_format", "output", "output_dir", "checkpoint", "collect_errors", "merge_sorted_by", "dedup_ids", "ledger", "threads"]
+    )]
+    output_url: Option<String>,
+
+    /// Number of records per request to --output-url
+    #[cfg(feature = "http-sink")]
+    #[arg(long, default_value_t = 500, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    #[command(flatten)]
+    timestamps: TimestampArgs,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
 }
 
 impl Args {
     fn input_format(&self) -> Result<Format, ParseError> {
-        Format::from_str(&self.input_format)
+        match &self.input_format {
+            Some(format) => Format::from_str(format),
+            #[cfg(feature = "http-source")]
+            None if is_url(&self.input[0]) => {
+                // The query and fragment are not part of the file name.
+                let path = self.input[0].split(['?', '#']).next().unwrap_or_default();
+                Path::new(path)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .and_then(Format::from_extension)
+                    .ok_or_else(|| {
+                        ParseError::InvalidFormat(format!(
+                            "cannot infer the format of {}",
+                            self.input[0]
+                        ))
+                    })
+            }
+            None => Format::infer_from_path(Path::new(&self.input[0])),
+        }
+    }
+
+    /// Builds the request for a URL input with the `--input-header` headers.
+    #[cfg(feature = "http-source")]
+    fn http_source(&self, url: &str) -> Result<HttpSource, ParseError> {
+        let mut source = HttpSource::new(url)?;
+        for header in &self.input_headers {
+            let Some((name, value)) = header.split_once(':') else {
+                return Err(ParseError::InvalidRawValue {
+                    field: "input header".to_string(),
+                    value: header.clone(),
+                });
+            };
+            source = source.with_header(name, value);
+        }
+        if let Some(path) = &self.input_ca_cert {
+            source = source.with_root_certificates(&std::fs::read(path)?)?;
+        }
+        Ok(source)
     }
 
     fn output_format(&self) -> Result<Format, ParseError> {
-        Format::from_str(&self.output_format)
+        Format::from_str(self.output_format.as_deref().unwrap_or_default())
+    }
+
+    fn input_parser(&self) -> Result<CommonParser, ParseError> {
+        let options = ParseOptions {
+            csv_schema: self
+                .schema_map
+                .as_ref()
+                .map(SchemaMap::from_path)
+                .transpose()?,
+            tombstones: self
+                .tombstones
+                .as_ref()
+                .map(Tombstones::from_path)
+                .transpose()?,
+            #[cfg(feature = "wasm-rules")]
+            wasm_rules: self
+                .wasm_rules
+                .iter()
+                .map(WasmRule::from_path)
+                .collect::<Result<_, _>>()?,
+            reject_rules: self.reject_if.clone(),
+            skip_invalid_records: self.collect_errors.is_some(),
+            timestamp_unit: TimestampUnit::from_str(&self.ts_unit)?,
+            timestamp_check: self.timestamps.timestamp_check()?,
+            txt_comments: self.txt_comments()?,
+            csv_has_header: !self.no_input_header,
+            ..ParseOptions::default()
+        };
+        Ok(CommonParser::with_options(self.input_format()?, options))
+    }
+
+    fn txt_comments(&self) -> Result<TxtComments, ParseError> {
+        match self.txt_comments.to_lowercase().as_str() {
+            "skip" => Ok(TxtComments::Skip),
+            "warn" => Ok(TxtComments::Collect(CommentLog::new())),
+            "reject" => Ok(TxtComments::Reject),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "comment handling".to_string(),
+                value: self.txt_comments.clone(),
+            }),
+        }
+    }
+
+    fn output_parser(&self) -> Result<CommonParser, ParseError> {
+        let options = ParseOptions {
+            timestamp_unit: TimestampUnit::from_str(&self.output_ts_unit)?,
+            csv_write_header: match (&self.output_header, self.no_output_header) {
+                (Some(header), _) => CsvHeader::Custom(header.clone()),
+                (None, true) => CsvHeader::None,
+                (None, false) => CsvHeader::Standard,
+            },
+            pg_table: self.pg_table.clone(),
+            ..ParseOptions::default()
+        };
+        Ok(CommonParser::with_options(self.output_format()?, options))
+    }
+
+    fn pipeline(&self) -> Result<Pipeline, ParseError> {
+        let profiles = self
+            .redact
+            .iter()
+            .map(|profile| RedactionProfile::from_str(profile))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut pipeline = Pipeline::new();
+        let mut filter = match &self.filter {
+            Some(path) => RecordFilter::from_path(path)?,
+            None => RecordFilter::new(),
+        };
+        if !self.users.is_empty() {
+            let side = UserSide::from_str(&self.user_side)?;
+            filter = filter.users(self.users.iter().copied(), side);
+        }
+        if !filter.is_empty() {
+            pipeline = pipeline.with_stage(filter);
+        }
+        for expr in &self.where_exprs {
+            pipeline = pipeline.with_stage(expr.clone());
+        }
+        if let Some(rates) = &self.exchange_rates {
+            pipeline = pipeline.with_enricher(ExchangeRates::from_path(rates)?);
+        }
+        if let Some(transform) = &self.transform {
+            pipeline = pipeline.with_stage(Transformer::from_path(transform)?);
+        }
+        if !profiles.is_empty() {
+            pipeline = pipeline.with_stage(Redactor::new(&profiles));
+        }
+
+        Ok(pipeline)
+    }
+
+    fn merge_key(&self) -> Result<Option<SortKey>, ParseError> {
+        self.merge_sorted_by
+            .as_deref()
+            .map(SortKey::from_str)
+            .transpose()
+    }
+
+    /// Hashes the conversion settings and the content of every input.
+    ///
+    /// The same inputs converted with the same settings always get the same hash. The
+    /// files are rewound afterwards.
+    fn content_hash(&self, input_files: &mut [File]) -> Result<String, ParseError> {
+        let mut digest = Sha256::new();
+        let settings = format!(
+            "{}\n{}\n{}\n{}\n{}\n{:?} {}\n{} {}\n{} {} {:?}\n{} {}\n{:?}\n{:?}\n{:?}\n",
+            env!("CARGO_PKG_VERSION"),
+            self.input_format()?.as_str(),
+            self.output_format()?.as_str(),
+            self.redact.join(",").to_lowercase(),
+            self.merge_key()?.map_or("", |key| key.as_str()),
+            self.users,
+            self.user_side.to_lowercase(),
+            self.ts_unit.to_lowercase(),
+            self.output_ts_unit.to_lowercase(),
+            self.no_input_header,
+            self.no_output_header,
+            self.output_header,
+            self.final_only,
+            self.dedup_ids,
+            self.pg_table,
+            self.where_exprs
+                .iter()
+                .map(RuleExpr::to_string)
+                .collect::<Vec<_>>(),
+            self.reject_if
+                .iter()
+                .map(RuleExpr::to_string)
+                .collect::<Vec<_>>(),
+        );
+        digest.update(settings.as_bytes());
+        for (name, config) in [
+            ("schema-map", &self.schema_map),
+            ("filter", &self.filter),
+            ("exchange-rates", &self.exchange_rates),
+            ("transform", &self.transform),
+            ("tombstones", &self.tombstones),
+        ] {
+            if let Some(path) = config {
+                let mut config_digest = Sha256::new();
+                config_digest.update(&std::fs::read(path)?);
+                digest.update(name.as_bytes());
+                digest.update(&config_digest.finish());
+            }
+        }
+        #[cfg(feature = "wasm-rules")]
+        for path in &self.wasm_rules {
+            let mut rule_digest = Sha256::new();
+            rule_digest.update(&std::fs::read(path)?);
+            digest.update(b"wasm-rule");
+            digest.update(&rule_digest.finish());
+        }
+
+        for input_file in input_files {
+            let mut input_digest = Sha256::new();
+            input_digest.update_from_read(input_file)?;
+            input_file.seek(SeekFrom::Start(0))?;
+            digest.update(&input_digest.finish());
+        }
+
+        Ok(digest.finish_hex())
+    }
+}
+
+/// Keeps only the latest state of every transaction when `final_only` is set.
+///
+/// The records are held in memory then, as a later status update can change any of them.
+fn final_only_records<'a>(
+    records: impl Iterator<Item = Result<YPBankRecord, ParseError>> + 'a,
+    final_only: bool,
+) -> Box<dyn Iterator<Item = Result<YPBankRecord, ParseError>> + 'a> {
+    if !final_only {
+        return Box::new(records);
+    }
+    match final_states(records) {
+        Ok(records) => Box::new(records.into_iter().map(Ok)),
+        Err(err) => Box::new(std::iter::once(Err(err))),
+    }
+}
+
+/// Returns whether an input names a URL rather than a file.
+#[cfg(feature = "http-source")]
+fn is_url(input: &str) -> bool {
+    input.contains("://")
+}
+
+/// An input file, or the body of a URL input read as it arrives.
+enum Input {
+    File(File),
+    #[cfg(feature = "http-source")]
+    Url(HttpBody),
+    /// A URL input hashed as it is read, for `--manifest`.
+    #[cfg(feature = "http-source")]
+    Tallied(Box<Input>, Arc<Mutex<InputTally>>),
+}
+
+/// Hash and length of the bytes read from a URL input, which cannot be read again.
+#[cfg(feature = "http-source")]
+#[derive(Default)]
+struct InputTally {
+    digest: Sha256,
+    bytes: u64,
+}
+
+#[cfg(feature = "http-source")]
+impl InputTally {
+    /// Returns the hash and length of the bytes read so far.
+    fn finish(&self) -> (String, u64) {
+        (self.digest.clone().finish_hex(), self.bytes)
+    }
+}
+
+impl Input {
+    /// Returns the file of a file input, for conversions that read inputs twice.
+    fn into_file(self) -> Option<File> {
+        match self {
+            Input::File(file) => Some(file),
+            #[cfg(feature = "http-source")]
+            Input::Url(_) => None,
+            #[cfg(feature = "http-source")]
+            Input::Tallied(input, _) => input.into_file(),
+        }
+    }
+}
+
+impl std::io::Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Input::File(file) => file.read(buf),
+            #[cfg(feature = "http-source")]
+            Input::Url(body) => body.read(buf),
+            #[cfg(feature = "http-source")]
+            Input::Tallied(input, tally) => {
+                let read = input.read(buf)?;
+                let mut tally = tally.lock().expect("no reader panicked");
+                tally.digest.update(&buf[..read]);
+                tally.bytes += read as u64;
+                Ok(read)
+            }
+        }
+    }
+}
+
+/// Stage counting the records passing through it, for `--manifest`.
+struct RecordCounter(Arc<AtomicU64>);
+
+impl Stage for RecordCounter {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(record))
+    }
+}
+
+/// Wraps `pipeline` in counters of the records entering it and the records it keeps.
+fn counted(pipeline: Pipeline, read: &Arc<AtomicU64>, written: &Arc<AtomicU64>) -> Pipeline {
+    Pipeline::new()
+        .with_stage(RecordCounter(read.clone()))
+        .with_stage(pipeline)
+        .with_stage(RecordCounter(written.clone()))
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Writes `s` as a JSON string.
+fn json_str(s: &str) -> String {
+    let mut out = String::from('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if ch.is_control() => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_list(items: impl IntoIterator<Item = String>) -> String {
+    format!("[{}]", items.into_iter().collect::<Vec<_>>().join(","))
+}
+
+/// Describes a file read or written by a conversion, with its SHA-256 hash and length.
+fn json_file(path: &str, format: Option<Format>, sha256: &str, bytes: u64) -> String {
+    format!(
+        "{{\"path\":{},\"format\":{},\"sha256\":\"{}\",\"bytes\":{}}}",
+        json_str(path),
+        format.map_or("null".to_string(), |format| json_str(format.as_str())),
+        sha256,
+        bytes
+    )
+}
+
+/// Returns the SHA-256 hash and length of a file on disk.
+fn file_digest(path: &Path) -> Result<(String, u64), ParseError> {
+    let mut digest = Sha256::new();
+    let mut file = File::open(path)?;
+    digest.update_from_read(&mut file)?;
+    Ok((digest.finish_hex(), file.metadata()?.len()))
+}
+
+/// Describes a configuration file of a conversion, hashing its content.
+fn json_config(name: &str, path: &Path) -> Result<String, ParseError> {
+    let (sha256, bytes) = file_digest(path)?;
+    Ok(format!(
+        "{{\"name\":\"{}\",\"path\":{},\"sha256\":\"{}\",\"bytes\":{}}}",
+        name,
+        json_str(&path.display().to_string()),
+        sha256,
+        bytes
+    ))
+}
+
+/// What a completed conversion read and wrote, collected for `--manifest`.
+struct Manifest {
+    started: SystemTime,
+    finished: SystemTime,
+    /// Input paths with the hash and length of URL inputs as they were read; files are
+    /// hashed whole when rendering.
+    inputs: Vec<(String, Option<(String, u64)>)>,
+    records_read: u64,
+    records_written: u64,
+}
+
+impl Manifest {
+    /// Renders the manifest as one JSON object, hashing the input files, outputs and
+    /// configuration files named by `args`.
+    fn to_json(&self, args: &Args) -> Result<String, ParseError> {
+        let input_format = args.input_format()?;
+        let mut inputs = Vec::new();
+        for (path, digest) in &self.inputs {
+            let (sha256, bytes) = match digest {
+                Some(digest) => digest.clone(),
+                None => file_digest(Path::new(path))?,
+            };
+            inputs.push(json_file(path, Some(input_format), &sha256, bytes));
+        }
+        let mut outputs = Vec::new();
+        for (path, format) in [
+            (&args.output, args.output_format()?),
+            (&args.collect_errors, input_format),
+        ] {
+            if let Some(path) = path {
+                let (sha256, bytes) = file_digest(path)?;
+                outputs.push(json_file(
+                    &path.display().to_string(),
+                    Some(format),
+                    &sha256,
+                    bytes,
+                ));
+            }
+        }
+
+        let mut config = Vec::new();
+        for (name, path) in [
+            ("schema-map", &args.schema_map),
+            ("filter", &args.filter),
+            ("exchange-rates", &args.exchange_rates),
+            ("transform", &args.transform),
+            ("tombstones", &args.tombstones),
+        ] {
+            if let Some(path) = path {
+                config.push(json_config(name, path)?);
+            }
+        }
+        #[cfg(feature = "wasm-rules")]
+        for path in &args.wasm_rules {
+            config.push(json_config("wasm-rule", path)?);
+        }
+
+        let exprs =
+            |exprs: &[RuleExpr]| json_list(exprs.iter().map(|expr| json_str(&expr.to_string())));
+        let filters = format!(
+            "{{\"users\":{},\"user_side\":{},\"where\":{},\"reject_if\":{},\"final_only\":{},\"dedup_ids\":{},\"redact\":{}}}",
+            json_list(args.users.iter().map(u64::to_string)),
+            json_str(&args.user_side.to_lowercase()),
+            exprs(&args.where_exprs),
+            exprs(&args.reject_if),
+            args.final_only,
+            args.dedup_ids,
+            json_list(
+                args.redact
+                    .iter()
+                    .map(|profile| json_str(&profile.to_lowercase()))
+            ),
+        );
+        let (started, finished) = (millis(self.started), millis(self.finished));
+        Ok(format!(
+            "{{\"tool\":\"converter\",\"version\":\"{}\",\"started_at\":{},\"finished_at\":{},\"duration_ms\":{},\"inputs\":{},\"outputs\":{},\"records_read\":{},\"records_written\":{},\"filters\":{},\"config\":{}}}\n",
+            env!("CARGO_PKG_VERSION"),
+            started,
+            finished,
+            finished.saturating_sub(started),
+            json_list(inputs),
+            json_list(outputs),
+            self.records_read,
+            self.records_written,
+            filters,
+            json_list(config),
+        ))
     }
 }
 
 fn run_logic<R: std::io::Read, W: std::io::Write>(
     input_file: &mut R,
-    input_format: Format,
-    output_format: Format,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
+    final_only: bool,
+    threads: usize,
+    output_file: &mut W,
+) -> bool {
+    let records = match input_parser.iter_read(input_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+
+    let records = final_only_records(records, final_only);
+    if let Err(err) = output_parser.write_iter_parallel(output_file, records, pipeline, threads) {
+        cli::error(format!("Failed to convert: [{}] {err}", err.code()));
+        return false;
+    }
+    true
+}
+
+fn merge_logic<R: std::io::Read, W: std::io::Write>(
+    input_files: Vec<R>,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    key: SortKey,
+    pipeline: &Pipeline,
+    final_only: bool,
+    output_file: &mut W,
+) -> bool {
+    let mut inputs = Vec::with_capacity(input_files.len());
+    for input_file in input_files {
+        match input_parser.iter_read(input_file) {
+            Ok(records) => inputs.push(records),
+            Err(err) => {
+                cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+                return false;
+            }
+        }
+    }
+
+    let records =
+        final_only_records(merge_sorted(inputs, key), final_only).filter_map(
+            |record| match record {
+                Ok(record) => pipeline.process(record).transpose(),
+                Err(err) => Some(Err(err)),
+            },
+        );
+    if let Err(err) = output_parser.write_iter(output_file, records) {
+        cli::error(format!("Failed to merge inputs: [{}] {err}", err.code()));
+        return false;
+    }
+    true
+}
+
+/// Last stage of the pipeline, adding the records that leave it, which are the records
+/// written, to the statistics of `--stats-sidecar`.
+struct StatsCollector(Arc<Mutex<ColumnStats>>);
+
+impl Stage for StatsCollector {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        self.0.lock().expect("no stage panicked").add(&record);
+        Ok(Some(record))
+    }
+}
+
+/// Converts the inputs one after another, keeping only the first record of every id.
+fn dedup_logic<R: std::io::Read, W: std::io::Write>(
+    input_files: Vec<R>,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
+    seen: &mut SeenIds,
+    threads: usize,
+    output_file: &mut W,
+) -> bool {
+    let mut inputs = Vec::with_capacity(input_files.len());
+    for input_file in input_files {
+        match input_parser.iter_read(input_file) {
+            Ok(records) => inputs.push(records),
+            Err(err) => {
+                cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+                return false;
+            }
+        }
+    }
+
+    let records = seen.dedup(inputs.into_iter().flatten());
+    if let Err(err) = output_parser.write_iter_parallel(output_file, records, pipeline, threads) {
+        cli::error(format!(
+            "Failed to deduplicate inputs: [{}] {err}",
+            err.code()
+        ));
+        return false;
+    }
+    true
+}
+
+/// Posts the processed records to `sink`, stopping at the first record that fails to
+/// parse; the batches posted before it stay posted.
+#[cfg(feature = "http-sink")]
+fn post_logic<R: std::io::Read>(
+    input_file: R,
+    input_parser: &CommonParser,
+    pipeline: &Pipeline,
+    final_only: bool,
+    sink: &HttpSink,
+) -> bool {
+    let records = match input_parser.iter_read(input_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+
+    let records = final_only_records(records, final_only).filter_map(|record| match record {
+        Ok(record) => pipeline.process(record).transpose(),
+        Err(err) => Some(Err(err)),
+    });
+    match sink.send_all(records) {
+        Ok(sent) => {
+            cli::summary(format!(
+                "Posted {sent} records in {} requests",
+                sink.requests()
+            ));
+            true
+        }
+        Err(err) => {
+            cli::error(format!(
+                "Failed to post records after {} were posted: [{}] {err}",
+                sink.posted(),
+                err.code()
+            ));
+            false
+        }
+    }
+}
+
+/// Writes the ledger lines of the processed records to `output_file`.
+fn ledger_logic<R: std::io::Read, W: std::io::Write>(
+    input_file: R,
+    input_parser: &CommonParser,
+    pipeline: &Pipeline,
+    final_only: bool,
+    accounts: &AccountMap,
+    output_file: &mut W,
+) -> bool {
+    let records = match input_parser.iter_read(input_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+
+    let records = final_only_records(records, final_only).filter_map(|record| match record {
+        Ok(record) => pipeline.process(record).transpose(),
+        Err(err) => Some(Err(err)),
+    });
+    match LedgerWriter::write_iter(output_file, records, accounts) {
+        Ok(lines) => {
+            cli::summary(format!("Wrote {lines} ledger lines"));
+            true
+        }
+        Err(err) => {
+            cli::error(format!("Failed to write ledger: [{}] {err}", err.code()));
+            false
+        }
+    }
+}
+
+/// Converts the records that parse, writing the others to `rejects` as they were read and
+/// their errors to `reasons`, one line per rejected record.
+///
+/// `rejects` starts with the input header, so it can be fixed and converted again.
+fn collect_errors_logic<R: std::io::Read, W: std::io::Write>(
+    input_file: R,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
     output_file: &mut W,
-) {
-    let input_parser = CommonParser::new(input_format);
-    let output_parser = CommonParser::new(output_format);
-    let records = match input_parser.from_read(input_file) {
+    rejects: &mut impl Write,
+    reasons: &mut impl Write,
+) -> bool {
+    let mut records = match input_parser.iter_read(input_file) {
         Ok(records) => records,
         Err(err) => {
-            println!("Failed to read input: {err}");
-            return;
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    if let Err(err) = rejects.write_all(records.raw_record()) {
+        cli::error(format!(
+            "Failed to write rejects: [{}] {err}",
+            ErrorCode::Io
+        ));
+        return false;
+    }
+
+    let mut rejected = 0;
+    let accepted = std::iter::from_fn(|| {
+        loop {
+            let err = match records.next()? {
+                Ok(record) => match pipeline.process(record).transpose() {
+                    Some(record) => return Some(record),
+                    None => continue,
+                },
+                Err(err) if err.code() == ErrorCode::Io => return Some(Err(err)),
+                Err(err) => err,
+            };
+
+            let raw = records.raw_record();
+            let offset = records.offset() - raw.len() as u64;
+            cli::warning(format!(
+                "Rejected record at offset {offset}: [{}] {err}",
+                err.code()
+            ));
+            let written = rejects
+                .write_all(raw)
+                .and_then(|_| writeln!(reasons, "{offset}: [{}] {err}", err.code()));
+            if let Err(err) = written {
+                return Some(Err(err.into()));
+            }
+            rejected += 1;
+        }
+    });
+    if let Err(err) = output_parser.write_iter(output_file, accepted) {
+        cli::error(format!("Failed to convert: [{}] {err}", err.code()));
+        return false;
+    }
+    if let Err(err) = rejects.flush().and_then(|_| reasons.flush()) {
+        cli::error(format!(
+            "Failed to write rejects: [{}] {err}",
+            ErrorCode::Io
+        ));
+        return false;
+    }
+
+    cli::summary(format!("Rejected {rejected} records"));
+    true
+}
+
+/// Reports the TXT comments and implausible timestamps collected while reading the
+/// inputs, one warning per comment or record.
+fn report_warnings(input_parser: &CommonParser) {
+    cli::report_timestamps(&input_parser.options().timestamp_check);
+    let TxtComments::Collect(log) = &input_parser.options().txt_comments else {
+        return;
+    };
+    let comments = log.take();
+    for comment in &comments {
+        cli::warning(format!(
+            "Comment at line {}: {}",
+            comment.line, comment.text
+        ));
+    }
+    if !comments.is_empty() {
+        cli::summary(format!("Read {} comment lines", comments.len()));
+    }
+}
+
+/// Runs [`collect_errors_logic`] with the rejects written to `path` and the reasons next to it.
+fn collect_errors<R: std::io::Read, W: std::io::Write>(
+    path: &Path,
+    input_file: R,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
+    output_file: &mut W,
+) -> bool {
+    let mut reasons_path = path.as_os_str().to_owned();
+    reasons_path.push(".reasons");
+    let files = File::create(path).and_then(|rejects| Ok((rejects, File::create(&reasons_path)?)));
+    let (rejects, reasons) = match files {
+        Ok(files) => files,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to create reject file {}: [{}] {err}",
+                path.display(),
+                ErrorCode::Io
+            ));
+            return false;
         }
     };
-    if let Err(err) = output_parser.write_to(output_file, &records) {
-        println!("Failed to write output: {err}");
+
+    collect_errors_logic(
+        input_file,
+        input_parser,
+        output_parser,
+        pipeline,
+        output_file,
+        &mut BufWriter::new(rejects),
+        &mut BufWriter::new(reasons),
+    )
+}
+
+/// Progress of a conversion saved with `--checkpoint`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Checkpoint {
+    /// Input offset right after the last converted record.
+    input_offset: u64,
+    records_written: u64,
+    /// Length of the output holding exactly `records_written` records.
+    output_len: u64,
+}
+
+impl Checkpoint {
+    /// Loads a checkpoint, or returns `None` when there is none yet.
+    fn load(path: &Path) -> Result<Option<Self>, ParseError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut checkpoint = Self::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let value = value.parse().map_err(|_| ParseError::InvalidRawValue {
+                field: key.to_string(),
+                value: value.to_string(),
+            })?;
+            match key {
+                "input_offset" => checkpoint.input_offset = value,
+                "records_written" => checkpoint.records_written = value,
+                "output_len" => checkpoint.output_len = value,
+                _ => return Err(ParseError::InvalidRow(line.to_string())),
+            }
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Replaces the saved checkpoint, so that an interruption leaves either the old or the new one.
+    fn save(&self, path: &Path) -> Result<(), ParseError> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+        std::fs::write(
+            &tmp_path,
+            format!(
+                "input_offset={}\nrecords_written={}\noutput_len={}\n",
+                self.input_offset, self.records_written, self.output_len
+            ),
+        )?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
     }
 }
 
-fn main() {
+/// Converts `input_file` into `output_path`, saving a checkpoint every `every` input records.
+///
+/// When `checkpoint_path` holds a checkpoint, the output is truncated to the checkpointed
+/// length and the conversion continues at the checkpointed input offset. The checkpoint is
+/// removed once the conversion completes.
+fn checkpoint_logic<R: std::io::Read + Seek>(
+    input_file: R,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
+    output_path: &Path,
+    checkpoint_path: &Path,
+    every: u64,
+) -> bool {
+    if !output_parser.format().is_streaming() {
+        cli::error(format!(
+            "Checkpoints are not supported for {} output",
+            output_parser.format().as_str()
+        ));
+        return false;
+    }
+
+    let checkpoint = match Checkpoint::load(checkpoint_path) {
+        Ok(checkpoint) => checkpoint,
+        Err(err) => {
+            cli::error(format!("Failed to load checkpoint: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    if let Some(checkpoint) = checkpoint {
+        cli::summary(format!(
+            "Resuming after {} records written",
+            checkpoint.records_written
+        ));
+    }
+
+    let result = convert_with_checkpoints(
+        input_file,
+        input_parser,
+        output_parser,
+        pipeline,
+        output_path,
+        checkpoint_path,
+        checkpoint,
+        every,
+    );
+    match result {
+        Ok(records_written) => {
+            cli::summary(format!("Wrote {records_written} records"));
+            true
+        }
+        Err(err) => {
+            cli::error(format!("Failed to convert: [{}] {err}", err.code()));
+            false
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn convert_with_checkpoints<R: std::io::Read + Seek>(
+    input_file: R,
+    input_parser: &CommonParser,
+    output_parser: &CommonParser,
+    pipeline: &Pipeline,
+    output_path: &Path,
+    checkpoint_path: &Path,
+    checkpoint: Option<Checkpoint>,
+    every: u64,
+) -> Result<u64, ParseError> {
+    let (mut checkpoint, mut output_file) = match checkpoint {
+        Some(checkpoint) => {
+            let mut output_file = std::fs::OpenOptions::new().write(true).open(output_path)?;
+            output_file.set_len(checkpoint.output_len)?;
+            output_file.seek(SeekFrom::End(0))?;
+            (checkpoint, BufWriter::new(output_file))
+        }
+        None => {
+            let mut output_file = BufWriter::new(File::create(output_path)?);
+            output_parser.write_iter(&mut output_file, std::iter::empty())?;
+            (Checkpoint::default(), output_file)
+        }
+    };
+
+    let mut records = input_parser.iter_read_at(input_file, checkpoint.input_offset)?;
+    loop {
+        let mut records_read = 0;
+        let mut records_written = 0;
+        let chunk = records
+            .by_ref()
+            .take(every as usize)
+            .inspect(|_| records_read += 1)
+            .filter_map(|record| match record {
+                Ok(record) => pipeline.process(record).transpose(),
+                Err(err) => Some(Err(err)),
+            })
+            .inspect(|_| records_written += 1);
+        output_parser.append_iter(&mut output_file, chunk)?;
+
+        output_file.flush()?;
+        output_file.get_ref().sync_data()?;
+        checkpoint = Checkpoint {
+            input_offset: records.offset(),
+            records_written: checkpoint.records_written + records_written,
+            output_len: output_file.get_mut().stream_position()?,
+        };
+
+        if records_read < every {
+            std::fs::remove_file(checkpoint_path).or_else(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => Ok(()),
+                _ => Err(err),
+            })?;
+            return Ok(checkpoint.records_written);
+        }
+        checkpoint.save(checkpoint_path)?;
+    }
+}
+
+/// Runs `convert` into `dir/<name>` unless that file already exists.
+///
+/// The output goes to a temporary file that is renamed once `convert` succeeds, so an
+/// interrupted run never leaves a file that looks up to date. Returns the path of a new
+/// output, or `None` when it already existed.
+fn write_content_addressed(
+    dir: &Path,
+    name: &str,
+    convert: impl FnOnce(&mut BufWriter<File>) -> bool,
+) -> Result<Option<PathBuf>, ParseError> {
+    let path = dir.join(name);
+    if path.exists() {
+        return Ok(None);
+    }
+
+    write_atomic(&path, None, |output| {
+        if !convert(output) {
+            return Err(ParseError::IOError(format!(
+                "conversion to {} failed",
+                path.display()
+            )));
+        }
+        Ok(())
+    })?;
+    Ok(Some(path))
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
     let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+    let started = SystemTime::now();
+
+    #[cfg(feature = "http-source")]
+    if args.input.iter().any(|input| is_url(input))
+        && (args.checkpoint.is_some() || args.output_dir.is_some())
+    {
+        cli::error("URL inputs are not available with --checkpoint or --output-dir");
+        return Status::Usage.into();
+    }
+
+    let mut input_files = Vec::with_capacity(args.input.len());
+    for input in &args.input {
+        #[cfg(feature = "http-source")]
+        if is_url(input) {
+            match args.http_source(input).and_then(|source| source.open()) {
+                Ok(body) => input_files.push(Input::Url(body)),
+                Err(err) => {
+                    cli::error(format!(
+                        "Failed to fetch input {}: [{}] {err}",
+                        input,
+                        err.code()
+                    ));
+                    return Status::Failure.into();
+                }
+            }
+            continue;
+        }
+        match std::fs::File::open(input) {
+            Ok(file) => input_files.push(Input::File(file)),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to open input file {}: [{}] {err}",
+                    input,
+                    ErrorCode::Io
+                ));
+                return Status::Failure.into();
+            }
+        }
+    }
+    // URL inputs are hashed as they arrive, as they cannot be read again for the manifest.
+    #[cfg(feature = "http-source")]
+    let tallies: Vec<Option<Arc<Mutex<InputTally>>>> = input_files
+        .iter()
+        .map(|input| (args.manifest.is_some() && matches!(input, Input::Url(_))).then(Arc::default))
+        .collect();
+    #[cfg(feature = "http-source")]
+    {
+        input_files = input_files
+            .into_iter()
+            .zip(&tallies)
+            .map(|(input, tally)| match tally {
+                Some(tally) => Input::Tallied(Box::new(input), tally.clone()),
+                None => input,
+            })
+            .collect();
+    }
+    if let Err(err) = args.input_format() {
+        match &args.input_format {
+            Some(format) => cli::error(format!(
+                "Invalid input format {}: [{}] {err}",
+                format,
+                err.code()
+            )),
+            None => cli::error(format!(
+                "Failed to infer input format: [{}] {err}",
+                err.code()
+            )),
+        }
+        return Status::Usage.into();
+    }
 
-    let mut input_file = match std::fs::File::open(&args.input) {
-        Ok(file) => file,
+    let input_parser = match args.input_parser() {
+        Ok(parser) => parser,
         Err(err) => {
-            println!("Failed to open input file {}: {err}", args.input);
-            return;
+            cli::error(format!(
+                "Invalid schema map, tombstones, timestamp unit, timestamp check or comment handling: [{}] {err}",
+                err.code()
+            ));
+            return Status::Usage.into();
         }
     };
-    let mut output_file = std::io::stdout();
 
-    let input_format = match args.input_format() {
-        Ok(format) => format,
+    let pipeline = match args.pipeline() {
+        Ok(pipeline) => pipeline,
         Err(err) => {
-            println!("Invalid input format {}: {err}", args.input_format);
-            return;
+            cli::error(format!(
+                "Invalid filter, user side, exchange rates, transforms or redaction profile: [{}] {err}",
+                err.code()
+            ));
+            return Status::Usage.into();
         }
     };
+    let stats = args
+        .stats_sidecar
+        .then(|| Arc::new(Mutex::new(ColumnStats::new())));
+    let pipeline = match &stats {
+        Some(stats) => pipeline.with_stage(StatsCollector(stats.clone())),
+        None => pipeline,
+    };
+    let (records_read, records_written) = (Arc::default(), Arc::default());
+    let pipeline = match &args.manifest {
+        Some(_) => counted(pipeline, &records_read, &records_written),
+        None => pipeline,
+    };
+
+    let merge_key = match args.merge_key() {
+        Ok(key) => key,
+        Err(err) => {
+            cli::error(format!("Invalid merge key: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+
+    if merge_key.is_none() && !args.dedup_ids && input_files.len() > 1 {
+        cli::error("Multiple input files require --merge-sorted-by or --dedup-ids");
+        return Status::Usage.into();
+    }
+
+    #[cfg(feature = "http-sink")]
+    if let Some(url) = &args.output_url {
+        let sink = match HttpSink::new(url) {
+            Ok(sink) => sink.with_batch_size(args.batch_size as usize),
+            Err(err) => {
+                cli::error(format!(
+                    "Invalid output URL {}: [{}] {err}",
+                    url,
+                    err.code()
+                ));
+                return Status::Usage.into();
+            }
+        };
+        let posted = post_logic(
+            &mut input_files[0],
+            &input_parser,
+            &pipeline,
+            args.final_only,
+            &sink,
+        );
+        report_warnings(&input_parser);
+        return Status::from(posted).into();
+    }
+
+    if let Some(accounts) = &args.ledger {
+        let expanded = match &args.output {
+            Some(output_path) => match File::create(output_path) {
+                Ok(mut output_file) => ledger_logic(
+                    &mut input_files[0],
+                    &input_parser,
+                    &pipeline,
+                    args.final_only,
+                    accounts,
+                    &mut output_file,
+                ),
+                Err(err) => {
+                    cli::error(format!(
+                        "Failed to create output file {}: [{}] {err}",
+                        output_path.display(),
+                        ErrorCode::Io
+                    ));
+                    return Status::Failure.into();
+                }
+            },
+            None => ledger_logic(
+                &mut input_files[0],
+                &input_parser,
+                &pipeline,
+                args.final_only,
+                accounts,
+                &mut std::io::stdout(),
+            ),
+        };
+        report_warnings(&input_parser);
+        return Status::from(expanded).into();
+    }
 
     let output_format = match args.output_format() {
         Ok(format) => format,
         Err(err) => {
-            println!("Invalid output format {}: {err}", args.output_format);
-            return;
+            cli::error(format!(
+                "Invalid output format {}: [{}] {err}",
+                args.output_format.as_deref().unwrap_or_default(),
+                err.code()
+            ));
+            return Status::Usage.into();
         }
     };
 
-    run_logic(
-        &mut input_file,
-        input_format,
-        output_format,
-        &mut output_file,
-    );
+    if args.pg_table.is_some() && output_format != Format::PgCopy {
+        cli::error("--pg-table requires --output-format pgcopy");
+        return Status::Usage.into();
+    }
+
+    let output_parser = match args.output_parser() {
+        Ok(parser) => parser,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid output timestamp unit {}: [{}] {err}",
+                args.output_ts_unit,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+
+    let convert = |mut input_files: Vec<Input>, mut output: &mut dyn Write| {
+        let converted = match (merge_key, &args.collect_errors) {
+            _ if args.dedup_ids => dedup_logic(
+                input_files,
+                &input_parser,
+                &output_parser,
+                &pipeline,
+                &mut SeenIds::new(std::env::temp_dir(), args.dedup_memory as usize),
+                args.threads as usize,
+                &mut output,
+            ),
+            (Some(key), _) => merge_logic(
+                input_files,
+                &input_parser,
+                &output_parser,
+                key,
+                &pipeline,
+                args.final_only,
+                &mut output,
+            ),
+            (None, Some(reject_path)) => collect_errors(
+                reject_path,
+                &mut input_files[0],
+                &input_parser,
+                &output_parser,
+                &pipeline,
+                &mut output,
+            ),
+            (None, None) => run_logic(
+                &mut input_files[0],
+                &input_parser,
+                &output_parser,
+                &pipeline,
+                args.final_only,
+                args.threads as usize,
+                &mut output,
+            ),
+        };
+        report_warnings(&input_parser);
+        converted
+    };
+
+    if let Some(checkpoint_path) = &args.checkpoint {
+        let output_path = args.output.as_deref().expect("required by --checkpoint");
+        let input_file = input_files.swap_remove(0).into_file();
+        let converted = checkpoint_logic(
+            input_file.expect("URL inputs are rejected with --checkpoint"),
+            &input_parser,
+            &output_parser,
+            &pipeline,
+            output_path,
+            checkpoint_path,
+            args.checkpoint_every,
+        );
+        report_warnings(&input_parser);
+        return Status::from(converted).into();
+    }
+
+    if let Some(output_path) = &args.output {
+        let mut output_file = match File::create(output_path) {
+            Ok(output_file) => BufWriter::new(output_file),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to create output file {}: [{}] {err}",
+                    output_path.display(),
+                    ErrorCode::Io
+                ));
+                return Status::Failure.into();
+            }
+        };
+        if !convert(input_files, &mut output_file) {
+            return Status::Failure.into();
+        }
+        if let Err(err) = output_file.flush() {
+            cli::error(format!("Failed to write output: [{}] {err}", ErrorCode::Io));
+            return Status::Failure.into();
+        }
+        drop(output_file);
+        if let Some(stats) = &stats
+            && let Err(err) = stats
+                .lock()
+                .expect("no stage panicked")
+                .write_sidecar(output_path)
+        {
+            cli::error(format!(
+                "Failed to write statistics of {}: [{}] {err}",
+                output_path.display(),
+                err.code()
+            ));
+            return Status::Failure.into();
+        }
+        if let Some(manifest_path) = &args.manifest {
+            #[cfg(feature = "http-source")]
+            let digests = tallies
+                .iter()
+                .map(|tally| {
+                    let tally = tally.as_ref()?;
+                    Some(tally.lock().expect("no reader panicked").finish())
+                })
+                .collect::<Vec<_>>();
+            #[cfg(not(feature = "http-source"))]
+            let digests = vec![None; args.input.len()];
+            let manifest = Manifest {
+                started,
+                finished: SystemTime::now(),
+                inputs: args.input.iter().cloned().zip(digests).collect(),
+                records_read: records_read.load(Ordering::Relaxed),
+                records_written: records_written.load(Ordering::Relaxed),
+            };
+            let written = manifest
+                .to_json(&args)
+                .and_then(|json| Ok(std::fs::write(manifest_path, json)?));
+            if let Err(err) = written {
+                cli::error(format!(
+                    "Failed to write manifest {}: [{}] {err}",
+                    manifest_path.display(),
+                    err.code()
+                ));
+                return Status::Failure.into();
+            }
+        }
+        return Status::Success.into();
+    }
+
+    let Some(output_dir) = &args.output_dir else {
+        return Status::from(convert(input_files, &mut std::io::stdout())).into();
+    };
+
+    let mut input_files: Vec<File> = input_files
+        .into_iter()
+        .map(|input| {
+            input
+                .into_file()
+                .expect("URL inputs are rejected with --output-dir")
+        })
+        .collect();
+    let hash = match args.content_hash(&mut input_files) {
+        Ok(hash) => hash,
+        Err(err) => {
+            cli::error(format!("Failed to hash inputs: [{}] {err}", err.code()));
+            return Status::Failure.into();
+        }
+    };
+    let name = format!("{}.{}", hash, output_format.as_str());
+    match write_content_addressed(output_dir, &name, |output_file| {
+        convert(
+            input_files.into_iter().map(Input::File).collect(),
+            output_file,
+        )
+    }) {
+        Ok(Some(path)) => cli::summary(format!("Wrote {}", path.display())),
+        Ok(None) => cli::summary(format!(
+            "{} is up to date",
+            output_dir.join(&name).display()
+        )),
+        Err(err) => {
+            cli::error(format!("Failed to write output: [{}] {err}", err.code()));
+            return Status::Failure.into();
+        }
+    }
+    Status::Success.into()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use parser::{TransactionStatus, TransactionType, YPBankRecord};
+    use parser::{
+        REDACTED_USER_ID, TimestampCheck, TransactionStatus, TransactionType, YPBankRecord,
+    };
     use std::io::Cursor;
 
     fn create_csv_data(records: Vec<YPBankRecord>) -> Vec<u8> {
@@ -142,7 +1638,7 @@ mod tests {
             data.extend_from_slice(&record.ts.to_be_bytes());
             data.push(record.status.as_int());
             data.extend_from_slice(&desc_len.to_be_bytes());
-            data.extend_from_slice(&record.description.as_bytes());
+            data.extend_from_slice(record.description.as_bytes());
         }
         data
     }
@@ -194,7 +1690,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Csv, Format::Txt, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Txt),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_txt(&output_data);
@@ -215,7 +1719,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Csv, Format::Bin, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Bin),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_bin(&output_data);
@@ -236,7 +1748,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Txt, Format::Csv, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Txt),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_csv(&output_data);
@@ -257,7 +1777,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Txt, Format::Bin, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Txt),
+            &CommonParser::new(Format::Bin),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_bin(&output_data);
@@ -278,7 +1806,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Bin, Format::Csv, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Bin),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_csv(&output_data);
@@ -299,7 +1835,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Bin, Format::Txt, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Bin),
+            &CommonParser::new(Format::Txt),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_txt(&output_data);
@@ -320,7 +1864,15 @@ mod tests {
         let mut input = Cursor::new(input_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Csv, Format::Csv, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_csv(&output_data);
@@ -339,10 +1891,962 @@ mod tests {
         let mut input = Cursor::new(csv_data);
         let mut output = Cursor::new(Vec::new());
 
-        run_logic(&mut input, Format::Csv, Format::Txt, &mut output);
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Txt),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        );
 
         let output_data = output.into_inner();
         let parsed_records = parse_output_txt(&output_data);
         assert_eq!(parsed_records.len(), 0);
     }
+
+    #[test]
+    fn test_redact_pii_and_amounts() {
+        let records = vec![create_test_record(1000000000000000, 100)];
+
+        let input_data = create_csv_data(records);
+        let mut input = Cursor::new(input_data);
+        let mut output = Cursor::new(Vec::new());
+        let pipeline = Pipeline::new().with_stage(Redactor::new(&[
+            RedactionProfile::Pii,
+            RedactionProfile::Amounts,
+        ]));
+
+        run_logic(
+            &mut input,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &pipeline,
+            false,
+            1,
+            &mut output,
+        );
+
+        let output_data = output.into_inner();
+        let parsed_records = parse_output_csv(&output_data);
+        assert_eq!(parsed_records.len(), 1);
+        assert_eq!(parsed_records[0].amount, 0);
+        assert_eq!(parsed_records[0].from_user_id, 0);
+        assert_eq!(parsed_records[0].to_user_id, REDACTED_USER_ID);
+    }
+
+    #[test]
+    fn test_user_filter_runs_before_redaction() {
+        let records = (1..=4)
+            .map(|id| {
+                let mut record = create_test_record(id, 100);
+                record.to_user_id = id * 10;
+                record
+            })
+            .collect();
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "csv",
+            "--user",
+            "20",
+            "--user",
+            "40",
+            "--redact",
+            "pii",
+        ]);
+        let mut output = Cursor::new(Vec::new());
+
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(records)),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &args.pipeline().expect("Should build pipeline"),
+            args.final_only,
+            1,
+            &mut output,
+        ));
+
+        let parsed_records = parse_output_csv(&output.into_inner());
+        assert_eq!(
+            parsed_records.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert!(
+            parsed_records
+                .iter()
+                .all(|r| r.to_user_id == REDACTED_USER_ID)
+        );
+    }
+
+    #[test]
+    fn test_where_and_reject_if() {
+        let records = (1..=4)
+            .map(|id| create_test_record(id, id as i64 * 100))
+            .collect();
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "csv",
+            "--where",
+            "amount >= 200",
+            "--reject-if",
+            "id == 4",
+        ]);
+        let input_parser = args.input_parser().expect("Should build parser");
+        let mut output = Cursor::new(Vec::new());
+
+        // Refused records fail the conversion, as records failing to parse do.
+        assert!(!run_logic(
+            &mut Cursor::new(create_csv_data(records)),
+            &input_parser,
+            &CommonParser::new(Format::Csv),
+            &args.pipeline().expect("Should build pipeline"),
+            args.final_only,
+            1,
+            &mut output,
+        ));
+
+        let parsed_records = parse_output_csv(&output.into_inner());
+        assert_eq!(
+            parsed_records.iter().map(|r| r.id).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert!(
+            Args::try_parse_from(["converter", "--input", "a.csv", "--where", "amount >"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_exchange_rates_run_before_transforms() {
+        let dir = std::env::temp_dir().join(format!("converter-rates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let rates_path = dir.join("rates.toml");
+        let transform_path = dir.join("transform.toml");
+        std::fs::write(&rates_path, "[rates]\n2021-09-30 = \"1.5\"\n").expect("Should write");
+        std::fs::write(&transform_path, "[transforms]\namount_add = 1\n").expect("Should write");
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "csv",
+            "--exchange-rates",
+            rates_path.to_str().expect("Should be UTF-8"),
+            "--transform",
+            transform_path.to_str().expect("Should be UTF-8"),
+        ]);
+        let mut output = Cursor::new(Vec::new());
+
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(vec![create_test_record(1, 100)])),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &args.pipeline().expect("Should build pipeline"),
+            args.final_only,
+            1,
+            &mut output,
+        ));
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert_eq!(parse_output_csv(&output.into_inner())[0].amount, 151);
+    }
+
+    #[test]
+    fn test_threads_keep_input_order() {
+        let records: Vec<_> = (1..=3000).map(|id| create_test_record(id, 100)).collect();
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "txt",
+            "--threads",
+            "4",
+            "--user",
+            "9223372036854775807",
+        ]);
+        let convert = |threads| {
+            let mut output = Cursor::new(Vec::new());
+            assert!(run_logic(
+                &mut Cursor::new(create_csv_data(records.clone())),
+                &CommonParser::new(Format::Csv),
+                &CommonParser::new(Format::Txt),
+                &args.pipeline().expect("Should build pipeline"),
+                args.final_only,
+                threads,
+                &mut output,
+            ));
+            output.into_inner()
+        };
+
+        let output = convert(args.threads as usize);
+        assert!(output == convert(1));
+        let parsed = CommonParser::new(Format::Txt)
+            .from_read(&mut output.as_slice())
+            .expect("Should parse output");
+        assert_eq!(parsed.len(), 3000);
+        assert!(parsed.iter().zip(1..).all(|(record, id)| record.id == id));
+    }
+
+    #[test]
+    fn test_timestamp_units() {
+        let mut seconds = create_test_record(1, 100);
+        seconds.ts = 1633036861;
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "csv",
+            "--ts-unit",
+            "auto",
+            "--output-ts-unit",
+            "s",
+        ]);
+        let mut output = Cursor::new(Vec::new());
+
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(vec![seconds, create_test_record(2, 100)])),
+            &args.input_parser().expect("Should build input parser"),
+            &args.output_parser().expect("Should build output parser"),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        ));
+
+        assert_eq!(
+            parse_output_csv(&output.into_inner())
+                .iter()
+                .map(|r| r.ts)
+                .collect::<Vec<_>>(),
+            vec![1633036861, 1633036860]
+        );
+    }
+
+    #[test]
+    fn test_txt_comments_are_collected() {
+        let mut input = b"# Reviewed\n".to_vec();
+        input.extend(create_txt_data(vec![create_test_record(1, 100)]));
+        let args = |mode: &str| {
+            Args::parse_from([
+                "converter",
+                "--input",
+                "records.txt",
+                "--output-format",
+                "csv",
+                "--txt-comments",
+                mode,
+            ])
+        };
+
+        let input_parser = args("warn").input_parser().expect("Should build parser");
+        assert!(run_logic(
+            &mut input.as_slice(),
+            &input_parser,
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut Vec::new(),
+        ));
+        let TxtComments::Collect(log) = &input_parser.options().txt_comments else {
+            panic!("Should collect comments");
+        };
+        assert_eq!(log.len(), 1);
+
+        assert!(!run_logic(
+            &mut input.as_slice(),
+            &args("reject").input_parser().expect("Should build parser"),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut Vec::new(),
+        ));
+        assert!(args("keep").input_parser().is_err());
+    }
+
+    #[test]
+    fn test_ts_check_catches_seconds() {
+        let mut seconds = create_test_record(2, 200);
+        seconds.ts = 1633036860;
+        let input = create_csv_data(vec![create_test_record(1, 100), seconds]);
+        let args = |extra: &[&str]| {
+            let mut args = vec![
+                "converter",
+                "--input",
+                "records.csv",
+                "--output-format",
+                "csv",
+            ];
+            args.extend_from_slice(extra);
+            Args::parse_from(args)
+        };
+        let convert = |input_parser: &CommonParser| {
+            let mut output = Vec::new();
+            let converted = run_logic(
+                &mut input.as_slice(),
+                input_parser,
+                &CommonParser::new(Format::Csv),
+                &Pipeline::new(),
+                false,
+                1,
+                &mut output,
+            );
+            (converted, parse_output_csv(&output).len())
+        };
+
+        let input_parser = args(&[]).input_parser().expect("Should build parser");
+        assert_eq!(convert(&input_parser), (true, 2));
+        let TimestampCheck::Warn(_, log) = &input_parser.options().timestamp_check else {
+            panic!("Should warn by default");
+        };
+        assert_eq!(
+            log.take().iter().map(|entry| entry.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        let input_parser = args(&["--ts-check", "reject"])
+            .input_parser()
+            .expect("Should build parser");
+        assert_eq!(convert(&input_parser), (false, 1));
+        let input_parser = args(&["--ts-check", "reject", "--ts-unit", "auto"])
+            .input_parser()
+            .expect("Should build parser");
+        assert_eq!(convert(&input_parser), (true, 2));
+        assert!(args(&["--ts-check", "maybe"]).input_parser().is_err());
+    }
+
+    #[test]
+    fn test_headerless_csv() {
+        let input = create_csv_data(vec![create_test_record(1, 100)]);
+        let header_len = input.iter().position(|byte| *byte == b'\n').unwrap() + 1;
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "csv",
+            "--no-input-header",
+            "--no-output-header",
+        ]);
+        let mut output = Vec::new();
+
+        assert!(run_logic(
+            &mut &input[header_len..],
+            &args.input_parser().expect("Should build input parser"),
+            &args.output_parser().expect("Should build output parser"),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        ));
+        assert!(output.starts_with(b"1,DEPOSIT,"));
+    }
+
+    #[test]
+    fn test_final_only() {
+        let record = |id: u64, ts: u64, status: TransactionStatus| {
+            YPBankRecord::new(
+                id,
+                TransactionType::Deposit,
+                0,
+                9223372036854775807,
+                100,
+                ts,
+                status,
+                format!("\"Record number {}\"", id),
+            )
+        };
+        let records = vec![
+            record(1, 10, TransactionStatus::Pending),
+            record(2, 20, TransactionStatus::Pending),
+            record(1, 30, TransactionStatus::Success),
+        ];
+
+        let mut output = Cursor::new(Vec::new());
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(records.clone())),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            true,
+            1,
+            &mut output,
+        ));
+        assert_eq!(
+            parse_output_csv(&output.into_inner()),
+            vec![records[2].clone(), records[1].clone()]
+        );
+
+        let mut output = Cursor::new(Vec::new());
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(records.clone())),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        ));
+        assert_eq!(parse_output_csv(&output.into_inner()), records);
+    }
+
+    #[test]
+    fn test_ledger() {
+        let mut failed = create_test_record(2, 50);
+        failed.status = TransactionStatus::Failure;
+        let mut output = Cursor::new(Vec::new());
+
+        assert!(ledger_logic(
+            &mut Cursor::new(create_csv_data(vec![create_test_record(1, 100), failed])),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            &AccountMap::new().with_cash_account("1000"),
+            &mut output,
+        ));
+        assert_eq!(
+            String::from_utf8(output.into_inner()).expect("Should be UTF-8"),
+            "TX_ID,TIMESTAMP,ACCOUNT,DEBIT,CREDIT,DESCRIPTION\n\
+             1,1633036860000,1000,100,0,\"Record number 1\"\n\
+             1,1633036860000,USER-9223372036854775807,0,100,\"Record number 1\"\n"
+        );
+    }
+
+    #[test]
+    fn test_pg_table() {
+        let dir = std::env::temp_dir().join(format!("converter-pg-table-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let table_path = dir.join("ledger.sql");
+        std::fs::write(
+            &table_path,
+            "CREATE TABLE ledger (id bigserial, amount bigint, tx_id bigint, description text);",
+        )
+        .expect("Should write table");
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            "records.csv",
+            "--output-format",
+            "pgcopy",
+            "--pg-table",
+            table_path.to_str().expect("Should be UTF-8"),
+        ]);
+        let mut output = Cursor::new(Vec::new());
+
+        assert!(run_logic(
+            &mut Cursor::new(create_csv_data(vec![create_test_record(7, 250)])),
+            &CommonParser::new(Format::Csv),
+            &args.output_parser().expect("Should build output parser"),
+            &Pipeline::new(),
+            false,
+            1,
+            &mut output,
+        ));
+        assert_eq!(
+            String::from_utf8(output.into_inner()).expect("Should be UTF-8"),
+            "250\t7\t\"Record number 7\"\n"
+        );
+
+        std::fs::write(&table_path, "CREATE TABLE ledger (id bigserial)").expect("Should write");
+        assert!(
+            Args::try_parse_from([
+                "converter",
+                "--input",
+                "records.csv",
+                "--output-format",
+                "pgcopy",
+                "--pg-table",
+                table_path.to_str().expect("Should be UTF-8"),
+            ])
+            .is_err()
+        );
+        std::fs::remove_dir_all(&dir).expect("Should clean up");
+    }
+
+    #[cfg(feature = "http-source")]
+    #[test]
+    fn test_url_input() {
+        let args = |input: &str, header: &str| {
+            Args::parse_from([
+                "converter",
+                "--input",
+                input,
+                "--input-header",
+                header,
+                "--output-format",
+                "txt",
+            ])
+        };
+
+        let url = args(
+            "http://partner.example/exports/today.csv?token=1",
+            "Authorization: Bearer x",
+        );
+        assert_eq!(url.input_format(), Ok(Format::Csv));
+        assert!(url.http_source(&url.input[0]).is_ok());
+        assert!(matches!(
+            args("http://partner.example/exports/today", "X: 1").input_format(),
+            Err(ParseError::InvalidFormat(_))
+        ));
+        assert!(url.http_source("https://partner.example/today.csv").is_ok());
+        let untrusted = Args::parse_from([
+            "converter",
+            "--input",
+            "https://partner.example/today.csv",
+            "--input-ca-cert",
+            "missing-ca.pem",
+            "--output-format",
+            "txt",
+        ]);
+        assert!(untrusted.http_source(&untrusted.input[0]).is_err());
+
+        let invalid = args("http://partner.example/today.csv", "Authorization");
+        assert!(matches!(
+            invalid.http_source(&invalid.input[0]),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+    }
+
+    #[cfg(feature = "http-sink")]
+    #[test]
+    fn test_post_to_url() {
+        use std::io::{BufRead, BufReader, Read};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("Should bind");
+        let url = format!(
+            "http://{}/ingest",
+            listener.local_addr().expect("Should have address")
+        );
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().expect("Should accept");
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).expect("Should read header");
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().expect("Should be a length");
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).expect("Should read body");
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\n\r\n")
+                .expect("Should respond");
+            String::from_utf8(body).expect("Should be UTF-8")
+        });
+
+        let args = Args::parse_from(["converter", "--input", "records.csv", "--output-url", &url]);
+        assert_eq!(args.output_format, None);
+        let sink = HttpSink::new(&url).expect("Should parse URL");
+        assert!(post_logic(
+            Cursor::new(create_csv_data(vec![
+                create_test_record(1, 100),
+                create_test_record(2, 200)
+            ])),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            false,
+            &sink,
+        ));
+
+        let body = server.join().expect("Should serve");
+        assert!(body.starts_with("[{\"TX_ID\":1,"), "{}", body);
+        assert_eq!(body.matches("\"TX_ID\"").count(), 2);
+        assert_eq!(sink.posted(), 2);
+    }
+
+    #[test]
+    fn test_merge_sorted_by_ts() {
+        let record = |id: u64, ts: u64| {
+            YPBankRecord::new(
+                id,
+                TransactionType::Deposit,
+                0,
+                9223372036854775807,
+                100,
+                ts,
+                TransactionStatus::Success,
+                format!("\"Record number {}\"", id),
+            )
+        };
+
+        let inputs = vec![
+            Cursor::new(create_csv_data(vec![record(1, 10), record(2, 30)])),
+            Cursor::new(create_csv_data(vec![record(3, 20), record(4, 40)])),
+        ];
+        let mut output = Cursor::new(Vec::new());
+
+        merge_logic(
+            inputs,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Txt),
+            SortKey::Ts,
+            &Pipeline::new(),
+            false,
+            &mut output,
+        );
+
+        let output_data = output.into_inner();
+        let parsed_records = parse_output_txt(&output_data);
+        assert_eq!(
+            parsed_records,
+            vec![record(1, 10), record(3, 20), record(2, 30), record(4, 40)]
+        );
+    }
+
+    #[test]
+    fn test_collect_errors() {
+        let header = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n";
+        let bad_row = "2,DEPOSIT,0,9223372036854775807,abc,1633036860000,SUCCESS,Bad\n";
+        let mut input_data = create_csv_data(vec![create_test_record(1, 100)]);
+        let bad_offset = input_data.len();
+        input_data.extend_from_slice(bad_row.as_bytes());
+        let last = create_csv_data(vec![create_test_record(3, 300)]);
+        input_data.extend_from_slice(&last[header.len()..]);
+
+        let options = ParseOptions {
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+        let mut output = Vec::new();
+        let mut rejects = Vec::new();
+        let mut reasons = Vec::new();
+        assert!(collect_errors_logic(
+            input_data.as_slice(),
+            &CommonParser::with_options(Format::Csv, options),
+            &CommonParser::new(Format::Txt),
+            &Pipeline::new(),
+            &mut output,
+            &mut rejects,
+            &mut reasons,
+        ));
+
+        assert_eq!(
+            parse_output_txt(&output),
+            vec![create_test_record(1, 100), create_test_record(3, 300)]
+        );
+        assert_eq!(
+            String::from_utf8(rejects).expect("Should be UTF-8"),
+            format!("{header}{bad_row}")
+        );
+        let reasons = String::from_utf8(reasons).expect("Should be UTF-8");
+        assert!(
+            reasons.starts_with(&format!("{bad_offset}: [E005 invalid-raw-value]")),
+            "{reasons}"
+        );
+        assert_eq!(reasons.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_dedup_ids_across_inputs() {
+        let inputs = vec![
+            Cursor::new(create_csv_data(vec![
+                create_test_record(1, 100),
+                create_test_record(2, 200),
+            ])),
+            Cursor::new(create_csv_data(vec![
+                create_test_record(2, 300),
+                create_test_record(3, 400),
+                create_test_record(1, 500),
+            ])),
+        ];
+        let dir =
+            std::env::temp_dir().join(format!("ypbank-converter-dedup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create temp dir");
+        let mut seen = SeenIds::new(&dir, 1);
+        let mut output = Vec::new();
+
+        let deduped = dedup_logic(
+            inputs,
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            &mut seen,
+            1,
+            &mut output,
+        );
+        drop(seen);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+
+        assert!(deduped);
+        assert_eq!(
+            parse_output_csv(&output)
+                .iter()
+                .map(|record| (record.id, record.amount))
+                .collect::<Vec<_>>(),
+            vec![(1, 100), (2, 200), (3, 400)]
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let dir = std::env::temp_dir().join(format!("converter-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let (input_path, output_path) = (dir.join("records.csv"), dir.join("records.txt"));
+        let input = create_csv_data(
+            (1..=4)
+                .map(|id| create_test_record(id, id as i64 * 100))
+                .collect(),
+        );
+        std::fs::write(&input_path, &input).expect("Should write input");
+        let args = Args::parse_from([
+            "converter",
+            "--input",
+            input_path.to_str().expect("Should be UTF-8"),
+            "--output-format",
+            "txt",
+            "--output",
+            output_path.to_str().expect("Should be UTF-8"),
+            "--manifest",
+            "manifest.json",
+            "--where",
+            "amount >= 200",
+        ]);
+
+        let (records_read, records_written) = (Arc::default(), Arc::default());
+        let mut input_file = Input::File(File::open(&input_path).expect("Should open input"));
+        let converted = run_logic(
+            &mut input_file,
+            &args.input_parser().expect("Should build parser"),
+            &args.output_parser().expect("Should build parser"),
+            &counted(
+                args.pipeline().expect("Should build pipeline"),
+                &records_read,
+                &records_written,
+            ),
+            false,
+            1,
+            &mut File::create(&output_path).expect("Should create output"),
+        );
+        assert!(converted);
+        let manifest = Manifest {
+            started: UNIX_EPOCH,
+            finished: UNIX_EPOCH + std::time::Duration::from_millis(25),
+            inputs: vec![(args.input[0].clone(), None)],
+            records_read: records_read.load(Ordering::Relaxed),
+            records_written: records_written.load(Ordering::Relaxed),
+        };
+        let json = manifest.to_json(&args).expect("Should render manifest");
+        let output_digest = file_digest(&output_path).expect("Should hash output");
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        let mut input_digest = Sha256::new();
+        input_digest.update(&input);
+        for expected in [
+            format!(
+                "\"inputs\":[{}]",
+                json_file(
+                    &args.input[0],
+                    Some(Format::Csv),
+                    &input_digest.finish_hex(),
+                    input.len() as u64
+                )
+            ),
+            format!(
+                "\"outputs\":[{}]",
+                json_file(
+                    &output_path.display().to_string(),
+                    Some(Format::Txt),
+                    &output_digest.0,
+                    output_digest.1
+                )
+            ),
+            "\"records_read\":4,\"records_written\":3".to_string(),
+            "\"where\":[\"amount >= 200\"]".to_string(),
+            "\"duration_ms\":25".to_string(),
+        ] {
+            assert!(json.contains(&expected), "{expected} missing from {json}");
+        }
+        assert_eq!(json_str("a \"b\"\n"), "\"a \\\"b\\\"\\u000a\"");
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let path =
+            std::env::temp_dir().join(format!("ypbank-converter-hash-{}.csv", std::process::id()));
+        std::fs::write(&path, create_csv_data(vec![create_test_record(1, 100)]))
+            .expect("Should write input");
+        let args = |output_format: &str| {
+            Args::parse_from([
+                "converter",
+                "--input",
+                "records.csv",
+                "--input-format",
+                "csv",
+                "--output-format",
+                output_format,
+            ])
+        };
+        let hash = |args: Args| {
+            let mut files = vec![File::open(&path).expect("Should open input")];
+            let hash = args.content_hash(&mut files).expect("Should hash");
+            assert_eq!(files[0].stream_position().expect("Should seek"), 0);
+            hash
+        };
+
+        let first = hash(args("txt"));
+        let second = hash(args("txt"));
+        let other_format = hash(args("binary"));
+        std::fs::write(&path, create_csv_data(vec![create_test_record(1, 200)]))
+            .expect("Should write input");
+        let other_content = hash(args("txt"));
+        std::fs::remove_file(&path).expect("Should remove input");
+
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 64);
+        assert_ne!(first, other_format);
+        assert_ne!(first, other_content);
+    }
+
+    #[test]
+    fn test_write_content_addressed_skips_existing() {
+        let dir =
+            std::env::temp_dir().join(format!("ypbank-converter-output-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+
+        let written = write_content_addressed(&dir, "hash.csv", |file| {
+            file.write_all(b"converted").is_ok()
+        })
+        .expect("Should write");
+        let skipped =
+            write_content_addressed(&dir, "hash.csv", |_| panic!("Should not convert again"))
+                .expect("Should skip");
+        let failed = write_content_addressed(&dir, "failed.csv", |_| false);
+        let content = std::fs::read(dir.join("hash.csv")).expect("Should read output");
+        let files = std::fs::read_dir(&dir).expect("Should list dir").count();
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert_eq!(written, Some(dir.join("hash.csv")));
+        assert_eq!(skipped, None);
+        assert!(matches!(failed, Err(ParseError::IOError(_))));
+        assert_eq!(content, b"converted");
+        assert_eq!(files, 1);
+    }
+
+    struct FailAt(u64);
+
+    impl parser::Stage for FailAt {
+        fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+            if record.id == self.0 {
+                return Err(ParseError::InconsistentRecord("interrupted".to_string()));
+            }
+            Ok(Some(record))
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let dir = std::env::temp_dir().join(format!(
+            "ypbank-converter-checkpoint-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let output_path = dir.join("output.csv");
+        let checkpoint_path = dir.join("checkpoint");
+        let records: Vec<_> = (1..=5).map(|id| create_test_record(id, 100)).collect();
+        let input_data = create_csv_data((1..=5).map(|id| create_test_record(id, 100)).collect());
+
+        let interrupted = checkpoint_logic(
+            Cursor::new(&input_data),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new().with_stage(FailAt(4)),
+            &output_path,
+            &checkpoint_path,
+            2,
+        );
+        let checkpoint = Checkpoint::load(&checkpoint_path).expect("Should load checkpoint");
+
+        let resumed = checkpoint_logic(
+            Cursor::new(&input_data),
+            &CommonParser::new(Format::Csv),
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            &output_path,
+            &checkpoint_path,
+            2,
+        );
+        let output_data = std::fs::read(&output_path).expect("Should read output");
+        let checkpoint_left = checkpoint_path.exists();
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert!(!interrupted);
+        assert_eq!(
+            checkpoint.map(|checkpoint| checkpoint.records_written),
+            Some(2)
+        );
+        assert!(resumed);
+        assert!(!checkpoint_left);
+        assert_eq!(parse_output_csv(&output_data), records);
+    }
+
+    #[test]
+    fn test_checkpoint_resume_with_schema_map() {
+        let dir = std::env::temp_dir().join(format!(
+            "ypbank-converter-checkpoint-schema-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let output_path = dir.join("output.csv");
+        let checkpoint_path = dir.join("checkpoint");
+        let mut input_data =
+            b"id,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n".to_vec();
+        for id in 1..=5 {
+            input_data.extend_from_slice(
+                format!("{id},DEPOSIT,0,1,100,1633036860000,SUCCESS,\"Record {id}\"\n").as_bytes(),
+            );
+        }
+        let options = ParseOptions {
+            csv_schema: Some(
+                SchemaMap::new()
+                    .with_column("id", "TX_ID")
+                    .expect("Should map column"),
+            ),
+            ..ParseOptions::default()
+        };
+        let input_parser = CommonParser::with_options(Format::Csv, options);
+
+        let interrupted = checkpoint_logic(
+            Cursor::new(&input_data),
+            &input_parser,
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new().with_stage(FailAt(4)),
+            &output_path,
+            &checkpoint_path,
+            2,
+        );
+        let resumed = checkpoint_logic(
+            Cursor::new(&input_data),
+            &input_parser,
+            &CommonParser::new(Format::Csv),
+            &Pipeline::new(),
+            &output_path,
+            &checkpoint_path,
+            2,
+        );
+        let output_data = std::fs::read(&output_path).expect("Should read output");
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert!(!interrupted);
+        assert!(resumed);
+        assert_eq!(
+            parse_output_csv(&output_data)
+                .iter()
+                .map(|record| record.id)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+    }
 }
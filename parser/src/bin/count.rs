@@ -0,0 +1,86 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{Format, ParseError, count_records};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Files whose records to count
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Format of the files; inferred from each file's extension or content when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+fn count(path: &Path, format: &Option<String>) -> Result<u64, ParseError> {
+    let format = match format {
+        Some(format) => Format::from_str(format)?,
+        None => Format::infer_from_path(path)?,
+    };
+    count_records(File::open(path)?, format)
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let mut total = 0;
+    let mut succeeded = true;
+    for path in &args.files {
+        match count(path, &args.format) {
+            Ok(count) => {
+                println!("{} {}", count, path.display());
+                total += count;
+            }
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to count {}: [{}] {err}",
+                    path.display(),
+                    err.code()
+                ));
+                succeeded = false;
+            }
+        }
+    }
+    if args.files.len() > 1 {
+        println!("{} total", total);
+    }
+
+    Status::from(succeeded).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{CommonParser, fixtures};
+
+    #[test]
+    fn test_count_infers_format() {
+        let path = std::env::temp_dir().join(format!("count_{}.toml", std::process::id()));
+        CommonParser::new(Format::Toml)
+            .write_to_path_atomic(&path, &fixtures::records(), None)
+            .expect("Should write records");
+
+        let (inferred, given) = (count(&path, &None), count(&path, &Some("csv".to_string())));
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!(inferred, Ok(20));
+        assert!(matches!(given, Err(ParseError::InvalidCsvHeader(_))));
+    }
+}
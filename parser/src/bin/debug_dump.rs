@@ -0,0 +1,444 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::spec::{self, BinarySpec, FieldSpec, FieldType};
+use parser::{ErrorCode, ParseError};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Bytes shown on a line of the dump.
+const BYTES_PER_LINE: usize = 16;
+/// Bytes shown of a range without records, e.g. garbage between two records.
+const MAX_SKIPPED_SHOWN: usize = 64;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Binary file to dump
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Byte offset to start dumping at
+    #[arg(long, default_value_t = 0)]
+    offset: u64,
+
+    /// Dump at most this many records
+    #[arg(long)]
+    records: Option<u64>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// What a dump found.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Summary {
+    records: u64,
+    /// Framing problems: missing magic, truncated fields, invalid values.
+    problems: u64,
+}
+
+/// Writes the bytes of one field, [`BYTES_PER_LINE`] per line, with its name and decoded
+/// value on the first line.
+fn write_field<W: Write>(
+    w: &mut W,
+    offset: usize,
+    bytes: &[u8],
+    name: &str,
+    value: &str,
+) -> std::io::Result<()> {
+    let mut lines = bytes.chunks(BYTES_PER_LINE);
+    let first = lines.next().unwrap_or_default();
+    writeln!(
+        w,
+        "  {:08x}  {:<width$}  {:<16} {}",
+        offset,
+        hex(first),
+        name,
+        value,
+        width = BYTES_PER_LINE * 3 - 1
+    )?;
+    for (index, line) in (1..).zip(lines) {
+        writeln!(
+            w,
+            "  {:08x}  {}",
+            offset + index * BYTES_PER_LINE,
+            hex(line)
+        )?;
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Reads a big-endian unsigned number of up to 8 bytes.
+fn unsigned(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0, |value, byte| (value << 8) | *byte as u64)
+}
+
+/// Decodes a fixed field of the record layout.
+fn decode(spec: &BinarySpec, field: &FieldSpec, bytes: &[u8]) -> Result<String, String> {
+    match field.field_type {
+        FieldType::I64 => Ok(unsigned(bytes).cast_signed().to_string()),
+        FieldType::U8 => {
+            let value = bytes[0];
+            let name = spec
+                .enums
+                .iter()
+                .find(|spec| spec.field == field.name)
+                .map(|spec| spec.values.iter().find(|(known, _)| *known == value));
+            match name {
+                Some(Some((_, name))) => Ok(format!("{} ({})", value, name)),
+                Some(None) => Err(format!("{} (invalid)", value)),
+                None => Ok(value.to_string()),
+            }
+        }
+        FieldType::U32 | FieldType::U64 => Ok(unsigned(bytes).to_string()),
+        FieldType::Bytes | FieldType::Utf8 if bytes == spec.magic => {
+            Ok(format!("{:?}", String::from_utf8_lossy(bytes)))
+        }
+        FieldType::Bytes | FieldType::Utf8 => Err("invalid magic".to_string()),
+    }
+}
+
+/// Dumps the records of binary `data` from `start`, going on after framing errors as far
+/// as it can: garbage is skipped up to the next magic, and a truncated record ends the
+/// dump.
+struct Dumper<'a, W: Write> {
+    data: &'a [u8],
+    spec: &'a BinarySpec,
+    w: &'a mut W,
+    summary: Summary,
+}
+
+impl<W: Write> Dumper<'_, W> {
+    /// Writes the bytes at `offset` of a field of `size` bytes, returning them, or `None`
+    /// after writing the bytes left when the data ends first.
+    fn field(
+        &mut self,
+        offset: usize,
+        size: usize,
+        name: &str,
+        decode: impl FnOnce(&[u8]) -> Result<String, String>,
+    ) -> std::io::Result<Option<&[u8]>> {
+        let data = self.data;
+        let end = offset.saturating_add(size);
+        if end > data.len() {
+            let left = &data[offset.min(data.len())..];
+            let message = format!("truncated: {} of {} bytes", left.len(), size);
+            write_field(self.w, offset, left, name, &message)?;
+            self.summary.problems += 1;
+            return Ok(None);
+        }
+
+        let bytes = &data[offset..end];
+        let value = decode(bytes).unwrap_or_else(|problem| {
+            self.summary.problems += 1;
+            problem
+        });
+        write_field(self.w, offset, bytes, name, &value)?;
+        Ok(Some(bytes))
+    }
+
+    /// Dumps the record at `start`, returning where the next one starts, or `None` when
+    /// the records end there.
+    fn record(&mut self, start: usize) -> Result<Option<usize>, ParseError> {
+        writeln!(
+            self.w,
+            "Record {} at {:#010x}",
+            self.summary.records + 1,
+            start
+        )?;
+        self.summary.records += 1;
+
+        let spec = self.spec;
+        let fixed: Vec<&FieldSpec> = spec
+            .record
+            .iter()
+            .filter(|field| field.offset.is_some() && field.size.is_some())
+            .collect();
+        let mut record_size = 0;
+        let mut description_len = 0;
+        let mut fields_end = 0;
+        for field in &fixed {
+            let offset = field.offset.unwrap_or_default() as usize;
+            let size = field.size.unwrap_or_default() as usize;
+            let decoded = |bytes: &[u8]| decode(spec, field, bytes);
+            let Some(bytes) = self.field(start + offset, size, field.name, decoded)? else {
+                return Ok(None);
+            };
+            match field.name {
+                "RECORD_SIZE" => record_size = unsigned(bytes) as usize,
+                "DESCRIPTION_LEN" => description_len = unsigned(bytes) as usize,
+                _ => {}
+            }
+            if field.name == "RECORD_SIZE" && record_size == 0 {
+                writeln!(self.w, "  end of records")?;
+                return Ok(None);
+            }
+            fields_end = offset + size;
+        }
+
+        let description = start + fields_end;
+        let decoded = |bytes: &[u8]| match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(format!("{:?}", text)),
+            Err(_) => Err("invalid UTF-8".to_string()),
+        };
+        if self
+            .field(description, description_len, "DESCRIPTION", decoded)?
+            .is_none()
+        {
+            return Ok(None);
+        }
+
+        // RECORD_SIZE counts the bytes after the magic and itself.
+        let header = fixed
+            .iter()
+            .find(|field| field.name == "TX_ID")
+            .and_then(|field| field.offset)
+            .unwrap_or_default() as usize;
+        let fields_size = fields_end - header + description_len;
+        if record_size < fields_size {
+            writeln!(
+                self.w,
+                "  RECORD_SIZE {} is less than the {} bytes of the fields",
+                record_size, fields_size
+            )?;
+            self.summary.problems += 1;
+        } else if record_size > fields_size {
+            let extension = description + description_len;
+            if !self.extension(extension, record_size - fields_size)? {
+                return Ok(None);
+            }
+        }
+        Ok(Some(start + header + record_size.max(fields_size)))
+    }
+
+    /// Dumps the extension entries of `len` bytes at `start`, returning whether they are
+    /// all in the data.
+    fn extension(&mut self, start: usize, len: usize) -> Result<bool, ParseError> {
+        let end = start + len;
+        let mut offset = start;
+        while offset < end {
+            let Some(header) = self.data.get(offset..(offset + 5).min(end)) else {
+                self.field(offset, end - offset, "EXTENSION", |_| Ok(String::new()))?;
+                return Ok(false);
+            };
+            if header.len() < 5 {
+                self.field(offset, end - offset, "EXTENSION", |_| {
+                    Err("invalid entry".to_string())
+                })?;
+                return Ok(true);
+            }
+            let tag = header[0];
+            let data_len = unsigned(&header[1..5]) as usize;
+            let entry_len = (5 + data_len).min(end - offset);
+            let complete = 5 + data_len <= end - offset;
+            let decoded = |entry: &[u8]| match tag {
+                _ if !complete => Err("invalid entry length".to_string()),
+                1 => extra(&entry[5..]),
+                _ => Ok(format!("tag {}, {} bytes", tag, data_len)),
+            };
+            if self
+                .field(offset, entry_len, "EXTENSION", decoded)?
+                .is_none()
+            {
+                return Ok(false);
+            }
+            offset += entry_len;
+        }
+        Ok(true)
+    }
+
+    /// Writes the bytes from `start` up to the next magic, returning where it is.
+    fn skip(&mut self, start: usize) -> std::io::Result<Option<usize>> {
+        let next = self.data[start + 1..]
+            .windows(self.spec.magic.len())
+            .position(|window| window == self.spec.magic)
+            .map(|position| start + 1 + position);
+        let end = next.unwrap_or(self.data.len());
+        let shown = (end - start).min(MAX_SKIPPED_SHOWN);
+        let message = match next {
+            Some(next) => format!("no magic; {} bytes skipped to {:#010x}", end - start, next),
+            None => format!("no magic; {} bytes to the end", end - start),
+        };
+        write_field(
+            self.w,
+            start,
+            &self.data[start..start + shown],
+            "(skipped)",
+            &message,
+        )?;
+        self.summary.problems += 1;
+        Ok(next)
+    }
+}
+
+/// Decodes the data of an extra entry, `NAME_LEN`, `NAME` and `VALUE`.
+fn extra(data: &[u8]) -> Result<String, String> {
+    let invalid = || "invalid extra".to_string();
+    let name_len = data.first_chunk::<4>().ok_or_else(invalid)?;
+    let name_len = u32::from_be_bytes(*name_len) as usize;
+    let (name, value) = data[4..].split_at_checked(name_len).ok_or_else(invalid)?;
+    match (std::str::from_utf8(name), std::str::from_utf8(value)) {
+        (Ok(name), Ok(value)) => Ok(format!("extra {:?} = {:?}", name, value)),
+        _ => Err(invalid()),
+    }
+}
+
+fn dump<W: Write>(
+    data: &[u8],
+    start: usize,
+    max_records: Option<u64>,
+    w: &mut W,
+) -> Result<Summary, ParseError> {
+    let spec = spec::binary();
+    let mut dumper = Dumper {
+        data,
+        spec: &spec,
+        w,
+        summary: Summary::default(),
+    };
+    let mut offset = start;
+    while offset < data.len() && max_records.is_none_or(|max| dumper.summary.records < max) {
+        if !data[offset..].starts_with(&dumper.spec.magic) {
+            match dumper.skip(offset)? {
+                Some(next) => offset = next,
+                None => break,
+            }
+            continue;
+        }
+        match dumper.record(offset)? {
+            Some(next) => offset = next,
+            None => break,
+        }
+    }
+    Ok(dumper.summary)
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let data = match std::fs::read(&args.file) {
+        Ok(data) => data,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to read {}: [{}] {err}",
+                args.file.display(),
+                ErrorCode::Io
+            ));
+            return Status::Failure.into();
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    match dump(&data, args.offset as usize, args.records, &mut stdout) {
+        Ok(summary) => {
+            cli::summary(format!(
+                "Dumped {} records, found {} problems",
+                summary.records, summary.problems
+            ));
+            if summary.problems == 0 {
+                Status::Success.into()
+            } else {
+                Status::Mismatch.into()
+            }
+        }
+        Err(err) => {
+            cli::error(format!("Failed to write dump: [{}] {err}", err.code()));
+            Status::Failure.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{encode_bin_record, fixtures};
+
+    fn dumped(data: &[u8], max_records: Option<u64>) -> (Summary, String) {
+        let mut out = Vec::new();
+        let summary = dump(data, 0, max_records, &mut out).unwrap();
+        (summary, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut data = Vec::new();
+        for record in fixtures::records() {
+            encode_bin_record(&record, &mut data);
+        }
+
+        let (summary, out) = dumped(&data, None);
+        assert_eq!(summary.records, fixtures::records().len() as u64);
+        assert_eq!(summary.problems, 0);
+        assert!(out.starts_with("Record 1 at 0x00000000\n  00000000  59 50 42 4e"));
+        assert!(out.contains("TX_TYPE          0 (DEPOSIT)"));
+
+        let (summary, _) = dumped(&data, Some(2));
+        assert_eq!(summary.records, 2);
+    }
+
+    #[test]
+    fn test_dump_corrupted() {
+        let records = fixtures::records();
+        let mut data = Vec::new();
+        encode_bin_record(&records[0], &mut data);
+        // Transaction type 9 does not exist.
+        data[16] = 9;
+        data.extend_from_slice(b"garbage");
+        let second = data.len();
+        encode_bin_record(&records[1], &mut data);
+        data.truncate(data.len() - 1);
+
+        let (summary, out) = dumped(&data, None);
+        assert_eq!(
+            summary,
+            Summary {
+                records: 2,
+                problems: 3
+            }
+        );
+        assert!(out.contains("TX_TYPE          9 (invalid)"));
+        assert!(out.contains(&format!("no magic; 7 bytes skipped to {:#010x}", second)));
+        assert!(out.contains("DESCRIPTION      truncated:"));
+    }
+
+    #[test]
+    fn test_dump_extension() {
+        let mut data = Vec::new();
+        encode_bin_record(&fixtures::records()[0], &mut data);
+        let mut entry = vec![1, 0, 0, 0, 10, 0, 0, 0, 3];
+        entry.extend_from_slice(b"keyval");
+        let size = u32::from_be_bytes(data[4..8].try_into().unwrap()) + entry.len() as u32;
+        data[4..8].copy_from_slice(&size.to_be_bytes());
+        data.extend_from_slice(&entry);
+        encode_bin_record(&fixtures::records()[1], &mut data);
+
+        let (summary, out) = dumped(&data, None);
+        assert_eq!(
+            summary,
+            Summary {
+                records: 2,
+                problems: 0
+            }
+        );
+        assert!(out.contains("EXTENSION        extra \"key\" = \"val\""));
+    }
+}
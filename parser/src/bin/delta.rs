@@ -0,0 +1,304 @@
+use clap::{CommandFactory, Parser, Subcommand};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{CommonParser, Delta, ErrorCode, Format, ParseError};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes the records added, removed or changed between two files
+    Create {
+        #[arg(long)]
+        old: String,
+
+        #[arg(long)]
+        new: String,
+
+        /// Format of both input files
+        #[arg(long)]
+        input_format: String,
+
+        /// Format of the records inside the delta: csv, txt or binary
+        #[arg(long)]
+        delta_format: String,
+    },
+    /// Rebuilds the new file from the old file and a delta
+    ApplyDelta {
+        #[arg(long)]
+        old: String,
+
+        #[arg(long)]
+        delta: String,
+
+        /// Format of the old file and of the output
+        #[arg(long)]
+        input_format: String,
+
+        /// Format of the records inside the delta
+        #[arg(long)]
+        delta_format: String,
+    },
+}
+
+fn create_delta<R: std::io::Read, W: std::io::Write>(
+    old_file: &mut R,
+    new_file: &mut R,
+    input_format: Format,
+    delta_format: Format,
+    output_file: &mut W,
+) -> bool {
+    let input_parser = CommonParser::new(input_format);
+    let old_records = match input_parser.from_read(old_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read old file: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    let new_records = match input_parser.from_read(new_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read new file: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+
+    let delta = match Delta::between(old_records, new_records) {
+        Ok(delta) => delta,
+        Err(err) => {
+            cli::error(format!("Failed to compute delta: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    if let Err(err) = delta.write_to(output_file, &CommonParser::new(delta_format)) {
+        cli::error(format!("Failed to write delta: [{}] {err}", err.code()));
+        return false;
+    }
+    true
+}
+
+fn apply_delta<R: std::io::Read, W: std::io::Write>(
+    old_file: &mut R,
+    delta_file: &mut R,
+    input_format: Format,
+    delta_format: Format,
+    output_file: &mut W,
+) -> bool {
+    let input_parser = CommonParser::new(input_format);
+    let old_records = match input_parser.from_read(old_file) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to read old file: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    let delta = match Delta::from_read(delta_file, &CommonParser::new(delta_format)) {
+        Ok(delta) => delta,
+        Err(err) => {
+            cli::error(format!("Failed to read delta: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+
+    let records = match delta.apply(old_records) {
+        Ok(records) => records,
+        Err(err) => {
+            cli::error(format!("Failed to apply delta: [{}] {err}", err.code()));
+            return false;
+        }
+    };
+    if let Err(err) = input_parser.write_to(output_file, &records) {
+        cli::error(format!("Failed to write output: [{}] {err}", err.code()));
+        return false;
+    }
+    true
+}
+
+fn parse_formats(input_format: &str, delta_format: &str) -> Result<(Format, Format), ParseError> {
+    Ok((
+        Format::from_str(input_format)?,
+        Format::from_str(delta_format)?,
+    ))
+}
+
+fn open_file(path: &str) -> Option<std::fs::File> {
+    match std::fs::File::open(path) {
+        Ok(file) => Some(file),
+        Err(err) => {
+            cli::error(format!(
+                "Failed to open file {path}: [{}] {err}",
+                ErrorCode::Io
+            ));
+            None
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+    let mut output_file = std::io::stdout();
+
+    match args.command {
+        Command::Create {
+            old,
+            new,
+            input_format,
+            delta_format,
+        } => {
+            let (input_format, delta_format) = match parse_formats(&input_format, &delta_format) {
+                Ok(formats) => formats,
+                Err(err) => {
+                    cli::error(format!("Invalid format: [{}] {err}", err.code()));
+                    return Status::Usage.into();
+                }
+            };
+            let (Some(mut old_file), Some(mut new_file)) = (open_file(&old), open_file(&new))
+            else {
+                return Status::Failure.into();
+            };
+
+            Status::from(create_delta(
+                &mut old_file,
+                &mut new_file,
+                input_format,
+                delta_format,
+                &mut output_file,
+            ))
+            .into()
+        }
+        Command::ApplyDelta {
+            old,
+            delta,
+            input_format,
+            delta_format,
+        } => {
+            let (input_format, delta_format) = match parse_formats(&input_format, &delta_format) {
+                Ok(formats) => formats,
+                Err(err) => {
+                    cli::error(format!("Invalid format: [{}] {err}", err.code()));
+                    return Status::Usage.into();
+                }
+            };
+            let (Some(mut old_file), Some(mut delta_file)) = (open_file(&old), open_file(&delta))
+            else {
+                return Status::Failure.into();
+            };
+
+            Status::from(apply_delta(
+                &mut old_file,
+                &mut delta_file,
+                input_format,
+                delta_format,
+                &mut output_file,
+            ))
+            .into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TransactionStatus, TransactionType, YPBankRecord};
+    use std::io::Cursor;
+
+    fn create_test_record(id: u64, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            amount,
+            1633036860000,
+            TransactionStatus::Success,
+            format!("\"Record number {}\"", id),
+        )
+    }
+
+    fn write_records(format: Format, records: &Vec<YPBankRecord>) -> Vec<u8> {
+        let mut data = Vec::new();
+        CommonParser::new(format)
+            .write_to(&mut data, records)
+            .expect("Should write records");
+        data
+    }
+
+    #[test]
+    fn test_create_and_apply_delta() {
+        let old_records = vec![
+            create_test_record(1000000000000000, 100),
+            create_test_record(1000000000000001, 200),
+        ];
+        let new_records = vec![
+            create_test_record(1000000000000000, 150),
+            create_test_record(1000000000000002, 300),
+        ];
+
+        let mut old = Cursor::new(write_records(Format::Csv, &old_records));
+        let mut new = Cursor::new(write_records(Format::Csv, &new_records));
+        let mut delta = Cursor::new(Vec::new());
+        assert!(create_delta(
+            &mut old,
+            &mut new,
+            Format::Csv,
+            Format::Bin,
+            &mut delta
+        ));
+
+        let mut old = Cursor::new(write_records(Format::Csv, &old_records));
+        let mut delta = Cursor::new(delta.into_inner());
+        let mut output = Cursor::new(Vec::new());
+        assert!(apply_delta(
+            &mut old,
+            &mut delta,
+            Format::Csv,
+            Format::Bin,
+            &mut output
+        ));
+
+        let mut reader = Cursor::new(output.into_inner());
+        let parsed_records = CommonParser::new(Format::Csv)
+            .from_read(&mut reader)
+            .expect("Should parse output as CSV");
+        assert_eq!(parsed_records, new_records);
+    }
+
+    #[test]
+    fn test_create_delta_identical_files() {
+        let records = vec![create_test_record(1000000000000000, 100)];
+
+        let mut old = Cursor::new(write_records(Format::Txt, &records));
+        let mut new = Cursor::new(write_records(Format::Txt, &records));
+        let mut delta = Cursor::new(Vec::new());
+        assert!(create_delta(
+            &mut old,
+            &mut new,
+            Format::Txt,
+            Format::Csv,
+            &mut delta
+        ));
+
+        assert_eq!(
+            delta.into_inner(),
+            b"YPBANK-DELTA\nTX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n"
+        );
+    }
+}
@@ -0,0 +1,494 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{
+    CommonParser, ErrorCode, Format, ParseError, TransactionStatus, TransactionType, YPBankRecord,
+};
+use std::io::{BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// File to edit in place
+    #[arg(long)]
+    file: PathBuf,
+
+    #[arg(long)]
+    format: String,
+
+    /// File with one command per line; commands are read from stdin without it
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    /// `set <id> <field> <value>`; the value runs to the end of the line.
+    Set {
+        id: u64,
+        field: String,
+        value: String,
+    },
+    /// `delete <id>`
+    Delete(u64),
+    /// `add <csv row>`
+    Add(YPBankRecord),
+    /// `show <id>`
+    Show(u64),
+    /// `write`
+    Write,
+    /// `quit`
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (name, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let rest = rest.trim();
+        let id = |s: &str| {
+            s.parse::<u64>().map_err(|_| ParseError::InvalidRawValue {
+                field: "TX_ID".to_string(),
+                value: s.to_string(),
+            })
+        };
+
+        match (name.to_lowercase().as_str(), rest) {
+            ("set", rest) => {
+                let mut parts = rest.splitn(3, ' ');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(record_id), Some(field), Some(value)) => Ok(Command::Set {
+                        id: id(record_id)?,
+                        field: field.to_uppercase(),
+                        value: value.trim().to_string(),
+                    }),
+                    _ => Err(ParseError::InvalidRow(line.to_string())),
+                }
+            }
+            ("delete", record_id) if !record_id.is_empty() => Ok(Command::Delete(id(record_id)?)),
+            ("show", record_id) if !record_id.is_empty() => Ok(Command::Show(id(record_id)?)),
+            ("add", row) if !row.is_empty() => Ok(Command::Add(parse_csv_row(row)?)),
+            ("write", "") => Ok(Command::Write),
+            ("quit", "") => Ok(Command::Quit),
+            _ => Err(ParseError::InvalidRow(line.to_string())),
+        }
+    }
+}
+
+/// Parses a record from a CSV row without header, e.g. `1,DEPOSIT,0,2,100,1633036860000,SUCCESS,"Salary"`.
+fn parse_csv_row(row: &str) -> Result<YPBankRecord, ParseError> {
+    let parser = CommonParser::new(Format::Csv);
+    let mut data = Vec::new();
+    parser.write_to(&mut data, &Vec::new())?;
+    data.extend_from_slice(row.as_bytes());
+    data.push(b'\n');
+
+    let mut records = parser.from_read(&mut data.as_slice())?;
+    match records.pop() {
+        Some(record) if records.is_empty() => Ok(record),
+        _ => Err(ParseError::InvalidRow(row.to_string())),
+    }
+}
+
+fn set_field(record: &mut YPBankRecord, field: &str, value: &str) -> Result<(), ParseError> {
+    // Only numeric fields are parsed, so a well-formed integer fails by not fitting.
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let invalid = || match !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        true => ParseError::ValueOutOfRange {
+            field: field.to_string(),
+            value: value.to_string(),
+        },
+        false => ParseError::InvalidRawValue {
+            field: field.to_string(),
+            value: value.to_string(),
+        },
+    };
+    match field {
+        "TX_ID" => record.id = value.parse().map_err(|_| invalid())?,
+        "TX_TYPE" => record.transaction_type = TransactionType::from_str(value)?,
+        "FROM_USER_ID" => record.from_user_id = value.parse().map_err(|_| invalid())?,
+        "TO_USER_ID" => record.to_user_id = value.parse().map_err(|_| invalid())?,
+        "AMOUNT" => record.amount = value.parse().map_err(|_| invalid())?,
+        "TIMESTAMP" => record.ts = value.parse().map_err(|_| invalid())?,
+        "STATUS" => record.status = TransactionStatus::from_str(value)?,
+        "DESCRIPTION" => record.description = value.into(),
+        _ => return Err(ParseError::FieldNotFound(field.to_string())),
+    }
+    Ok(())
+}
+
+/// Records of the edited file, changed by commands and saved with [`Editor::save`].
+struct Editor {
+    records: Vec<YPBankRecord>,
+    changed: bool,
+}
+
+impl Editor {
+    fn new(records: Vec<YPBankRecord>) -> Self {
+        Self {
+            records,
+            changed: false,
+        }
+    }
+
+    fn position(&self, id: u64) -> Result<usize, ParseError> {
+        self.records
+            .iter()
+            .position(|record| record.id == id)
+            .ok_or_else(|| ParseError::InconsistentRecord(format!("record {} is missing", id)))
+    }
+
+    fn ensure_new_id(&self, id: u64) -> Result<(), ParseError> {
+        match self.position(id) {
+            Ok(_) => Err(ParseError::InconsistentRecord(format!(
+                "record {} already exists",
+                id
+            ))),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Applies a command that changes or shows records.
+    fn apply(&mut self, command: Command) -> Result<(), ParseError> {
+        match command {
+            Command::Set { id, field, value } => {
+                let position = self.position(id)?;
+                let mut edited = self.records[position].clone();
+                set_field(&mut edited, &field, &value)?;
+                if edited.id != id {
+                    self.ensure_new_id(edited.id)?;
+                }
+                self.records[position] = edited;
+            }
+            Command::Delete(id) => {
+                let position = self.position(id)?;
+                self.records.remove(position);
+            }
+            Command::Add(record) => {
+                self.ensure_new_id(record.id)?;
+                self.records.push(record);
+            }
+            Command::Show(id) => {
+                println!("{:?}", self.records[self.position(id)?]);
+                return Ok(());
+            }
+            Command::Write | Command::Quit => return Ok(()),
+        }
+        self.changed = true;
+        Ok(())
+    }
+
+    /// Writes the records to `path` in `format`.
+    ///
+    /// The output is read back before it replaces the file, so records the format
+    /// cannot hold, such as a transfer from user `0`, leave the file untouched.
+    fn save(&mut self, path: &Path, format: Format) -> Result<(), ParseError> {
+        let parser = CommonParser::new(format);
+        let mut data = Vec::new();
+        parser.write_to(&mut data, &self.records)?;
+        if parser.from_read(&mut data.as_slice())? != self.records {
+            return Err(ParseError::InconsistentRecord(
+                "records change when written".to_string(),
+            ));
+        }
+
+        parser.write_to_path_atomic(path, &self.records, None)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Runs the commands of `input` against `editor`, saving to `path` on `write` and at the end.
+///
+/// In a script the first failing command stops the run before anything is saved;
+/// interactively failures are reported and the session goes on. Returns whether all
+/// commands succeeded.
+fn run_commands<R: BufRead>(
+    input: R,
+    editor: &mut Editor,
+    path: &Path,
+    format: Format,
+    interactive: bool,
+) -> bool {
+    let mut succeeded = true;
+    for (index, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to read commands: [{}] {err}",
+                    ErrorCode::Io
+                ));
+                return false;
+            }
+        };
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let command = Command::from_str(&line);
+        let result = match command {
+            Ok(Command::Write) => editor.save(path, format),
+            Ok(Command::Quit) => break,
+            Ok(command) => editor.apply(command),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            cli::error(format!("Line {}: [{}] {err}", index + 1, err.code()));
+            if !interactive {
+                return false;
+            }
+            succeeded = false;
+        }
+    }
+
+    if editor.changed
+        && let Err(err) = editor.save(path, format)
+    {
+        cli::error(format!(
+            "Failed to write {}: [{}] {err}",
+            path.display(),
+            err.code()
+        ));
+        return false;
+    }
+    succeeded
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match Format::from_str(&args.format) {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid format {}: [{}] {err}",
+                args.format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+    let records = match std::fs::File::open(&args.file) {
+        Ok(mut file) => CommonParser::new(format).from_read(&mut file),
+        Err(err) => {
+            cli::error(format!(
+                "Failed to open file {}: [{}] {err}",
+                args.file.display(),
+                ErrorCode::Io
+            ));
+            return Status::Failure.into();
+        }
+    };
+    let mut editor = match records {
+        Ok(records) => Editor::new(records),
+        Err(err) => {
+            cli::error(format!("Failed to read file: [{}] {err}", err.code()));
+            return Status::Failure.into();
+        }
+    };
+
+    let succeeded = match &args.script {
+        Some(script) => match std::fs::File::open(script) {
+            Ok(script) => {
+                let input = std::io::BufReader::new(script);
+                run_commands(input, &mut editor, &args.file, format, false)
+            }
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to open script {}: [{}] {err}",
+                    script.display(),
+                    ErrorCode::Io
+                ));
+                false
+            }
+        },
+        None => {
+            let stdin = std::io::stdin();
+            let interactive = stdin.is_terminal();
+            if interactive {
+                cli::summary(format!(
+                    "{} records loaded; commands: set, delete, add, show, write, quit",
+                    editor.records.len()
+                ));
+            }
+            run_commands(stdin.lock(), &mut editor, &args.file, format, interactive)
+        }
+    };
+    Status::from(succeeded).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_record(id: u64, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            amount,
+            1633036860000,
+            TransactionStatus::Success,
+            format!("Record number {}", id),
+        )
+    }
+
+    fn write_file(name: &str, format: Format, records: &Vec<YPBankRecord>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("edit_{}_{}", std::process::id(), name));
+        let mut data = Vec::new();
+        CommonParser::new(format)
+            .write_to(&mut data, records)
+            .expect("Should write records");
+        std::fs::write(&path, data).expect("Should write file");
+        path
+    }
+
+    fn read_file(path: &Path, format: Format) -> Vec<YPBankRecord> {
+        let mut file = std::fs::File::open(path).expect("Should open file");
+        CommonParser::new(format)
+            .from_read(&mut file)
+            .expect("Should read records")
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            Command::from_str("set 12345 description Paid in full"),
+            Ok(Command::Set {
+                id: 12345,
+                field: "DESCRIPTION".to_string(),
+                value: "Paid in full".to_string(),
+            })
+        );
+        assert_eq!(Command::from_str("DELETE 999"), Ok(Command::Delete(999)));
+        assert_eq!(
+            Command::from_str("add 1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary"),
+            Ok(Command::Add(YPBankRecord::new(
+                1,
+                TransactionType::Deposit,
+                0,
+                2,
+                100,
+                1633036860000,
+                TransactionStatus::Success,
+                "Salary",
+            )))
+        );
+        assert!(matches!(
+            Command::from_str("delete"),
+            Err(ParseError::InvalidRow(_))
+        ));
+        assert!(matches!(
+            Command::from_str("delete abc"),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+    }
+
+    #[test]
+    fn test_set_field_extreme_amounts() {
+        let mut record = create_test_record(1, 100);
+        set_field(&mut record, "AMOUNT", "-9223372036854775808").expect("Should set");
+        assert_eq!(record.amount, i64::MIN);
+        assert_eq!(
+            set_field(&mut record, "AMOUNT", "9223372036854775808"),
+            Err(ParseError::ValueOutOfRange {
+                field: "AMOUNT".to_string(),
+                value: "9223372036854775808".to_string(),
+            })
+        );
+        assert!(matches!(
+            set_field(&mut record, "AMOUNT", "12a"),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+        assert_eq!(record.amount, i64::MIN);
+    }
+
+    #[test]
+    fn test_run_script() {
+        let path = write_file(
+            "script.bin",
+            Format::Bin,
+            &vec![create_test_record(1, 100), create_test_record(2, 200)],
+        );
+        let script = "# corrections\nset 1 status FAILURE\ndelete 2\nadd 3,WITHDRAWAL,5,0,50,1633036860000,PENDING,ATM\n";
+
+        let mut editor = Editor::new(read_file(&path, Format::Bin));
+        assert!(run_commands(
+            script.as_bytes(),
+            &mut editor,
+            &path,
+            Format::Bin,
+            false
+        ));
+
+        let records = read_file(&path, Format::Bin);
+        std::fs::remove_file(&path).expect("Should remove file");
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].status, TransactionStatus::Failure);
+        assert_eq!(records[1].id, 3);
+        assert_eq!(&*records[1].description, "ATM");
+    }
+
+    #[test]
+    fn test_run_script_failure_keeps_file() {
+        let records = vec![create_test_record(1, 100)];
+        let path = write_file("failure.csv", Format::Csv, &records);
+
+        let mut editor = Editor::new(read_file(&path, Format::Csv));
+        assert!(!run_commands(
+            "set 1 amount 5\ndelete 7\n".as_bytes(),
+            &mut editor,
+            &path,
+            Format::Csv,
+            false
+        ));
+
+        let unchanged = read_file(&path, Format::Csv);
+        std::fs::remove_file(&path).expect("Should remove file");
+        assert_eq!(unchanged, records);
+    }
+
+    #[test]
+    fn test_save_validates_records() {
+        let path = write_file(
+            "invalid.txt",
+            Format::Txt,
+            &vec![create_test_record(1, 100)],
+        );
+
+        let mut editor = Editor::new(read_file(&path, Format::Txt));
+        let result = editor
+            .apply(Command::from_str("set 1 tx_type TRANSFER").expect("Should parse"))
+            .and_then(|_| editor.save(&path, Format::Txt));
+
+        std::fs::remove_file(&path).expect("Should remove file");
+        assert!(matches!(result, Err(ParseError::InvalidUserId(..))));
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let mut editor = Editor::new(vec![create_test_record(1, 100), create_test_record(2, 200)]);
+
+        assert!(matches!(
+            editor.apply(Command::from_str("set 1 tx_id 2").expect("Should parse")),
+            Err(ParseError::InconsistentRecord(_))
+        ));
+        assert!(!editor.changed);
+    }
+}
@@ -0,0 +1,188 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, Target, VerbosityArgs};
+use parser::{
+    ErasureMode, ErasureReport, Format, ParseError, RecordStore, UserErasure, erase_user_in_file,
+};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Id of the user to erase
+    #[arg(long)]
+    user: u64,
+
+    /// File, directory of files or record store to erase the user from; may be repeated
+    #[arg(long, required = true)]
+    input: Vec<PathBuf>,
+
+    /// Format of the input files; inferred from their extension when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// What happens to the records of the user: remove or anonymize
+    #[arg(long, default_value = "anonymize")]
+    mode: String,
+
+    /// File the signed report of the affected records is written to
+    #[arg(long, value_name = "FILE")]
+    report: PathBuf,
+
+    /// File holding the key the report is signed with
+    #[arg(long, value_name = "FILE")]
+    key_file: PathBuf,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+fn erase(
+    target: &Target,
+    erasure: &UserErasure,
+    report: &mut ErasureReport,
+) -> Result<u64, ParseError> {
+    match target {
+        Target::File(path, format) => erase_user_in_file(path, *format, erasure, report),
+        Target::Store(dir) => RecordStore::open(dir)?.erase_user(erasure, report),
+    }
+}
+
+/// Erases the user from every target in turn, stopping at the first failure.
+fn erase_all<'a>(
+    targets: &'a [Target],
+    erasure: &UserErasure,
+    report: &mut ErasureReport,
+) -> Result<(), (&'a Target, ParseError)> {
+    for target in targets {
+        let erased = erase(target, erasure, report).map_err(|err| (target, err))?;
+        println!("{}: {} erased", target.path().display(), erased);
+    }
+    Ok(())
+}
+
+fn write_report(path: &Path, report: &ErasureReport, key: &[u8]) -> Result<(), ParseError> {
+    let mut w = BufWriter::new(File::create(path)?);
+    report.write_signed(&mut w, key)?;
+    w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let erasure = ErasureMode::from_str(&args.mode)
+        .and_then(|mode| UserErasure::new(args.user, mode))
+        .and_then(|erasure| {
+            let format = args.format.as_deref().map(Format::from_str).transpose()?;
+            Ok((erasure, cli::targets(&args.input, format)?))
+        });
+    let (erasure, targets) = match erasure {
+        Ok(erasure) => erasure,
+        Err(err) => {
+            cli::error(format!("Invalid arguments: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let key = match std::fs::read(&args.key_file) {
+        Ok(key) if !key.trim_ascii().is_empty() => key.trim_ascii().to_vec(),
+        Ok(_) => {
+            cli::error(format!("Key file {} is empty", args.key_file.display()));
+            return Status::Usage.into();
+        }
+        Err(err) => {
+            cli::error(format!("Failed to read {}: {err}", args.key_file.display()));
+            return Status::Usage.into();
+        }
+    };
+
+    // The report is written after a failure too, listing what was erased before it.
+    let mut report = ErasureReport::new(&erasure);
+    let result = erase_all(&targets, &erasure, &mut report);
+    if let Err(err) = write_report(&args.report, &report, &key) {
+        cli::error(format!(
+            "Failed to write report {}: [{}] {err}",
+            args.report.display(),
+            err.code()
+        ));
+        return Status::Failure.into();
+    }
+    if let Err((target, err)) = result {
+        cli::error(format!(
+            "Failed to erase user {} from {}: [{}] {err}",
+            args.user,
+            target.path().display(),
+            err.code()
+        ));
+        return Status::Failure.into();
+    }
+
+    cli::summary(format!(
+        "Erased {} records of user {} from {} inputs",
+        report.records().len(),
+        args.user,
+        targets.len()
+    ));
+    Status::Success.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{CommonParser, TransactionStatus, TransactionType, YPBankRecord};
+
+    #[test]
+    fn test_erase_all_reports_files() {
+        let dir = std::env::temp_dir().join(format!("erase_user_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let record = |id, from, to| {
+            YPBankRecord::new(
+                id,
+                TransactionType::Transfer,
+                from,
+                to,
+                100,
+                id,
+                TransactionStatus::Success,
+                "Rent",
+            )
+        };
+        for (name, records) in [
+            ("2021-10.csv", vec![record(1, 7, 8), record(2, 8, 9)]),
+            ("2021-11.csv", vec![record(3, 9, 7)]),
+        ] {
+            CommonParser::new(Format::Csv)
+                .write_to(
+                    &mut File::create(dir.join(name)).expect("Should create file"),
+                    &records,
+                )
+                .expect("Should write records");
+        }
+
+        let erasure = UserErasure::new(7, ErasureMode::Remove).expect("Should be a user");
+        let mut report = ErasureReport::new(&erasure);
+        let targets = cli::targets(std::slice::from_ref(&dir), None).expect("Should list dir");
+        let result = erase_all(&targets, &erasure, &mut report);
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            report.records(),
+            [
+                (1, dir.join("2021-10.csv").display().to_string()),
+                (3, dir.join("2021-11.csv").display().to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use clap::{CommandFactory, Parser};
+#[cfg(feature = "tz")]
+use parser::TimeZone;
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{CommonParser, Field, Format, IdBloom, ParseError, QueryEngine, YPBankRecord};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// False positive rate of the bloom filters written with `--build-bloom`.
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Input file; repeat to search several files in order
+    #[arg(long, required = true)]
+    input: Vec<String>,
+
+    #[arg(long)]
+    format: String,
+
+    /// Transaction id to look for
+    #[arg(long, required_unless_present = "build_bloom")]
+    id: Option<u64>,
+
+    /// Write bloom-filter sidecars of the inputs instead of searching
+    #[arg(long, conflicts_with_all = ["id", "output_format"])]
+    build_bloom: bool,
+
+    /// Format to print the record in; defaults to the input format
+    #[arg(long)]
+    output_format: Option<String>,
+
+    /// Also report the time of the record found in this timezone (e.g. Europe/Moscow)
+    #[cfg(feature = "tz")]
+    #[arg(long, value_name = "ZONE", conflicts_with = "build_bloom", value_parser = |name: &str| TimeZone::load(name))]
+    tz: Option<TimeZone>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl Args {
+    fn format(&self) -> Result<Format, ParseError> {
+        Format::from_str(&self.format)
+    }
+
+    fn output_format(&self) -> Result<Format, ParseError> {
+        match &self.output_format {
+            Some(format) => Format::from_str(format),
+            None => self.format(),
+        }
+    }
+
+    /// Reports the time of `record` in `--tz`, if given.
+    fn report_time(&self, record: &YPBankRecord) {
+        #[cfg(feature = "tz")]
+        if let Some(time_zone) = &self.tz {
+            cli::summary(format!(
+                "Record {} was made at {} ({})",
+                record.id,
+                time_zone.format_ts(record.ts),
+                time_zone.name()
+            ));
+        }
+        #[cfg(not(feature = "tz"))]
+        let _ = record;
+    }
+}
+
+/// Looks for the record with `id` in `inputs`, stopping at the first match. Files whose
+/// bloom-filter sidecar rules out `id` are not read.
+fn find_record(
+    inputs: &[String],
+    format: Format,
+    id: u64,
+) -> Result<Option<YPBankRecord>, ParseError> {
+    for input in inputs {
+        let found = QueryEngine::open(input, format)?
+            .filter(Field::Id.equals(id))
+            .first()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
+
+/// Writes a bloom-filter sidecar next to each of `inputs`.
+fn build_blooms(inputs: &[String], format: Format) -> Status {
+    for input in inputs {
+        let result = IdBloom::from_path(input, format, BLOOM_FALSE_POSITIVE_RATE)
+            .and_then(|bloom| bloom.write_sidecar(input));
+        if let Err(err) = result {
+            cli::error(format!(
+                "Failed to build bloom filter of {input}: [{}] {err}",
+                err.code()
+            ));
+            return Status::Failure;
+        }
+    }
+    cli::summary(format!("Wrote bloom filters of {} files", inputs.len()));
+    Status::Success
+}
+
+/// Writes the record with `id` in `output_format` and passes it to `on_found`.
+fn run_logic<W: std::io::Write>(
+    inputs: &[String],
+    format: Format,
+    id: u64,
+    output_format: Format,
+    output: &mut W,
+    on_found: impl FnOnce(&YPBankRecord),
+) -> Status {
+    match find_record(inputs, format, id) {
+        Ok(Some(record)) => {
+            let records = vec![record];
+            if let Err(err) = CommonParser::new(output_format).write_to(output, &records) {
+                cli::error(format!("Failed to write record: [{}] {err}", err.code()));
+                return Status::Failure;
+            }
+            on_found(&records[0]);
+            Status::Success
+        }
+        Ok(None) => {
+            cli::summary(format!("Record {id} not found"));
+            Status::Mismatch
+        }
+        Err(err) => {
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            Status::Failure
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match args.format() {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid format {}: [{}] {err}",
+                args.format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+    let Some(id) = args.id else {
+        return build_blooms(&args.input, format).into();
+    };
+    let output_format = match args.output_format() {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!("Invalid output format: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+
+    run_logic(
+        &args.input,
+        format,
+        id,
+        output_format,
+        &mut std::io::stdout(),
+        |record| args.report_time(record),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TransactionStatus, TransactionType};
+
+    fn create_test_record(id: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            format!("Record number {}", id),
+        )
+    }
+
+    #[test]
+    fn test_run_logic() {
+        let path = std::env::temp_dir().join(format!("find_{}.bin", std::process::id()));
+        let records = (1..=100).map(create_test_record).collect();
+        CommonParser::new(Format::Bin)
+            .write_to(
+                &mut std::fs::File::create(&path).expect("Should create file"),
+                &records,
+            )
+            .expect("Should write records");
+
+        let inputs = [path.to_str().expect("Should be UTF-8").to_string()];
+        let mut output = Vec::new();
+        let status = run_logic(&inputs, Format::Bin, 42, Format::Csv, &mut output, |_| ());
+        let missing = run_logic(
+            &inputs,
+            Format::Bin,
+            101,
+            Format::Csv,
+            &mut Vec::new(),
+            |_| (),
+        );
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!((status, missing), (Status::Success, Status::Mismatch));
+
+        let found = CommonParser::new(Format::Csv)
+            .from_read(&mut output.as_slice())
+            .expect("Should parse output as CSV");
+        assert_eq!(found, vec![create_test_record(42)]);
+    }
+
+    #[test]
+    fn test_search_several_inputs_with_blooms() {
+        let inputs: Vec<String> = [(1..=50), (51..=100)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, ids)| {
+                let path = std::env::temp_dir().join(format!(
+                    "find_bloom_{}_{}.bin",
+                    std::process::id(),
+                    index
+                ));
+                CommonParser::new(Format::Bin)
+                    .write_to(
+                        &mut std::fs::File::create(&path).expect("Should create file"),
+                        &ids.map(create_test_record).collect(),
+                    )
+                    .expect("Should write records");
+                path.to_str().expect("Should be UTF-8").to_string()
+            })
+            .collect();
+
+        let built = build_blooms(&inputs, Format::Bin);
+        let mut output = Vec::new();
+        let status = run_logic(&inputs, Format::Bin, 77, Format::Csv, &mut output, |_| ());
+        for input in &inputs {
+            std::fs::remove_file(IdBloom::sidecar_path(input)).expect("Should remove sidecar");
+            std::fs::remove_file(input).expect("Should remove file");
+        }
+
+        assert_eq!((built, status), (Status::Success, Status::Success));
+        let found = CommonParser::new(Format::Csv)
+            .from_read(&mut output.as_slice())
+            .expect("Should parse output as CSV");
+        assert_eq!(found, vec![create_test_record(77)]);
+    }
+}
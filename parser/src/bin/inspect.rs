@@ -0,0 +1,482 @@
+use clap::{CommandFactory, Parser};
+#[cfg(feature = "tz")]
+use parser::TimeZone;
+use parser::cli::{self, Status, TimestampArgs, VerbosityArgs};
+use parser::{
+    ColumnStats, CommonParser, Format, ParseError, ParseOptions, Period, TimestampCheck,
+    TransactionStatus, TransactionType, YPBankRecord,
+};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// Bytes of the file start the format is recognized by.
+const SNIFF_LEN: u64 = 4096;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// File to inspect
+    #[arg(value_name = "FILE")]
+    file: PathBuf,
+
+    /// Format of the file; recognized by its content or extension when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Number of records to show as samples
+    #[arg(long, default_value_t = 3)]
+    samples: usize,
+
+    /// Read every record even when the file has an up-to-date statistics sidecar
+    #[arg(long)]
+    rescan: bool,
+
+    /// Show timestamps in this timezone (e.g. Europe/Moscow) instead of UTC
+    #[cfg(feature = "tz")]
+    #[arg(long, value_name = "ZONE", value_parser = |name: &str| TimeZone::load(name))]
+    tz: Option<TimeZone>,
+
+    #[command(flatten)]
+    timestamps: TimestampArgs,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+impl Args {
+    /// Renders `ts` as a datetime in `--tz`, or in UTC.
+    fn datetime(&self, ts: u64) -> String {
+        #[cfg(feature = "tz")]
+        if let Some(time_zone) = &self.tz {
+            return time_zone.format_ts(ts);
+        }
+        Period::Hour.label(ts)
+    }
+}
+
+/// What was found in a file, read without knowing anything of it in advance.
+#[derive(Debug, Default)]
+struct Report {
+    format: Option<Format>,
+    /// Where the format comes from: `given`, `content` or `extension`.
+    detected_from: &'static str,
+    records: u64,
+    invalid_records: u64,
+    first_error: Option<String>,
+    empty_descriptions: u64,
+    zero_amounts: u64,
+    types: BTreeMap<&'static str, u64>,
+    statuses: BTreeMap<&'static str, u64>,
+    ids: Option<(u64, u64)>,
+    amounts: Option<(i64, i64)>,
+    timestamps: Option<(u64, u64)>,
+    /// Records with timestamps outside the plausible window, when warned about.
+    implausible_timestamps: Option<u64>,
+    /// Whether the counts come from the statistics sidecar rather than the records.
+    from_sidecar: bool,
+    samples: Vec<YPBankRecord>,
+}
+
+/// Widens `range` to hold `value`.
+fn widen<T: Copy + Ord>(range: &mut Option<(T, T)>, value: T) {
+    *range = Some(match *range {
+        Some((min, max)) => (min.min(value), max.max(value)),
+        None => (value, value),
+    });
+}
+
+impl Report {
+    fn add(&mut self, record: YPBankRecord, samples: usize) {
+        self.records += 1;
+        if record.description.trim_matches(['"', ' ']).is_empty() {
+            self.empty_descriptions += 1;
+        }
+        if record.amount == 0 {
+            self.zero_amounts += 1;
+        }
+        *self
+            .types
+            .entry(record.transaction_type.as_str())
+            .or_default() += 1;
+        *self.statuses.entry(record.status.as_str()).or_default() += 1;
+        widen(&mut self.ids, record.id);
+        widen(&mut self.amounts, record.amount);
+        widen(&mut self.timestamps, record.ts);
+        if self.samples.len() < samples {
+            self.samples.push(record);
+        }
+    }
+
+    /// Takes the counts of a statistics sidecar, which only lists valid records.
+    fn add_column_stats(&mut self, stats: &ColumnStats) {
+        self.records = stats.records;
+        self.empty_descriptions = stats.empty_descriptions;
+        self.zero_amounts = stats.zero_amounts;
+        for transaction_type in [
+            TransactionType::Deposit,
+            TransactionType::Transfer,
+            TransactionType::Withdrawal,
+        ] {
+            let count = stats.type_count(transaction_type);
+            if count > 0 {
+                self.types.insert(transaction_type.as_str(), count);
+            }
+        }
+        for status in [
+            TransactionStatus::Success,
+            TransactionStatus::Failure,
+            TransactionStatus::Pending,
+        ] {
+            let count = stats.status_count(status);
+            if count > 0 {
+                self.statuses.insert(status.as_str(), count);
+            }
+        }
+        self.ids = stats.ids;
+        self.amounts = stats.amounts;
+        self.timestamps = stats.timestamps;
+        self.from_sidecar = true;
+    }
+}
+
+/// Returns the format of the file at `path` and where it comes from. The content is
+/// tried before the extension, as files of partners are often misnamed.
+fn detect_format(
+    path: &Path,
+    given: &Option<String>,
+) -> Result<(Format, &'static str), ParseError> {
+    if let Some(format) = given {
+        return Ok((Format::from_str(format)?, "given"));
+    }
+
+    let mut head = Vec::new();
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+    if let Some(format) = Format::sniff(&head) {
+        return Ok((format, "content"));
+    }
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(Format::from_extension)
+        .map(|format| (format, "extension"))
+        .ok_or_else(|| {
+            ParseError::InvalidFormat(format!("cannot infer the format of {}", path.display()))
+        })
+}
+
+/// Reads every record of the file, counting invalid ones instead of stopping at them.
+///
+/// Unless `rescan` is set, the counts of an up-to-date statistics sidecar are used
+/// instead and only the samples are read; timestamps are then not checked.
+fn inspect(
+    path: &Path,
+    given: &Option<String>,
+    samples: usize,
+    timestamp_check: TimestampCheck,
+    rescan: bool,
+) -> Result<Report, ParseError> {
+    let (format, detected_from) = detect_format(path, given)?;
+    let mut report = Report {
+        format: Some(format),
+        detected_from,
+        ..Report::default()
+    };
+
+    if !rescan && let Some(stats) = ColumnStats::load_sidecar(path)? {
+        report.add_column_stats(&stats);
+        let parser = CommonParser::new(format);
+        for record in parser.iter_read(File::open(path)?)?.take(samples) {
+            report.samples.push(record?);
+        }
+        return Ok(report);
+    }
+
+    let options = ParseOptions {
+        skip_invalid_records: true,
+        timestamp_check: timestamp_check.clone(),
+        ..ParseOptions::default()
+    };
+    for record in CommonParser::with_options(format, options).iter_read(File::open(path)?)? {
+        match record {
+            Ok(record) => report.add(record, samples),
+            Err(err) => {
+                report.invalid_records += 1;
+                report
+                    .first_error
+                    .get_or_insert_with(|| format!("[{}] {err}", err.code()));
+            }
+        }
+    }
+    if let TimestampCheck::Warn(_, log) = &timestamp_check {
+        report.implausible_timestamps = Some(log.len() as u64);
+    }
+    Ok(report)
+}
+
+/// Writes `report`, rendering timestamps with `datetime`.
+fn write_report<W: Write>(
+    report: &Report,
+    datetime: impl Fn(u64) -> String,
+    w: &mut W,
+) -> Result<(), ParseError> {
+    let counts = |counts: &BTreeMap<&str, u64>| {
+        counts
+            .iter()
+            .map(|(key, count)| format!("{} {}", key, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let range = |range: Option<(String, String)>| {
+        range.map_or("-".to_string(), |(min, max)| format!("{}..{}", min, max))
+    };
+
+    if let Some(format) = report.format {
+        writeln!(
+            w,
+            "Format: {} (from {})",
+            format.as_str(),
+            report.detected_from
+        )?;
+    }
+    if report.from_sidecar {
+        writeln!(w, "Statistics: from sidecar")?;
+    }
+    writeln!(w, "Records: {}", report.records)?;
+    writeln!(w, "Invalid records: {}", report.invalid_records)?;
+    if let Some(err) = &report.first_error {
+        writeln!(w, "First error: {}", err)?;
+    }
+    writeln!(w, "Empty descriptions: {}", report.empty_descriptions)?;
+    writeln!(w, "Zero amounts: {}", report.zero_amounts)?;
+    writeln!(w, "Types: {}", counts(&report.types))?;
+    writeln!(w, "Statuses: {}", counts(&report.statuses))?;
+    writeln!(
+        w,
+        "TX_ID: {}",
+        range(
+            report
+                .ids
+                .map(|(min, max)| (min.to_string(), max.to_string()))
+        )
+    )?;
+    writeln!(
+        w,
+        "AMOUNT: {}",
+        range(
+            report
+                .amounts
+                .map(|(min, max)| (min.to_string(), max.to_string()))
+        )
+    )?;
+    writeln!(
+        w,
+        "TIMESTAMP: {}",
+        range(report.timestamps.map(|(min, max)| {
+            (
+                format!("{} ({})", min, datetime(min)),
+                format!("{} ({})", max, datetime(max)),
+            )
+        }))
+    )?;
+    if let Some(count) = report.implausible_timestamps {
+        writeln!(w, "Implausible timestamps: {}", count)?;
+    }
+
+    if !report.samples.is_empty() {
+        writeln!(w, "Samples:")?;
+        CommonParser::new(Format::Csv).write_to(w, &report.samples)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let timestamp_check = match args.timestamps.timestamp_check() {
+        Ok(timestamp_check) => timestamp_check,
+        Err(err) => {
+            cli::error(format!("Invalid timestamp check: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let report = match inspect(
+        &args.file,
+        &args.format,
+        args.samples,
+        timestamp_check,
+        args.rescan,
+    ) {
+        Ok(report) => report,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to inspect {}: [{}] {err}",
+                args.file.display(),
+                err.code()
+            ));
+            return Status::Failure.into();
+        }
+    };
+    if let Err(err) = write_report(&report, |ts| args.datetime(ts), &mut std::io::stdout()) {
+        cli::error(format!("Failed to write report: [{}] {err}", err.code()));
+        return Status::Failure.into();
+    }
+    Status::from(report.invalid_records == 0).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TimestampLog, TimestampWindow, fixtures};
+
+    #[test]
+    fn test_inspect_misnamed_file() {
+        let path = std::env::temp_dir().join(format!("inspect_{}.dat", std::process::id()));
+        let mut records = fixtures::records();
+        records[0].amount = 0;
+        records[1].description = "".into();
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+        data.extend_from_slice(b"21,DEPOSIT,0,1,abc,1633046460000,SUCCESS,Broken\n");
+        std::fs::write(&path, &data).expect("Should write file");
+
+        let report = inspect(&path, &None, 2, TimestampCheck::Off, false);
+        std::fs::remove_file(&path).expect("Should remove file");
+        let report = report.expect("Should inspect file");
+
+        let mut output = Vec::new();
+        write_report(&report, |ts| Period::Hour.label(ts), &mut output)
+            .expect("Should write report");
+        assert_eq!(
+            String::from_utf8(output).expect("Should be UTF-8"),
+            "Format: csv (from content)\n\
+             Records: 20\n\
+             Invalid records: 1\n\
+             First error: [E005 invalid-raw-value] Invalid raw value of field AMOUNT: abc\n\
+             Empty descriptions: 1\n\
+             Zero amounts: 1\n\
+             Types: DEPOSIT 7, TRANSFER 7, WITHDRAWAL 6\n\
+             Statuses: PENDING 5, SUCCESS 15\n\
+             TX_ID: 1000000000000000..1000000000000019\n\
+             AMOUNT: 0..2000\n\
+             TIMESTAMP: 1633046460000 (2021-10-01T00:01)..1633047600000 (2021-10-01T00:20)\n\
+             Samples:\n\
+             TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+             1000000000000000,DEPOSIT,0,1,0,1633046460000,SUCCESS,Record number 1\n\
+             1000000000000001,TRANSFER,2,3,200,1633046520000,SUCCESS,\n"
+        );
+    }
+
+    #[test]
+    fn test_inspect_implausible_timestamps() {
+        let path = std::env::temp_dir().join(format!("inspect_ts_{}.csv", std::process::id()));
+        let mut records = fixtures::records();
+        records[3].ts /= 1000;
+        records[4].ts *= 1000;
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+        std::fs::write(&path, &data).expect("Should write file");
+
+        let window = TimestampWindow::new(TimestampWindow::Y2000, 1700000000000);
+        let warned = inspect(
+            &path,
+            &None,
+            0,
+            TimestampCheck::Warn(window, TimestampLog::new()),
+            false,
+        );
+        let rejected = inspect(&path, &None, 0, TimestampCheck::Reject(window), false);
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        let warned = warned.expect("Should inspect file");
+        assert_eq!(
+            (warned.records, warned.implausible_timestamps),
+            (20, Some(2))
+        );
+        let mut output = Vec::new();
+        write_report(&warned, |ts| Period::Hour.label(ts), &mut output)
+            .expect("Should write report");
+        assert!(
+            String::from_utf8(output)
+                .expect("Should be UTF-8")
+                .contains("\nImplausible timestamps: 2\n")
+        );
+        let rejected = rejected.expect("Should inspect file");
+        assert_eq!((rejected.records, rejected.invalid_records), (18, 2));
+    }
+
+    #[test]
+    fn test_inspect_from_sidecar() {
+        let path = std::env::temp_dir().join(format!("inspect_stats_{}.csv", std::process::id()));
+        let parser = CommonParser::with_options(
+            Format::Csv,
+            ParseOptions {
+                stats_sidecar: true,
+                ..ParseOptions::default()
+            },
+        );
+        parser
+            .write_to_path_atomic(&path, &fixtures::records(), None)
+            .expect("Should write records");
+
+        let check = || TimestampCheck::Warn(TimestampWindow::plausible(), TimestampLog::new());
+        let from_sidecar = inspect(&path, &None, 1, check(), false);
+        let rescanned = inspect(&path, &None, 1, check(), true);
+        std::fs::remove_file(&path).expect("Should remove file");
+        std::fs::remove_file(ColumnStats::sidecar_path(&path)).expect("Should remove sidecar");
+
+        let (from_sidecar, rescanned) = (
+            from_sidecar.expect("Should inspect file"),
+            rescanned.expect("Should inspect file"),
+        );
+        assert!(from_sidecar.from_sidecar && !rescanned.from_sidecar);
+        assert_eq!(from_sidecar.implausible_timestamps, None);
+        assert_eq!(
+            (
+                from_sidecar.records,
+                from_sidecar.types,
+                from_sidecar.statuses,
+                from_sidecar.amounts,
+                from_sidecar.samples
+            ),
+            (
+                rescanned.records,
+                rescanned.types,
+                rescanned.statuses,
+                rescanned.amounts,
+                rescanned.samples
+            )
+        );
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let path = std::env::temp_dir().join(format!("inspect_{}.toml", std::process::id()));
+        std::fs::write(&path, b"").expect("Should write file");
+
+        let detected = (
+            detect_format(&path, &None),
+            detect_format(&path, &Some("binary".to_string())),
+        );
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!(
+            detected,
+            (Ok((Format::Toml, "extension")), Ok((Format::Bin, "given")))
+        );
+    }
+}
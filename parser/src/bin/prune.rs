@@ -0,0 +1,124 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, Target, VerbosityArgs};
+use parser::{Format, ParseError, PruneLog, RecordStore, Retention, prune_file};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// File, directory of files or record store to prune; may be repeated
+    #[arg(long, required = true)]
+    input: Vec<PathBuf>,
+
+    /// Format of the input files; inferred from their extension when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// Remove records with a timestamp before TS, in milliseconds
+    #[arg(
+        long,
+        value_name = "TS",
+        required_unless_present = "keep_days",
+        conflicts_with = "keep_days"
+    )]
+    before: Option<u64>,
+
+    /// Remove records older than N days
+    #[arg(long, value_name = "N")]
+    keep_days: Option<u64>,
+
+    /// File the removed records are appended to
+    #[arg(long, value_name = "FILE")]
+    audit_log: PathBuf,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+fn prune<W: Write>(
+    target: &Target,
+    retention: &Retention,
+    log: &mut PruneLog<W>,
+) -> Result<u64, ParseError> {
+    match target {
+        Target::File(path, format) => prune_file(path, *format, retention, log),
+        Target::Store(dir) => RecordStore::open(dir)?.prune(retention, log),
+    }
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match args.format.as_deref().map(Format::from_str).transpose() {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!("Invalid format: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let retention = match (args.before, args.keep_days) {
+        (Some(before), _) => Retention::before(before),
+        (None, Some(days)) => {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64);
+            Retention::window(days, now)
+        }
+        (None, None) => unreachable!("clap requires --before or --keep-days"),
+    };
+    let targets = match cli::targets(&args.input, format) {
+        Ok(targets) => targets,
+        Err(err) => {
+            cli::error(format!("Invalid input: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let audit_log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&args.audit_log);
+    let mut log = match audit_log {
+        Ok(file) => PruneLog::new(file),
+        Err(err) => {
+            cli::error(format!(
+                "Failed to open {}: {err}",
+                args.audit_log.display()
+            ));
+            return Status::Failure.into();
+        }
+    };
+
+    for target in &targets {
+        match prune(target, &retention, &mut log) {
+            Ok(removed) => println!("{}: {} removed", target.path().display(), removed),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to prune {}: [{}] {err}",
+                    target.path().display(),
+                    err.code()
+                ));
+                return Status::Failure.into();
+            }
+        }
+    }
+    cli::summary(format!(
+        "Removed {} records before {} from {} inputs",
+        log.removed(),
+        retention.cutoff(),
+        targets.len()
+    ));
+    Status::Success.into()
+}
@@ -0,0 +1,343 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{
+    CommonParser, Format, ParseError, ParseOptions, RecordStream, Sha256, TransactionStatus,
+    TransactionType, YPBankRecord,
+};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Length of the magic that starts every binary record.
+const MAGIC_LEN: u64 = 4;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Binary file to repair
+    #[arg(long)]
+    input: PathBuf,
+
+    /// File the records that parse are copied to
+    #[arg(long)]
+    output: PathBuf,
+
+    /// Patch every unrecoverable range with a marker record and write the marker ids
+    /// to this tombstone file, so that readers using it leave the markers out
+    #[arg(long, value_name = "FILE")]
+    mark_gaps: Option<PathBuf>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// Byte range of the input no record could be recovered from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Gap {
+    start: u64,
+    end: u64,
+}
+
+/// Copies the records of a binary stream that parse, resynchronizing on the next record
+/// magic after each invalid one and keeping the byte ranges that were skipped.
+struct Repair<'a> {
+    records: RecordStream<'a>,
+    gaps: Vec<Gap>,
+    /// Whether the last record failed, in which case the magic of the next one has been
+    /// read already.
+    after_error: bool,
+    mark_gaps: bool,
+    markers: Vec<u64>,
+    /// Record to return after the marker of the gap before it.
+    pending: Option<YPBankRecord>,
+    last_ts: u64,
+    copied: u64,
+}
+
+impl<'a> Repair<'a> {
+    fn new(records: RecordStream<'a>, mark_gaps: bool) -> Self {
+        Self {
+            records,
+            gaps: Vec::new(),
+            after_error: false,
+            mark_gaps,
+            markers: Vec::new(),
+            pending: None,
+            last_ts: 0,
+            copied: 0,
+        }
+    }
+
+    fn outcome(self) -> Outcome {
+        Outcome {
+            copied: self.copied,
+            gaps: self.gaps,
+            markers: self.markers,
+        }
+    }
+
+    /// Ends the open gap at `end`, returning its marker when gaps are marked.
+    fn close_gap(&mut self, end: u64) -> Option<YPBankRecord> {
+        if !std::mem::take(&mut self.after_error) {
+            return None;
+        }
+        let gap = self.gaps.last_mut()?;
+        gap.end = end;
+        if !self.mark_gaps {
+            return None;
+        }
+
+        let id = u64::MAX - self.markers.len() as u64;
+        self.markers.push(id);
+        Some(YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            u64::MAX,
+            0,
+            self.last_ts,
+            TransactionStatus::Failure,
+            format!("UNRECOVERABLE BYTES {}..{}", gap.start, gap.end),
+        ))
+    }
+}
+
+impl Iterator for Repair<'_> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(record) = self.pending.take() {
+            return Some(Ok(record));
+        }
+
+        loop {
+            let start = match self.after_error {
+                true => self.records.offset() - MAGIC_LEN,
+                false => self.records.offset(),
+            };
+            match self.records.next() {
+                Some(Ok(record)) => {
+                    self.copied += 1;
+                    self.last_ts = record.ts;
+                    return match self.close_gap(start) {
+                        Some(marker) => {
+                            self.pending = Some(record);
+                            Some(Ok(marker))
+                        }
+                        None => Some(Ok(record)),
+                    };
+                }
+                Some(Err(err)) => {
+                    cli::warning(format!(
+                        "Skipping bytes from {}: [{}] {err}",
+                        start,
+                        err.code()
+                    ));
+                    let end = self.records.offset();
+                    match self.gaps.last_mut() {
+                        Some(gap) if self.after_error => gap.end = end,
+                        _ => self.gaps.push(Gap { start, end }),
+                    }
+                    self.after_error = true;
+                }
+                None => return self.close_gap(self.records.offset()).map(Ok),
+            }
+        }
+    }
+}
+
+/// What a repair found and wrote.
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    copied: u64,
+    gaps: Vec<Gap>,
+    markers: Vec<u64>,
+}
+
+impl Outcome {
+    fn lost_bytes(&self) -> u64 {
+        self.gaps.iter().map(|gap| gap.end - gap.start).sum()
+    }
+}
+
+/// Returns the records of a binary input that parse, to be written to the copy.
+fn repair<'a, R: std::io::Read + 'a>(input: R, mark_gaps: bool) -> Result<Repair<'a>, ParseError> {
+    let options = ParseOptions {
+        skip_invalid_records: true,
+        ..ParseOptions::default()
+    };
+    let records = CommonParser::with_options(Format::Bin, options).iter_read(input)?;
+    Ok(Repair::new(records, mark_gaps))
+}
+
+/// Reads the copy back, failing unless it holds `records` records, and returns its
+/// SHA-256.
+fn verify_copy(path: &Path, records: u64) -> Result<String, ParseError> {
+    let mut read = 0;
+    for record in CommonParser::new(Format::Bin).iter_read(File::open(path)?)? {
+        record?;
+        read += 1;
+    }
+    if read != records {
+        return Err(ParseError::InconsistentRecord(format!(
+            "copy holds {} records instead of {}",
+            read, records
+        )));
+    }
+
+    let mut sha = Sha256::new();
+    sha.update_from_read(&mut File::open(path)?)?;
+    Ok(sha.finish_hex())
+}
+
+/// Writes the marker ids, one per line, with the range each marker stands for.
+fn write_tombstones<W: Write>(w: &mut W, outcome: &Outcome) -> std::io::Result<()> {
+    for (id, gap) in outcome.markers.iter().zip(&outcome.gaps) {
+        writeln!(w, "{}  # bytes {}..{}", id, gap.start, gap.end)?;
+    }
+    Ok(())
+}
+
+fn run(args: &Args) -> Result<Outcome, ParseError> {
+    let mut repair = repair(File::open(&args.input)?, args.mark_gaps.is_some())?;
+    CommonParser::new(Format::Bin).write_iter_to_path_atomic(&args.output, &mut repair, None)?;
+    let outcome = repair.outcome();
+
+    if let Some(path) = &args.mark_gaps {
+        let mut tombstones = Vec::new();
+        write_tombstones(&mut tombstones, &outcome)?;
+        std::fs::write(path, tombstones)?;
+    }
+    let sha = verify_copy(&args.output, outcome.copied + outcome.markers.len() as u64)?;
+
+    for gap in &outcome.gaps {
+        println!("{}..{}", gap.start, gap.end);
+    }
+    cli::summary(format!(
+        "Copied {} records to {} (sha256 {}), lost {} bytes in {} ranges",
+        outcome.copied,
+        args.output.display(),
+        sha,
+        outcome.lost_bytes(),
+        outcome.gaps.len()
+    ));
+    Ok(outcome)
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    match run(&args) {
+        Ok(outcome) if outcome.gaps.is_empty() => Status::Success.into(),
+        Ok(_) => Status::Mismatch.into(),
+        Err(err) => {
+            cli::error(format!(
+                "Failed to repair {}: [{}] {err}",
+                args.input.display(),
+                err.code()
+            ));
+            Status::Failure.into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{encode_bin_record, fixtures};
+
+    /// Encodes the fixture records, corrupting the second and third and the last.
+    fn corrupted() -> (Vec<u8>, Vec<Gap>) {
+        let mut data = Vec::new();
+        let mut gaps: Vec<Gap> = Vec::new();
+        let records = fixtures::records();
+        for (index, record) in records.iter().take(5).enumerate() {
+            let start = data.len() as u64;
+            encode_bin_record(record, &mut data);
+            if matches!(index, 1 | 2 | 4) {
+                // Transaction type 9 does not exist.
+                data[start as usize + 16] = 9;
+                match gaps.last_mut() {
+                    Some(gap) if gap.end == start => gap.end = data.len() as u64,
+                    _ => gaps.push(Gap {
+                        start,
+                        end: data.len() as u64,
+                    }),
+                }
+            }
+        }
+        (data, gaps)
+    }
+
+    #[test]
+    fn test_repair_copies_good_records() {
+        let (data, gaps) = corrupted();
+        let mut repair = repair(&data[..], false).expect("Should read header");
+        let mut output = Vec::new();
+        CommonParser::new(Format::Bin)
+            .write_iter(&mut output, &mut repair)
+            .expect("Should write copy");
+
+        let copy = CommonParser::new(Format::Bin)
+            .from_read(&mut &output[..])
+            .expect("Should read copy");
+        let records = fixtures::records();
+        assert_eq!(copy, vec![records[0].clone(), records[3].clone()]);
+        assert_eq!(
+            repair.outcome(),
+            Outcome {
+                copied: 2,
+                gaps,
+                markers: vec![]
+            }
+        );
+    }
+
+    #[test]
+    fn test_repair_marks_gaps() {
+        let (data, gaps) = corrupted();
+        let mut repair = repair(&data[..], true).expect("Should read header");
+        let mut output = Vec::new();
+        CommonParser::new(Format::Bin)
+            .write_iter(&mut output, &mut repair)
+            .expect("Should write copy");
+        let outcome = repair.outcome();
+
+        let copy = CommonParser::new(Format::Bin)
+            .from_read(&mut &output[..])
+            .expect("Should read copy");
+        let ids: Vec<u64> = copy.iter().map(|record| record.id).collect();
+        let records = fixtures::records();
+        assert_eq!(
+            ids,
+            vec![records[0].id, u64::MAX, records[3].id, u64::MAX - 1]
+        );
+        assert_eq!(
+            copy[1].description.as_ref(),
+            format!("UNRECOVERABLE BYTES {}..{}", gaps[0].start, gaps[0].end)
+        );
+
+        let mut tombstones = Vec::new();
+        write_tombstones(&mut tombstones, &outcome).expect("Should write tombstones");
+        let tombstones =
+            parser::Tombstones::from_text(&String::from_utf8(tombstones).expect("Should be UTF-8"))
+                .expect("Should read tombstones");
+        let options = ParseOptions {
+            tombstones: Some(tombstones),
+            ..ParseOptions::default()
+        };
+        let kept = CommonParser::with_options(Format::Bin, options)
+            .from_read(&mut &output[..])
+            .expect("Should read copy");
+        assert_eq!(kept, vec![records[0].clone(), records[3].clone()]);
+    }
+}
@@ -0,0 +1,276 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{CommonParser, ErrorCode, Format, ParseError, YPBankRecord};
+use std::fs::File;
+use std::io::Write;
+use std::net::TcpStream;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(long)]
+    input: String,
+
+    #[arg(long)]
+    format: String,
+
+    /// Format of the emitted records (default: the input format)
+    #[arg(long)]
+    output_format: Option<String>,
+
+    /// Emit this many records per second
+    #[arg(long, conflicts_with = "time_scale", value_parser = positive)]
+    rate: Option<f64>,
+
+    /// Keep the original spacing of timestamps, sped up by this factor (2 is twice as fast)
+    #[arg(long, value_parser = positive)]
+    time_scale: Option<f64>,
+
+    /// Send records to a TCP socket instead of stdout
+    #[arg(long, value_name = "HOST:PORT")]
+    connect: Option<String>,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+fn positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("{} is not a positive number", s)),
+    }
+}
+
+/// When records are due, relative to the start of the replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pace {
+    Unlimited,
+    /// Records per second.
+    Rate(f64),
+    /// Speed-up of the original timestamp spacing.
+    TimeScale(f64),
+}
+
+struct Pacer {
+    pace: Pace,
+    emitted: u64,
+    first_ts: Option<u64>,
+}
+
+impl Pacer {
+    fn new(pace: Pace) -> Self {
+        Self {
+            pace,
+            emitted: 0,
+            first_ts: None,
+        }
+    }
+
+    /// Returns the offset from the start of the replay at which `record` is due.
+    ///
+    /// Records with timestamps before the first record's are due immediately. Offsets
+    /// too large for a `Duration`, e.g. with `--time-scale 1e-300`, saturate to
+    /// `Duration::MAX`.
+    fn due(&mut self, record: &YPBankRecord) -> Duration {
+        let index = self.emitted;
+        self.emitted += 1;
+        match self.pace {
+            Pace::Unlimited => Duration::ZERO,
+            Pace::Rate(rate) => seconds(index as f64 / rate),
+            Pace::TimeScale(scale) => {
+                let first_ts = *self.first_ts.get_or_insert(record.ts);
+                let offset_ms = record.ts.saturating_sub(first_ts);
+                seconds(offset_ms as f64 / 1000.0 / scale)
+            }
+        }
+    }
+}
+
+/// Converts non-negative seconds into a `Duration`, saturating at `Duration::MAX`.
+fn seconds(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// Flushes after every write so each record leaves as soon as it is due.
+struct Flushing<W: Write>(W);
+
+impl<W: Write> Write for Flushing<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.0.write(buf)?;
+        self.0.flush()?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn replay<R: std::io::Read, W: Write>(
+    input: R,
+    format: Format,
+    output_format: Format,
+    pace: Pace,
+    output: &mut W,
+) -> Result<(), ParseError> {
+    let mut pacer = Pacer::new(pace);
+    let start = Instant::now();
+    let records = CommonParser::new(format).iter_read(input)?.map(|record| {
+        let record = record?;
+        std::thread::sleep(pacer.due(&record).saturating_sub(start.elapsed()));
+        Ok(record)
+    });
+    CommonParser::new(output_format).write_iter(&mut Flushing(output), records)
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match Format::from_str(&args.format) {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid format {}: [{}] {err}",
+                args.format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+    let output_format = match args.output_format.as_deref().map(Format::from_str) {
+        None => format,
+        Some(Ok(format)) => format,
+        Some(Err(err)) => {
+            cli::error(format!("Invalid output format: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let pace = match (args.rate, args.time_scale) {
+        (Some(rate), _) => Pace::Rate(rate),
+        (None, Some(scale)) => Pace::TimeScale(scale),
+        (None, None) => Pace::Unlimited,
+    };
+
+    let input = match File::open(&args.input) {
+        Ok(file) => file,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to open input file {}: [{}] {err}",
+                args.input,
+                ErrorCode::Io
+            ));
+            return Status::Failure.into();
+        }
+    };
+
+    let result = match &args.connect {
+        Some(address) => match TcpStream::connect(address) {
+            Ok(mut stream) => replay(input, format, output_format, pace, &mut stream),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to connect to {}: [{}] {err}",
+                    address,
+                    ErrorCode::Io
+                ));
+                return Status::Failure.into();
+            }
+        },
+        None => replay(
+            input,
+            format,
+            output_format,
+            pace,
+            &mut std::io::stdout().lock(),
+        ),
+    };
+    if let Err(err) = result {
+        cli::error(format!("Failed to replay records: [{}] {err}", err.code()));
+        return Status::Failure.into();
+    }
+    Status::Success.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TransactionStatus, TransactionType};
+
+    fn record(id: u64, ts: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            7,
+            100,
+            ts,
+            TransactionStatus::Success,
+            "Replayed",
+        )
+    }
+
+    #[test]
+    fn test_pacer_due() {
+        let mut pacer = Pacer::new(Pace::Rate(4.0));
+        let due: Vec<_> = (0..3).map(|id| pacer.due(&record(id, 0))).collect();
+        assert_eq!(
+            due,
+            vec![
+                Duration::ZERO,
+                Duration::from_millis(250),
+                Duration::from_millis(500)
+            ]
+        );
+
+        let mut pacer = Pacer::new(Pace::TimeScale(2.0));
+        let due: Vec<_> = [10_000, 14_000, 9_000]
+            .into_iter()
+            .map(|ts| pacer.due(&record(1, ts)))
+            .collect();
+        assert_eq!(
+            due,
+            vec![Duration::ZERO, Duration::from_secs(2), Duration::ZERO]
+        );
+
+        let mut pacer = Pacer::new(Pace::TimeScale(1e-300));
+        pacer.due(&record(1, 0));
+        assert_eq!(pacer.due(&record(2, 1)), Duration::MAX);
+        let mut pacer = Pacer::new(Pace::Rate(f64::MIN_POSITIVE));
+        pacer.due(&record(1, 0));
+        assert_eq!(pacer.due(&record(2, 0)), Duration::MAX);
+    }
+
+    #[test]
+    fn test_replay_converts_records() {
+        let records = vec![record(1, 10), record(2, 20)];
+        let mut input = Vec::new();
+        CommonParser::new(Format::Bin)
+            .write_to(&mut input, &records)
+            .expect("Should write records");
+
+        let mut output = Vec::new();
+        replay(
+            input.as_slice(),
+            Format::Bin,
+            Format::Csv,
+            Pace::Rate(1000.0),
+            &mut output,
+        )
+        .expect("Should replay");
+
+        let replayed = CommonParser::new(Format::Csv)
+            .from_read(&mut output.as_slice())
+            .expect("Should read records");
+        assert_eq!(replayed, records);
+    }
+}
@@ -0,0 +1,1115 @@
+use clap::{CommandFactory, Parser, ValueEnum};
+#[cfg(feature = "http-sink")]
+use parser::HttpSink;
+#[cfg(feature = "tz")]
+use parser::TimeZone;
+use parser::cli::{self, Status, TimestampArgs, VerbosityArgs};
+use parser::{
+    Alert, Alerts, AmountHistogram, BusinessCalendar, ColumnStats, CommonParser, DayKind,
+    DayKindFilter, ErrorCode, Format, ParseError, ParseOptions, Period, Stats, TransactionStatus,
+    TransactionType, Window, WindowSummary, windows,
+};
+use std::io::Write;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(long)]
+    input: String,
+
+    #[arg(long)]
+    format: String,
+
+    /// Comma-separated increasing bounds of the amount histogram buckets
+    #[arg(
+        long,
+        value_delimiter = ',',
+        allow_negative_numbers = true,
+        default_value = "0,100,1000,10000,100000"
+    )]
+    buckets: Vec<i64>,
+
+    /// Number of users with the highest volume to list
+    #[arg(long, default_value_t = 10)]
+    top: usize,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output_format: OutputFormat,
+
+    /// Print totals per tumbling window of this length (e.g. 1m, 15m, 1h) as each window
+    /// completes, instead of statistics of the whole input
+    #[arg(long, value_name = "DURATION")]
+    window: Option<String>,
+
+    /// TOML file with a [calendar] table of weekend days and holidays; totals are then
+    /// also grouped by day kind (business, weekend, holiday)
+    #[arg(long, value_name = "FILE", value_parser = |path: &str| BusinessCalendar::from_path(path))]
+    calendar: Option<BusinessCalendar>,
+
+    /// Count only records on days of this kind (business, weekend, holiday); can be repeated
+    #[arg(long = "day-kind", value_name = "KIND", requires = "calendar", value_parser = |kind: &str| DayKind::from_str(kind))]
+    day_kinds: Vec<DayKind>,
+
+    /// Print the smallest and largest value and the zero or empty values of every field,
+    /// from the statistics sidecar of the input when it is up to date
+    #[arg(long, conflicts_with_all = ["window", "calendar"])]
+    columns: bool,
+
+    /// File of alert rules, one per line (e.g. `FAILURE > 10 in 5m`); alerts are printed as
+    /// soon as a record raises them, instead of statistics of the whole input
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["window", "columns"], value_parser = |path: &str| Alerts::from_path(path))]
+    alerts: Option<Alerts>,
+
+    /// Shell command to run on every alert, with ALERT_RULE, ALERT_USER_ID, ALERT_VALUE,
+    /// ALERT_TS and ALERT_JSON set
+    #[arg(long, value_name = "COMMAND", requires = "alerts")]
+    on_alert: Option<String>,
+
+    /// POST every alert as a JSON object to this http:// URL
+    #[cfg(feature = "http-sink")]
+    #[arg(long, value_name = "URL", requires = "alerts")]
+    alert_url: Option<String>,
+
+    /// Count and label hours, days and windows in this timezone (e.g. Europe/Moscow)
+    /// instead of UTC
+    #[cfg(feature = "tz")]
+    #[arg(long, value_name = "ZONE", conflicts_with = "columns", value_parser = |name: &str| TimeZone::load(name))]
+    tz: Option<TimeZone>,
+
+    #[command(flatten)]
+    timestamps: TimestampArgs,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+impl Args {
+    /// Returns the filter of `--day-kind`, or `None` to count records of every day.
+    fn day_kind_filter(&self) -> Option<DayKindFilter> {
+        let calendar = self.calendar.clone()?;
+        (!self.day_kinds.is_empty()).then(|| DayKindFilter::new(calendar, self.day_kinds.clone()))
+    }
+
+    /// Returns empty statistics with the buckets, calendar and timezone of the flags.
+    fn stats(&self) -> Result<Stats, ParseError> {
+        let mut stats = Stats::new(AmountHistogram::new(self.buckets.clone())?);
+        if let Some(calendar) = self.calendar.clone() {
+            stats = stats.with_calendar(calendar);
+        }
+        #[cfg(feature = "tz")]
+        if let Some(time_zone) = self.tz.clone() {
+            stats = stats.with_time_zone(time_zone);
+        }
+        Ok(stats)
+    }
+
+    /// Renders the start of a window in `--tz`, or in UTC.
+    fn window_label(&self, window: &WindowSummary) -> String {
+        #[cfg(feature = "tz")]
+        if let Some(time_zone) = &self.tz {
+            return window.label_in(time_zone);
+        }
+        window.label()
+    }
+}
+
+/// Reads the records of `input`, leaving out those `filter` does not match.
+fn read_records<'a, R: std::io::Read + 'a>(
+    input: R,
+    parser: &CommonParser,
+    filter: Option<&'a DayKindFilter>,
+) -> Result<impl Iterator<Item = Result<parser::YPBankRecord, ParseError>> + 'a, ParseError> {
+    Ok(parser
+        .iter_read(input)?
+        .filter(move |record| match (record, filter) {
+            (Ok(record), Some(filter)) => filter.matches(record),
+            _ => true,
+        }))
+}
+
+fn collect_stats<R: std::io::Read>(
+    input: R,
+    parser: &CommonParser,
+    mut stats: Stats,
+    filter: Option<&DayKindFilter>,
+) -> Result<Stats, ParseError> {
+    for record in read_records(input, parser, filter)? {
+        stats.add(&record?)?;
+    }
+    Ok(stats)
+}
+
+/// Renders a bucket as a range such as `..0`, `0..100` or `100..`.
+fn bucket_label(from: Option<i64>, to: Option<i64>) -> String {
+    let bound = |bound: Option<i64>| bound.map(|bound| bound.to_string()).unwrap_or_default();
+    format!("{}..{}", bound(from), bound(to))
+}
+
+fn write_text<W: Write>(stats: &Stats, top: usize, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "Records: {}", stats.totals.all.count)?;
+    writeln!(w, "Total amount: {}", stats.totals.all.amount)?;
+
+    writeln!(w, "\nAmounts:")?;
+    for (from, to, count) in stats.amounts().buckets() {
+        writeln!(w, "  {:<24} {}", bucket_label(from, to), count)?;
+    }
+    for (title, period) in [
+        ("Records per day:", Period::Day),
+        ("Records per hour:", Period::Hour),
+    ] {
+        writeln!(w, "\n{}", title)?;
+        for (start, count) in stats.counts_per(period) {
+            writeln!(w, "  {:<24} {}", stats.label(period, *start), count)?;
+        }
+    }
+
+    if stats.for_day_kind(DayKind::Business).is_some() {
+        writeln!(w, "\nRecords per day kind:")?;
+        for (kind, total) in day_kind_totals(stats) {
+            writeln!(
+                w,
+                "  {:<24} {} records, amount {}",
+                kind.as_str(),
+                total.count,
+                total.amount
+            )?;
+        }
+    }
+
+    writeln!(w, "\nTop users by volume:")?;
+    for user in stats.top_users(top) {
+        writeln!(
+            w,
+            "  {:<24} {} records, volume {}",
+            user.user_id, user.count, user.volume
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the totals per day kind, or none without a calendar.
+fn day_kind_totals(stats: &Stats) -> Vec<(DayKind, parser::Total)> {
+    DayKind::ALL
+        .into_iter()
+        .filter_map(|kind| Some((kind, stats.for_day_kind(kind)?.all)))
+        .collect()
+}
+
+/// Writes one row per value as `metric,key,count,amount`.
+fn write_csv<W: Write>(stats: &Stats, top: usize, w: &mut W) -> std::io::Result<()> {
+    writeln!(w, "METRIC,KEY,COUNT,AMOUNT")?;
+    writeln!(
+        w,
+        "records,,{},{}",
+        stats.totals.all.count, stats.totals.all.amount
+    )?;
+    for (from, to, count) in stats.amounts().buckets() {
+        writeln!(w, "amount,{},{},", bucket_label(from, to), count)?;
+    }
+    for (metric, period) in [("day", Period::Day), ("hour", Period::Hour)] {
+        for (start, count) in stats.counts_per(period) {
+            writeln!(w, "{},{},{},", metric, stats.label(period, *start), count)?;
+        }
+    }
+    for (kind, total) in day_kind_totals(stats) {
+        writeln!(
+            w,
+            "day_kind,{},{},{}",
+            kind.as_str(),
+            total.count,
+            total.amount
+        )?;
+    }
+    for user in stats.top_users(top) {
+        writeln!(w, "user,{},{},{}", user.user_id, user.count, user.volume)?;
+    }
+    Ok(())
+}
+
+fn write_json<W: Write>(stats: &Stats, top: usize, w: &mut W) -> std::io::Result<()> {
+    let bound = |bound: Option<i64>| bound.map_or("null".to_string(), |bound| bound.to_string());
+    let buckets: Vec<String> = stats
+        .amounts()
+        .buckets()
+        .map(|(from, to, count)| {
+            format!(
+                "{{\"from\":{},\"to\":{},\"count\":{}}}",
+                bound(from),
+                bound(to),
+                count
+            )
+        })
+        .collect();
+    let periods = |period: Period| {
+        stats
+            .counts_per(period)
+            .iter()
+            .map(|(start, count)| {
+                format!(
+                    "{{\"start\":\"{}\",\"count\":{}}}",
+                    stats.label(period, *start),
+                    count
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let users: Vec<String> = stats
+        .top_users(top)
+        .iter()
+        .map(|user| {
+            format!(
+                "{{\"user_id\":{},\"count\":{},\"volume\":{}}}",
+                user.user_id, user.count, user.volume
+            )
+        })
+        .collect();
+    let day_kinds = match day_kind_totals(stats) {
+        totals if totals.is_empty() => String::new(),
+        totals => format!(
+            ",\"day_kinds\":[{}]",
+            totals
+                .iter()
+                .map(|(kind, total)| format!(
+                    "{{\"kind\":\"{}\",\"count\":{},\"amount\":{}}}",
+                    kind.as_str(),
+                    total.count,
+                    total.amount
+                ))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    };
+
+    writeln!(
+        w,
+        "{{\"records\":{},\"total_amount\":{},\"amounts\":[{}],\"per_day\":[{}],\"per_hour\":[{}],\"top_users\":[{}]{}}}",
+        stats.totals.all.count,
+        stats.totals.all.amount,
+        buckets.join(","),
+        periods(Period::Day),
+        periods(Period::Hour),
+        users.join(","),
+        day_kinds
+    )
+}
+
+const WINDOW_TYPES: [TransactionType; 3] = [
+    TransactionType::Deposit,
+    TransactionType::Transfer,
+    TransactionType::Withdrawal,
+];
+
+/// Writes the header of window rows, if the output format has one.
+fn write_window_header<W: Write>(output_format: OutputFormat, w: &mut W) -> std::io::Result<()> {
+    if output_format == OutputFormat::Csv {
+        write!(w, "WINDOW,COUNT,AMOUNT")?;
+        for transaction_type in WINDOW_TYPES {
+            let name = transaction_type.as_str();
+            write!(w, ",{}_COUNT,{}_AMOUNT", name, name)?;
+        }
+        writeln!(w, ",LATE")?;
+    }
+    Ok(())
+}
+
+/// Writes one window as a text line, a CSV row or a JSON line.
+fn write_window<W: Write>(
+    window: &WindowSummary,
+    label: &str,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> std::io::Result<()> {
+    let all = window.totals.all;
+    match output_format {
+        OutputFormat::Text => {
+            write!(
+                w,
+                "{:<18} {} records, amount {}",
+                label, all.count, all.amount
+            )?;
+            for transaction_type in WINDOW_TYPES {
+                let total = window.totals.for_type(transaction_type);
+                write!(
+                    w,
+                    "; {} {}/{}",
+                    transaction_type.as_str(),
+                    total.count,
+                    total.amount
+                )?;
+            }
+            if window.late > 0 {
+                write!(w, "; {} late", window.late)?;
+            }
+            writeln!(w)
+        }
+        OutputFormat::Csv => {
+            write!(w, "{},{},{}", label, all.count, all.amount)?;
+            for transaction_type in WINDOW_TYPES {
+                let total = window.totals.for_type(transaction_type);
+                write!(w, ",{},{}", total.count, total.amount)?;
+            }
+            writeln!(w, ",{}", window.late)
+        }
+        OutputFormat::Json => {
+            let types: Vec<String> = WINDOW_TYPES
+                .iter()
+                .map(|transaction_type| {
+                    let total = window.totals.for_type(*transaction_type);
+                    format!(
+                        "\"{}\":{{\"count\":{},\"amount\":{}}}",
+                        transaction_type.as_str(),
+                        total.count,
+                        total.amount
+                    )
+                })
+                .collect();
+            writeln!(
+                w,
+                "{{\"window\":\"{}\",\"count\":{},\"amount\":{},\"types\":{{{}}},\"late\":{}}}",
+                label,
+                all.count,
+                all.amount,
+                types.join(","),
+                window.late
+            )
+        }
+    }
+}
+
+/// Writes the totals of every window of the input as soon as the window completes.
+fn stream_windows<R: std::io::Read, W: Write>(
+    input: R,
+    parser: &CommonParser,
+    window: Window,
+    filter: Option<&DayKindFilter>,
+    label: impl Fn(&WindowSummary) -> String,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> Result<u64, ParseError> {
+    write_window_header(output_format, w)?;
+    let mut count = 0;
+    for summary in windows(read_records(input, parser, filter)?, window) {
+        let summary = summary?;
+        write_window(&summary, &label(&summary), output_format, w)?;
+        w.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// What an alert triggers besides being written.
+struct AlertActions {
+    /// Command of `--on-alert`.
+    command: Option<String>,
+    #[cfg(feature = "http-sink")]
+    sink: Option<HttpSink>,
+}
+
+impl AlertActions {
+    /// Runs the command and posts the alert, warning of failures so that watching goes on.
+    fn trigger(&self, alert: &Alert) {
+        if let Some(command) = &self.command {
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("ALERT_RULE", alert.rule.to_string())
+                .env(
+                    "ALERT_USER_ID",
+                    alert.user_id.map(|id| id.to_string()).unwrap_or_default(),
+                )
+                .env("ALERT_VALUE", alert.value.to_string())
+                .env("ALERT_TS", alert.ts.to_string())
+                .env("ALERT_JSON", alert.to_json())
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => cli::warning(format!("Alert command failed: {status}")),
+                Err(err) => cli::warning(format!(
+                    "Failed to run alert command: [{}] {err}",
+                    ErrorCode::Io
+                )),
+            }
+        }
+        #[cfg(feature = "http-sink")]
+        if let Some(sink) = &self.sink
+            && let Err(err) = sink.post_json(&alert.to_json())
+        {
+            cli::warning(format!("Failed to post alert: [{}] {err}", err.code()));
+        }
+    }
+}
+
+/// Writes one alert as a text line, a CSV row or a JSON line.
+fn write_alert<W: Write>(
+    alert: &Alert,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> std::io::Result<()> {
+    match output_format {
+        OutputFormat::Text => writeln!(w, "ALERT {}", alert),
+        OutputFormat::Csv => writeln!(
+            w,
+            "{},\"{}\",{},{}",
+            alert.ts,
+            alert.rule.to_string().replace('"', "\"\""),
+            alert.user_id.map(|id| id.to_string()).unwrap_or_default(),
+            alert.value
+        ),
+        OutputFormat::Json => writeln!(w, "{}", alert.to_json()),
+    }
+}
+
+/// Checks the records of the input against `alerts`, writing and triggering every alert
+/// as soon as the record raising it is read. Returns the number of alerts.
+fn watch_alerts<R: std::io::Read, W: Write>(
+    input: R,
+    parser: &CommonParser,
+    mut alerts: Alerts,
+    filter: Option<&DayKindFilter>,
+    actions: &AlertActions,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> Result<u64, ParseError> {
+    if output_format == OutputFormat::Csv {
+        writeln!(w, "TIMESTAMP,RULE,USER_ID,VALUE")?;
+    }
+    let mut count = 0;
+    for record in read_records(input, parser, filter)? {
+        for alert in alerts.push(&record?) {
+            write_alert(&alert, output_format, w)?;
+            w.flush()?;
+            actions.trigger(&alert);
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Returns the column statistics of the input and whether they come from its sidecar.
+fn column_stats(
+    path: &str,
+    input: std::fs::File,
+    parser: &CommonParser,
+) -> Result<(ColumnStats, bool), ParseError> {
+    if let Some(stats) = ColumnStats::load_sidecar(path)? {
+        return Ok((stats, true));
+    }
+    let mut stats = ColumnStats::new();
+    for record in parser.iter_read(input)? {
+        stats.add(&record?);
+    }
+    Ok((stats, false))
+}
+
+/// A field with the range of its values and how many are zero or empty, when that is
+/// tracked for the field.
+type Column = (&'static str, Option<(i128, i128)>, Option<u64>);
+
+fn columns(stats: &ColumnStats) -> Vec<Column> {
+    let range = |range: Option<(u64, u64)>| range.map(|(min, max)| (min as i128, max as i128));
+    vec![
+        ("TX_ID", range(stats.ids), None),
+        (
+            "FROM_USER_ID",
+            range(stats.from_user_ids),
+            Some(stats.zero_from_user_ids),
+        ),
+        (
+            "TO_USER_ID",
+            range(stats.to_user_ids),
+            Some(stats.zero_to_user_ids),
+        ),
+        (
+            "AMOUNT",
+            stats.amounts.map(|(min, max)| (min as i128, max as i128)),
+            Some(stats.zero_amounts),
+        ),
+        ("TIMESTAMP", range(stats.timestamps), None),
+        (
+            "DESCRIPTION",
+            range(stats.description_lens),
+            Some(stats.empty_descriptions),
+        ),
+    ]
+}
+
+const STATUSES: [TransactionStatus; 3] = [
+    TransactionStatus::Success,
+    TransactionStatus::Failure,
+    TransactionStatus::Pending,
+];
+
+/// Writes column statistics; the range of `DESCRIPTION` is that of its length in bytes.
+fn write_columns<W: Write>(
+    stats: &ColumnStats,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> std::io::Result<()> {
+    match output_format {
+        OutputFormat::Text => {
+            writeln!(w, "Records: {}", stats.records)?;
+            writeln!(w, "Total amount: {}", stats.amount_sum)?;
+            writeln!(
+                w,
+                "
+Columns:"
+            )?;
+            for (name, range, zero) in columns(stats) {
+                let range = range.map_or("-".to_string(), |(min, max)| format!("{}..{}", min, max));
+                write!(w, "  {:<24} {}", name, range)?;
+                match zero {
+                    Some(zero) if name == "DESCRIPTION" => writeln!(w, ", {} empty", zero)?,
+                    Some(zero) => writeln!(w, ", {} zero", zero)?,
+                    None => writeln!(w)?,
+                }
+            }
+            writeln!(
+                w,
+                "
+Types:"
+            )?;
+            for transaction_type in WINDOW_TYPES {
+                let count = stats.type_count(transaction_type);
+                writeln!(w, "  {:<24} {}", transaction_type.as_str(), count)?;
+            }
+            writeln!(
+                w,
+                "
+Statuses:"
+            )?;
+            for status in STATUSES {
+                writeln!(
+                    w,
+                    "  {:<24} {}",
+                    status.as_str(),
+                    stats.status_count(status)
+                )?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            writeln!(w, "METRIC,KEY,VALUE")?;
+            writeln!(w, "records,,{}", stats.records)?;
+            writeln!(w, "total_amount,,{}", stats.amount_sum)?;
+            for (name, range, zero) in columns(stats) {
+                if let Some((min, max)) = range {
+                    writeln!(w, "min,{},{}", name, min)?;
+                    writeln!(w, "max,{},{}", name, max)?;
+                }
+                if let Some(zero) = zero {
+                    writeln!(w, "zero_or_empty,{},{}", name, zero)?;
+                }
+            }
+            for transaction_type in WINDOW_TYPES {
+                let count = stats.type_count(transaction_type);
+                writeln!(w, "type,{},{}", transaction_type.as_str(), count)?;
+            }
+            for status in STATUSES {
+                writeln!(
+                    w,
+                    "status,{},{}",
+                    status.as_str(),
+                    stats.status_count(status)
+                )?;
+            }
+            Ok(())
+        }
+        OutputFormat::Json => {
+            let bound = |bound: Option<i128>| bound.map_or("null".to_string(), |b| b.to_string());
+            let columns: Vec<String> = columns(stats)
+                .into_iter()
+                .map(|(name, range, zero)| {
+                    format!(
+                        "{{\"column\":\"{}\",\"min\":{},\"max\":{},\"zero_or_empty\":{}}}",
+                        name,
+                        bound(range.map(|(min, _)| min)),
+                        bound(range.map(|(_, max)| max)),
+                        zero.map_or("null".to_string(), |zero| zero.to_string())
+                    )
+                })
+                .collect();
+            let types: Vec<String> = WINDOW_TYPES
+                .iter()
+                .map(|t| format!("\"{}\":{}", t.as_str(), stats.type_count(*t)))
+                .collect();
+            let statuses: Vec<String> = STATUSES
+                .iter()
+                .map(|s| format!("\"{}\":{}", s.as_str(), stats.status_count(*s)))
+                .collect();
+            writeln!(
+                w,
+                "{{\"records\":{},\"total_amount\":{},\"columns\":[{}],\"types\":{{{}}},\"statuses\":{{{}}}}}",
+                stats.records,
+                stats.amount_sum,
+                columns.join(","),
+                types.join(","),
+                statuses.join(",")
+            )
+        }
+    }
+}
+
+fn write_stats<W: Write>(
+    stats: &Stats,
+    top: usize,
+    output_format: OutputFormat,
+    w: &mut W,
+) -> std::io::Result<()> {
+    match output_format {
+        OutputFormat::Text => write_text(stats, top, w),
+        OutputFormat::Csv => write_csv(stats, top, w),
+        OutputFormat::Json => write_json(stats, top, w),
+    }
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let format = match Format::from_str(&args.format) {
+        Ok(format) => format,
+        Err(err) => {
+            cli::error(format!(
+                "Invalid format {}: [{}] {err}",
+                args.format,
+                err.code()
+            ));
+            return Status::Usage.into();
+        }
+    };
+    let options = match args.timestamps.timestamp_check() {
+        Ok(timestamp_check) => ParseOptions {
+            timestamp_check,
+            ..ParseOptions::default()
+        },
+        Err(err) => {
+            cli::error(format!("Invalid timestamp check: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+    };
+    let parser = CommonParser::with_options(format, options);
+    let input = match std::fs::File::open(&args.input) {
+        Ok(file) => file,
+        Err(err) => {
+            cli::error(format!(
+                "Failed to open input file {}: [{}] {err}",
+                args.input,
+                ErrorCode::Io
+            ));
+            return Status::Failure.into();
+        }
+    };
+
+    if args.columns {
+        let collected = column_stats(&args.input, input, &parser);
+        cli::report_timestamps(&parser.options().timestamp_check);
+        let stats = match collected {
+            Ok((stats, from_sidecar)) => {
+                if from_sidecar {
+                    cli::summary(format!(
+                        "Read statistics from {}",
+                        ColumnStats::sidecar_path(&args.input).display()
+                    ));
+                }
+                stats
+            }
+            Err(err) => {
+                cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+                return Status::Failure.into();
+            }
+        };
+        if let Err(err) = write_columns(&stats, args.output_format, &mut std::io::stdout()) {
+            cli::error(format!("Failed to write stats: [{}] {err}", ErrorCode::Io));
+            return Status::Failure.into();
+        }
+        return Status::Success.into();
+    }
+
+    if let Some(alerts) = &args.alerts {
+        let actions = AlertActions {
+            command: args.on_alert.clone(),
+            #[cfg(feature = "http-sink")]
+            sink: match args.alert_url.as_deref().map(HttpSink::new).transpose() {
+                Ok(sink) => sink,
+                Err(err) => {
+                    cli::error(format!("Invalid alert URL: [{}] {err}", err.code()));
+                    return Status::Usage.into();
+                }
+            },
+        };
+        let filter = args.day_kind_filter();
+        let watched = watch_alerts(
+            input,
+            &parser,
+            alerts.clone(),
+            filter.as_ref(),
+            &actions,
+            args.output_format,
+            &mut std::io::stdout(),
+        );
+        cli::report_timestamps(&parser.options().timestamp_check);
+        return match watched {
+            Ok(count) => {
+                cli::summary(format!("Raised {count} alerts"));
+                Status::Success.into()
+            }
+            Err(err) => {
+                cli::error(format!("Failed to watch input: [{}] {err}", err.code()));
+                Status::Failure.into()
+            }
+        };
+    }
+
+    if let Some(window) = &args.window {
+        let window = match Window::from_str(window) {
+            Ok(window) => window,
+            Err(err) => {
+                cli::error(format!("Invalid window {}: [{}] {err}", window, err.code()));
+                return Status::Usage.into();
+            }
+        };
+        let mut stdout = std::io::stdout();
+        let filter = args.day_kind_filter();
+        let output_format = args.output_format;
+        let streamed = stream_windows(
+            input,
+            &parser,
+            window,
+            filter.as_ref(),
+            |summary| args.window_label(summary),
+            output_format,
+            &mut stdout,
+        );
+        cli::report_timestamps(&parser.options().timestamp_check);
+        return match streamed {
+            Ok(count) => {
+                cli::summary(format!("Wrote {count} windows"));
+                Status::Success.into()
+            }
+            Err(err) => {
+                cli::error(format!("Failed to aggregate input: [{}] {err}", err.code()));
+                Status::Failure.into()
+            }
+        };
+    }
+
+    let filter = args.day_kind_filter();
+    let collected = args
+        .stats()
+        .and_then(|stats| collect_stats(input, &parser, stats, filter.as_ref()));
+    cli::report_timestamps(&parser.options().timestamp_check);
+    let stats = match collected {
+        Ok(stats) => stats,
+        Err(err) => {
+            cli::error(format!("Failed to read input: [{}] {err}", err.code()));
+            return Status::Failure.into();
+        }
+    };
+    if let Err(err) = write_stats(&stats, args.top, args.output_format, &mut std::io::stdout()) {
+        cli::error(format!("Failed to write stats: [{}] {err}", ErrorCode::Io));
+        return Status::Failure.into();
+    }
+    Status::Success.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::{TransactionStatus, TransactionType, YPBankRecord};
+
+    fn stats() -> Stats {
+        collect(Stats::new(
+            AmountHistogram::new(vec![0, 100]).expect("Should build"),
+        ))
+    }
+
+    fn collect(stats: Stats) -> Stats {
+        let records = vec![
+            YPBankRecord::new(
+                1,
+                TransactionType::Deposit,
+                0,
+                7,
+                50,
+                1633036860000,
+                TransactionStatus::Success,
+                "Salary",
+            ),
+            YPBankRecord::new(
+                2,
+                TransactionType::Transfer,
+                7,
+                8,
+                500,
+                1633040460000,
+                TransactionStatus::Success,
+                "Rent",
+            ),
+        ];
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+        collect_stats(
+            data.as_slice(),
+            &CommonParser::new(Format::Csv),
+            stats,
+            None,
+        )
+        .expect("Should collect stats")
+    }
+
+    fn render(output_format: OutputFormat) -> String {
+        let mut output = Vec::new();
+        write_stats(&stats(), 1, output_format, &mut output).expect("Should write stats");
+        String::from_utf8(output).expect("Should be UTF-8")
+    }
+
+    #[test]
+    fn test_write_csv() {
+        assert_eq!(
+            render(OutputFormat::Csv),
+            "METRIC,KEY,COUNT,AMOUNT\n\
+             records,,2,550\n\
+             amount,..0,0,\n\
+             amount,0..100,1,\n\
+             amount,100..,1,\n\
+             day,2021-09-30,2,\n\
+             hour,2021-09-30T21:00,1,\n\
+             hour,2021-09-30T22:00,1,\n\
+             user,7,2,550\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_day_kinds() {
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &parser::fixtures::records()[..3].to_vec())
+            .expect("Should write records");
+        let calendar = BusinessCalendar::new()
+            .with_holiday("2021-10-01")
+            .expect("Should parse holiday");
+        let stats = collect_stats(
+            data.as_slice(),
+            &CommonParser::new(Format::Csv),
+            Stats::new(AmountHistogram::new(vec![0]).expect("Should build"))
+                .with_calendar(calendar),
+            None,
+        )
+        .expect("Should collect stats");
+        let mut output = Vec::new();
+        write_stats(&stats, 0, OutputFormat::Csv, &mut output).expect("Should write stats");
+        let output = String::from_utf8(output).expect("Should be UTF-8");
+        assert!(
+            output
+                .contains("day_kind,business,0,0\nday_kind,weekend,0,0\nday_kind,holiday,3,600\n")
+        );
+    }
+
+    #[test]
+    fn test_write_json_and_text() {
+        assert_eq!(
+            render(OutputFormat::Json),
+            "{\"records\":2,\"total_amount\":550,\
+             \"amounts\":[{\"from\":null,\"to\":0,\"count\":0},{\"from\":0,\"to\":100,\"count\":1},{\"from\":100,\"to\":null,\"count\":1}],\
+             \"per_day\":[{\"start\":\"2021-09-30\",\"count\":2}],\
+             \"per_hour\":[{\"start\":\"2021-09-30T21:00\",\"count\":1},{\"start\":\"2021-09-30T22:00\",\"count\":1}],\
+             \"top_users\":[{\"user_id\":7,\"count\":2,\"volume\":550}]}\n"
+        );
+        assert!(render(OutputFormat::Text).starts_with("Records: 2\nTotal amount: 550\n"));
+    }
+
+    #[test]
+    fn test_write_columns_csv() {
+        let mut stats = ColumnStats::new();
+        for record in &parser::fixtures::records()[..2] {
+            stats.add(record);
+        }
+        let mut output = Vec::new();
+        write_columns(&stats, OutputFormat::Csv, &mut output).expect("Should write columns");
+        assert_eq!(
+            String::from_utf8(output).expect("Should be UTF-8"),
+            "METRIC,KEY,VALUE\n\
+             records,,2\n\
+             total_amount,,300\n\
+             min,TX_ID,1000000000000000\n\
+             max,TX_ID,1000000000000001\n\
+             min,FROM_USER_ID,0\n\
+             max,FROM_USER_ID,2\n\
+             zero_or_empty,FROM_USER_ID,1\n\
+             min,TO_USER_ID,1\n\
+             max,TO_USER_ID,3\n\
+             zero_or_empty,TO_USER_ID,0\n\
+             min,AMOUNT,100\n\
+             max,AMOUNT,200\n\
+             zero_or_empty,AMOUNT,0\n\
+             min,TIMESTAMP,1633046460000\n\
+             max,TIMESTAMP,1633046520000\n\
+             min,DESCRIPTION,15\n\
+             max,DESCRIPTION,15\n\
+             zero_or_empty,DESCRIPTION,0\n\
+             type,DEPOSIT,1\n\
+             type,TRANSFER,1\n\
+             type,WITHDRAWAL,0\n\
+             status,SUCCESS,2\n\
+             status,FAILURE,0\n\
+             status,PENDING,0\n"
+        );
+    }
+
+    #[test]
+    fn test_stream_windows() {
+        let record = |transaction_type, amount, ts| {
+            YPBankRecord::new(
+                1,
+                transaction_type,
+                match transaction_type {
+                    TransactionType::Deposit => 0,
+                    _ => 3,
+                },
+                7,
+                amount,
+                ts,
+                TransactionStatus::Success,
+                "",
+            )
+        };
+        let records = vec![
+            record(TransactionType::Deposit, 50, 1633036860000),
+            record(TransactionType::Transfer, 500, 1633037000000),
+            record(TransactionType::Deposit, 10, 1633040460000),
+        ];
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+
+        let window = Window::from_str("1h").expect("Should parse window");
+        let render = |output_format| {
+            let mut output = Vec::new();
+            let count = stream_windows(
+                data.as_slice(),
+                &CommonParser::new(Format::Csv),
+                window,
+                None,
+                WindowSummary::label,
+                output_format,
+                &mut output,
+            )
+            .expect("Should aggregate");
+            assert_eq!(count, 2);
+            String::from_utf8(output).expect("Should be UTF-8")
+        };
+        assert_eq!(
+            render(OutputFormat::Csv),
+            "WINDOW,COUNT,AMOUNT,DEPOSIT_COUNT,DEPOSIT_AMOUNT,TRANSFER_COUNT,TRANSFER_AMOUNT,WITHDRAWAL_COUNT,WITHDRAWAL_AMOUNT,LATE\n\
+             2021-09-30T21:00,2,550,1,50,1,500,0,0,0\n\
+             2021-09-30T22:00,1,10,1,10,0,0,0,0,0\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Json).lines().next(),
+            Some(
+                "{\"window\":\"2021-09-30T21:00\",\"count\":2,\"amount\":550,\
+                 \"types\":{\"DEPOSIT\":{\"count\":1,\"amount\":50},\"TRANSFER\":{\"count\":1,\"amount\":500},\"WITHDRAWAL\":{\"count\":0,\"amount\":0}},\"late\":0}"
+            )
+        );
+        assert_eq!(
+            render(OutputFormat::Text).lines().last(),
+            Some(
+                "2021-09-30T22:00   1 records, amount 10; DEPOSIT 1/10; TRANSFER 0/0; WITHDRAWAL 0/0"
+            )
+        );
+    }
+
+    #[cfg(feature = "tz")]
+    #[test]
+    fn test_write_csv_in_time_zone() {
+        let moscow = TimeZone::from_posix("MSK-3").expect("Should parse");
+        let stats = collect(
+            Stats::new(AmountHistogram::new(vec![0]).expect("Should build")).with_time_zone(moscow),
+        );
+        let mut output = Vec::new();
+        write_stats(&stats, 0, OutputFormat::Csv, &mut output).expect("Should write stats");
+        let output = String::from_utf8(output).expect("Should be UTF-8");
+        assert!(
+            output.contains(
+                "day,2021-10-01,2,\nhour,2021-10-01T00:00,1,\nhour,2021-10-01T01:00,1,\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_watch_alerts() {
+        let failure = |ts| {
+            YPBankRecord::new(
+                1,
+                TransactionType::Deposit,
+                0,
+                7,
+                100,
+                ts,
+                TransactionStatus::Failure,
+                "",
+            )
+        };
+        let records: Vec<_> = [1633036860000, 1633036870000, 1633036880000]
+            .into_iter()
+            .map(failure)
+            .collect();
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+
+        let path = std::env::temp_dir().join(format!("stats_alerts_{}.log", std::process::id()));
+        let actions = AlertActions {
+            command: Some(format!(
+                "echo \"$ALERT_RULE $ALERT_VALUE $ALERT_TS\" >> {}",
+                path.display()
+            )),
+            #[cfg(feature = "http-sink")]
+            sink: None,
+        };
+        let mut output = Vec::new();
+        let count = watch_alerts(
+            data.as_slice(),
+            &CommonParser::new(Format::Csv),
+            Alerts::from_text("FAILURE > 1 in 5m").expect("Should parse rules"),
+            None,
+            &actions,
+            OutputFormat::Csv,
+            &mut output,
+        )
+        .expect("Should watch");
+        let ran = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!(count, 1);
+        assert_eq!(
+            String::from_utf8(output).expect("Should be UTF-8"),
+            "TIMESTAMP,RULE,USER_ID,VALUE\n1633036870000,\"FAILURE > 1 in 5m\",,2\n"
+        );
+        assert_eq!(
+            ran.expect("Should run command"),
+            "FAILURE > 1 in 5m 2 1633036870000\n"
+        );
+    }
+}
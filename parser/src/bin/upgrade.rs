@@ -0,0 +1,242 @@
+use clap::{CommandFactory, Parser};
+use parser::cli::{self, Status, VerbosityArgs};
+use parser::{CommonParser, Format, ParseError, ParseOptions, SchemaMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+const CSV_HEADER: &str =
+    "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION";
+
+/// Column names of CSV files written before the YPBank header was settled.
+const LEGACY_CSV_COLUMNS: [(&str, &str); 4] = [
+    ("TXID", "TX_ID"),
+    ("TRANSACTION_ID", "TX_ID"),
+    ("FROM", "FROM_USER_ID"),
+    ("TO", "TO_USER_ID"),
+];
+
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Files to rewrite in place in the current layout of their format
+    #[arg(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
+
+    /// Format of the files; inferred from each file's extension or content when omitted
+    #[arg(long)]
+    format: Option<String>,
+
+    /// TOML file mapping legacy CSV columns and values besides the known ones
+    #[arg(long, value_name = "FILE")]
+    schema_map: Option<PathBuf>,
+
+    /// Report the files that would be rewritten without changing them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Copy each file to FILE.bak before replacing it
+    #[arg(long)]
+    backup: bool,
+
+    #[command(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// Returns the schema map reading a CSV file with `header`, or `None` when the header
+/// is already the YPBank one.
+///
+/// Legacy column names are matched case-insensitively and added to `schema`.
+fn legacy_csv_schema(header: &str, schema: &SchemaMap) -> Result<Option<SchemaMap>, ParseError> {
+    let header = header.trim();
+    if header == CSV_HEADER {
+        return Ok(None);
+    }
+
+    let mut schema = schema.clone();
+    for column in header.split(',').map(str::trim) {
+        let field = LEGACY_CSV_COLUMNS
+            .iter()
+            .find(|(legacy, _)| legacy.eq_ignore_ascii_case(column))
+            .map(|(_, field)| *field)
+            .or_else(|| {
+                CSV_HEADER
+                    .split(',')
+                    .find(|field| field.eq_ignore_ascii_case(column))
+            });
+        if let Some(field) = field
+            && field != column
+        {
+            schema = schema.with_column(column, field)?;
+        }
+    }
+    Ok(Some(schema))
+}
+
+/// Returns the options reading the file at `path` in its old layout, or `None` when it
+/// is up to date. Formats other than CSV have a single layout so far.
+fn plan(
+    path: &Path,
+    format: Format,
+    schema: &SchemaMap,
+) -> Result<Option<ParseOptions>, ParseError> {
+    match format {
+        Format::Csv => {
+            let mut header = String::new();
+            BufReader::new(File::open(path)?).read_line(&mut header)?;
+            Ok(
+                legacy_csv_schema(&header, schema)?.map(|schema| ParseOptions {
+                    csv_schema: Some(schema),
+                    ..ParseOptions::default()
+                }),
+            )
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Streams the records of the file at `path` back to it in the current layout. The file
+/// is replaced only once every record has been read and written.
+fn rewrite(
+    path: &Path,
+    format: Format,
+    options: ParseOptions,
+    backup: Option<&Path>,
+) -> Result<(), ParseError> {
+    let records = CommonParser::with_options(format, options).iter_read(File::open(path)?)?;
+    CommonParser::new(format).write_iter_to_path_atomic(path, records, backup)
+}
+
+/// Upgrades one file, returning whether it was (or, in a dry run, would be) rewritten.
+fn upgrade(args: &Args, path: &Path, schema: &SchemaMap) -> Result<bool, ParseError> {
+    let format = match &args.format {
+        Some(format) => Format::from_str(format)?,
+        None => Format::infer_from_path(path)?,
+    };
+    let Some(options) = plan(path, format, schema)? else {
+        return Ok(false);
+    };
+    if !args.dry_run {
+        let backup = args.backup.then(|| {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            PathBuf::from(backup)
+        });
+        rewrite(path, format, options, backup.as_deref())?;
+    }
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    if let Some(result) = cli::generate(Args::command(), std::env::args(), &mut std::io::stdout()) {
+        if let Err(err) = result {
+            cli::error(format!("Failed to generate: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        return Status::Success.into();
+    }
+    let args = Args::parse();
+    cli::set_verbosity(args.verbosity.verbosity());
+
+    let schema = match args.schema_map.as_ref().map(SchemaMap::from_path) {
+        Some(Ok(schema)) => schema,
+        Some(Err(err)) => {
+            cli::error(format!("Invalid schema map: [{}] {err}", err.code()));
+            return Status::Usage.into();
+        }
+        None => SchemaMap::new(),
+    };
+
+    let mut upgraded = 0;
+    let mut succeeded = true;
+    for path in &args.files {
+        match upgrade(&args, path, &schema) {
+            Ok(true) if args.dry_run => {
+                println!("{}: would rewrite legacy CSV header", path.display());
+                upgraded += 1;
+            }
+            Ok(true) => {
+                println!("{}: rewrote legacy CSV header", path.display());
+                upgraded += 1;
+            }
+            Ok(false) => println!("{}: up to date", path.display()),
+            Err(err) => {
+                cli::error(format!(
+                    "Failed to upgrade {}: [{}] {err}",
+                    path.display(),
+                    err.code()
+                ));
+                succeeded = false;
+            }
+        }
+    }
+
+    match args.dry_run {
+        true => cli::summary(format!(
+            "{upgraded} of {} files would be upgraded",
+            args.files.len()
+        )),
+        false => cli::summary(format!("Upgraded {upgraded} of {} files", args.files.len())),
+    }
+    Status::from(succeeded).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::fixtures;
+
+    #[test]
+    fn test_legacy_csv_schema() {
+        let schema = SchemaMap::new();
+        assert_eq!(
+            legacy_csv_schema(&format!("{CSV_HEADER}\n"), &schema),
+            Ok(None)
+        );
+        assert_eq!(
+            legacy_csv_schema(
+                "txid,TX_TYPE,from,TO,amount,TIMESTAMP,STATUS,DESCRIPTION\n",
+                &schema
+            ),
+            Ok(Some(
+                SchemaMap::new()
+                    .with_column("txid", "TX_ID")
+                    .and_then(|schema| schema.with_column("from", "FROM_USER_ID"))
+                    .and_then(|schema| schema.with_column("TO", "TO_USER_ID"))
+                    .and_then(|schema| schema.with_column("amount", "AMOUNT"))
+                    .expect("Should map columns")
+            ))
+        );
+    }
+
+    #[test]
+    fn test_rewrite_legacy_csv_header() {
+        let dir = std::env::temp_dir().join(format!("upgrade_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create dir");
+        let path = dir.join("legacy.csv");
+        let records = fixtures::records();
+
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+        let data = String::from_utf8(data).expect("Should write UTF-8");
+        let legacy = data.replacen(
+            "TX_ID,TX_TYPE,FROM_USER_ID",
+            "TRANSACTION_ID,TX_TYPE,FROM",
+            1,
+        );
+        std::fs::write(&path, &legacy).expect("Should write legacy file");
+
+        let options = plan(&path, Format::Csv, &SchemaMap::new())
+            .expect("Should read header")
+            .expect("Should need upgrading");
+        rewrite(&path, Format::Csv, options, None).expect("Should rewrite");
+
+        assert_eq!(std::fs::read_to_string(&path).ok(), Some(data));
+        assert_eq!(plan(&path, Format::Csv, &SchemaMap::new()), Ok(None));
+        std::fs::remove_dir_all(dir).expect("Should remove dir");
+    }
+}
@@ -2,11 +2,86 @@ use crate::common::{
     TransactionStatus, TransactionType, read_i64_from_bytes, read_u8_from_bytes,
     read_u32_from_bytes, read_u64_from_bytes, validate_from_user_id, validate_to_user_id,
 };
+use crate::constant::DESCRIPTION;
 use crate::error::ParseError;
-use crate::parser::{Parser, YPBankRecordParser};
-use crate::record::YPBankRecord;
+use crate::options::{DescriptionRedaction, ParseOptions};
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Bytes of encoded records gathered by [`write_records_buffered`] before each write.
+const WRITE_BATCH_BYTES: usize = 64 * 1024;
+/// Tag of the extension entries holding an extra of [`YPBankRecordExt`].
+///
+/// Extension entries follow the description within the record size, each a tag byte,
+/// the length of the entry data and the data; extras are the length of their name,
+/// the name and the value. Readers skip entries of unknown tags.
+const EXTRA_TAG: u8 = 1;
+
+/// Appends the binary encoding of `record`, magic and size included, to `buf`.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{encode_bin_record, fixtures};
+///
+/// let mut buf = Vec::new();
+/// for record in fixtures::records() {
+///     encode_bin_record(&record, &mut buf);
+/// }
+/// assert_eq!(&buf[..4], b"YPBN");
+/// ```
+pub fn encode_bin_record(record: &YPBankRecord, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&YPBankBinRecordParser::MAGIC);
+    buf.extend_from_slice(
+        &YPBankBinRecordParser::get_record_size(&record.description).to_be_bytes(),
+    );
+
+    buf.extend_from_slice(&record.id.to_be_bytes());
+    buf.extend_from_slice(&record.transaction_type.as_int().to_be_bytes());
+    buf.extend_from_slice(&record.from_user_id.to_be_bytes());
+    buf.extend_from_slice(&record.to_user_id.to_be_bytes());
+    buf.extend_from_slice(&record.amount.to_be_bytes());
+    buf.extend_from_slice(&record.ts.to_be_bytes());
+    buf.extend_from_slice(&record.status.as_int().to_be_bytes());
+    buf.extend_from_slice(&(record.description.len() as u32).to_be_bytes());
+    buf.extend_from_slice(record.description.as_bytes());
+}
 
-pub struct YPBankBinRecordParser {}
+/// Writes records in the binary format, encoding them into one buffer written whenever
+/// it holds 64 KiB, so that unbuffered writers such as files get a write per batch
+/// rather than per record.
+pub fn write_records_buffered<W: std::io::Write>(
+    w: &mut W,
+    records: impl IntoIterator<Item = impl Borrow<YPBankRecord>>,
+) -> Result<(), ParseError> {
+    let mut buf = Vec::with_capacity(WRITE_BATCH_BYTES);
+    for record in records {
+        encode_bin_record(record.borrow(), &mut buf);
+        if buf.len() >= WRITE_BATCH_BYTES {
+            w.write_all(&buf)?;
+            buf.clear();
+        }
+    }
+    w.write_all(&buf)?;
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct YPBankBinRecordParser {
+    buf: Vec<u8>,
+    /// Whether extension entries are read into `extras` rather than skipped.
+    preserve_extras: bool,
+    /// Descriptions other than [`DescriptionRedaction::Keep`] are skipped unread.
+    redaction: DescriptionRedaction,
+    extras: BTreeMap<String, String>,
+    /// Whether the last failed record stopped before its end.
+    partial: bool,
+    /// Whether the magic of the next record was read while skipping an invalid one.
+    magic_read: bool,
+}
 
 impl YPBankBinRecordParser {
     const MAGIC: [u8; 4] = [0x59, 0x50, 0x42, 0x4E];
@@ -37,7 +112,20 @@ impl YPBankBinRecordParser {
         read_u32_from_bytes(r)
     }
 
-    fn parse_record<R: std::io::BufRead>(r: &mut R) -> Result<YPBankRecord, ParseError> {
+    /// Parses a record, returning it with the length of its description as written.
+    fn parse_record<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<(YPBankRecord, u32), ParseError> {
+        let mut record = Self::parse_fixed_fields(r)?;
+        let desc_len;
+        (record.description, desc_len) = self.read_description_from_bytes(r)?;
+
+        Ok((record, desc_len))
+    }
+
+    /// Parses every field but the description, which is left empty.
+    fn parse_fixed_fields<R: std::io::BufRead>(r: &mut R) -> Result<YPBankRecord, ParseError> {
         let id = read_u64_from_bytes(r)?;
         let transaction_type = TransactionType::from_int(read_u8_from_bytes(r)?)?;
         let from_user_id = validate_from_user_id(read_u64_from_bytes(r)?, transaction_type)?;
@@ -45,7 +133,6 @@ impl YPBankBinRecordParser {
         let amount = read_i64_from_bytes(r)?;
         let ts = read_u64_from_bytes(r)?;
         let status = TransactionStatus::from_int(read_u8_from_bytes(r)?)?;
-        let description = Self::read_description_from_bytes(r)?;
 
         Ok(YPBankRecord::new(
             id,
@@ -55,17 +142,163 @@ impl YPBankBinRecordParser {
             amount,
             ts,
             status,
-            description,
+            "",
         ))
     }
 
-    fn read_description_from_bytes<R: std::io::BufRead>(r: &mut R) -> Result<String, ParseError> {
-        let desc_len = read_u32_from_bytes(r)? as usize;
+    /// Reads the next record for which `keep` returns `true`.
+    ///
+    /// `keep` sees the record with an empty description; descriptions of skipped records
+    /// are seeked over instead of being read and decoded.
+    pub fn read_matching<R: std::io::Read + std::io::Seek>(
+        &mut self,
+        r: &mut std::io::BufReader<R>,
+        keep: impl Fn(&YPBankRecord) -> bool,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        loop {
+            if let Err(err) = Self::validate_magic(r) {
+                if err == ParseError::UnexpectedEOF {
+                    return Ok(None);
+                }
+
+                return Err(err);
+            }
+
+            let record_size = Self::parse_record_size(r)?;
+            if record_size == 0 {
+                return Ok(None);
+            }
 
-        let mut desc_bytes = vec![0; desc_len];
-        r.read_exact(&mut desc_bytes)?;
+            let mut record = Self::parse_fixed_fields(r)?;
+            if keep(&record) {
+                let desc_len;
+                (record.description, desc_len) = self.read_description_from_bytes(r)?;
+                self.read_extension(r, record_size, desc_len)?;
+                return Ok(Some(record));
+            }
 
-        String::from_utf8(desc_bytes).map_err(|err| ParseError::InvalidRawValue(err.to_string()))
+            let desc_len = read_u32_from_bytes(r)?;
+            let rest = record_size
+                .saturating_sub(Self::get_record_size(""))
+                .max(desc_len);
+            r.seek_relative(rest as i64)?;
+        }
+    }
+
+    /// Reads the description, returning it with its length. Redacted descriptions are
+    /// skipped without being decoded.
+    fn read_description_from_bytes<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<(Arc<str>, u32), ParseError> {
+        let desc_len = read_u32_from_bytes(r)?;
+
+        if let Some(description) = self.redaction.redact(desc_len as usize) {
+            let skipped = std::io::copy(
+                &mut <&mut R as std::io::Read>::take(&mut *r, desc_len as u64),
+                &mut std::io::sink(),
+            )?;
+            if skipped < desc_len as u64 {
+                return Err(ParseError::UnexpectedEOF);
+            }
+            self.partial = false;
+            return Ok((description, desc_len));
+        }
+
+        self.buf.clear();
+        self.buf.resize(desc_len as usize, 0);
+        r.read_exact(&mut self.buf)?;
+        self.partial = false;
+
+        let description =
+            std::str::from_utf8(&self.buf).map_err(|err| ParseError::InvalidRawValue {
+                field: DESCRIPTION.to_string(),
+                value: err.to_string(),
+            })?;
+        Ok((description.into(), desc_len))
+    }
+
+    /// Reads the extension entries between the description and the end of the record,
+    /// keeping the extras when they are preserved.
+    fn read_extension<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+        record_size: u32,
+        desc_len: u32,
+    ) -> Result<(), ParseError> {
+        self.extras.clear();
+        let len = record_size
+            .saturating_sub(Self::get_record_size(""))
+            .saturating_sub(desc_len) as usize;
+        if len == 0 {
+            return Ok(());
+        }
+
+        self.partial = true;
+        self.buf.clear();
+        self.buf.resize(len, 0);
+        r.read_exact(&mut self.buf)?;
+        self.partial = false;
+        if !self.preserve_extras {
+            return Ok(());
+        }
+
+        let mut entries = &self.buf[..];
+        while let Some((&tag, rest)) = entries.split_first() {
+            let data_len = rest
+                .first_chunk::<4>()
+                .map(|len| u32::from_be_bytes(*len) as usize)
+                .filter(|data_len| rest.len() - 4 >= *data_len)
+                .ok_or_else(Self::invalid_extension)?;
+            let data = &rest[4..4 + data_len];
+            entries = &rest[4 + data_len..];
+            if tag != EXTRA_TAG {
+                continue;
+            }
+
+            let name_len = data
+                .first_chunk::<4>()
+                .map(|len| u32::from_be_bytes(*len) as usize)
+                .filter(|name_len| data.len() - 4 >= *name_len)
+                .ok_or_else(Self::invalid_extension)?;
+            let (name, value) = data[4..].split_at(name_len);
+            match (std::str::from_utf8(name), std::str::from_utf8(value)) {
+                (Ok(name), Ok(value)) => {
+                    self.extras.insert(name.to_string(), value.to_string());
+                }
+                _ => return Err(Self::invalid_extension()),
+            }
+        }
+        Ok(())
+    }
+
+    fn invalid_extension() -> ParseError {
+        ParseError::InconsistentRecord("invalid extension entry".to_string())
+    }
+
+    /// Counts records by skipping over them by their size, without decoding them.
+    pub(crate) fn count_records<R: std::io::BufRead>(r: &mut R) -> Result<u64, ParseError> {
+        let mut count = 0;
+        loop {
+            match Self::validate_magic(r) {
+                Ok(()) => {}
+                Err(ParseError::UnexpectedEOF) => return Ok(count),
+                Err(err) => return Err(err),
+            }
+
+            let record_size = Self::parse_record_size(r)? as u64;
+            if record_size == 0 {
+                return Ok(count);
+            }
+            let skipped = std::io::copy(
+                &mut std::io::Read::take(&mut *r, record_size),
+                &mut std::io::sink(),
+            )?;
+            if skipped < record_size {
+                return Err(ParseError::UnexpectedEOF);
+            }
+            count += 1;
+        }
     }
 
     fn get_record_size(description: &str) -> u32 {
@@ -73,9 +306,25 @@ impl YPBankBinRecordParser {
     }
 }
 
-impl YPBankRecordParser for YPBankBinRecordParser {
-    fn from_read<R: std::io::BufRead>(r: &mut R) -> Result<Option<YPBankRecord>, ParseError> {
-        if let Err(err) = Self::validate_magic(r) {
+impl FromOptions for YPBankBinRecordParser {
+    fn with_options(options: &ParseOptions) -> Self {
+        Self {
+            preserve_extras: options.preserve_extras,
+            redaction: options.redact_descriptions.clone(),
+            ..Self::default()
+        }
+    }
+}
+
+impl RecordReader for YPBankBinRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        self.partial = true;
+        if !std::mem::take(&mut self.magic_read)
+            && let Err(err) = Self::validate_magic(r)
+        {
             if err == ParseError::UnexpectedEOF {
                 return Ok(None);
             }
@@ -88,27 +337,69 @@ impl YPBankRecordParser for YPBankBinRecordParser {
             return Ok(None);
         }
 
-        let record = Self::parse_record(r)?;
+        let (record, desc_len) = self.parse_record(r)?;
+        self.read_extension(r, record_size, desc_len)?;
         Ok(Some(record))
     }
 
-    fn write_to<W: std::io::Write>(record: &YPBankRecord, w: &mut W) -> Result<(), ParseError> {
-        let mut bytes: Vec<u8> = Vec::new();
+    fn take_extras(&mut self) -> BTreeMap<String, String> {
+        std::mem::take(&mut self.extras)
+    }
+
+    /// Skips to the magic of the next record. A description holding the magic bytes is
+    /// taken for the start of a record, which then fails to parse in turn.
+    fn skip_invalid<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        let mut window = [0; 4];
+        while self.partial {
+            let mut byte = [0];
+            if r.read(&mut byte)? == 0 {
+                break;
+            }
+            window.rotate_left(1);
+            window[3] = byte[0];
+            if window == Self::MAGIC {
+                self.magic_read = true;
+                break;
+            }
+        }
+        self.partial = false;
+        Ok(true)
+    }
+}
 
-        bytes.extend_from_slice(&Self::MAGIC);
-        bytes.extend_from_slice(&Self::get_record_size(&record.description).to_be_bytes());
+impl RecordWriter for YPBankBinRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        self.buf.clear();
+        encode_bin_record(record, &mut self.buf);
+        w.write_all(&self.buf)?;
 
-        bytes.extend_from_slice(&record.id.to_be_bytes());
-        bytes.extend_from_slice(&record.transaction_type.as_int().to_be_bytes());
-        bytes.extend_from_slice(&record.from_user_id.to_be_bytes());
-        bytes.extend_from_slice(&record.to_user_id.to_be_bytes());
-        bytes.extend_from_slice(&record.amount.to_be_bytes());
-        bytes.extend_from_slice(&record.ts.to_be_bytes());
-        bytes.extend_from_slice(&record.status.as_int().to_be_bytes());
-        bytes.extend_from_slice(&(record.description.len() as u32).to_be_bytes());
-        bytes.extend_from_slice(record.description.as_bytes());
+        Ok(())
+    }
 
-        w.write_all(&bytes)?;
+    /// Writes the extras as extension entries after the description.
+    fn write_ext_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecordExt,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        self.buf.clear();
+        encode_bin_record(&record.core, &mut self.buf);
+        for (name, value) in &record.extras {
+            self.buf.push(EXTRA_TAG);
+            self.buf
+                .extend_from_slice(&((4 + name.len() + value.len()) as u32).to_be_bytes());
+            self.buf
+                .extend_from_slice(&(name.len() as u32).to_be_bytes());
+            self.buf.extend_from_slice(name.as_bytes());
+            self.buf.extend_from_slice(value.as_bytes());
+        }
+        let record_size = (self.buf.len() - 8) as u32;
+        self.buf[4..8].copy_from_slice(&record_size.to_be_bytes());
+        w.write_all(&self.buf)?;
 
         Ok(())
     }
@@ -146,7 +437,7 @@ mod yp_bank_bin_record_tests {
         data.extend_from_slice(desc_bytes); // DESCRIPTION
 
         let mut reader = Cursor::new(data);
-        let result = YPBankBinRecordParser::from_read(&mut reader);
+        let result = YPBankBinRecordParser::default().from_read(&mut reader);
 
         let target_record = YPBankRecord::new(
             1000000000000000,
@@ -179,7 +470,7 @@ mod yp_bank_bin_record_tests {
         );
 
         let mut writer = Cursor::new(Vec::new());
-        let result = YPBankBinRecordParser::write_to(&record, &mut writer);
+        let result = YPBankBinRecordParser::default().write_to(&record, &mut writer);
         assert!(result.is_ok(), "Writing should succeed");
 
         let written = writer.into_inner();
@@ -190,7 +481,7 @@ mod yp_bank_bin_record_tests {
         assert_eq!(record_size, 46 + record.description.len() as u32);
 
         let mut reader = Cursor::new(&written);
-        let read_result = YPBankBinRecordParser::from_read(&mut reader);
+        let read_result = YPBankBinRecordParser::default().from_read(&mut reader);
         assert!(read_result.is_ok(), "Reading should succeed");
         let read_record_opt = read_result.expect("Should parse successfully");
         let read_record = read_record_opt.expect("Should have a record");
@@ -205,18 +496,45 @@ mod yp_bank_bin_record_tests {
         data.extend_from_slice(&0u32.to_be_bytes());
 
         let mut reader = Cursor::new(data);
-        let result = YPBankBinRecordParser::from_read(&mut reader);
+        let result = YPBankBinRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_err(), "Should return an error");
 
-        let error = result.err().expect("Should return an error");
+        let error = result.expect_err("Should return an error");
         assert!(matches!(error, ParseError::InvalidMagic(_)));
     }
 
+    #[test]
+    fn test_from_read_invalid_description() {
+        let record = YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "ab".to_string(),
+        );
+        let mut writer = Cursor::new(Vec::new());
+        YPBankBinRecordParser::default()
+            .write_to(&record, &mut writer)
+            .expect("Writing should succeed");
+        let mut data = writer.into_inner();
+        let len = data.len();
+        data[len - 1] = 0xff;
+
+        let result = YPBankBinRecordParser::default().from_read(&mut Cursor::new(data));
+        assert!(matches!(
+            result,
+            Err(ParseError::InvalidRawValue { field, .. }) if field == "DESCRIPTION"
+        ));
+    }
+
     #[test]
     fn test_from_read_eof() {
         let mut reader = Cursor::new(Vec::<u8>::new());
-        let result = YPBankBinRecordParser::from_read(&mut reader);
+        let result = YPBankBinRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_ok(), "EOF should return Ok(None)");
         assert!(
@@ -229,8 +547,11 @@ mod yp_bank_bin_record_tests {
 #[cfg(test)]
 mod bin_parser_tests {
     use super::*;
+    use crate::error::ErrorCode;
+    use crate::options::ParseOptions;
     use std::io::Cursor;
 
+    #[allow(clippy::too_many_arguments)]
     fn create_record_data(
         id: u64,
         tx_type: u8,
@@ -286,7 +607,7 @@ mod bin_parser_tests {
         ));
 
         let mut reader = Cursor::new(data);
-        let result = BinParser::from_read(&mut reader);
+        let result = BinParser::from_read(&mut reader, &ParseOptions::default());
 
         let target_records = vec![
             YPBankRecord::new(
@@ -317,6 +638,45 @@ mod bin_parser_tests {
         assert_eq!(records, target_records);
     }
 
+    #[test]
+    fn test_iter_read_skips_invalid_records() {
+        let mut data = create_record_data(1, 0, 0, 2, 100, 1633036860000, 0, "Salary");
+        data.extend_from_slice(&create_record_data(
+            2,
+            7,
+            0,
+            2,
+            200,
+            1633036860000,
+            0,
+            "Bad",
+        ));
+        data.extend_from_slice(b"garbage");
+        data.extend_from_slice(&create_record_data(
+            3,
+            0,
+            0,
+            2,
+            300,
+            1633036860000,
+            0,
+            "Bonus",
+        ));
+        let options = ParseOptions {
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+
+        let ids: Vec<_> = BinParser::iter_read(data.as_slice(), &options)
+            .expect("Should start reading")
+            .map(|record| record.map(|record| record.id).map_err(|err| err.code()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![Ok(1), Err(ErrorCode::InvalidTransactionType), Ok(3)]
+        );
+    }
+
     #[test]
     fn test_write_to_multiple_records() {
         let records = vec![
@@ -343,16 +703,159 @@ mod bin_parser_tests {
         ];
 
         let mut writer = Cursor::new(Vec::new());
-        let result = BinParser::write_to(&mut writer, &records);
+        let result = BinParser::write_to(&mut writer, &records, &ParseOptions::default());
         assert!(result.is_ok(), "Writing should succeed");
 
         let written = writer.into_inner();
 
         let mut reader = Cursor::new(&written);
-        let read_result = BinParser::from_read(&mut reader);
+        let read_result = BinParser::from_read(&mut reader, &ParseOptions::default());
         assert!(read_result.is_ok(), "Reading should succeed");
         let read_records = read_result.expect("Should parse successfully");
         assert_eq!(read_records.len(), 2);
         assert_eq!(read_records, records);
     }
+
+    #[test]
+    fn test_iter_buf_read_leaves_data_after_records() {
+        let records = crate::fixtures::records();
+        let mut data = Vec::new();
+        BinParser::write_to(&mut data, &records, &ParseOptions::default())
+            .expect("Should write records");
+        data.extend_from_slice(&YPBankBinRecordParser::MAGIC);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(b"trailer");
+
+        let mut r = std::io::BufReader::new(data.as_slice());
+        let read = BinParser::iter_buf_read_at(&mut r, &ParseOptions::default(), 0)
+            .expect("Should start reading")
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(read, Ok(records));
+
+        let mut rest = String::new();
+        std::io::Read::read_to_string(&mut r, &mut rest).expect("Should read the rest");
+        assert_eq!(rest, "trailer");
+    }
+
+    /// Counts the writes it receives.
+    #[derive(Default)]
+    struct CountingWriter {
+        data: Vec<u8>,
+        writes: usize,
+    }
+
+    impl std::io::Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.writes += 1;
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_records_buffered() {
+        let records = crate::fixtures::records();
+        let mut expected = Vec::new();
+        BinParser::write_to(&mut expected, &records, &ParseOptions::default())
+            .expect("Should write records");
+
+        let mut w = CountingWriter::default();
+        write_records_buffered(&mut w, &records).expect("Should write records");
+        assert_eq!((w.data, w.writes), (expected, 1));
+
+        let many = std::iter::repeat_n(&records[0], 5000);
+        let mut w = CountingWriter::default();
+        write_records_buffered(&mut w, many.clone()).expect("Should write records");
+        assert_eq!(w.writes, w.data.len().div_ceil(WRITE_BATCH_BYTES));
+        assert_eq!(
+            BinParser::from_read(&mut w.data.as_slice(), &ParseOptions::default()),
+            Ok(many.cloned().collect())
+        );
+
+        let mut w = CountingWriter::default();
+        BinParser::write_to(&mut w, &records, &ParseOptions::default())
+            .expect("Should write records");
+        assert_eq!(w.writes, 1);
+    }
+
+    #[test]
+    fn test_extras_round_trip() {
+        let mut record = YPBankRecordExt::from(YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "Salary",
+        ));
+        record
+            .extras
+            .insert("audited_by".to_string(), "jdoe".to_string());
+        let options = ParseOptions {
+            preserve_extras: true,
+            ..ParseOptions::default()
+        };
+
+        let mut data = Vec::new();
+        BinParser::write_ext_to(&mut data, std::slice::from_ref(&record), &options)
+            .expect("Should write successfully");
+        // An entry of an unknown tag, left by a newer writer.
+        data.extend_from_slice(&[9, 0, 0, 0, 1, 0xFF]);
+        let record_size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) + 6;
+        data[4..8].copy_from_slice(&record_size.to_be_bytes());
+        data.extend_from_slice(&data.clone());
+
+        let read = BinParser::iter_read(Cursor::new(&data), &options)
+            .expect("Should read successfully")
+            .with_extras()
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(read, Ok(vec![record.clone(), record.clone()]));
+
+        let read = BinParser::from_read(&mut Cursor::new(&data), &ParseOptions::default());
+        assert_eq!(read, Ok(vec![record.core.clone(), record.core]));
+    }
+
+    #[test]
+    fn test_redacted_descriptions_are_not_decoded() {
+        let mut record = YPBankRecordExt::from(YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "ab",
+        ));
+        record
+            .extras
+            .insert("audited_by".to_string(), "jdoe".to_string());
+        let options = ParseOptions {
+            preserve_extras: true,
+            ..ParseOptions::default()
+        };
+        let mut data = Vec::new();
+        BinParser::write_ext_to(&mut data, std::slice::from_ref(&record), &options)
+            .expect("Should write successfully");
+        // Invalid UTF-8, which fails unless the description is skipped.
+        data[54] = 0xff;
+
+        let options = ParseOptions {
+            redact_descriptions: DescriptionRedaction::Mask("[REDACTED]".into()),
+            ..options
+        };
+        let read = BinParser::iter_read(Cursor::new(&data), &options)
+            .expect("Should read successfully")
+            .with_extras()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Should skip the description");
+        assert_eq!(&*read[0].core.description, "[REDACTED]");
+        assert_eq!(read[0].extras, record.extras);
+    }
 }
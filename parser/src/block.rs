@@ -0,0 +1,640 @@
+use crate::bin_format::YPBankBinRecordParser;
+use crate::common::{
+    read_i64_from_bytes, read_u8_from_bytes, read_u32_from_bytes, read_u64_from_bytes,
+};
+use crate::error::ParseError;
+use crate::parser::{RecordReader, RecordWriter};
+use crate::record::YPBankRecord;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+use std::sync::Arc;
+
+pub(crate) const MAGIC: [u8; 4] = *b"YPBZ";
+const HEADER_LEN: usize = 45;
+
+/// Compresses the payloads of blocks, see [`BlockWriter`].
+///
+/// Codecs other than the built-in [`Stored`] and [`Lz`] can be plugged in, e.g. one
+/// wrapping a zstd library, and are found by their id when reading with [`Codecs`].
+pub trait Codec: Send + Sync {
+    /// Identifies the codec in block headers. `0` and `1` are used by [`Stored`] and [`Lz`].
+    fn id(&self) -> u8;
+
+    /// Appends the compressed `data` to `out`.
+    fn compress(&self, data: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError>;
+
+    /// Appends the decompressed `data` to `out`. `size` is the uncompressed size from the
+    /// block header.
+    fn decompress(&self, data: &[u8], size: usize, out: &mut Vec<u8>) -> Result<(), ParseError>;
+}
+
+/// Keeps payloads uncompressed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stored;
+
+impl Codec for Stored {
+    fn id(&self) -> u8 {
+        0
+    }
+
+    fn compress(&self, data: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+        out.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn decompress(&self, data: &[u8], size: usize, out: &mut Vec<u8>) -> Result<(), ParseError> {
+        if data.len() != size {
+            return Err(corrupt("stored size differs from the header"));
+        }
+        out.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// A small LZ77 codec, replacing repeated byte sequences such as descriptions and user
+/// ids with references to their previous occurrence.
+///
+/// A payload is a series of sequences: a varint literal count, the literals, then, unless
+/// the payload ends, a varint match length and a varint distance back into the output.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Lz;
+
+impl Lz {
+    const MIN_MATCH: usize = 4;
+    const MAX_DISTANCE: usize = 1 << 16;
+    const HASH_BITS: u32 = 14;
+
+    fn hash(bytes: &[u8]) -> usize {
+        let key = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        (key.wrapping_mul(2654435761) >> (32 - Self::HASH_BITS)) as usize
+    }
+}
+
+impl Codec for Lz {
+    fn id(&self) -> u8 {
+        1
+    }
+
+    fn compress(&self, data: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+        let mut table = vec![usize::MAX; 1 << Self::HASH_BITS];
+        let mut literals = 0;
+        let mut pos = 0;
+        while pos + Self::MIN_MATCH <= data.len() {
+            let slot = Self::hash(&data[pos..]);
+            let candidate = std::mem::replace(&mut table[slot], pos);
+            if candidate == usize::MAX
+                || pos - candidate > Self::MAX_DISTANCE
+                || data[candidate..candidate + Self::MIN_MATCH] != data[pos..pos + Self::MIN_MATCH]
+            {
+                pos += 1;
+                continue;
+            }
+
+            let mut len = Self::MIN_MATCH;
+            while pos + len < data.len() && data[candidate + len] == data[pos + len] {
+                len += 1;
+            }
+            write_varint(out, (pos - literals) as u64);
+            out.extend_from_slice(&data[literals..pos]);
+            write_varint(out, len as u64);
+            write_varint(out, (pos - candidate) as u64);
+            pos += len;
+            literals = pos;
+        }
+
+        write_varint(out, (data.len() - literals) as u64);
+        out.extend_from_slice(&data[literals..]);
+        Ok(())
+    }
+
+    fn decompress(&self, data: &[u8], size: usize, out: &mut Vec<u8>) -> Result<(), ParseError> {
+        let start = out.len();
+        let mut pos = 0;
+        loop {
+            let count = read_varint(data, &mut pos)?;
+            let literals = data
+                .get(pos..pos.saturating_add(count))
+                .ok_or_else(|| corrupt("literals past the end of the payload"))?;
+            if count > size - (out.len() - start) {
+                return Err(corrupt("payload larger than the header says"));
+            }
+            out.extend_from_slice(literals);
+            pos += count;
+            if pos == data.len() {
+                break;
+            }
+
+            let len = read_varint(data, &mut pos)?;
+            let distance = read_varint(data, &mut pos)?;
+            if distance == 0 || distance > out.len() - start {
+                return Err(corrupt("match before the start of the payload"));
+            }
+            if len > size - (out.len() - start) {
+                return Err(corrupt("payload larger than the header says"));
+            }
+            // Matches may overlap their own output, so they are copied byte by byte.
+            for _ in 0..len {
+                out.push(out[out.len() - distance]);
+            }
+        }
+
+        if out.len() - start != size {
+            return Err(corrupt("payload smaller than the header says"));
+        }
+        Ok(())
+    }
+}
+
+fn corrupt(reason: &str) -> ParseError {
+    ParseError::InconsistentRecord(format!("corrupt block: {}", reason))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<usize, ParseError> {
+    let mut value = 0usize;
+    for shift in (0..usize::BITS).step_by(7) {
+        let byte = *data
+            .get(*pos)
+            .ok_or_else(|| corrupt("varint past the end of the payload"))?;
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize)
+            .checked_shl(shift)
+            .ok_or_else(|| corrupt("varint too long"))?;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+/// Codecs known when reading archives, found by the id in block headers.
+///
+/// The default set holds [`Stored`] and [`Lz`].
+#[derive(Clone)]
+pub struct Codecs {
+    codecs: Vec<Arc<dyn Codec>>,
+}
+
+impl Default for Codecs {
+    fn default() -> Self {
+        Self {
+            codecs: vec![Arc::new(Stored), Arc::new(Lz)],
+        }
+    }
+}
+
+impl Codecs {
+    /// Adds a codec, replacing a codec with the same id.
+    pub fn with_codec(mut self, codec: impl Codec + 'static) -> Self {
+        self.codecs.retain(|known| known.id() != codec.id());
+        self.codecs.push(Arc::new(codec));
+        self
+    }
+
+    fn get(&self, id: u8) -> Result<&dyn Codec, ParseError> {
+        self.codecs
+            .iter()
+            .find(|codec| codec.id() == id)
+            .map(|codec| codec.as_ref())
+            .ok_or_else(|| ParseError::UnsupportedOperation(format!("unknown block codec {}", id)))
+    }
+}
+
+/// Header of a block in an archive written by [`BlockWriter`].
+///
+/// The ranges of timestamps and amounts let readers skip blocks without matching
+/// records, see [`crate::QueryEngine`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub codec: u8,
+    pub records: u32,
+    pub compressed_len: u32,
+    pub uncompressed_len: u32,
+    pub min_ts: u64,
+    pub max_ts: u64,
+    pub min_amount: i64,
+    pub max_amount: i64,
+}
+
+impl BlockHeader {
+    fn write_to<W: Write>(&self, w: &mut W) -> Result<(), ParseError> {
+        w.write_all(&[self.codec])?;
+        w.write_all(&self.records.to_be_bytes())?;
+        w.write_all(&self.compressed_len.to_be_bytes())?;
+        w.write_all(&self.uncompressed_len.to_be_bytes())?;
+        w.write_all(&self.min_ts.to_be_bytes())?;
+        w.write_all(&self.max_ts.to_be_bytes())?;
+        w.write_all(&self.min_amount.to_be_bytes())?;
+        w.write_all(&self.max_amount.to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Reads a header, returning `None` at the end of the input.
+    fn read_from<R: BufRead>(r: &mut R) -> Result<Option<Self>, ParseError> {
+        if r.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        let mut header = [0; HEADER_LEN];
+        r.read_exact(&mut header)
+            .map_err(|_| ParseError::UnexpectedEOF)?;
+        let mut header = header.as_slice();
+        Ok(Some(Self {
+            codec: read_u8_from_bytes(&mut header)?,
+            records: read_u32_from_bytes(&mut header)?,
+            compressed_len: read_u32_from_bytes(&mut header)?,
+            uncompressed_len: read_u32_from_bytes(&mut header)?,
+            min_ts: read_u64_from_bytes(&mut header)?,
+            max_ts: read_u64_from_bytes(&mut header)?,
+            min_amount: read_i64_from_bytes(&mut header)?,
+            max_amount: read_i64_from_bytes(&mut header)?,
+        }))
+    }
+}
+
+/// Writes records to a block-compressed archive, compressing every `block_records`
+/// records into a block.
+///
+/// An archive starts with the magic `YPBZ`, followed by the blocks. Every block has a
+/// 45-byte header: the codec id (`u8`), the record count, the compressed and the
+/// uncompressed payload size (big-endian `u32`s), then the smallest and largest
+/// timestamp (`u64`s) and amount (`i64`s) of its records. The payload is the records
+/// in the binary format, compressed by the codec. Unlike a compressed file, an archive can be
+/// searched block by block: blocks that are not needed are skipped without being
+/// decompressed, see [`BlockReader::skip_block`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{BlockReader, BlockWriter, Codecs, Lz, fixtures};
+///
+/// let mut writer = BlockWriter::new(Vec::new(), Lz, 8).unwrap();
+/// for record in fixtures::records() {
+///     writer.write(&record).unwrap();
+/// }
+/// let archive = writer.finish().unwrap();
+///
+/// let reader = BlockReader::new(archive.as_slice(), Codecs::default()).unwrap();
+/// let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(records, fixtures::records());
+/// ```
+pub struct BlockWriter<W: Write> {
+    w: W,
+    codec: Box<dyn Codec>,
+    block_records: u32,
+    record_parser: YPBankBinRecordParser,
+    records: u32,
+    ts: (u64, u64),
+    amount: (i64, i64),
+    block: Vec<u8>,
+    compressed: Vec<u8>,
+}
+
+impl<W: Write> BlockWriter<W> {
+    /// Writes the archive magic. `block_records` of 0 is taken as 1.
+    pub fn new(
+        mut w: W,
+        codec: impl Codec + 'static,
+        block_records: u32,
+    ) -> Result<Self, ParseError> {
+        w.write_all(&MAGIC)?;
+        Ok(Self {
+            w,
+            codec: Box::new(codec),
+            block_records: block_records.max(1),
+            record_parser: YPBankBinRecordParser::default(),
+            records: 0,
+            ts: (u64::MAX, u64::MIN),
+            amount: (i64::MAX, i64::MIN),
+            block: Vec::new(),
+            compressed: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        self.record_parser.write_to(record, &mut self.block)?;
+        self.records += 1;
+        self.ts = (self.ts.0.min(record.ts), self.ts.1.max(record.ts));
+        self.amount = (
+            self.amount.0.min(record.amount),
+            self.amount.1.max(record.amount),
+        );
+        if self.records == self.block_records {
+            self.flush_block()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last, partial block and returns the writer.
+    pub fn finish(mut self) -> Result<W, ParseError> {
+        self.flush_block()?;
+        self.w.flush()?;
+        Ok(self.w)
+    }
+
+    fn flush_block(&mut self) -> Result<(), ParseError> {
+        if self.records == 0 {
+            return Ok(());
+        }
+
+        self.compressed.clear();
+        self.codec.compress(&self.block, &mut self.compressed)?;
+        let len = |len: usize| {
+            u32::try_from(len).map_err(|_| ParseError::Overflow("block size".to_string()))
+        };
+        BlockHeader {
+            codec: self.codec.id(),
+            records: self.records,
+            compressed_len: len(self.compressed.len())?,
+            uncompressed_len: len(self.block.len())?,
+            min_ts: self.ts.0,
+            max_ts: self.ts.1,
+            min_amount: self.amount.0,
+            max_amount: self.amount.1,
+        }
+        .write_to(&mut self.w)?;
+        self.w.write_all(&self.compressed)?;
+
+        self.block.clear();
+        self.records = 0;
+        self.ts = (u64::MAX, u64::MIN);
+        self.amount = (i64::MAX, i64::MIN);
+        Ok(())
+    }
+}
+
+/// Reads the records of a block-compressed archive, see [`BlockWriter`].
+///
+/// Iterating yields the records of every block. [`BlockReader::next_block`] reads a
+/// block header instead, after which the block is read with
+/// [`BlockReader::read_block`] or passed over with [`BlockReader::skip_block`].
+pub struct BlockReader<R: Read> {
+    r: BufReader<R>,
+    codecs: Codecs,
+    record_parser: YPBankBinRecordParser,
+    compressed: Vec<u8>,
+    records: std::vec::IntoIter<YPBankRecord>,
+    done: bool,
+}
+
+impl<R: Read> BlockReader<R> {
+    /// Fails with [`ParseError::InvalidMagic`] for anything but an archive.
+    pub fn new(r: R, codecs: Codecs) -> Result<Self, ParseError> {
+        let mut r = BufReader::new(r);
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ParseError::InvalidMagic(
+                String::from_utf8_lossy(&magic).to_string(),
+            ));
+        }
+
+        Ok(Self {
+            r,
+            codecs,
+            record_parser: YPBankBinRecordParser::default(),
+            compressed: Vec::new(),
+            records: Vec::new().into_iter(),
+            done: false,
+        })
+    }
+
+    /// Reads the header of the next block, returning `None` at the end of the archive.
+    pub fn next_block(&mut self) -> Result<Option<BlockHeader>, ParseError> {
+        BlockHeader::read_from(&mut self.r)
+    }
+
+    /// Reads the records of the block whose header was just returned by
+    /// [`BlockReader::next_block`].
+    pub fn read_block(&mut self, header: &BlockHeader) -> Result<Vec<YPBankRecord>, ParseError> {
+        let codec = self.codecs.get(header.codec)?;
+        self.compressed.resize(header.compressed_len as usize, 0);
+        self.r
+            .read_exact(&mut self.compressed)
+            .map_err(|_| ParseError::UnexpectedEOF)?;
+
+        let mut block = Vec::with_capacity(header.uncompressed_len as usize);
+        codec.decompress(
+            &self.compressed,
+            header.uncompressed_len as usize,
+            &mut block,
+        )?;
+
+        let mut block = block.as_slice();
+        let mut records = Vec::with_capacity(header.records as usize);
+        for _ in 0..header.records {
+            match self.record_parser.from_read(&mut block)? {
+                Some(record) => records.push(record),
+                None => return Err(corrupt("fewer records than the header says")),
+            }
+        }
+        if !block.is_empty() {
+            return Err(corrupt("more records than the header says"));
+        }
+        Ok(records)
+    }
+}
+
+impl<R: Read + Seek> BlockReader<R> {
+    /// Passes over the block whose header was just returned by
+    /// [`BlockReader::next_block`] without reading its payload.
+    pub fn skip_block(&mut self, header: &BlockHeader) -> Result<(), ParseError> {
+        self.r.seek_relative(header.compressed_len as i64)?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for BlockReader<R> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    /// At least the records left in the current block, whose header gives their number.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.records.len();
+        (left, self.done.then_some(left))
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(record) = self.records.next() {
+                return Some(Ok(record));
+            }
+            if self.done {
+                return None;
+            }
+
+            let records = self
+                .next_block()
+                .and_then(|header| header.map(|header| self.read_block(&header)).transpose());
+            match records {
+                Ok(Some(records)) => self.records = records.into_iter(),
+                Ok(None) => self.done = true,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod block_tests {
+    use super::*;
+    use crate::fixtures;
+    use std::io::Cursor;
+
+    fn archive(codec: impl Codec + 'static, block_records: u32) -> Vec<u8> {
+        let mut writer = BlockWriter::new(Vec::new(), codec, block_records).expect("Should start");
+        for record in fixtures::records() {
+            writer.write(&record).expect("Should write record");
+        }
+        writer.finish().expect("Should finish")
+    }
+
+    #[test]
+    fn test_lz_round_trip() {
+        let inputs: [&[u8]; 4] = [
+            b"",
+            b"abc",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            b"Record number 1, Record number 2, Record number 3",
+        ];
+        for input in inputs {
+            let mut compressed = Vec::new();
+            Lz.compress(input, &mut compressed)
+                .expect("Should compress");
+            let mut output = Vec::new();
+            Lz.decompress(&compressed, input.len(), &mut output)
+                .expect("Should decompress");
+            assert_eq!(output, input);
+        }
+
+        let mut compressed = Vec::new();
+        Lz.compress(&[7; 1000], &mut compressed)
+            .expect("Should compress");
+        assert!(compressed.len() < 10, "{:?}", compressed);
+    }
+
+    #[test]
+    fn test_lz_rejects_corrupt_payloads() {
+        let mut output = Vec::new();
+        // Three literals, then a match 5 bytes back.
+        assert!(
+            Lz.decompress(&[3, b'a', b'b', b'c', 4, 5], 7, &mut output)
+                .is_err()
+        );
+        assert!(Lz.decompress(&[3, b'a', b'b'], 3, &mut output).is_err());
+        assert!(
+            Lz.decompress(&[3, b'a', b'b', b'c'], 4, &mut output)
+                .is_err()
+        );
+        // A match and a literal run as long as the varint allows.
+        let mut huge = vec![0xFF; 9];
+        huge.push(0x01);
+        let mut long_match = vec![1, b'a'];
+        long_match.extend_from_slice(&huge);
+        long_match.push(1);
+        assert!(Lz.decompress(&long_match, 8, &mut output).is_err());
+        let mut long_literals = huge.clone();
+        long_literals.push(b'a');
+        assert!(Lz.decompress(&long_literals, 8, &mut output).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_in_blocks() {
+        let stored = archive(Stored, 8);
+        let compressed = archive(Lz, 8);
+        assert!(compressed.len() < stored.len());
+
+        for data in [stored, compressed] {
+            let mut reader =
+                BlockReader::new(data.as_slice(), Codecs::default()).expect("Should open");
+            let mut records = Vec::new();
+            let mut counts = Vec::new();
+            while let Some(header) = reader.next_block().expect("Should read header") {
+                counts.push(header.records);
+                if counts.len() == 1 {
+                    assert_eq!(
+                        (header.min_ts, header.max_ts),
+                        (1633046460000, 1633046460000 + 7 * 60_000)
+                    );
+                    assert_eq!((header.min_amount, header.max_amount), (100, 800));
+                }
+                records.extend(reader.read_block(&header).expect("Should read block"));
+            }
+            assert_eq!(counts, vec![8, 8, 4]);
+            assert_eq!(records, fixtures::records());
+        }
+    }
+
+    #[test]
+    fn test_size_hint_counts_block_records() {
+        let data = archive(Lz, 8);
+        let mut reader = BlockReader::new(data.as_slice(), Codecs::default()).expect("Should open");
+        assert_eq!(reader.size_hint(), (0, None));
+        reader.next();
+        assert_eq!(reader.size_hint(), (7, None));
+        assert_eq!(reader.by_ref().skip(15).count(), 4);
+        assert_eq!(reader.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_skip_block() {
+        let mut reader =
+            BlockReader::new(Cursor::new(archive(Lz, 8)), Codecs::default()).expect("Should open");
+        let header = reader.next_block().expect("Should read header").unwrap();
+        reader.skip_block(&header).expect("Should skip block");
+
+        let header = reader.next_block().expect("Should read header").unwrap();
+        let records = reader.read_block(&header).expect("Should read block");
+        assert_eq!(records, fixtures::records()[8..16]);
+    }
+
+    #[test]
+    fn test_plugged_codec() {
+        struct Reversed;
+
+        impl Codec for Reversed {
+            fn id(&self) -> u8 {
+                9
+            }
+
+            fn compress(&self, data: &[u8], out: &mut Vec<u8>) -> Result<(), ParseError> {
+                out.extend(data.iter().rev());
+                Ok(())
+            }
+
+            fn decompress(
+                &self,
+                data: &[u8],
+                _size: usize,
+                out: &mut Vec<u8>,
+            ) -> Result<(), ParseError> {
+                out.extend(data.iter().rev());
+                Ok(())
+            }
+        }
+
+        let data = archive(Reversed, 8);
+        let mut reader = BlockReader::new(data.as_slice(), Codecs::default()).expect("Should open");
+        assert_eq!(
+            reader.next(),
+            Some(Err(ParseError::UnsupportedOperation(
+                "unknown block codec 9".to_string()
+            )))
+        );
+        assert_eq!(reader.next(), None);
+
+        let reader = BlockReader::new(data.as_slice(), Codecs::default().with_codec(Reversed))
+            .expect("Should open");
+        assert_eq!(
+            reader.collect::<Result<Vec<_>, _>>(),
+            Ok(fixtures::records())
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::atomic;
+use crate::common::{Format, read_u32_from_bytes, read_u64_from_bytes};
+use crate::digest::Sha256;
+use crate::error::ParseError;
+use crate::query::QueryEngine;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: [u8; 4] = *b"YPBD";
+/// Magic of sidecars that recorded only the length of their file; they are ignored.
+const LEGACY_MAGIC: [u8; 4] = *b"YPBF";
+const SIDECAR_EXTENSION: &str = "bloom";
+const MAX_HASHES: u32 = 16;
+
+/// A bloom filter over transaction ids, answering whether a file may hold an id without
+/// reading it.
+///
+/// [`IdBloom::may_contain`] never returns `false` for an inserted id and returns `true` for
+/// other ids with about the false positive rate given to [`IdBloom::new`].
+///
+/// Filters of archives are kept in sidecar files next to them, see
+/// [`IdBloom::write_sidecar`]. [`QueryEngine`] uses them to skip files when looking for an id.
+///
+/// # Examples
+///
+/// ```
+/// use parser::IdBloom;
+///
+/// let mut bloom = IdBloom::new(1000, 0.01);
+/// bloom.insert(1000000000000007);
+/// assert!(bloom.may_contain(1000000000000007));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdBloom {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl IdBloom {
+    /// Creates an empty filter sized for `expected` ids with `false_positive_rate`.
+    pub fn new(expected: usize, false_positive_rate: f64) -> Self {
+        let expected = expected.max(1) as f64;
+        let rate = false_positive_rate.clamp(f64::MIN_POSITIVE, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-expected * rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = (bits / expected * ln2).round() as u32;
+        Self {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes: hashes.clamp(1, MAX_HASHES),
+        }
+    }
+
+    /// Builds a filter over the ids of the records in the file at `path`.
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        format: Format,
+        false_positive_rate: f64,
+    ) -> Result<Self, ParseError> {
+        let ids: Vec<u64> = QueryEngine::open(path, format)?
+            .collect()?
+            .iter()
+            .map(|record| record.id)
+            .collect();
+        let mut bloom = Self::new(ids.len(), false_positive_rate);
+        for id in ids {
+            bloom.insert(id);
+        }
+        Ok(bloom)
+    }
+
+    pub fn insert(&mut self, id: u64) {
+        for bit in self.positions(id) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `false` only when `id` was never inserted.
+    pub fn may_contain(&self, id: u64) -> bool {
+        self.positions(id)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Bit positions of `id`, derived from two hashes by double hashing.
+    fn positions(&self, id: u64) -> impl Iterator<Item = usize> + use<> {
+        let len = self.bits.len() as u64 * 64;
+        let first = mix(id);
+        let second = mix(first) | 1;
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    /// Returns the path of the sidecar of the file at `path`, `path` with `.bloom` appended.
+    pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+        let mut sidecar = path.as_ref().as_os_str().to_owned();
+        sidecar.push(".");
+        sidecar.push(SIDECAR_EXTENSION);
+        PathBuf::from(sidecar)
+    }
+
+    /// Writes the filter, built from the current content of the file at `path`, as its
+    /// sidecar.
+    ///
+    /// The sidecar records a SHA-256 digest of the file, so that it is ignored once the
+    /// file is appended to or replaced, even by a file of the same length.
+    pub fn write_sidecar(&self, path: impl AsRef<Path>) -> Result<(), ParseError> {
+        let path = path.as_ref();
+        let digest = source_digest(path)?;
+        atomic::write_atomic(&Self::sidecar_path(path), None, |w| {
+            self.write_to(w, &digest)
+        })
+    }
+
+    /// Loads the sidecar of the file at `path`, or returns `None` when there is none or it
+    /// was written for a different version of the file.
+    ///
+    /// The file is hashed to check that, which reads it but spares parsing it.
+    pub fn load_sidecar(path: impl AsRef<Path>) -> Result<Option<Self>, ParseError> {
+        let path = path.as_ref();
+        let sidecar = match std::fs::File::open(Self::sidecar_path(path)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let Some((bloom, digest)) = Self::read_from(&mut BufReader::new(sidecar))? else {
+            return Ok(None);
+        };
+        if digest != source_digest(path)? {
+            return Ok(None);
+        }
+        Ok(Some(bloom))
+    }
+
+    fn write_to<W: Write>(&self, w: &mut W, source_digest: &[u8; 32]) -> Result<(), ParseError> {
+        w.write_all(&MAGIC)?;
+        w.write_all(source_digest)?;
+        w.write_all(&self.hashes.to_be_bytes())?;
+        w.write_all(&(self.bits.len() as u64).to_be_bytes())?;
+        for word in &self.bits {
+            w.write_all(&word.to_be_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Reads a sidecar and the digest of its file, or `None` for a legacy sidecar.
+    fn read_from<R: BufRead>(r: &mut R) -> Result<Option<(Self, [u8; 32])>, ParseError> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if magic == LEGACY_MAGIC {
+            return Ok(None);
+        }
+        if magic != MAGIC {
+            return Err(ParseError::InvalidMagic(
+                String::from_utf8_lossy(&magic).to_string(),
+            ));
+        }
+
+        let mut digest = [0; 32];
+        r.read_exact(&mut digest)?;
+        let hashes = read_u32_from_bytes(r)?;
+        let words = read_u64_from_bytes(r)?;
+        if hashes == 0 || hashes > MAX_HASHES || words == 0 {
+            return Err(ParseError::InconsistentRecord(format!(
+                "bloom filter with {} hashes over {} words",
+                hashes, words
+            )));
+        }
+        let mut bits = Vec::new();
+        for _ in 0..words {
+            bits.push(read_u64_from_bytes(r)?);
+        }
+        if !r.fill_buf()?.is_empty() {
+            return Err(ParseError::InconsistentRecord(format!(
+                "data after {} bloom filter words",
+                words
+            )));
+        }
+        Ok(Some((Self { bits, hashes }, digest)))
+    }
+}
+
+fn source_digest(path: &Path) -> Result<[u8; 32], ParseError> {
+    let mut hasher = Sha256::new();
+    hasher.update_from_read(&mut std::fs::File::open(path)?)?;
+    Ok(hasher.finish())
+}
+
+/// SplitMix64 finalizer, spreading sequential ids over all bits.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod bloom_tests {
+    use super::*;
+    use crate::CommonParser;
+    use crate::common::{TransactionStatus, TransactionType};
+    use crate::record::YPBankRecord;
+
+    #[test]
+    fn test_no_false_negatives_and_false_positive_rate() {
+        let mut bloom = IdBloom::new(10_000, 0.01);
+        for id in 1000000000000000..1000000000010000 {
+            bloom.insert(id);
+        }
+
+        assert!((1000000000000000..1000000000010000).all(|id| bloom.may_contain(id)));
+        let false_positives = (2000000000000000..2000000000100000u64)
+            .filter(|&id| bloom.may_contain(id))
+            .count();
+        assert!(false_positives < 2000, "{}", false_positives);
+    }
+
+    #[test]
+    fn test_sidecar() {
+        let path = std::env::temp_dir().join(format!("bloom_{}.bin", std::process::id()));
+        let records: Vec<YPBankRecord> = (1..=50)
+            .map(|id| {
+                YPBankRecord::new(
+                    id * 3,
+                    TransactionType::Transfer,
+                    1,
+                    2,
+                    100,
+                    1633036860000,
+                    TransactionStatus::Success,
+                    "Payment",
+                )
+            })
+            .collect();
+        let parser = CommonParser::new(Format::Bin);
+        parser
+            .write_to(&mut std::fs::File::create(&path).unwrap(), &records)
+            .unwrap();
+
+        let original_len = std::fs::metadata(&path).unwrap().len();
+        let missing = IdBloom::load_sidecar(&path).unwrap();
+        let bloom = IdBloom::from_path(&path, Format::Bin, 0.01).unwrap();
+        bloom.write_sidecar(&path).unwrap();
+        let loaded = IdBloom::load_sidecar(&path).unwrap();
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        parser
+            .append_iter(&mut file, records[..1].iter().cloned().map(Ok))
+            .unwrap();
+        let stale = IdBloom::load_sidecar(&path).unwrap();
+
+        // A rewrite changing one id keeps the length of the file.
+        bloom.write_sidecar(&path).unwrap();
+        let mut edited = records.clone();
+        edited[0].id = 1;
+        parser.write_to_path_atomic(&path, &edited, None).unwrap();
+        let rewritten = IdBloom::load_sidecar(&path).unwrap();
+        let edited_len = std::fs::metadata(&path).unwrap().len();
+
+        std::fs::remove_file(IdBloom::sidecar_path(&path)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(missing, None);
+        assert_eq!(loaded.as_ref(), Some(&bloom));
+        assert!(records.iter().all(|record| bloom.may_contain(record.id)));
+        assert_eq!(stale, None);
+        assert_eq!(edited_len, original_len);
+        assert_eq!(rewritten, None);
+    }
+
+    #[test]
+    fn test_read_invalid_sidecar() {
+        let mut data = Vec::new();
+        IdBloom::new(10, 0.01)
+            .write_to(&mut data, &[0; 32])
+            .unwrap();
+
+        let mut trailing = data.clone();
+        trailing.push(0);
+        assert!(matches!(
+            IdBloom::read_from(&mut trailing.as_slice()),
+            Err(ParseError::InconsistentRecord(_))
+        ));
+        data[0] = b'X';
+        assert!(matches!(
+            IdBloom::read_from(&mut data.as_slice()),
+            Err(ParseError::InvalidMagic(_))
+        ));
+        data[..4].copy_from_slice(&LEGACY_MAGIC);
+        assert_eq!(IdBloom::read_from(&mut data.as_slice()), Ok(None));
+    }
+}
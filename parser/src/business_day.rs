@@ -0,0 +1,351 @@
+use crate::calendar::{SECONDS_PER_DAY, format_date, parse_date};
+use crate::error::ParseError;
+use crate::pipeline::Stage;
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use crate::toml_format::YPBankTomlRecordParser;
+#[cfg(feature = "tz")]
+use crate::tz::TimeZone;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::str::FromStr;
+
+const CALENDAR_TABLE: &str = "[calendar]";
+const LIST_SEP: char = ',';
+const WEEKDAYS: [&str; 7] = ["MON", "TUE", "WED", "THU", "FRI", "SAT", "SUN"];
+/// Extra holding the [`DayKind`] of a record tagged by [`BusinessCalendar::tag`].
+pub const DAY_KIND_EXTRA: &str = "DAY_KIND";
+/// Extra holding the business date of a record tagged by [`BusinessCalendar::tag`].
+pub const BUSINESS_DATE_EXTRA: &str = "BUSINESS_DATE";
+/// Bound on the days searched for the next business day, so that a calendar without
+/// any cannot loop forever.
+const MAX_CLOSED_DAYS: i64 = 366;
+
+/// Whether a day is a business day, or why not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DayKind {
+    Business,
+    Weekend,
+    Holiday,
+}
+
+impl DayKind {
+    pub const ALL: [DayKind; 3] = [DayKind::Business, DayKind::Weekend, DayKind::Holiday];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DayKind::Business => "business",
+            DayKind::Weekend => "weekend",
+            DayKind::Holiday => "holiday",
+        }
+    }
+}
+
+impl FromStr for DayKind {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "business" => Ok(DayKind::Business),
+            "weekend" => Ok(DayKind::Weekend),
+            "holiday" => Ok(DayKind::Holiday),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "day kind".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Weekend days and holidays deciding which days are business days.
+///
+/// Days are UTC days, or with the `tz` feature days of the calendar's time zone, so that
+/// records just after midnight local time fall on the right day. Calendars are usually
+/// loaded from TOML:
+///
+/// ```toml
+/// [calendar]
+/// weekend = "SAT,SUN"
+/// holidays = "2021-12-25,2022-01-01"
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use parser::{BusinessCalendar, DayKind};
+///
+/// let calendar = BusinessCalendar::new().with_holiday("2021-10-01").unwrap();
+/// // Friday 2021-10-01 00:01 UTC, a holiday, settles on Monday.
+/// assert_eq!(calendar.day_kind(1633046460000), DayKind::Holiday);
+/// assert_eq!(calendar.business_date(1633046460000), "2021-10-04");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusinessCalendar {
+    /// Weekend days by ISO weekday index, Monday first.
+    weekend: [bool; 7],
+    holidays: BTreeSet<i64>,
+    #[cfg(feature = "tz")]
+    time_zone: Option<TimeZone>,
+}
+
+impl Default for BusinessCalendar {
+    /// Saturdays and Sundays off, no holidays.
+    fn default() -> Self {
+        Self {
+            weekend: [false, false, false, false, false, true, true],
+            holidays: BTreeSet::new(),
+            #[cfg(feature = "tz")]
+            time_zone: None,
+        }
+    }
+}
+
+impl BusinessCalendar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the weekend days, given by name (`MON` to `SUN`), e.g. `["FRI", "SAT"]`.
+    pub fn with_weekend<'a>(
+        mut self,
+        days: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, ParseError> {
+        self.weekend = [false; 7];
+        for day in days {
+            let index = WEEKDAYS
+                .iter()
+                .position(|name| name.eq_ignore_ascii_case(day.trim()))
+                .ok_or_else(|| ParseError::InvalidRawValue {
+                    field: "weekend".to_string(),
+                    value: day.to_string(),
+                })?;
+            self.weekend[index] = true;
+        }
+        Ok(self)
+    }
+
+    /// Adds a holiday given as `YYYY-MM-DD`.
+    pub fn with_holiday(mut self, date: &str) -> Result<Self, ParseError> {
+        self.holidays.insert(parse_date("holiday", date)?);
+        Ok(self)
+    }
+
+    /// Decides days in `time_zone` instead of UTC.
+    #[cfg(feature = "tz")]
+    pub fn with_time_zone(mut self, time_zone: TimeZone) -> Self {
+        self.time_zone = Some(time_zone);
+        self
+    }
+
+    /// Reads a `[calendar]` table with comma-separated `weekend` days and `holidays`,
+    /// and with the `tz` feature a `time_zone`.
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut calendar = Self::new();
+        let mut in_table = false;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_table = line == CALENDAR_TABLE;
+                if !in_table {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=').filter(|_| in_table) else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let key = key.trim();
+            let value = YPBankTomlRecordParser::parse_raw_value(key, value.trim())?;
+            let list = || value.split(LIST_SEP).filter(|item| !item.trim().is_empty());
+            calendar = match key {
+                "weekend" => calendar.with_weekend(list())?,
+                "holidays" => {
+                    list().try_fold(calendar, |calendar, date| calendar.with_holiday(date))?
+                }
+                #[cfg(feature = "tz")]
+                "time_zone" => calendar.with_time_zone(TimeZone::load(&value)?),
+                _ => return Err(ParseError::InvalidRow(line.to_string())),
+            };
+        }
+
+        Ok(calendar)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns the day of a timestamp in milliseconds, as days since 1970-01-01.
+    fn day(&self, ts: u64) -> i64 {
+        let secs = (ts / 1000) as i64;
+        #[cfg(feature = "tz")]
+        let secs = match &self.time_zone {
+            Some(time_zone) => secs + time_zone.offset_at(secs) as i64,
+            None => secs,
+        };
+        secs.div_euclid(SECONDS_PER_DAY)
+    }
+
+    fn kind_of_day(&self, day: i64) -> DayKind {
+        // 1970-01-01 was a Thursday, index 3.
+        if self.holidays.contains(&day) {
+            DayKind::Holiday
+        } else if self.weekend[(day + 3).rem_euclid(7) as usize] {
+            DayKind::Weekend
+        } else {
+            DayKind::Business
+        }
+    }
+
+    /// Returns the kind of the day of a timestamp in milliseconds. Holidays falling on a
+    /// weekend are holidays.
+    pub fn day_kind(&self, ts: u64) -> DayKind {
+        self.kind_of_day(self.day(ts))
+    }
+
+    pub fn is_business_day(&self, ts: u64) -> bool {
+        self.day_kind(ts) == DayKind::Business
+    }
+
+    /// Returns the business day a timestamp counts for as `YYYY-MM-DD`: its own day, or
+    /// the next business day after a weekend or holiday.
+    ///
+    /// Calendars closed for more than a year return the day after that year.
+    pub fn business_date(&self, ts: u64) -> String {
+        let mut day = self.day(ts);
+        for _ in 0..MAX_CLOSED_DAYS {
+            if self.kind_of_day(day) == DayKind::Business {
+                break;
+            }
+            day += 1;
+        }
+        format_date(day)
+    }
+
+    /// Tags a record with its [`DAY_KIND_EXTRA`] and [`BUSINESS_DATE_EXTRA`] extras,
+    /// written with [`crate::CommonParser::write_ext_to`].
+    pub fn tag(&self, record: &mut YPBankRecordExt) {
+        let ts = record.core.ts;
+        record.extras.insert(
+            DAY_KIND_EXTRA.to_string(),
+            self.day_kind(ts).as_str().to_string(),
+        );
+        record
+            .extras
+            .insert(BUSINESS_DATE_EXTRA.to_string(), self.business_date(ts));
+    }
+}
+
+/// A [`Stage`] keeping records whose day is of one of the given kinds, e.g. only
+/// records booked on business days.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayKindFilter {
+    calendar: BusinessCalendar,
+    kinds: Vec<DayKind>,
+}
+
+impl DayKindFilter {
+    pub fn new(calendar: BusinessCalendar, kinds: impl IntoIterator<Item = DayKind>) -> Self {
+        Self {
+            calendar,
+            kinds: kinds.into_iter().collect(),
+        }
+    }
+
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        self.kinds.contains(&self.calendar.day_kind(record.ts))
+    }
+}
+
+impl Stage for DayKindFilter {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Ok(self.matches(&record).then_some(record))
+    }
+}
+
+#[cfg(test)]
+mod business_day_tests {
+    use super::*;
+    use crate::fixtures;
+
+    /// Midnight UTC of a date, in milliseconds.
+    fn ts(date: &str) -> u64 {
+        parse_date("holiday", date).expect("Should be a date") as u64
+            * SECONDS_PER_DAY as u64
+            * 1000
+    }
+
+    #[test]
+    fn test_day_kind_and_business_date() {
+        let calendar = BusinessCalendar::new()
+            .with_holiday("2021-12-24")
+            .and_then(|calendar| calendar.with_holiday("2021-12-27"))
+            .expect("Should parse holidays");
+
+        assert_eq!(calendar.day_kind(ts("2021-12-23")), DayKind::Business);
+        assert_eq!(calendar.day_kind(ts("2021-12-24")), DayKind::Holiday);
+        assert_eq!(
+            calendar.day_kind(ts("2021-12-25") + 86_399_999),
+            DayKind::Weekend
+        );
+        assert_eq!(calendar.business_date(ts("2021-12-23") + 1), "2021-12-23");
+        assert_eq!(calendar.business_date(ts("2021-12-24")), "2021-12-28");
+
+        let gulf = BusinessCalendar::new()
+            .with_weekend(["FRI", "sat"])
+            .expect("Should parse weekend");
+        assert!(gulf.is_business_day(ts("2021-12-26")));
+        assert_eq!(gulf.day_kind(ts("2021-12-24")), DayKind::Weekend);
+
+        let closed = BusinessCalendar::new()
+            .with_weekend(WEEKDAYS)
+            .expect("Should parse weekend");
+        assert_eq!(closed.business_date(0), "1971-01-02");
+    }
+
+    #[test]
+    fn test_from_toml() {
+        assert_eq!(
+            BusinessCalendar::from_toml(
+                "# Moscow exchange\n[calendar]\nweekend = \"SAT, SUN\"\nholidays = \"2022-01-03,2022-01-07\"\n"
+            ),
+            BusinessCalendar::new()
+                .with_holiday("2022-01-03")
+                .and_then(|calendar| calendar.with_holiday("2022-01-07"))
+        );
+        for invalid in [
+            "weekend = \"SAT\"",
+            "[calendar]\nweekend = \"SATURDAY\"",
+            "[calendar]\nholidays = \"2021-02-29\"",
+            "[calendar]\nholidays = \"2021-2-01\"",
+            "[calendar]\nopen = \"yes\"",
+        ] {
+            assert!(BusinessCalendar::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_tag_and_filter() {
+        // 2021-10-01 was a Friday; the fixtures are a minute apart from 00:01 UTC.
+        let calendar = BusinessCalendar::new()
+            .with_weekend(["FRI"])
+            .expect("Should parse weekend");
+        let mut record = YPBankRecordExt::from(fixtures::records().remove(0));
+        calendar.tag(&mut record);
+        assert_eq!(
+            record.extras.get(DAY_KIND_EXTRA).map(String::as_str),
+            Some("weekend")
+        );
+        assert_eq!(
+            record.extras.get(BUSINESS_DATE_EXTRA).map(String::as_str),
+            Some("2021-10-02")
+        );
+
+        let filter = DayKindFilter::new(calendar, [DayKind::Business, DayKind::Holiday]);
+        assert_eq!(filter.process(record.core), Ok(None));
+    }
+}
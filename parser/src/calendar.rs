@@ -0,0 +1,88 @@
+use crate::error::ParseError;
+
+pub(crate) const SECONDS_PER_DAY: i64 = 86_400;
+
+pub(crate) fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = month as i64;
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Renders a UTC timestamp in milliseconds as `YYYY-MM-DD`, or `YYYY-MM-DDTHH:MM` with `with_time`.
+pub(crate) fn format_utc(ts: u64, with_time: bool) -> String {
+    let secs = (ts / 1000) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECONDS_PER_DAY));
+    let time = secs.rem_euclid(SECONDS_PER_DAY);
+    if with_time {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60
+        )
+    } else {
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// Parses `YYYY-MM-DD` into days since 1970-01-01, naming `field` in errors.
+pub(crate) fn parse_date(field: &str, s: &str) -> Result<i64, ParseError> {
+    let invalid = || ParseError::InvalidRawValue {
+        field: field.to_string(),
+        value: s.to_string(),
+    };
+    let mut parts = s.trim().splitn(3, '-');
+    let mut next = || parts.next().ok_or_else(invalid);
+    let (year, month, day) = (next()?, next()?, next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return Err(invalid());
+    }
+    let year: i64 = year.parse().map_err(|_| invalid())?;
+    let month: u32 = month.parse().map_err(|_| invalid())?;
+    let day: u32 = day.parse().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day))
+}
+
+/// Renders days since 1970-01-01 as `YYYY-MM-DD`.
+pub(crate) fn format_date(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
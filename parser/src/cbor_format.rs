@@ -0,0 +1,277 @@
+use crate::error::ParseError;
+use crate::keyed_record::{
+    FIELDS, FrameReader, KeyedValue, from_keyed_values, read_frame, write_frame,
+};
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::YPBankRecord;
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_NEGATIVE_INT: u8 = 1;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_MAP: u8 = 5;
+
+/// Reads and writes records as length-prefixed CBOR maps.
+///
+/// Every record is a big-endian `u32` payload length followed by a definite-length
+/// CBOR map with the keys from [`FIELDS`]. Writers use the shortest argument encodings.
+#[derive(Default)]
+pub struct YPBankCborRecordParser {
+    buf: Vec<u8>,
+}
+
+impl YPBankCborRecordParser {
+    fn decode_head(r: &mut FrameReader) -> Result<(u8, u64), ParseError> {
+        let initial = r.read_u8()?;
+        let major = initial >> 5;
+        let argument = match initial & 0x1f {
+            info @ 0..=23 => info as u64,
+            24 => r.read_uint(1)?,
+            25 => r.read_uint(2)?,
+            26 => r.read_uint(4)?,
+            27 => r.read_uint(8)?,
+            _ => {
+                return Err(ParseError::InvalidRawValue {
+                    field: "cbor head".to_string(),
+                    value: format!("{:#04x}", initial),
+                });
+            }
+        };
+
+        Ok((major, argument))
+    }
+
+    fn decode_value(r: &mut FrameReader) -> Result<KeyedValue, ParseError> {
+        let value = match Self::decode_head(r)? {
+            (MAJOR_UINT, val) => KeyedValue::Int(val as i128),
+            (MAJOR_NEGATIVE_INT, val) => KeyedValue::Int(-1 - val as i128),
+            (MAJOR_TEXT, len) => KeyedValue::Str(r.read_string(len as usize)?),
+            (major, _) => {
+                return Err(ParseError::InvalidRawValue {
+                    field: "cbor major type".to_string(),
+                    value: major.to_string(),
+                });
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn encode_head(buf: &mut Vec<u8>, major: u8, argument: u64) {
+        let major = major << 5;
+        match argument {
+            0..=23 => buf.push(major | argument as u8),
+            24..=0xff => buf.extend_from_slice(&[major | 24, argument as u8]),
+            0x100..=0xffff => {
+                buf.push(major | 25);
+                buf.extend_from_slice(&(argument as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                buf.push(major | 26);
+                buf.extend_from_slice(&(argument as u32).to_be_bytes());
+            }
+            _ => {
+                buf.push(major | 27);
+                buf.extend_from_slice(&argument.to_be_bytes());
+            }
+        }
+    }
+
+    fn encode_int(buf: &mut Vec<u8>, val: i64) {
+        match u64::try_from(val) {
+            Ok(val) => Self::encode_head(buf, MAJOR_UINT, val),
+            Err(_) => Self::encode_head(buf, MAJOR_NEGATIVE_INT, !(val as u64)),
+        }
+    }
+
+    fn encode_str(buf: &mut Vec<u8>, val: &str) {
+        Self::encode_head(buf, MAJOR_TEXT, val.len() as u64);
+        buf.extend_from_slice(val.as_bytes());
+    }
+}
+
+impl FromOptions for YPBankCborRecordParser {}
+
+impl RecordReader for YPBankCborRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        if !read_frame(r, &mut self.buf)? {
+            return Ok(None);
+        }
+
+        let mut frame = FrameReader::new(&self.buf);
+        let len = match Self::decode_head(&mut frame)? {
+            (MAJOR_MAP, len) => len as usize,
+            (major, _) => {
+                return Err(ParseError::InvalidRawValue {
+                    field: "cbor major type".to_string(),
+                    value: major.to_string(),
+                });
+            }
+        };
+        let mut entries = Vec::with_capacity(len.min(FIELDS.len()));
+        for _ in 0..len {
+            let key = match Self::decode_value(&mut frame)? {
+                KeyedValue::Str(key) => key,
+                KeyedValue::Int(key) => return Err(ParseError::InvalidRow(key.to_string())),
+            };
+            entries.push((key, Self::decode_value(&mut frame)?));
+        }
+        frame.finish()?;
+
+        Ok(Some(from_keyed_values(entries)?))
+    }
+}
+
+impl RecordWriter for YPBankCborRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        let buf = &mut self.buf;
+        buf.clear();
+
+        Self::encode_head(buf, MAJOR_MAP, FIELDS.len() as u64);
+        Self::encode_str(buf, FIELDS[0]);
+        Self::encode_head(buf, MAJOR_UINT, record.id);
+        Self::encode_str(buf, FIELDS[1]);
+        Self::encode_str(buf, record.transaction_type.as_str());
+        Self::encode_str(buf, FIELDS[2]);
+        Self::encode_head(buf, MAJOR_UINT, record.from_user_id);
+        Self::encode_str(buf, FIELDS[3]);
+        Self::encode_head(buf, MAJOR_UINT, record.to_user_id);
+        Self::encode_str(buf, FIELDS[4]);
+        Self::encode_int(buf, record.amount);
+        Self::encode_str(buf, FIELDS[5]);
+        Self::encode_head(buf, MAJOR_UINT, record.ts);
+        Self::encode_str(buf, FIELDS[6]);
+        Self::encode_str(buf, record.status.as_str());
+        Self::encode_str(buf, FIELDS[7]);
+        Self::encode_str(buf, &record.description);
+
+        write_frame(w, buf)
+    }
+}
+
+pub struct CborParser {}
+
+impl Parser<YPBankCborRecordParser> for CborParser {}
+
+#[cfg(test)]
+mod yp_bank_cbor_record_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_encode_int_boundaries() {
+        let cases: [(i64, &[u8]); 5] = [
+            (10, &[0x0a]),
+            (-1, &[0x20]),
+            (-25, &[0x38, 0x18]),
+            (1000, &[0x19, 0x03, 0xe8]),
+            (
+                i64::MIN,
+                &[0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ];
+
+        for (val, expected) in cases {
+            let mut buf = vec![];
+            YPBankCborRecordParser::encode_int(&mut buf, val);
+            assert_eq!(buf, expected, "Encoding of {}", val);
+
+            let decoded = YPBankCborRecordParser::decode_value(&mut FrameReader::new(&buf))
+                .expect("Should decode");
+            assert_eq!(decoded, KeyedValue::Int(val as i128));
+        }
+    }
+
+    #[test]
+    fn test_write_to_regular_case() {
+        let record = YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            3,
+            TransactionStatus::Failure,
+            "d".to_string(),
+        );
+
+        let mut writer = Cursor::new(Vec::new());
+        YPBankCborRecordParser::default()
+            .write_to(&record, &mut writer)
+            .expect("Writing should succeed");
+        let written = writer.into_inner();
+
+        let payload_len = u32::from_be_bytes([written[0], written[1], written[2], written[3]]);
+        assert_eq!(payload_len as usize, written.len() - 4);
+        assert_eq!(
+            &written[4..12],
+            &[0xa8, 0x65, b't', b'x', b'_', b'i', b'd', 0x01]
+        );
+
+        let mut reader = Cursor::new(written);
+        let read_record = YPBankCborRecordParser::default()
+            .from_read(&mut reader)
+            .expect("Reading should succeed");
+        assert_eq!(read_record, Some(record));
+    }
+
+    #[test]
+    fn test_from_read_not_a_map() {
+        let mut data = 1u32.to_be_bytes().to_vec();
+        data.push(0x80);
+
+        let mut reader = Cursor::new(data);
+        let result = YPBankCborRecordParser::default().from_read(&mut reader);
+        assert!(matches!(result, Err(ParseError::InvalidRawValue { .. })));
+    }
+}
+
+#[cfg(test)]
+mod cbor_parser_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use crate::options::ParseOptions;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip_multiple_records() {
+        let records = vec![
+            YPBankRecord::new(
+                u64::MAX,
+                TransactionType::Withdrawal,
+                9223372036854775807,
+                0,
+                i64::MIN,
+                1633036860000,
+                TransactionStatus::Pending,
+                "x".repeat(300),
+            ),
+            YPBankRecord::new(
+                1000000000000001,
+                TransactionType::Transfer,
+                1,
+                2,
+                -200,
+                1633036920000,
+                TransactionStatus::Success,
+                "Описание".to_string(),
+            ),
+        ];
+
+        let mut writer = Cursor::new(Vec::new());
+        CborParser::write_to(&mut writer, &records, &ParseOptions::default())
+            .expect("Should write successfully");
+
+        let mut reader = Cursor::new(writer.into_inner());
+        let read_records = CborParser::from_read(&mut reader, &ParseOptions::default())
+            .expect("Should parse successfully");
+        assert_eq!(read_records, records);
+    }
+}
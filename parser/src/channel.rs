@@ -0,0 +1,116 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::fs::File;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::thread::JoinHandle;
+
+/// Parses the file at `path` on a new thread, sending its records through a channel
+/// holding at most `bound` records.
+///
+/// The reader blocks while the channel is full, so a slow consumer limits memory
+/// instead of the file size. Failing to open or parse the file is sent as the last
+/// item. The thread stops early when the receiver is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Format, spawn_reader};
+/// # parser::fixtures::enter().unwrap();
+///
+/// let (reader, records) = spawn_reader("records.bin", Format::Bin, 1024);
+/// for record in records {
+///     println!("{}", record.unwrap().id);
+/// }
+/// reader.join().unwrap();
+/// ```
+pub fn spawn_reader(
+    path: impl AsRef<Path>,
+    format: Format,
+    bound: usize,
+) -> (JoinHandle<()>, Receiver<Result<YPBankRecord, ParseError>>) {
+    let path = path.as_ref().to_path_buf();
+    let (sender, receiver) = sync_channel(bound);
+    let handle = std::thread::spawn(move || {
+        let result = File::open(&path)
+            .map_err(ParseError::from)
+            .and_then(|file| send_records(file, format, &sender));
+        if let Err(err) = result {
+            let _ = sender.send(Err(err));
+        }
+    });
+
+    (handle, receiver)
+}
+
+/// Sends records until the input ends or the receiver is gone; parse errors are returned.
+fn send_records<R: std::io::Read>(
+    r: R,
+    format: Format,
+    sender: &SyncSender<Result<YPBankRecord, ParseError>>,
+) -> Result<(), ParseError> {
+    for record in CommonParser::new(format).iter_read(r)? {
+        if sender.send(Ok(record?)).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod channel_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(id: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            format!("Record number {}", id),
+        )
+    }
+
+    #[test]
+    fn test_spawn_reader() {
+        let path = std::env::temp_dir().join(format!("channel_{}.bin", std::process::id()));
+        let records: Vec<_> = (1..=5).map(record).collect();
+        CommonParser::new(Format::Bin)
+            .write_to(
+                &mut File::create(&path).expect("Should create file"),
+                &records,
+            )
+            .expect("Should write records");
+
+        let (reader, receiver) = spawn_reader(&path, Format::Bin, 2);
+        let read = receiver
+            .iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Should read records");
+        reader.join().expect("Reader should finish");
+
+        let (reader, receiver) = spawn_reader(&path, Format::Bin, 1);
+        let first = receiver.recv().expect("Should receive");
+        drop(receiver);
+        reader.join().expect("Reader should stop");
+
+        std::fs::remove_file(&path).expect("Should remove file");
+        assert_eq!(read, records);
+        assert_eq!(first, Ok(record(1)));
+    }
+
+    #[test]
+    fn test_spawn_reader_missing_file() {
+        let (reader, receiver) = spawn_reader("/nonexistent/records.bin", Format::Bin, 1);
+        let items: Vec<_> = receiver.iter().collect();
+        reader.join().expect("Reader should finish");
+
+        assert!(matches!(items[..], [Err(ParseError::IOError(_))]));
+    }
+}
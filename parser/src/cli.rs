@@ -0,0 +1,837 @@
+//! Shell completions and man pages for the command-line tools, generated from their
+//! `clap` definitions.
+//!
+//! Every tool answers `<tool> completions <SHELL>`, `<tool> manpage` and
+//! `<tool> spec [json|markdown]` through [`generate`] before parsing its own arguments.
+//!
+//! The tools also share their exit statuses ([`Status`]) and the `--quiet` and
+//! `--verbose` flags ([`VerbosityArgs`]), which decide which of the messages written
+//! by [`error`], [`summary`] and [`warning`] reach stderr. Stdout only carries the
+//! output of a tool, such as converted records. Tools reading records check their
+//! timestamps with the `--ts-check` and `--allow-future-ts` flags ([`TimestampArgs`]),
+//! and tools rewriting data in place expand their inputs with [`targets`].
+
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::options::{TimestampCheck, TimestampWindow};
+use crate::spec::{self, SpecFormat};
+use crate::store::RecordStore;
+use crate::timestamp_log::TimestampLog;
+use clap::{Arg, Command};
+use std::fmt::Display;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Exit status of a tool.
+///
+/// Arguments rejected by `clap` itself, such as a missing `--input`, also exit with
+/// [`Status::Usage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+    Success = 0,
+    /// Reading, processing or writing records failed.
+    Failure = 1,
+    /// An argument has an invalid value, e.g. an unknown format.
+    Usage = 2,
+    /// The tool ran but did not find what it checks for: files differ in `comparer`,
+    /// the record is missing in `find`, records were lost in `repair`.
+    Mismatch = 3,
+}
+
+impl From<Status> for std::process::ExitCode {
+    fn from(status: Status) -> Self {
+        std::process::ExitCode::from(status as u8)
+    }
+}
+
+impl From<bool> for Status {
+    /// Maps whether a run succeeded to [`Status::Success`] or [`Status::Failure`].
+    fn from(succeeded: bool) -> Self {
+        match succeeded {
+            true => Status::Success,
+            false => Status::Failure,
+        }
+    }
+}
+
+/// Messages a tool writes to stderr.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Errors only.
+    Quiet = 0,
+    /// Errors and a summary of the run, e.g. the number of records written.
+    Normal = 1,
+    /// Errors, the summary and warnings about single records.
+    Verbose = 2,
+}
+
+/// The `--quiet` and `--verbose` flags, flattened into the arguments of every tool.
+#[derive(clap::Args, Debug, Clone, Copy, Default)]
+pub struct VerbosityArgs {
+    /// Only report errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also report warnings about single records
+    #[arg(short, long, global = true)]
+    verbose: bool,
+}
+
+impl VerbosityArgs {
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (_, true) => Verbosity::Verbose,
+            _ => Verbosity::Normal,
+        }
+    }
+}
+
+/// The `--ts-check` and `--allow-future-ts` flags of tools reading records.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TimestampArgs {
+    /// Handling of timestamps before 2000-01-01 or more than a day ahead, usually a
+    /// wrong unit: off, warn (report them) or reject (fail)
+    #[arg(long, value_name = "MODE", default_value = "warn")]
+    ts_check: String,
+
+    /// Accept timestamps in the future, e.g. of scheduled transfers
+    #[arg(long)]
+    allow_future_ts: bool,
+}
+
+impl TimestampArgs {
+    /// Returns the check of the flags, warning into a new log with `warn`.
+    pub fn timestamp_check(&self) -> Result<TimestampCheck, ParseError> {
+        let mut window = TimestampWindow::plausible();
+        if self.allow_future_ts {
+            window = window.allow_future();
+        }
+        match self.ts_check.to_lowercase().as_str() {
+            "off" => Ok(TimestampCheck::Off),
+            "warn" => Ok(TimestampCheck::Warn(window, TimestampLog::new())),
+            "reject" => Ok(TimestampCheck::Reject(window)),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "timestamp check".to_string(),
+                value: self.ts_check.clone(),
+            }),
+        }
+    }
+}
+
+/// Reports the implausible timestamps logged by `check`, one warning per record.
+pub fn report_timestamps(check: &TimestampCheck) {
+    let TimestampCheck::Warn(window, log) = check else {
+        return;
+    };
+    let entries = log.take();
+    for entry in &entries {
+        warning(format!(
+            "Record {} has an implausible timestamp {} outside {}..={}",
+            entry.id, entry.ts, window.from, window.to
+        ));
+    }
+    if !entries.is_empty() {
+        summary(format!(
+            "Read {} records with implausible timestamps; check the timestamp unit",
+            entries.len()
+        ));
+    }
+}
+
+/// Sets the verbosity of the process, [`Verbosity::Normal`] until called.
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Reports a failure; written at every verbosity.
+pub fn error(message: impl Display) {
+    eprintln!("{message}");
+}
+
+/// Reports the outcome of a run; hidden by `--quiet`.
+pub fn summary(message: impl Display) {
+    if verbosity() >= Verbosity::Normal {
+        eprintln!("{message}");
+    }
+}
+
+/// Reports a detail about a single record; shown with `--verbose` only.
+pub fn warning(message: impl Display) {
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("{message}");
+    }
+}
+
+/// A file or record store that tools rewriting data in place, such as `prune` and
+/// `erase-user`, work on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    File(PathBuf, Format),
+    Store(PathBuf),
+}
+
+impl Target {
+    pub fn path(&self) -> &Path {
+        match self {
+            Target::File(path, _) | Target::Store(path) => path,
+        }
+    }
+}
+
+/// Expands `inputs` to the files and record stores they name. Directories that are not
+/// record stores stand for the stores and the files with the extension of a readable
+/// format in them; the other entries are skipped with a warning. Files given directly
+/// are read in `format`, inferred from the file when `None`.
+pub fn targets(inputs: &[PathBuf], format: Option<Format>) -> Result<Vec<Target>, ParseError> {
+    let mut targets = vec![];
+    for input in inputs {
+        if RecordStore::exists(input) {
+            targets.push(Target::Store(input.clone()));
+        } else if input.is_dir() {
+            let mut paths = std::fs::read_dir(input)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            paths.sort();
+            for path in paths {
+                if RecordStore::exists(&path) {
+                    targets.push(Target::Store(path));
+                    continue;
+                }
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                match extension.and_then(Format::from_extension) {
+                    Some(format) if path.is_file() && format.is_readable() => {
+                        targets.push(Target::File(path, format))
+                    }
+                    _ => warning(format!("Skipping {}", path.display())),
+                }
+            }
+        } else {
+            let format = match format {
+                Some(format) => format,
+                None => Format::infer_from_path(input)?,
+            };
+            targets.push(Target::File(input.clone(), format));
+        }
+    }
+    Ok(targets)
+}
+
+/// Shells completion scripts are generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl std::str::FromStr for Shell {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "SHELL".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Handles the `completions <SHELL>` and `manpage` commands of a tool, writing the
+/// output for `cmd` to `w`, and the `spec [json|markdown]` command, writing the
+/// [`spec::binary`] layout of the binary format (JSON by default).
+///
+/// `args` are the arguments of the process, starting with the program path, which also
+/// names the tool. Returns `None` when the arguments are not one of these commands, so
+/// the tool should parse them itself.
+///
+/// # Examples
+///
+/// ```
+/// use clap::{Arg, Command};
+/// use parser::cli;
+///
+/// let cmd = Command::new("parser").arg(Arg::new("input").long("input"));
+/// let mut script = Vec::new();
+/// let args = ["./target/debug/find", "completions", "bash"].map(String::from);
+///
+/// cli::generate(cmd, args, &mut script).unwrap().unwrap();
+/// assert!(String::from_utf8(script).unwrap().contains("complete -F _find"));
+/// ```
+pub fn generate<W: Write>(
+    cmd: Command,
+    args: impl IntoIterator<Item = String>,
+    w: &mut W,
+) -> Option<Result<(), ParseError>> {
+    let args: Vec<String> = args.into_iter().collect();
+    let name = args
+        .first()
+        .and_then(|program| Path::new(program).file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| cmd.get_name().to_string());
+    let cmd = cmd.bin_name(name);
+
+    match args.get(1).map(String::as_str) {
+        Some("completions") => Some(match args.get(2) {
+            Some(shell) => shell.parse().and_then(|shell| completions(&cmd, shell, w)),
+            None => Err(ParseError::FieldNotFound("SHELL".to_string())),
+        }),
+        Some("manpage") => Some(manpage(&cmd, w)),
+        Some("spec") => Some(
+            args.get(2)
+                .map_or(Ok(SpecFormat::default()), |format| format.parse())
+                .and_then(|format| Ok(w.write_all(spec::binary().render(format).as_bytes())?)),
+        ),
+        _ => None,
+    }
+}
+
+/// Writes the completion script of `cmd` for `shell`.
+///
+/// Options of subcommands are offered wherever the subcommands are, except by fish,
+/// which completes them only after their subcommand.
+pub fn completions<W: Write>(cmd: &Command, shell: Shell, w: &mut W) -> Result<(), ParseError> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    match shell {
+        Shell::Bash => bash(&cmd, w),
+        Shell::Zsh => zsh(&cmd, w),
+        Shell::Fish => fish(&cmd, w),
+    }
+}
+
+/// Writes the man page of `cmd` in roff, for `man -l` or a `man1` directory.
+pub fn manpage<W: Write>(cmd: &Command, w: &mut W) -> Result<(), ParseError> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let name = tool_name(&cmd).to_string();
+
+    writeln!(
+        w,
+        ".TH {} 1 \"\" \"{} {}\"",
+        roff(&name.to_uppercase()),
+        roff(&name),
+        roff(cmd.get_version().unwrap_or_default())
+    )?;
+    writeln!(w, ".SH NAME")?;
+    match cmd.get_about() {
+        Some(about) => writeln!(w, "{} \\- {}", roff(&name), roff(&about.to_string()))?,
+        None => writeln!(w, "{}", roff(&name))?,
+    }
+
+    writeln!(w, ".SH SYNOPSIS")?;
+    let subcommands = if cmd.has_subcommands() {
+        " \\fICOMMAND\\fR"
+    } else {
+        ""
+    };
+    writeln!(
+        w,
+        "\\fB{}\\fR [\\fIOPTIONS\\fR]{}",
+        roff(&name),
+        subcommands
+    )?;
+
+    if let Some(about) = cmd.get_long_about().or(cmd.get_about()) {
+        writeln!(w, ".SH DESCRIPTION")?;
+        writeln!(w, "{}", roff(&about.to_string()))?;
+    }
+
+    writeln!(w, ".SH OPTIONS")?;
+    write_man_options(&cmd, w)?;
+
+    let subcommands: Vec<&Command> = visible_subcommands(&cmd).collect();
+    if !subcommands.is_empty() {
+        writeln!(w, ".SH COMMANDS")?;
+        for subcommand in subcommands {
+            writeln!(w, ".SS {}", roff(subcommand.get_name()))?;
+            if let Some(about) = subcommand.get_about() {
+                writeln!(w, "{}", roff(&about.to_string()))?;
+            }
+            write_man_options(subcommand, w)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_man_options<W: Write>(cmd: &Command, w: &mut W) -> Result<(), ParseError> {
+    for arg in options(cmd) {
+        let mut names = Vec::new();
+        if let Some(short) = arg.get_short() {
+            names.push(format!("\\fB\\-{}\\fR", roff(&short.to_string())));
+        }
+        if let Some(long) = arg.get_long() {
+            names.push(format!("\\fB\\-\\-{}\\fR", roff(long)));
+        }
+        let value = match takes_value(arg) {
+            true => format!(" \\fI{}\\fR", roff(&value_name(arg))),
+            false => String::new(),
+        };
+
+        writeln!(w, ".TP")?;
+        writeln!(w, "{}{}", names.join(", "), value)?;
+        let mut help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+        let values = possible_values(arg);
+        if !values.is_empty() {
+            help.push_str(&format!(" [possible values: {}]", values.join(", ")));
+        }
+        let defaults: Vec<_> = arg
+            .get_default_values()
+            .iter()
+            .map(|value| value.to_string_lossy())
+            .collect();
+        if !defaults.is_empty() {
+            help.push_str(&format!(" [default: {}]", defaults.join(",")));
+        }
+        if !help.trim().is_empty() {
+            writeln!(w, "{}", roff(help.trim()))?;
+        }
+    }
+    Ok(())
+}
+
+fn bash<W: Write>(cmd: &Command, w: &mut W) -> Result<(), ParseError> {
+    let name = tool_name(cmd);
+    let function = format!("_{}", name.replace('-', "_"));
+    let commands: Vec<&Command> = std::iter::once(cmd)
+        .chain(visible_subcommands(cmd))
+        .collect();
+
+    let mut words: Vec<String> = Vec::new();
+    let mut cases: Vec<(Vec<String>, Vec<String>)> = Vec::new();
+    for arg in commands.iter().flat_map(|cmd| options(cmd)) {
+        let flags = flags(arg);
+        if flags.iter().any(|flag| words.contains(flag)) {
+            continue;
+        }
+        if takes_value(arg) {
+            cases.push((flags.clone(), possible_values(arg)));
+        }
+        words.extend(flags);
+    }
+    words.extend(visible_subcommands(cmd).map(|cmd| cmd.get_name().to_string()));
+
+    writeln!(w, "{}() {{", function)?;
+    writeln!(w, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(w, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+    writeln!(w, "    case \"$prev\" in")?;
+    for (flags, values) in cases {
+        writeln!(w, "        {})", flags.join("|"))?;
+        match values.is_empty() {
+            true => writeln!(w, "            COMPREPLY=($(compgen -f -- \"$cur\"))")?,
+            false => writeln!(
+                w,
+                "            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+                values.join(" ")
+            )?,
+        }
+        writeln!(w, "            return 0")?;
+        writeln!(w, "            ;;")?;
+    }
+    writeln!(w, "    esac")?;
+    writeln!(
+        w,
+        "    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        words.join(" ")
+    )?;
+    writeln!(w, "}}")?;
+    writeln!(
+        w,
+        "complete -F {} -o bashdefault -o default {}",
+        function, name
+    )?;
+    Ok(())
+}
+
+fn zsh<W: Write>(cmd: &Command, w: &mut W) -> Result<(), ParseError> {
+    let name = tool_name(cmd);
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut specs: Vec<String> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+
+    for arg in std::iter::once(cmd)
+        .chain(visible_subcommands(cmd))
+        .flat_map(options)
+    {
+        let help = zsh_quote(&first_line(arg));
+        let action = match (takes_value(arg), possible_values(arg)) {
+            (false, _) => String::new(),
+            (true, values) if values.is_empty() => format!(":{}:_files", value_name(arg)),
+            (true, values) => format!(":{}:({})", value_name(arg), values.join(" ")),
+        };
+        let equals = if takes_value(arg) { "=" } else { "" };
+        for flag in flags(arg) {
+            if seen.contains(&flag) {
+                continue;
+            }
+            let equals = if flag.starts_with("--") { equals } else { "" };
+            specs.push(format!("'{}{}[{}]{}'", flag, equals, help, action));
+            seen.push(flag);
+        }
+    }
+
+    let subcommands: Vec<&str> = visible_subcommands(cmd).map(Command::get_name).collect();
+    if !subcommands.is_empty() {
+        specs.push(format!("'1:command:({})'", subcommands.join(" ")));
+    }
+
+    writeln!(w, "#compdef {}", name)?;
+    writeln!(w)?;
+    writeln!(w, "{}() {{", function)?;
+    writeln!(w, "    _arguments -s \\")?;
+    for spec in &specs {
+        writeln!(w, "        {} \\", spec)?;
+    }
+    writeln!(w, "        '*::file:_files'")?;
+    writeln!(w, "}}")?;
+    writeln!(w)?;
+    writeln!(w, "{} \"$@\"", function)?;
+    Ok(())
+}
+
+fn fish<W: Write>(cmd: &Command, w: &mut W) -> Result<(), ParseError> {
+    let name = tool_name(cmd);
+    write_fish_options(name, cmd, None, w)?;
+    for subcommand in visible_subcommands(cmd) {
+        let help = fish_quote(
+            &subcommand
+                .get_about()
+                .map(ToString::to_string)
+                .unwrap_or_default(),
+        );
+        writeln!(
+            w,
+            "complete -c {} -f -n '__fish_use_subcommand' -a {} -d '{}'",
+            name,
+            subcommand.get_name(),
+            help
+        )?;
+        write_fish_options(name, subcommand, Some(subcommand.get_name()), w)?;
+    }
+    Ok(())
+}
+
+fn write_fish_options<W: Write>(
+    name: &str,
+    cmd: &Command,
+    subcommand: Option<&str>,
+    w: &mut W,
+) -> Result<(), ParseError> {
+    let condition = match subcommand {
+        Some(subcommand) => format!(" -n '__fish_seen_subcommand_from {}'", subcommand),
+        None if cmd.has_subcommands() => " -n '__fish_use_subcommand'".to_string(),
+        None => String::new(),
+    };
+
+    for arg in options(cmd) {
+        let mut line = format!("complete -c {}{}", name, condition);
+        if let Some(long) = arg.get_long() {
+            line.push_str(&format!(" -l {}", long));
+        }
+        if let Some(short) = arg.get_short() {
+            line.push_str(&format!(" -s {}", short));
+        }
+        line.push_str(&format!(" -d '{}'", fish_quote(&first_line(arg))));
+        if takes_value(arg) {
+            let values = possible_values(arg);
+            match values.is_empty() {
+                true => line.push_str(" -r -F"),
+                false => line.push_str(&format!(" -r -f -a '{}'", values.join(" "))),
+            }
+        }
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Name the tool is invoked as, which `clap` derives from the package otherwise.
+fn tool_name(cmd: &Command) -> &str {
+    cmd.get_bin_name().unwrap_or(cmd.get_name())
+}
+
+/// Named arguments of `cmd`, without positional and hidden ones.
+fn options(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+fn visible_subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let short = arg.get_short().map(|short| format!("-{}", short));
+    let long = arg.get_long().map(|long| format!("--{}", long));
+    short.into_iter().chain(long).collect()
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some([name, ..]) => name.to_string(),
+        _ => arg.get_id().to_string().to_uppercase(),
+    }
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
+
+fn first_line(arg: &Arg) -> String {
+    let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+    help.lines().next().unwrap_or_default().to_string()
+}
+
+/// Escapes `text` for roff, including lines that would start with a control character.
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\e")
+        .replace('-', "\\-")
+        .lines()
+        .map(|line| match line.starts_with(['.', '\'']) {
+            true => format!("\\&{}", line),
+            false => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Escapes `text` for the description of a zsh `_arguments` spec in single quotes.
+fn zsh_quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn fish_quote(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+    use clap::ArgAction;
+
+    fn command() -> Command {
+        Command::new("parser")
+            .version("0.1.0")
+            .about("Replays records")
+            .arg(
+                Arg::new("input")
+                    .long("input")
+                    .short('i')
+                    .help("Input file"),
+            )
+            .arg(
+                Arg::new("format")
+                    .long("format")
+                    .value_parser(["csv", "binary"])
+                    .default_value("csv")
+                    .help("Format of the input"),
+            )
+            .arg(
+                Arg::new("dry_run")
+                    .long("dry-run")
+                    .action(ArgAction::SetTrue)
+                    .help("Don't write [anything]"),
+            )
+            .subcommand(
+                Command::new("apply")
+                    .about("Applies a delta")
+                    .arg(Arg::new("delta").long("delta")),
+            )
+    }
+
+    fn generated(args: &[&str]) -> Option<Result<String, ParseError>> {
+        let mut output = Vec::new();
+        let args = args.iter().map(|arg| arg.to_string());
+        generate(command(), args, &mut output).map(|result| {
+            result.map(|()| String::from_utf8(output).expect("Output should be UTF-8"))
+        })
+    }
+
+    #[test]
+    fn test_completions() {
+        let bash = generated(&["/usr/bin/replay", "completions", "bash"])
+            .expect("Should be handled")
+            .expect("Should generate");
+        assert!(
+            bash.contains("        --format)\n            COMPREPLY=($(compgen -W \"csv binary\"")
+        );
+        assert!(bash.contains("        -i|--input)\n            COMPREPLY=($(compgen -f"));
+        assert!(
+            bash.contains("\"-i --input --format --dry-run -h --help -V --version --delta apply\"")
+        );
+        assert!(bash.ends_with("complete -F _replay -o bashdefault -o default replay\n"));
+
+        let zsh = generated(&["replay", "completions", "zsh"])
+            .expect("Should be handled")
+            .expect("Should generate");
+        assert!(zsh.starts_with("#compdef replay\n"));
+        assert!(zsh.contains("'--format=[Format of the input]:FORMAT:(csv binary)'"));
+        assert!(zsh.contains("'--dry-run[Don'\\''t write \\[anything\\]]'"));
+        assert!(zsh.contains("'1:command:(apply)'"));
+
+        let fish = generated(&["replay", "completions", "FISH"])
+            .expect("Should be handled")
+            .expect("Should generate");
+        assert!(fish.contains(
+            "complete -c replay -n '__fish_use_subcommand' -l input -s i -d 'Input file' -r -F\n"
+        ));
+        assert!(fish.contains(
+            "complete -c replay -n '__fish_seen_subcommand_from apply' -l delta -d '' -r -F\n"
+        ));
+    }
+
+    #[test]
+    fn test_manpage() {
+        let page = generated(&["replay", "manpage"])
+            .expect("Should be handled")
+            .expect("Should generate");
+
+        assert!(page.starts_with(
+            ".TH REPLAY 1 \"\" \"replay 0.1.0\"\n.SH NAME\nreplay \\- Replays records\n"
+        ));
+        assert!(
+            page.contains(".TP\n\\fB\\-i\\fR, \\fB\\-\\-input\\fR \\fIINPUT\\fR\nInput file\n")
+        );
+        assert!(
+            page.contains("Format of the input [possible values: csv, binary] [default: csv]\n")
+        );
+        assert!(page.contains(".SH COMMANDS\n.SS apply\nApplies a delta\n"));
+    }
+
+    #[derive(clap::Parser)]
+    struct Args {
+        #[command(flatten)]
+        verbosity: VerbosityArgs,
+
+        #[command(flatten)]
+        timestamps: TimestampArgs,
+    }
+
+    #[test]
+    fn test_verbosity_args() {
+        use clap::Parser;
+
+        let verbosity =
+            |args: &[&str]| Args::try_parse_from(args).map(|args| args.verbosity.verbosity());
+        assert_eq!(verbosity(&["tool"]).ok(), Some(Verbosity::Normal));
+        assert_eq!(verbosity(&["tool", "-q"]).ok(), Some(Verbosity::Quiet));
+        assert_eq!(
+            verbosity(&["tool", "--verbose"]).ok(),
+            Some(Verbosity::Verbose)
+        );
+        assert!(verbosity(&["tool", "--quiet", "--verbose"]).is_err());
+        assert_eq!(Status::from(false), Status::Failure);
+    }
+
+    #[test]
+    fn test_timestamp_args() {
+        use clap::Parser;
+
+        let check = |args: &[&str]| {
+            Args::try_parse_from(args)
+                .map_err(|err| ParseError::InvalidFormat(err.to_string()))
+                .and_then(|args| args.timestamps.timestamp_check())
+        };
+        let Ok(TimestampCheck::Warn(window, _)) = check(&["tool"]) else {
+            panic!("Should warn by default");
+        };
+        assert_eq!(window.from, TimestampWindow::Y2000);
+        assert!(window.to < u64::MAX);
+        assert_eq!(
+            check(&["tool", "--ts-check", "off"]),
+            Ok(TimestampCheck::Off)
+        );
+        assert_eq!(
+            check(&["tool", "--ts-check", "REJECT", "--allow-future-ts"]),
+            Ok(TimestampCheck::Reject(window.allow_future()))
+        );
+        assert!(check(&["tool", "--ts-check", "fail"]).is_err());
+    }
+
+    #[test]
+    fn test_spec() {
+        assert_eq!(
+            generated(&["replay", "spec"]),
+            Some(Ok(spec::binary().to_json()))
+        );
+        let markdown = generated(&["replay", "spec", "markdown"])
+            .expect("Should be handled")
+            .expect("Should render");
+        assert!(markdown.starts_with("# YPBank Binary Format\n"));
+        assert!(matches!(
+            generated(&["replay", "spec", "xml"]),
+            Some(Err(ParseError::InvalidRawValue { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_other_arguments_are_not_handled() {
+        assert!(generated(&["replay", "--input", "records.csv"]).is_none());
+        assert!(generated(&["replay"]).is_none());
+        assert!(matches!(
+            generated(&["replay", "completions", "powershell"]),
+            Some(Err(ParseError::InvalidRawValue { .. }))
+        ));
+        assert_eq!(
+            generated(&["replay", "completions"]),
+            Some(Err(ParseError::FieldNotFound("SHELL".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        let dir = std::env::temp_dir().join(format!("cli_targets_{}", std::process::id()));
+        let store = dir.join("store");
+        RecordStore::create(&store).expect("Should create store");
+        for name in ["2021-10.bin", "2021-10.bin.bloom", "2021-11.csv"] {
+            std::fs::write(dir.join(name), "").expect("Should write file");
+        }
+
+        let found = targets(std::slice::from_ref(&dir), None);
+        let explicit = targets(&[dir.join("2021-10.bin.bloom")], Some(Format::Bin));
+        std::fs::remove_dir_all(&dir).expect("Should remove dir");
+
+        assert_eq!(
+            found,
+            Ok(vec![
+                Target::File(dir.join("2021-10.bin"), Format::Bin),
+                Target::File(dir.join("2021-11.csv"), Format::Csv),
+                Target::Store(store),
+            ])
+        );
+        assert_eq!(
+            explicit,
+            Ok(vec![Target::File(
+                dir.join("2021-10.bin.bloom"),
+                Format::Bin
+            )])
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use crate::CommonParser;
+use crate::atomic;
+use crate::common::{Format, TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const HEADER: &str = "YPBank column stats 1";
+const SIDECAR_EXTENSION: &str = "stats";
+
+/// Per-field statistics of a file: record counts, the smallest and largest value of every
+/// numeric field, and how many values are zero or empty.
+///
+/// Kept in a small sidecar next to the file (`<file>.stats`), so that tools report them
+/// without reading the file, see [`ColumnStats::write_sidecar`] and
+/// [`crate::ParseOptions::stats_sidecar`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ColumnStats, Format, TransactionType};
+/// # parser::fixtures::enter().unwrap();
+///
+/// let stats = ColumnStats::from_path("records.bin", Format::Bin).unwrap();
+/// stats.write_sidecar("records.bin").unwrap();
+///
+/// let loaded = ColumnStats::load_sidecar("records.bin").unwrap().unwrap();
+/// assert_eq!(loaded.records, 20);
+/// assert_eq!(loaded.type_count(TransactionType::Deposit), 7);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub records: u64,
+    pub amount_sum: i128,
+    pub ids: Option<(u64, u64)>,
+    pub from_user_ids: Option<(u64, u64)>,
+    pub to_user_ids: Option<(u64, u64)>,
+    pub amounts: Option<(i64, i64)>,
+    pub timestamps: Option<(u64, u64)>,
+    /// Lengths of descriptions in bytes.
+    pub description_lens: Option<(u64, u64)>,
+    /// Records from user 0, the bank side of deposits.
+    pub zero_from_user_ids: u64,
+    /// Records to user 0, the bank side of withdrawals.
+    pub zero_to_user_ids: u64,
+    pub zero_amounts: u64,
+    /// Descriptions that are empty or only spaces and quotes.
+    pub empty_descriptions: u64,
+    types: [u64; 3],
+    statuses: [u64; 3],
+}
+
+/// Widens `range` to hold `value`.
+fn widen<T: Copy + Ord>(range: &mut Option<(T, T)>, value: T) {
+    *range = Some(match *range {
+        Some((min, max)) => (min.min(value), max.max(value)),
+        None => (value, value),
+    });
+}
+
+impl ColumnStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, record: &YPBankRecord) {
+        self.records += 1;
+        self.amount_sum += record.amount as i128;
+        widen(&mut self.ids, record.id);
+        widen(&mut self.from_user_ids, record.from_user_id);
+        widen(&mut self.to_user_ids, record.to_user_id);
+        widen(&mut self.amounts, record.amount);
+        widen(&mut self.timestamps, record.ts);
+        widen(&mut self.description_lens, record.description.len() as u64);
+        self.zero_from_user_ids += (record.from_user_id == 0) as u64;
+        self.zero_to_user_ids += (record.to_user_id == 0) as u64;
+        self.zero_amounts += (record.amount == 0) as u64;
+        self.empty_descriptions += record.description.trim_matches(['"', ' ']).is_empty() as u64;
+        self.types[record.transaction_type.as_int() as usize] += 1;
+        self.statuses[record.status.as_int() as usize] += 1;
+    }
+
+    /// Computes the statistics of the records in the file at `path`.
+    pub fn from_path(path: impl AsRef<Path>, format: Format) -> Result<Self, ParseError> {
+        let mut stats = Self::new();
+        for record in CommonParser::new(format).iter_read(File::open(path)?)? {
+            stats.add(&record?);
+        }
+        Ok(stats)
+    }
+
+    pub fn type_count(&self, transaction_type: TransactionType) -> u64 {
+        self.types[transaction_type.as_int() as usize]
+    }
+
+    pub fn status_count(&self, status: TransactionStatus) -> u64 {
+        self.statuses[status.as_int() as usize]
+    }
+
+    /// Returns the path of the sidecar of the file at `path`, `path` with `.stats` appended.
+    pub fn sidecar_path(path: impl AsRef<Path>) -> PathBuf {
+        let mut sidecar = path.as_ref().as_os_str().to_owned();
+        sidecar.push(".");
+        sidecar.push(SIDECAR_EXTENSION);
+        PathBuf::from(sidecar)
+    }
+
+    /// Writes the statistics as the sidecar of the file at `path`.
+    ///
+    /// The sidecar records the length and modification time of the file, so that it is
+    /// ignored once the file is changed by anything that does not update it.
+    pub fn write_sidecar(&self, path: impl AsRef<Path>) -> Result<(), ParseError> {
+        let path = path.as_ref();
+        let version = file_version(path)?;
+        atomic::write_atomic(&Self::sidecar_path(path), None, |w| {
+            self.write_to(w, version)
+        })
+    }
+
+    /// Loads the sidecar of the file at `path`, or returns `None` when there is none or it
+    /// was written for a different version of the file.
+    pub fn load_sidecar(path: impl AsRef<Path>) -> Result<Option<Self>, ParseError> {
+        let path = path.as_ref();
+        let text = match std::fs::read_to_string(Self::sidecar_path(path)) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let (stats, version) = Self::parse(&text)?;
+        if version != file_version(path)? {
+            return Ok(None);
+        }
+        Ok(Some(stats))
+    }
+
+    fn write_to<W: Write>(
+        &self,
+        w: &mut W,
+        (len, modified): (u64, u128),
+    ) -> Result<(), ParseError> {
+        writeln!(w, "{}", HEADER)?;
+        writeln!(w, "source_len {}", len)?;
+        writeln!(w, "source_modified {}", modified)?;
+        writeln!(w, "records {}", self.records)?;
+        writeln!(w, "amount_sum {}", self.amount_sum)?;
+        write_range(w, "tx_id", self.ids)?;
+        write_range(w, "from_user_id", self.from_user_ids)?;
+        write_range(w, "to_user_id", self.to_user_ids)?;
+        write_range(w, "amount", self.amounts)?;
+        write_range(w, "timestamp", self.timestamps)?;
+        write_range(w, "description_len", self.description_lens)?;
+        writeln!(w, "zero_from_user_ids {}", self.zero_from_user_ids)?;
+        writeln!(w, "zero_to_user_ids {}", self.zero_to_user_ids)?;
+        writeln!(w, "zero_amounts {}", self.zero_amounts)?;
+        writeln!(w, "empty_descriptions {}", self.empty_descriptions)?;
+        let [deposits, transfers, withdrawals] = self.types;
+        writeln!(w, "types {} {} {}", deposits, transfers, withdrawals)?;
+        let [successes, failures, pendings] = self.statuses;
+        writeln!(w, "statuses {} {} {}", successes, failures, pendings)?;
+        Ok(())
+    }
+
+    fn parse(text: &str) -> Result<(Self, (u64, u128)), ParseError> {
+        let mut lines = text.lines();
+        let header = lines.next().unwrap_or_default();
+        if header != HEADER {
+            return Err(ParseError::InvalidMagic(header.to_string()));
+        }
+        let mut values: HashMap<&str, Vec<i128>> = HashMap::new();
+        for line in lines {
+            let mut words = line.split(' ');
+            let name = words.next().unwrap_or_default();
+            let numbers = words
+                .map(|word| word.parse().map_err(|_| invalid(name)))
+                .collect::<Result<_, _>>()?;
+            values.insert(name, numbers);
+        }
+
+        let numbers = |name: &str, len: usize| match values.get(name) {
+            Some(numbers) if numbers.len() == len => Ok(Some(numbers.as_slice())),
+            Some(_) => Err(invalid(name)),
+            None => Ok(None),
+        };
+        let value = |name: &str| match numbers(name, 1)? {
+            Some(numbers) => Ok(numbers[0]),
+            None => Err(invalid(name)),
+        };
+        let range = |name: &str| numbers(name, 2).map(|range| range.map(|r| (r[0], r[1])));
+        let counts = |name: &str| match numbers(name, 3)? {
+            Some(counts) => Ok([counts[0], counts[1], counts[2]]),
+            None => Err(invalid(name)),
+        };
+
+        let stats = Self {
+            records: cast(value("records")?, "records")?,
+            amount_sum: value("amount_sum")?,
+            ids: cast_range(range("tx_id")?, "tx_id")?,
+            from_user_ids: cast_range(range("from_user_id")?, "from_user_id")?,
+            to_user_ids: cast_range(range("to_user_id")?, "to_user_id")?,
+            amounts: cast_range(range("amount")?, "amount")?,
+            timestamps: cast_range(range("timestamp")?, "timestamp")?,
+            description_lens: cast_range(range("description_len")?, "description_len")?,
+            zero_from_user_ids: cast(value("zero_from_user_ids")?, "zero_from_user_ids")?,
+            zero_to_user_ids: cast(value("zero_to_user_ids")?, "zero_to_user_ids")?,
+            zero_amounts: cast(value("zero_amounts")?, "zero_amounts")?,
+            empty_descriptions: cast(value("empty_descriptions")?, "empty_descriptions")?,
+            types: cast_counts(counts("types")?, "types")?,
+            statuses: cast_counts(counts("statuses")?, "statuses")?,
+        };
+        let version = (
+            cast(value("source_len")?, "source_len")?,
+            cast(value("source_modified")?, "source_modified")?,
+        );
+        Ok((stats, version))
+    }
+}
+
+/// Writes a `<name> <min> <max>` line, or nothing without values.
+fn write_range<W: Write, T: std::fmt::Display>(
+    w: &mut W,
+    name: &str,
+    range: Option<(T, T)>,
+) -> std::io::Result<()> {
+    match range {
+        Some((min, max)) => writeln!(w, "{} {} {}", name, min, max),
+        None => Ok(()),
+    }
+}
+
+fn invalid(name: &str) -> ParseError {
+    ParseError::InconsistentRecord(format!("invalid {} in stats sidecar", name))
+}
+
+fn cast<T: TryFrom<i128>>(value: i128, name: &str) -> Result<T, ParseError> {
+    T::try_from(value).map_err(|_| invalid(name))
+}
+
+fn cast_range<T: TryFrom<i128>>(
+    range: Option<(i128, i128)>,
+    name: &str,
+) -> Result<Option<(T, T)>, ParseError> {
+    range
+        .map(|(min, max)| Ok((cast(min, name)?, cast(max, name)?)))
+        .transpose()
+}
+
+fn cast_counts(counts: [i128; 3], name: &str) -> Result<[u64; 3], ParseError> {
+    Ok([
+        cast(counts[0], name)?,
+        cast(counts[1], name)?,
+        cast(counts[2], name)?,
+    ])
+}
+
+/// Length and modification time in nanoseconds of the file at `path`.
+fn file_version(path: &Path) -> Result<(u64, u128), ParseError> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    Ok((metadata.len(), modified))
+}
+
+#[cfg(test)]
+mod column_stats_tests {
+    use super::*;
+
+    fn record(id: u64, to: u64, amount: i64, description: &str) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            to,
+            amount,
+            id * 10,
+            TransactionStatus::Pending,
+            description,
+        )
+    }
+
+    #[test]
+    fn test_sidecar_round_trip_and_staleness() {
+        let path = std::env::temp_dir().join(format!("column_stats_{}.csv", std::process::id()));
+        let records = vec![
+            record(3, 7, -50, "Refund"),
+            record(1, 9, 0, " "),
+            record(2, 8, 120, "Salary"),
+        ];
+        CommonParser::new(Format::Csv)
+            .write_to_path_atomic(&path, &records, None)
+            .expect("Should write records");
+
+        let stats = ColumnStats::from_path(&path, Format::Csv).expect("Should read stats");
+        let written = stats.write_sidecar(&path);
+        let loaded = ColumnStats::load_sidecar(&path);
+        std::fs::write(&path, "TX_ID\n").expect("Should change file");
+        let stale = ColumnStats::load_sidecar(&path);
+        std::fs::remove_file(&path).expect("Should remove file");
+        std::fs::remove_file(ColumnStats::sidecar_path(&path)).expect("Should remove sidecar");
+
+        assert_eq!(written, Ok(()));
+        assert_eq!(loaded, Ok(Some(stats.clone())));
+        assert_eq!(stale, Ok(None));
+        assert_eq!(
+            (stats.records, stats.amount_sum, stats.ids, stats.amounts),
+            (3, 70, Some((1, 3)), Some((-50, 120)))
+        );
+        assert_eq!(
+            (
+                stats.zero_from_user_ids,
+                stats.zero_to_user_ids,
+                stats.zero_amounts,
+                stats.empty_descriptions
+            ),
+            (3, 0, 1, 1)
+        );
+        assert_eq!(stats.description_lens, Some((0, 6)));
+        assert_eq!(stats.status_count(TransactionStatus::Pending), 3);
+    }
+
+    #[test]
+    fn test_parse_empty_stats() {
+        let mut text = vec![];
+        ColumnStats::new()
+            .write_to(&mut text, (0, 0))
+            .expect("Should write stats");
+        let text = String::from_utf8(text).expect("Should be UTF-8");
+
+        assert_eq!(ColumnStats::parse(&text), Ok((ColumnStats::new(), (0, 0))));
+        assert!(ColumnStats::parse(&text.replace("records 0", "records x")).is_err());
+    }
+
+    #[test]
+    fn test_sidecar_written_with_file() {
+        let path = std::env::temp_dir().join(format!("column_stats_{}.bin", std::process::id()));
+        let options = crate::ParseOptions {
+            stats_sidecar: true,
+            ..crate::ParseOptions::default()
+        };
+        let records = [record(1, 7, 10, "Salary"), record(2, 8, 20, "")];
+        CommonParser::with_options(Format::Bin, options)
+            .write_iter_to_path_atomic(&path, records.map(Ok), None)
+            .expect("Should write records");
+
+        let loaded = ColumnStats::load_sidecar(&path);
+        let scanned = ColumnStats::from_path(&path, Format::Bin);
+        std::fs::remove_file(&path).expect("Should remove file");
+        std::fs::remove_file(ColumnStats::sidecar_path(&path)).expect("Should remove sidecar");
+
+        let loaded = loaded
+            .expect("Should load sidecar")
+            .expect("Should be fresh");
+        assert_eq!(Ok(loaded.clone()), scanned);
+        assert_eq!((loaded.records, loaded.empty_descriptions), (2, 1));
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A comment line of a TXT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// Number of the line, from 1 at the start of the read.
+    pub line: u64,
+    /// The line without its `#` and surrounding whitespace.
+    pub text: String,
+}
+
+/// Comments collected while reading TXT files, see [`crate::TxtComments::Collect`].
+///
+/// Cloning the log is cheap and the clones share the same comments, so the log given to
+/// a parser can be read once the records are.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CommentLog, CommonParser, Format, ParseOptions, TxtComments};
+///
+/// let comments = CommentLog::new();
+/// let options = ParseOptions {
+///     txt_comments: TxtComments::Collect(comments.clone()),
+///     ..ParseOptions::default()
+/// };
+/// let data = "# Checked by audit\nTX_ID: 1\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\n\
+///             TO_USER_ID: 2\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: SUCCESS\n\
+///             DESCRIPTION: \"Salary\"\n";
+/// CommonParser::with_options(Format::Txt, options)
+///     .from_read(&mut data.as_bytes())
+///     .unwrap();
+///
+/// let comments = comments.take();
+/// assert_eq!((comments[0].line, comments[0].text.as_str()), (1, "Checked by audit"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CommentLog {
+    comments: Arc<Mutex<Vec<Comment>>>,
+}
+
+impl CommentLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn push(&self, comment: Comment) {
+        self.comments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(comment);
+    }
+
+    /// Removes and returns the comments collected so far, in the order they were read.
+    pub fn take(&self) -> Vec<Comment> {
+        std::mem::take(&mut *self.comments.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn len(&self) -> usize {
+        self.comments
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Logs are equal when they share the same comments.
+impl PartialEq for CommentLog {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.comments, &other.comments)
+    }
+}
+
+impl Eq for CommentLog {}
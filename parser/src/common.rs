@@ -1,18 +1,38 @@
+use crate::constant::{AMOUNT, FROM_USER_ID, TIMESTAMP};
 use crate::constant::{DEPOSIT, TRANSFER, WITHDRAWAL};
 use crate::constant::{FAILURE, PENDING, SUCCESS};
 use crate::error::ParseError;
 use crate::error::ParseError::{InvalidStatusValue, InvalidTransactionTypeValue};
-use std::io::BufRead;
+use crate::locale::AmountFormat;
+use crate::options::{NumericPolicy, ParseOptions};
+#[cfg(feature = "tz")]
+use crate::tz::TimeZone;
+use std::io::{BufRead, Read};
+use std::path::Path;
+
+/// Number of bytes read by [`Format::infer_from_path`] to recognize a format by content.
+const SNIFF_LEN: u64 = 4096;
 
 /// Supported file formats for bank transaction records.
 ///
-/// This enum represents the three formats that can be used to store and read
-/// bank transaction records: CSV, TXT (text), and binary.
+/// This enum represents the formats that can be used to store and read
+/// bank transaction records: CSV, TXT (text), binary, and TOML, plus the write-only
+/// HTML report and PostgreSQL `COPY` data. MessagePack and CBOR are available behind the `msgpack` and `cbor`
+/// features, and the write-only XLSX format behind the `xlsx` feature.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Format {
     Csv,
     Txt,
     Bin,
+    Toml,
+    Html,
+    PgCopy,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+    #[cfg(feature = "cbor")]
+    Cbor,
+    #[cfg(feature = "xlsx")]
+    Xlsx,
 }
 
 impl Format {
@@ -23,6 +43,12 @@ impl Format {
     /// * `"csv"` for CSV format
     /// * `"txt"` for TXT format
     /// * `"binary"` for binary format
+    /// * `"toml"` for TOML format
+    /// * `"html"` for HTML report format
+    /// * `"pgcopy"` for PostgreSQL `COPY` text format
+    /// * `"msgpack"` for MessagePack format (`msgpack` feature)
+    /// * `"cbor"` for CBOR format (`cbor` feature)
+    /// * `"xlsx"` for XLSX format (`xlsx` feature)
     ///
     /// # Examples
     ///
@@ -37,6 +63,114 @@ impl Format {
             Format::Csv => "csv",
             Format::Txt => "txt",
             Format::Bin => "binary",
+            Format::Toml => "toml",
+            Format::Html => "html",
+            Format::PgCopy => "pgcopy",
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => "msgpack",
+            #[cfg(feature = "cbor")]
+            Format::Cbor => "cbor",
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => "xlsx",
+        }
+    }
+
+    /// Infers the format of the file at `path` from its extension, or from its first
+    /// bytes when the extension is missing or not one of [`Format::from_extension`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::Format;
+    /// use std::path::Path;
+    ///
+    /// let format = Format::infer_from_path(Path::new("records.YPB")).unwrap();
+    /// assert_eq!(format, Format::Bin);
+    /// ```
+    pub fn infer_from_path(path: &Path) -> Result<Format, ParseError> {
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if let Some(format) = extension.and_then(Self::from_extension) {
+            return Ok(format);
+        }
+
+        let mut head = Vec::new();
+        std::fs::File::open(path)?
+            .take(SNIFF_LEN)
+            .read_to_end(&mut head)?;
+        Self::sniff(&head).ok_or_else(|| {
+            ParseError::InvalidFormat(format!("cannot infer the format of {}", path.display()))
+        })
+    }
+
+    /// Maps a file extension to its format, ignoring case: `csv`, `txt`, `bin` or `ypb`,
+    /// `toml`, `html` or `htm`, and with their features `msgpack` or `mpk`, `cbor` and `xlsx`.
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        match extension.to_lowercase().as_str() {
+            "csv" => Some(Format::Csv),
+            "txt" => Some(Format::Txt),
+            "bin" | "ypb" => Some(Format::Bin),
+            "toml" => Some(Format::Toml),
+            "html" | "htm" => Some(Format::Html),
+            #[cfg(feature = "msgpack")]
+            "msgpack" | "mpk" => Some(Format::MsgPack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Some(Format::Cbor),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Some(Format::Xlsx),
+            _ => None,
+        }
+    }
+
+    /// Recognizes a format by the start of a file: the `YPBN` magic, the CSV header, a
+    /// TOML `[[transaction]]` table, an HTML document or a TXT `KEY: value` line.
+    ///
+    /// Blank lines and `#` comments before the first significant line are skipped.
+    pub fn sniff(head: &[u8]) -> Option<Format> {
+        if head.starts_with(b"YPBN") {
+            return Some(Format::Bin);
+        }
+
+        let text = String::from_utf8_lossy(head.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(head));
+        let line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))?;
+        let lowercase = line.to_lowercase();
+        if line.starts_with("TX_ID,") {
+            Some(Format::Csv)
+        } else if line == "[[transaction]]" {
+            Some(Format::Toml)
+        } else if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+            Some(Format::Html)
+        } else {
+            let key = line.split_once(':')?.0.trim();
+            let is_key = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+            is_key.then_some(Format::Txt)
+        }
+    }
+
+    /// Returns whether records can be read back. HTML and XLSX reports and PostgreSQL
+    /// `COPY` data are write-only.
+    pub fn is_readable(&self) -> bool {
+        match self {
+            Format::Html | Format::PgCopy => false,
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => false,
+            _ => true,
+        }
+    }
+
+    /// Returns whether records are written one at a time, so that output can be
+    /// continued with [`crate::CommonParser::append_iter`]. Reports are written as a whole.
+    pub fn is_streaming(&self) -> bool {
+        match self {
+            Format::Html => false,
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => false,
+            _ => true,
         }
     }
 }
@@ -49,6 +183,15 @@ impl std::str::FromStr for Format {
             "csv" => Ok(Format::Csv),
             "txt" => Ok(Format::Txt),
             "binary" => Ok(Format::Bin),
+            "toml" => Ok(Format::Toml),
+            "html" => Ok(Format::Html),
+            "pgcopy" => Ok(Format::PgCopy),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Ok(Format::MsgPack),
+            #[cfg(feature = "cbor")]
+            "cbor" => Ok(Format::Cbor),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Ok(Format::Xlsx),
             _ => Err(ParseError::InvalidFormat(s.to_string())),
         }
     }
@@ -57,7 +200,7 @@ impl std::str::FromStr for Format {
 /// Type of bank transaction.
 ///
 /// Represents the three possible transaction types in the banking system.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TransactionType {
     Deposit,
     Transfer,
@@ -107,7 +250,7 @@ impl std::str::FromStr for TransactionType {
 /// Status of a bank transaction.
 ///
 /// Represents the three possible states a transaction can be in.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TransactionStatus {
     Success,
     Failure,
@@ -154,42 +297,128 @@ impl std::str::FromStr for TransactionStatus {
     }
 }
 
-pub fn parse_value_from_string<T: std::str::FromStr>(s: String) -> Result<T, ParseError> {
+/// Parses a value of `field`, failing with [`ParseError::ValueOutOfRange`] for integers
+/// that don't fit `T` and with [`ParseError::InvalidRawValue`] otherwise.
+pub fn parse_value_from_str<T: std::str::FromStr>(field: &str, s: &str) -> Result<T, ParseError> {
     match s.parse::<T>() {
         Ok(v) => Ok(v),
-        Err(_) => Err(ParseError::InvalidRawValue(s)),
+        Err(_) if is_integer(s) => Err(ParseError::ValueOutOfRange {
+            field: field.to_string(),
+            value: s.to_string(),
+        }),
+        Err(_) => Err(ParseError::InvalidRawValue {
+            field: field.to_string(),
+            value: s.to_string(),
+        }),
     }
 }
 
-pub fn parse_from_user_id(s: String, transaction_type: TransactionType) -> Result<u64, ParseError> {
-    let val = s
-        .parse::<u64>()
-        .map_err(|_| ParseError::InvalidRawValue(s))?;
+/// Returns whether `s` is a well-formed integer, so that failing to parse it can only
+/// mean it is out of range.
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix(['+', '-']).unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit())
+}
 
-    validate_from_user_id(val, transaction_type)
+/// Parses the text values of record fields according to [`ParseOptions`].
+///
+/// Numbers follow [`ParseOptions::numeric_policy`]; numbers outside the range of their
+/// field fail with [`ParseError::ValueOutOfRange`]. Amounts may be localized with
+/// [`ParseOptions::amount_format`], and with the `tz` feature and
+/// [`ParseOptions::timestamp_tz`] set, timestamps may be local datetimes.
+#[derive(Debug, Default, Clone)]
+pub struct ValueParser {
+    policy: NumericPolicy,
+    amount_format: Option<AmountFormat>,
+    #[cfg(feature = "tz")]
+    tz: Option<TimeZone>,
 }
 
-pub fn parse_to_user_id(s: String, transaction_type: TransactionType) -> Result<u64, ParseError> {
-    let val = s
-        .parse::<u64>()
-        .map_err(|_| ParseError::InvalidRawValue(s))?;
+impl ValueParser {
+    pub fn new(options: &ParseOptions) -> Self {
+        Self {
+            policy: options.numeric_policy,
+            amount_format: options.amount_format,
+            #[cfg(feature = "tz")]
+            tz: options.timestamp_tz.clone(),
+        }
+    }
+
+    /// Parses an integer value of `field`.
+    pub fn number<T: std::str::FromStr>(&self, field: &str, s: &str) -> Result<T, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: field.to_string(),
+            value: s.to_string(),
+        };
+        let value = if self.policy.trim_whitespace {
+            s.trim()
+        } else {
+            s
+        };
+
+        let digits = match value.strip_prefix('+') {
+            Some(_) if !self.policy.allow_plus_sign => return Err(invalid()),
+            Some(digits) => digits,
+            None => value.strip_prefix('-').unwrap_or(value),
+        };
+        if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        if !self.policy.allow_leading_zeros && digits.len() > 1 && digits.starts_with('0') {
+            return Err(invalid());
+        }
+
+        // The value is a well-formed integer, so it can only fail by not fitting `T`.
+        value.parse().map_err(|_| ParseError::ValueOutOfRange {
+            field: field.to_string(),
+            value: s.to_string(),
+        })
+    }
+
+    pub fn user_id(
+        &self,
+        field: &str,
+        s: &str,
+        transaction_type: TransactionType,
+    ) -> Result<u64, ParseError> {
+        let val = self.number(field, s)?;
+        match field {
+            FROM_USER_ID => validate_from_user_id(val, transaction_type),
+            _ => validate_to_user_id(val, transaction_type),
+        }
+    }
 
-    validate_to_user_id(val, transaction_type)
+    pub fn amount(&self, s: &str) -> Result<i64, ParseError> {
+        match &self.amount_format {
+            Some(format) => format.parse(s),
+            None => self.number(AMOUNT, s),
+        }
+    }
+
+    pub fn timestamp(&self, s: &str) -> Result<u64, ParseError> {
+        #[cfg(feature = "tz")]
+        if let (Err(_), Some(tz)) = (s.parse::<u64>(), &self.tz) {
+            return tz.parse_local(s);
+        }
+        self.number(TIMESTAMP, s)
+    }
 }
 
+/// Deposits come from user 0, the bank, and every other transaction from a user.
 pub fn validate_from_user_id(
     val: u64,
     transaction_type: TransactionType,
 ) -> Result<u64, ParseError> {
-    if val == 0 && transaction_type != TransactionType::Deposit {
+    if (val == 0) != (transaction_type == TransactionType::Deposit) {
         return Err(ParseError::InvalidUserId(val.to_string(), transaction_type));
     }
 
     Ok(val)
 }
 
+/// Withdrawals go to user 0, the bank, and every other transaction to a user.
 pub fn validate_to_user_id(val: u64, transaction_type: TransactionType) -> Result<u64, ParseError> {
-    if val == 0 && transaction_type != TransactionType::Withdrawal {
+    if (val == 0) != (transaction_type == TransactionType::Withdrawal) {
         return Err(ParseError::InvalidUserId(val.to_string(), transaction_type));
     }
 
@@ -210,3 +439,50 @@ impl_read_from_bytes!(read_u64_from_bytes, u64, 8);
 impl_read_from_bytes!(read_u8_from_bytes, u8, 1);
 impl_read_from_bytes!(read_i64_from_bytes, i64, 8);
 impl_read_from_bytes!(read_u32_from_bytes, u32, 4);
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(Format::from_extension("CSV"), Some(Format::Csv));
+        assert_eq!(Format::from_extension("ypb"), Some(Format::Bin));
+        assert_eq!(Format::from_extension("htm"), Some(Format::Html));
+        assert_eq!(Format::from_extension("json"), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(Format::sniff(b"YPBN\x00\x00"), Some(Format::Bin));
+        assert_eq!(
+            Format::sniff(b"\xEF\xBB\xBFTX_ID,TX_TYPE,FROM_USER_ID\n"),
+            Some(Format::Csv)
+        );
+        assert_eq!(
+            Format::sniff(b"# exported\n\n[[transaction]]\ntx_id = 1\n"),
+            Some(Format::Toml)
+        );
+        assert_eq!(
+            Format::sniff(b"# comment\nTX_ID: 1\nTX_TYPE: DEPOSIT\n"),
+            Some(Format::Txt)
+        );
+        assert_eq!(Format::sniff(b"<!DOCTYPE html>\n"), Some(Format::Html));
+        assert_eq!(Format::sniff(b"{\"tx_id\": 1}"), None);
+        assert_eq!(Format::sniff(b""), None);
+    }
+
+    #[test]
+    fn test_infer_from_path_sniffs_unknown_extensions() {
+        let path = std::env::temp_dir().join(format!("infer_{}.dat", std::process::id()));
+        std::fs::write(&path, "TX_ID: 1\n").expect("Should write file");
+
+        let format = Format::infer_from_path(&path);
+        std::fs::remove_file(&path).expect("Should remove file");
+        assert_eq!(format, Ok(Format::Txt));
+        assert!(matches!(
+            Format::infer_from_path(Path::new("/nonexistent/records.json")),
+            Err(ParseError::IOError(_))
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::common::{TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A fixed-size record holding its description as an index into a [`CompactRecords`] table.
+///
+/// The layout is `#[repr(C)]` and takes 48 bytes, against 64 bytes for [`YPBankRecord`]
+/// plus its description allocation.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactRecord {
+    pub id: u64,
+    pub from_user_id: u64,
+    pub to_user_id: u64,
+    pub amount: i64,
+    pub ts: u64,
+    description: u32,
+    transaction_type: u8,
+    status: u8,
+}
+
+impl CompactRecord {
+    pub fn transaction_type(&self) -> TransactionType {
+        TransactionType::from_int(self.transaction_type).expect("stored from a valid type")
+    }
+
+    pub fn status(&self) -> TransactionStatus {
+        TransactionStatus::from_int(self.status).expect("stored from a valid status")
+    }
+}
+
+/// A compact collection of records sharing one table of distinct descriptions.
+///
+/// Meant for keeping very large numbers of records in memory for analytics. Records
+/// converted back to [`YPBankRecord`] share the description allocations of the table.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CompactRecords, TransactionStatus, TransactionType, YPBankRecord};
+///
+/// let record = YPBankRecord::new(1, TransactionType::Deposit, 0, 2, 100, 3, TransactionStatus::Success, "ATM");
+/// let mut records = CompactRecords::new();
+/// records.push(&record).unwrap();
+///
+/// assert_eq!(records.get(0), Some(record));
+/// ```
+#[derive(Debug, Default)]
+pub struct CompactRecords {
+    records: Vec<CompactRecord>,
+    descriptions: Vec<Arc<str>>,
+    description_index: HashMap<Arc<str>, u32>,
+}
+
+impl CompactRecords {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            records: Vec::with_capacity(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Appends a record, adding its description to the table if it is new.
+    pub fn push(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        let description = match self.description_index.get(&record.description) {
+            Some(index) => *index,
+            None => {
+                let index = u32::try_from(self.descriptions.len()).map_err(|_| {
+                    ParseError::UnsupportedOperation(
+                        "more than u32::MAX distinct descriptions".to_string(),
+                    )
+                })?;
+                self.descriptions.push(record.description.clone());
+                self.description_index
+                    .insert(record.description.clone(), index);
+                index
+            }
+        };
+
+        self.records.push(CompactRecord {
+            id: record.id,
+            from_user_id: record.from_user_id,
+            to_user_id: record.to_user_id,
+            amount: record.amount,
+            ts: record.ts,
+            description,
+            transaction_type: record.transaction_type.as_int(),
+            status: record.status.as_int(),
+        });
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the number of distinct descriptions.
+    pub fn distinct_descriptions(&self) -> usize {
+        self.descriptions.len()
+    }
+
+    pub fn records(&self) -> &[CompactRecord] {
+        &self.records
+    }
+
+    pub fn description(&self, record: &CompactRecord) -> &Arc<str> {
+        &self.descriptions[record.description as usize]
+    }
+
+    /// Converts the record at `index` back into a [`YPBankRecord`].
+    pub fn get(&self, index: usize) -> Option<YPBankRecord> {
+        self.records.get(index).map(|record| self.to_record(record))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = YPBankRecord> + '_ {
+        self.records.iter().map(|record| self.to_record(record))
+    }
+
+    fn to_record(&self, record: &CompactRecord) -> YPBankRecord {
+        YPBankRecord::new(
+            record.id,
+            record.transaction_type(),
+            record.from_user_id,
+            record.to_user_id,
+            record.amount,
+            record.ts,
+            record.status(),
+            self.description(record).clone(),
+        )
+    }
+}
+
+impl TryFrom<&[YPBankRecord]> for CompactRecords {
+    type Error = ParseError;
+
+    fn try_from(records: &[YPBankRecord]) -> Result<Self, Self::Error> {
+        let mut compact = Self::with_capacity(records.len());
+        for record in records {
+            compact.push(record)?;
+        }
+        Ok(compact)
+    }
+}
+
+impl From<&CompactRecords> for Vec<YPBankRecord> {
+    fn from(records: &CompactRecords) -> Self {
+        records.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod compact_records_tests {
+    use super::*;
+
+    fn record(id: u64, description: &str) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Withdrawal,
+            id,
+            0,
+            -(id as i64),
+            1633036860000 + id,
+            TransactionStatus::Pending,
+            description,
+        )
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(std::mem::size_of::<CompactRecord>(), 48);
+        assert!(std::mem::size_of::<CompactRecord>() < std::mem::size_of::<YPBankRecord>());
+    }
+
+    #[test]
+    fn test_round_trip_shares_descriptions() {
+        let records = vec![
+            record(1, "ATM WITHDRAWAL"),
+            record(2, "CARD PAYMENT"),
+            record(3, "ATM WITHDRAWAL"),
+        ];
+
+        let compact = CompactRecords::try_from(records.as_slice()).expect("Should convert");
+        assert_eq!(compact.len(), 3);
+        assert_eq!(compact.distinct_descriptions(), 2);
+
+        let restored = Vec::<YPBankRecord>::from(&compact);
+        assert_eq!(restored, records);
+        assert!(Arc::ptr_eq(
+            &restored[0].description,
+            &restored[2].description
+        ));
+    }
+}
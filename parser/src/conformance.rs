@@ -0,0 +1,462 @@
+//! Checks that an implementation of a format reads and writes the golden files of
+//! [`crate::testdata`] exactly, that it survives inputs that once crashed it, and that
+//! random records survive a round trip through every format alike.
+
+use crate::CommonParser;
+use crate::common::{Format, TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::testdata;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+/// An implementation of one record format under test.
+pub trait Codec {
+    fn format(&self) -> Format;
+
+    fn read(&self, data: &[u8]) -> Result<Vec<YPBankRecord>, ParseError>;
+
+    fn write(&self, records: &[YPBankRecord]) -> Result<Vec<u8>, ParseError>;
+}
+
+impl Codec for CommonParser {
+    fn format(&self) -> Format {
+        CommonParser::format(self)
+    }
+
+    fn read(&self, mut data: &[u8]) -> Result<Vec<YPBankRecord>, ParseError> {
+        self.from_read(&mut data)
+    }
+
+    fn write(&self, records: &[YPBankRecord]) -> Result<Vec<u8>, ParseError> {
+        let mut data = Vec::new();
+        self.write_to(&mut data, &records.to_vec())?;
+        Ok(data)
+    }
+}
+
+/// Checks that `codec` reads the golden file of its format as the canonical records,
+/// writes the canonical records byte for byte as the golden file, and reads back what
+/// it writes.
+///
+/// The first failed check is returned as [`ParseError::InconsistentRecord`]; formats
+/// without a golden file fail with [`ParseError::UnsupportedOperation`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::conformance;
+/// use parser::{CommonParser, Format};
+///
+/// for format in [Format::Csv, Format::Txt, Format::Bin, Format::Toml] {
+///     conformance::run(&CommonParser::new(format)).unwrap();
+/// }
+/// ```
+pub fn run(codec: &impl Codec) -> Result<(), ParseError> {
+    let format = codec.format();
+    let Some(golden) = testdata::golden(format) else {
+        return Err(ParseError::UnsupportedOperation(format!(
+            "no golden file for {:?}",
+            format
+        )));
+    };
+    let records = testdata::records();
+
+    check_records("reading the golden file", &codec.read(golden)?, &records)?;
+
+    let written = codec.write(&records)?;
+    if let Some(position) = first_difference(&written, golden) {
+        return Err(ParseError::InconsistentRecord(format!(
+            "written data differs from the golden file at byte {}",
+            position
+        )));
+    }
+
+    check_records("reading written data", &codec.read(&written)?, &records)
+}
+
+/// Reads every file of `dir`, e.g. inputs that crashed a fuzzer, with `codec`, checking
+/// that it returns instead of panicking. Inputs that have become valid may be read as
+/// records; all others must fail with an error.
+///
+/// The first panic is returned as [`ParseError::InconsistentRecord`] naming the file.
+/// Subdirectories are not read, so a corpus can keep one directory per format, such
+/// as the `testdata/regressions/` directory of the crate.
+///
+/// # Examples
+///
+/// ```
+/// use parser::conformance;
+/// use parser::{CommonParser, Format, ParseOptions};
+///
+/// let options = ParseOptions {
+///     skip_invalid_records: true,
+///     ..ParseOptions::default()
+/// };
+/// let codec = CommonParser::with_options(Format::Bin, options);
+/// conformance::assert_no_panic(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/regressions/bin"), &codec)
+///     .unwrap();
+/// ```
+pub fn assert_no_panic(dir: impl AsRef<Path>, codec: &impl Codec) -> Result<(), ParseError> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    for path in paths {
+        let data = std::fs::read(&path)?;
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| codec.read(&data))) {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            return Err(ParseError::InconsistentRecord(format!(
+                "reading {} panicked: {}",
+                path.display(),
+                message
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Generates random valid records from a seed, so that a failing run can be repeated.
+///
+/// Values favour the edges of their range, and descriptions mix the characters formats
+/// quote or escape differently: separators, `#`, backslashes and non-ASCII text.
+/// Descriptions are those every format carries: CSV and TXT keep the quotes of
+/// descriptions, so ones holding commas are quoted as in [`crate::testdata::records`],
+/// and none starts or ends with whitespace, which TXT trims.
+#[derive(Debug, Clone)]
+pub struct RecordGenerator {
+    state: u64,
+}
+
+/// Pieces descriptions are made of.
+const DESCRIPTION_PIECES: [&str; 15] = [
+    "ATM", "Rent", " ", ",", ";", "'", "#", "=", ":", "\\", "é", "東京", "☕", "0", "-",
+];
+
+impl RecordGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // SplitMix64, whose output differs widely between consecutive seeds.
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// A value that is one of the bounds or zero one time in four.
+    fn edgy_u64(&mut self) -> u64 {
+        match self.below(8) {
+            0 => u64::MAX,
+            1 => 1,
+            _ => self.next_u64() >> self.below(64),
+        }
+    }
+
+    fn edgy_i64(&mut self) -> i64 {
+        match self.below(8) {
+            0 => i64::MAX,
+            1 => i64::MIN,
+            2 => 0,
+            _ => (self.next_u64() >> self.below(64)).cast_signed(),
+        }
+    }
+
+    fn description(&mut self) -> String {
+        let description: String = (0..self.below(8))
+            .map(|_| DESCRIPTION_PIECES[self.below(DESCRIPTION_PIECES.len() as u64) as usize])
+            .collect();
+        let description = description.trim();
+        match description.contains(',') {
+            true => format!("\"{}\"", description),
+            false => description.to_string(),
+        }
+    }
+
+    /// Returns a record that passes [`YPBankRecord::validate`].
+    pub fn record(&mut self) -> YPBankRecord {
+        let transaction_type = TransactionType::from_int(self.below(3) as u8)
+            .expect("transaction types are numbered from 0");
+        let mut user = || self.edgy_u64().max(1);
+        let (from_user_id, to_user_id) = match transaction_type {
+            TransactionType::Deposit => (0, user()),
+            TransactionType::Withdrawal => (user(), 0),
+            TransactionType::Transfer => (user(), user()),
+        };
+        YPBankRecord::new(
+            self.edgy_u64(),
+            transaction_type,
+            from_user_id,
+            to_user_id,
+            self.edgy_i64(),
+            self.edgy_u64(),
+            TransactionStatus::from_int(self.below(3) as u8).expect("statuses are numbered from 0"),
+            self.description(),
+        )
+    }
+
+    pub fn records(&mut self, count: usize) -> Vec<YPBankRecord> {
+        (0..count).map(|_| self.record()).collect()
+    }
+}
+
+/// Writes `records` with every codec and reads them back, checking that each reads the
+/// records it wrote, and that writing the records read gives the same bytes again.
+///
+/// The first failed check is returned as [`ParseError::InconsistentRecord`] naming the
+/// format; write and read errors are returned as they are.
+///
+/// # Examples
+///
+/// ```
+/// use parser::conformance::{self, Codec, RecordGenerator};
+/// use parser::{CommonParser, Format};
+///
+/// let codecs = [Format::Csv, Format::Txt, Format::Bin, Format::Toml].map(CommonParser::new);
+/// let codecs: Vec<&dyn Codec> = codecs.iter().map(|codec| codec as &dyn Codec).collect();
+/// let records = RecordGenerator::new(7).records(100);
+/// conformance::differential(&codecs, &records).unwrap();
+/// ```
+pub fn differential(codecs: &[&dyn Codec], records: &[YPBankRecord]) -> Result<(), ParseError> {
+    for codec in codecs {
+        let format = codec.format().as_str();
+        let written = codec.write(records)?;
+        let read = codec.read(&written)?;
+        check_records(&format!("reading written {}", format), &read, records)?;
+
+        let rewritten = codec.write(&read)?;
+        if let Some(position) = first_difference(&rewritten, &written) {
+            return Err(ParseError::InconsistentRecord(format!(
+                "rewriting read {} differs at byte {}",
+                format, position
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Runs [`differential`] on `rounds` batches of `count` records generated from `seed`
+/// on, one seed per round, returning the seed of the first failing round with its error.
+pub fn fuzz(
+    codecs: &[&dyn Codec],
+    seed: u64,
+    rounds: u64,
+    count: usize,
+) -> Result<(), (u64, ParseError)> {
+    for seed in seed..seed.saturating_add(rounds) {
+        let records = RecordGenerator::new(seed).records(count);
+        differential(codecs, &records).map_err(|err| (seed, err))?;
+    }
+    Ok(())
+}
+
+fn check_records(
+    check: &str,
+    actual: &[YPBankRecord],
+    expected: &[YPBankRecord],
+) -> Result<(), ParseError> {
+    if let Some(index) = (0..expected.len()).find(|&index| actual.get(index) != expected.get(index))
+    {
+        return Err(ParseError::InconsistentRecord(format!(
+            "{}: record {} is {:?}, expected {:?}",
+            check,
+            index,
+            actual.get(index),
+            expected[index]
+        )));
+    }
+    if actual.len() != expected.len() {
+        return Err(ParseError::InconsistentRecord(format!(
+            "{}: {} records, expected {}",
+            check,
+            actual.len(),
+            expected.len()
+        )));
+    }
+    Ok(())
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(position) => Some(position),
+        None if a.len() != b.len() => Some(a.len().min(b.len())),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod conformance_tests {
+    use super::*;
+
+    #[test]
+    fn test_common_parser_conforms() {
+        let formats = [
+            Format::Csv,
+            Format::Txt,
+            Format::Bin,
+            Format::Toml,
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ];
+
+        for format in formats {
+            assert_eq!(run(&CommonParser::new(format)), Ok(()), "{:?}", format);
+        }
+    }
+
+    struct DroppingLastRecord;
+
+    impl Codec for DroppingLastRecord {
+        fn format(&self) -> Format {
+            Format::Bin
+        }
+
+        fn read(&self, data: &[u8]) -> Result<Vec<YPBankRecord>, ParseError> {
+            let mut records = CommonParser::new(Format::Bin).read(data)?;
+            records.pop();
+            Ok(records)
+        }
+
+        fn write(&self, records: &[YPBankRecord]) -> Result<Vec<u8>, ParseError> {
+            CommonParser::new(Format::Bin).write(records)
+        }
+    }
+
+    #[test]
+    fn test_regressions_do_not_panic() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/regressions");
+        for (dir, format) in [
+            ("csv", Format::Csv),
+            ("txt", Format::Txt),
+            ("bin", Format::Bin),
+            ("toml", Format::Toml),
+        ] {
+            let codec = CommonParser::new(format);
+            assert_eq!(assert_no_panic(corpus.join(dir), &codec), Ok(()), "{}", dir);
+            for path in std::fs::read_dir(corpus.join(dir)).unwrap() {
+                let data = std::fs::read(path.unwrap().path()).unwrap();
+                assert!(codec.read(&data).is_err());
+            }
+        }
+    }
+
+    struct Panicking;
+
+    impl Codec for Panicking {
+        fn format(&self) -> Format {
+            Format::Bin
+        }
+
+        fn read(&self, data: &[u8]) -> Result<Vec<YPBankRecord>, ParseError> {
+            if data.starts_with(b"YPBN") {
+                panic!("unexpected magic");
+            }
+            Ok(Vec::new())
+        }
+
+        fn write(&self, records: &[YPBankRecord]) -> Result<Vec<u8>, ParseError> {
+            CommonParser::new(Format::Bin).write(records)
+        }
+    }
+
+    #[test]
+    fn test_assert_no_panic_reports_panics() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/regressions/bin");
+        assert!(matches!(
+            assert_no_panic(&corpus, &Panicking),
+            Err(ParseError::InconsistentRecord(msg))
+                if msg.contains("description_past_end.bin panicked: unexpected magic")
+        ));
+    }
+
+    /// Writes descriptions without the `☕` they end with, like a writer trimming them.
+    struct TrimmingTxt;
+
+    impl Codec for TrimmingTxt {
+        fn format(&self) -> Format {
+            Format::Txt
+        }
+
+        fn read(&self, data: &[u8]) -> Result<Vec<YPBankRecord>, ParseError> {
+            CommonParser::new(Format::Txt).read(data)
+        }
+
+        fn write(&self, records: &[YPBankRecord]) -> Result<Vec<u8>, ParseError> {
+            let trimmed: Vec<YPBankRecord> = records
+                .iter()
+                .map(|record| YPBankRecord {
+                    description: record.description.trim_end_matches('☕').into(),
+                    ..record.clone()
+                })
+                .collect();
+            CommonParser::new(Format::Txt).write(&trimmed)
+        }
+    }
+
+    #[test]
+    fn test_generator() {
+        let records = RecordGenerator::new(3).records(500);
+        assert_eq!(records, RecordGenerator::new(3).records(500));
+        assert_ne!(records, RecordGenerator::new(4).records(500));
+        assert!(records.iter().all(|record| record.validate().is_ok()));
+        assert!(records.iter().any(|record| record.amount == i64::MIN));
+        assert!(
+            records
+                .iter()
+                .any(|record| record.description.contains(','))
+        );
+    }
+
+    #[test]
+    fn test_fuzz() {
+        let codecs = [
+            Format::Csv,
+            Format::Txt,
+            Format::Bin,
+            Format::Toml,
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ]
+        .map(CommonParser::new);
+        let codecs: Vec<&dyn Codec> = codecs.iter().map(|codec| codec as &dyn Codec).collect();
+        assert_eq!(fuzz(&codecs, 0, 50, 20), Ok(()));
+
+        let failing = fuzz(&[&TrimmingTxt], 0, 50, 20);
+        assert!(matches!(
+            failing,
+            Err((_, ParseError::InconsistentRecord(msg))) if msg.starts_with("reading written txt")
+        ));
+    }
+
+    #[test]
+    fn test_run_reports_failures() {
+        assert!(matches!(
+            run(&DroppingLastRecord),
+            Err(ParseError::InconsistentRecord(msg)) if msg.starts_with("reading the golden file: record 4")
+        ));
+        assert!(matches!(
+            run(&CommonParser::new(Format::Html)),
+            Err(ParseError::UnsupportedOperation(_))
+        ));
+    }
+}
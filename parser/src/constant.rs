@@ -5,3 +5,11 @@ pub const WITHDRAWAL: &str = "WITHDRAWAL";
 pub const SUCCESS: &str = "SUCCESS";
 pub const FAILURE: &str = "FAILURE";
 pub const PENDING: &str = "PENDING";
+
+pub const TX_ID: &str = "TX_ID";
+pub const FROM_USER_ID: &str = "FROM_USER_ID";
+pub const TO_USER_ID: &str = "TO_USER_ID";
+pub const AMOUNT: &str = "AMOUNT";
+pub const TIMESTAMP: &str = "TIMESTAMP";
+pub const STATUS: &str = "STATUS";
+pub const DESCRIPTION: &str = "DESCRIPTION";
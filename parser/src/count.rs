@@ -0,0 +1,99 @@
+use crate::CommonParser;
+use crate::bin_format::YPBankBinRecordParser;
+use crate::common::Format;
+use crate::csv_format::YPBankCsvRecordParser;
+use crate::error::ParseError;
+use crate::toml_format::YPBankTomlRecordParser;
+use crate::txt_format::YPBankTxtRecordParser;
+use std::io::{BufReader, Read};
+
+/// Counts the records of `r` without parsing them into records where the format allows.
+///
+/// Binary records are skipped over by their size, CSV records counted by their lines,
+/// TXT records by their key lines and TOML records by their table headers. Field values
+/// are not validated, so a record that would fail to parse is counted all the same; a
+/// last TXT record missing some of its lines is not counted. Other formats are read
+/// record by record.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Format, count_records};
+/// use std::fs::File;
+/// # parser::fixtures::enter().unwrap();
+///
+/// let count = count_records(File::open("records.bin").unwrap(), Format::Bin).unwrap();
+/// assert_eq!(count, 20);
+/// ```
+pub fn count_records<R: Read>(r: R, format: Format) -> Result<u64, ParseError> {
+    let mut r = BufReader::new(r);
+    match format {
+        Format::Csv => YPBankCsvRecordParser::count_records(&mut r),
+        Format::Txt => YPBankTxtRecordParser::count_records(&mut r),
+        Format::Bin => YPBankBinRecordParser::count_records(&mut r),
+        Format::Toml => YPBankTomlRecordParser::count_records(&mut r),
+        _ => CommonParser::new(format)
+            .iter_read(r)?
+            .try_fold(0, |count, record| record.map(|_| count + 1)),
+    }
+}
+
+#[cfg(test)]
+mod count_records_tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_count_records() {
+        let records = fixtures::records();
+        for format in [Format::Csv, Format::Txt, Format::Bin, Format::Toml] {
+            let mut data = Vec::new();
+            CommonParser::new(format)
+                .write_to(&mut data, &records)
+                .expect("Should write records");
+            assert_eq!(
+                count_records(data.as_slice(), format),
+                Ok(records.len() as u64),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_count_records_by_lines() {
+        let csv = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                   1,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Two\n\nlines\"\n\
+                   \n\
+                   2,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"One line\"\n";
+        assert_eq!(count_records(csv.as_bytes(), Format::Csv), Ok(2));
+        let concatenated = format!("{}{}", csv, csv);
+        assert_eq!(count_records(concatenated.as_bytes(), Format::Csv), Ok(4));
+        let crlf = concatenated.replace('\n', "\r\n");
+        assert_eq!(count_records(crlf.as_bytes(), Format::Csv), Ok(4));
+        assert!(matches!(
+            count_records("TX_ID\n".as_bytes(), Format::Csv),
+            Err(ParseError::InvalidCsvHeader(_))
+        ));
+
+        let mut txt = Vec::new();
+        CommonParser::new(Format::Txt)
+            .write_to(&mut txt, &fixtures::records()[..2].to_vec())
+            .expect("Should write records");
+        txt.extend_from_slice(b"# Still being written\nTX_ID: 3\nTX_TYPE: DEPOSIT\n");
+        assert_eq!(count_records(txt.as_slice(), Format::Txt), Ok(2));
+    }
+
+    #[test]
+    fn test_count_truncated_binary() {
+        let mut data = Vec::new();
+        CommonParser::new(Format::Bin)
+            .write_to(&mut data, &fixtures::records()[..2].to_vec())
+            .expect("Should write records");
+        data.truncate(data.len() - 1);
+        assert_eq!(
+            count_records(data.as_slice(), Format::Bin),
+            Err(ParseError::UnexpectedEOF)
+        );
+    }
+}
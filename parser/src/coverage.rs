@@ -0,0 +1,122 @@
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::record_eq::RecordKey;
+use std::collections::HashMap;
+
+/// How the records of a baseline are found across a set of files, see [`coverage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Baseline records found in none of the files.
+    pub missing: Vec<YPBankRecord>,
+    /// Baseline records found more than once, with the number of times they were found.
+    pub duplicated: Vec<(YPBankRecord, usize)>,
+    /// Records of the files that are not in the baseline, in the order they were read.
+    pub unexpected: Vec<YPBankRecord>,
+}
+
+impl Coverage {
+    /// Whether every baseline record was found exactly once, and nothing else.
+    pub fn is_exact(&self) -> bool {
+        self.missing.is_empty() && self.duplicated.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Checks that every record of `baseline` appears exactly once across `set`, the
+/// records of several files read one after the other, e.g. a full export against its
+/// hourly incrementals.
+///
+/// Records are matched under `key`. Only the keys of the baseline and the unexpected
+/// records are held, so the set is read as a stream. Fails with the first read error of
+/// the set.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ById, coverage, fixtures};
+///
+/// let baseline = fixtures::records();
+/// let (first, second) = baseline.split_at(10);
+/// let set = first.iter().chain(second).chain(&second[..1]).cloned().map(Ok);
+///
+/// let coverage = coverage(&baseline, set, &ById).unwrap();
+/// assert!(coverage.missing.is_empty());
+/// assert_eq!(coverage.duplicated, vec![(second[0].clone(), 2)]);
+/// ```
+pub fn coverage<K: RecordKey>(
+    baseline: &[YPBankRecord],
+    set: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    key: &K,
+) -> Result<Coverage, ParseError> {
+    let mut found: HashMap<K::Key, usize> =
+        baseline.iter().map(|record| (key.key(record), 0)).collect();
+    let mut coverage = Coverage::default();
+
+    for record in set {
+        let record = record?;
+        match found.get_mut(&key.key(&record)) {
+            Some(count) => *count += 1,
+            None => coverage.unexpected.push(record),
+        }
+    }
+
+    for record in baseline {
+        match found[&key.key(record)] {
+            0 => coverage.missing.push(record.clone()),
+            1 => {}
+            count => coverage.duplicated.push((record.clone(), count)),
+        }
+    }
+    Ok(coverage)
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use crate::record_eq::{ById, Exact};
+
+    fn record(id: u64, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            2,
+            amount,
+            1633036860000,
+            TransactionStatus::Success,
+            "Salary",
+        )
+    }
+
+    #[test]
+    fn test_coverage() {
+        let baseline = vec![record(1, 100), record(2, 100), record(3, 100)];
+        let set = [
+            record(3, 100),
+            record(1, 100),
+            record(1, 100),
+            record(4, 100),
+        ];
+
+        assert_eq!(
+            coverage(&baseline, set.iter().cloned().map(Ok), &Exact),
+            Ok(Coverage {
+                missing: vec![record(2, 100)],
+                duplicated: vec![(record(1, 100), 2)],
+                unexpected: vec![record(4, 100)],
+            })
+        );
+
+        let changed = [record(1, 100), record(2, 200), record(3, 100)];
+        let exact = coverage(&baseline, changed.iter().cloned().map(Ok), &Exact);
+        assert_eq!(exact.map(|coverage| coverage.is_exact()), Ok(false));
+        let by_id = coverage(&baseline, changed.iter().cloned().map(Ok), &ById);
+        assert_eq!(by_id.map(|coverage| coverage.is_exact()), Ok(true));
+
+        let failing = [Ok(record(1, 100)), Err(ParseError::UnexpectedEOF)];
+        assert_eq!(
+            coverage(&baseline, failing, &Exact),
+            Err(ParseError::UnexpectedEOF)
+        );
+    }
+}
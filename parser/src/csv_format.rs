@@ -1,8 +1,14 @@
-use crate::common::parse_value_from_string;
-use crate::common::{TransactionType, parse_from_user_id, parse_to_user_id};
+use crate::common::{TransactionType, ValueParser, parse_value_from_str};
+use crate::constant::{FROM_USER_ID, STATUS, TO_USER_ID, TX_ID};
 use crate::error::ParseError;
-use crate::parser::{Parser, YPBankRecordParser};
-use crate::record::YPBankRecord;
+use crate::options::{
+    CsvEmptyLines, CsvHeader, CsvIdFormat, CsvLimits, CsvQuotes, CsvRepeatedHeaders, ParseOptions,
+};
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use crate::schema::{SchemaMap, UnknownColumns};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Display, Formatter, Write};
 use std::str::FromStr;
 
 const SEP: char = ',';
@@ -10,131 +16,531 @@ const QUOTE: char = '"';
 const TARGET_HEADER: &str =
     "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n";
 
-struct Separator {
-    line: String,
+struct Separator<'a> {
+    line: &'a str,
     index: usize,
     is_inside_quotes: bool,
+    /// Set after the last field; a line ending with a separator ends with an empty field.
+    finished: bool,
 }
 
-impl Separator {
-    fn new(line: String) -> Self {
+impl<'a> Separator<'a> {
+    fn new(line: &'a str) -> Self {
         Separator {
             line,
             index: 0,
             is_inside_quotes: false,
+            finished: line.is_empty(),
         }
     }
 }
 
-impl Iterator for Separator {
-    type Item = String;
+impl<'a> Iterator for Separator<'a> {
+    type Item = &'a str;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.index >= self.line.len() {
+        if self.finished {
             return None;
         }
 
+        // Separators and quotes are ASCII, so their bytes never occur inside other
+        // characters and the line can be scanned byte by byte from the last field.
         let start = self.index;
-        let mut field_end = start;
-
-        for (byte_pos, ch) in self.line.char_indices().skip_while(|(i, _)| *i < start) {
-            if !self.is_inside_quotes && ch == SEP {
-                self.index = byte_pos + ch.len_utf8();
-                return Some(self.line[start..field_end].to_string());
+        for (offset, byte) in self.line.as_bytes()[start..].iter().enumerate() {
+            if !self.is_inside_quotes && *byte == SEP as u8 {
+                self.index = start + offset + 1;
+                return Some(&self.line[start..start + offset]);
             }
 
-            if ch == QUOTE {
+            if *byte == QUOTE as u8 {
                 self.is_inside_quotes = !self.is_inside_quotes;
             }
-            field_end = byte_pos + ch.len_utf8();
         }
 
-        let result = self.line[start..field_end].to_string();
         self.index = self.line.len();
-        Some(result)
+        self.finished = true;
+        Some(&self.line[start..])
+    }
+}
+
+/// Returns the byte position of the quote still open at the end of `text`, if any.
+fn open_quote(text: &str) -> Option<usize> {
+    let mut open = None;
+    for (byte_pos, ch) in text.char_indices() {
+        if ch == QUOTE {
+            open = match open {
+                Some(_) => None,
+                None => Some(byte_pos),
+            };
+        }
+    }
+    open
+}
+
+/// Strips the quotes of ids written with [`CsvIdFormat::Quoted`] or
+/// [`CsvIdFormat::Formula`].
+fn unquote_id(value: &str) -> &str {
+    let quoted = value.strip_prefix('=').unwrap_or(value);
+    match quoted
+        .strip_prefix(QUOTE)
+        .and_then(|quoted| quoted.strip_suffix(QUOTE))
+    {
+        Some(id) => id,
+        None => value,
+    }
+}
+
+/// An id written in a [`CsvIdFormat`].
+struct Id(u64, CsvIdFormat);
+
+impl Display for Id {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.1 {
+            CsvIdFormat::Plain => write!(f, "{}", self.0),
+            CsvIdFormat::Quoted => write!(f, "\"{}\"", self.0),
+            CsvIdFormat::Formula => write!(f, "=\"{}\"", self.0),
+        }
     }
 }
 
-pub struct YPBankCsvRecordParser {}
+#[derive(Default)]
+pub struct YPBankCsvRecordParser {
+    line: String,
+    values: ValueParser,
+    schema: Option<SchemaMap>,
+    /// Field index of every column, set from the header when a schema map is used;
+    /// `None` for unknown columns.
+    columns: Vec<Option<usize>>,
+    /// Position and name of the unknown columns captured into [`Self::extras`].
+    captured: Vec<(usize, String)>,
+    /// Values of the captured columns of the last record read.
+    extras: BTreeMap<String, String>,
+    /// Names of the extra columns written after the YPBank ones.
+    extra_columns: Vec<String>,
+    quotes: CsvQuotes,
+    empty_lines: CsvEmptyLines,
+    repeated_headers: CsvRepeatedHeaders,
+    /// The header line of the input without its line break, once read.
+    header: Option<String>,
+    id_format: CsvIdFormat,
+    limits: CsvLimits,
+    has_header: bool,
+    /// Number of lines read so far, for locating unterminated quotes.
+    lines_read: u64,
+    /// Whether the last failed record stopped inside a line that was too long.
+    partial: bool,
+}
 
 impl YPBankCsvRecordParser {
-    fn from_raw_values(raw_values: Vec<String>) -> Result<YPBankRecord, ParseError> {
-        if raw_values.len() != 8 {
+    /// Appends the next line to `self.line`, reading at most [`CsvLimits::max_line_len`]
+    /// bytes of it.
+    fn read_line<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<usize, ParseError> {
+        let max = self.limits.max_line_len;
+        let mut limited = std::io::Read::take(&mut *r, max as u64);
+        let read = std::io::BufRead::read_line(&mut limited, &mut self.line);
+        let exhausted = limited.limit() == 0;
+        match read {
+            Ok(len) if !exhausted || self.line.ends_with('\n') || r.fill_buf()?.is_empty() => {
+                Ok(len)
+            }
+            Err(err) if !exhausted => Err(err.into()),
+            _ => {
+                self.partial = true;
+                Err(ParseError::LineTooLong {
+                    line: self.lines_read + 1,
+                    max,
+                })
+            }
+        }
+    }
+
+    /// Splits `self.line` into fields, failing beyond [`CsvLimits::max_fields`].
+    fn split_line(&self, line: u64) -> Result<Vec<&str>, ParseError> {
+        let max = self.limits.max_fields;
+        let values = Separator::new(self.line.trim())
+            .take(max + 1)
+            .collect::<Vec<&str>>();
+        if values.len() > max {
+            return Err(ParseError::TooManyFields { line, max });
+        }
+        Ok(values)
+    }
+
+    /// Reads the continuation lines of a record whose last quoted field is still open.
+    ///
+    /// `first_line` is the number of the first line of the record in `self.line`.
+    fn read_quoted_lines<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+        first_line: u64,
+    ) -> Result<(), ParseError> {
+        let mut record_lines = 1;
+        while let Some(quote_pos) = open_quote(&self.line) {
+            let max_lines = match self.quotes {
+                CsvQuotes::Lenient => return Ok(()),
+                CsvQuotes::Strict => 1,
+                CsvQuotes::MultiLine { max_lines } => max_lines,
+            };
+            if record_lines >= max_lines || self.read_line(r)? == 0 {
+                let before_quote = &self.line[..quote_pos];
+                let line_start = before_quote.rfind('\n').map_or(0, |pos| pos + 1);
+                return Err(ParseError::UnterminatedQuote {
+                    line: first_line + before_quote.matches('\n').count() as u64,
+                    column: before_quote[line_start..].chars().count() + 1,
+                });
+            }
+            record_lines += 1;
+            self.lines_read += 1;
+        }
+        Ok(())
+    }
+
+    /// Reads the empty lines that follow, returning whether a non-empty line follows them.
+    fn skip_empty_lines<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        loop {
+            self.line.clear();
+            if self.read_line(r)? == 0 {
+                return Ok(false);
+            }
+            if !self.line.trim().is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Returns whether `self.line` is a header line: one starting with the first column
+    /// name of the header of the input.
+    fn is_header(&self, header: &str) -> bool {
+        let first_column = |line: &str| {
+            Separator::new(line)
+                .next()
+                .map(|column| column.trim().trim_matches(QUOTE).to_uppercase())
+        };
+        first_column(self.line.trim()) == first_column(header)
+    }
+
+    fn record_from_raw_values(&self, raw_values: &[&str]) -> Result<YPBankRecord, ParseError> {
+        let expected = match self.schema {
+            Some(_) => self.columns.len(),
+            None => 8,
+        };
+        if raw_values.len() != expected {
             return Err(ParseError::InvalidRow(format!(
-                "Expected 8 fields, got {}",
+                "Expected {} fields, got {}",
+                expected,
                 raw_values.len()
             )));
         }
 
-        let tt_parse_result = TransactionType::from_str(&raw_values[1])?;
+        let mapped_values;
+        let raw_values = match &self.schema {
+            Some(schema) => {
+                let mut values = [""; 8];
+                for (field, value) in self.columns.iter().zip(raw_values) {
+                    if let Some(field) = *field {
+                        values[field] = schema.map_value(field, value);
+                    }
+                }
+                mapped_values = values;
+                &mapped_values[..]
+            }
+            None => raw_values,
+        };
+
+        let transaction_type = TransactionType::from_str(raw_values[1])?;
 
         Ok(YPBankRecord::new(
-            parse_value_from_string(raw_values[0].clone())?,
-            parse_value_from_string(raw_values[1].clone())?,
-            parse_from_user_id(raw_values[2].clone(), tt_parse_result)?,
-            parse_to_user_id(raw_values[3].clone(), tt_parse_result)?,
-            parse_value_from_string(raw_values[4].clone())?,
-            parse_value_from_string(raw_values[5].clone())?,
-            parse_value_from_string(raw_values[6].clone())?,
-            raw_values[7].clone(),
+            self.values.number(TX_ID, unquote_id(raw_values[0]))?,
+            transaction_type,
+            self.values
+                .user_id(FROM_USER_ID, unquote_id(raw_values[2]), transaction_type)?,
+            self.values
+                .user_id(TO_USER_ID, unquote_id(raw_values[3]), transaction_type)?,
+            self.values.amount(raw_values[4])?,
+            self.values.timestamp(raw_values[5])?,
+            parse_value_from_str(STATUS, raw_values[6])?,
+            raw_values[7].to_string(),
         ))
     }
 }
 
-impl YPBankRecordParser for YPBankCsvRecordParser {
-    fn from_read<R: std::io::BufRead>(r: &mut R) -> Result<Option<YPBankRecord>, ParseError> {
+impl YPBankCsvRecordParser {
+    /// Counts records by their lines, without splitting them into fields. Lines of quoted
+    /// fields spanning lines count once; empty lines are not counted.
+    pub(crate) fn count_records<R: std::io::BufRead>(r: &mut R) -> Result<u64, ParseError> {
         let mut line = String::new();
-        let bytes_read = r.read_line(&mut line)?;
+        r.read_line(&mut line)?;
+        let header = TARGET_HEADER.trim();
+        if line.trim() != header {
+            return Err(ParseError::InvalidCsvHeader(line));
+        }
 
-        if bytes_read == 0 || line.trim().is_empty() {
-            return Ok(None);
+        let mut count = 0;
+        let mut is_inside_quotes = false;
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Ok(count);
+            }
+            if !is_inside_quotes {
+                if line.trim().is_empty() || line.trim() == header {
+                    continue;
+                }
+                count += 1;
+            }
+            if line.matches(QUOTE).count() % 2 == 1 {
+                is_inside_quotes = !is_inside_quotes;
+            }
         }
+    }
+}
 
-        let sep = Separator::new(line.trim().to_string());
-        let mut values = vec![];
-        for value in sep {
-            values.push(value);
+impl FromOptions for YPBankCsvRecordParser {
+    fn with_options(options: &ParseOptions) -> Self {
+        Self {
+            values: ValueParser::new(options),
+            limits: options.csv_limits,
+            has_header: options.csv_has_header,
+            header: (options.csv_has_header && options.csv_schema.is_none())
+                .then(|| TARGET_HEADER.trim().to_string()),
+            schema: options.csv_schema.clone(),
+            quotes: options.csv_quotes,
+            empty_lines: options.csv_empty_lines,
+            repeated_headers: options.csv_repeated_headers,
+            id_format: options.csv_id_format,
+            ..Self::default()
         }
+    }
+}
 
-        let record = Self::from_raw_values(values)?;
+impl RecordReader for YPBankCsvRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        // Loops past skipped repeated headers, as a file may hold any number of them.
+        loop {
+            self.line.clear();
+            if self.read_line(r)? == 0 {
+                return Ok(None);
+            }
+            self.lines_read += 1;
+
+            while self.line.trim().is_empty() {
+                let empty_line = self.lines_read;
+                match self.empty_lines {
+                    CsvEmptyLines::Stop => return Ok(None),
+                    CsvEmptyLines::Skip => {}
+                    CsvEmptyLines::Error => {
+                        if !self.skip_empty_lines(r)? {
+                            return Ok(None);
+                        }
+                        return Err(ParseError::EmptyLine { line: empty_line });
+                    }
+                }
+
+                self.line.clear();
+                if self.read_line(r)? == 0 {
+                    return Ok(None);
+                }
+                self.lines_read += 1;
+            }
+            let Some(header) = &self.header else {
+                break;
+            };
+            if !self.is_header(header) {
+                break;
+            }
+            if self.repeated_headers != CsvRepeatedHeaders::Skip || self.line.trim() != header {
+                return Err(ParseError::InvalidCsvHeader(self.line.clone()));
+            }
+        }
+        let first_line = self.lines_read;
+        self.read_quoted_lines(r, first_line)?;
+
+        let values = self.split_line(first_line)?;
+
+        let record = self.record_from_raw_values(&values)?;
+        if !self.captured.is_empty() {
+            let extras = self
+                .captured
+                .iter()
+                .map(|(position, name)| (name.clone(), values[*position].to_string()))
+                .collect();
+            self.extras = extras;
+        }
         Ok(Some(record))
     }
 
-    fn write_to<W: std::io::Write>(record: &YPBankRecord, w: &mut W) -> Result<(), ParseError> {
-        let record_str = format!(
-            "{},{},{},{},{},{},{},{}\n",
-            record.id,
+    fn take_extras(&mut self) -> BTreeMap<String, String> {
+        std::mem::take(&mut self.extras)
+    }
+
+    /// Records end at the end of a line, so a failed record has been read whole unless
+    /// a line was too long; the rest of that line is skipped.
+    fn skip_invalid<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        if !std::mem::take(&mut self.partial) {
+            return Ok(true);
+        }
+
+        self.lines_read += 1;
+        loop {
+            let buf = r.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(true);
+            }
+            if let Some(pos) = buf.iter().position(|byte| *byte == b'\n') {
+                r.consume(pos + 1);
+                return Ok(true);
+            }
+            let len = buf.len();
+            r.consume(len);
+        }
+    }
+}
+
+impl RecordWriter for YPBankCsvRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        self.line.clear();
+        self.push_fields(record)?;
+        self.line.push('\n');
+
+        w.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes the extras after the YPBank columns, in the order of the extra columns of
+    /// the header. Values holding commas or line breaks fail, as they would split the
+    /// row.
+    fn write_ext_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecordExt,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        self.line.clear();
+        self.push_fields(&record.core)?;
+        for column in &self.extra_columns {
+            let value = record.extras.get(column).map_or("", String::as_str);
+            if value.contains([SEP, '\n', '\r']) {
+                return Err(ParseError::InvalidRawValue {
+                    field: column.clone(),
+                    value: value.to_string(),
+                });
+            }
+            self.line.push(SEP);
+            self.line.push_str(value);
+        }
+        self.line.push('\n');
+
+        w.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl YPBankCsvRecordParser {
+    /// Appends the YPBank columns of `record` to `self.line`.
+    fn push_fields(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        let id_format = self.id_format;
+        write!(
+            self.line,
+            "{},{},{},{},{},{},{},{}",
+            Id(record.id, id_format),
             record.transaction_type.as_str(),
-            record.from_user_id,
-            record.to_user_id,
+            Id(record.from_user_id, id_format),
+            Id(record.to_user_id, id_format),
             record.amount,
             record.ts,
             record.status.as_str(),
             record.description
-        );
-
-        w.write_all(record_str.as_bytes())?;
-        Ok(())
+        )
+        .map_err(|err| ParseError::IOError(err.to_string()))
     }
 }
 
 pub struct CsvParser {}
 
 impl Parser<YPBankCsvRecordParser> for CsvParser {
-    fn pre_read<R: std::io::BufRead>(r: &mut R) -> Result<(), ParseError> {
-        let mut line = String::new();
+    fn pre_read<R: std::io::BufRead>(
+        r: &mut R,
+        record_parser: &mut YPBankCsvRecordParser,
+    ) -> Result<(), ParseError> {
+        if !record_parser.has_header {
+            if record_parser.schema.is_some() {
+                record_parser.columns = (0..8).map(Some).collect();
+            }
+            return Ok(());
+        }
 
-        r.read_line(&mut line)?;
+        record_parser.line.clear();
+        record_parser.read_line(r)?;
+        record_parser.lines_read += 1;
+        record_parser.header = Some(record_parser.line.trim().to_string());
+
+        if let Some(schema) = &record_parser.schema {
+            let header = record_parser.split_line(1)?;
+            let columns = schema.map_header(&header)?;
+            if schema.unknown_columns() == UnknownColumns::Capture {
+                record_parser.captured = columns
+                    .iter()
+                    .zip(&header)
+                    .enumerate()
+                    .filter(|(_, (field, _))| field.is_none())
+                    .map(|(position, (_, name))| (position, name.trim().to_string()))
+                    .collect();
+            }
+            record_parser.columns = columns;
+            return Ok(());
+        }
 
-        if line != TARGET_HEADER {
-            return Err(ParseError::InvalidCsvHeader(line));
+        if record_parser.line != TARGET_HEADER {
+            return Err(ParseError::InvalidCsvHeader(record_parser.line.clone()));
         }
 
         Ok(())
     }
 
-    fn pre_write<W: std::io::Write>(w: &mut W) -> Result<(), ParseError> {
-        w.write_all(TARGET_HEADER.as_bytes())?;
+    fn pre_write<W: std::io::Write>(w: &mut W, options: &ParseOptions) -> Result<(), ParseError> {
+        match &options.csv_write_header {
+            CsvHeader::Standard => w.write_all(TARGET_HEADER.as_bytes())?,
+            CsvHeader::None => {}
+            CsvHeader::Custom(header) => {
+                w.write_all(header.as_bytes())?;
+                if !header.ends_with('\n') {
+                    w.write_all(b"\n")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a column for every extra name found in `records` to the standard header.
+    fn pre_write_ext<W: std::io::Write>(
+        w: &mut W,
+        record_parser: &mut YPBankCsvRecordParser,
+        records: &[YPBankRecordExt],
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let columns: BTreeSet<&String> = records
+            .iter()
+            .flat_map(|record| record.extras.keys())
+            .collect();
+        record_parser.extra_columns = columns.into_iter().cloned().collect();
+
+        if options.csv_write_header != CsvHeader::Standard || record_parser.extra_columns.is_empty()
+        {
+            return Self::pre_write(w, options);
+        }
+        let mut header = TARGET_HEADER.trim_end().to_string();
+        for column in &record_parser.extra_columns {
+            header.push(SEP);
+            header.push_str(column);
+        }
+        header.push('\n');
+        w.write_all(header.as_bytes())?;
         Ok(())
     }
 }
@@ -145,45 +551,51 @@ mod separator_tests {
 
     #[test]
     fn test_regular_case() {
-        let test_line = "val1,val 2, val 3 ".to_string();
+        let test_line = "val1,val 2, val 3 ";
         let target_values = vec!["val1", "val 2", " val 3 "];
 
         let sep = Separator::new(test_line);
 
-        let result = sep.collect::<Vec<String>>();
+        let result = sep.collect::<Vec<&str>>();
         assert_eq!(result, target_values);
     }
 
     #[test]
     fn test_quotes() {
-        let test_line = "val1,val 2, \" val,,,3 \" ".to_string();
+        let test_line = "val1,val 2, \" val,,,3 \" ";
         let target_values = vec!["val1", "val 2", " \" val,,,3 \" "];
 
         let sep = Separator::new(test_line);
 
-        let result = sep.collect::<Vec<String>>();
+        let result = sep.collect::<Vec<&str>>();
         assert_eq!(result, target_values);
     }
 
     #[test]
     fn test_empty_line() {
-        let test_line = "".to_string();
-        let target_values: Vec<String> = vec![];
+        let test_line = "";
+        let target_values: Vec<&str> = vec![];
 
         let sep = Separator::new(test_line);
 
-        let result = sep.collect::<Vec<String>>();
+        let result = sep.collect::<Vec<&str>>();
         assert_eq!(result, target_values);
     }
 
+    #[test]
+    fn test_empty_last_value() {
+        let result = Separator::new("val1,").collect::<Vec<&str>>();
+        assert_eq!(result, vec!["val1", ""]);
+    }
+
     #[test]
     fn test_empty_value_in_line() {
-        let test_line = "val1,,val3".to_string();
+        let test_line = "val1,,val3";
         let target_values = vec!["val1", "", "val3"];
 
         let sep = Separator::new(test_line);
 
-        let result = sep.collect::<Vec<String>>();
+        let result = sep.collect::<Vec<&str>>();
         assert_eq!(result, target_values);
     }
 }
@@ -192,17 +604,18 @@ mod separator_tests {
 mod yp_bank_csv_record_tests {
     use super::*;
     use crate::common::TransactionStatus;
+    use crate::options::NumericPolicy;
     use std::io::Cursor;
 
     #[test]
     fn test_from_read_regular_case() {
-        let raw_line = "1000000000000000,DEPOSIT,1,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n";
+        let raw_line = "1000000000000000,DEPOSIT,0,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n";
         let mut reader = Cursor::new(raw_line.as_bytes());
 
         let target_record = YPBankRecord::new(
             1000000000000000,
             TransactionType::Deposit,
-            1,
+            0,
             9223372036854775807,
             100,
             1633036860000,
@@ -210,7 +623,7 @@ mod yp_bank_csv_record_tests {
             "\"Record number 1\"".to_string(),
         );
 
-        let result = YPBankCsvRecordParser::from_read(&mut reader);
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_ok(), "Parsing should succeed");
         let record_opt = result.expect("Should parse successfully");
@@ -218,16 +631,96 @@ mod yp_bank_csv_record_tests {
         assert_eq!(record_opt.expect("Should have a record"), target_record);
     }
 
+    #[test]
+    fn test_from_read_localized_amount() {
+        let raw_line = "1,DEPOSIT,0,2,\"1 000,50\",1633036860000,SUCCESS,\"Salary\"\n";
+        let mut reader = Cursor::new(raw_line.as_bytes());
+        let options = ParseOptions {
+            amount_format: Some(crate::locale::AmountFormat::european()),
+            ..ParseOptions::default()
+        };
+
+        let record = YPBankCsvRecordParser::with_options(&options)
+            .from_read(&mut reader)
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        assert_eq!(record.amount, 100050);
+    }
+
+    #[test]
+    fn test_from_read_invalid_amount() {
+        let raw_line = "1,DEPOSIT,0,2,abc,1633036860000,SUCCESS,\"Salary\"\n";
+        let mut reader = Cursor::new(raw_line.as_bytes());
+
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidRawValue {
+                field: "AMOUNT".to_string(),
+                value: "abc".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_read_value_out_of_range() {
+        let raw_line = "18446744073709551616,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Salary\"\n";
+        let mut reader = Cursor::new(raw_line.as_bytes());
+
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
+
+        assert_eq!(
+            result,
+            Err(ParseError::ValueOutOfRange {
+                field: "TX_ID".to_string(),
+                value: "18446744073709551616".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_read_numeric_policy() {
+        let raw_line = "1,DEPOSIT,0,2,+0100, 1633036860000 ,SUCCESS,\"Salary\"\n";
+        let options = ParseOptions {
+            numeric_policy: NumericPolicy {
+                trim_whitespace: true,
+                ..NumericPolicy::default()
+            },
+            ..ParseOptions::default()
+        };
+
+        let record = YPBankCsvRecordParser::with_options(&options)
+            .from_read(&mut Cursor::new(raw_line.as_bytes()))
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        assert_eq!((record.amount, record.ts), (100, 1633036860000));
+
+        let options = ParseOptions {
+            numeric_policy: NumericPolicy::strict(),
+            ..ParseOptions::default()
+        };
+        let result = YPBankCsvRecordParser::with_options(&options)
+            .from_read(&mut Cursor::new(raw_line.as_bytes()));
+        assert_eq!(
+            result,
+            Err(ParseError::InvalidRawValue {
+                field: "AMOUNT".to_string(),
+                value: "+0100".to_string()
+            })
+        );
+    }
+
     #[test]
     fn test_from_read_invalid_from_user_id() {
         let raw_line = "1000000000000000,TRANSFER,0,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n";
         let mut reader = Cursor::new(raw_line.as_bytes());
 
-        let result = YPBankCsvRecordParser::from_read(&mut reader);
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_err(), "Should return an error");
 
-        let error = result.err().expect("Should return an error");
+        let error = result.expect_err("Should return an error");
         assert_eq!(
             error,
             ParseError::InvalidUserId("0".to_string(), TransactionType::Transfer)
@@ -240,21 +733,46 @@ mod yp_bank_csv_record_tests {
             "1000000000000000,TRANSFER,1,0,100,1633036860000,FAILURE,\"Record number 1\"\n";
         let mut reader = Cursor::new(raw_line.as_bytes());
 
-        let result = YPBankCsvRecordParser::from_read(&mut reader);
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_err(), "Should return an error");
 
-        let error = result.err().expect("Should return an error");
+        let error = result.expect_err("Should return an error");
         assert_eq!(
             error,
             ParseError::InvalidUserId("0".to_string(), TransactionType::Transfer)
         );
     }
 
+    #[test]
+    fn test_from_read_user_id_for_type() {
+        for (raw_line, user_id, transaction_type) in [
+            (
+                "1,DEPOSIT,5,2,100,1633036860000,SUCCESS,\"\"\n",
+                "5",
+                TransactionType::Deposit,
+            ),
+            (
+                "1,WITHDRAWAL,2,5,100,1633036860000,SUCCESS,\"\"\n",
+                "5",
+                TransactionType::Withdrawal,
+            ),
+        ] {
+            let mut reader = Cursor::new(raw_line.as_bytes());
+            assert_eq!(
+                YPBankCsvRecordParser::default().from_read(&mut reader),
+                Err(ParseError::InvalidUserId(
+                    user_id.to_string(),
+                    transaction_type
+                ))
+            );
+        }
+    }
+
     #[test]
     fn test_from_read_eof() {
         let mut reader = Cursor::new(Vec::<u8>::new());
-        let result = YPBankCsvRecordParser::from_read(&mut reader);
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_ok(), "EOF should return Ok(None)");
         assert!(
@@ -268,7 +786,7 @@ mod yp_bank_csv_record_tests {
         let raw_line = "\n";
         let mut reader = Cursor::new(raw_line.as_bytes());
 
-        let result = YPBankCsvRecordParser::from_read(&mut reader);
+        let result = YPBankCsvRecordParser::default().from_read(&mut reader);
 
         assert!(result.is_ok(), "Empty line should return Ok(None)");
         assert!(
@@ -277,22 +795,90 @@ mod yp_bank_csv_record_tests {
         );
     }
 
+    #[test]
+    fn test_from_read_multi_line_description() {
+        let raw = "1,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Salary,\nMarch\"\n2,DEPOSIT,0,2,5,1,SUCCESS,\"Tip\"\n";
+        let mut reader = Cursor::new(raw.as_bytes());
+        let mut parser = YPBankCsvRecordParser::default();
+
+        let first = parser
+            .from_read(&mut reader)
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        let second = parser
+            .from_read(&mut reader)
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        assert_eq!(&*first.description, "\"Salary,\nMarch\"");
+        assert_eq!(second.id, 2);
+    }
+
+    #[test]
+    fn test_from_read_unterminated_quote() {
+        let raw =
+            "1,DEPOSIT,0,2,100,1,SUCCESS,\"Ok\"\n2,DEPOSIT,0,2,100,1,SUCCESS,\"Salary, March\n";
+        let read_second = |quotes| {
+            let options = ParseOptions {
+                csv_quotes: quotes,
+                ..ParseOptions::default()
+            };
+            let mut parser = YPBankCsvRecordParser::with_options(&options);
+            let mut reader = Cursor::new(raw.as_bytes());
+            parser.from_read(&mut reader).expect("Should parse first");
+            parser.from_read(&mut reader)
+        };
+        let unterminated = Err(ParseError::UnterminatedQuote {
+            line: 2,
+            column: 29,
+        });
+
+        assert_eq!(read_second(CsvQuotes::default()), unterminated);
+        assert_eq!(read_second(CsvQuotes::Strict), unterminated);
+        assert_eq!(
+            read_second(CsvQuotes::Lenient)
+                .expect("Should parse leniently")
+                .expect("Should have a record")
+                .description
+                .as_ref(),
+            "\"Salary, March"
+        );
+    }
+
+    #[test]
+    fn test_from_read_quote_exceeding_max_lines() {
+        let raw = "1,DEPOSIT,0,2,100,1,SUCCESS,\"a\nb\nc\"\n";
+        let options = ParseOptions {
+            csv_quotes: CsvQuotes::MultiLine { max_lines: 2 },
+            ..ParseOptions::default()
+        };
+
+        let result = YPBankCsvRecordParser::with_options(&options)
+            .from_read(&mut Cursor::new(raw.as_bytes()));
+        assert_eq!(
+            result,
+            Err(ParseError::UnterminatedQuote {
+                line: 1,
+                column: 29
+            })
+        );
+    }
+
     #[test]
     fn test_write_to_regular_case() {
         let record = YPBankRecord::new(
             1000000000000000,
             TransactionType::Deposit,
-            1,
+            0,
             9223372036854775807,
             100,
             1633036860000,
             TransactionStatus::Failure,
             "\"Record number 1\"".to_string(),
         );
-        let target_result = "1000000000000000,DEPOSIT,1,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n";
+        let target_result = "1000000000000000,DEPOSIT,0,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n";
 
         let mut writer = Cursor::new(Vec::new());
-        let result = YPBankCsvRecordParser::write_to(&record, &mut writer);
+        let result = YPBankCsvRecordParser::default().write_to(&record, &mut writer);
         assert!(result.is_ok(), "Writing should succeed");
 
         let written =
@@ -305,15 +891,17 @@ mod yp_bank_csv_record_tests {
 mod csv_parser_tests {
     use super::*;
     use crate::common::TransactionStatus;
+    use crate::intern::DescriptionPool;
+    use crate::options::ParseOptions;
 
     #[test]
     fn test_from_read() {
-        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1000000000000000,DEPOSIT,1,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n1000000000000001,TRANSFER,1,9223372036854775807,200,1633036860000,PENDING,\"Record number 2\"\n";
+        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1000000000000000,DEPOSIT,0,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n1000000000000001,TRANSFER,1,9223372036854775807,200,1633036860000,PENDING,\"Record number 2\"\n";
         let target_records: Vec<YPBankRecord> = vec![
             YPBankRecord::new(
                 1000000000000000,
                 TransactionType::Deposit,
-                1,
+                0,
                 9223372036854775807,
                 100,
                 1633036860000,
@@ -333,19 +921,150 @@ mod csv_parser_tests {
         ];
 
         let mut reader = std::io::Cursor::new(raw_data.as_bytes());
-        let records = CsvParser::from_read(&mut reader).expect("Should parse successfully");
+        let records = CsvParser::from_read(&mut reader, &ParseOptions::default())
+            .expect("Should parse successfully");
         assert_eq!(records.len(), 2);
 
         assert_eq!(records, target_records);
     }
 
+    #[test]
+    fn test_from_read_interned_descriptions() {
+        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1,WITHDRAWAL,1,0,100,1633036860000,SUCCESS,\"ATM WITHDRAWAL\"\n2,WITHDRAWAL,2,0,200,1633036860000,SUCCESS,\"ATM WITHDRAWAL\"\n";
+        let options = ParseOptions {
+            intern_descriptions: Some(DescriptionPool::new()),
+            ..ParseOptions::default()
+        };
+
+        let mut reader = std::io::Cursor::new(raw_data.as_bytes());
+        let records =
+            CsvParser::from_read(&mut reader, &options).expect("Should parse successfully");
+
+        assert!(std::sync::Arc::ptr_eq(
+            &records[0].description,
+            &records[1].description
+        ));
+        assert_eq!(options.intern_descriptions.map(|pool| pool.len()), Some(1));
+    }
+
+    #[test]
+    fn test_from_read_schema_map() {
+        let raw_data = "kind,id,debit_account,credit_account,AMOUNT,TIMESTAMP,DESCRIPTION,result\nTRANSFER,1,10,20,100,1633036860000,\"Rent\",OK\n";
+        let schema = SchemaMap::from_toml(
+            "[columns]\nid = \"TX_ID\"\nkind = \"TX_TYPE\"\ndebit_account = \"FROM_USER_ID\"\ncredit_account = \"TO_USER_ID\"\nresult = \"STATUS\"\n[values.STATUS]\nOK = \"SUCCESS\"\n",
+        )
+        .expect("Should parse schema");
+        let options = ParseOptions {
+            csv_schema: Some(schema),
+            ..ParseOptions::default()
+        };
+
+        let mut reader = std::io::Cursor::new(raw_data.as_bytes());
+        let records =
+            CsvParser::from_read(&mut reader, &options).expect("Should parse successfully");
+
+        assert_eq!(
+            records,
+            vec![YPBankRecord::new(
+                1,
+                TransactionType::Transfer,
+                10,
+                20,
+                100,
+                1633036860000,
+                TransactionStatus::Success,
+                "\"Rent\"",
+            )]
+        );
+    }
+
+    #[test]
+    fn test_from_read_unknown_columns() {
+        let raw_data = "TX_ID,TX_TYPE,audited_by,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,audit_ts\n1,DEPOSIT,jdoe,0,2,100,1633036860000,SUCCESS,\"Salary\",1633036900000\n";
+        let expected = YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "\"Salary\"",
+        );
+
+        let options = ParseOptions {
+            csv_schema: Some(SchemaMap::default()),
+            ..ParseOptions::default()
+        };
+        assert!(matches!(
+            CsvParser::from_read(&mut raw_data.as_bytes(), &options),
+            Err(ParseError::InvalidCsvHeader(_))
+        ));
+
+        let options = ParseOptions {
+            csv_schema: Some(SchemaMap::default().with_unknown_columns(UnknownColumns::Ignore)),
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            CsvParser::from_read(&mut raw_data.as_bytes(), &options),
+            Ok(vec![expected.clone()])
+        );
+
+        let options = ParseOptions {
+            csv_schema: Some(SchemaMap::default().with_unknown_columns(UnknownColumns::Capture)),
+            ..ParseOptions::default()
+        };
+        let mut records = CsvParser::iter_read(raw_data.as_bytes(), &options)
+            .expect("Should read header")
+            .with_extras();
+        let record = records
+            .next()
+            .expect("Should have a record")
+            .expect("Should parse successfully");
+        assert_eq!(record.core, expected);
+        assert_eq!(
+            record.extras,
+            BTreeMap::from([
+                ("audited_by".to_string(), "jdoe".to_string()),
+                ("audit_ts".to_string(), "1633036900000".to_string()),
+            ])
+        );
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_iter_read_at_offset() {
+        use crate::parser::RecordSource;
+
+        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1,WITHDRAWAL,1,0,100,1633036860000,SUCCESS,\"First\"\n2,WITHDRAWAL,2,0,200,1633036860000,SUCCESS,\"Second\"\n";
+        let options = ParseOptions::default();
+
+        let mut records =
+            CsvParser::iter_read(raw_data.as_bytes(), &options).expect("Should read header");
+        assert_eq!(records.offset(), TARGET_HEADER.len() as u64);
+        let first = records.next().expect("Should have a record");
+        let offset = records.offset();
+        assert_eq!(&raw_data[offset as usize..offset as usize + 2], "2,");
+
+        let rest =
+            CsvParser::iter_read_at(&raw_data.as_bytes()[offset as usize..], &options, offset)
+                .expect("Should not read header")
+                .collect::<Result<Vec<_>, _>>()
+                .expect("Should parse successfully");
+        assert_eq!(first.expect("Should parse").id, 1);
+        assert_eq!(
+            rest.iter().map(|record| record.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
     #[test]
     fn test_write_to() {
         let records = vec![
             YPBankRecord::new(
                 1000000000000000,
                 TransactionType::Deposit,
-                1,
+                0,
                 9223372036854775807,
                 100,
                 1633036860000,
@@ -363,11 +1082,347 @@ mod csv_parser_tests {
                 "\"Record number 2\"".to_string(),
             ),
         ];
-        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1000000000000000,DEPOSIT,1,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n1000000000000001,TRANSFER,1,9223372036854775807,200,1633036860000,PENDING,\"Record number 2\"\n";
+        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n1000000000000000,DEPOSIT,0,9223372036854775807,100,1633036860000,FAILURE,\"Record number 1\"\n1000000000000001,TRANSFER,1,9223372036854775807,200,1633036860000,PENDING,\"Record number 2\"\n";
 
         let mut writer = std::io::Cursor::new(Vec::new());
-        CsvParser::write_to(&mut writer, &records).expect("Should write successfully");
+        CsvParser::write_to(&mut writer, &records, &ParseOptions::default())
+            .expect("Should write successfully");
         let result = writer.into_inner();
         assert_eq!(result, raw_data.as_bytes());
     }
+
+    #[test]
+    fn test_write_to_invalid_user_id() {
+        let records = vec![YPBankRecord::new(
+            1,
+            TransactionType::Withdrawal,
+            2,
+            5,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "",
+        )];
+
+        let mut writer = Vec::new();
+        assert!(CsvParser::write_to(&mut writer, &records, &ParseOptions::default()).is_ok());
+
+        let options = ParseOptions {
+            validate_writes: true,
+            ..ParseOptions::default()
+        };
+        let mut writer = Vec::new();
+        assert_eq!(
+            CsvParser::write_to(&mut writer, &records, &options),
+            Err(ParseError::InvalidRecord {
+                index: 0,
+                reason: "Invalid user id 5 for transaction type WITHDRAWAL".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_read_empty_lines() {
+        let raw_data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                        1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n\
+                        \n\
+                        2,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n\
+                        3,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Rent\n\n";
+        let read = |empty_lines| {
+            let options = ParseOptions {
+                csv_empty_lines: empty_lines,
+                ..ParseOptions::default()
+            };
+            CsvParser::from_read(&mut raw_data.as_bytes(), &options)
+                .map(|records| records.iter().map(|record| record.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(read(CsvEmptyLines::Stop), Ok(vec![1]));
+        assert_eq!(
+            read(CsvEmptyLines::Error),
+            Err(ParseError::EmptyLine { line: 3 })
+        );
+        assert_eq!(
+            read(CsvEmptyLines::Skip),
+            Err(ParseError::UnterminatedQuote {
+                line: 5,
+                column: 41
+            })
+        );
+
+        let trailing = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                        1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n\n \n";
+        assert_eq!(
+            CsvParser::from_read(&mut trailing.as_bytes(), &ParseOptions::default())
+                .map(|records| records.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_from_read_repeated_headers() {
+        let header = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n";
+        let day = |id| {
+            format!(
+                "{}{},DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n",
+                header, id
+            )
+        };
+        let concatenated = format!("{}{}{}", day(1), day(2), day(3));
+        let read = |data: &str, repeated_headers| {
+            let options = ParseOptions {
+                csv_repeated_headers: repeated_headers,
+                ..ParseOptions::default()
+            };
+            CsvParser::from_read(&mut data.as_bytes(), &options)
+                .map(|records| records.iter().map(|record| record.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(
+            read(&concatenated, CsvRepeatedHeaders::Skip),
+            Ok(vec![1, 2, 3])
+        );
+        assert_eq!(
+            read(&concatenated, CsvRepeatedHeaders::Reject),
+            Err(ParseError::InvalidCsvHeader(header.to_string()))
+        );
+        let reordered = format!(
+            "{}TX_ID,TX_TYPE,TO_USER_ID,FROM_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n",
+            day(1)
+        );
+        assert!(matches!(
+            read(&reordered, CsvRepeatedHeaders::Skip),
+            Err(ParseError::InvalidCsvHeader(_))
+        ));
+
+        // Resumed reads skip headers without having read the first one.
+        let offset = day(1).len();
+        let resumed = CsvParser::iter_read_at(
+            &concatenated.as_bytes()[offset..],
+            &ParseOptions::default(),
+            offset as u64,
+        )
+        .expect("Should not read header")
+        .map(|record| record.map(|record| record.id))
+        .collect::<Result<Vec<_>, _>>();
+        assert_eq!(resumed, Ok(vec![2, 3]));
+
+        // Any number of consecutive headers is skipped without growing the stack.
+        let many = format!("{}{}", header.repeat(300_000), day(4));
+        assert_eq!(read(&many, CsvRepeatedHeaders::Skip), Ok(vec![4]));
+    }
+
+    #[test]
+    fn test_id_formats_round_trip() {
+        let records = vec![YPBankRecord::new(
+            1000000000000001,
+            TransactionType::Transfer,
+            1000000000000002,
+            1000000000000003,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "Rent",
+        )];
+
+        for (id_format, line) in [
+            (
+                CsvIdFormat::Plain,
+                "1000000000000001,TRANSFER,1000000000000002,1000000000000003,",
+            ),
+            (
+                CsvIdFormat::Quoted,
+                "\"1000000000000001\",TRANSFER,\"1000000000000002\",\"1000000000000003\",",
+            ),
+            (
+                CsvIdFormat::Formula,
+                "=\"1000000000000001\",TRANSFER,=\"1000000000000002\",=\"1000000000000003\",",
+            ),
+        ] {
+            let options = ParseOptions {
+                csv_id_format: id_format,
+                ..ParseOptions::default()
+            };
+            let mut data = Vec::new();
+            CsvParser::write_to(&mut data, &records, &options).expect("Should write records");
+            let data = String::from_utf8(data).expect("Should write UTF-8");
+            assert!(
+                data.lines().nth(1).unwrap().starts_with(line),
+                "{:?}",
+                id_format
+            );
+
+            assert_eq!(
+                CsvParser::from_read(&mut data.as_bytes(), &ParseOptions::default()),
+                Ok(records.clone()),
+                "{:?}",
+                id_format
+            );
+        }
+    }
+
+    #[test]
+    fn test_headers() {
+        let records = crate::fixtures::records()[..2].to_vec();
+        let write = |header: CsvHeader| {
+            let options = ParseOptions {
+                csv_write_header: header,
+                ..ParseOptions::default()
+            };
+            let mut data = Vec::new();
+            CsvParser::write_to(&mut data, &records, &options).expect("Should write records");
+            String::from_utf8(data).expect("Should write UTF-8")
+        };
+
+        let standard = write(CsvHeader::Standard);
+        let headerless = write(CsvHeader::None);
+        assert_eq!(
+            standard.strip_prefix(TARGET_HEADER),
+            Some(headerless.as_str())
+        );
+        assert_eq!(
+            write(CsvHeader::Custom(
+                "id,type,from,to,amount,ts,status,text".to_string()
+            )),
+            format!("id,type,from,to,amount,ts,status,text\n{}", headerless)
+        );
+
+        let options = ParseOptions {
+            csv_has_header: false,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            CsvParser::from_read(&mut headerless.as_bytes(), &options),
+            Ok(records.clone())
+        );
+        assert!(CsvParser::from_read(&mut standard.as_bytes(), &options).is_err());
+
+        let schema = SchemaMap::from_toml("[values.TX_TYPE]\nPAYMENT = \"TRANSFER\"\n")
+            .expect("Should parse schema map");
+        let options = ParseOptions {
+            csv_schema: Some(schema),
+            csv_has_header: false,
+            ..ParseOptions::default()
+        };
+        let foreign = headerless.replace("TRANSFER", "PAYMENT");
+        assert_eq!(
+            CsvParser::from_read(&mut foreign.as_bytes(), &options),
+            Ok(records)
+        );
+    }
+
+    #[test]
+    fn test_line_limits() {
+        let header = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n";
+        let record = |id: u64, description: &str| {
+            format!("{id},DEPOSIT,0,2,100,1633036860000,SUCCESS,{description}\n")
+        };
+        let raw_data = format!(
+            "{}{}{}{}{}",
+            header,
+            record(1, "Salary"),
+            record(2, &"x".repeat(200)),
+            record(3, "a,b,c,d,e,f,g,h,i"),
+            record(4, "Salary")
+        );
+        let options = ParseOptions {
+            csv_limits: CsvLimits {
+                max_fields: 12,
+                max_line_len: 100,
+            },
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+
+        let ids: Vec<_> = CsvParser::iter_read(raw_data.as_bytes(), &options)
+            .expect("Should start reading")
+            .map(|record| record.map(|record| record.id))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                Ok(1),
+                Err(ParseError::LineTooLong { line: 3, max: 100 }),
+                Err(ParseError::TooManyFields { line: 4, max: 12 }),
+                Ok(4)
+            ]
+        );
+
+        let last_line = format!("{}{}", header, record(1, &"x".repeat(60)).trim_end());
+        assert_eq!(last_line.len() - header.len(), 100);
+        assert_eq!(
+            CsvParser::from_read(&mut last_line.as_bytes(), &options).map(|records| records.len()),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_long_lines_are_split() {
+        let line = vec!["\"a,b\""; 100_000].join(",");
+        let fields = Separator::new(&line).collect::<Vec<_>>();
+        assert_eq!(fields.len(), 100_000);
+        assert!(fields.iter().all(|field| *field == "\"a,b\""));
+    }
+
+    #[test]
+    fn test_write_ext_to_extra_columns() {
+        let core = YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "\"Salary\"",
+        );
+        let mut first = YPBankRecordExt::from(core.clone());
+        first
+            .extras
+            .insert("audited_by".to_string(), "jdoe".to_string());
+        let mut second = YPBankRecordExt::from(core);
+        second
+            .extras
+            .insert("audit_ts".to_string(), "1633036900000".to_string());
+        let options = ParseOptions {
+            preserve_extras: true,
+            ..ParseOptions::default()
+        };
+
+        let mut data = Vec::new();
+        CsvParser::write_ext_to(&mut data, &[first.clone(), second.clone()], &options)
+            .expect("Should write successfully");
+        assert_eq!(
+            String::from_utf8(data.clone()).unwrap(),
+            "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,audit_ts,audited_by\n\
+             1,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Salary\",,jdoe\n\
+             1,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Salary\",1633036900000,\n"
+        );
+
+        let options = ParseOptions {
+            csv_schema: Some(SchemaMap::default().with_unknown_columns(UnknownColumns::Capture)),
+            ..ParseOptions::default()
+        };
+        let read = CsvParser::iter_read(&data[..], &options)
+            .expect("Should read header")
+            .with_extras()
+            .map(|record| record.map(|record| record.extras))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Should read successfully");
+        assert_eq!(read[0]["audited_by"], "jdoe");
+        assert_eq!(read[1]["audit_ts"], "1633036900000");
+
+        first.extras.insert("note".to_string(), "a,b".to_string());
+        assert!(matches!(
+            CsvParser::write_ext_to(
+                &mut Vec::new(),
+                &[first],
+                &ParseOptions {
+                    preserve_extras: true,
+                    ..ParseOptions::default()
+                }
+            ),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+    }
 }
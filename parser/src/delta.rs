@@ -0,0 +1,483 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::options::ParseOptions;
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use crate::schema::{SchemaMap, UnknownColumns};
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+const DELTA_MAGIC: &str = "YPBANK-DELTA";
+/// Extra holding the op of a record.
+const OP: &str = "OP";
+/// Extra holding the position of a record in the new version.
+const POSITION: &str = "POSITION";
+
+/// The operation recorded for one record of a [`Delta`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeltaOp {
+    Added,
+    Removed,
+    Changed,
+    /// The record is unchanged but has a different position relative to the others.
+    Moved,
+}
+
+impl DeltaOp {
+    pub fn as_char(&self) -> char {
+        match self {
+            DeltaOp::Added => '+',
+            DeltaOp::Removed => '-',
+            DeltaOp::Changed => '~',
+            DeltaOp::Moved => '>',
+        }
+    }
+
+    pub fn from_char(ch: char) -> Result<Self, ParseError> {
+        match ch {
+            '+' => Ok(DeltaOp::Added),
+            '-' => Ok(DeltaOp::Removed),
+            '~' => Ok(DeltaOp::Changed),
+            '>' => Ok(DeltaOp::Moved),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "delta op".to_string(),
+                value: ch.to_string(),
+            }),
+        }
+    }
+}
+
+/// Added, removed, changed and moved records between two versions of a file.
+///
+/// Records are matched by transaction id. A delta file is a `YPBANK-DELTA` line followed
+/// by the records in CSV, TXT or binary format, each with its op (`+`, `-`, `~` or `>`)
+/// in an `OP` extra and, unless removed, its position in the new version in a
+/// `POSITION` extra: extra CSV columns, TXT keys or binary extension entries. Removed
+/// records are stored with their old values, the others with their new ones.
+///
+/// Unchanged records in the same order in both versions are not stored, so applying
+/// the delta to the old version gives back the new one exactly.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Delta, TransactionStatus, TransactionType, YPBankRecord};
+///
+/// let record = |id, amount| {
+///     YPBankRecord::new(id, TransactionType::Deposit, 0, 2, amount, 3, TransactionStatus::Success, String::new())
+/// };
+///
+/// let old = vec![record(1, 100), record(2, 100)];
+/// let new = vec![record(3, 100), record(1, 200), record(2, 100)];
+/// let delta = Delta::between(old.clone(), new.clone()).unwrap();
+/// assert_eq!(delta.len(), 2);
+/// assert_eq!(delta.apply(old).unwrap(), new);
+/// ```
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+    ops: Vec<DeltaOp>,
+    records: Vec<YPBankRecord>,
+    /// Position in the new version of every record but removed ones.
+    positions: Vec<Option<usize>>,
+}
+
+impl Delta {
+    /// Computes the delta turning `old` into `new`.
+    ///
+    /// Removals come first in the order of `old`, followed by additions, changes and
+    /// moves in the order of `new`. The fewest unchanged records are moved.
+    pub fn between(old: Vec<YPBankRecord>, new: Vec<YPBankRecord>) -> Result<Self, ParseError> {
+        let old_index = index_by_id(old.iter())?;
+        let new_index = index_by_id(new.iter())?;
+
+        let mut delta = Self::default();
+        let mut unchanged = Vec::new();
+        for record in &old {
+            match new_index.get(&record.id) {
+                None => delta.push(DeltaOp::Removed, record.clone(), None),
+                Some(position) if new[*position] == *record => unchanged.push(*position),
+                Some(_) => {}
+            }
+        }
+        let in_place = longest_increasing(&unchanged);
+
+        for (position, record) in new.into_iter().enumerate() {
+            let op = match old_index.get(&record.id) {
+                None => DeltaOp::Added,
+                Some(index) if old[*index] != record => DeltaOp::Changed,
+                Some(_) if !in_place.contains(&position) => DeltaOp::Moved,
+                Some(_) => continue,
+            };
+            delta.push(op, record, Some(position));
+        }
+
+        Ok(delta)
+    }
+
+    /// Applies the delta to `old`, returning the new version of the records.
+    ///
+    /// Added, changed and moved records are put at their positions; the other surviving
+    /// records fill the remaining ones in their old order.
+    pub fn apply(self, old: Vec<YPBankRecord>) -> Result<Vec<YPBankRecord>, ParseError> {
+        let mut records = old.into_iter().map(Some).collect::<Vec<_>>();
+        let index = index_by_id(records.iter().flatten())?;
+
+        let mut placed = Vec::new();
+        for ((op, record), position) in self.ops.into_iter().zip(self.records).zip(self.positions) {
+            let slot = index
+                .get(&record.id)
+                .map(|position| &mut records[*position]);
+            match (op, slot) {
+                (DeltaOp::Removed, Some(slot @ Some(_))) => {
+                    *slot = None;
+                    continue;
+                }
+                (DeltaOp::Changed | DeltaOp::Moved, Some(slot @ Some(_))) => *slot = None,
+                (DeltaOp::Added, None | Some(None)) => {}
+                (DeltaOp::Added, _) => {
+                    return Err(ParseError::InconsistentRecord(format!(
+                        "added record {} already exists",
+                        record.id
+                    )));
+                }
+                (_, _) => {
+                    return Err(ParseError::InconsistentRecord(format!(
+                        "record {} is missing",
+                        record.id
+                    )));
+                }
+            }
+            let position = position.ok_or_else(|| {
+                ParseError::InconsistentRecord(format!("record {} has no position", record.id))
+            })?;
+            placed.push((position, record));
+        }
+
+        let survivors = records.into_iter().flatten().collect::<Vec<_>>();
+        let mut new = (0..placed.len() + survivors.len())
+            .map(|_| None)
+            .collect::<Vec<_>>();
+        for (position, record) in placed {
+            match new.get_mut(position) {
+                Some(slot @ None) => *slot = Some(record),
+                _ => {
+                    return Err(ParseError::InconsistentRecord(format!(
+                        "position {} of record {} is taken or out of range",
+                        position, record.id
+                    )));
+                }
+            }
+        }
+        let mut survivors = survivors.into_iter();
+        Ok(new
+            .into_iter()
+            .map(|slot| slot.or_else(|| survivors.next()))
+            .collect::<Option<_>>()
+            .expect("free positions match the survivors"))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Iterates over the recorded operations with their records.
+    pub fn entries(&self) -> impl Iterator<Item = (DeltaOp, &YPBankRecord)> {
+        self.ops.iter().copied().zip(self.records.iter())
+    }
+
+    /// Reads a delta whose records are in the format of `parser`.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_read<R: std::io::Read>(
+        r: &mut R,
+        parser: &CommonParser,
+    ) -> Result<Self, ParseError> {
+        let parser = with_op_column(parser)?;
+        let mut buf_reader = std::io::BufReader::new(r);
+
+        let mut line = String::new();
+        buf_reader.read_line(&mut line)?;
+        if line.trim_end_matches(['\r', '\n']) != DELTA_MAGIC {
+            return Err(ParseError::InvalidMagic(line.trim_end().to_string()));
+        }
+
+        let mut delta = Self::default();
+        for record in parser.iter_read_extended(buf_reader)? {
+            let YPBankRecordExt { core, mut extras } = record?;
+            let op = extras.remove(OP).unwrap_or_default();
+            let op = match op.chars().collect::<Vec<_>>()[..] {
+                [ch] => DeltaOp::from_char(ch)?,
+                _ => {
+                    return Err(ParseError::InvalidRawValue {
+                        field: OP.to_string(),
+                        value: op,
+                    });
+                }
+            };
+            // CSV rows of removed records have an empty position.
+            let position = extras
+                .remove(POSITION)
+                .filter(|position| !position.is_empty())
+                .map(|position| {
+                    position.parse().map_err(|_| ParseError::InvalidRawValue {
+                        field: POSITION.to_string(),
+                        value: position,
+                    })
+                })
+                .transpose()?;
+            delta.push(op, core, position);
+        }
+
+        Ok(delta)
+    }
+
+    /// Writes the delta with its records in the format of `parser`, which must be CSV,
+    /// TXT or binary.
+    pub fn write_to<W: std::io::Write>(
+        &self,
+        w: &mut W,
+        parser: &CommonParser,
+    ) -> Result<(), ParseError> {
+        let parser = with_op_column(parser)?;
+        let records = self
+            .entries()
+            .zip(&self.positions)
+            .map(|((op, record), position)| {
+                let mut record = YPBankRecordExt::from(record.clone());
+                record
+                    .extras
+                    .insert(OP.to_string(), op.as_char().to_string());
+                if let Some(position) = position {
+                    record
+                        .extras
+                        .insert(POSITION.to_string(), position.to_string());
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+        writeln!(w, "{}", DELTA_MAGIC)?;
+        parser.write_ext_to(w, &records)
+    }
+
+    fn push(&mut self, op: DeltaOp, record: YPBankRecord, position: Option<usize>) {
+        self.ops.push(op);
+        self.records.push(record);
+        self.positions.push(position);
+    }
+}
+
+/// Returns `parser` writing and reading the op and position of every record as extras.
+fn with_op_column(parser: &CommonParser) -> Result<CommonParser, ParseError> {
+    if !matches!(parser.format(), Format::Csv | Format::Txt | Format::Bin) {
+        return Err(ParseError::UnsupportedOperation(format!(
+            "deltas cannot be stored in {}, which has no place for the op of a record",
+            parser.format().as_str()
+        )));
+    }
+    let options = ParseOptions {
+        preserve_extras: true,
+        csv_schema: Some(SchemaMap::default().with_unknown_columns(UnknownColumns::Capture)),
+        ..parser.options().clone()
+    };
+    Ok(CommonParser::with_options(parser.format(), options))
+}
+
+/// Returns the values of a longest strictly increasing subsequence of `values`.
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // Index of the smallest last value of an increasing subsequence of each length.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+    for (index, value) in values.iter().enumerate() {
+        let length = tails.partition_point(|tail| values[*tail] < *value);
+        previous[index] = length.checked_sub(1).map(|length| tails[length]);
+        match tails.get_mut(length) {
+            Some(tail) => *tail = index,
+            None => tails.push(index),
+        }
+    }
+
+    let mut kept = HashSet::new();
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        kept.insert(values[index]);
+        next = previous[index];
+    }
+    kept
+}
+
+pub(crate) fn index_by_id<'a>(
+    records: impl Iterator<Item = &'a YPBankRecord>,
+) -> Result<HashMap<u64, usize>, ParseError> {
+    let mut index = HashMap::new();
+    for (position, record) in records.enumerate() {
+        if index.insert(record.id, position).is_some() {
+            return Err(ParseError::InconsistentRecord(format!(
+                "duplicate transaction id {}",
+                record.id
+            )));
+        }
+    }
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod delta_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use std::io::Cursor;
+
+    fn record(id: u64, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Transfer,
+            1,
+            2,
+            amount,
+            1633036860000 + id,
+            TransactionStatus::Success,
+            format!("Record {}", id),
+        )
+    }
+
+    #[test]
+    fn test_between_and_apply() {
+        let old = vec![record(1, 10), record(2, 20), record(3, 30)];
+        let new = vec![record(1, 10), record(3, 31), record(4, 40)];
+
+        let delta = Delta::between(old, new).expect("Should compute delta");
+        let entries = delta
+            .entries()
+            .map(|(op, record)| (op, record.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (DeltaOp::Removed, 2),
+                (DeltaOp::Changed, 3),
+                (DeltaOp::Added, 4)
+            ]
+        );
+
+        let applied = delta
+            .apply(vec![record(1, 10), record(2, 20), record(3, 30)])
+            .expect("Should apply delta");
+        assert_eq!(applied, vec![record(1, 10), record(3, 31), record(4, 40)]);
+    }
+
+    #[test]
+    fn test_apply_keeps_positions() {
+        let old = (1..=6).map(|id| record(id, 10)).collect::<Vec<_>>();
+        let new = vec![
+            record(7, 70),
+            record(6, 10),
+            record(1, 10),
+            record(3, 30),
+            record(2, 10),
+            record(5, 10),
+        ];
+
+        let delta = Delta::between(old.clone(), new.clone()).expect("Should compute delta");
+        let entries = delta
+            .entries()
+            .map(|(op, record)| (op, record.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            vec![
+                (DeltaOp::Removed, 4),
+                (DeltaOp::Added, 7),
+                (DeltaOp::Moved, 6),
+                (DeltaOp::Changed, 3)
+            ]
+        );
+        assert_eq!(delta.apply(old.clone()).expect("Should apply delta"), new);
+
+        let reversed = old.iter().rev().cloned().collect::<Vec<_>>();
+        let delta = Delta::between(old.clone(), reversed.clone()).expect("Should compute delta");
+        assert_eq!(delta.len(), 5);
+        assert_eq!(delta.apply(old).expect("Should apply delta"), reversed);
+    }
+
+    #[test]
+    fn test_apply_invalid_position() {
+        let mut delta =
+            Delta::between(vec![record(1, 10)], vec![record(2, 20)]).expect("Should compute");
+        delta.positions[1] = Some(5);
+        assert_eq!(
+            delta.apply(vec![record(1, 10)]),
+            Err(ParseError::InconsistentRecord(
+                "position 5 of record 2 is taken or out of range".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_apply_missing_record() {
+        let delta =
+            Delta::between(vec![record(1, 10)], vec![record(1, 11)]).expect("Should compute");
+
+        let result = delta.apply(vec![record(2, 20)]);
+        assert_eq!(
+            result,
+            Err(ParseError::InconsistentRecord(
+                "record 1 is missing".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_between_duplicate_ids() {
+        let result = Delta::between(vec![record(1, 10), record(1, 11)], vec![]);
+        assert!(matches!(result, Err(ParseError::InconsistentRecord(_))));
+    }
+
+    #[test]
+    fn test_write_and_read_round_trip() {
+        let delta = Delta::between(
+            vec![record(1, 10), record(2, 20)],
+            vec![record(2, 21), record(3, 30)],
+        )
+        .expect("Should compute delta");
+
+        for format in [Format::Csv, Format::Txt, Format::Bin] {
+            let parser = CommonParser::new(format);
+            let mut written = Vec::new();
+            delta
+                .write_to(&mut written, &parser)
+                .expect("Should write delta");
+            assert!(written.starts_with(b"YPBANK-DELTA\n"));
+
+            let read =
+                Delta::from_read(&mut Cursor::new(written), &parser).expect("Should read delta");
+            assert_eq!(read, delta, "Round trip through {}", format.as_str());
+        }
+
+        let mut written = Vec::new();
+        delta
+            .write_to(&mut written, &CommonParser::new(Format::Csv))
+            .expect("Should write delta");
+        assert_eq!(
+            String::from_utf8(written).expect("Should be UTF-8"),
+            "YPBANK-DELTA\n\
+             TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,OP,POSITION\n\
+             1,TRANSFER,1,2,10,1633036860001,SUCCESS,Record 1,-,\n\
+             2,TRANSFER,1,2,21,1633036860002,SUCCESS,Record 2,~,0\n\
+             3,TRANSFER,1,2,30,1633036860003,SUCCESS,Record 3,+,1\n"
+        );
+        assert!(matches!(
+            delta.write_to(&mut Vec::new(), &CommonParser::new(Format::Toml)),
+            Err(ParseError::UnsupportedOperation(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_read_invalid_magic() {
+        let parser = CommonParser::new(Format::Csv);
+        let result = Delta::from_read(&mut Cursor::new(b"TX_ID\n".to_vec()), &parser);
+        assert_eq!(result, Err(ParseError::InvalidMagic("TX_ID".to_string())));
+    }
+}
@@ -0,0 +1,282 @@
+use std::io::Read;
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 digest, used to name and verify outputs by their content.
+///
+/// # Examples
+///
+/// ```
+/// use parser::Sha256;
+///
+/// let mut digest = Sha256::new();
+/// digest.update(b"ab");
+/// digest.update(b"c");
+/// assert_eq!(
+///     digest.finish_hex(),
+///     "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    total_len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            block: [0; 64],
+            block_len: 0,
+            total_len: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.total_len += data.len() as u64;
+        while !data.is_empty() {
+            let len = data.len().min(64 - self.block_len);
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&data[..len]);
+            self.block_len += len;
+            data = &data[len..];
+
+            if self.block_len == 64 {
+                self.compress();
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Feeds everything left in `r` into the digest.
+    pub fn update_from_read<R: Read>(&mut self, r: &mut R) -> std::io::Result<()> {
+        let mut buf = [0; 8192];
+        loop {
+            match r.read(&mut buf)? {
+                0 => return Ok(()),
+                len => self.update(&buf[..len]),
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        let bit_len = self.total_len.wrapping_mul(8);
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bit_len.to_be_bytes());
+
+        let mut digest = [0; 32];
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+
+    /// Returns the digest as lowercase hex.
+    pub fn finish_hex(self) -> String {
+        to_hex(&self.finish())
+    }
+
+    /// Returns the HMAC-SHA256 of `message` under `key` as lowercase hex, for signing
+    /// reports with a key shared with whoever checks them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::Sha256;
+    ///
+    /// assert_eq!(
+    ///     Sha256::hmac_hex(b"Jefe", b"what do ya want for nothing?"),
+    ///     "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    /// );
+    /// ```
+    pub fn hmac_hex(key: &[u8], message: &[u8]) -> String {
+        to_hex(&Self::hmac(key, message))
+    }
+
+    /// Returns whether `signature` is the hex HMAC-SHA256 of `message` under `key`,
+    /// comparing the MACs in constant time.
+    pub(crate) fn verify_hmac_hex(key: &[u8], message: &[u8], signature: &str) -> bool {
+        let Some(signature) = from_hex::<32>(signature) else {
+            return false;
+        };
+        let expected = Self::hmac(key, message);
+        signature
+            .iter()
+            .zip(expected)
+            .fold(0, |diff, (left, right)| diff | (left ^ right))
+            == 0
+    }
+
+    fn hmac(key: &[u8], message: &[u8]) -> [u8; 32] {
+        let mut block = [0u8; 64];
+        if key.len() > block.len() {
+            let mut digest = Sha256::new();
+            digest.update(key);
+            block[..32].copy_from_slice(&digest.finish());
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&block.map(|byte| byte ^ 0x36));
+        inner.update(message);
+        let mut outer = Sha256::new();
+        outer.update(&block.map(|byte| byte ^ 0x5c));
+        outer.update(&inner.finish());
+        outer.finish()
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (word, chunk) in w.iter_mut().zip(self.block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(chunk.try_into().expect("4 bytes"));
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let digit = |byte: u8| char::from(byte).to_digit(16);
+    let mut bytes = [0; N];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = (digit(pair[0])? * 16 + digit(pair[1])?) as u8;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod sha256_tests {
+    use super::*;
+
+    fn hex(data: &[u8]) -> String {
+        let mut digest = Sha256::new();
+        digest.update(data);
+        digest.finish_hex()
+    }
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(
+            hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(&[b'a'; 1000]),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn test_update_from_read() {
+        let data = vec![7u8; 20_000];
+        let mut digest = Sha256::new();
+        digest
+            .update_from_read(&mut data.as_slice())
+            .expect("Should read");
+        assert_eq!(digest.finish_hex(), hex(&data));
+    }
+
+    #[test]
+    fn test_hmac_long_key() {
+        // RFC 4231, test case 6.
+        assert_eq!(
+            Sha256::hmac_hex(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_verify_hmac_hex() {
+        let signature = Sha256::hmac_hex(b"key", b"message");
+
+        assert!(Sha256::verify_hmac_hex(b"key", b"message", &signature));
+        assert!(!Sha256::verify_hmac_hex(b"key", b"other", &signature));
+        assert!(!Sha256::verify_hmac_hex(
+            b"key",
+            b"message",
+            &signature[2..]
+        ));
+        assert!(!Sha256::verify_hmac_hex(
+            b"key",
+            b"message",
+            &"zz".repeat(32)
+        ));
+        assert!(!Sha256::verify_hmac_hex(
+            b"key",
+            b"message",
+            &"é".repeat(32)
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::options::ParseOptions;
+use crate::record::YPBankRecord;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// Records read by a [`DynRecordParser`]; the iterator ends after the first error.
+pub type DynRecords<'a> = Box<dyn Iterator<Item = Result<YPBankRecord, ParseError>> + 'a>;
+
+/// A record format usable as a trait object, so that parsers can be kept in
+/// collections and chosen at runtime, including formats outside of [`Format`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CommonParser, DynRecordParser, Format};
+///
+/// let parsers: Vec<Box<dyn DynRecordParser>> = vec![
+///     Box::new(CommonParser::new(Format::Csv)),
+///     Box::new(CommonParser::new(Format::Bin)),
+/// ];
+/// for parser in &parsers {
+///     let mut data = Vec::new();
+///     parser.write_records(&mut data, &[]).unwrap();
+///     assert_eq!(parser.read_records(Box::new(data.as_slice())).unwrap().count(), 0);
+/// }
+/// ```
+pub trait DynRecordParser: Send + Sync {
+    /// Name the parser is registered under, e.g. `"csv"`.
+    fn name(&self) -> &str;
+
+    fn read_records<'a>(&self, r: Box<dyn Read + 'a>) -> Result<DynRecords<'a>, ParseError>;
+
+    fn write_records(&self, w: &mut dyn Write, records: &[YPBankRecord]) -> Result<(), ParseError>;
+}
+
+impl DynRecordParser for CommonParser {
+    fn name(&self) -> &str {
+        self.format().as_str()
+    }
+
+    fn read_records<'a>(&self, r: Box<dyn Read + 'a>) -> Result<DynRecords<'a>, ParseError> {
+        Ok(Box::new(self.iter_read(r)?))
+    }
+
+    fn write_records(
+        &self,
+        mut w: &mut dyn Write,
+        records: &[YPBankRecord],
+    ) -> Result<(), ParseError> {
+        self.write_to(&mut w, &records.to_vec())
+    }
+}
+
+/// Parsers looked up by name, e.g. from a configuration file.
+///
+/// Names are case-insensitive. [`ParserRegistry::new`] holds the built-in formats
+/// under their [`Format::as_str`] names; registering a name again replaces its parser.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CommonParser, Format, ParserRegistry};
+///
+/// let mut registry = ParserRegistry::new();
+/// registry.register("legacy", Box::new(CommonParser::new(Format::Txt)));
+///
+/// assert!(registry.get("CSV").is_some());
+/// assert_eq!(registry.get("legacy").unwrap().name(), "txt");
+/// ```
+pub struct ParserRegistry {
+    parsers: BTreeMap<String, Box<dyn DynRecordParser>>,
+}
+
+impl ParserRegistry {
+    pub fn new() -> Self {
+        Self::with_options(&ParseOptions::default())
+    }
+
+    /// Creates a registry whose built-in formats read records with `options`.
+    pub fn with_options(options: &ParseOptions) -> Self {
+        let formats = [
+            Format::Csv,
+            Format::Txt,
+            Format::Bin,
+            Format::Toml,
+            Format::Html,
+            Format::PgCopy,
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx,
+        ];
+
+        let mut registry = Self {
+            parsers: BTreeMap::new(),
+        };
+        for format in formats {
+            registry.register(
+                format.as_str(),
+                Box::new(CommonParser::with_options(format, options.clone())),
+            );
+        }
+        registry
+    }
+
+    pub fn register(&mut self, name: &str, parser: Box<dyn DynRecordParser>) {
+        self.parsers.insert(name.to_lowercase(), parser);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn DynRecordParser> {
+        self.parsers.get(&name.to_lowercase()).map(Box::as_ref)
+    }
+
+    /// Returns the registered names in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.parsers.keys().map(String::as_str)
+    }
+}
+
+impl Default for ParserRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod dyn_parser_tests {
+    use super::*;
+    use crate::testdata;
+
+    /// A format outside of [`Format`]: one record id per line.
+    struct IdLines;
+
+    impl DynRecordParser for IdLines {
+        fn name(&self) -> &str {
+            "ids"
+        }
+
+        fn read_records<'a>(
+            &self,
+            mut r: Box<dyn Read + 'a>,
+        ) -> Result<DynRecords<'a>, ParseError> {
+            let mut data = String::new();
+            r.read_to_string(&mut data)?;
+            let records: Vec<_> = data
+                .lines()
+                .map(|id| {
+                    let mut record = testdata::records()[0].clone();
+                    record.id = id.parse().map_err(|_| ParseError::InvalidRawValue {
+                        field: "TX_ID".to_string(),
+                        value: id.to_string(),
+                    })?;
+                    Ok(record)
+                })
+                .collect();
+            Ok(Box::new(records.into_iter()))
+        }
+
+        fn write_records(
+            &self,
+            w: &mut dyn Write,
+            records: &[YPBankRecord],
+        ) -> Result<(), ParseError> {
+            for record in records {
+                writeln!(w, "{}", record.id)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_registry_round_trip() {
+        let mut registry = ParserRegistry::new();
+        registry.register("IDS", Box::new(IdLines));
+        let records = testdata::records();
+
+        for name in ["csv", "txt", "binary", "toml", "ids"] {
+            let parser = registry.get(name).expect("Should be registered");
+            let mut data = Vec::new();
+            parser
+                .write_records(&mut data, &records)
+                .expect("Should write records");
+            let read = parser
+                .read_records(Box::new(data.as_slice()))
+                .expect("Should read records")
+                .collect::<Result<Vec<_>, _>>()
+                .expect("Should parse records");
+
+            let ids: Vec<_> = read.iter().map(|record| record.id).collect();
+            let expected: Vec<_> = records.iter().map(|record| record.id).collect();
+            assert_eq!(ids, expected, "{}", name);
+        }
+        assert!(registry.names().any(|name| name == "ids"));
+        assert!(registry.get("json").is_none());
+    }
+
+    #[test]
+    fn test_write_only_format() {
+        let registry = ParserRegistry::new();
+        let html = registry.get("html").expect("Should be registered");
+        assert!(matches!(
+            html.read_records(Box::new(&b""[..])),
+            Err(ParseError::UnsupportedOperation(_))
+        ));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::digest::Sha256;
+use crate::error::ParseError;
+use crate::pipeline::REDACTED_USER_ID;
+use crate::record::YPBankRecord;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+const REPORT_HEADER: &str = "YPBank erasure report 1";
+const SIGNATURE_PREFIX: &str = "hmac-sha256 ";
+
+/// What happens to the records of an erased user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErasureMode {
+    /// Drops the records.
+    Remove,
+    /// Replaces the user id with [`REDACTED_USER_ID`] and clears the description,
+    /// keeping amounts and timestamps for totals.
+    #[default]
+    Anonymize,
+}
+
+impl ErasureMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErasureMode::Remove => "remove",
+            ErasureMode::Anonymize => "anonymize",
+        }
+    }
+}
+
+impl std::str::FromStr for ErasureMode {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "remove" => Ok(ErasureMode::Remove),
+            "anonymize" => Ok(ErasureMode::Anonymize),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "erasure mode".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Erasure of every record of one user, as either the sender or the recipient.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ErasureMode, REDACTED_USER_ID, TransactionStatus, TransactionType, UserErasure, YPBankRecord};
+///
+/// let erasure = UserErasure::new(7, ErasureMode::Anonymize).unwrap();
+/// let record = YPBankRecord::new(1, TransactionType::Transfer, 7, 8, 100, 10, TransactionStatus::Success, "Rent, flat 3");
+///
+/// let erased = erasure.apply(record).unwrap();
+/// assert_eq!((erased.from_user_id, erased.to_user_id), (REDACTED_USER_ID, 8));
+/// assert_eq!(&*erased.description, "");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UserErasure {
+    user_id: u64,
+    mode: ErasureMode,
+}
+
+impl UserErasure {
+    /// Fails for user 0, the bank side of deposits and withdrawals, and for
+    /// [`REDACTED_USER_ID`].
+    pub fn new(user_id: u64, mode: ErasureMode) -> Result<Self, ParseError> {
+        if user_id == 0 || user_id == REDACTED_USER_ID {
+            return Err(ParseError::ValueOutOfRange {
+                field: "user id".to_string(),
+                value: user_id.to_string(),
+            });
+        }
+        Ok(Self { user_id, mode })
+    }
+
+    pub fn user_id(&self) -> u64 {
+        self.user_id
+    }
+
+    pub fn mode(&self) -> ErasureMode {
+        self.mode
+    }
+
+    /// Returns whether `record` is from or to the user.
+    pub fn touches(&self, record: &YPBankRecord) -> bool {
+        record.from_user_id == self.user_id || record.to_user_id == self.user_id
+    }
+
+    /// Returns `record` erased, or `None` when it is removed. Records of other users
+    /// are returned as they are.
+    pub fn apply(&self, mut record: YPBankRecord) -> Option<YPBankRecord> {
+        if !self.touches(&record) {
+            return Some(record);
+        }
+        match self.mode {
+            ErasureMode::Remove => None,
+            ErasureMode::Anonymize => {
+                if record.from_user_id == self.user_id {
+                    record.from_user_id = REDACTED_USER_ID;
+                }
+                if record.to_user_id == self.user_id {
+                    record.to_user_id = REDACTED_USER_ID;
+                }
+                record.description = "".into();
+                Some(record)
+            }
+        }
+    }
+}
+
+/// The records changed by a [`UserErasure`], written signed with a key so that the
+/// report can be shown to have been produced by whoever holds the key.
+///
+/// The report lists transaction ids and where they were, never the erased values:
+///
+/// ```text
+/// YPBank erasure report 1
+/// user 7
+/// mode anonymize
+/// 1000000000000001 archive/2021-10.bin
+/// hmac-sha256 <HMAC-SHA256 of the lines above>
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ErasureMode, ErasureReport, Format, UserErasure, erase_user_in_file};
+/// # parser::fixtures::enter().unwrap();
+///
+/// let erasure = UserErasure::new(2, ErasureMode::Remove).unwrap();
+/// let mut report = ErasureReport::new(&erasure);
+/// erase_user_in_file("records.csv", Format::Csv, &erasure, &mut report).unwrap();
+///
+/// let mut signed = Vec::new();
+/// report.write_signed(&mut signed, b"secret").unwrap();
+/// let verified = ErasureReport::verify(std::str::from_utf8(&signed).unwrap(), b"secret").unwrap();
+/// assert_eq!(verified, report);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErasureReport {
+    user_id: u64,
+    mode: ErasureMode,
+    records: Vec<(u64, String)>,
+}
+
+impl ErasureReport {
+    pub fn new(erasure: &UserErasure) -> Self {
+        Self {
+            user_id: erasure.user_id,
+            mode: erasure.mode,
+            records: vec![],
+        }
+    }
+
+    /// Returns the ids of the affected records with the file or store they were in, in
+    /// the order they were erased.
+    pub fn records(&self) -> &[(u64, String)] {
+        &self.records
+    }
+
+    pub(crate) fn add(&mut self, source: &Path, ids: impl IntoIterator<Item = u64>) {
+        let source = source.display().to_string();
+        self.records
+            .extend(ids.into_iter().map(|id| (id, source.clone())));
+    }
+
+    /// Writes the report followed by its signature under `key`.
+    pub fn write_signed<W: Write>(&self, w: &mut W, key: &[u8]) -> Result<(), ParseError> {
+        let body = self.body();
+        w.write_all(body.as_bytes())?;
+        writeln!(
+            w,
+            "{}{}",
+            SIGNATURE_PREFIX,
+            Sha256::hmac_hex(key, body.as_bytes())
+        )?;
+        Ok(())
+    }
+
+    /// Reads a report written by [`ErasureReport::write_signed`], failing with
+    /// [`ParseError::InconsistentRecord`] unless it was signed with `key` and left
+    /// unchanged since.
+    pub fn verify(text: &str, key: &[u8]) -> Result<Self, ParseError> {
+        let invalid = |reason: &str| ParseError::InconsistentRecord(reason.to_string());
+        let body_len = text
+            .trim_end_matches('\n')
+            .rfind('\n')
+            .map_or(0, |position| position + 1);
+        let (body, signature) = text.split_at(body_len);
+        let signature = signature
+            .trim_end()
+            .strip_prefix(SIGNATURE_PREFIX)
+            .ok_or_else(|| invalid("erasure report is not signed"))?;
+        if !Sha256::verify_hmac_hex(key, body.as_bytes(), signature) {
+            return Err(invalid("erasure report signature does not match"));
+        }
+
+        let mut lines = body.lines();
+        if lines.next() != Some(REPORT_HEADER) {
+            return Err(invalid("not an erasure report"));
+        }
+        let user_id = lines
+            .next()
+            .and_then(|line| line.strip_prefix("user "))
+            .and_then(|id| id.parse().ok())
+            .ok_or_else(|| invalid("invalid user line"))?;
+        let mode = lines
+            .next()
+            .and_then(|line| line.strip_prefix("mode "))
+            .ok_or_else(|| invalid("invalid mode line"))?
+            .parse()?;
+        let records = lines
+            .map(|line| {
+                let (id, source) = line.split_once(' ')?;
+                Some((id.parse().ok()?, source.to_string()))
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid("invalid record line"))?;
+        Ok(Self {
+            user_id,
+            mode,
+            records,
+        })
+    }
+
+    fn body(&self) -> String {
+        let mut body = format!(
+            "{}\nuser {}\nmode {}\n",
+            REPORT_HEADER,
+            self.user_id,
+            self.mode.as_str()
+        );
+        for (id, source) in &self.records {
+            body.push_str(&format!("{} {}\n", id, source));
+        }
+        body
+    }
+}
+
+/// Erases the records of a user from the file at `path`, adding them to `report`, and
+/// returns how many were affected.
+///
+/// The file is read once to find the records and left untouched when there are none;
+/// otherwise it is rewritten and replaced atomically, see
+/// [`CommonParser::write_iter_to_path_atomic`].
+pub fn erase_user_in_file(
+    path: impl AsRef<Path>,
+    format: Format,
+    erasure: &UserErasure,
+    report: &mut ErasureReport,
+) -> Result<u64, ParseError> {
+    let path = path.as_ref();
+    let parser = CommonParser::new(format);
+    let mut affected = vec![];
+    for record in parser.iter_read(File::open(path)?)? {
+        let record = record?;
+        if erasure.touches(&record) {
+            affected.push(record.id);
+        }
+    }
+    if affected.is_empty() {
+        return Ok(0);
+    }
+
+    let erased = parser
+        .iter_read(File::open(path)?)?
+        .filter_map(|record| match record {
+            Ok(record) => erasure.apply(record).map(Ok),
+            Err(err) => Some(Err(err)),
+        });
+    parser.write_iter_to_path_atomic(path, erased, None)?;
+    let count = affected.len() as u64;
+    report.add(path, affected);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod erasure_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(id: u64, from: u64, to: u64) -> YPBankRecord {
+        let transaction_type = match (from, to) {
+            (0, _) => TransactionType::Deposit,
+            (_, 0) => TransactionType::Withdrawal,
+            _ => TransactionType::Transfer,
+        };
+        YPBankRecord::new(
+            id,
+            transaction_type,
+            from,
+            to,
+            100,
+            id * 10,
+            TransactionStatus::Success,
+            "Flat 3, Baker St",
+        )
+    }
+
+    #[test]
+    fn test_erase_user_in_file() {
+        let path = std::env::temp_dir().join(format!("erasure_{}.bin", std::process::id()));
+        let parser = CommonParser::new(Format::Bin);
+        let records = vec![record(1, 0, 7), record(2, 7, 8), record(3, 8, 0)];
+        parser
+            .write_to_path_atomic(&path, &records, None)
+            .expect("Should write records");
+
+        let erasure = UserErasure::new(7, ErasureMode::Anonymize).expect("Should be a user");
+        let mut report = ErasureReport::new(&erasure);
+        let erased = erase_user_in_file(&path, Format::Bin, &erasure, &mut report);
+        let again = erase_user_in_file(&path, Format::Bin, &erasure, &mut report);
+        let read = parser.from_read(&mut File::open(&path).expect("Should open file"));
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!((erased, again), (Ok(2), Ok(0)));
+        let mut anonymized = records.clone();
+        anonymized[0].to_user_id = REDACTED_USER_ID;
+        anonymized[1].from_user_id = REDACTED_USER_ID;
+        anonymized[0].description = "".into();
+        anonymized[1].description = "".into();
+        assert_eq!(read, Ok(anonymized));
+        let source = path.display().to_string();
+        assert_eq!(report.records(), [(1, source.clone()), (2, source)]);
+    }
+
+    #[test]
+    fn test_report_signature() {
+        let erasure = UserErasure::new(7, ErasureMode::Remove).expect("Should be a user");
+        let mut report = ErasureReport::new(&erasure);
+        report.add(Path::new("2021-10.bin"), [4, 9]);
+        let mut signed = vec![];
+        report
+            .write_signed(&mut signed, b"key")
+            .expect("Should write report");
+        let signed = String::from_utf8(signed).expect("Should be UTF-8");
+
+        assert_eq!(ErasureReport::verify(&signed, b"key"), Ok(report));
+        assert!(ErasureReport::verify(&signed, b"other key").is_err());
+        let tampered = signed.replace("4 2021-10.bin", "5 2021-10.bin");
+        assert!(ErasureReport::verify(&tampered, b"key").is_err());
+        assert!(UserErasure::new(0, ErasureMode::Remove).is_err());
+    }
+}
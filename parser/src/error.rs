@@ -13,7 +13,10 @@ pub enum ParseError {
     InvalidTransactionTypeValue(String),
     InvalidStatusValue(String),
     InvalidUserId(String, TransactionType),
-    InvalidRawValue(String),
+    InvalidRawValue {
+        field: String,
+        value: String,
+    },
     InvalidRow(String),
     InvalidCsvHeader(String),
     UnexpectedEOF,
@@ -21,6 +24,161 @@ pub enum ParseError {
     InconsistentRecord(String),
     InvalidMagic(String),
     InvalidFormat(String),
+    UnsupportedOperation(String),
+    Overflow(String),
+    ValueOutOfRange {
+        field: String,
+        value: String,
+    },
+    /// A CSV quote that is never closed; `line` and `column` locate the opening quote,
+    /// counting from 1 at the start of the read.
+    UnterminatedQuote {
+        line: u64,
+        column: usize,
+    },
+    /// Bytes other than whitespace after the last record, starting at the given input
+    /// offset. Only reported when [`crate::ParseOptions::reject_trailing_data`] is set.
+    TrailingData(u64),
+    /// An empty CSV line followed by more records, rejected by
+    /// [`crate::CsvEmptyLines::Error`]; `line` counts from 1 at the start of the read.
+    EmptyLine {
+        line: u64,
+    },
+    /// A CSV line longer than [`crate::CsvLimits::max_line_len`] bytes; `line` counts
+    /// from 1 at the start of the read.
+    LineTooLong {
+        line: u64,
+        max: usize,
+    },
+    /// A CSV line with more than [`crate::CsvLimits::max_fields`] fields; `line` counts
+    /// from 1 at the start of the read.
+    TooManyFields {
+        line: u64,
+        max: usize,
+    },
+    /// A record breaking the invariants of [`crate::YPBankRecord::validate`], refused by a
+    /// write with [`crate::ParseOptions::validate_writes`] set; `index` counts the records
+    /// given to the write from 0.
+    InvalidRecord {
+        index: u64,
+        reason: String,
+    },
+    /// A record refused by a validation rule, with the code the rule returned.
+    RuleViolation {
+        id: u64,
+        rule: String,
+        code: i32,
+    },
+}
+
+/// Stable identifier of a [`ParseError`] variant, for grouping failures without
+/// matching on messages.
+///
+/// Numbers and names never change once released; new variants get new numbers.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ErrorCode, ParseError};
+///
+/// let code = ParseError::UnexpectedEOF.code();
+/// assert_eq!(code, ErrorCode::UnexpectedEof);
+/// assert_eq!((code.number(), code.as_str()), (8, "unexpected-eof"));
+/// assert_eq!(code.to_string(), "E008 unexpected-eof");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum ErrorCode {
+    Io = 1,
+    InvalidTransactionType = 2,
+    InvalidStatus = 3,
+    InvalidUserId = 4,
+    InvalidRawValue = 5,
+    InvalidRow = 6,
+    InvalidCsvHeader = 7,
+    UnexpectedEof = 8,
+    FieldNotFound = 9,
+    InconsistentRecord = 10,
+    InvalidMagic = 11,
+    InvalidFormat = 12,
+    UnsupportedOperation = 13,
+    Overflow = 14,
+    ValueOutOfRange = 15,
+    UnterminatedQuote = 16,
+    TrailingData = 17,
+    EmptyLine = 18,
+    LineTooLong = 19,
+    TooManyFields = 20,
+    InvalidRecord = 21,
+    RuleViolation = 22,
+}
+
+impl ErrorCode {
+    pub fn number(&self) -> u16 {
+        *self as u16
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Io => "io",
+            ErrorCode::InvalidTransactionType => "invalid-transaction-type",
+            ErrorCode::InvalidStatus => "invalid-status",
+            ErrorCode::InvalidUserId => "invalid-user-id",
+            ErrorCode::InvalidRawValue => "invalid-raw-value",
+            ErrorCode::InvalidRow => "invalid-row",
+            ErrorCode::InvalidCsvHeader => "invalid-csv-header",
+            ErrorCode::UnexpectedEof => "unexpected-eof",
+            ErrorCode::FieldNotFound => "field-not-found",
+            ErrorCode::InconsistentRecord => "inconsistent-record",
+            ErrorCode::InvalidMagic => "invalid-magic",
+            ErrorCode::InvalidFormat => "invalid-format",
+            ErrorCode::UnsupportedOperation => "unsupported-operation",
+            ErrorCode::Overflow => "overflow",
+            ErrorCode::ValueOutOfRange => "value-out-of-range",
+            ErrorCode::UnterminatedQuote => "unterminated-quote",
+            ErrorCode::TrailingData => "trailing-data",
+            ErrorCode::EmptyLine => "empty-line",
+            ErrorCode::LineTooLong => "line-too-long",
+            ErrorCode::TooManyFields => "too-many-fields",
+            ErrorCode::InvalidRecord => "invalid-record",
+            ErrorCode::RuleViolation => "rule-violation",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:03} {}", self.number(), self.as_str())
+    }
+}
+
+impl ParseError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ParseError::IOError(_) => ErrorCode::Io,
+            ParseError::InvalidTransactionTypeValue(_) => ErrorCode::InvalidTransactionType,
+            ParseError::InvalidStatusValue(_) => ErrorCode::InvalidStatus,
+            ParseError::InvalidUserId(..) => ErrorCode::InvalidUserId,
+            ParseError::InvalidRawValue { .. } => ErrorCode::InvalidRawValue,
+            ParseError::InvalidRow(_) => ErrorCode::InvalidRow,
+            ParseError::InvalidCsvHeader(_) => ErrorCode::InvalidCsvHeader,
+            ParseError::UnexpectedEOF => ErrorCode::UnexpectedEof,
+            ParseError::FieldNotFound(_) => ErrorCode::FieldNotFound,
+            ParseError::InconsistentRecord(_) => ErrorCode::InconsistentRecord,
+            ParseError::InvalidMagic(_) => ErrorCode::InvalidMagic,
+            ParseError::InvalidFormat(_) => ErrorCode::InvalidFormat,
+            ParseError::UnsupportedOperation(_) => ErrorCode::UnsupportedOperation,
+            ParseError::Overflow(_) => ErrorCode::Overflow,
+            ParseError::ValueOutOfRange { .. } => ErrorCode::ValueOutOfRange,
+            ParseError::UnterminatedQuote { .. } => ErrorCode::UnterminatedQuote,
+            ParseError::TrailingData(_) => ErrorCode::TrailingData,
+            ParseError::EmptyLine { .. } => ErrorCode::EmptyLine,
+            ParseError::LineTooLong { .. } => ErrorCode::LineTooLong,
+            ParseError::TooManyFields { .. } => ErrorCode::TooManyFields,
+            ParseError::InvalidRecord { .. } => ErrorCode::InvalidRecord,
+            ParseError::RuleViolation { .. } => ErrorCode::RuleViolation,
+        }
+    }
 }
 
 impl fmt::Display for ParseError {
@@ -39,7 +197,10 @@ impl fmt::Display for ParseError {
                 user_id,
                 transaction_type.as_str()
             ),
-            ParseError::InvalidRawValue(ref msg) => write!(f, "Invalid raw value found: {}", msg),
+            ParseError::InvalidRawValue {
+                ref field,
+                ref value,
+            } => write!(f, "Invalid raw value of field {}: {}", field, value),
             ParseError::InvalidRow(ref msg) => write!(f, "Invalid row found: {}", msg),
             ParseError::InvalidCsvHeader(ref msg) => write!(f, "Invalid CSV header: {}", msg),
             ParseError::UnexpectedEOF => write!(f, "Unexpected EOF"),
@@ -49,6 +210,33 @@ impl fmt::Display for ParseError {
             }
             ParseError::InvalidMagic(ref msg) => write!(f, "Invalid magic found: {}", msg),
             ParseError::InvalidFormat(ref msg) => write!(f, "Invalid file format found: {}", msg),
+            ParseError::UnsupportedOperation(ref msg) => {
+                write!(f, "Unsupported operation: {}", msg)
+            }
+            ParseError::Overflow(ref msg) => write!(f, "Arithmetic overflow: {}", msg),
+            ParseError::ValueOutOfRange {
+                ref field,
+                ref value,
+            } => write!(f, "Value {} is out of range for field {}", value, field),
+            ParseError::UnterminatedQuote { line, column } => {
+                write!(f, "Unterminated quote at line {}, column {}", line, column)
+            }
+            ParseError::TrailingData(offset) => {
+                write!(f, "Trailing data after the last record at byte {}", offset)
+            }
+            ParseError::EmptyLine { line } => write!(f, "Empty line at line {}", line),
+            ParseError::LineTooLong { line, max } => {
+                write!(f, "Line {} is longer than {} bytes", line, max)
+            }
+            ParseError::TooManyFields { line, max } => {
+                write!(f, "Line {} has more than {} fields", line, max)
+            }
+            ParseError::InvalidRecord { index, ref reason } => {
+                write!(f, "Invalid record at index {}: {}", index, reason)
+            }
+            ParseError::RuleViolation { id, ref rule, code } => {
+                write!(f, "Record {} violates rule {} with code {}", id, rule, code)
+            }
         }
     }
 }
@@ -60,3 +248,59 @@ impl From<std::io::Error> for ParseError {
         ParseError::IOError(err.to_string())
     }
 }
+
+#[cfg(test)]
+mod error_code_tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let errors = [
+            ParseError::IOError(String::new()),
+            ParseError::InvalidTransactionTypeValue(String::new()),
+            ParseError::InvalidStatusValue(String::new()),
+            ParseError::InvalidUserId(String::new(), TransactionType::Deposit),
+            ParseError::InvalidRawValue {
+                field: String::new(),
+                value: String::new(),
+            },
+            ParseError::InvalidRow(String::new()),
+            ParseError::InvalidCsvHeader(String::new()),
+            ParseError::UnexpectedEOF,
+            ParseError::FieldNotFound(String::new()),
+            ParseError::InconsistentRecord(String::new()),
+            ParseError::InvalidMagic(String::new()),
+            ParseError::InvalidFormat(String::new()),
+            ParseError::UnsupportedOperation(String::new()),
+            ParseError::Overflow(String::new()),
+            ParseError::ValueOutOfRange {
+                field: String::new(),
+                value: String::new(),
+            },
+            ParseError::UnterminatedQuote { line: 0, column: 0 },
+            ParseError::TrailingData(0),
+            ParseError::EmptyLine { line: 0 },
+            ParseError::LineTooLong { line: 0, max: 0 },
+            ParseError::TooManyFields { line: 0, max: 0 },
+            ParseError::InvalidRecord {
+                index: 0,
+                reason: String::new(),
+            },
+            ParseError::RuleViolation {
+                id: 0,
+                rule: String::new(),
+                code: 0,
+            },
+        ];
+
+        let codes: Vec<ErrorCode> = errors.iter().map(ParseError::code).collect();
+        for (index, code) in codes.iter().enumerate() {
+            assert_eq!(code.number() as usize, index + 1);
+            assert!(
+                !codes[..index]
+                    .iter()
+                    .any(|other| other.as_str() == code.as_str())
+            );
+        }
+    }
+}
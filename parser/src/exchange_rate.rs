@@ -0,0 +1,216 @@
+use crate::calendar::{SECONDS_PER_DAY, format_date, parse_date};
+use crate::error::ParseError;
+use crate::pipeline::Enricher;
+use crate::record::YPBankRecord;
+use crate::toml_format::YPBankTomlRecordParser;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const RATES_TABLE: &str = "[rates]";
+/// Most decimal places of a rate, so that its scale fits an `i64`.
+const MAX_RATE_DECIMALS: usize = 18;
+
+/// A positive decimal rate, `mantissa / 10^decimals`, kept exact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rate {
+    mantissa: i64,
+    decimals: u32,
+}
+
+impl Rate {
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: "exchange rate".to_string(),
+            value: s.to_string(),
+        };
+        let (whole, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+        let digits = format!("{}{}", whole, fraction);
+        if whole.is_empty()
+            || s.trim().ends_with('.')
+            || fraction.len() > MAX_RATE_DECIMALS
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let mantissa: i64 = digits.parse().map_err(|_| invalid())?;
+        if mantissa == 0 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            mantissa,
+            decimals: fraction.len() as u32,
+        })
+    }
+
+    /// Multiplies `amount` by the rate, rounding half away from zero.
+    fn apply(&self, amount: i64) -> Option<i64> {
+        let scale = 10i128.pow(self.decimals);
+        let product = amount as i128 * self.mantissa as i128;
+        let rounded = (product.abs() + scale / 2) / scale;
+        i64::try_from(rounded * product.signum()).ok()
+    }
+}
+
+/// Exchange rates by date, converting record amounts into another currency.
+///
+/// A record is converted with the rate of its UTC day, or of the latest earlier day with
+/// a rate, so weekends and holidays without a fixing use the last one. Amounts are
+/// multiplied by the rate and rounded half away from zero. Rates are usually loaded from
+/// TOML, with decimal rates quoted to keep them exact:
+///
+/// ```toml
+/// [rates]
+/// 2021-09-30 = "1.1580"
+/// 2021-10-01 = "1.1595"
+/// ```
+///
+/// As an [`Enricher`], the table converts every record passing through a pipeline and
+/// fails for records older than its first rate.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ExchangeRates, Pipeline, fixtures};
+///
+/// let rates = ExchangeRates::from_toml("[rates]\n2021-10-01 = \"1.5\"\n").unwrap();
+/// // Fixture records start on 2021-10-01 with an amount of 100.
+/// let pipeline = Pipeline::new().with_enricher(rates);
+/// let records = pipeline.run(fixtures::records()).unwrap();
+/// assert_eq!(records[0].amount, 150);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExchangeRates {
+    rates: BTreeMap<i64, Rate>,
+}
+
+impl ExchangeRates {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the rate of a `YYYY-MM-DD` date, given as a positive decimal like `1.1595`.
+    pub fn with_rate(mut self, date: &str, rate: &str) -> Result<Self, ParseError> {
+        self.rates
+            .insert(parse_date("rate date", date)?, Rate::parse(rate)?);
+        Ok(self)
+    }
+
+    /// Reads a `[rates]` table of rates by `YYYY-MM-DD` date.
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut rates = Self::new();
+        let mut in_table = false;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_table = line == RATES_TABLE;
+                if !in_table {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                }
+                continue;
+            }
+
+            let Some((date, value)) = line.split_once('=').filter(|_| in_table) else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let date = date.trim().trim_matches('"');
+            let rate = YPBankTomlRecordParser::parse_raw_value(date, value.trim())?;
+            rates = rates.with_rate(date, &rate)?;
+        }
+
+        Ok(rates)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Converts an amount with the rate of a timestamp in milliseconds.
+    ///
+    /// Fails with [`ParseError::FieldNotFound`] before the first rate, and with
+    /// [`ParseError::Overflow`] for amounts that don't fit once converted.
+    pub fn convert(&self, amount: i64, ts: u64) -> Result<i64, ParseError> {
+        let day = (ts / 1000) as i64 / SECONDS_PER_DAY;
+        let Some((_, rate)) = self.rates.range(..=day).next_back() else {
+            return Err(ParseError::FieldNotFound(format!(
+                "exchange rate on {}",
+                format_date(day)
+            )));
+        };
+        rate.apply(amount).ok_or_else(|| {
+            ParseError::Overflow(format!(
+                "amount {} converted on {}",
+                amount,
+                format_date(day)
+            ))
+        })
+    }
+}
+
+impl Enricher for ExchangeRates {
+    fn enrich(&self, record: &mut YPBankRecord) -> Result<(), ParseError> {
+        record.amount = self.convert(record.amount, record.ts)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod exchange_rate_tests {
+    use super::*;
+
+    const DAY_MS: u64 = SECONDS_PER_DAY as u64 * 1000;
+
+    fn ts(date: &str) -> u64 {
+        parse_date("date", date).expect("Should be a date") as u64 * DAY_MS
+    }
+
+    #[test]
+    fn test_convert_uses_latest_rate() {
+        let rates = ExchangeRates::new()
+            .with_rate("2021-10-01", "1.1595")
+            .and_then(|rates| rates.with_rate("2021-10-04", "0.5"))
+            .expect("Should parse rates");
+
+        assert_eq!(rates.convert(10_000, ts("2021-10-01")), Ok(11_595));
+        // The weekend keeps Friday's rate.
+        assert_eq!(
+            rates.convert(10_000, ts("2021-10-03") + DAY_MS - 1),
+            Ok(11_595)
+        );
+        assert_eq!(rates.convert(-3, ts("2021-10-04")), Ok(-2));
+        assert_eq!(rates.convert(3, ts("2021-10-05")), Ok(2));
+        assert!(matches!(
+            rates.convert(100, ts("2021-09-30")),
+            Err(ParseError::FieldNotFound(_))
+        ));
+        assert!(matches!(
+            rates.convert(i64::MAX, ts("2021-10-01")),
+            Err(ParseError::Overflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        assert_eq!(
+            ExchangeRates::from_toml(
+                "# EUR to USD\n[rates]\n2021-10-01 = \"1.1595\"\n\"2021-10-04\" = 2\n"
+            ),
+            ExchangeRates::new()
+                .with_rate("2021-10-01", "1.1595")
+                .and_then(|rates| rates.with_rate("2021-10-04", "2"))
+        );
+        for invalid in [
+            "2021-10-01 = \"1.1\"",
+            "[fx]\n2021-10-01 = \"1.1\"",
+            "[rates]\n2021-13-01 = \"1.1\"",
+            "[rates]\n2021-10-01 = \"0\"",
+            "[rates]\n2021-10-01 = \"-1.1\"",
+            "[rates]\n2021-10-01 = \"1.\"",
+        ] {
+            assert!(ExchangeRates::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
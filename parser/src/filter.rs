@@ -0,0 +1,318 @@
+use crate::common::{TransactionStatus, TransactionType, parse_value_from_str};
+use crate::error::ParseError;
+use crate::pipeline::{Stage, UserFilter, UserSide};
+use crate::record::YPBankRecord;
+use crate::toml_format::YPBankTomlRecordParser;
+use std::fmt::Write;
+use std::path::Path;
+
+const FILTER_TABLE: &str = "[filter]";
+const LIST_SEP: char = ',';
+
+/// Conditions a record must all meet, such as a status, a range of amounts or a user.
+///
+/// Conditions on the same field are alternatives: `status(Pending).status(Failure)`
+/// keeps records of either status. Filters are [`Stage`]s of a [`crate::Pipeline`], and
+/// can be written to and read from TOML, so they can be shared through configuration:
+///
+/// ```toml
+/// [filter]
+/// status = "PENDING,FAILURE"
+/// tx_type = "TRANSFER"
+/// min_amount = 100
+/// max_amount = 5000
+/// from_ts = 1633046400000
+/// to_ts = 1633132799999
+/// users = "17,42"
+/// user_side = "either"
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use parser::{RecordFilter, TransactionStatus, TransactionType, fixtures};
+///
+/// let filter = RecordFilter::new()
+///     .status(TransactionStatus::Pending)
+///     .amount_between(500, 1500)
+///     .ty(TransactionType::Transfer);
+/// let pending = fixtures::records()
+///     .into_iter()
+///     .filter(filter.as_fn())
+///     .collect::<Vec<_>>();
+/// assert_eq!(pending.len(), 1);
+///
+/// assert_eq!(RecordFilter::from_toml(&filter.to_toml()), Ok(filter));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordFilter {
+    statuses: Vec<TransactionStatus>,
+    types: Vec<TransactionType>,
+    min_amount: Option<i64>,
+    max_amount: Option<i64>,
+    from_ts: Option<u64>,
+    to_ts: Option<u64>,
+    users: Option<UserFilter>,
+}
+
+impl RecordFilter {
+    /// A filter keeping every record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps records with `status`, or with the statuses of previous calls.
+    pub fn status(mut self, status: TransactionStatus) -> Self {
+        if !self.statuses.contains(&status) {
+            self.statuses.push(status);
+        }
+        self
+    }
+
+    /// Keeps records of `transaction_type`, or of the types of previous calls.
+    pub fn ty(mut self, transaction_type: TransactionType) -> Self {
+        if !self.types.contains(&transaction_type) {
+            self.types.push(transaction_type);
+        }
+        self
+    }
+
+    /// Keeps records with `min <= amount <= max`.
+    pub fn amount_between(mut self, min: i64, max: i64) -> Self {
+        self.min_amount = Some(min);
+        self.max_amount = Some(max);
+        self
+    }
+
+    pub fn min_amount(mut self, min: i64) -> Self {
+        self.min_amount = Some(min);
+        self
+    }
+
+    pub fn max_amount(mut self, max: i64) -> Self {
+        self.max_amount = Some(max);
+        self
+    }
+
+    /// Keeps records with `from <= ts <= to`.
+    pub fn ts_between(mut self, from: u64, to: u64) -> Self {
+        self.from_ts = Some(from);
+        self.to_ts = Some(to);
+        self
+    }
+
+    /// Keeps records of the users in `ids`, replacing users given before.
+    pub fn users(mut self, ids: impl IntoIterator<Item = u64>, side: UserSide) -> Self {
+        self.users = Some(UserFilter::new(ids, side));
+        self
+    }
+
+    /// Returns whether the filter keeps every record.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        (self.statuses.is_empty() || self.statuses.contains(&record.status))
+            && (self.types.is_empty() || self.types.contains(&record.transaction_type))
+            && self.min_amount.is_none_or(|min| record.amount >= min)
+            && self.max_amount.is_none_or(|max| record.amount <= max)
+            && self.from_ts.is_none_or(|from| record.ts >= from)
+            && self.to_ts.is_none_or(|to| record.ts <= to)
+            && self
+                .users
+                .as_ref()
+                .is_none_or(|users| users.matches(record))
+    }
+
+    /// Returns the filter as a closure, e.g. for [`Iterator::filter`].
+    pub fn as_fn(&self) -> impl Fn(&YPBankRecord) -> bool + '_ {
+        |record| self.matches(record)
+    }
+
+    /// Reads a `[filter]` table, see [`RecordFilter`]. Lists are comma-separated strings.
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut filter = Self::new();
+        let mut users = None;
+        let mut user_side = UserSide::default();
+        let mut in_table = false;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_table = line == FILTER_TABLE;
+                if !in_table {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=').filter(|_| in_table) else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let key = key.trim();
+            let value = YPBankTomlRecordParser::parse_raw_value(key, value.trim())?;
+            let list = || value.split(LIST_SEP).map(str::trim);
+            match key {
+                "status" => {
+                    for status in list() {
+                        filter = filter.status(parse_value_from_str(key, status)?);
+                    }
+                }
+                "tx_type" => {
+                    for transaction_type in list() {
+                        filter = filter.ty(parse_value_from_str(key, transaction_type)?);
+                    }
+                }
+                "min_amount" => filter.min_amount = Some(parse_value_from_str(key, &value)?),
+                "max_amount" => filter.max_amount = Some(parse_value_from_str(key, &value)?),
+                "from_ts" => filter.from_ts = Some(parse_value_from_str(key, &value)?),
+                "to_ts" => filter.to_ts = Some(parse_value_from_str(key, &value)?),
+                "users" => {
+                    users = Some(
+                        list()
+                            .map(|id| parse_value_from_str(key, id))
+                            .collect::<Result<Vec<u64>, _>>()?,
+                    )
+                }
+                "user_side" => user_side = parse_value_from_str(key, &value)?,
+                _ => return Err(ParseError::InvalidRow(line.to_string())),
+            }
+        }
+
+        if let Some(users) = users {
+            filter = filter.users(users, user_side);
+        }
+        Ok(filter)
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Writes the filter as a `[filter]` table read back by [`RecordFilter::from_toml`].
+    pub fn to_toml(&self) -> String {
+        let join = |values: Vec<&str>| values.join(&LIST_SEP.to_string());
+
+        let mut s = format!("{}\n", FILTER_TABLE);
+        if !self.statuses.is_empty() {
+            let statuses = self.statuses.iter().map(|status| status.as_str()).collect();
+            let _ = writeln!(s, "status = \"{}\"", join(statuses));
+        }
+        if !self.types.is_empty() {
+            let types = self.types.iter().map(|ty| ty.as_str()).collect();
+            let _ = writeln!(s, "tx_type = \"{}\"", join(types));
+        }
+        for (key, value) in [
+            ("min_amount", self.min_amount),
+            ("max_amount", self.max_amount),
+        ] {
+            if let Some(value) = value {
+                let _ = writeln!(s, "{} = {}", key, value);
+            }
+        }
+        for (key, value) in [("from_ts", self.from_ts), ("to_ts", self.to_ts)] {
+            if let Some(value) = value {
+                let _ = writeln!(s, "{} = {}", key, value);
+            }
+        }
+        if let Some(users) = &self.users {
+            let ids = users.ids().map(|id| id.to_string()).collect::<Vec<_>>();
+            let _ = writeln!(s, "users = \"{}\"", ids.join(&LIST_SEP.to_string()));
+            let _ = writeln!(s, "user_side = \"{}\"", users.side().as_str());
+        }
+        s
+    }
+}
+
+impl Stage for RecordFilter {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Ok(self.matches(&record).then_some(record))
+    }
+}
+
+#[cfg(test)]
+mod record_filter_tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn ids(filter: &RecordFilter) -> Vec<u64> {
+        fixtures::records()
+            .into_iter()
+            .filter(filter.as_fn())
+            .map(|record| record.id - 1000000000000000)
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_all_conditions() {
+        assert_eq!(ids(&RecordFilter::new()).len(), 20);
+        assert_eq!(
+            ids(&RecordFilter::new()
+                .status(TransactionStatus::Pending)
+                .status(TransactionStatus::Failure)),
+            vec![3, 7, 11, 15, 19]
+        );
+        assert_eq!(
+            ids(&RecordFilter::new()
+                .ty(TransactionType::Deposit)
+                .amount_between(400, 1300)),
+            vec![3, 6, 9, 12]
+        );
+        assert_eq!(
+            ids(&RecordFilter::new()
+                .ty(TransactionType::Transfer)
+                .users([2], UserSide::From)),
+            vec![1, 16]
+        );
+        assert_eq!(
+            ids(&RecordFilter::new().ts_between(1633046460000 + 60_000, 1633046460000 + 120_000)),
+            vec![1, 2]
+        );
+    }
+
+    #[test]
+    fn test_toml_round_trip() {
+        let filter = RecordFilter::new()
+            .status(TransactionStatus::Pending)
+            .status(TransactionStatus::Failure)
+            .ty(TransactionType::Transfer)
+            .min_amount(-5)
+            .ts_between(10, 20)
+            .users([42, 17], UserSide::To);
+        let toml = filter.to_toml();
+        assert_eq!(
+            toml,
+            "[filter]\nstatus = \"PENDING,FAILURE\"\ntx_type = \"TRANSFER\"\nmin_amount = -5\n\
+             from_ts = 10\nto_ts = 20\nusers = \"17,42\"\nuser_side = \"to\"\n"
+        );
+        assert_eq!(RecordFilter::from_toml(&toml), Ok(filter));
+        assert_eq!(RecordFilter::from_toml(""), Ok(RecordFilter::new()));
+
+        assert!(RecordFilter::from_toml("[filter]\nstatus = \"DONE\"\n").is_err());
+        assert_eq!(
+            RecordFilter::from_toml("[filter]\ncolor = \"red\"\n"),
+            Err(ParseError::InvalidRow("color = \"red\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_extreme_amounts() {
+        let filter = RecordFilter::new().amount_between(i64::MIN, i64::MAX);
+        assert_eq!(RecordFilter::from_toml(&filter.to_toml()), Ok(filter));
+        assert_eq!(
+            RecordFilter::from_toml("[filter]\nmin_amount = -9223372036854775809\n"),
+            Err(ParseError::ValueOutOfRange {
+                field: "min_amount".to_string(),
+                value: "-9223372036854775809".to_string(),
+            })
+        );
+        assert!(matches!(
+            RecordFilter::from_toml("[filter]\nmax_amount = 1e3\n"),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+    }
+}
@@ -0,0 +1,129 @@
+//! Example files named in the documentation, generated from code so that the examples
+//! run against real data.
+//!
+//! Doc examples call [`enter`] in a hidden first line. `cargo run --example fixtures -- DIR`
+//! writes the same files to `DIR` for trying the examples by hand.
+
+use crate::CommonParser;
+use crate::common::{Format, TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::path::{Path, PathBuf};
+
+/// Files written by [`write_to`] with the format of each. `archive.bin` holds the records
+/// in reverse order, for sorting examples.
+pub const FILES: [(&str, Format); 5] = [
+    ("records.csv", Format::Csv),
+    ("records.txt", Format::Txt),
+    ("records.bin", Format::Bin),
+    ("records.toml", Format::Toml),
+    ("archive.bin", Format::Bin),
+];
+
+/// Directory created next to the files by [`write_to`], for examples writing output.
+pub const OUTPUT_DIR: &str = "out";
+
+const RECORD_COUNT: u64 = 20;
+
+/// Returns the records of the example files: one per minute from 2021-10-01 00:01 UTC,
+/// with amounts growing by 100 and the transaction types taking turns.
+pub fn records() -> Vec<YPBankRecord> {
+    (0..RECORD_COUNT)
+        .map(|index| {
+            let (transaction_type, from_user_id, to_user_id) = match index % 3 {
+                0 => (TransactionType::Deposit, 0, index % 5 + 1),
+                1 => (
+                    TransactionType::Transfer,
+                    index % 5 + 1,
+                    (index + 1) % 5 + 1,
+                ),
+                _ => (TransactionType::Withdrawal, index % 5 + 1, 0),
+            };
+            let status = match index % 4 {
+                3 => TransactionStatus::Pending,
+                _ => TransactionStatus::Success,
+            };
+            YPBankRecord::new(
+                1000000000000000 + index,
+                transaction_type,
+                from_user_id,
+                to_user_id,
+                (index as i64 + 1) * 100,
+                1633046460000 + index * 60_000,
+                status,
+                format!("Record number {}", index + 1),
+            )
+        })
+        .collect()
+}
+
+/// Writes the [`FILES`] and an [`OUTPUT_DIR`] to `dir`, which must exist.
+///
+/// Files are replaced atomically, so processes writing the same directory at once never
+/// see them half-written.
+pub fn write_to(dir: &Path) -> Result<(), ParseError> {
+    let records = records();
+    let mut archive = records.clone();
+    archive.reverse();
+
+    for (name, format) in FILES {
+        let records = match name {
+            "archive.bin" => &archive,
+            _ => &records,
+        };
+        CommonParser::new(format).write_to_path_atomic(dir.join(name), records, None)?;
+    }
+    std::fs::create_dir_all(dir.join(OUTPUT_DIR))?;
+    Ok(())
+}
+
+/// Writes the example files to a directory under the system temporary directory and
+/// makes it the current directory, returning its path.
+///
+/// The directory is shared by all processes using the same version of the crate, so
+/// examples run in parallel must not modify the example files.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CommonParser, Format, fixtures};
+/// use std::fs::File;
+///
+/// fixtures::enter().unwrap();
+/// let records = CommonParser::new(Format::Csv)
+///     .from_read(&mut File::open("records.csv").unwrap())
+///     .unwrap();
+/// assert_eq!(records, fixtures::records());
+/// ```
+pub fn enter() -> Result<PathBuf, ParseError> {
+    let dir = std::env::temp_dir().join(format!("ypbank-fixtures-{}", env!("CARGO_PKG_VERSION")));
+    std::fs::create_dir_all(&dir)?;
+    write_to(&dir)?;
+    std::env::set_current_dir(&dir)?;
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod fixtures_tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_write_to() {
+        let dir = std::env::temp_dir().join(format!("ypbank-fixtures-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create directory");
+        write_to(&dir).expect("Should write fixtures");
+
+        for (name, format) in FILES {
+            let mut records = CommonParser::new(format)
+                .from_read(&mut File::open(dir.join(name)).expect("Should open fixture"))
+                .expect("Should read fixture");
+            if name == "archive.bin" {
+                records.reverse();
+            }
+            assert_eq!(records, super::records(), "{}", name);
+        }
+        assert!(dir.join(OUTPUT_DIR).is_dir());
+        std::fs::remove_dir_all(dir).expect("Should remove directory");
+    }
+}
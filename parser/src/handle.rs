@@ -0,0 +1,262 @@
+use crate::CommonParser;
+use crate::RecordStream;
+use crate::batch::Batch;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::options::ParseOptions;
+use crate::record::YPBankRecord;
+use std::fmt::{self, Display, Formatter};
+use std::ops::ControlFlow;
+
+/// Whether records are read from or written to a format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Direction::Read => "reading",
+            Direction::Write => "writing",
+        }
+    }
+}
+
+/// Returned by [`CommonParser::reader`] and [`CommonParser::writer`] for a format that
+/// cannot be used in the requested direction, such as reading HTML reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedDirection {
+    pub format: Format,
+    pub direction: Direction,
+}
+
+impl Display for UnsupportedDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} files is not supported",
+            self.direction.as_str(),
+            self.format.as_str()
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedDirection {}
+
+impl From<UnsupportedDirection> for ParseError {
+    fn from(err: UnsupportedDirection) -> Self {
+        ParseError::UnsupportedOperation(err.to_string())
+    }
+}
+
+/// A parser for a format that can be read, see [`CommonParser::reader`].
+///
+/// Only reading methods are available, so a handle can be passed to code that must not
+/// write with it.
+pub struct ReaderHandle {
+    parser: CommonParser,
+}
+
+impl ReaderHandle {
+    pub fn format(&self) -> Format {
+        self.parser.format()
+    }
+
+    /// See [`CommonParser::from_read`].
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_read<Reader: std::io::Read>(
+        &self,
+        r: &mut Reader,
+    ) -> Result<Vec<YPBankRecord>, ParseError> {
+        self.parser.from_read(r)
+    }
+
+    /// See [`CommonParser::from_read_with`].
+    #[allow(clippy::wrong_self_convention)]
+    pub fn from_read_with<Reader: std::io::Read, B>(
+        &self,
+        r: &mut Reader,
+        f: impl FnMut(YPBankRecord) -> ControlFlow<B>,
+    ) -> Result<ControlFlow<B>, ParseError> {
+        self.parser.from_read_with(r, f)
+    }
+
+    /// See [`CommonParser::iter_read`].
+    pub fn iter_read<'a, Reader: std::io::Read + 'a>(
+        &self,
+        r: Reader,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        self.parser.iter_read(r)
+    }
+
+    /// See [`CommonParser::iter_read_at`].
+    pub fn iter_read_at<'a, Reader: std::io::Read + std::io::Seek + 'a>(
+        &self,
+        r: Reader,
+        offset: u64,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        self.parser.iter_read_at(r, offset)
+    }
+
+    /// See [`CommonParser::read_batch`].
+    pub fn read_batch<Reader: std::io::Read>(&self, r: &mut Reader) -> Result<Batch, ParseError> {
+        self.parser.read_batch(r)
+    }
+}
+
+/// A parser for a format that can be written, see [`CommonParser::writer`].
+pub struct WriterHandle {
+    parser: CommonParser,
+}
+
+impl WriterHandle {
+    pub fn format(&self) -> Format {
+        self.parser.format()
+    }
+
+    /// See [`CommonParser::write_to`].
+    pub fn write_to<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: &Vec<YPBankRecord>,
+    ) -> Result<(), ParseError> {
+        self.parser.write_to(w, records)
+    }
+
+    /// See [`CommonParser::write_iter`].
+    pub fn write_iter<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        self.parser.write_iter(w, records)
+    }
+
+    /// See [`CommonParser::append_iter`].
+    pub fn append_iter<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        self.parser.append_iter(w, records)
+    }
+
+    /// See [`CommonParser::write_batch`].
+    pub fn write_batch<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        batch: &Batch,
+    ) -> Result<(), ParseError> {
+        self.parser.write_batch(w, batch)
+    }
+
+    /// See [`CommonParser::write_to_path_atomic`].
+    pub fn write_to_path_atomic(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        records: &Vec<YPBankRecord>,
+        backup: Option<&std::path::Path>,
+    ) -> Result<(), ParseError> {
+        self.parser.write_to_path_atomic(path, records, backup)
+    }
+}
+
+impl CommonParser {
+    /// Returns a handle that can only read `format`, failing right away for write-only
+    /// formats instead of at the first read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Direction, Format};
+    ///
+    /// assert!(CommonParser::reader(Format::Csv).is_ok());
+    ///
+    /// let err = CommonParser::reader(Format::Html).err().unwrap();
+    /// assert_eq!(err.direction, Direction::Read);
+    /// assert_eq!(err.to_string(), "reading html files is not supported");
+    /// ```
+    pub fn reader(format: Format) -> Result<ReaderHandle, UnsupportedDirection> {
+        Self::reader_with_options(format, ParseOptions::default())
+    }
+
+    /// Like [`CommonParser::reader`], reading according to the given [`ParseOptions`].
+    pub fn reader_with_options(
+        format: Format,
+        options: ParseOptions,
+    ) -> Result<ReaderHandle, UnsupportedDirection> {
+        if !format.is_readable() {
+            return Err(UnsupportedDirection {
+                format,
+                direction: Direction::Read,
+            });
+        }
+        Ok(ReaderHandle {
+            parser: Self::with_options(format, options),
+        })
+    }
+
+    /// Returns a handle that can only write `format`. Every format can be written at the
+    /// moment; the result keeps callers ready for read-only formats.
+    pub fn writer(format: Format) -> Result<WriterHandle, UnsupportedDirection> {
+        Self::writer_with_options(format, ParseOptions::default())
+    }
+
+    /// Like [`CommonParser::writer`], writing according to the given [`ParseOptions`].
+    pub fn writer_with_options(
+        format: Format,
+        options: ParseOptions,
+    ) -> Result<WriterHandle, UnsupportedDirection> {
+        Ok(WriterHandle {
+            parser: Self::with_options(format, options),
+        })
+    }
+}
+
+#[cfg(test)]
+mod handle_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    #[test]
+    fn test_reader_rejects_write_only_formats() {
+        let err = CommonParser::reader(Format::Html).err();
+        assert_eq!(
+            err,
+            Some(UnsupportedDirection {
+                format: Format::Html,
+                direction: Direction::Read,
+            })
+        );
+        assert_eq!(
+            ParseError::from(err.unwrap()),
+            ParseError::UnsupportedOperation("reading html files is not supported".to_string())
+        );
+        assert!(CommonParser::writer(Format::Html).is_ok());
+    }
+
+    #[test]
+    fn test_round_trip_through_handles() {
+        let records = vec![YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "Salary",
+        )];
+
+        let mut data = Vec::new();
+        CommonParser::writer(Format::Toml)
+            .expect("Should write TOML")
+            .write_to(&mut data, &records)
+            .expect("Should write records");
+        let reader = CommonParser::reader(Format::Toml).expect("Should read TOML");
+        assert_eq!(reader.format(), Format::Toml);
+        assert_eq!(reader.from_read(&mut data.as_slice()), Ok(records));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::common::{TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::totals::Totals;
+use std::io::Write;
+
+const COLUMNS: [(&str, &str); 8] = [
+    ("TX_ID", "number"),
+    ("TX_TYPE", "text"),
+    ("FROM_USER_ID", "number"),
+    ("TO_USER_ID", "number"),
+    ("AMOUNT", "number"),
+    ("TIMESTAMP", "number"),
+    ("STATUS", "text"),
+    ("DESCRIPTION", "text"),
+];
+
+const HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Transactions report</title>
+<style>
+body { font-family: sans-serif; display: flex; flex-direction: column; align-items: flex-start; }
+h1, #summary { order: -1; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; }
+th[data-type] { cursor: pointer; background: #eee; }
+td.number { text-align: right; font-family: monospace; }
+</style>
+</head>
+<body>
+"#;
+
+const SCRIPT: &str = r##"<script>
+document.querySelectorAll("#records th").forEach(function (th, column) {
+  th.addEventListener("click", function () {
+    var body = th.closest("table").tBodies[0];
+    var ascending = th.dataset.order !== "asc";
+    th.dataset.order = ascending ? "asc" : "desc";
+    var numeric = th.dataset.type === "number";
+    Array.from(body.rows).sort(function (a, b) {
+      var x = a.cells[column].textContent, y = b.cells[column].textContent;
+      var cmp = numeric
+        ? (BigInt(x) < BigInt(y) ? -1 : BigInt(x) > BigInt(y) ? 1 : 0)
+        : x.localeCompare(y);
+      return ascending ? cmp : -cmp;
+    }).forEach(function (row) { body.appendChild(row); });
+  });
+});
+</script>
+"##;
+
+const TAIL: &str = "</body>\n</html>\n";
+
+/// Writes records as a standalone HTML report.
+///
+/// The report shows summary statistics above a table that can be sorted by clicking on
+/// a column header. Rows are written as records arrive and the summary is written after
+/// them, once it is known; the stylesheet shows it first.
+pub struct HtmlWriter {}
+
+impl HtmlWriter {
+    pub fn write_to<W: std::io::Write>(
+        w: &mut W,
+        records: &[YPBankRecord],
+    ) -> Result<(), ParseError> {
+        Self::write_iter(w, records.iter().cloned().map(Ok))
+    }
+
+    pub fn write_iter<W: std::io::Write>(
+        w: &mut W,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        let mut w = std::io::BufWriter::new(w);
+        let mut summary = Summary::default();
+
+        w.write_all(HEAD.as_bytes())?;
+        w.write_all(b"<table id=\"records\">\n<thead><tr>")?;
+        for (name, column_type) in COLUMNS {
+            write!(w, "<th data-type=\"{}\">{}</th>", column_type, name)?;
+        }
+        w.write_all(b"</tr></thead>\n<tbody>\n")?;
+
+        for record in records {
+            let record = record?;
+            summary.add(&record)?;
+            write!(
+                w,
+                "<tr><td class=\"number\">{}</td><td>{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td><td>{}</td><td>",
+                record.id,
+                record.transaction_type.as_str(),
+                record.from_user_id,
+                record.to_user_id,
+                record.amount,
+                record.ts,
+                record.status.as_str(),
+            )?;
+            write_escaped(&mut w, &record.description)?;
+            w.write_all(b"</td></tr>\n")?;
+        }
+
+        w.write_all(b"</tbody>\n</table>\n")?;
+        summary.write_to(&mut w)?;
+        w.write_all(SCRIPT.as_bytes())?;
+        w.write_all(TAIL.as_bytes())?;
+        w.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Statistics of the records written so far.
+#[derive(Default)]
+struct Summary {
+    count: usize,
+    totals: Totals,
+    timestamps: Option<(u64, u64)>,
+}
+
+impl Summary {
+    fn add(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        self.totals.add(record)?;
+        self.count += 1;
+        self.timestamps = Some(match self.timestamps {
+            Some((first, last)) => (first.min(record.ts), last.max(record.ts)),
+            None => (record.ts, record.ts),
+        });
+        Ok(())
+    }
+
+    fn write_to<W: std::io::Write>(&self, w: &mut W) -> Result<(), ParseError> {
+        let types = [
+            TransactionType::Deposit,
+            TransactionType::Transfer,
+            TransactionType::Withdrawal,
+        ];
+        let statuses = [
+            TransactionStatus::Success,
+            TransactionStatus::Failure,
+            TransactionStatus::Pending,
+        ];
+
+        w.write_all(b"<h1>Transactions report</h1>\n<table id=\"summary\">\n")?;
+        writeln!(w, "<tr><th>Records</th><td>{}</td></tr>", self.count)?;
+        writeln!(
+            w,
+            "<tr><th>Total amount</th><td>{}</td></tr>",
+            self.totals.all.amount
+        )?;
+        if let Some((first, last)) = self.timestamps {
+            writeln!(
+                w,
+                "<tr><th>Timestamps</th><td>{} &ndash; {}</td></tr>",
+                first, last
+            )?;
+        }
+        for transaction_type in types {
+            let total = self.totals.for_type(transaction_type);
+            writeln!(
+                w,
+                "<tr><th>{}</th><td>{} records, amount {}</td></tr>",
+                transaction_type.as_str(),
+                total.count,
+                total.amount,
+            )?;
+        }
+        for status in statuses {
+            writeln!(
+                w,
+                "<tr><th>{}</th><td>{} records</td></tr>",
+                status.as_str(),
+                self.totals.for_status(status).count,
+            )?;
+        }
+        w.write_all(b"</table>\n")?;
+
+        Ok(())
+    }
+}
+
+fn write_escaped<W: std::io::Write>(w: &mut W, value: &str) -> Result<(), ParseError> {
+    let mut start = 0;
+    for (pos, ch) in value.char_indices() {
+        let escaped = match ch {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' => "&quot;",
+            '\'' => "&#39;",
+            _ => continue,
+        };
+        w.write_all(&value.as_bytes()[start..pos])?;
+        w.write_all(escaped.as_bytes())?;
+        start = pos + ch.len_utf8();
+    }
+    w.write_all(&value.as_bytes()[start..])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod html_writer_tests {
+    use super::*;
+
+    fn record(id: u64, transaction_type: TransactionType, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            transaction_type,
+            1,
+            2,
+            amount,
+            1633036860000 + id,
+            TransactionStatus::Success,
+            "<b>\"Tom\" & 'Jerry'</b>".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_write_to_summary_and_rows() {
+        let records = vec![
+            record(1, TransactionType::Deposit, i64::MAX),
+            record(2, TransactionType::Deposit, i64::MAX),
+            record(3, TransactionType::Transfer, -5),
+        ];
+
+        let mut written = Vec::new();
+        HtmlWriter::write_to(&mut written, &records).expect("Should write");
+        let html = String::from_utf8(written).expect("Should be valid UTF-8");
+
+        assert!(html.contains("<tr><th>Records</th><td>3</td></tr>"));
+        assert!(html.contains("<tr><th>Total amount</th><td>18446744073709551609</td></tr>"));
+        assert!(
+            html.contains(
+                "<tr><th>DEPOSIT</th><td>2 records, amount 18446744073709551614</td></tr>"
+            )
+        );
+        assert!(
+            html.contains(
+                "<tr><th>Timestamps</th><td>1633036860001 &ndash; 1633036860003</td></tr>"
+            )
+        );
+        assert!(html.contains("<th data-type=\"number\">AMOUNT</th>"));
+        assert!(html.contains("&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;"));
+        assert_eq!(html.matches("<tr><td class=\"number\">").count(), 3);
+        assert!(html.ends_with("</html>\n"));
+    }
+
+    #[test]
+    fn test_write_to_empty() {
+        let mut written = Vec::new();
+        HtmlWriter::write_to(&mut written, &[]).expect("Should write");
+        let html = String::from_utf8(written).expect("Should be valid UTF-8");
+
+        assert!(html.contains("<tr><th>Records</th><td>0</td></tr>"));
+        assert!(!html.contains("Timestamps"));
+    }
+
+    #[test]
+    fn test_write_iter_stops_at_error() {
+        let records = vec![
+            Ok(record(1, TransactionType::Deposit, 10)),
+            Err(ParseError::InvalidRow("broken".to_string())),
+        ];
+
+        let mut written = Vec::new();
+        assert_eq!(
+            HtmlWriter::write_iter(&mut written, records),
+            Err(ParseError::InvalidRow("broken".to_string()))
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::error::ParseError;
+use std::io::{BufRead, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+const HTTP_SCHEME: &str = "http://";
+const HTTPS_SCHEME: &str = "https://";
+const DEFAULT_PORT: u16 = 80;
+const DEFAULT_TLS_PORT: u16 = 443;
+
+/// Host, port and path of an `http://` or `https://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Endpoint {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) path: String,
+    /// Whether the URL is `https://`, so the connection needs TLS.
+    pub(crate) tls: bool,
+}
+
+/// Returns the part of `url` after `scheme`, ignoring the case of the scheme.
+fn strip_scheme<'a>(url: &'a str, scheme: &str) -> Option<&'a str> {
+    url.get(..scheme.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(scheme))
+        .map(|_| &url[scheme.len()..])
+}
+
+impl Endpoint {
+    /// Parses an `http://` or `https://` URL.
+    pub(crate) fn parse(url: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: "url".to_string(),
+            value: url.to_string(),
+        };
+        let (rest, tls) = match strip_scheme(url, HTTPS_SCHEME) {
+            Some(rest) => (rest, true),
+            None => (strip_scheme(url, HTTP_SCHEME).ok_or_else(invalid)?, false),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None if tls => (authority, DEFAULT_TLS_PORT),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            tls,
+        })
+    }
+
+    /// Connects with `timeout` as connect, read and write timeout.
+    pub(crate) fn connect(&self, timeout: Duration) -> std::io::Result<TcpStream> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("cannot resolve {}", self.host),
+                )
+            })?;
+        let stream = TcpStream::connect_timeout(&address, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Ok(stream)
+    }
+
+    /// Writes an HTTP/1.1 request closing the connection after the response.
+    pub(crate) fn write_request<W: Write>(
+        &self,
+        w: &mut W,
+        method: &str,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) -> std::io::Result<()> {
+        write!(
+            w,
+            "{} {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\n",
+            method, self.path, self.host, self.port
+        )?;
+        for (name, value) in headers {
+            write!(w, "{}: {}\r\n", name, value)?;
+        }
+        if !body.is_empty() {
+            write!(w, "Content-Length: {}\r\n", body.len())?;
+        }
+        w.write_all(b"\r\n")?;
+        w.write_all(body)?;
+        w.flush()
+    }
+
+    /// Renders the endpoint as a URL for messages.
+    pub(crate) fn url(&self) -> String {
+        let scheme = if self.tls { HTTPS_SCHEME } else { HTTP_SCHEME };
+        format!("{}{}:{}{}", scheme, self.host, self.port, self.path)
+    }
+}
+
+/// Status and headers of an HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResponseHead {
+    pub(crate) status: u16,
+    headers: Vec<(String, String)>,
+}
+
+impl ResponseHead {
+    /// Reads the status line and the headers, leaving the body in `r`.
+    pub(crate) fn read<R: BufRead>(r: &mut R) -> Result<Self, ParseError> {
+        let mut line = String::new();
+        r.read_line(&mut line)?;
+        let status = line
+            .split_whitespace()
+            .nth(1)
+            .filter(|_| line.starts_with("HTTP/"))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| {
+                ParseError::IOError(format!("invalid HTTP response: {}", line.trim()))
+            })?;
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Err(ParseError::UnexpectedEOF);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                return Err(ParseError::IOError(format!(
+                    "invalid HTTP header: {}",
+                    line
+                )));
+            };
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+        Ok(Self { status, headers })
+    }
+
+    /// Returns the value of the first header called `name`, ignoring case.
+    #[cfg_attr(not(feature = "http-source"), allow(dead_code))]
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub(crate) fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+#[cfg(test)]
+mod http_tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(
+            Endpoint::parse("HTTP://example.com:8080/a/b?c=d"),
+            Ok(Endpoint {
+                host: "example.com".to_string(),
+                port: 8080,
+                path: "/a/b?c=d".to_string(),
+                tls: false,
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("http://example.com").map(|endpoint| endpoint.url()),
+            Ok("http://example.com:80/".to_string())
+        );
+        assert_eq!(
+            Endpoint::parse("HTTPS://example.com/records.csv").map(|endpoint| endpoint.url()),
+            Ok("https://example.com:443/records.csv".to_string())
+        );
+        for invalid in [
+            "example.com",
+            "ftp://example.com",
+            "http://",
+            "http://a:port/",
+        ] {
+            assert!(Endpoint::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_write_request_and_read_head() {
+        let endpoint = Endpoint::parse("http://localhost:8080/ingest").expect("Should parse");
+        let mut request = Vec::new();
+        endpoint
+            .write_request(
+                &mut request,
+                "POST",
+                &[("Content-Type".to_string(), "application/json".to_string())],
+                b"[]",
+            )
+            .expect("Should write");
+        assert_eq!(
+            String::from_utf8(request).expect("Should be UTF-8"),
+            "POST /ingest HTTP/1.1\r\nHost: localhost:8080\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: 2\r\n\r\n[]"
+        );
+
+        let mut response = &b"HTTP/1.1 404 Not Found\r\ncontent-length: 3\r\n\r\nabc"[..];
+        let head = ResponseHead::read(&mut response).expect("Should read head");
+        assert_eq!(head.status, 404);
+        assert!(!head.is_success());
+        assert_eq!(head.header("Content-Length"), Some("3"));
+        assert_eq!(response, b"abc");
+
+        assert!(ResponseHead::read(&mut &b"garbage\r\n\r\n"[..]).is_err());
+        assert_eq!(
+            ResponseHead::read(&mut &b"HTTP/1.1 200 OK\r\n"[..]),
+            Err(ParseError::UnexpectedEOF)
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use crate::error::ParseError;
+use crate::http::{Endpoint, ResponseHead};
+use crate::pipeline::Stage;
+use crate::record::YPBankRecord;
+use crate::schema::CSV_FIELDS;
+use std::fmt::Write as _;
+use std::io::{BufReader, Read};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_BATCH_SIZE: usize = 500;
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Writes `s` as a JSON string.
+fn push_json_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Renders a record as a JSON object keyed by the YPBank field names.
+pub(crate) fn record_to_json(record: &YPBankRecord) -> String {
+    let mut json = String::from("{");
+    let numbers = [
+        (CSV_FIELDS[0], record.id.to_string()),
+        (CSV_FIELDS[2], record.from_user_id.to_string()),
+        (CSV_FIELDS[3], record.to_user_id.to_string()),
+        (CSV_FIELDS[4], record.amount.to_string()),
+        (CSV_FIELDS[5], record.ts.to_string()),
+    ];
+    let strings = [
+        (CSV_FIELDS[1], record.transaction_type.as_str()),
+        (CSV_FIELDS[6], record.status.as_str()),
+        (CSV_FIELDS[7], record.description.as_ref()),
+    ];
+    for (name, value) in numbers {
+        let _ = write!(json, "\"{}\":{},", name, value);
+    }
+    for (name, value) in strings {
+        let _ = write!(json, "\"{}\":", name);
+        push_json_str(&mut json, value);
+        json.push(',');
+    }
+    json.pop();
+    json.push('}');
+    json
+}
+
+/// Failure of one POST, and whether sending it again may succeed.
+struct PostError {
+    err: ParseError,
+    retry: bool,
+}
+
+/// Records waiting to be posted and counters of what was posted.
+#[derive(Debug, Default)]
+struct Batch {
+    records: Vec<String>,
+    posted: u64,
+    requests: u64,
+}
+
+/// Posts records as JSON arrays to an HTTP endpoint, in batches.
+///
+/// Every batch is one `POST` with `Content-Type: application/json` and a body such as
+/// `[{"TX_ID":1,...,"STATUS":"SUCCESS","DESCRIPTION":"Salary"}]`. Connection failures,
+/// timeouts and `408`, `429` and `5xx` responses are retried with exponential backoff;
+/// other non-`2xx` responses fail at once. Only plain `http://` endpoints are supported,
+/// TLS is left to a local proxy.
+///
+/// The sink is a [`Stage`] behind an [`Arc`], passing records on unchanged, so it can be
+/// added to a [`crate::Pipeline`] while keeping a handle to [`HttpSink::flush`] the last
+/// batch:
+///
+/// ```no_run
+/// use parser::{HttpSink, Pipeline};
+/// use std::sync::Arc;
+///
+/// let sink = Arc::new(HttpSink::new("http://localhost:8080/ingest").unwrap().with_batch_size(100));
+/// let pipeline = Pipeline::new().with_stage(sink.clone());
+/// let records = pipeline.run(parser::fixtures::records()).unwrap();
+/// sink.flush().unwrap();
+/// assert_eq!(sink.posted(), records.len() as u64);
+/// ```
+#[derive(Debug)]
+pub struct HttpSink {
+    endpoint: Endpoint,
+    batch_size: usize,
+    retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+    batch: Mutex<Batch>,
+}
+
+impl HttpSink {
+    /// Creates a sink posting to `url`, e.g. `http://localhost:8080/ingest`.
+    pub fn new(url: &str) -> Result<Self, ParseError> {
+        let endpoint = Endpoint::parse(url)?;
+        if endpoint.tls {
+            return Err(ParseError::UnsupportedOperation(format!(
+                "{} needs TLS, which only URL inputs support; post through a TLS-terminating proxy",
+                url
+            )));
+        }
+        Ok(Self {
+            endpoint,
+            batch_size: DEFAULT_BATCH_SIZE,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+            timeout: DEFAULT_TIMEOUT,
+            batch: Mutex::new(Batch::default()),
+        })
+    }
+
+    /// Sets the number of records per request, 500 by default.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets how often a failed request is retried, waiting `backoff` before the first
+    /// retry and twice as long before each further one. Defaults to 3 retries after 500 ms.
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets the connect, read and write timeout of requests, 30 seconds by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adds a record to the batch, posting the batch once it is full.
+    pub fn send(&self, record: &YPBankRecord) -> Result<(), ParseError> {
+        let mut batch = self.lock();
+        batch.records.push(record_to_json(record));
+        if batch.records.len() >= self.batch_size {
+            self.post_batch(&mut batch)?;
+        }
+        Ok(())
+    }
+
+    /// Sends all records and posts the last batch, returning the number of records sent.
+    ///
+    /// Stops at the first error.
+    pub fn send_all(
+        &self,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<u64, ParseError> {
+        let mut sent = 0;
+        for record in records {
+            self.send(&record?)?;
+            sent += 1;
+        }
+        self.flush()?;
+        Ok(sent)
+    }
+
+    /// Posts the records of an incomplete batch.
+    pub fn flush(&self) -> Result<(), ParseError> {
+        let mut batch = self.lock();
+        if batch.records.is_empty() {
+            return Ok(());
+        }
+        self.post_batch(&mut batch)
+    }
+
+    /// Posts one JSON document, such as an [`crate::Alert`], outside of the batches.
+    ///
+    /// Failures are retried like batches.
+    pub fn post_json(&self, json: &str) -> Result<(), ParseError> {
+        self.post_retrying(json.as_bytes())
+    }
+
+    /// Returns the number of records posted successfully.
+    pub fn posted(&self) -> u64 {
+        self.lock().posted
+    }
+
+    /// Returns the number of successful requests.
+    pub fn requests(&self) -> u64 {
+        self.lock().requests
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Batch> {
+        self.batch
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Posts the batch, keeping it for a later flush when every attempt fails.
+    fn post_batch(&self, batch: &mut Batch) -> Result<(), ParseError> {
+        let body = format!("[{}]", batch.records.join(","));
+        self.post_retrying(body.as_bytes())?;
+        batch.posted += batch.records.len() as u64;
+        batch.requests += 1;
+        batch.records.clear();
+        Ok(())
+    }
+
+    /// Posts `body`, retrying with backoff while the failure may pass.
+    fn post_retrying(&self, body: &[u8]) -> Result<(), ParseError> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match self.post(body) {
+                Ok(()) => return Ok(()),
+                Err(PostError { err, retry }) if !retry || attempt == self.retries => {
+                    return Err(err);
+                }
+                Err(_) => {
+                    attempt += 1;
+                    std::thread::sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                }
+            }
+        }
+    }
+
+    fn post(&self, body: &[u8]) -> Result<(), PostError> {
+        let retry = |err: ParseError| PostError { err, retry: true };
+        let mut stream = self
+            .endpoint
+            .connect(self.timeout)
+            .map_err(|err| retry(err.into()))?;
+        let headers = [("Content-Type".to_string(), "application/json".to_string())];
+        self.endpoint
+            .write_request(&mut stream, "POST", &headers, body)
+            .map_err(|err| retry(err.into()))?;
+
+        let mut response = BufReader::new(stream);
+        let head = ResponseHead::read(&mut response).map_err(retry)?;
+        // The body is not needed, but reading it lets the server close cleanly.
+        let _ = response.read_to_end(&mut Vec::new());
+        if head.is_success() {
+            return Ok(());
+        }
+        Err(PostError {
+            err: ParseError::IOError(format!(
+                "POST to {} answered {}",
+                self.endpoint.url(),
+                head.status
+            )),
+            retry: matches!(head.status, 408 | 429 | 500..=599),
+        })
+    }
+}
+
+impl Stage for Arc<HttpSink> {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        self.send(&record)?;
+        Ok(Some(record))
+    }
+}
+
+#[cfg(test)]
+mod http_sink_tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::pipeline::Pipeline;
+    use std::io::{BufRead, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// Serves one connection per status, returning the request bodies received.
+    fn serve(statuses: Vec<u16>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Should bind");
+        let url = format!(
+            "http://{}/ingest",
+            listener.local_addr().expect("Should have address")
+        );
+        let handle = std::thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().expect("Should accept");
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                let mut line = String::new();
+                reader
+                    .read_line(&mut line)
+                    .expect("Should read request line");
+                assert_eq!(line, "POST /ingest HTTP/1.1\r\n");
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).expect("Should read header");
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().expect("Should be a length");
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).expect("Should read body");
+                bodies.push(String::from_utf8(body).expect("Should be UTF-8"));
+                write!(
+                    reader.get_mut(),
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\n\r\n",
+                    status
+                )
+                .expect("Should respond");
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_record_to_json() {
+        let mut record = fixtures::records().remove(0);
+        record.description = "Line \"one\"\n\u{1}".into();
+        assert_eq!(
+            record_to_json(&record),
+            format!(
+                "{{\"TX_ID\":{},\"FROM_USER_ID\":0,\"TO_USER_ID\":1,\"AMOUNT\":100,\"TIMESTAMP\":{},\
+                 \"TX_TYPE\":\"DEPOSIT\",\"STATUS\":\"SUCCESS\",\"DESCRIPTION\":\"Line \\\"one\\\"\\n\\u0001\"}}",
+                record.id, record.ts
+            )
+        );
+    }
+
+    #[test]
+    fn test_batches_and_retries() {
+        let (url, server) = serve(vec![503, 200, 200]);
+        let sink = Arc::new(
+            HttpSink::new(&url)
+                .expect("Should parse URL")
+                .with_batch_size(2)
+                .with_retries(1, Duration::from_millis(1)),
+        );
+        let records: Vec<YPBankRecord> = fixtures::records().into_iter().take(3).collect();
+
+        let passed = Pipeline::new()
+            .with_stage(sink.clone())
+            .run(records.clone())
+            .expect("Should post records");
+        sink.flush().expect("Should post last batch");
+        assert_eq!(passed, records);
+        assert_eq!((sink.posted(), sink.requests()), (3, 2));
+
+        let bodies = server.join().expect("Should serve");
+        let json = |records: &[YPBankRecord]| {
+            let records: Vec<String> = records.iter().map(record_to_json).collect();
+            format!("[{}]", records.join(","))
+        };
+        assert_eq!(
+            bodies,
+            vec![
+                json(&records[..2]),
+                json(&records[..2]),
+                json(&records[2..])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_client_error_is_not_retried() {
+        let (url, server) = serve(vec![400]);
+        let sink = HttpSink::new(&url)
+            .expect("Should parse URL")
+            .with_retries(3, Duration::from_millis(1));
+
+        let result = sink.send_all(fixtures::records().into_iter().take(1).map(Ok));
+        assert!(
+            matches!(result, Err(ParseError::IOError(message)) if message.ends_with("answered 400"))
+        );
+        assert_eq!(server.join().expect("Should serve").len(), 1);
+        assert_eq!(sink.posted(), 0);
+
+        let (url, server) = serve(vec![503, 200]);
+        let sink = HttpSink::new(&url)
+            .expect("Should parse URL")
+            .with_retries(1, Duration::from_millis(1));
+        sink.post_json("{\"value\":1}").expect("Should post");
+        assert_eq!(
+            server.join().expect("Should serve"),
+            vec!["{\"value\":1}", "{\"value\":1}"]
+        );
+        assert_eq!((sink.posted(), sink.requests()), (0, 0));
+        assert!(matches!(
+            HttpSink::new("https://localhost/ingest"),
+            Err(ParseError::UnsupportedOperation(_))
+        ));
+    }
+}
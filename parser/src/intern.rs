@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A thread-safe pool of shared descriptions.
+///
+/// Cloning the pool is cheap and the clones share the same strings, so one pool can be
+/// used by parsers running in different threads.
+///
+/// # Examples
+///
+/// ```
+/// use parser::DescriptionPool;
+/// use std::sync::Arc;
+///
+/// let pool = DescriptionPool::new();
+/// let first = pool.intern("ATM WITHDRAWAL");
+/// let second = pool.clone().intern("ATM WITHDRAWAL");
+/// assert!(Arc::ptr_eq(&first, &second));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DescriptionPool {
+    strings: Arc<Mutex<HashSet<Arc<str>>>>,
+}
+
+impl DescriptionPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the pooled copy of `value`, adding it to the pool if needed.
+    pub fn intern(&self, value: &str) -> Arc<str> {
+        let mut strings = self.strings.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(interned) = strings.get(value) {
+            return interned.clone();
+        }
+
+        let interned: Arc<str> = Arc::from(value);
+        strings.insert(interned.clone());
+        interned
+    }
+
+    /// Returns the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Pools are equal when they share the same strings.
+impl PartialEq for DescriptionPool {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.strings, &other.strings)
+    }
+}
+
+impl Eq for DescriptionPool {}
+
+#[cfg(test)]
+mod description_pool_tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_strings_across_threads() {
+        let pool = DescriptionPool::new();
+        let first = pool.intern("ATM WITHDRAWAL");
+
+        let handles = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || pool.intern("ATM WITHDRAWAL"))
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            let interned = handle.join().expect("Thread should not panic");
+            assert!(Arc::ptr_eq(&first, &interned));
+        }
+
+        pool.intern("SALARY");
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_eq_compares_shared_storage() {
+        let pool = DescriptionPool::new();
+        assert_eq!(pool, pool.clone());
+        assert_ne!(pool, DescriptionPool::new());
+    }
+}
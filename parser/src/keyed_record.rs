@@ -0,0 +1,244 @@
+use crate::common::{
+    TransactionStatus, TransactionType, read_u32_from_bytes, validate_from_user_id,
+    validate_to_user_id,
+};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::str::FromStr;
+
+/// Map keys used by the self-describing encodings (MessagePack, CBOR).
+pub const FIELDS: [&str; 8] = [
+    "tx_id",
+    "tx_type",
+    "from_user_id",
+    "to_user_id",
+    "amount",
+    "timestamp",
+    "status",
+    "description",
+];
+
+/// A decoded map value. Integers are widened so that both `u64` and `i64` fit.
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyedValue {
+    Int(i128),
+    Str(String),
+}
+
+impl KeyedValue {
+    fn into_int<T: TryFrom<i128>>(self, field: &str) -> Result<T, ParseError> {
+        match self {
+            KeyedValue::Int(val) => T::try_from(val).map_err(|_| ParseError::ValueOutOfRange {
+                field: field.to_uppercase(),
+                value: val.to_string(),
+            }),
+            KeyedValue::Str(val) => Err(ParseError::InvalidRawValue {
+                field: field.to_uppercase(),
+                value: val,
+            }),
+        }
+    }
+
+    fn into_string(self, field: &str) -> Result<String, ParseError> {
+        match self {
+            KeyedValue::Str(val) => Ok(val),
+            KeyedValue::Int(val) => Err(ParseError::InvalidRawValue {
+                field: field.to_uppercase(),
+                value: val.to_string(),
+            }),
+        }
+    }
+}
+
+/// Builds a record from decoded key/value pairs, in any order.
+pub fn from_keyed_values(entries: Vec<(String, KeyedValue)>) -> Result<YPBankRecord, ParseError> {
+    let mut values: [Option<KeyedValue>; 8] = Default::default();
+    for (key, value) in entries {
+        let Some(index) = FIELDS.iter().position(|field| *field == key) else {
+            return Err(ParseError::InvalidRow(key));
+        };
+        if values[index].replace(value).is_some() {
+            return Err(ParseError::InconsistentRecord(format!(
+                "duplicate key {}",
+                key
+            )));
+        }
+    }
+
+    let mut fields = vec![];
+    for (field, value) in FIELDS.iter().zip(values) {
+        match value {
+            Some(value) => fields.push(value),
+            None => return Err(ParseError::FieldNotFound(field.to_string())),
+        }
+    }
+
+    let mut fields = fields.into_iter().zip(FIELDS);
+    let mut next = || fields.next().expect("all fields are present");
+
+    let (id, field) = next();
+    let id = id.into_int(field)?;
+    let (transaction_type, field) = next();
+    let transaction_type = TransactionType::from_str(&transaction_type.into_string(field)?)?;
+    let (from_user_id, field) = next();
+    let from_user_id = validate_from_user_id(from_user_id.into_int(field)?, transaction_type)?;
+    let (to_user_id, field) = next();
+    let to_user_id = validate_to_user_id(to_user_id.into_int(field)?, transaction_type)?;
+    let (amount, field) = next();
+    let amount = amount.into_int(field)?;
+    let (ts, field) = next();
+    let ts = ts.into_int(field)?;
+    let (status, field) = next();
+    let status = TransactionStatus::from_str(&status.into_string(field)?)?;
+    let (description, field) = next();
+    let description = description.into_string(field)?;
+
+    Ok(YPBankRecord::new(
+        id,
+        transaction_type,
+        from_user_id,
+        to_user_id,
+        amount,
+        ts,
+        status,
+        description,
+    ))
+}
+
+/// Reads one length-prefixed frame into `buf`.
+///
+/// Returns `Ok(false)` on a clean end of input before the length prefix.
+pub fn read_frame<R: std::io::BufRead>(r: &mut R, buf: &mut Vec<u8>) -> Result<bool, ParseError> {
+    if r.fill_buf()?.is_empty() {
+        return Ok(false);
+    }
+
+    let len = read_u32_from_bytes(r)? as usize;
+    buf.clear();
+    buf.resize(len, 0);
+    r.read_exact(buf)?;
+
+    Ok(true)
+}
+
+/// Writes `payload` as one length-prefixed frame.
+pub fn write_frame<W: std::io::Write>(w: &mut W, payload: &[u8]) -> Result<(), ParseError> {
+    let len = u32::try_from(payload.len())
+        .map_err(|_| ParseError::InconsistentRecord("record is too large".to_string()))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(payload)?;
+    Ok(())
+}
+
+/// A forward-only reader over a decoded frame.
+pub struct FrameReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FrameReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| ParseError::InconsistentRecord("truncated frame".to_string()))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    /// Reads a big-endian unsigned integer of `len` bytes (at most 8).
+    pub fn read_uint(&mut self, len: usize) -> Result<u64, ParseError> {
+        Ok(self
+            .read_bytes(len)?
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    pub fn read_string(&mut self, len: usize) -> Result<String, ParseError> {
+        std::str::from_utf8(self.read_bytes(len)?)
+            .map(str::to_string)
+            .map_err(|err| ParseError::InvalidRawValue {
+                field: "string".to_string(),
+                value: err.to_string(),
+            })
+    }
+
+    pub fn finish(&self) -> Result<(), ParseError> {
+        if self.pos != self.data.len() {
+            return Err(ParseError::InconsistentRecord(
+                "unexpected bytes after record".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod keyed_record_tests {
+    use super::*;
+
+    fn entries(tx_type: &str, from: i128) -> Vec<(String, KeyedValue)> {
+        vec![
+            (
+                "description".to_string(),
+                KeyedValue::Str("desc".to_string()),
+            ),
+            ("tx_id".to_string(), KeyedValue::Int(1)),
+            ("tx_type".to_string(), KeyedValue::Str(tx_type.to_string())),
+            ("from_user_id".to_string(), KeyedValue::Int(from)),
+            ("to_user_id".to_string(), KeyedValue::Int(2)),
+            ("amount".to_string(), KeyedValue::Int(-5)),
+            ("timestamp".to_string(), KeyedValue::Int(10)),
+            ("status".to_string(), KeyedValue::Str("SUCCESS".to_string())),
+        ]
+    }
+
+    #[test]
+    fn test_from_keyed_values_any_order() {
+        let record = from_keyed_values(entries("TRANSFER", 3)).expect("Should build a record");
+        assert_eq!(
+            record,
+            YPBankRecord::new(
+                1,
+                TransactionType::Transfer,
+                3,
+                2,
+                -5,
+                10,
+                TransactionStatus::Success,
+                "desc".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn test_from_keyed_values_out_of_range() {
+        let result = from_keyed_values(entries("TRANSFER", -1));
+        assert_eq!(
+            result,
+            Err(ParseError::ValueOutOfRange {
+                field: "FROM_USER_ID".to_string(),
+                value: "-1".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_keyed_values_missing_field() {
+        let mut values = entries("TRANSFER", 3);
+        values.pop();
+        let result = from_keyed_values(values);
+        assert_eq!(result, Err(ParseError::FieldNotFound("status".to_string())));
+    }
+}
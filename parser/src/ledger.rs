@@ -0,0 +1,338 @@
+use crate::common::{TransactionStatus, TransactionType, parse_value_from_str};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::toml_format::YPBankTomlRecordParser;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+const ACCOUNTS_TABLE: &str = "accounts";
+const USERS_TABLE: &str = "users";
+const USER_PLACEHOLDER: &str = "{user}";
+const DEFAULT_CASH_ACCOUNT: &str = "CASH";
+const DEFAULT_USER_ACCOUNT: &str = "USER-{user}";
+const LEDGER_HEADER: &str = "TX_ID,TIMESTAMP,ACCOUNT,DEBIT,CREDIT,DESCRIPTION";
+
+/// Side of the ledger a line is booked on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+/// One line of a double-entry booking: `amount` debited or credited to `account`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerLine {
+    pub tx_id: u64,
+    pub ts: u64,
+    pub account: String,
+    pub side: EntrySide,
+    pub amount: u64,
+    pub description: Arc<str>,
+}
+
+/// Accounts that records are booked to by [`AccountMap::expand`].
+///
+/// Money entering and leaving the bank goes through the cash account; every user has an
+/// account named after the user template, where `{user}` stands for the user id, unless
+/// the user is given an account of its own. Maps are usually loaded from TOML:
+///
+/// ```toml
+/// [accounts]
+/// cash = "1000"
+/// user = "2000-{user}"
+///
+/// [users]
+/// 42 = "3100"
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use parser::AccountMap;
+///
+/// let accounts = AccountMap::new()
+///     .with_cash_account("1000")
+///     .with_user_account("2000-{user}")
+///     .with_user(42, "3100");
+/// assert_eq!(accounts.user_account(7), "2000-7");
+///
+/// let toml = "[accounts]\ncash = \"1000\"\nuser = \"2000-{user}\"\n[users]\n42 = \"3100\"\n";
+/// assert_eq!(AccountMap::from_toml(toml).unwrap(), accounts);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountMap {
+    cash: String,
+    user: String,
+    users: HashMap<u64, String>,
+}
+
+impl Default for AccountMap {
+    /// Books to `CASH` and `USER-{user}` accounts.
+    fn default() -> Self {
+        Self {
+            cash: DEFAULT_CASH_ACCOUNT.to_string(),
+            user: DEFAULT_USER_ACCOUNT.to_string(),
+            users: HashMap::new(),
+        }
+    }
+}
+
+impl AccountMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the account of money entering the bank with deposits and leaving it with
+    /// withdrawals.
+    pub fn with_cash_account(mut self, account: &str) -> Self {
+        self.cash = account.to_string();
+        self
+    }
+
+    /// Sets the template of user accounts, where `{user}` stands for the user id.
+    pub fn with_user_account(mut self, template: &str) -> Self {
+        self.user = template.to_string();
+        self
+    }
+
+    /// Books the records of `user_id` to `account` instead of the user template.
+    pub fn with_user(mut self, user_id: u64, account: &str) -> Self {
+        self.users.insert(user_id, account.to_string());
+        self
+    }
+
+    pub fn cash_account(&self) -> &str {
+        &self.cash
+    }
+
+    /// Returns the account of `user_id`.
+    pub fn user_account(&self, user_id: u64) -> String {
+        match self.users.get(&user_id) {
+            Some(account) => account.clone(),
+            None => self.user.replace(USER_PLACEHOLDER, &user_id.to_string()),
+        }
+    }
+
+    /// Reads a map from TOML with an `[accounts]` table setting `cash` and `user`, and
+    /// a `[users]` table of accounts by user id.
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut accounts = Self::new();
+        let mut table = None;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let Some((name, _)) = name.split_once(']') else {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                };
+                table = Some(name.trim().to_string());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let key = key.trim().trim_matches('"');
+            let value = value.trim();
+            if !value.starts_with(['"', '\'']) {
+                return Err(ParseError::InvalidRawValue {
+                    field: key.to_string(),
+                    value: value.to_string(),
+                });
+            }
+            let value = YPBankTomlRecordParser::parse_raw_value(key, value)?;
+            accounts = match (table.as_deref(), key) {
+                (Some(ACCOUNTS_TABLE), "cash") => accounts.with_cash_account(&value),
+                (Some(ACCOUNTS_TABLE), "user") => accounts.with_user_account(&value),
+                (Some(USERS_TABLE), user_id) => {
+                    accounts.with_user(parse_value_from_str(USERS_TABLE, user_id)?, &value)
+                }
+                _ => return Err(ParseError::InvalidRow(line.to_string())),
+            };
+        }
+
+        Ok(accounts)
+    }
+
+    /// Reads a TOML map from a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Expands a record into its debit and credit lines, or `None` for records that did
+    /// not succeed, which move no money.
+    ///
+    /// User accounts are what the bank owes its users: a deposit debits the cash account
+    /// and credits the receiving user, a withdrawal debits the paying user and credits
+    /// the cash account, and a transfer debits the paying user and credits the receiving
+    /// one. Negative amounts swap the sides.
+    pub fn expand(&self, record: &YPBankRecord) -> Option<[LedgerLine; 2]> {
+        if record.status != TransactionStatus::Success {
+            return None;
+        }
+        let (debit, credit) = match record.transaction_type {
+            TransactionType::Deposit => (self.cash.clone(), self.user_account(record.to_user_id)),
+            TransactionType::Withdrawal => {
+                (self.user_account(record.from_user_id), self.cash.clone())
+            }
+            TransactionType::Transfer => (
+                self.user_account(record.from_user_id),
+                self.user_account(record.to_user_id),
+            ),
+        };
+        let (debit, credit) = match record.amount < 0 {
+            true => (credit, debit),
+            false => (debit, credit),
+        };
+        let line = |account, side| LedgerLine {
+            tx_id: record.id,
+            ts: record.ts,
+            account,
+            side,
+            amount: record.amount.unsigned_abs(),
+            description: record.description.clone(),
+        };
+        Some([
+            line(debit, EntrySide::Debit),
+            line(credit, EntrySide::Credit),
+        ])
+    }
+}
+
+/// Writes the ledger lines of records as CSV.
+///
+/// Every successful record becomes a debit line and a credit line of the same amount,
+/// see [`AccountMap::expand`], under a `TX_ID,TIMESTAMP,ACCOUNT,DEBIT,CREDIT,DESCRIPTION`
+/// header; the column of the other side holds `0`.
+pub struct LedgerWriter {}
+
+impl LedgerWriter {
+    /// Writes the lines of `records`, returning the number of lines written.
+    pub fn write_iter<W: Write>(
+        w: &mut W,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        accounts: &AccountMap,
+    ) -> Result<u64, ParseError> {
+        let mut w = std::io::BufWriter::new(w);
+        writeln!(w, "{}", LEDGER_HEADER)?;
+        let mut written = 0;
+        for record in records {
+            for line in accounts.expand(&record?).into_iter().flatten() {
+                let (debit, credit) = match line.side {
+                    EntrySide::Debit => (line.amount, 0),
+                    EntrySide::Credit => (0, line.amount),
+                };
+                writeln!(
+                    w,
+                    "{},{},{},{},{},{}",
+                    line.tx_id, line.ts, line.account, debit, credit, line.description
+                )?;
+                written += 1;
+            }
+        }
+        w.flush()?;
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod ledger_tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_expand_balances_every_type() {
+        let accounts = AccountMap::new().with_user(2, "TREASURY");
+        let record = |transaction_type, from_user_id, to_user_id, amount| {
+            YPBankRecord::new(
+                1,
+                transaction_type,
+                from_user_id,
+                to_user_id,
+                amount,
+                0,
+                TransactionStatus::Success,
+                "",
+            )
+        };
+        let accounts_of = |record: &YPBankRecord| {
+            let [debit, credit] = accounts.expand(record).expect("Should expand");
+            assert_eq!(
+                (debit.side, credit.side),
+                (EntrySide::Debit, EntrySide::Credit)
+            );
+            assert_eq!(debit.amount, credit.amount);
+            (debit.account, credit.account, debit.amount)
+        };
+
+        assert_eq!(
+            accounts_of(&record(TransactionType::Deposit, 0, 1, 100)),
+            ("CASH".to_string(), "USER-1".to_string(), 100)
+        );
+        assert_eq!(
+            accounts_of(&record(TransactionType::Withdrawal, 1, 0, 100)),
+            ("USER-1".to_string(), "CASH".to_string(), 100)
+        );
+        assert_eq!(
+            accounts_of(&record(TransactionType::Transfer, 1, 2, 100)),
+            ("USER-1".to_string(), "TREASURY".to_string(), 100)
+        );
+        assert_eq!(
+            accounts_of(&record(TransactionType::Transfer, 1, 2, -100)),
+            ("TREASURY".to_string(), "USER-1".to_string(), 100)
+        );
+
+        assert_eq!(
+            accounts_of(&record(TransactionType::Transfer, 1, 2, i64::MIN)),
+            ("TREASURY".to_string(), "USER-1".to_string(), 1 << 63)
+        );
+
+        let mut pending = record(TransactionType::Deposit, 0, 1, 100);
+        pending.status = TransactionStatus::Pending;
+        assert_eq!(accounts.expand(&pending), None);
+    }
+
+    #[test]
+    fn test_from_toml() {
+        assert_eq!(
+            AccountMap::from_toml(
+                "# chart of accounts\n[accounts]\nuser = \"2000-{user}\"\n\n[users]\n\"7\" = \"3100\"\n"
+            ),
+            Ok(AccountMap::new()
+                .with_user_account("2000-{user}")
+                .with_user(7, "3100"))
+        );
+        for invalid in [
+            "cash = \"1000\"",
+            "[accounts]\nbank = \"1000\"",
+            "[users]\nalice = \"3100\"",
+            "[accounts]\ncash = 1000",
+        ] {
+            assert!(AccountMap::from_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_write_iter() {
+        let records = fixtures::records().into_iter().take(4).map(Ok);
+        let mut written = Vec::new();
+        let lines = LedgerWriter::write_iter(&mut written, records, &AccountMap::new())
+            .expect("Should write");
+        assert_eq!(lines, 6);
+        assert_eq!(
+            String::from_utf8(written).expect("Should be UTF-8"),
+            "TX_ID,TIMESTAMP,ACCOUNT,DEBIT,CREDIT,DESCRIPTION\n\
+             1000000000000000,1633046460000,CASH,100,0,Record number 1\n\
+             1000000000000000,1633046460000,USER-1,0,100,Record number 1\n\
+             1000000000000001,1633046520000,USER-2,200,0,Record number 2\n\
+             1000000000000001,1633046520000,USER-3,0,200,Record number 2\n\
+             1000000000000002,1633046580000,USER-3,300,0,Record number 3\n\
+             1000000000000002,1633046580000,CASH,0,300,Record number 3\n"
+        );
+    }
+}
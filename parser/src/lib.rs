@@ -1,28 +1,167 @@
+mod alert;
+mod atomic;
+mod balances;
+mod batch;
 mod bin_format;
+mod block;
+mod bloom;
+mod business_day;
+mod calendar;
+#[cfg(feature = "cbor")]
+mod cbor_format;
+mod channel;
+pub mod cli;
+mod column_stats;
+mod comment;
 mod common;
+mod compact;
+pub mod conformance;
 mod constant;
+mod count;
+mod coverage;
 mod csv_format;
+mod delta;
+mod digest;
+mod dyn_parser;
+mod erasure;
 mod error;
+mod exchange_rate;
+mod filter;
+pub mod fixtures;
+mod handle;
+mod html_format;
+#[cfg(any(feature = "http-sink", feature = "http-source"))]
+mod http;
+#[cfg(feature = "http-sink")]
+mod http_sink;
+#[cfg(feature = "http-source")]
+mod http_source;
+mod intern;
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+mod keyed_record;
+mod ledger;
+mod lifecycle;
+mod locale;
+mod merge;
+#[cfg(feature = "msgpack")]
+mod msgpack_format;
+mod options;
+mod parallel;
 mod parser;
+mod pg_copy;
+mod pipeline;
+mod query;
 mod record;
+mod record_eq;
+mod retention;
+mod rule_expr;
+mod schema;
+mod seen_ids;
+mod sort;
+pub mod spec;
+mod stats;
+mod store;
+pub mod testdata;
+mod timestamp_log;
+mod tombstone;
+mod toml_format;
+mod totals;
 mod txt_format;
+#[cfg(feature = "tz")]
+mod tz;
+#[cfg(feature = "wasm-rules")]
+mod wasm_rule;
+#[cfg(feature = "xlsx")]
+mod xlsx_format;
 
 use bin_format::{BinParser, YPBankBinRecordParser};
+#[cfg(feature = "cbor")]
+use cbor_format::{CborParser, YPBankCborRecordParser};
 use csv_format::{CsvParser, YPBankCsvRecordParser};
-use parser::Parser;
+use html_format::HtmlWriter;
+#[cfg(feature = "msgpack")]
+use msgpack_format::{MsgPackParser, YPBankMsgPackRecordParser};
+use parser::{Parser, RecordSource};
+use pg_copy::PgCopyWriter;
+use std::ops::ControlFlow;
+use toml_format::{TomlParser, YPBankTomlRecordParser};
 use txt_format::{TxtParser, YPBankTxtRecordParser};
+#[cfg(feature = "xlsx")]
+use xlsx_format::XlsxWriter;
 
+pub use alert::{Alert, AlertRule, Alerts};
+pub use atomic::write_atomic;
+pub use balances::{Balance, BalanceFormat, Balances};
+pub use batch::{Batch, BatchMetadata};
+pub use bin_format::{encode_bin_record, write_records_buffered};
+pub use block::{BlockHeader, BlockReader, BlockWriter, Codec, Codecs, Lz, Stored};
+pub use bloom::IdBloom;
+pub use business_day::{
+    BUSINESS_DATE_EXTRA, BusinessCalendar, DAY_KIND_EXTRA, DayKind, DayKindFilter,
+};
+pub use channel::spawn_reader;
+pub use column_stats::ColumnStats;
+pub use comment::{Comment, CommentLog};
 pub use common::{Format, TransactionStatus, TransactionType};
-pub use error::ParseError;
-pub use record::YPBankRecord;
+pub use compact::{CompactRecord, CompactRecords};
+pub use count::count_records;
+pub use coverage::{Coverage, coverage};
+pub use delta::{Delta, DeltaOp};
+pub use digest::Sha256;
+pub use dyn_parser::{DynRecordParser, DynRecords, ParserRegistry};
+pub use erasure::{ErasureMode, ErasureReport, UserErasure, erase_user_in_file};
+pub use error::{ErrorCode, ParseError};
+pub use exchange_rate::ExchangeRates;
+pub use filter::RecordFilter;
+pub use handle::{Direction, ReaderHandle, UnsupportedDirection, WriterHandle};
+#[cfg(feature = "http-sink")]
+pub use http_sink::HttpSink;
+#[cfg(feature = "http-source")]
+pub use http_source::{HttpBody, HttpSource, HttpStream};
+pub use intern::DescriptionPool;
+pub use ledger::{AccountMap, EntrySide, LedgerLine, LedgerWriter};
+pub use lifecycle::{TransactionHistory, final_states, link_transactions};
+pub use locale::AmountFormat;
+pub use merge::{Conflict, ConflictStrategy, Merged, Side, merge, merge_by};
+pub use options::{
+    CsvEmptyLines, CsvHeader, CsvIdFormat, CsvLimits, CsvQuotes, CsvRepeatedHeaders,
+    DescriptionRedaction, NumericPolicy, ParseOptions, TimestampCheck, TimestampUnit,
+    TimestampWindow, TxtComments, TxtSeparator,
+};
+pub use pg_copy::PgTable;
+pub use pipeline::{
+    Enricher, Pipeline, REDACTED_USER_ID, RedactionProfile, Redactor, Stage, Transform,
+    Transformer, UserFilter, UserSide,
+};
+pub use query::{Field, Predicate, QueryEngine};
+pub use record::{YPBankRecord, YPBankRecordExt};
+pub use record_eq::{ById, Exact, RecordEq, RecordKey, Semantic, dedup};
+pub use retention::{PruneLog, Retention, prune_file};
+pub use rule_expr::RuleExpr;
+pub use schema::{SchemaMap, UnknownColumns};
+pub use seen_ids::SeenIds;
+pub use sort::{MergeSorted, SortKey, merge_sorted, sort_file};
+pub use stats::{
+    AmountHistogram, Period, Stats, UserVolume, Window, WindowAggregator, WindowSummary, Windows,
+    windows,
+};
+pub use store::{AppliedSource, RecordStore};
+pub use timestamp_log::{ImplausibleTimestamp, TimestampLog};
+pub use tombstone::Tombstones;
+pub use totals::{Total, Totals, totals};
+#[cfg(feature = "tz")]
+pub use tz::TimeZone;
+#[cfg(feature = "wasm-rules")]
+pub use wasm_rule::WasmRule;
 
-/// A unified parser that can read and write bank records in multiple formats - CSV, TXT, and binary.
+/// A unified parser that can read and write bank records in multiple formats - CSV, TXT, binary and TOML.
 ///
 /// # Examples
 ///
-/// ```no_run
+/// ```
 /// use parser::{CommonParser, Format};
 /// use std::fs::File;
+/// # parser::fixtures::enter().unwrap();
 ///
 /// let parser = CommonParser::new(Format::Csv);
 /// let mut file = File::open("records.csv").unwrap();
@@ -30,11 +169,33 @@ pub use record::YPBankRecord;
 /// ```
 pub struct CommonParser {
     format: Format,
+    options: ParseOptions,
 }
 
 impl CommonParser {
     pub fn new(format: Format) -> Self {
-        Self { format }
+        Self::with_options(format, ParseOptions::default())
+    }
+
+    /// Creates a parser that reads records according to the given [`ParseOptions`].
+    pub fn with_options(format: Format, options: ParseOptions) -> Self {
+        Self { format, options }
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    fn read_unsupported(&self) -> ParseError {
+        UnsupportedDirection {
+            format: self.format,
+            direction: Direction::Read,
+        }
+        .into()
     }
 }
 
@@ -55,9 +216,10 @@ impl CommonParser {
     ///
     /// # Examples
     ///
-    /// ```no_run
+    /// ```
     /// use parser::{CommonParser, Format};
     /// use std::fs::File;
+    /// # parser::fixtures::enter().unwrap();
     ///
     /// let parser = CommonParser::new(Format::Csv);
     /// let mut file = File::open("records.csv").unwrap();
@@ -68,10 +230,284 @@ impl CommonParser {
         r: &mut Reader,
     ) -> Result<Vec<YPBankRecord>, ParseError> {
         match self.format {
-            Format::Csv => <CsvParser as Parser<YPBankCsvRecordParser>>::from_read(r),
-            Format::Txt => <TxtParser as Parser<YPBankTxtRecordParser>>::from_read(r),
-            Format::Bin => <BinParser as Parser<YPBankBinRecordParser>>::from_read(r),
+            Format::Csv => {
+                <CsvParser as Parser<YPBankCsvRecordParser>>::from_read(r, &self.options)
+            }
+            Format::Txt => {
+                <TxtParser as Parser<YPBankTxtRecordParser>>::from_read(r, &self.options)
+            }
+            Format::Bin => {
+                <BinParser as Parser<YPBankBinRecordParser>>::from_read(r, &self.options)
+            }
+            Format::Toml => {
+                <TomlParser as Parser<YPBankTomlRecordParser>>::from_read(r, &self.options)
+            }
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => {
+                <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::from_read(r, &self.options)
+            }
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                <CborParser as Parser<YPBankCborRecordParser>>::from_read(r, &self.options)
+            }
+            Format::Html | Format::PgCopy => Err(self.read_unsupported()),
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => Err(self.read_unsupported()),
+        }
+    }
+
+    /// Passes records to `f` as they are parsed, stopping as soon as `f` breaks.
+    ///
+    /// Unlike [`CommonParser::from_read`] no records are collected, and the input after
+    /// the break is not read. Returns the break value, or [`ControlFlow::Continue`] when
+    /// all records were passed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format};
+    /// use std::ops::ControlFlow;
+    ///
+    /// let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+    ///             1,DEPOSIT,0,2,100,1633036860000,SUCCESS,\"Salary\"\n\
+    ///             2,DEPOSIT,0,3,200,1633036860000,SUCCESS,\"Bonus\"\n\
+    ///             3,broken";
+    ///
+    /// let found = CommonParser::new(Format::Csv)
+    ///     .from_read_with(&mut data.as_bytes(), |record| match record.id {
+    ///         2 => ControlFlow::Break(record.amount),
+    ///         _ => ControlFlow::Continue(()),
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(found, ControlFlow::Break(200));
+    /// ```
+    pub fn from_read_with<Reader: std::io::Read, B>(
+        &self,
+        r: &mut Reader,
+        mut f: impl FnMut(YPBankRecord) -> ControlFlow<B>,
+    ) -> Result<ControlFlow<B>, ParseError> {
+        for record in self.iter_read(r)? {
+            if let ControlFlow::Break(value) = f(record?) {
+                return Ok(ControlFlow::Break(value));
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Returns an iterator reading records one at a time, so that large files are
+    /// not loaded into memory as a whole.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format};
+    /// use std::fs::File;
+    /// # parser::fixtures::enter().unwrap();
+    ///
+    /// let parser = CommonParser::new(Format::Csv);
+    /// for record in parser.iter_read(File::open("records.csv").unwrap()).unwrap() {
+    ///     println!("{:?}", record.unwrap());
+    /// }
+    /// ```
+    pub fn iter_read<'a, Reader: std::io::Read + 'a>(
+        &self,
+        r: Reader,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        let r = std::io::BufReader::with_capacity(self.options.read_buffer_capacity, r);
+        self.stream_at(r, 0)
+    }
+
+    /// Continues reading at `offset`, a value of [`RecordStream::offset`] saved
+    /// by an earlier read of the same input.
+    ///
+    /// The format header is read again from the start of `r` first, so that CSV columns
+    /// mapped by a [`SchemaMap`] are known when reading resumes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format};
+    /// use std::fs::File;
+    /// # parser::fixtures::enter().unwrap();
+    ///
+    /// let parser = CommonParser::new(Format::Csv);
+    /// let mut records = parser.iter_read(File::open("records.csv").unwrap()).unwrap();
+    /// records.next();
+    /// let offset = records.offset();
+    ///
+    /// let rest = parser.iter_read_at(File::open("records.csv").unwrap(), offset).unwrap();
+    /// ```
+    pub fn iter_read_at<'a, Reader: std::io::Read + std::io::Seek + 'a>(
+        &self,
+        r: Reader,
+        offset: u64,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        let r = std::io::BufReader::with_capacity(self.options.read_buffer_capacity, r);
+        let options = &self.options;
+        let inner: Box<dyn RecordSource + 'a> = match self.format {
+            Format::Csv => Box::new(
+                <CsvParser as Parser<YPBankCsvRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            Format::Txt => Box::new(
+                <TxtParser as Parser<YPBankTxtRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            Format::Bin => Box::new(
+                <BinParser as Parser<YPBankBinRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            Format::Toml => Box::new(
+                <TomlParser as Parser<YPBankTomlRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => Box::new(
+                <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Box::new(
+                <CborParser as Parser<YPBankCborRecordParser>>::iter_buf_read_resumed(
+                    r, options, offset,
+                )?,
+            ),
+            Format::Html | Format::PgCopy => return Err(self.read_unsupported()),
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => return Err(self.read_unsupported()),
+        };
+
+        Ok(RecordStream { inner })
+    }
+
+    /// Reads all records through the buffer of `r` instead of wrapping it in a buffer
+    /// of its own, so that nothing is read ahead of the records and lost, and no memory is
+    /// spent on a second buffer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format};
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// # parser::fixtures::enter().unwrap();
+    ///
+    /// let mut file = BufReader::with_capacity(1 << 20, File::open("records.csv").unwrap());
+    /// let records = CommonParser::new(Format::Csv).from_buf_read(&mut file).unwrap();
+    /// assert_eq!(records.len(), 20);
+    /// ```
+    pub fn from_buf_read<Reader: std::io::BufRead>(
+        &self,
+        r: &mut Reader,
+    ) -> Result<Vec<YPBankRecord>, ParseError> {
+        let mut records = Vec::with_capacity(self.options.capacity_hint.unwrap_or_default());
+        for record in self.iter_buf_read(r)? {
+            records.push(record?);
         }
+        Ok(records)
+    }
+
+    /// Like [`CommonParser::iter_read`], reading through the buffer of `r`.
+    pub fn iter_buf_read<'a, Reader: std::io::BufRead + 'a>(
+        &self,
+        r: Reader,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        self.stream_at(r, 0)
+    }
+
+    /// Like [`CommonParser::iter_read`], pairing every record with its extras: the
+    /// unknown CSV columns a [`SchemaMap`] captures ([`UnknownColumns::Capture`]), and
+    /// the extra TXT keys and binary extension entries read with
+    /// [`ParseOptions::preserve_extras`]. Records of other formats have no extras.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format, ParseOptions, SchemaMap, UnknownColumns};
+    ///
+    /// let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION,AUDITED_BY\n\
+    ///             1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary,jdoe\n";
+    /// let options = ParseOptions {
+    ///     csv_schema: Some(SchemaMap::default().with_unknown_columns(UnknownColumns::Capture)),
+    ///     ..ParseOptions::default()
+    /// };
+    ///
+    /// let mut records = CommonParser::with_options(Format::Csv, options)
+    ///     .iter_read_extended(data.as_bytes())
+    ///     .unwrap();
+    /// let record = records.next().unwrap().unwrap();
+    /// assert_eq!(record.extras["AUDITED_BY"], "jdoe");
+    /// ```
+    pub fn iter_read_extended<'a, Reader: std::io::Read + 'a>(
+        &self,
+        r: Reader,
+    ) -> Result<ExtendedRecords<'a>, ParseError> {
+        let options = &self.options;
+        let inner: Box<dyn Iterator<Item = Result<YPBankRecordExt, ParseError>> + 'a> = match self
+            .format
+        {
+            Format::Csv => Box::new(
+                <CsvParser as Parser<YPBankCsvRecordParser>>::iter_read(r, options)?.with_extras(),
+            ),
+            Format::Txt => Box::new(
+                <TxtParser as Parser<YPBankTxtRecordParser>>::iter_read(r, options)?.with_extras(),
+            ),
+            Format::Bin => Box::new(
+                <BinParser as Parser<YPBankBinRecordParser>>::iter_read(r, options)?.with_extras(),
+            ),
+            _ => Box::new(
+                self.iter_read(r)?
+                    .map(|record| record.map(YPBankRecordExt::from)),
+            ),
+        };
+
+        Ok(ExtendedRecords { inner })
+    }
+
+    fn stream_at<'a, Reader: std::io::BufRead + 'a>(
+        &self,
+        r: Reader,
+        offset: u64,
+    ) -> Result<RecordStream<'a>, ParseError> {
+        let options = &self.options;
+        let inner: Box<dyn RecordSource + 'a> = match self.format {
+            Format::Csv => Box::new(
+                <CsvParser as Parser<YPBankCsvRecordParser>>::iter_buf_read_at(r, options, offset)?,
+            ),
+            Format::Txt => Box::new(
+                <TxtParser as Parser<YPBankTxtRecordParser>>::iter_buf_read_at(r, options, offset)?,
+            ),
+            Format::Bin => Box::new(
+                <BinParser as Parser<YPBankBinRecordParser>>::iter_buf_read_at(r, options, offset)?,
+            ),
+            Format::Toml => Box::new(
+                <TomlParser as Parser<YPBankTomlRecordParser>>::iter_buf_read_at(
+                    r, options, offset,
+                )?,
+            ),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => Box::new(
+                <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::iter_buf_read_at(
+                    r, options, offset,
+                )?,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => Box::new(
+                <CborParser as Parser<YPBankCborRecordParser>>::iter_buf_read_at(
+                    r, options, offset,
+                )?,
+            ),
+            Format::Html | Format::PgCopy => return Err(self.read_unsupported()),
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => return Err(self.read_unsupported()),
+        };
+
+        Ok(RecordStream { inner })
     }
 
     /// Writes records to a file in the parser's format.
@@ -79,6 +515,9 @@ impl CommonParser {
     /// This method writes all records to the specified writer, including
     /// format-specific headers and structure.
     ///
+    /// Records are written in the order of `records` in every format, unless
+    /// [`ParseOptions::sort_output`] is set.
+    ///
     /// # Arguments
     ///
     /// * `w` - A writable destination (file, buffer, stdout, etc.)
@@ -91,7 +530,7 @@ impl CommonParser {
     ///
     /// # Examples
     ///
-    /// ```no_run
+    /// ```
     /// use parser::{CommonParser, Format, YPBankRecord};
     /// use std::io::stdout;
     ///
@@ -104,10 +543,515 @@ impl CommonParser {
         w: &mut Writer,
         records: &Vec<YPBankRecord>,
     ) -> Result<(), ParseError> {
+        let sorted;
+        let records = match self.options.sort_output {
+            Some(key) => {
+                sorted = {
+                    let mut records = records.clone();
+                    records.sort_by_key(|record| key.of(record));
+                    records
+                };
+                &sorted
+            }
+            None => records,
+        };
+
+        match self.format {
+            Format::Csv => {
+                <CsvParser as Parser<YPBankCsvRecordParser>>::write_to(w, records, &self.options)
+            }
+            Format::Txt => {
+                <TxtParser as Parser<YPBankTxtRecordParser>>::write_to(w, records, &self.options)
+            }
+            Format::Bin => {
+                <BinParser as Parser<YPBankBinRecordParser>>::write_to(w, records, &self.options)
+            }
+            Format::Toml => {
+                <TomlParser as Parser<YPBankTomlRecordParser>>::write_to(w, records, &self.options)
+            }
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::write_to(
+                w,
+                records,
+                &self.options,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                <CborParser as Parser<YPBankCborRecordParser>>::write_to(w, records, &self.options)
+            }
+            Format::Html => HtmlWriter::write_to(w, records),
+            Format::PgCopy => PgCopyWriter::write_iter(
+                w,
+                records.iter().cloned().map(Ok),
+                self.options.pg_table.as_ref(),
+            ),
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => XlsxWriter::write_to(w, records),
+        }
+    }
+
+    /// Writes records with their extras when [`ParseOptions::preserve_extras`] is set,
+    /// as extra CSV columns after the YPBank ones, TXT keys before the record fields and
+    /// binary extension entries after the description. Otherwise just the core records
+    /// are written. Other formats fail with [`ParseError::UnsupportedOperation`] on
+    /// records with extras to preserve.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format, ParseOptions, YPBankRecordExt, fixtures};
+    ///
+    /// let mut record = YPBankRecordExt::from(fixtures::records().remove(0));
+    /// record.extras.insert("AUDITED_BY".to_string(), "jdoe".to_string());
+    /// let options = ParseOptions {
+    ///     preserve_extras: true,
+    ///     ..ParseOptions::default()
+    /// };
+    /// let parser = CommonParser::with_options(Format::Txt, options);
+    ///
+    /// let mut buf = Vec::new();
+    /// parser.write_ext_to(&mut buf, &[record.clone()]).unwrap();
+    /// let read = parser.iter_read_extended(&buf[..]).unwrap().next().unwrap();
+    /// assert_eq!(read.unwrap(), record);
+    /// ```
+    pub fn write_ext_to<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: &[YPBankRecordExt],
+    ) -> Result<(), ParseError> {
+        let sorted;
+        let records = match self.options.sort_output {
+            Some(key) => {
+                sorted = {
+                    let mut records = records.to_vec();
+                    records.sort_by_key(|record| key.of(&record.core));
+                    records
+                };
+                &sorted[..]
+            }
+            None => records,
+        };
+
+        let options = &self.options;
         match self.format {
-            Format::Csv => <CsvParser as Parser<YPBankCsvRecordParser>>::write_to(w, records),
-            Format::Txt => <TxtParser as Parser<YPBankTxtRecordParser>>::write_to(w, records),
-            Format::Bin => <BinParser as Parser<YPBankBinRecordParser>>::write_to(w, records),
+            Format::Csv => {
+                <CsvParser as Parser<YPBankCsvRecordParser>>::write_ext_to(w, records, options)
+            }
+            Format::Txt => {
+                <TxtParser as Parser<YPBankTxtRecordParser>>::write_ext_to(w, records, options)
+            }
+            Format::Bin => {
+                <BinParser as Parser<YPBankBinRecordParser>>::write_ext_to(w, records, options)
+            }
+            Format::Toml => {
+                <TomlParser as Parser<YPBankTomlRecordParser>>::write_ext_to(w, records, options)
+            }
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::write_ext_to(
+                w, records, options,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => {
+                <CborParser as Parser<YPBankCborRecordParser>>::write_ext_to(w, records, options)
+            }
+            _ if options.preserve_extras && records.iter().any(|r| !r.extras.is_empty()) => {
+                Err(ParseError::UnsupportedOperation(format!(
+                    "extras cannot be written in {}",
+                    self.format.as_str()
+                )))
+            }
+            _ => self.write_to(w, &records.iter().map(|r| r.core.clone()).collect()),
         }
     }
+
+    /// Reads all records together with the batch envelope at the start of the input.
+    ///
+    /// CSV and TXT envelopes are leading comment lines such as `# BATCH_ID: 7`,
+    /// `# CREATED_TS: 1633046400000` and `# SOURCE_SYSTEM: core-banking`; binary
+    /// envelopes are a `YPBH` header before the first record. Inputs without an envelope
+    /// are read with [`Batch::metadata`] set to `None`. Other formats fail with
+    /// [`ParseError::UnsupportedOperation`].
+    pub fn read_batch<Reader: std::io::Read>(&self, r: &mut Reader) -> Result<Batch, ParseError> {
+        let mut buf_reader = std::io::BufReader::new(r);
+        let metadata = batch::read_envelope(&mut buf_reader, self.format)?;
+        let records = self.from_read(&mut buf_reader)?;
+        Ok(Batch { metadata, records })
+    }
+
+    /// Writes the records of `batch` after its envelope, see [`CommonParser::read_batch`].
+    pub fn write_batch<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        batch: &Batch,
+    ) -> Result<(), ParseError> {
+        match &batch.metadata {
+            Some(metadata) => batch::write_envelope(w, self.format, metadata)?,
+            None => batch::check_supported(self.format)?,
+        }
+        self.write_to(w, &batch.records)
+    }
+
+    /// Writes records to the file at `path` so that readers never see it half-written.
+    ///
+    /// Records go to a temporary file in the same directory, which is synced and then
+    /// renamed over `path`. With `backup` set, the previous file is copied there before it
+    /// is replaced. If writing fails, `path` keeps its previous content.
+    ///
+    /// With [`ParseOptions::stats_sidecar`] set, the [`ColumnStats`] of the records are
+    /// written next to the file once it is replaced.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format};
+    /// use std::path::Path;
+    /// # parser::fixtures::enter().unwrap();
+    ///
+    /// let parser = CommonParser::new(Format::Csv);
+    /// let records = vec![/* ... */];
+    /// parser
+    ///     .write_to_path_atomic("out/records.csv", &records, Some(Path::new("out/records.csv.bak")))
+    ///     .unwrap();
+    /// ```
+    pub fn write_to_path_atomic(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        records: &Vec<YPBankRecord>,
+        backup: Option<&std::path::Path>,
+    ) -> Result<(), ParseError> {
+        atomic::write_atomic(path.as_ref(), backup, |w| self.write_to(w, records))?;
+        if self.options.stats_sidecar {
+            let mut stats = ColumnStats::new();
+            records.iter().for_each(|record| stats.add(record));
+            stats.write_sidecar(path)?;
+        }
+        Ok(())
+    }
+
+    /// Like [`CommonParser::write_to_path_atomic`], writing records as they are produced.
+    ///
+    /// The records may be read from the file being replaced, which keeps its previous
+    /// content until all of them are written.
+    pub fn write_iter_to_path_atomic(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        backup: Option<&std::path::Path>,
+    ) -> Result<(), ParseError> {
+        let mut stats = self.options.stats_sidecar.then(ColumnStats::new);
+        let records = records.into_iter().inspect(|record| {
+            if let (Some(stats), Ok(record)) = (&mut stats, record) {
+                stats.add(record);
+            }
+        });
+        atomic::write_atomic(path.as_ref(), backup, |w| self.write_iter(w, records))?;
+        if let Some(stats) = stats {
+            stats.write_sidecar(path)?;
+        }
+        Ok(())
+    }
+
+    /// Writes records as they are produced, e.g. by [`CommonParser::iter_read`].
+    ///
+    /// XLSX, which needs all records up front, collects them first, as do writes sorted
+    /// by [`ParseOptions::sort_output`].
+    pub fn write_iter<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        if self.options.sort_output.is_some() {
+            return self.write_to(w, &records.into_iter().collect::<Result<Vec<_>, _>>()?);
+        }
+
+        match self.format {
+            Format::Csv => {
+                <CsvParser as Parser<YPBankCsvRecordParser>>::write_iter(w, records, &self.options)
+            }
+            Format::Txt => {
+                <TxtParser as Parser<YPBankTxtRecordParser>>::write_iter(w, records, &self.options)
+            }
+            Format::Bin => {
+                <BinParser as Parser<YPBankBinRecordParser>>::write_iter(w, records, &self.options)
+            }
+            Format::Toml => <TomlParser as Parser<YPBankTomlRecordParser>>::write_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::write_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => <CborParser as Parser<YPBankCborRecordParser>>::write_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            Format::Html => HtmlWriter::write_iter(w, records),
+            Format::PgCopy => PgCopyWriter::write_iter(w, records, self.options.pg_table.as_ref()),
+            #[cfg(feature = "xlsx")]
+            Format::Xlsx => self.write_to(w, &records.into_iter().collect::<Result<Vec<_>, _>>()?),
+        }
+    }
+
+    /// Like [`CommonParser::write_iter`], running `pipeline` on the records and serializing
+    /// them on `threads` worker threads while the calling thread reads and writes them.
+    ///
+    /// Records are handed to the workers in batches, at most two per thread at a time,
+    /// and written in input order, so the output is the same as with one thread. Formats
+    /// that are not streaming (see [`Format::is_streaming`]) and writes sorted by
+    /// [`ParseOptions::sort_output`] run on the calling thread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format, Pipeline, fixtures};
+    ///
+    /// let records = fixtures::records();
+    /// let mut output = Vec::new();
+    /// CommonParser::new(Format::Csv)
+    ///     .write_iter_parallel(&mut output, records.into_iter().map(Ok), &Pipeline::new(), 4)
+    ///     .unwrap();
+    /// ```
+    pub fn write_iter_parallel<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        pipeline: &Pipeline,
+        threads: usize,
+    ) -> Result<(), ParseError> {
+        if threads > 1 && self.format.is_streaming() && self.options.sort_output.is_none() {
+            return parallel::write_parallel(self, w, records, pipeline, threads);
+        }
+        let records = records.into_iter().filter_map(|record| match record {
+            Ok(record) => pipeline.process(record).transpose(),
+            Err(err) => Some(Err(err)),
+        });
+        self.write_iter(w, records)
+    }
+
+    /// Writes records without the format header, continuing output written earlier
+    /// by [`CommonParser::write_iter`].
+    ///
+    /// Fails for formats that are not streaming (see [`Format::is_streaming`]).
+    pub fn append_iter<Writer: std::io::Write>(
+        &self,
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        match self.format {
+            Format::Csv => {
+                <CsvParser as Parser<YPBankCsvRecordParser>>::append_iter(w, records, &self.options)
+            }
+            Format::Txt => {
+                <TxtParser as Parser<YPBankTxtRecordParser>>::append_iter(w, records, &self.options)
+            }
+            Format::Bin => {
+                <BinParser as Parser<YPBankBinRecordParser>>::append_iter(w, records, &self.options)
+            }
+            Format::Toml => <TomlParser as Parser<YPBankTomlRecordParser>>::append_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack => <MsgPackParser as Parser<YPBankMsgPackRecordParser>>::append_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            #[cfg(feature = "cbor")]
+            Format::Cbor => <CborParser as Parser<YPBankCborRecordParser>>::append_iter(
+                w,
+                records,
+                &self.options,
+            ),
+            Format::PgCopy => PgCopyWriter::write_iter(w, records, self.options.pg_table.as_ref()),
+            format => Err(ParseError::UnsupportedOperation(format!(
+                "appending to {} files is not supported",
+                format.as_str()
+            ))),
+        }
+    }
+}
+
+/// An iterator over records and their extras read by
+/// [`CommonParser::iter_read_extended`].
+pub struct ExtendedRecords<'a> {
+    inner: Box<dyn Iterator<Item = Result<YPBankRecordExt, ParseError>> + 'a>,
+}
+
+impl Iterator for ExtendedRecords<'_> {
+    type Item = Result<YPBankRecordExt, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// An iterator over records read by [`CommonParser::iter_read`].
+///
+/// The iterator ends after the first error, unless
+/// [`ParseOptions::skip_invalid_records`] is set.
+pub struct RecordStream<'a> {
+    inner: Box<dyn RecordSource + 'a>,
+}
+
+impl RecordStream<'_> {
+    /// Returns the input offset right after the last returned record, to continue
+    /// from with [`CommonParser::iter_read_at`].
+    pub fn offset(&self) -> u64 {
+        self.inner.offset()
+    }
+
+    /// Returns the input bytes of the last returned record, or of the invalid record
+    /// behind the last returned error, when reading with
+    /// [`ParseOptions::skip_invalid_records`]. Empty otherwise.
+    ///
+    /// The bytes include separators and comments read before the record, and before the
+    /// first record they are the format header, so that rejected records can be written
+    /// out again in their source format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format, ParseOptions};
+    ///
+    /// let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+    ///             1,DEPOSIT,0,2,abc,1633036860000,SUCCESS,Salary\n\
+    ///             2,DEPOSIT,0,3,200,1633036860000,SUCCESS,Bonus\n";
+    /// let options = ParseOptions {
+    ///     skip_invalid_records: true,
+    ///     ..ParseOptions::default()
+    /// };
+    ///
+    /// let mut records = CommonParser::with_options(Format::Csv, options)
+    ///     .iter_read(data.as_bytes())
+    ///     .unwrap();
+    /// assert!(records.next().unwrap().is_err());
+    /// assert_eq!(
+    ///     records.raw_record(),
+    ///     b"1,DEPOSIT,0,2,abc,1633036860000,SUCCESS,Salary\n"
+    /// );
+    /// assert_eq!(records.next().unwrap().unwrap().id, 2);
+    /// ```
+    pub fn raw_record(&self) -> &[u8] {
+        self.inner.raw_record()
+    }
+}
+
+impl Iterator for RecordStream<'_> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod write_order_tests {
+    use super::*;
+
+    /// Formats whose output can be read back.
+    const FORMATS: &[Format] = &[
+        Format::Csv,
+        Format::Txt,
+        Format::Bin,
+        Format::Toml,
+        #[cfg(feature = "msgpack")]
+        Format::MsgPack,
+        #[cfg(feature = "cbor")]
+        Format::Cbor,
+    ];
+
+    /// Shuffles `records` with a linear congruential generator seeded by `seed`, so that
+    /// every run checks the same orders.
+    fn shuffled(mut records: Vec<YPBankRecord>, seed: u64) -> Vec<YPBankRecord> {
+        let mut state = seed;
+        for index in (1..records.len()).rev() {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            records.swap(index, (state >> 33) as usize % (index + 1));
+        }
+        records
+    }
+
+    #[test]
+    fn test_write_order_is_input_order() {
+        for seed in 0..32 {
+            let records = shuffled(fixtures::records(), seed);
+            for format in FORMATS {
+                let parser = CommonParser::new(*format);
+                let mut data = Vec::new();
+                parser
+                    .write_to(&mut data, &records)
+                    .expect("Should write successfully");
+                let read = parser
+                    .from_read(&mut &data[..])
+                    .expect("Should read successfully");
+                assert_eq!(read, records, "seed {} in {}", seed, format.as_str());
+
+                let mut streamed = Vec::new();
+                parser
+                    .write_iter(&mut streamed, records.iter().cloned().map(Ok))
+                    .expect("Should write successfully");
+                assert_eq!(streamed, data, "seed {} in {}", seed, format.as_str());
+            }
+        }
+    }
+
+    #[test]
+    fn test_sorted_output_is_canonical_and_stable() {
+        let mut records = fixtures::records();
+        for (index, record) in records.iter_mut().enumerate() {
+            record.ts = 1633046460000 + (index as u64 / 2) * 60_000;
+        }
+
+        for format in FORMATS {
+            let options = ParseOptions {
+                sort_output: Some(SortKey::Id),
+                ..ParseOptions::default()
+            };
+            let parser = CommonParser::with_options(*format, options);
+            let mut canonical = Vec::new();
+            parser
+                .write_to(&mut canonical, &records)
+                .expect("Should write successfully");
+
+            for seed in 0..8 {
+                let mut data = Vec::new();
+                parser
+                    .write_to(&mut data, &shuffled(records.clone(), seed))
+                    .expect("Should write successfully");
+                assert_eq!(data, canonical, "seed {} in {}", seed, format.as_str());
+            }
+        }
+
+        let options = ParseOptions {
+            sort_output: Some(SortKey::Ts),
+            ..ParseOptions::default()
+        };
+        let parser = CommonParser::with_options(Format::Csv, options);
+        let input = shuffled(records, 7);
+        let mut data = Vec::new();
+        parser
+            .write_iter(&mut data, input.iter().cloned().map(Ok))
+            .expect("Should write successfully");
+        let read = CommonParser::new(Format::Csv)
+            .from_read(&mut &data[..])
+            .expect("Should read successfully");
+
+        let mut expected = input;
+        expected.sort_by_key(|record| record.ts);
+        assert_eq!(read, expected);
+    }
 }
@@ -0,0 +1,161 @@
+use crate::common::TransactionStatus;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::collections::HashMap;
+
+/// The records of one logical transaction: records sharing an id, such as a
+/// [`TransactionStatus::Pending`] record followed by a status update to
+/// [`TransactionStatus::Success`] or [`TransactionStatus::Failure`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionHistory {
+    pub id: u64,
+    /// Records of the transaction in input order, never empty.
+    pub states: Vec<YPBankRecord>,
+}
+
+impl TransactionHistory {
+    /// Returns the current state of the transaction: the record with the latest
+    /// timestamp, or the later one in input order when timestamps are equal.
+    pub fn latest(&self) -> &YPBankRecord {
+        self.states
+            .iter()
+            .max_by_key(|record| record.ts)
+            .expect("Histories hold at least one record")
+    }
+
+    /// Returns whether the transaction reached a final status.
+    pub fn is_final(&self) -> bool {
+        self.latest().status != TransactionStatus::Pending
+    }
+}
+
+/// Links records sharing an id into histories, in the order of the first record of each.
+///
+/// Holds all records in memory. Stops at the first error.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{TransactionStatus, TransactionType, YPBankRecord, link_transactions};
+///
+/// let record = |status, ts| {
+///     Ok(YPBankRecord::new(7, TransactionType::Deposit, 0, 1, 100, ts, status, "Salary"))
+/// };
+/// let histories = link_transactions(vec![
+///     record(TransactionStatus::Pending, 1633036860000),
+///     record(TransactionStatus::Success, 1633036920000),
+/// ])
+/// .unwrap();
+///
+/// assert_eq!(histories.len(), 1);
+/// assert_eq!(histories[0].states.len(), 2);
+/// assert_eq!(histories[0].latest().status, TransactionStatus::Success);
+/// ```
+pub fn link_transactions(
+    records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+) -> Result<Vec<TransactionHistory>, ParseError> {
+    let mut histories: Vec<TransactionHistory> = Vec::new();
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    for record in records {
+        let record = record?;
+        match positions.get(&record.id) {
+            Some(&position) => histories[position].states.push(record),
+            None => {
+                positions.insert(record.id, histories.len());
+                histories.push(TransactionHistory {
+                    id: record.id,
+                    states: vec![record],
+                });
+            }
+        }
+    }
+    Ok(histories)
+}
+
+/// Keeps the latest state of every transaction ([`TransactionHistory::latest`]), at the
+/// position of its first record, so that status updates are not counted twice.
+pub fn final_states(
+    records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+) -> Result<Vec<YPBankRecord>, ParseError> {
+    Ok(link_transactions(records)?
+        .into_iter()
+        .map(|history| history.latest().clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod lifecycle_tests {
+    use super::*;
+    use crate::common::TransactionType;
+
+    fn record(id: u64, status: TransactionStatus, ts: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Transfer,
+            1,
+            2,
+            100,
+            ts,
+            status,
+            format!("{:?}", status),
+        )
+    }
+
+    #[test]
+    fn test_link_transactions() {
+        let records = [
+            record(1, TransactionStatus::Pending, 10),
+            record(2, TransactionStatus::Success, 10),
+            record(1, TransactionStatus::Failure, 20),
+            record(3, TransactionStatus::Pending, 30),
+        ];
+
+        let histories =
+            link_transactions(records.iter().cloned().map(Ok)).expect("Should link records");
+        assert_eq!(
+            histories
+                .iter()
+                .map(|history| (history.id, history.states.len(), history.is_final()))
+                .collect::<Vec<_>>(),
+            vec![(1, 2, true), (2, 1, true), (3, 1, false)]
+        );
+
+        assert_eq!(
+            final_states(records.iter().cloned().map(Ok)),
+            Ok(vec![
+                records[2].clone(),
+                records[1].clone(),
+                records[3].clone()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_latest_state() {
+        let history = TransactionHistory {
+            id: 1,
+            states: vec![
+                record(1, TransactionStatus::Success, 20),
+                record(1, TransactionStatus::Pending, 10),
+            ],
+        };
+        assert_eq!(history.latest().status, TransactionStatus::Success);
+
+        let history = TransactionHistory {
+            id: 1,
+            states: vec![
+                record(1, TransactionStatus::Pending, 10),
+                record(1, TransactionStatus::Success, 10),
+            ],
+        };
+        assert_eq!(history.latest().status, TransactionStatus::Success);
+
+        assert_eq!(
+            link_transactions(vec![
+                Ok(record(1, TransactionStatus::Pending, 10)),
+                Err(ParseError::UnexpectedEOF),
+            ]),
+            Err(ParseError::UnexpectedEOF)
+        );
+    }
+}
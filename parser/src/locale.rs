@@ -0,0 +1,161 @@
+use crate::constant::AMOUNT;
+use crate::error::ParseError;
+
+const SPACES: [char; 3] = [' ', '\u{a0}', '\u{202f}'];
+
+/// Separators of localized amounts such as `1 000,50` or `1,000.50`.
+///
+/// Amounts are converted to minor units, so with the default two minor digits
+/// `1 000,50` becomes `100050`. Plain integers are taken as major units.
+///
+/// # Examples
+///
+/// ```
+/// use parser::AmountFormat;
+///
+/// assert_eq!(AmountFormat::european().parse("-1 000,5"), Ok(-100050));
+/// assert_eq!(AmountFormat::english().parse("1,000.50"), Ok(100050));
+/// assert!(AmountFormat::english().parse("1.005").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountFormat {
+    thousands_separator: Option<char>,
+    decimal_separator: char,
+    minor_digits: u32,
+}
+
+impl AmountFormat {
+    /// Creates a format with two minor digits.
+    ///
+    /// A space as thousands separator also accepts non-breaking spaces.
+    pub fn new(thousands_separator: Option<char>, decimal_separator: char) -> Self {
+        Self {
+            thousands_separator,
+            decimal_separator,
+            minor_digits: 2,
+        }
+    }
+
+    /// `1 000,50`
+    pub fn european() -> Self {
+        Self::new(Some(' '), ',')
+    }
+
+    /// `1,000.50`
+    pub fn english() -> Self {
+        Self::new(Some(','), '.')
+    }
+
+    /// Sets the number of digits after the decimal separator in minor units.
+    pub fn with_minor_digits(mut self, minor_digits: u32) -> Self {
+        self.minor_digits = minor_digits;
+        self
+    }
+
+    /// Parses a localized amount into minor units.
+    pub fn parse(&self, s: &str) -> Result<i64, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: AMOUNT.to_string(),
+            value: s.to_string(),
+        };
+        let value = s.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        let (negative, value) = match value.strip_prefix('-') {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+
+        let (major, minor) = match value.split_once(self.decimal_separator) {
+            Some((major, minor)) => (major, minor),
+            None => (value, ""),
+        };
+        if minor.len() > self.minor_digits as usize || !minor.chars().all(|ch| ch.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let mut digits = String::with_capacity(major.len() + self.minor_digits as usize);
+        for ch in major.chars() {
+            match ch {
+                '0'..='9' => digits.push(ch),
+                ch if self.is_thousands_separator(ch) => {}
+                _ => return Err(invalid()),
+            }
+        }
+        if digits.is_empty() {
+            return Err(invalid());
+        }
+        digits.push_str(minor);
+        digits.extend(std::iter::repeat_n(
+            '0',
+            self.minor_digits as usize - minor.len(),
+        ));
+
+        let out_of_range = || ParseError::ValueOutOfRange {
+            field: AMOUNT.to_string(),
+            value: s.to_string(),
+        };
+        let minor_units = digits.parse::<i128>().map_err(|_| out_of_range())?;
+        let minor_units = if negative { -minor_units } else { minor_units };
+        i64::try_from(minor_units).map_err(|_| out_of_range())
+    }
+
+    fn is_thousands_separator(&self, ch: char) -> bool {
+        match self.thousands_separator {
+            Some(' ') => SPACES.contains(&ch),
+            Some(separator) => ch == separator,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod amount_format_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let european = AmountFormat::european();
+
+        assert_eq!(european.parse("1 000,50"), Ok(100050));
+        assert_eq!(european.parse("1\u{a0}000"), Ok(100000));
+        assert_eq!(european.parse("\"12,3\""), Ok(1230));
+        assert_eq!(
+            AmountFormat::new(None, '.')
+                .with_minor_digits(0)
+                .parse("-42"),
+            Ok(-42)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let english = AmountFormat::english();
+
+        assert_eq!(
+            english.parse("1 000.50"),
+            Err(ParseError::InvalidRawValue {
+                field: "AMOUNT".to_string(),
+                value: "1 000.50".to_string()
+            })
+        );
+        assert_eq!(
+            english.parse(".50"),
+            Err(ParseError::InvalidRawValue {
+                field: "AMOUNT".to_string(),
+                value: ".50".to_string()
+            })
+        );
+        assert_eq!(
+            english.parse("92,233,720,368,547,758.08"),
+            Err(ParseError::ValueOutOfRange {
+                field: "AMOUNT".to_string(),
+                value: "92,233,720,368,547,758.08".to_string()
+            })
+        );
+        assert_eq!(english.parse("-92,233,720,368,547,758.08"), Ok(i64::MIN));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::delta::index_by_id;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::record_eq::{Exact, RecordEq};
+
+/// How [`merge`] resolves records of both inputs that share an id but differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictStrategy {
+    /// Keeps the record with the later timestamp; on equal timestamps keeps the first input.
+    LatestTimestampWins,
+    PreferA,
+    PreferB,
+    /// Fails with [`ParseError::InconsistentRecord`] on the first conflict.
+    ErrorOnConflict,
+}
+
+/// The input a record comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    A,
+    B,
+}
+
+/// Records of both inputs sharing an id with different values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub a: YPBankRecord,
+    pub b: YPBankRecord,
+    /// The input whose record was kept.
+    pub kept: Side,
+}
+
+impl Conflict {
+    pub fn id(&self) -> u64 {
+        self.a.id
+    }
+}
+
+/// Merged records with a report of the conflicts resolved on the way.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Merged {
+    pub records: Vec<YPBankRecord>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merges two sets of records, keeping one record per transaction id.
+///
+/// Records of `a` come first in their order, followed by records only in `b` in their
+/// order; the result depends only on the inputs and `strategy`. Records present in both
+/// inputs with equal values are not conflicts. Duplicate ids inside one input are an
+/// [`ParseError::InconsistentRecord`] error.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ConflictStrategy, Side, TransactionStatus, TransactionType, YPBankRecord, merge};
+///
+/// let record = |id, amount, ts| {
+///     YPBankRecord::new(id, TransactionType::Deposit, 0, 2, amount, ts, TransactionStatus::Success, String::new())
+/// };
+///
+/// let primary = vec![record(1, 100, 10), record(2, 200, 10)];
+/// let replica = vec![record(1, 150, 20), record(3, 300, 10)];
+/// let merged = merge(primary, replica, ConflictStrategy::LatestTimestampWins).unwrap();
+///
+/// assert_eq!(merged.records, vec![record(1, 150, 20), record(2, 200, 10), record(3, 300, 10)]);
+/// assert_eq!(merged.conflicts[0].kept, Side::B);
+/// ```
+pub fn merge(
+    a: Vec<YPBankRecord>,
+    b: Vec<YPBankRecord>,
+    strategy: ConflictStrategy,
+) -> Result<Merged, ParseError> {
+    merge_by(a, b, strategy, &Exact)
+}
+
+/// Merges like [`merge`], treating records that are the same under `eq` as equal.
+///
+/// With [`Semantic`](crate::Semantic), records differing only in their description are
+/// not conflicts and the record of `a` is kept.
+pub fn merge_by(
+    a: Vec<YPBankRecord>,
+    b: Vec<YPBankRecord>,
+    strategy: ConflictStrategy,
+    eq: &impl RecordEq,
+) -> Result<Merged, ParseError> {
+    let index = index_by_id(a.iter())?;
+    index_by_id(b.iter())?;
+
+    let mut merged = Merged {
+        records: a,
+        conflicts: Vec::new(),
+    };
+    for record in b {
+        let Some(position) = index.get(&record.id) else {
+            merged.records.push(record);
+            continue;
+        };
+        let kept = &mut merged.records[*position];
+        if eq.same(kept, &record) {
+            continue;
+        }
+
+        let side = match strategy {
+            ConflictStrategy::LatestTimestampWins if record.ts > kept.ts => Side::B,
+            ConflictStrategy::LatestTimestampWins | ConflictStrategy::PreferA => Side::A,
+            ConflictStrategy::PreferB => Side::B,
+            ConflictStrategy::ErrorOnConflict => {
+                return Err(ParseError::InconsistentRecord(format!(
+                    "conflicting records for transaction id {}",
+                    record.id
+                )));
+            }
+        };
+        merged.conflicts.push(Conflict {
+            a: kept.clone(),
+            b: record.clone(),
+            kept: side,
+        });
+        if side == Side::B {
+            *kept = record;
+        }
+    }
+
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod merge_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(id: u64, amount: i64, ts: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Transfer,
+            1,
+            2,
+            amount,
+            ts,
+            TransactionStatus::Success,
+            format!("Record number {}", id),
+        )
+    }
+
+    #[test]
+    fn test_merge_strategies() {
+        let a = || vec![record(1, 100, 20), record(2, 200, 10)];
+        let b = || vec![record(2, 250, 30), record(1, 150, 10), record(3, 300, 10)];
+
+        let merged = merge(a(), b(), ConflictStrategy::LatestTimestampWins).expect("Should merge");
+        assert_eq!(
+            merged.records,
+            vec![record(1, 100, 20), record(2, 250, 30), record(3, 300, 10)]
+        );
+        assert_eq!(
+            merged
+                .conflicts
+                .iter()
+                .map(|conflict| (conflict.id(), conflict.kept))
+                .collect::<Vec<_>>(),
+            vec![(2, Side::B), (1, Side::A)]
+        );
+
+        let merged = merge(a(), b(), ConflictStrategy::PreferA).expect("Should merge");
+        assert_eq!(merged.records[..2], a()[..]);
+
+        let merged = merge(a(), b(), ConflictStrategy::PreferB).expect("Should merge");
+        assert_eq!(
+            merged.records,
+            vec![record(1, 150, 10), record(2, 250, 30), record(3, 300, 10)]
+        );
+        assert_eq!(merged.conflicts[1].a, record(1, 100, 20));
+    }
+
+    #[test]
+    fn test_merge_equal_records_are_not_conflicts() {
+        let merged = merge(
+            vec![record(1, 100, 10)],
+            vec![record(1, 100, 10)],
+            ConflictStrategy::ErrorOnConflict,
+        )
+        .expect("Should merge");
+        assert_eq!(merged.records, vec![record(1, 100, 10)]);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_by_semantic_ignores_descriptions() {
+        let mut renamed = record(1, 100, 10);
+        renamed.description = "Renamed".into();
+
+        let merged = merge_by(
+            vec![record(1, 100, 10)],
+            vec![renamed],
+            ConflictStrategy::ErrorOnConflict,
+            &crate::record_eq::Semantic,
+        )
+        .expect("Should merge");
+        assert_eq!(merged.records, vec![record(1, 100, 10)]);
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_errors() {
+        assert_eq!(
+            merge(
+                vec![record(1, 100, 10)],
+                vec![record(1, 200, 10)],
+                ConflictStrategy::ErrorOnConflict,
+            ),
+            Err(ParseError::InconsistentRecord(
+                "conflicting records for transaction id 1".to_string()
+            ))
+        );
+        assert!(matches!(
+            merge(
+                vec![],
+                vec![record(1, 100, 10), record(1, 100, 10)],
+                ConflictStrategy::PreferA,
+            ),
+            Err(ParseError::InconsistentRecord(_))
+        ));
+    }
+}
@@ -0,0 +1,300 @@
+use crate::error::ParseError;
+use crate::keyed_record::{
+    FIELDS, FrameReader, KeyedValue, from_keyed_values, read_frame, write_frame,
+};
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::YPBankRecord;
+
+/// Reads and writes records as length-prefixed MessagePack maps.
+///
+/// Every record is a big-endian `u32` payload length followed by a MessagePack map
+/// with the keys from [`FIELDS`]. Writers use the smallest integer and string encodings.
+#[derive(Default)]
+pub struct YPBankMsgPackRecordParser {
+    buf: Vec<u8>,
+}
+
+impl YPBankMsgPackRecordParser {
+    fn decode_map_len(r: &mut FrameReader) -> Result<usize, ParseError> {
+        let marker = r.read_u8()?;
+        match marker {
+            0x80..=0x8f => Ok((marker & 0x0f) as usize),
+            0xde => Ok(r.read_uint(2)? as usize),
+            0xdf => Ok(r.read_uint(4)? as usize),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "msgpack marker".to_string(),
+                value: format!("{:#04x}", marker),
+            }),
+        }
+    }
+
+    fn decode_value(r: &mut FrameReader) -> Result<KeyedValue, ParseError> {
+        let marker = r.read_u8()?;
+        let value = match marker {
+            0x00..=0x7f => KeyedValue::Int(marker as i128),
+            0xe0..=0xff => KeyedValue::Int(marker as i8 as i128),
+            0xcc => KeyedValue::Int(r.read_uint(1)? as i128),
+            0xcd => KeyedValue::Int(r.read_uint(2)? as i128),
+            0xce => KeyedValue::Int(r.read_uint(4)? as i128),
+            0xcf => KeyedValue::Int(r.read_uint(8)? as i128),
+            0xd0 => KeyedValue::Int(r.read_uint(1)? as u8 as i8 as i128),
+            0xd1 => KeyedValue::Int(r.read_uint(2)? as u16 as i16 as i128),
+            0xd2 => KeyedValue::Int(r.read_uint(4)? as u32 as i32 as i128),
+            0xd3 => KeyedValue::Int(r.read_uint(8)? as i64 as i128),
+            0xa0..=0xbf => KeyedValue::Str(r.read_string((marker & 0x1f) as usize)?),
+            0xd9 => {
+                let len = r.read_uint(1)? as usize;
+                KeyedValue::Str(r.read_string(len)?)
+            }
+            0xda => {
+                let len = r.read_uint(2)? as usize;
+                KeyedValue::Str(r.read_string(len)?)
+            }
+            0xdb => {
+                let len = r.read_uint(4)? as usize;
+                KeyedValue::Str(r.read_string(len)?)
+            }
+            _ => {
+                return Err(ParseError::InvalidRawValue {
+                    field: "msgpack marker".to_string(),
+                    value: format!("{:#04x}", marker),
+                });
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn encode_uint(buf: &mut Vec<u8>, val: u64) {
+        match val {
+            0..=0x7f => buf.push(val as u8),
+            0x80..=0xff => buf.extend_from_slice(&[0xcc, val as u8]),
+            0x100..=0xffff => {
+                buf.push(0xcd);
+                buf.extend_from_slice(&(val as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                buf.push(0xce);
+                buf.extend_from_slice(&(val as u32).to_be_bytes());
+            }
+            _ => {
+                buf.push(0xcf);
+                buf.extend_from_slice(&val.to_be_bytes());
+            }
+        }
+    }
+
+    fn encode_int(buf: &mut Vec<u8>, val: i64) {
+        match val {
+            0.. => Self::encode_uint(buf, val as u64),
+            -32..=-1 => buf.push(val as i8 as u8),
+            -0x80..=-33 => buf.extend_from_slice(&[0xd0, val as i8 as u8]),
+            -0x8000..=-0x81 => {
+                buf.push(0xd1);
+                buf.extend_from_slice(&(val as i16).to_be_bytes());
+            }
+            -0x8000_0000..=-0x8001 => {
+                buf.push(0xd2);
+                buf.extend_from_slice(&(val as i32).to_be_bytes());
+            }
+            _ => {
+                buf.push(0xd3);
+                buf.extend_from_slice(&val.to_be_bytes());
+            }
+        }
+    }
+
+    fn encode_str(buf: &mut Vec<u8>, val: &str) {
+        let len = val.len();
+        match len {
+            0..=31 => buf.push(0xa0 | len as u8),
+            32..=0xff => buf.extend_from_slice(&[0xd9, len as u8]),
+            0x100..=0xffff => {
+                buf.push(0xda);
+                buf.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            _ => {
+                buf.push(0xdb);
+                buf.extend_from_slice(&(len as u32).to_be_bytes());
+            }
+        }
+        buf.extend_from_slice(val.as_bytes());
+    }
+}
+
+impl FromOptions for YPBankMsgPackRecordParser {}
+
+impl RecordReader for YPBankMsgPackRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        if !read_frame(r, &mut self.buf)? {
+            return Ok(None);
+        }
+
+        let mut frame = FrameReader::new(&self.buf);
+        let len = Self::decode_map_len(&mut frame)?;
+        let mut entries = Vec::with_capacity(len.min(FIELDS.len()));
+        for _ in 0..len {
+            let key = match Self::decode_value(&mut frame)? {
+                KeyedValue::Str(key) => key,
+                KeyedValue::Int(key) => return Err(ParseError::InvalidRow(key.to_string())),
+            };
+            entries.push((key, Self::decode_value(&mut frame)?));
+        }
+        frame.finish()?;
+
+        Ok(Some(from_keyed_values(entries)?))
+    }
+}
+
+impl RecordWriter for YPBankMsgPackRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        let buf = &mut self.buf;
+        buf.clear();
+
+        buf.push(0x80 | FIELDS.len() as u8);
+        Self::encode_str(buf, FIELDS[0]);
+        Self::encode_uint(buf, record.id);
+        Self::encode_str(buf, FIELDS[1]);
+        Self::encode_str(buf, record.transaction_type.as_str());
+        Self::encode_str(buf, FIELDS[2]);
+        Self::encode_uint(buf, record.from_user_id);
+        Self::encode_str(buf, FIELDS[3]);
+        Self::encode_uint(buf, record.to_user_id);
+        Self::encode_str(buf, FIELDS[4]);
+        Self::encode_int(buf, record.amount);
+        Self::encode_str(buf, FIELDS[5]);
+        Self::encode_uint(buf, record.ts);
+        Self::encode_str(buf, FIELDS[6]);
+        Self::encode_str(buf, record.status.as_str());
+        Self::encode_str(buf, FIELDS[7]);
+        Self::encode_str(buf, &record.description);
+
+        write_frame(w, buf)
+    }
+}
+
+pub struct MsgPackParser {}
+
+impl Parser<YPBankMsgPackRecordParser> for MsgPackParser {}
+
+#[cfg(test)]
+mod yp_bank_msgpack_record_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use std::io::Cursor;
+
+    #[test]
+    fn test_encode_int_boundaries() {
+        let cases: [(i64, &[u8]); 6] = [
+            (5, &[0x05]),
+            (-1, &[0xff]),
+            (-33, &[0xd0, 0xdf]),
+            (200, &[0xcc, 0xc8]),
+            (-129, &[0xd1, 0xff, 0x7f]),
+            (i64::MIN, &[0xd3, 0x80, 0, 0, 0, 0, 0, 0, 0]),
+        ];
+
+        for (val, expected) in cases {
+            let mut buf = vec![];
+            YPBankMsgPackRecordParser::encode_int(&mut buf, val);
+            assert_eq!(buf, expected, "Encoding of {}", val);
+
+            let decoded = YPBankMsgPackRecordParser::decode_value(&mut FrameReader::new(&buf))
+                .expect("Should decode");
+            assert_eq!(decoded, KeyedValue::Int(val as i128));
+        }
+    }
+
+    #[test]
+    fn test_write_to_regular_case() {
+        let record = YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            3,
+            TransactionStatus::Failure,
+            "d".to_string(),
+        );
+
+        let mut writer = Cursor::new(Vec::new());
+        YPBankMsgPackRecordParser::default()
+            .write_to(&record, &mut writer)
+            .expect("Writing should succeed");
+        let written = writer.into_inner();
+
+        let payload_len = u32::from_be_bytes([written[0], written[1], written[2], written[3]]);
+        assert_eq!(payload_len as usize, written.len() - 4);
+        assert_eq!(
+            &written[4..12],
+            &[0x88, 0xa5, b't', b'x', b'_', b'i', b'd', 0x01]
+        );
+
+        let mut reader = Cursor::new(written);
+        let read_record = YPBankMsgPackRecordParser::default()
+            .from_read(&mut reader)
+            .expect("Reading should succeed");
+        assert_eq!(read_record, Some(record));
+    }
+
+    #[test]
+    fn test_from_read_trailing_bytes_in_frame() {
+        let mut data = 2u32.to_be_bytes().to_vec();
+        data.extend_from_slice(&[0x80, 0x00]);
+
+        let mut reader = Cursor::new(data);
+        let result = YPBankMsgPackRecordParser::default().from_read(&mut reader);
+        assert!(matches!(result, Err(ParseError::InconsistentRecord(_))));
+    }
+}
+
+#[cfg(test)]
+mod msgpack_parser_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use crate::options::ParseOptions;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip_multiple_records() {
+        let records = vec![
+            YPBankRecord::new(
+                u64::MAX,
+                TransactionType::Withdrawal,
+                9223372036854775807,
+                0,
+                i64::MIN,
+                1633036860000,
+                TransactionStatus::Pending,
+                "x".repeat(300),
+            ),
+            YPBankRecord::new(
+                1000000000000001,
+                TransactionType::Transfer,
+                1,
+                2,
+                -200,
+                1633036920000,
+                TransactionStatus::Success,
+                String::new(),
+            ),
+        ];
+
+        let mut writer = Cursor::new(Vec::new());
+        MsgPackParser::write_to(&mut writer, &records, &ParseOptions::default())
+            .expect("Should write successfully");
+
+        let mut reader = Cursor::new(writer.into_inner());
+        let read_records = MsgPackParser::from_read(&mut reader, &ParseOptions::default())
+            .expect("Should parse successfully");
+        assert_eq!(read_records, records);
+    }
+}
@@ -0,0 +1,624 @@
+use crate::comment::CommentLog;
+use crate::constant::TIMESTAMP;
+use crate::error::ParseError;
+use crate::intern::DescriptionPool;
+use crate::locale::AmountFormat;
+use crate::pg_copy::PgTable;
+use crate::record::YPBankRecord;
+use crate::rule_expr::RuleExpr;
+use crate::schema::SchemaMap;
+use crate::sort::SortKey;
+use crate::timestamp_log::{ImplausibleTimestamp, TimestampLog};
+use crate::tombstone::Tombstones;
+#[cfg(feature = "tz")]
+use crate::tz::TimeZone;
+#[cfg(feature = "wasm-rules")]
+use crate::wasm_rule::WasmRule;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TXT_KEY_ALIASES: [(&str, &str); 4] = [
+    ("TXID", "TX_ID"),
+    ("TRANSACTION_ID", "TX_ID"),
+    ("FROM", "FROM_USER_ID"),
+    ("TO", "TO_USER_ID"),
+];
+
+/// How numeric fields of text formats treat inputs that are only numbers when read leniently.
+///
+/// Values out of the range of their field always fail with
+/// [`crate::ParseError::ValueOutOfRange`]; values rejected by the policy fail with
+/// [`crate::ParseError::InvalidRawValue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumericPolicy {
+    /// Accepts a leading `+`, as in `+100`. Allowed by default.
+    pub allow_plus_sign: bool,
+    /// Accepts leading zeros, as in `007`. Allowed by default.
+    pub allow_leading_zeros: bool,
+    /// Ignores whitespace around values, as in `" 100 "`. Disabled by default.
+    pub trim_whitespace: bool,
+}
+
+impl NumericPolicy {
+    /// A policy accepting only canonical numbers.
+    pub fn strict() -> Self {
+        Self {
+            allow_plus_sign: false,
+            allow_leading_zeros: false,
+            trim_whitespace: false,
+        }
+    }
+}
+
+impl Default for NumericPolicy {
+    fn default() -> Self {
+        Self {
+            allow_plus_sign: true,
+            allow_leading_zeros: true,
+            trim_whitespace: false,
+        }
+    }
+}
+
+/// How CSV quotes still open at the end of a line are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvQuotes {
+    /// Continues the quoted field on the following lines, as RFC 4180 allows, reading at
+    /// most `max_lines` lines per record. A quote still open after them or at the end of
+    /// the input fails with [`crate::ParseError::UnterminatedQuote`].
+    MultiLine { max_lines: usize },
+    /// Fails with [`crate::ParseError::UnterminatedQuote`] at the end of the line.
+    Strict,
+    /// Closes the quote at the end of the line, keeping the rest of the line in the field.
+    Lenient,
+}
+
+impl Default for CsvQuotes {
+    fn default() -> Self {
+        CsvQuotes::MultiLine { max_lines: 16 }
+    }
+}
+
+/// How empty lines between CSV records are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvEmptyLines {
+    /// Ignores empty lines.
+    Skip,
+    /// Ends the records at the first empty line, ignoring the rest of the input.
+    Stop,
+    /// Fails with [`crate::ParseError::EmptyLine`] when records follow an empty line.
+    /// Empty lines at the end of the input are accepted.
+    #[default]
+    Error,
+}
+
+/// How header lines repeated between CSV records are read, as in daily files joined
+/// with `cat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvRepeatedHeaders {
+    /// Ignores lines equal to the header of the input. Header lines that differ from it,
+    /// starting with the same column name, fail with
+    /// [`crate::ParseError::InvalidCsvHeader`].
+    #[default]
+    Skip,
+    /// Fails with [`crate::ParseError::InvalidCsvHeader`] at every header line after the
+    /// first.
+    Reject,
+}
+
+/// Bounds on CSV lines, so that malformed or hostile inputs fail early instead of
+/// being buffered whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvLimits {
+    /// Maximum number of fields per line, 1024 by default. Longer lines fail with
+    /// [`crate::ParseError::TooManyFields`].
+    pub max_fields: usize,
+    /// Maximum length of a line in bytes, including its line break; 1 MiB by default.
+    /// Each line of a quoted field spanning lines is bounded on its own. Longer lines
+    /// fail with [`crate::ParseError::LineTooLong`] as soon as the limit is read.
+    pub max_line_len: usize,
+}
+
+impl Default for CsvLimits {
+    fn default() -> Self {
+        Self {
+            max_fields: 1024,
+            max_line_len: 1 << 20,
+        }
+    }
+}
+
+/// The header line written before CSV records.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum CsvHeader {
+    /// The YPBank header, `TX_ID,TX_TYPE,...,DESCRIPTION`.
+    #[default]
+    Standard,
+    /// No header, e.g. for records appended to an existing file.
+    None,
+    /// A header of the caller's, written as given. Columns are written in the YPBank
+    /// order all the same.
+    Custom(String),
+}
+
+/// How `TX_ID`, `FROM_USER_ID` and `TO_USER_ID` values are written to CSV.
+///
+/// Spreadsheets such as Excel read long ids as numbers and round them, so that
+/// `1000000000000001` comes back as `1000000000000000`. Readers accept every format
+/// whatever the option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CsvIdFormat {
+    /// Bare numbers, as in `1000000000000001`.
+    #[default]
+    Plain,
+    /// Quoted numbers, as in `"1000000000000001"`.
+    Quoted,
+    /// Text formulas, as in `="1000000000000001"`, kept as text even by spreadsheets
+    /// ignoring quotes.
+    Formula,
+}
+
+/// Lines written after each TXT record, keeping multi-record files readable.
+///
+/// Readers accept blank lines and `---` lines between records whatever the separator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TxtSeparator {
+    /// An empty line.
+    #[default]
+    BlankLine,
+    /// A `---` line.
+    Dashes,
+    /// Nothing, records follow each other directly.
+    None,
+}
+
+impl TxtSeparator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxtSeparator::BlankLine => "\n",
+            TxtSeparator::Dashes => "---\n",
+            TxtSeparator::None => "",
+        }
+    }
+}
+
+/// How `#` comment lines of TXT files are read.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TxtComments {
+    /// Ignores comments.
+    #[default]
+    Skip,
+    /// Ignores comments after adding them to the log with their line numbers, so that
+    /// annotations of the source file aren't lost in a conversion.
+    Collect(CommentLog),
+    /// Fails with [`crate::ParseError::InvalidRow`] at the first comment.
+    Reject,
+}
+
+/// What readers keep of descriptions, for services that must not hold them, e.g. under
+/// a data-minimization policy, while reading the numeric fields.
+///
+/// Descriptions are replaced before records leave the reader, and before they would be
+/// added to [`ParseOptions::intern_descriptions`]. Binary readers skip the description
+/// bytes without decoding them; text formats hold the line being parsed only until the
+/// record is built.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DescriptionRedaction {
+    /// Keeps descriptions.
+    #[default]
+    Keep,
+    /// Reads every description as empty.
+    Drop,
+    /// Reads every description that is not empty as this text, e.g. `[REDACTED]`, so
+    /// that records that had one can still be told apart. Records share the text.
+    Mask(Arc<str>),
+}
+
+impl DescriptionRedaction {
+    /// Returns what a record whose description is `description_len` bytes long holds
+    /// instead, or `None` to keep the description.
+    pub(crate) fn redact(&self, description_len: usize) -> Option<Arc<str>> {
+        match self {
+            DescriptionRedaction::Keep => None,
+            DescriptionRedaction::Mask(mask) if description_len > 0 => Some(mask.clone()),
+            DescriptionRedaction::Drop | DescriptionRedaction::Mask(_) => Some("".into()),
+        }
+    }
+}
+
+/// Unit of `TIMESTAMP` values in files. Records always hold milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimestampUnit {
+    /// Milliseconds since the Unix epoch.
+    #[default]
+    Millis,
+    /// Seconds since the Unix epoch. Written timestamps lose their milliseconds.
+    Seconds,
+    /// Seconds for values below [`TimestampUnit::AUTO_THRESHOLD`], milliseconds otherwise,
+    /// decided for each value. Written as milliseconds.
+    Auto,
+}
+
+impl TimestampUnit {
+    /// `10^11`: in milliseconds, March 1973; in seconds, the year 5138.
+    pub const AUTO_THRESHOLD: u64 = 100_000_000_000;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimestampUnit::Millis => "ms",
+            TimestampUnit::Seconds => "s",
+            TimestampUnit::Auto => "auto",
+        }
+    }
+
+    /// Converts a timestamp read in this unit to milliseconds.
+    pub fn to_millis(&self, ts: u64) -> Result<u64, ParseError> {
+        let seconds = match self {
+            TimestampUnit::Millis => false,
+            TimestampUnit::Seconds => true,
+            TimestampUnit::Auto => ts < Self::AUTO_THRESHOLD,
+        };
+        if !seconds {
+            return Ok(ts);
+        }
+        ts.checked_mul(1000)
+            .ok_or_else(|| ParseError::ValueOutOfRange {
+                field: TIMESTAMP.to_string(),
+                value: ts.to_string(),
+            })
+    }
+
+    /// Converts a timestamp in milliseconds to the value written in this unit.
+    pub fn from_millis(&self, ts: u64) -> u64 {
+        match self {
+            TimestampUnit::Seconds => ts / 1000,
+            TimestampUnit::Millis | TimestampUnit::Auto => ts,
+        }
+    }
+}
+
+impl FromStr for TimestampUnit {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ms" => Ok(TimestampUnit::Millis),
+            "s" => Ok(TimestampUnit::Seconds),
+            "auto" => Ok(TimestampUnit::Auto),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "timestamp unit".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Range of plausible record timestamps in milliseconds, bounds included.
+///
+/// Catches unit mistakes and garbage values early: seconds read as milliseconds fall in
+/// January 1970, microseconds read as milliseconds tens of thousands of years ahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampWindow {
+    pub from: u64,
+    pub to: u64,
+}
+
+impl TimestampWindow {
+    /// 2000-01-01T00:00:00Z in milliseconds.
+    pub const Y2000: u64 = 946_684_800_000;
+    /// Time the upper bound of [`TimestampWindow::plausible`] is ahead of now, allowing
+    /// for clocks running ahead.
+    pub const FUTURE_MARGIN_MS: u64 = 24 * 60 * 60 * 1000;
+
+    pub fn new(from: u64, to: u64) -> Self {
+        Self { from, to }
+    }
+
+    /// From 2000-01-01 to one day from now.
+    pub fn plausible() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        Self::new(Self::Y2000, now.saturating_add(Self::FUTURE_MARGIN_MS))
+    }
+
+    /// Lifts the upper bound, for records dated ahead on purpose such as scheduled
+    /// transfers.
+    pub fn allow_future(self) -> Self {
+        Self {
+            to: u64::MAX,
+            ..self
+        }
+    }
+
+    pub fn contains(&self, ts: u64) -> bool {
+        (self.from..=self.to).contains(&ts)
+    }
+}
+
+/// How records with timestamps outside a [`TimestampWindow`] are read.
+///
+/// Timestamps are checked in milliseconds, once converted from the
+/// [`TimestampUnit`] of the file.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum TimestampCheck {
+    /// Accepts every timestamp.
+    #[default]
+    Off,
+    /// Keeps the records after adding them to the log.
+    Warn(TimestampWindow, TimestampLog),
+    /// Fails with [`crate::ParseError::ValueOutOfRange`] for the `TIMESTAMP` of the record.
+    Reject(TimestampWindow),
+}
+
+impl TimestampCheck {
+    pub(crate) fn check(&self, record: &YPBankRecord) -> Result<(), ParseError> {
+        match self {
+            TimestampCheck::Warn(window, log) if !window.contains(record.ts) => {
+                log.push(ImplausibleTimestamp {
+                    id: record.id,
+                    ts: record.ts,
+                });
+                Ok(())
+            }
+            TimestampCheck::Reject(window) if !window.contains(record.ts) => {
+                Err(ParseError::ValueOutOfRange {
+                    field: TIMESTAMP.to_string(),
+                    value: record.ts.to_string(),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Options controlling how records are parsed.
+///
+/// # Examples
+///
+/// ```
+/// use parser::ParseOptions;
+///
+/// let mut options = ParseOptions::default();
+/// options
+///     .txt_key_aliases
+///     .insert("SENDER".to_string(), "FROM_USER_ID".to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Alternative TXT keys mapped to the canonical field names.
+    ///
+    /// Keys are matched case-insensitively, so `tx_id` and `TX_ID` are the same key.
+    pub txt_key_aliases: HashMap<String, String>,
+
+    /// Pool used to share repeated descriptions between parsed records.
+    ///
+    /// Files often contain thousands of identical descriptions (`ATM WITHDRAWAL`); with a
+    /// pool set, all of them point to a single allocation. Disabled by default.
+    pub intern_descriptions: Option<DescriptionPool>,
+
+    /// Drops or masks descriptions while reading. Kept by default.
+    pub redact_descriptions: DescriptionRedaction,
+
+    /// Handling of signs, leading zeros and whitespace in CSV and TXT numbers.
+    pub numeric_policy: NumericPolicy,
+
+    /// Separators of localized CSV and TXT `AMOUNT` values such as `1 000,50`.
+    ///
+    /// When set, amounts are read with these separators and converted to minor units.
+    /// Disabled by default.
+    pub amount_format: Option<AmountFormat>,
+
+    /// Mapping of foreign CSV columns and values to YPBank fields.
+    ///
+    /// When set, CSV headers are matched by name instead of requiring the YPBank header.
+    pub csv_schema: Option<SchemaMap>,
+
+    /// Handling of CSV quoted fields spanning lines or never closed.
+    ///
+    /// By default a quoted field may span up to 16 lines.
+    pub csv_quotes: CsvQuotes,
+
+    /// Bounds on the fields and length of CSV lines.
+    pub csv_limits: CsvLimits,
+
+    /// Header line written before CSV records. The YPBank header by default.
+    pub csv_write_header: CsvHeader,
+
+    /// Whether CSV inputs start with a header line. Enabled by default.
+    ///
+    /// Without a header, columns are read in the YPBank order, also when a schema map
+    /// maps their values.
+    pub csv_has_header: bool,
+
+    /// Format of ids written to CSV. Bare numbers by default.
+    pub csv_id_format: CsvIdFormat,
+
+    /// Separator written after each TXT record. A blank line by default.
+    pub txt_separator: TxtSeparator,
+
+    /// Handling of TXT comment lines. Ignored by default.
+    pub txt_comments: TxtComments,
+
+    /// Handling of empty lines between CSV records.
+    ///
+    /// By default records after an empty line fail, so that missing data isn't masked.
+    pub csv_empty_lines: CsvEmptyLines,
+
+    /// Handling of header lines between CSV records.
+    ///
+    /// By default lines equal to the header are skipped, so that concatenated files are
+    /// read as one.
+    pub csv_repeated_headers: CsvRepeatedHeaders,
+
+    /// Fails with [`crate::ParseError::TrailingData`] when anything but whitespace
+    /// follows the last record, instead of ignoring it. Disabled by default.
+    ///
+    /// Catches files concatenated by mistake, e.g. a second CSV header after an empty
+    /// line.
+    pub reject_trailing_data: bool,
+
+    /// Capacity in bytes of the buffer that reads go through, 8 KiB by default. Larger
+    /// buffers mean fewer reads on fast storage.
+    ///
+    /// Unused by [`crate::CommonParser::from_buf_read`] and
+    /// [`crate::CommonParser::iter_buf_read`], which read through the caller's buffer.
+    pub read_buffer_capacity: usize,
+
+    /// Number of records expected, used to allocate the records read by `from_read` at
+    /// once instead of growing them record by record. Not a limit: more records are read
+    /// all the same. Disabled by default.
+    pub capacity_hint: Option<usize>,
+
+    /// Goes on reading after records that fail to parse instead of ending at the first
+    /// error. Disabled by default.
+    ///
+    /// Record iterators still yield the error of every invalid record, and
+    /// [`crate::RecordStream::raw_record`] returns its input bytes. Formats that cannot
+    /// find the next record after an invalid one (MessagePack, CBOR) end there anyway.
+    pub skip_invalid_records: bool,
+
+    /// Ids of records that are left out when reading, as if they were deleted.
+    ///
+    /// Corrects archives that must not be edited without rewriting them. Disabled by
+    /// default.
+    pub tombstones: Option<Tombstones>,
+
+    /// Zone of local datetimes accepted in CSV and TXT `TIMESTAMP` values.
+    ///
+    /// When set, `TIMESTAMP: 2021-10-01 00:21:00` is read as a local time in this zone,
+    /// while plain millisecond values are still accepted. Disabled by default.
+    #[cfg(feature = "tz")]
+    pub timestamp_tz: Option<TimeZone>,
+
+    /// Unit of `TIMESTAMP` values read and written, converted from and to the
+    /// milliseconds of records. Milliseconds by default.
+    ///
+    /// Files mixing seconds and milliseconds are read with [`TimestampUnit::Auto`].
+    pub timestamp_unit: TimestampUnit,
+
+    /// Handling of timestamps outside a plausible window, such as seconds read as
+    /// milliseconds. Unchecked by default.
+    pub timestamp_check: TimestampCheck,
+
+    /// Rules every record read must pass, failing with
+    /// [`crate::ParseError::RuleViolation`] at records they refuse. None by default.
+    ///
+    /// With [`ParseOptions::skip_invalid_records`], reading goes on after refused records.
+    #[cfg(feature = "wasm-rules")]
+    pub wasm_rules: Vec<WasmRule>,
+
+    /// Expressions of records to refuse when reading: records matching any of them fail
+    /// with [`crate::ParseError::RuleViolation`]. None by default.
+    ///
+    /// With [`ParseOptions::skip_invalid_records`], reading goes on after refused records.
+    pub reject_rules: Vec<RuleExpr>,
+
+    /// Keeps the extras of [`crate::YPBankRecordExt`] records: TXT keys other than the
+    /// record fields and binary extension entries are read into them, and
+    /// [`crate::CommonParser::write_ext_to`] writes them as extra CSV columns, TXT keys
+    /// and binary extension entries. Disabled by default, so that extras are stripped
+    /// and files stay readable by readers expecting plain records.
+    ///
+    /// Extra CSV columns are read with [`crate::UnknownColumns::Capture`].
+    pub preserve_extras: bool,
+
+    /// Stable-sorts records by this key before writing them, for canonical output that
+    /// does not depend on the order records were produced in. Records with equal keys
+    /// keep their order. Disabled by default.
+    ///
+    /// Unsorted, records are written in the order given, in every format.
+    /// [`crate::CommonParser::append_iter`] always writes in the order given, as records
+    /// written before cannot be reordered.
+    pub sort_output: Option<SortKey>,
+
+    /// Table whose columns PostgreSQL `COPY` output holds, in table order. A
+    /// `transactions` table with a column per YPBank field by default.
+    pub pg_table: Option<PgTable>,
+
+    /// Checks records with [`YPBankRecord::validate`] before writing them, failing with
+    /// [`crate::ParseError::InvalidRecord`] at the first invalid one instead of writing a
+    /// file the readers reject. Disabled by default, enabled by [`ParseOptions::strict`].
+    ///
+    /// Applies to the formats the crate reads back; reports such as HTML are not checked.
+    pub validate_writes: bool,
+
+    /// Writes the [`crate::ColumnStats`] of the records next to files written with
+    /// [`crate::CommonParser::write_to_path_atomic`] and
+    /// [`crate::CommonParser::write_iter_to_path_atomic`], computed while they are
+    /// written. Disabled by default.
+    pub stats_sidecar: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            txt_key_aliases: TXT_KEY_ALIASES
+                .iter()
+                .map(|(alias, field)| (alias.to_string(), field.to_string()))
+                .collect(),
+            intern_descriptions: None,
+            redact_descriptions: DescriptionRedaction::default(),
+            numeric_policy: NumericPolicy::default(),
+            amount_format: None,
+            csv_schema: None,
+            csv_quotes: CsvQuotes::default(),
+            csv_limits: CsvLimits::default(),
+            csv_write_header: CsvHeader::default(),
+            csv_has_header: true,
+            csv_id_format: CsvIdFormat::default(),
+            txt_separator: TxtSeparator::default(),
+            txt_comments: TxtComments::default(),
+            csv_empty_lines: CsvEmptyLines::default(),
+            csv_repeated_headers: CsvRepeatedHeaders::default(),
+            reject_trailing_data: false,
+            read_buffer_capacity: 8 * 1024,
+            capacity_hint: None,
+            skip_invalid_records: false,
+            tombstones: None,
+            #[cfg(feature = "tz")]
+            timestamp_tz: None,
+            timestamp_unit: TimestampUnit::default(),
+            timestamp_check: TimestampCheck::default(),
+            #[cfg(feature = "wasm-rules")]
+            wasm_rules: Vec::new(),
+            reject_rules: Vec::new(),
+            preserve_extras: false,
+            sort_output: None,
+            pg_table: None,
+            validate_writes: false,
+            stats_sidecar: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// Options accepting only canonical input and output: numbers under
+    /// [`NumericPolicy::strict`], CSV quotes closed on their line, no data after the last
+    /// record, and writes checked with [`ParseOptions::validate_writes`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{CommonParser, Format, ParseError, ParseOptions, TransactionStatus};
+    /// use parser::{TransactionType, YPBankRecord};
+    ///
+    /// let withdrawal =
+    ///     YPBankRecord::new(1, TransactionType::Withdrawal, 2, 5, 100, 3, TransactionStatus::Success, "");
+    /// let parser = CommonParser::with_options(Format::Csv, ParseOptions::strict());
+    ///
+    /// let result = parser.write_to(&mut Vec::new(), &vec![withdrawal]);
+    /// assert!(matches!(result, Err(ParseError::InvalidRecord { index: 0, .. })));
+    /// ```
+    pub fn strict() -> Self {
+        Self {
+            numeric_policy: NumericPolicy::strict(),
+            csv_quotes: CsvQuotes::Strict,
+            reject_trailing_data: true,
+            validate_writes: true,
+            ..Self::default()
+        }
+    }
+}
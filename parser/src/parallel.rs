@@ -0,0 +1,296 @@
+use crate::CommonParser;
+use crate::error::ParseError;
+use crate::pipeline::Pipeline;
+use crate::record::YPBankRecord;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, channel, sync_channel};
+
+/// Records handed to a worker at a time.
+const BATCH_SIZE: usize = 1024;
+/// Batches in flight per worker, so that workers don't wait for the next one while
+/// memory stays bounded.
+const BATCHES_PER_THREAD: usize = 2;
+
+/// The output of a batch, the number of records given to the writer, and the error that
+/// ended it early, if any.
+type Serialized = (Vec<u8>, u64, Option<ParseError>);
+
+/// Runs `pipeline` on batches of records and serializes them on `threads` worker threads,
+/// while the calling thread reads the records and writes the batches in input order.
+///
+/// Output and errors are those of a sequential [`CommonParser::write_iter`]: the records
+/// before the first failing one are written, then the error is returned.
+pub(crate) fn write_parallel<W: Write>(
+    parser: &CommonParser,
+    w: &mut W,
+    records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    pipeline: &Pipeline,
+    threads: usize,
+) -> Result<(), ParseError> {
+    let max_in_flight = threads * BATCHES_PER_THREAD;
+    let (work_sender, work_receiver) = sync_channel::<(u64, Vec<YPBankRecord>)>(max_in_flight);
+    let (result_sender, result_receiver) = channel::<(u64, Serialized)>();
+    let work_receiver = Mutex::new(work_receiver);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let result_sender = result_sender.clone();
+            let work_receiver = &work_receiver;
+            scope.spawn(move || {
+                while let Ok(Ok((seq, batch))) = work_receiver.lock().map(|batches| batches.recv())
+                {
+                    let serialized = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        serialize_batch(parser, pipeline, batch)
+                    }))
+                    .unwrap_or_else(|_| (Vec::new(), 0, Some(worker_failed())));
+                    if result_sender.send((seq, serialized)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(result_sender);
+
+        // Writes the header of the format, so that batches can be appended.
+        parser.write_iter(w, std::iter::empty())?;
+        let mut ordered = OrderedWriter {
+            w,
+            results: result_receiver,
+            pending: BTreeMap::new(),
+            next: 0,
+            written: 0,
+        };
+        let mut records = records.into_iter();
+        let mut read_error = None;
+        let mut dispatched = 0;
+        loop {
+            let mut batch = Vec::with_capacity(BATCH_SIZE);
+            for record in records.by_ref() {
+                match record {
+                    Ok(record) => batch.push(record),
+                    Err(err) => {
+                        read_error = Some(err);
+                        break;
+                    }
+                }
+                if batch.len() == BATCH_SIZE {
+                    break;
+                }
+            }
+            let last = batch.len() < BATCH_SIZE;
+            if !batch.is_empty() {
+                while dispatched - ordered.next >= max_in_flight as u64 {
+                    ordered.receive()?;
+                }
+                work_sender
+                    .send((dispatched, batch))
+                    .map_err(|_| worker_failed())?;
+                dispatched += 1;
+            }
+            if last {
+                break;
+            }
+        }
+        drop(work_sender);
+
+        while ordered.next < dispatched {
+            ordered.receive()?;
+        }
+        read_error.map_or(Ok(()), Err)
+    })
+}
+
+fn worker_failed() -> ParseError {
+    ParseError::IOError("conversion thread failed".to_string())
+}
+
+/// Runs a batch through the pipeline and serializes the records it keeps.
+fn serialize_batch(
+    parser: &CommonParser,
+    pipeline: &Pipeline,
+    batch: Vec<YPBankRecord>,
+) -> Serialized {
+    let mut processed = Vec::with_capacity(batch.len());
+    let mut error = None;
+    for record in batch {
+        match pipeline.process(record) {
+            Ok(Some(record)) => processed.push(record),
+            Ok(None) => {}
+            Err(err) => {
+                error = Some(err);
+                break;
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    let count = processed.len() as u64;
+    if let Err(err) = parser.append_iter(&mut output, processed.into_iter().map(Ok)) {
+        return (output, count, Some(err));
+    }
+    (output, count, error)
+}
+
+/// Writes serialized batches in the order they were dispatched.
+struct OrderedWriter<'w, W: Write> {
+    w: &'w mut W,
+    results: Receiver<(u64, Serialized)>,
+    pending: BTreeMap<u64, Serialized>,
+    next: u64,
+    /// Records given to the writer in the batches written so far.
+    written: u64,
+}
+
+impl<W: Write> OrderedWriter<'_, W> {
+    /// Waits for a batch, then writes all batches that are next in order.
+    fn receive(&mut self) -> Result<(), ParseError> {
+        let (seq, serialized) = self.results.recv().map_err(|_| worker_failed())?;
+        self.pending.insert(seq, serialized);
+        while let Some((output, count, error)) = self.pending.remove(&self.next) {
+            self.w.write_all(&output)?;
+            self.next += 1;
+            match error {
+                // Batches count their records from 0.
+                Some(ParseError::InvalidRecord { index, reason }) => {
+                    return Err(ParseError::InvalidRecord {
+                        index: self.written + index,
+                        reason,
+                    });
+                }
+                Some(err) => return Err(err),
+                None => self.written += count,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod parallel_tests {
+    use super::*;
+    use crate::common::{Format, TransactionStatus, TransactionType};
+    use crate::options::ParseOptions;
+    use crate::pipeline::Stage;
+
+    struct FailAt(u64);
+
+    impl Stage for FailAt {
+        fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+            match record.id == self.0 {
+                true => Err(ParseError::InvalidRow(record.id.to_string())),
+                false => Ok(Some(record)),
+            }
+        }
+    }
+
+    fn records(count: u64) -> Vec<YPBankRecord> {
+        (0..count)
+            .map(|id| {
+                YPBankRecord::new(
+                    id,
+                    TransactionType::Transfer,
+                    id % 7 + 1,
+                    id % 5 + 1,
+                    id as i64 * 3,
+                    1633036860000 + id,
+                    TransactionStatus::Success,
+                    format!("Record number {}", id),
+                )
+            })
+            .collect()
+    }
+
+    fn sequential(parser: &CommonParser, records: Vec<YPBankRecord>) -> Vec<u8> {
+        let mut output = Vec::new();
+        parser
+            .write_iter(&mut output, records.into_iter().map(Ok))
+            .expect("Should write");
+        output
+    }
+
+    #[test]
+    fn test_matches_sequential_output() {
+        let records = records(BATCH_SIZE as u64 * 5 + 17);
+        let formats = [
+            Format::Csv,
+            Format::Txt,
+            Format::Bin,
+            Format::Toml,
+            Format::PgCopy,
+            #[cfg(feature = "msgpack")]
+            Format::MsgPack,
+            #[cfg(feature = "cbor")]
+            Format::Cbor,
+        ];
+        for format in formats {
+            let parser = CommonParser::new(format);
+            let mut output = Vec::new();
+            write_parallel(
+                &parser,
+                &mut output,
+                records.clone().into_iter().map(Ok),
+                &Pipeline::new(),
+                3,
+            )
+            .expect("Should write");
+            assert!(
+                output == sequential(&parser, records.clone()),
+                "{:?}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn test_writes_records_before_error() {
+        let records = records(BATCH_SIZE as u64 * 3);
+        let parser = CommonParser::new(Format::Csv);
+        let failing = BATCH_SIZE as u64 + 5;
+        let mut output = Vec::new();
+
+        let result = write_parallel(
+            &parser,
+            &mut output,
+            records.clone().into_iter().map(Ok),
+            &Pipeline::new().with_stage(FailAt(failing)),
+            4,
+        );
+
+        assert_eq!(result, Err(ParseError::InvalidRow(failing.to_string())));
+        assert!(output == sequential(&parser, records[..failing as usize].to_vec()));
+
+        let mut output = Vec::new();
+        let read = records
+            .iter()
+            .take(10)
+            .cloned()
+            .map(Ok)
+            .chain([Err(ParseError::UnexpectedEOF)]);
+        let result = write_parallel(&parser, &mut output, read, &Pipeline::new(), 2);
+        assert_eq!(result, Err(ParseError::UnexpectedEOF));
+        assert!(output == sequential(&parser, records[..10].to_vec()));
+    }
+
+    #[test]
+    fn test_invalid_record_index() {
+        let mut records = records(BATCH_SIZE as u64 * 3);
+        let invalid = BATCH_SIZE * 2 + 7;
+        records[invalid].to_user_id = 0;
+        let options = ParseOptions {
+            validate_writes: true,
+            ..ParseOptions::default()
+        };
+        let parser = CommonParser::with_options(Format::Csv, options);
+
+        let mut output = Vec::new();
+        let read = records.into_iter().map(Ok);
+        let result = write_parallel(&parser, &mut output, read, &Pipeline::new(), 4);
+        assert!(matches!(
+            result,
+            Err(ParseError::InvalidRecord { index, .. }) if index == invalid as u64
+        ));
+    }
+}
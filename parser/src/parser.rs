@@ -1,47 +1,757 @@
 use crate::error::ParseError;
-use crate::record::YPBankRecord;
+use crate::intern::DescriptionPool;
+use crate::options::{DescriptionRedaction, ParseOptions, TimestampCheck, TimestampUnit};
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use crate::rule_expr::RuleExpr;
+use crate::tombstone::Tombstones;
+#[cfg(feature = "wasm-rules")]
+use crate::wasm_rule::WasmRule;
+use std::collections::BTreeMap;
 
-pub trait YPBankRecordParser {
-    fn from_read<R: std::io::BufRead>(r: &mut R) -> Result<Option<YPBankRecord>, ParseError>;
-    fn write_to<W: std::io::Write>(record: &YPBankRecord, w: &mut W) -> Result<(), ParseError>;
+/// Bytes of written records gathered before each write to the underlying writer.
+const WRITE_BATCH_BYTES: usize = 64 * 1024;
+
+/// Record readers and writers configured by [`ParseOptions`].
+pub trait FromOptions: Default {
+    fn with_options(_: &ParseOptions) -> Self {
+        Self::default()
+    }
+}
+
+/// Reads single records of one format.
+///
+/// Record readers are stateful so that the buffers used while reading can be reused
+/// across records instead of being allocated per call.
+pub trait RecordReader: FromOptions {
+    #[allow(clippy::wrong_self_convention)]
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError>;
+
+    /// Skips what is left of a record whose `from_read` failed, so that reading can go on
+    /// with the next record. Returns `false` when the format cannot find the next record.
+    fn skip_invalid<R: std::io::BufRead>(&mut self, _r: &mut R) -> Result<bool, ParseError> {
+        Ok(false)
+    }
+
+    /// Takes the extras of the last record read, see [`YPBankRecordExt`]. Formats without
+    /// extras have none.
+    fn take_extras(&mut self) -> BTreeMap<String, String> {
+        BTreeMap::new()
+    }
+}
+
+/// Writes single records of one format, reusing its buffers across records.
+pub trait RecordWriter: FromOptions {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError>;
+
+    /// Writes `record` with its extras. Formats without a place for extras fail on
+    /// records that have some.
+    fn write_ext_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecordExt,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        if !record.extras.is_empty() {
+            return Err(ParseError::UnsupportedOperation(
+                "extras cannot be written in this format".to_string(),
+            ));
+        }
+        self.write_to(&record.core, w)
+    }
 }
 
+/// Reads and writes single records of one format.
+///
+/// Implemented for every type that is both a [`RecordReader`] and a [`RecordWriter`];
+/// formats supporting one direction only implement just that trait.
+pub trait YPBankRecordParser: RecordReader + RecordWriter {}
+
+impl<T: RecordReader + RecordWriter> YPBankRecordParser for T {}
+
+/// Writers are buffered, so that formats writing records field by field don't issue one
+/// write per field on unbuffered writers such as files.
 pub trait Parser<RecordParser: YPBankRecordParser> {
-    fn from_read<Reader: std::io::Read>(r: &mut Reader) -> Result<Vec<YPBankRecord>, ParseError> {
-        let mut buf_reader = std::io::BufReader::new(r);
+    fn from_read<Reader: std::io::Read>(
+        r: &mut Reader,
+        options: &ParseOptions,
+    ) -> Result<Vec<YPBankRecord>, ParseError> {
+        let mut records = Vec::with_capacity(options.capacity_hint.unwrap_or_default());
+        for record in Self::iter_read(r, options)? {
+            records.push(record?);
+        }
+        Ok(records)
+    }
 
-        Self::pre_read(&mut buf_reader)?;
+    /// Reads the format header and returns an iterator parsing one record at a time.
+    fn iter_read<Reader: std::io::Read>(
+        r: Reader,
+        options: &ParseOptions,
+    ) -> Result<RecordIter<std::io::BufReader<Reader>, RecordParser>, ParseError> {
+        Self::iter_read_at(r, options, 0)
+    }
 
-        let mut records: Vec<YPBankRecord> = vec![];
-        loop {
-            let record_opt = RecordParser::from_read(&mut buf_reader)?;
-            match record_opt {
-                Some(record) => records.push(record),
-                None => break,
-            }
+    /// Returns an iterator for a reader placed at `offset`, a record boundary reported
+    /// by [`RecordSource::offset`] of an earlier read. The header is only read at `0`.
+    fn iter_read_at<Reader: std::io::Read>(
+        r: Reader,
+        options: &ParseOptions,
+        offset: u64,
+    ) -> Result<RecordIter<std::io::BufReader<Reader>, RecordParser>, ParseError> {
+        let r = std::io::BufReader::with_capacity(options.read_buffer_capacity, r);
+        Self::iter_buf_read_at(r, options, offset)
+    }
+
+    /// Like [`Parser::iter_read_at`], reading through the buffer of `r` instead of a new
+    /// one.
+    fn iter_buf_read_at<Reader: std::io::BufRead>(
+        r: Reader,
+        options: &ParseOptions,
+        offset: u64,
+    ) -> Result<RecordIter<Reader, RecordParser>, ParseError> {
+        let mut reader = OffsetReader {
+            inner: r,
+            offset,
+            raw: options.skip_invalid_records.then(Vec::new),
+        };
+        let mut record_parser = RecordParser::with_options(options);
+
+        if offset == 0 {
+            Self::pre_read(&mut reader, &mut record_parser)?;
         }
 
+        Ok(RecordIter {
+            reader,
+            record_parser,
+            pool: options.intern_descriptions.clone(),
+            redaction: options.redact_descriptions.clone(),
+            tombstones: options.tombstones.clone(),
+            timestamp_unit: options.timestamp_unit,
+            timestamp_check: options.timestamp_check.clone(),
+            #[cfg(feature = "wasm-rules")]
+            wasm_rules: options.wasm_rules.clone(),
+            reject_rules: options.reject_rules.clone(),
+            reject_trailing_data: options.reject_trailing_data,
+            skip_invalid_records: options.skip_invalid_records,
+            done: false,
+        })
+    }
+
+    /// Like [`Parser::iter_buf_read_at`] for a reader that can seek: the format header is
+    /// read from the start of `r` before reading goes on at `offset`, so that a resumed
+    /// read is configured like the first one, e.g. with the columns of a schema map.
+    fn iter_buf_read_resumed<Reader: std::io::BufRead + std::io::Seek>(
+        mut r: Reader,
+        options: &ParseOptions,
+        offset: u64,
+    ) -> Result<RecordIter<Reader, RecordParser>, ParseError> {
+        r.seek(std::io::SeekFrom::Start(0))?;
+        let mut records = Self::iter_buf_read_at(r, options, 0)?;
+        if offset > 0 {
+            records
+                .reader
+                .inner
+                .seek(std::io::SeekFrom::Start(offset))?;
+            records.reader.offset = offset;
+            if let Some(raw) = &mut records.reader.raw {
+                raw.clear();
+            }
+        }
         Ok(records)
     }
 
     fn write_to<Writer: std::io::Write>(
         w: &mut Writer,
         records: &Vec<YPBankRecord>,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let mut w = std::io::BufWriter::with_capacity(WRITE_BATCH_BYTES, w);
+        Self::pre_write(&mut w, options)?;
+
+        let mut record_parser = RecordParser::with_options(options);
+        for (index, record) in (0..).zip(records) {
+            check_write(options, index, record)?;
+            write_record(&mut record_parser, record, &mut w, options.timestamp_unit)?;
+            Self::write_separator(&mut w, options)?;
+        }
+
+        std::io::Write::flush(&mut w)?;
+        Ok(())
+    }
+
+    /// Writes records with their extras when [`ParseOptions::preserve_extras`] is set, and
+    /// just their core records otherwise.
+    fn write_ext_to<Writer: std::io::Write>(
+        w: &mut Writer,
+        records: &[YPBankRecordExt],
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let mut w = std::io::BufWriter::with_capacity(WRITE_BATCH_BYTES, w);
+        let mut record_parser = RecordParser::with_options(options);
+        if options.preserve_extras {
+            Self::pre_write_ext(&mut w, &mut record_parser, records, options)?;
+        } else {
+            Self::pre_write(&mut w, options)?;
+        }
+
+        for (index, record) in records.iter().enumerate() {
+            check_write(options, index, &record.core)?;
+            if options.preserve_extras {
+                write_record_ext(&mut record_parser, record, &mut w, options.timestamp_unit)?;
+            } else {
+                write_record(
+                    &mut record_parser,
+                    &record.core,
+                    &mut w,
+                    options.timestamp_unit,
+                )?;
+            }
+            Self::write_separator(&mut w, options)?;
+        }
+
+        std::io::Write::flush(&mut w)?;
+        Ok(())
+    }
+
+    /// Writes records as they are produced, stopping at the first error.
+    fn write_iter<Writer: std::io::Write>(
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        options: &ParseOptions,
     ) -> Result<(), ParseError> {
-        Self::pre_write(w)?;
+        Self::pre_write(w, options)?;
+        Self::append_iter(w, records, options)
+    }
 
-        for record in records {
-            RecordParser::write_to(record, w)?;
+    /// Writes records without the format header, continuing an earlier write.
+    fn append_iter<Writer: std::io::Write>(
+        w: &mut Writer,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        let mut w = std::io::BufWriter::with_capacity(WRITE_BATCH_BYTES, w);
+        let mut record_parser = RecordParser::with_options(options);
+        for (index, record) in records.into_iter().enumerate() {
+            let record = record?;
+            check_write(options, index, &record)?;
+            write_record(&mut record_parser, &record, &mut w, options.timestamp_unit)?;
+            Self::write_separator(&mut w, options)?;
         }
 
+        std::io::Write::flush(&mut w)?;
+        Ok(())
+    }
+
+    /// Writes what follows each record, so that appended records stay separated too.
+    fn write_separator<Writer: std::io::Write>(
+        _: &mut Writer,
+        _options: &ParseOptions,
+    ) -> Result<(), ParseError> {
         Ok(())
     }
 
-    fn pre_read<Reader: std::io::BufRead>(_: &mut Reader) -> Result<(), ParseError> {
+    /// Reads the format header, configuring `record_parser` for the records that follow.
+    fn pre_read<Reader: std::io::BufRead>(
+        _: &mut Reader,
+        _record_parser: &mut RecordParser,
+    ) -> Result<(), ParseError> {
         Ok(())
     }
 
-    fn pre_write<Writer: std::io::Write>(_: &mut Writer) -> Result<(), ParseError> {
+    fn pre_write<Writer: std::io::Write>(
+        _: &mut Writer,
+        _options: &ParseOptions,
+    ) -> Result<(), ParseError> {
         Ok(())
     }
+
+    /// Writes the format header for `records` written with their extras, configuring
+    /// `record_parser` for them.
+    fn pre_write_ext<Writer: std::io::Write>(
+        w: &mut Writer,
+        _record_parser: &mut RecordParser,
+        _records: &[YPBankRecordExt],
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        Self::pre_write(w, options)
+    }
+}
+
+/// Checks `record`, the `index`th of a write, when [`ParseOptions::validate_writes`] is
+/// set.
+fn check_write(
+    options: &ParseOptions,
+    index: usize,
+    record: &YPBankRecord,
+) -> Result<(), ParseError> {
+    if options.validate_writes
+        && let Err(err) = record.validate()
+    {
+        return Err(ParseError::InvalidRecord {
+            index: index as u64,
+            reason: err.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Writes `record` with its timestamp converted to `unit`.
+fn write_record<RecordParser: RecordWriter, W: std::io::Write>(
+    record_parser: &mut RecordParser,
+    record: &YPBankRecord,
+    w: &mut W,
+    unit: TimestampUnit,
+) -> Result<(), ParseError> {
+    let ts = unit.from_millis(record.ts);
+    if ts == record.ts {
+        return record_parser.write_to(record, w);
+    }
+    record_parser.write_to(
+        &YPBankRecord {
+            ts,
+            ..record.clone()
+        },
+        w,
+    )
+}
+
+/// Writes `record` and its extras with its timestamp converted to `unit`.
+fn write_record_ext<RecordParser: RecordWriter, W: std::io::Write>(
+    record_parser: &mut RecordParser,
+    record: &YPBankRecordExt,
+    w: &mut W,
+    unit: TimestampUnit,
+) -> Result<(), ParseError> {
+    let ts = unit.from_millis(record.core.ts);
+    if ts == record.core.ts {
+        return record_parser.write_ext_to(record, w);
+    }
+    record_parser.write_ext_to(
+        &YPBankRecordExt {
+            core: YPBankRecord {
+                ts,
+                ..record.core.clone()
+            },
+            extras: record.extras.clone(),
+        },
+        w,
+    )
+}
+
+/// A reader counting the bytes consumed from it.
+///
+/// With `raw` set, the consumed bytes are kept there as well.
+pub struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+    raw: Option<Vec<u8>>,
+}
+
+impl<R: std::io::Read> std::io::Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.offset += len as u64;
+        if let Some(raw) = &mut self.raw {
+            raw.extend_from_slice(&buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+impl<R: std::io::BufRead> std::io::BufRead for OffsetReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Some(raw) = &mut self.raw
+            && let Ok(buf) = self.inner.fill_buf()
+        {
+            raw.extend_from_slice(&buf[..amount]);
+        }
+        self.inner.consume(amount);
+        self.offset += amount as u64;
+    }
+}
+
+/// A record iterator that knows how much of its input it has consumed.
+pub trait RecordSource: Iterator<Item = Result<YPBankRecord, ParseError>> {
+    /// Returns the input offset right after the last returned record.
+    fn offset(&self) -> u64;
+
+    /// Returns the input bytes of the last returned record or error when reading with
+    /// [`ParseOptions::skip_invalid_records`], and nothing otherwise. Before the first
+    /// record these are the bytes of the format header.
+    fn raw_record(&self) -> &[u8];
+}
+
+/// Streams records parsed by a [`RecordReader`].
+///
+/// The iterator ends after the first error, unless invalid records are skipped.
+pub struct RecordIter<R: std::io::BufRead, RecordParser: RecordReader> {
+    reader: OffsetReader<R>,
+    record_parser: RecordParser,
+    pool: Option<DescriptionPool>,
+    redaction: DescriptionRedaction,
+    tombstones: Option<Tombstones>,
+    timestamp_unit: TimestampUnit,
+    timestamp_check: TimestampCheck,
+    #[cfg(feature = "wasm-rules")]
+    wasm_rules: Vec<WasmRule>,
+    reject_rules: Vec<RuleExpr>,
+    reject_trailing_data: bool,
+    skip_invalid_records: bool,
+    done: bool,
+}
+
+impl<R: std::io::BufRead, RecordParser: RecordReader> RecordSource for RecordIter<R, RecordParser> {
+    fn offset(&self) -> u64 {
+        self.reader.offset
+    }
+
+    fn raw_record(&self) -> &[u8] {
+        self.reader.raw.as_deref().unwrap_or_default()
+    }
+}
+
+impl<R: std::io::BufRead, RecordParser: RecordReader> RecordIter<R, RecordParser> {
+    /// Pairs every record with its extras.
+    pub(crate) fn with_extras(
+        mut self,
+    ) -> impl Iterator<Item = Result<YPBankRecordExt, ParseError>> {
+        std::iter::from_fn(move || {
+            let record = self.next()?;
+            let extras = self.record_parser.take_extras();
+            Some(record.map(|core| YPBankRecordExt { core, extras }))
+        })
+    }
+
+    /// Consumes the whitespace left after the last record, failing at anything else.
+    fn check_trailing_data(&mut self) -> Result<(), ParseError> {
+        use std::io::BufRead;
+
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(());
+            }
+            if let Some(position) = buf.iter().position(|byte| !byte.is_ascii_whitespace()) {
+                return Err(ParseError::TrailingData(
+                    self.reader.offset + position as u64,
+                ));
+            }
+            let len = buf.len();
+            self.reader.consume(len);
+        }
+    }
+}
+
+impl<R: std::io::BufRead, RecordParser: RecordReader> Iterator for RecordIter<R, RecordParser> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    /// Nothing is known of the records left until the input ends.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done { (0, Some(0)) } else { (0, None) }
+    }
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if let Some(raw) = &mut self.reader.raw {
+                raw.clear();
+            }
+
+            let start = self.reader.offset;
+            return match self.record_parser.from_read(&mut self.reader) {
+                Ok(Some(record))
+                    if self
+                        .tombstones
+                        .as_ref()
+                        .is_some_and(|tombstones| tombstones.contains(record.id)) =>
+                {
+                    continue;
+                }
+                Ok(Some(mut record)) => {
+                    if let Some(description) = self.redaction.redact(record.description.len()) {
+                        record.description = description;
+                    } else if let Some(pool) = &self.pool {
+                        record.description = pool.intern(&record.description);
+                    }
+                    Some(self.timestamp_unit.to_millis(record.ts).and_then(|ts| {
+                        record.ts = ts;
+                        self.timestamp_check.check(&record)?;
+                        #[cfg(feature = "wasm-rules")]
+                        for rule in &self.wasm_rules {
+                            rule.check(&record)?;
+                        }
+                        for rule in &self.reject_rules {
+                            rule.reject(&record)?;
+                        }
+                        Ok(record)
+                    }))
+                }
+                Ok(None) => {
+                    self.done = true;
+                    if self.reject_trailing_data {
+                        return self.check_trailing_data().err().map(Err);
+                    }
+                    None
+                }
+                Err(err) => {
+                    self.done = !self.skip_invalid_records
+                        || !matches!(self.record_parser.skip_invalid(&mut self.reader), Ok(true))
+                        || self.reader.offset == start;
+                    Some(Err(err))
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod record_iter_tests {
+    use super::*;
+    use crate::csv_format::CsvParser;
+    use crate::options::CsvEmptyLines;
+
+    const CSV: &str = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+                       1,DEPOSIT,0,2,100,1633036860000,SUCCESS,Salary\n";
+
+    fn options(reject_trailing_data: bool) -> ParseOptions {
+        ParseOptions {
+            reject_trailing_data,
+            csv_empty_lines: CsvEmptyLines::Stop,
+            ..ParseOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_redact_descriptions() {
+        let data = format!("{}2,DEPOSIT,0,2,100,1633036860000,SUCCESS,\n", CSV);
+        let read = |redaction| {
+            let pool = DescriptionPool::new();
+            let options = ParseOptions {
+                intern_descriptions: Some(pool.clone()),
+                redact_descriptions: redaction,
+                ..ParseOptions::default()
+            };
+            let records = CsvParser::from_read(&mut data.as_bytes(), &options).unwrap();
+            let descriptions: Vec<String> = records
+                .iter()
+                .map(|record| record.description.to_string())
+                .collect();
+            (descriptions, pool.len())
+        };
+
+        assert_eq!(
+            read(DescriptionRedaction::Keep),
+            (vec!["Salary".to_string(), String::new()], 2)
+        );
+        assert_eq!(
+            read(DescriptionRedaction::Drop),
+            (vec![String::new(), String::new()], 0)
+        );
+        assert_eq!(
+            read(DescriptionRedaction::Mask("***".into())),
+            (vec!["***".to_string(), String::new()], 0)
+        );
+    }
+
+    #[test]
+    fn test_trailing_whitespace_is_accepted() {
+        let data = format!("{}\n \n\t\n", CSV);
+        let records = CsvParser::from_read(&mut data.as_bytes(), &options(true));
+        assert_eq!(records.map(|records| records.len()), Ok(1));
+    }
+
+    #[test]
+    fn test_trailing_data_is_rejected() {
+        let data = format!("{}\n{}", CSV, CSV);
+        assert_eq!(
+            CsvParser::from_read(&mut data.as_bytes(), &options(false))
+                .map(|records| records.len()),
+            Ok(1)
+        );
+        assert_eq!(
+            CsvParser::from_read(&mut data.as_bytes(), &options(true)),
+            Err(ParseError::TrailingData(CSV.len() as u64 + 1))
+        );
+    }
+
+    #[test]
+    fn test_raw_record_of_skipped_records() {
+        let data = format!(
+            "{}2,DEPOSIT,0,2,abc,1633036860000,SUCCESS,Bonus\n{}",
+            CSV,
+            &CSV[74..]
+        );
+        let options = ParseOptions {
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+
+        let mut records = CsvParser::iter_read(data.as_bytes(), &options).expect("Should start");
+        assert_eq!(records.raw_record(), &CSV.as_bytes()[..74]);
+        let mut raw = Vec::new();
+        while let Some(record) = records.next() {
+            raw.push((record.is_ok(), records.raw_record().to_vec()));
+        }
+        assert_eq!(
+            raw,
+            vec![
+                (true, CSV.as_bytes()[74..].to_vec()),
+                (
+                    false,
+                    b"2,DEPOSIT,0,2,abc,1633036860000,SUCCESS,Bonus\n".to_vec()
+                ),
+                (true, CSV.as_bytes()[74..].to_vec()),
+            ]
+        );
+        assert!(
+            CsvParser::iter_read(data.as_bytes(), &ParseOptions::default())
+                .expect("Should start")
+                .raw_record()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_tombstoned_records_are_left_out() {
+        let data = format!("{}2,DEPOSIT,0,3,200,1633036860000,SUCCESS,Bonus\n", CSV);
+        let options = ParseOptions {
+            tombstones: Some(Tombstones::new([1, 7])),
+            ..ParseOptions::default()
+        };
+
+        let records = CsvParser::from_read(&mut data.as_bytes(), &options).expect("Should read");
+        assert_eq!(
+            records.iter().map(|record| record.id).collect::<Vec<_>>(),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_timestamp_units() {
+        let data = format!("{}2,DEPOSIT,0,3,200,1633036861,SUCCESS,Bonus\n", CSV);
+        let ts = |unit| {
+            let options = ParseOptions {
+                timestamp_unit: unit,
+                ..ParseOptions::default()
+            };
+            CsvParser::from_read(&mut data.as_bytes(), &options)
+                .map(|records| records.iter().map(|record| record.ts).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            ts(TimestampUnit::Millis),
+            Ok(vec![1633036860000, 1633036861])
+        );
+        assert_eq!(
+            ts(TimestampUnit::Auto),
+            Ok(vec![1633036860000, 1633036861000])
+        );
+        assert_eq!(
+            ts(TimestampUnit::Seconds),
+            Ok(vec![1633036860000000, 1633036861000])
+        );
+
+        let records = CsvParser::from_read(&mut CSV.as_bytes(), &ParseOptions::default())
+            .expect("Should read");
+        let options = ParseOptions {
+            timestamp_unit: TimestampUnit::Seconds,
+            ..ParseOptions::default()
+        };
+        let mut written = Vec::new();
+        CsvParser::write_to(&mut written, &records, &options).expect("Should write");
+        assert!(String::from_utf8_lossy(&written).contains(",1633036860,"));
+        assert_eq!(
+            CsvParser::from_read(&mut written.as_slice(), &options),
+            Ok(records)
+        );
+    }
+
+    #[test]
+    fn test_timestamp_check() {
+        use crate::options::TimestampWindow;
+        use crate::timestamp_log::{ImplausibleTimestamp, TimestampLog};
+
+        // Seconds, then microseconds, read as milliseconds.
+        let data = format!(
+            "{}2,DEPOSIT,0,3,200,1633036861,SUCCESS,Bonus\n\
+             3,DEPOSIT,0,3,300,1633036862000000,SUCCESS,Bonus\n",
+            CSV
+        );
+        let window = TimestampWindow::new(TimestampWindow::Y2000, 1700000000000);
+        let log = TimestampLog::new();
+        let options = ParseOptions {
+            timestamp_check: TimestampCheck::Warn(window, log.clone()),
+            ..ParseOptions::default()
+        };
+        let records = CsvParser::from_read(&mut data.as_bytes(), &options).expect("Should read");
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            log.take(),
+            vec![
+                ImplausibleTimestamp {
+                    id: 2,
+                    ts: 1633036861
+                },
+                ImplausibleTimestamp {
+                    id: 3,
+                    ts: 1633036862000000
+                },
+            ]
+        );
+
+        // Checked once converted from the unit of the file.
+        let options = ParseOptions {
+            timestamp_unit: TimestampUnit::Auto,
+            timestamp_check: TimestampCheck::Reject(window),
+            ..ParseOptions::default()
+        };
+        let results = CsvParser::iter_read(data.as_bytes(), &options)
+            .expect("Should start")
+            .map(|record| record.map(|record| record.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            results,
+            vec![
+                Ok(1),
+                Ok(2),
+                Err(ParseError::ValueOutOfRange {
+                    field: "TIMESTAMP".to_string(),
+                    value: "1633036862000000".to_string(),
+                }),
+            ]
+        );
+
+        let options = ParseOptions {
+            timestamp_check: TimestampCheck::Reject(window.allow_future()),
+            ..ParseOptions::default()
+        };
+        assert!(CsvParser::from_read(&mut CSV.as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_capacity_hint() {
+        let options = ParseOptions {
+            capacity_hint: Some(100),
+            ..ParseOptions::default()
+        };
+        let records = CsvParser::from_read(&mut CSV.as_bytes(), &options).expect("Should read");
+        assert_eq!(records.len(), 1);
+        assert!(records.capacity() >= 100);
+
+        let mut records =
+            CsvParser::iter_read(CSV.as_bytes(), &ParseOptions::default()).expect("Should start");
+        assert_eq!(records.size_hint(), (0, None));
+        assert!(records.by_ref().all(|record| record.is_ok()));
+        assert_eq!(records.size_hint(), (0, Some(0)));
+    }
 }
@@ -0,0 +1,449 @@
+use crate::calendar::{SECONDS_PER_DAY, civil_from_days};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::schema::CSV_FIELDS;
+use std::io::Write;
+use std::path::Path;
+
+const DEFAULT_TABLE: &str = "transactions";
+
+/// Table entries of a `CREATE TABLE` statement that are constraints, not columns.
+const CONSTRAINT_KEYWORDS: [&str; 7] = [
+    "constraint",
+    "primary",
+    "unique",
+    "foreign",
+    "check",
+    "exclude",
+    "like",
+];
+
+/// How a column value is written, depending on the column type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PgType {
+    /// Any type taking the field as written in YPBank files, e.g. `bigint` or `text`.
+    Plain,
+    /// `timestamp`, written as UTC `YYYY-MM-DD HH:MM:SS.mmm`.
+    Timestamp,
+    /// `timestamptz`, written as UTC `YYYY-MM-DD HH:MM:SS.mmm+00`.
+    TimestampTz,
+    /// `date`, written as the UTC `YYYY-MM-DD`.
+    Date,
+}
+
+impl PgType {
+    fn from_sql(column_type: &str) -> Self {
+        let column_type = column_type.to_lowercase();
+        if column_type.starts_with("timestamptz")
+            || column_type.starts_with("timestamp") && column_type.contains("with time zone")
+        {
+            PgType::TimestampTz
+        } else if column_type.starts_with("timestamp") {
+            PgType::Timestamp
+        } else if column_type.starts_with("date") {
+            PgType::Date
+        } else {
+            PgType::Plain
+        }
+    }
+}
+
+/// A column of a [`PgTable`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PgColumn {
+    /// Name as written in the table definition, with its quotes if any.
+    name: String,
+    /// Index of the YPBank field held, or `None` for columns left to their default.
+    field: Option<usize>,
+    column_type: PgType,
+}
+
+impl PgColumn {
+    /// Returns the name without quotes, as PostgreSQL compares it.
+    fn unquoted(&self) -> String {
+        match self
+            .name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+        {
+            Some(name) => name.replace("\"\"", "\""),
+            None => self.name.to_lowercase(),
+        }
+    }
+}
+
+/// Columns of a PostgreSQL table that [`crate::Format::PgCopy`] output is loaded into.
+///
+/// Tables are usually read from their `CREATE TABLE` statement. Columns named like a
+/// YPBank field, ignoring case, hold that field; others can be mapped with
+/// [`PgTable::with_column`] or are left out of the copy, getting their default values.
+/// `timestamp`, `timestamptz` and `date` columns get `TIMESTAMP` as a UTC date and time
+/// instead of milliseconds.
+///
+/// # Examples
+///
+/// ```
+/// use parser::PgTable;
+///
+/// let table = PgTable::from_sql(
+///     "CREATE TABLE ledger (
+///          id bigserial PRIMARY KEY,
+///          tx_id bigint NOT NULL,
+///          booked_at timestamptz,
+///          amount bigint,
+///          description text
+///      );",
+/// )
+/// .unwrap()
+/// .with_column("booked_at", "TIMESTAMP")
+/// .unwrap();
+/// assert_eq!(
+///     table.copy_statement(),
+///     "COPY ledger (tx_id, booked_at, amount, description) FROM STDIN"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgTable {
+    name: String,
+    columns: Vec<PgColumn>,
+}
+
+impl Default for PgTable {
+    /// A `transactions` table with a column per YPBank field, in the YPBank order.
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_TABLE.to_string(),
+            columns: CSV_FIELDS
+                .iter()
+                .enumerate()
+                .map(|(field, name)| PgColumn {
+                    name: name.to_lowercase(),
+                    field: Some(field),
+                    column_type: PgType::Plain,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl PgTable {
+    /// Reads the table from a `CREATE TABLE` statement. `--` comments are ignored.
+    pub fn from_sql(sql: &str) -> Result<Self, ParseError> {
+        let sql: String = sql
+            .lines()
+            .map(|line| line.split_once("--").map_or(line, |(code, _)| code))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let invalid =
+            || ParseError::InvalidFormat(format!("not a CREATE TABLE statement: {}", sql.trim()));
+
+        let (head, body) = sql.split_once('(').ok_or_else(invalid)?;
+        let mut words = head.split_whitespace();
+        let keywords: Vec<String> = words.by_ref().take(2).map(str::to_lowercase).collect();
+        if keywords != ["create", "table"] {
+            return Err(invalid());
+        }
+        let mut name: Vec<&str> = words.collect();
+        if name.len() > 3 && name[..3].join(" ").eq_ignore_ascii_case("if not exists") {
+            name.drain(..3);
+        }
+        let [name] = name[..] else {
+            return Err(invalid());
+        };
+
+        let mut columns = Vec::new();
+        for entry in split_top_level(body).ok_or_else(invalid)? {
+            let entry = entry.trim();
+            let (column, column_type) = split_identifier(entry);
+            if column.is_empty() {
+                return Err(invalid());
+            }
+            if CONSTRAINT_KEYWORDS
+                .iter()
+                .any(|keyword| column.eq_ignore_ascii_case(keyword))
+            {
+                continue;
+            }
+            let mut column = PgColumn {
+                name: column.to_string(),
+                field: None,
+                column_type: PgType::from_sql(column_type.trim()),
+            };
+            let unquoted = column.unquoted();
+            column.field = CSV_FIELDS
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(&unquoted));
+            columns.push(column);
+        }
+
+        let table = Self {
+            name: name.to_string(),
+            columns,
+        };
+        table.check_columns()?;
+        Ok(table)
+    }
+
+    /// Reads the `CREATE TABLE` statement of a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_sql(&std::fs::read_to_string(path)?)
+    }
+
+    /// Makes `column` hold the YPBank `field`, e.g. `booked_at` the `TIMESTAMP`.
+    pub fn with_column(mut self, column: &str, field: &str) -> Result<Self, ParseError> {
+        let field_index = CSV_FIELDS
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(field.trim()))
+            .ok_or_else(|| ParseError::FieldNotFound(field.trim().to_string()))?;
+        let column = self
+            .columns
+            .iter_mut()
+            .find(|c| c.name == column || c.unquoted() == column.to_lowercase())
+            .ok_or_else(|| ParseError::FieldNotFound(column.to_string()))?;
+        column.field = Some(field_index);
+        self.check_columns()?;
+        Ok(self)
+    }
+
+    /// Returns the name of the table.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the columns written, in table order.
+    pub fn columns(&self) -> impl Iterator<Item = &str> {
+        self.copied().map(|column| column.name.as_str())
+    }
+
+    /// Returns the statement loading the output, e.g. with `psql -c "<statement>" < file`.
+    pub fn copy_statement(&self) -> String {
+        format!(
+            "COPY {} ({}) FROM STDIN",
+            self.name,
+            self.columns().collect::<Vec<_>>().join(", ")
+        )
+    }
+
+    fn copied(&self) -> impl Iterator<Item = &PgColumn> {
+        self.columns.iter().filter(|column| column.field.is_some())
+    }
+
+    fn check_columns(&self) -> Result<(), ParseError> {
+        if self.copied().next().is_none() {
+            return Err(ParseError::InvalidFormat(format!(
+                "table {} has no column holding a YPBank field",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Writes one record as a line of `COPY` text format.
+    fn write_record<W: Write>(&self, w: &mut W, record: &YPBankRecord) -> Result<(), ParseError> {
+        let mut line = String::new();
+        for (index, column) in self.copied().enumerate() {
+            if index > 0 {
+                line.push('\t');
+            }
+            let field = column.field.unwrap_or_default();
+            match (field, column.column_type) {
+                (5, PgType::Timestamp) => line.push_str(&format_ts(record.ts, false)),
+                (5, PgType::TimestampTz) => line.push_str(&format_ts(record.ts, true)),
+                (5, PgType::Date) => line.push_str(&format_ts(record.ts, false)[..10]),
+                (0, _) => line.push_str(&record.id.to_string()),
+                (1, _) => line.push_str(record.transaction_type.as_str()),
+                (2, _) => line.push_str(&record.from_user_id.to_string()),
+                (3, _) => line.push_str(&record.to_user_id.to_string()),
+                (4, _) => line.push_str(&record.amount.to_string()),
+                (5, _) => line.push_str(&record.ts.to_string()),
+                (6, _) => line.push_str(record.status.as_str()),
+                _ => push_escaped(&mut line, &record.description),
+            }
+        }
+        line.push('\n');
+        w.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Splits the entries of a table definition body at commas outside parentheses and
+/// quotes, e.g. `numeric(20, 0)`. Returns `None` when the body is not closed.
+fn split_top_level(body: &str) -> Option<Vec<&str>> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut start = 0;
+    for (index, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => {
+                entries.push(&body[start..index]);
+                return Some(entries);
+            }
+            (None, ')') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                entries.push(&body[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits the leading identifier, quoted or not, off a table entry.
+fn split_identifier(entry: &str) -> (&str, &str) {
+    if entry.starts_with('"') {
+        // A doubled quote is part of the name.
+        let mut index = 1;
+        while let Some(offset) = entry[index..].find('"') {
+            index += offset + 1;
+            if !entry[index..].starts_with('"') {
+                return entry.split_at(index);
+            }
+            index += 1;
+        }
+        return (entry, "");
+    }
+    entry.split_at(entry.find(char::is_whitespace).unwrap_or(entry.len()))
+}
+
+/// Renders a timestamp in milliseconds as UTC `YYYY-MM-DD HH:MM:SS.mmm`, followed by
+/// `+00` with `with_zone`.
+fn format_ts(ts: u64, with_zone: bool) -> String {
+    let secs = (ts / 1000) as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECONDS_PER_DAY));
+    let time = secs.rem_euclid(SECONDS_PER_DAY);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}{}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        ts % 1000,
+        if with_zone { "+00" } else { "" }
+    )
+}
+
+/// Escapes a value for `COPY` text format, where backslashes, tabs and line breaks
+/// have a meaning.
+fn push_escaped(line: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '\\' => line.push_str("\\\\"),
+            '\t' => line.push_str("\\t"),
+            '\n' => line.push_str("\\n"),
+            '\r' => line.push_str("\\r"),
+            c => line.push(c),
+        }
+    }
+}
+
+/// Writes records as the data of a PostgreSQL `COPY ... FROM STDIN` in text format.
+///
+/// Lines hold the columns of a [`PgTable`] separated by tabs, without header or `\.`
+/// terminator, so output can be appended to and loaded with `\copy` or
+/// [`PgTable::copy_statement`].
+pub struct PgCopyWriter {}
+
+impl PgCopyWriter {
+    pub fn write_iter<W: Write>(
+        w: &mut W,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        table: Option<&PgTable>,
+    ) -> Result<(), ParseError> {
+        let default;
+        let table = match table {
+            Some(table) => table,
+            None => {
+                default = PgTable::default();
+                &default
+            }
+        };
+        let mut w = std::io::BufWriter::new(w);
+        for record in records {
+            table.write_record(&mut w, &record?)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod pg_copy_tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_from_sql() {
+        let table = PgTable::from_sql(
+            "-- ledger, loaded nightly\n\
+             CREATE TABLE IF NOT EXISTS public.ledger (\n\
+                 \"Amount\" numeric(20, 0) NOT NULL, -- minor units\n\
+                 tx_id bigint,\n\
+                 \"Weird \"\" name\" text DEFAULT 'a,b',\n\
+                 status text CHECK (status IN ('SUCCESS', 'FAILURE', 'PENDING')),\n\
+                 ts timestamp with time zone,\n\
+                 PRIMARY KEY (tx_id)\n\
+             );",
+        )
+        .expect("Should parse")
+        .with_column("ts", "timestamp")
+        .expect("Should map");
+        assert_eq!(table.name(), "public.ledger");
+        assert_eq!(
+            table.copy_statement(),
+            "COPY public.ledger (\"Amount\", tx_id, status, ts) FROM STDIN"
+        );
+        assert_eq!(table.columns[2].unquoted(), "Weird \" name");
+        assert_eq!(table.columns[4].column_type, PgType::TimestampTz);
+
+        for invalid in [
+            "CREATE VIEW v (tx_id)",
+            "CREATE TABLE t (tx_id bigint",
+            "CREATE TABLE t (id bigint)",
+        ] {
+            assert!(PgTable::from_sql(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            PgTable::default().with_column("nope", "TX_ID"),
+            Err(ParseError::FieldNotFound("nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_write_iter_escapes_values() {
+        let mut record = fixtures::records().remove(0);
+        record.ts = 1_633_036_860_123;
+        record.description = "Tab\there\\ new\nline\r".into();
+        let table = PgTable::from_sql(
+            "CREATE TABLE t (description text, at timestamp, day date, amount bigint, tx_type text)",
+        )
+        .and_then(|table| table.with_column("at", "TIMESTAMP"))
+        .and_then(|table| table.with_column("day", "TIMESTAMP"))
+        .expect("Should parse");
+
+        let mut written = Vec::new();
+        PgCopyWriter::write_iter(&mut written, [Ok(record.clone())], Some(&table))
+            .expect("Should write");
+        assert_eq!(
+            String::from_utf8(written).expect("Should be UTF-8"),
+            "Tab\\there\\\\ new\\nline\\r\t2021-09-30 21:21:00.123\t2021-09-30\t100\tDEPOSIT\n"
+        );
+
+        let mut written = Vec::new();
+        PgCopyWriter::write_iter(&mut written, [Ok(record.clone())], None).expect("Should write");
+        assert_eq!(
+            String::from_utf8(written).expect("Should be UTF-8"),
+            format!(
+                "{}\tDEPOSIT\t0\t1\t100\t1633036860123\tSUCCESS\tTab\\there\\\\ new\\nline\\r\n",
+                record.id
+            )
+        );
+    }
+}
@@ -0,0 +1,610 @@
+use crate::common::parse_value_from_str;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::toml_format::YPBankTomlRecordParser;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// User id written in place of redacted non-zero user ids.
+pub const REDACTED_USER_ID: u64 = u64::MAX;
+
+/// A step applied to every record passing through a [`Pipeline`].
+///
+/// A stage returns the (possibly modified) record, `None` to drop it, or an error
+/// to abort processing.
+pub trait Stage: Send + Sync {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError>;
+}
+
+/// Adds or derives data of every record passing through a [`Pipeline`], e.g. from a
+/// lookup table.
+///
+/// Unlike a [`Stage`], an enricher never drops records; an error, e.g. for a record
+/// missing from the table, aborts processing.
+pub trait Enricher: Send + Sync {
+    fn enrich(&self, record: &mut YPBankRecord) -> Result<(), ParseError>;
+}
+
+/// Runs an [`Enricher`] as a stage.
+struct Enrich<E>(E);
+
+impl<E: Enricher> Stage for Enrich<E> {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        let mut record = record;
+        self.0.enrich(&mut record)?;
+        Ok(Some(record))
+    }
+}
+
+/// An ordered list of stages applied to records between reading and writing.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Pipeline, RedactionProfile, Redactor};
+///
+/// let pipeline = Pipeline::new().with_stage(Redactor::new(&[RedactionProfile::Amounts]));
+/// let records = pipeline.run(vec![]).unwrap();
+/// assert!(records.is_empty());
+/// ```
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a stage that runs after all previously added stages.
+    pub fn with_stage<S: Stage + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Appends an enricher that runs after all previously added stages.
+    pub fn with_enricher<E: Enricher + 'static>(self, enricher: E) -> Self {
+        self.with_stage(Enrich(enricher))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs a single record through all stages.
+    pub fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        let mut record = record;
+        for stage in &self.stages {
+            match stage.process(record)? {
+                Some(processed) => record = processed,
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some(record))
+    }
+
+    /// Runs all records through the pipeline, keeping their order.
+    pub fn run(&self, records: Vec<YPBankRecord>) -> Result<Vec<YPBankRecord>, ParseError> {
+        if self.is_empty() {
+            return Ok(records);
+        }
+
+        let mut processed = Vec::with_capacity(records.len());
+        for record in records {
+            if let Some(record) = self.process(record)? {
+                processed.push(record);
+            }
+        }
+
+        Ok(processed)
+    }
+}
+
+/// Runs a whole pipeline as one stage of another, e.g. to add stages in front of it.
+impl Stage for Pipeline {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Pipeline::process(self, record)
+    }
+}
+
+/// Groups of fields hidden by a [`Redactor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactionProfile {
+    /// Replaces non-zero user ids with [`REDACTED_USER_ID`].
+    Pii,
+    /// Sets amounts to zero.
+    Amounts,
+    /// Clears descriptions.
+    Descriptions,
+}
+
+impl RedactionProfile {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RedactionProfile::Pii => "pii",
+            RedactionProfile::Amounts => "amounts",
+            RedactionProfile::Descriptions => "descriptions",
+        }
+    }
+}
+
+impl std::str::FromStr for RedactionProfile {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pii" => Ok(RedactionProfile::Pii),
+            "amounts" => Ok(RedactionProfile::Amounts),
+            "descriptions" => Ok(RedactionProfile::Descriptions),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "redaction profile".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// A stage hiding the fields selected by a set of redaction profiles.
+///
+/// Zero user ids are kept as is, since they carry the deposit/withdrawal semantics
+/// and redacted records must remain readable by the parsers.
+pub struct Redactor {
+    profiles: Vec<RedactionProfile>,
+}
+
+impl Redactor {
+    pub fn new(profiles: &[RedactionProfile]) -> Self {
+        Self {
+            profiles: profiles.to_vec(),
+        }
+    }
+
+    pub fn redact(&self, mut record: YPBankRecord) -> YPBankRecord {
+        for profile in &self.profiles {
+            match profile {
+                RedactionProfile::Pii => {
+                    if record.from_user_id != 0 {
+                        record.from_user_id = REDACTED_USER_ID;
+                    }
+                    if record.to_user_id != 0 {
+                        record.to_user_id = REDACTED_USER_ID;
+                    }
+                }
+                RedactionProfile::Amounts => record.amount = 0,
+                RedactionProfile::Descriptions => record.description = "".into(),
+            }
+        }
+
+        record
+    }
+}
+
+impl Stage for Redactor {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Ok(Some(self.redact(record)))
+    }
+}
+
+/// User ids of a record matched by a [`UserFilter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UserSide {
+    /// Either the sender or the recipient.
+    #[default]
+    Either,
+    From,
+    To,
+}
+
+impl UserSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserSide::Either => "either",
+            UserSide::From => "from",
+            UserSide::To => "to",
+        }
+    }
+}
+
+impl std::str::FromStr for UserSide {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "either" => Ok(UserSide::Either),
+            "from" => Ok(UserSide::From),
+            "to" => Ok(UserSide::To),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "user side".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// A stage keeping only the records touching a set of users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserFilter {
+    ids: HashSet<u64>,
+    side: UserSide,
+}
+
+impl UserFilter {
+    pub fn new(ids: impl IntoIterator<Item = u64>, side: UserSide) -> Self {
+        Self {
+            ids: ids.into_iter().collect(),
+            side,
+        }
+    }
+
+    /// Returns the user ids in increasing order.
+    pub fn ids(&self) -> impl Iterator<Item = u64> {
+        let mut ids = self.ids.iter().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.into_iter()
+    }
+
+    pub fn side(&self) -> UserSide {
+        self.side
+    }
+
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        let from = self.ids.contains(&record.from_user_id);
+        let to = self.ids.contains(&record.to_user_id);
+        match self.side {
+            UserSide::Either => from || to,
+            UserSide::From => from,
+            UserSide::To => to,
+        }
+    }
+}
+
+impl Stage for UserFilter {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Ok(self.matches(&record).then_some(record))
+    }
+}
+
+const TRANSFORMS_TABLE: &str = "[transforms]";
+
+/// A change applied to a record field by a [`Transformer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// Multiplies amounts, e.g. by 100 to convert units to minor units.
+    MultiplyAmount(i64),
+    /// Adds to amounts.
+    AddAmount(i64),
+    /// Multiplies timestamps, e.g. by 1000 to convert seconds to milliseconds.
+    MultiplyTs(u64),
+    /// Shifts timestamps by an offset in milliseconds.
+    ShiftTs(i64),
+    /// Prepends a string to descriptions.
+    PrefixDescription(String),
+    /// Appends a string to descriptions.
+    SuffixDescription(String),
+}
+
+impl Transform {
+    /// Returns the config key of the transform.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Transform::MultiplyAmount(_) => "amount_multiply",
+            Transform::AddAmount(_) => "amount_add",
+            Transform::MultiplyTs(_) => "ts_multiply",
+            Transform::ShiftTs(_) => "ts_add",
+            Transform::PrefixDescription(_) => "description_prefix",
+            Transform::SuffixDescription(_) => "description_suffix",
+        }
+    }
+
+    /// Builds a transform from a config key and its unquoted value.
+    pub fn from_key_value(key: &str, value: &str) -> Result<Self, ParseError> {
+        match key {
+            "amount_multiply" => Ok(Transform::MultiplyAmount(parse_value_from_str(key, value)?)),
+            "amount_add" => Ok(Transform::AddAmount(parse_value_from_str(key, value)?)),
+            "ts_multiply" => Ok(Transform::MultiplyTs(parse_value_from_str(key, value)?)),
+            "ts_add" => Ok(Transform::ShiftTs(parse_value_from_str(key, value)?)),
+            "description_prefix" => Ok(Transform::PrefixDescription(value.to_string())),
+            "description_suffix" => Ok(Transform::SuffixDescription(value.to_string())),
+            _ => Err(ParseError::FieldNotFound(key.to_string())),
+        }
+    }
+
+    pub fn apply(&self, mut record: YPBankRecord) -> Result<YPBankRecord, ParseError> {
+        let overflow =
+            |field: &str| ParseError::Overflow(format!("{} of record {}", field, record.id));
+        match self {
+            Transform::MultiplyAmount(factor) => {
+                record.amount = record
+                    .amount
+                    .checked_mul(*factor)
+                    .ok_or_else(|| overflow("amount"))?
+            }
+            Transform::AddAmount(delta) => {
+                record.amount = record
+                    .amount
+                    .checked_add(*delta)
+                    .ok_or_else(|| overflow("amount"))?
+            }
+            Transform::MultiplyTs(factor) => {
+                record.ts = record
+                    .ts
+                    .checked_mul(*factor)
+                    .ok_or_else(|| overflow("timestamp"))?
+            }
+            Transform::ShiftTs(offset) => {
+                record.ts = record
+                    .ts
+                    .checked_add_signed(*offset)
+                    .ok_or_else(|| overflow("timestamp"))?
+            }
+            Transform::PrefixDescription(prefix) => {
+                record.description = format!("{}{}", prefix, record.description).into()
+            }
+            Transform::SuffixDescription(suffix) => {
+                record.description = format!("{}{}", record.description, suffix).into()
+            }
+        }
+        Ok(record)
+    }
+}
+
+/// A stage applying declarative transforms to every record, in order.
+///
+/// Transforms are usually loaded from a TOML file with a `[transforms]` table, applied
+/// in file order:
+///
+/// ```toml
+/// [transforms]
+/// ts_multiply = 1000           # seconds to milliseconds
+/// amount_multiply = 100
+/// description_prefix = "LEGACY "
+/// ```
+///
+/// Arithmetic that overflows fails with [`ParseError::Overflow`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Pipeline, Transform, Transformer};
+///
+/// let transformer = Transformer::from_toml("[transforms]\nts_multiply = 1000\n").unwrap();
+/// assert_eq!(transformer, Transformer::new(vec![Transform::MultiplyTs(1000)]));
+/// let pipeline = Pipeline::new().with_stage(transformer);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transformer {
+    transforms: Vec<Transform>,
+}
+
+impl Transformer {
+    pub fn new(transforms: Vec<Transform>) -> Self {
+        Self { transforms }
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut transforms = vec![];
+        let mut in_table = false;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                in_table = line == TRANSFORMS_TABLE;
+                if !in_table {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                }
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=').filter(|_| in_table) else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let value = YPBankTomlRecordParser::parse_raw_value(key.trim(), value.trim())?;
+            transforms.push(Transform::from_key_value(key.trim(), &value)?);
+        }
+
+        Ok(Self::new(transforms))
+    }
+
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn transforms(&self) -> &[Transform] {
+        &self.transforms
+    }
+}
+
+impl Stage for Transformer {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        let mut record = record;
+        for transform in &self.transforms {
+            record = transform.apply(record)?;
+        }
+        Ok(Some(record))
+    }
+}
+
+#[cfg(test)]
+mod pipeline_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    struct DropOddIds {}
+
+    impl Stage for DropOddIds {
+        fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+            Ok(record.id.is_multiple_of(2).then_some(record))
+        }
+    }
+
+    fn record(id: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            42,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "Salary".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_run_applies_stages_in_order() {
+        let pipeline = Pipeline::new()
+            .with_stage(DropOddIds {})
+            .with_stage(Redactor::new(&[RedactionProfile::Amounts]));
+
+        let records = pipeline
+            .run(vec![record(1), record(2), record(4)])
+            .expect("Should process");
+
+        assert_eq!(records.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2, 4]);
+        assert!(records.iter().all(|r| r.amount == 0));
+    }
+
+    #[test]
+    fn test_with_enricher() {
+        struct AddId {}
+
+        impl Enricher for AddId {
+            fn enrich(&self, record: &mut YPBankRecord) -> Result<(), ParseError> {
+                record.amount += record.id as i64;
+                Ok(())
+            }
+        }
+
+        let pipeline = Pipeline::new()
+            .with_stage(DropOddIds {})
+            .with_enricher(AddId {});
+
+        let records = pipeline
+            .run(vec![record(1), record(2)])
+            .expect("Should process");
+
+        assert_eq!(
+            records,
+            vec![YPBankRecord {
+                amount: 102,
+                ..record(2)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_redactor_pii_keeps_zero_ids() {
+        let redactor = Redactor::new(&[RedactionProfile::Pii, RedactionProfile::Descriptions]);
+
+        let redacted = redactor.redact(record(1));
+
+        assert_eq!(redacted.from_user_id, 0);
+        assert_eq!(redacted.to_user_id, REDACTED_USER_ID);
+        assert_eq!(redacted.amount, 100);
+        assert_eq!(&*redacted.description, "");
+    }
+
+    #[test]
+    fn test_user_filter() {
+        let transfer = |from, to| {
+            let mut record = record(from * 10 + to);
+            record.transaction_type = TransactionType::Transfer;
+            record.from_user_id = from;
+            record.to_user_id = to;
+            record
+        };
+        let records = vec![
+            transfer(1, 2),
+            transfer(2, 3),
+            transfer(3, 1),
+            transfer(4, 5),
+        ];
+
+        let ids = |side| {
+            Pipeline::new()
+                .with_stage(UserFilter::new([1, 3], side))
+                .run(records.clone())
+                .map(|records| records.iter().map(|r| r.id).collect::<Vec<_>>())
+        };
+        assert_eq!(ids(UserSide::Either), Ok(vec![12, 23, 31]));
+        assert_eq!(ids(UserSide::From), Ok(vec![12, 31]));
+        assert_eq!(ids(UserSide::To), Ok(vec![23, 31]));
+        assert_eq!("TO".parse(), Ok(UserSide::To));
+    }
+
+    #[test]
+    fn test_redaction_profile_from_str() {
+        assert_eq!("PII".parse(), Ok(RedactionProfile::Pii));
+        assert_eq!("amounts".parse(), Ok(RedactionProfile::Amounts));
+        assert_eq!(
+            "names".parse::<RedactionProfile>(),
+            Err(ParseError::InvalidRawValue {
+                field: "redaction profile".to_string(),
+                value: "names".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_transformer_from_toml() {
+        let transformer = Transformer::from_toml(
+            "# migration\n[transforms]\nts_multiply = 1_000\nts_add = -500 # shift\namount_multiply = 100\ndescription_prefix = \"LEGACY \"\n",
+        )
+        .expect("Should parse");
+
+        let mut input = record(1);
+        input.ts = 1633036860;
+        let transformed = transformer
+            .process(input)
+            .expect("Should transform")
+            .expect("Should keep the record");
+
+        assert_eq!(transformed.ts, 1633036859500);
+        assert_eq!(transformed.amount, 10000);
+        assert_eq!(&*transformed.description, "LEGACY Salary");
+    }
+
+    #[test]
+    fn test_transformer_errors() {
+        assert_eq!(
+            Transformer::from_toml("[transforms]\nid_add = 1\n"),
+            Err(ParseError::FieldNotFound("id_add".to_string()))
+        );
+        assert_eq!(
+            Transformer::new(vec![Transform::MultiplyAmount(i64::MAX)]).process(record(7)),
+            Err(ParseError::Overflow("amount of record 7".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_transformer_extreme_amounts() {
+        let min = YPBankRecord {
+            amount: i64::MIN,
+            ..record(7)
+        };
+        for transform in [Transform::MultiplyAmount(-1), Transform::AddAmount(-1)] {
+            assert_eq!(
+                transform.apply(min.clone()),
+                Err(ParseError::Overflow("amount of record 7".to_string()))
+            );
+        }
+        assert_eq!(
+            Transform::AddAmount(i64::MAX)
+                .apply(min)
+                .map(|record| record.amount),
+            Ok(-1)
+        );
+        assert_eq!(
+            Transformer::from_toml("[transforms]\namount_add = 9223372036854775808\n"),
+            Err(ParseError::ValueOutOfRange {
+                field: "amount_add".to_string(),
+                value: "9223372036854775808".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use crate::CommonParser;
+use crate::bin_format::YPBankBinRecordParser;
+use crate::block::{self, BlockHeader, BlockReader, Codecs};
+use crate::bloom::IdBloom;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::convert::Infallible;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+/// A numeric record field that can be used in a [`Predicate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    FromUserId,
+    ToUserId,
+    Amount,
+    Ts,
+}
+
+impl Field {
+    /// Returns the value of the field, widened so that all fields compare alike.
+    pub fn of(&self, record: &YPBankRecord) -> i128 {
+        match self {
+            Field::Id => record.id as i128,
+            Field::FromUserId => record.from_user_id as i128,
+            Field::ToUserId => record.to_user_id as i128,
+            Field::Amount => record.amount as i128,
+            Field::Ts => record.ts as i128,
+        }
+    }
+
+    pub fn equals(self, value: impl Into<i128>) -> Predicate {
+        Predicate::new(self, Comparison::Eq, value.into())
+    }
+
+    pub fn gt(self, value: impl Into<i128>) -> Predicate {
+        Predicate::new(self, Comparison::Gt, value.into())
+    }
+
+    pub fn ge(self, value: impl Into<i128>) -> Predicate {
+        Predicate::new(self, Comparison::Ge, value.into())
+    }
+
+    pub fn lt(self, value: impl Into<i128>) -> Predicate {
+        Predicate::new(self, Comparison::Lt, value.into())
+    }
+
+    pub fn le(self, value: impl Into<i128>) -> Predicate {
+        Predicate::new(self, Comparison::Le, value.into())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A comparison of a record field against a constant, built with the [`Field`] methods.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Predicate {
+    field: Field,
+    comparison: Comparison,
+    value: i128,
+}
+
+impl Predicate {
+    fn new(field: Field, comparison: Comparison, value: i128) -> Self {
+        Self {
+            field,
+            comparison,
+            value,
+        }
+    }
+
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        let field = self.field.of(record);
+        match self.comparison {
+            Comparison::Eq => field == self.value,
+            Comparison::Gt => field > self.value,
+            Comparison::Ge => field >= self.value,
+            Comparison::Lt => field < self.value,
+            Comparison::Le => field <= self.value,
+        }
+    }
+
+    /// Returns whether records of a block with `header` may match. Only timestamps and
+    /// amounts have ranges in block headers; predicates on other fields always may match.
+    pub fn may_match_block(&self, header: &BlockHeader) -> bool {
+        let (min, max) = match self.field {
+            Field::Ts => (header.min_ts as i128, header.max_ts as i128),
+            Field::Amount => (header.min_amount as i128, header.max_amount as i128),
+            _ => return true,
+        };
+        match self.comparison {
+            Comparison::Eq => min <= self.value && self.value <= max,
+            Comparison::Gt => max > self.value,
+            Comparison::Ge => max >= self.value,
+            Comparison::Lt => min < self.value,
+            Comparison::Le => min <= self.value,
+        }
+    }
+}
+
+/// Selects records of a file matching all given predicates.
+///
+/// Predicates only look at fixed-size fields, so for binary files they are evaluated
+/// before the description is read and descriptions of non-matching records are skipped.
+/// Other formats are scanned record by record.
+///
+/// Block-compressed archives written by [`crate::BlockWriter`] are opened as
+/// [`Format::Bin`] and recognized by their magic. Blocks whose timestamp and amount
+/// ranges rule out a match are skipped without being decompressed.
+///
+/// When looking for an id in a file with an up-to-date [`IdBloom`] sidecar, a file the
+/// filter rules out is not read at all.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Field, Format, QueryEngine};
+/// # parser::fixtures::enter().unwrap();
+///
+/// let records = QueryEngine::open("records.bin", Format::Bin)
+///     .unwrap()
+///     .filter(Field::Amount.gt(1000))
+///     .between_ts(1633036800000, 1633123200000)
+///     .collect()
+///     .unwrap();
+/// ```
+pub struct QueryEngine {
+    path: PathBuf,
+    format: Format,
+    predicates: Vec<Predicate>,
+    codecs: Codecs,
+}
+
+impl QueryEngine {
+    pub fn open(path: impl AsRef<Path>, format: Format) -> Result<Self, ParseError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_file() {
+            return Err(ParseError::IOError(format!(
+                "{} is not a file",
+                path.display()
+            )));
+        }
+
+        Ok(Self {
+            path,
+            format,
+            predicates: vec![],
+            codecs: Codecs::default(),
+        })
+    }
+
+    /// Keeps only records matching `predicate`, in addition to previous filters.
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// Keeps only records with `from <= ts <= to`.
+    pub fn between_ts(self, from: u64, to: u64) -> Self {
+        self.filter(Field::Ts.ge(from)).filter(Field::Ts.le(to))
+    }
+
+    /// Reads block-compressed archives with `codecs` instead of the built-in ones.
+    pub fn with_codecs(mut self, codecs: Codecs) -> Self {
+        self.codecs = codecs;
+        self
+    }
+
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        self.predicates
+            .iter()
+            .all(|predicate| predicate.matches(record))
+    }
+
+    /// Returns whether the bloom-filter sidecar of the file rules out an id predicate.
+    fn ruled_out_by_bloom(&self) -> Result<bool, ParseError> {
+        let ids: Vec<i128> = self
+            .predicates
+            .iter()
+            .filter(|predicate| {
+                predicate.field == Field::Id && predicate.comparison == Comparison::Eq
+            })
+            .map(|predicate| predicate.value)
+            .collect();
+        if ids.is_empty() {
+            return Ok(false);
+        }
+        let Some(bloom) = IdBloom::load_sidecar(&self.path)? else {
+            return Ok(false);
+        };
+        Ok(ids
+            .into_iter()
+            .any(|id| u64::try_from(id).map_or(true, |id| !bloom.may_contain(id))))
+    }
+
+    /// Returns whether the file starts with the magic of block-compressed archives,
+    /// leaving it at its start.
+    fn is_archive(file: &mut File) -> Result<bool, ParseError> {
+        let mut magic = Vec::new();
+        file.take(block::MAGIC.len() as u64)
+            .read_to_end(&mut magic)?;
+        file.rewind()?;
+        Ok(magic == block::MAGIC)
+    }
+
+    /// Passes matching records of a block-compressed archive to `f`, skipping blocks
+    /// whose header rules out a match, until `f` breaks.
+    fn scan_archive<B>(
+        &self,
+        file: File,
+        mut f: impl FnMut(YPBankRecord) -> ControlFlow<B>,
+    ) -> Result<ControlFlow<B>, ParseError> {
+        let mut reader = BlockReader::new(file, self.codecs.clone())?;
+        while let Some(header) = reader.next_block()? {
+            if !self
+                .predicates
+                .iter()
+                .all(|predicate| predicate.may_match_block(&header))
+            {
+                reader.skip_block(&header)?;
+                continue;
+            }
+
+            for record in reader.read_block(&header)? {
+                if self.matches(&record)
+                    && let ControlFlow::Break(value) = f(record)
+                {
+                    return Ok(ControlFlow::Break(value));
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    }
+
+    /// Runs the query, returning matching records in file order.
+    pub fn collect(&self) -> Result<Vec<YPBankRecord>, ParseError> {
+        if self.ruled_out_by_bloom()? {
+            return Ok(vec![]);
+        }
+        let mut file = File::open(&self.path)?;
+
+        if self.format == Format::Bin && Self::is_archive(&mut file)? {
+            let mut records = vec![];
+            let ControlFlow::Continue(()) = self.scan_archive(file, |record| {
+                records.push(record);
+                ControlFlow::<Infallible>::Continue(())
+            })?;
+            return Ok(records);
+        }
+
+        if self.format == Format::Bin {
+            let mut reader = std::io::BufReader::new(file);
+            let mut record_parser = YPBankBinRecordParser::default();
+            let mut records = vec![];
+            while let Some(record) =
+                record_parser.read_matching(&mut reader, |record| self.matches(record))?
+            {
+                records.push(record);
+            }
+            return Ok(records);
+        }
+
+        let mut records = vec![];
+        for record in CommonParser::new(self.format).iter_read(file)? {
+            let record = record?;
+            if self.matches(&record) {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Returns the first matching record, reading the file only up to it.
+    pub fn first(&self) -> Result<Option<YPBankRecord>, ParseError> {
+        if self.ruled_out_by_bloom()? {
+            return Ok(None);
+        }
+        let mut file = File::open(&self.path)?;
+
+        if self.format == Format::Bin && Self::is_archive(&mut file)? {
+            return Ok(self.scan_archive(file, ControlFlow::Break)?.break_value());
+        }
+
+        if self.format == Format::Bin {
+            let mut reader = std::io::BufReader::new(file);
+            return YPBankBinRecordParser::default()
+                .read_matching(&mut reader, |record| self.matches(record));
+        }
+
+        let found = CommonParser::new(self.format).from_read_with(&mut file, |record| {
+            if self.matches(&record) {
+                ControlFlow::Break(record)
+            } else {
+                ControlFlow::Continue(())
+            }
+        })?;
+        Ok(found.break_value())
+    }
+}
+
+#[cfg(test)]
+mod query_engine_tests {
+    use super::*;
+    use crate::block::{BlockWriter, Stored};
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn records() -> Vec<YPBankRecord> {
+        (0..10)
+            .map(|index| {
+                YPBankRecord::new(
+                    index,
+                    TransactionType::Deposit,
+                    0,
+                    1,
+                    index as i64 * 500,
+                    1000 + index,
+                    TransactionStatus::Success,
+                    format!("Record {}", index),
+                )
+            })
+            .collect()
+    }
+
+    fn write_file(name: &str, format: Format) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("ypbank-query-{}-{}", std::process::id(), name));
+        let mut file = std::fs::File::create(&path).expect("Should create file");
+        CommonParser::new(format)
+            .write_to(&mut file, &records())
+            .expect("Should write records");
+        path
+    }
+
+    #[test]
+    fn test_collect_binary_and_csv() {
+        for (name, format) in [("records.bin", Format::Bin), ("records.csv", Format::Csv)] {
+            let path = write_file(name, format);
+
+            let found = QueryEngine::open(&path, format)
+                .expect("Should open")
+                .filter(Field::Amount.gt(1000))
+                .between_ts(1001, 1005)
+                .collect()
+                .expect("Should query");
+            std::fs::remove_file(&path).expect("Should remove file");
+
+            assert_eq!(
+                found.iter().map(|record| record.id).collect::<Vec<_>>(),
+                vec![3, 4, 5]
+            );
+            assert_eq!(&*found[0].description, "Record 3");
+        }
+    }
+
+    #[test]
+    fn test_first() {
+        for (name, format) in [("first.bin", Format::Bin), ("first.txt", Format::Txt)] {
+            let path = write_file(name, format);
+
+            let first = |id: u64| {
+                QueryEngine::open(&path, format)
+                    .expect("Should open")
+                    .filter(Field::Id.equals(id))
+                    .first()
+            };
+            let (found, missing) = (first(7), first(70));
+            std::fs::remove_file(&path).expect("Should remove file");
+
+            assert_eq!(
+                found.expect("Should query").map(|record| record.amount),
+                Some(3500)
+            );
+            assert_eq!(missing, Ok(None));
+        }
+    }
+
+    #[test]
+    fn test_archive_skips_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("ypbank-query-{}-archive", std::process::id()));
+        let mut writer = BlockWriter::new(Vec::new(), Stored, 5).expect("Should start archive");
+        for record in records() {
+            writer.write(&record).expect("Should write record");
+        }
+        let mut archive = writer.finish().expect("Should finish archive");
+        // Breaks the payload of the first block, which holds timestamps 1000 to 1004.
+        let payload = 4 + 45;
+        archive[payload..payload + 4].copy_from_slice(b"XXXX");
+        std::fs::write(&path, &archive).expect("Should write archive");
+
+        let query = |from, to| {
+            QueryEngine::open(&path, Format::Bin)
+                .expect("Should open")
+                .between_ts(from, to)
+                .collect()
+        };
+        let (found, broken) = (query(1006, 1007), query(1003, 1007));
+        let first = QueryEngine::open(&path, Format::Bin)
+            .expect("Should open")
+            .filter(Field::Amount.ge(4000))
+            .first();
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!(
+            found.map(|records| records.iter().map(|record| record.id).collect::<Vec<_>>()),
+            Ok(vec![6, 7])
+        );
+        assert!(broken.is_err());
+        assert_eq!(
+            first.map(|record| record.map(|record| record.id)),
+            Ok(Some(8))
+        );
+    }
+
+    #[test]
+    fn test_bloom_sidecar_skips_file() {
+        let path = write_file("bloom.bin", Format::Bin);
+        IdBloom::from_path(&path, Format::Bin, 0.001)
+            .and_then(|bloom| bloom.write_sidecar(&path))
+            .expect("Should write sidecar");
+        // Reads the binary file as CSV, so that only skipped reads succeed.
+        let first = |id: u64| {
+            QueryEngine::open(&path, Format::Csv)
+                .expect("Should open")
+                .filter(Field::Id.equals(id))
+                .first()
+        };
+        let (present, absent) = (first(7), first(1000000000000007));
+        std::fs::remove_file(IdBloom::sidecar_path(&path)).expect("Should remove sidecar");
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert!(present.is_err());
+        assert_eq!(absent, Ok(None));
+    }
+
+    #[test]
+    fn test_open_missing_file() {
+        let result = QueryEngine::open("/nonexistent/records.bin", Format::Bin);
+        assert!(matches!(result, Err(ParseError::IOError(_))));
+    }
+}
@@ -1,10 +1,19 @@
-use crate::common::{TransactionStatus, TransactionType};
+use crate::common::{
+    TransactionStatus, TransactionType, validate_from_user_id, validate_to_user_id,
+};
+use crate::error::ParseError;
+use std::collections::BTreeMap;
+use std::sync::Arc;
 
 /// Represents a bank transaction record.
 ///
 /// This struct contains all the information about a single bank transaction,
 /// including transaction ID, type, user IDs, amount, timestamp, status, and description.
-#[derive(Debug, PartialEq, Eq)]
+/// Descriptions are shared strings, so records parsed with
+/// [`ParseOptions::intern_descriptions`](crate::ParseOptions::intern_descriptions) can
+/// point to a single copy of a repeated description. They were `String`s before 0.2;
+/// assign new ones with `.into()` and compare them as `&*record.description`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct YPBankRecord {
     pub id: u64,
     pub transaction_type: TransactionType,
@@ -13,7 +22,7 @@ pub struct YPBankRecord {
     pub amount: i64,
     pub ts: u64,
     pub status: TransactionStatus,
-    pub description: String,
+    pub description: Arc<str>,
 }
 
 impl YPBankRecord {
@@ -26,7 +35,7 @@ impl YPBankRecord {
         amount: i64,
         ts: u64,
         status: TransactionStatus,
-        description: String,
+        description: impl Into<Arc<str>>,
     ) -> Self {
         Self {
             id,
@@ -36,7 +45,52 @@ impl YPBankRecord {
             amount,
             ts,
             status,
-            description,
+            description: description.into(),
+        }
+    }
+
+    /// Checks the invariants between fields: deposits come from user 0 and withdrawals
+    /// go to user 0, while transfers are between two users. Readers reject records
+    /// breaking them, as do writes with
+    /// [`ParseOptions::validate_writes`](crate::ParseOptions::validate_writes) set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{ParseError, TransactionStatus, TransactionType, YPBankRecord};
+    ///
+    /// let deposit = |from| {
+    ///     YPBankRecord::new(1, TransactionType::Deposit, from, 2, 100, 3, TransactionStatus::Success, "")
+    /// };
+    /// assert_eq!(deposit(0).validate(), Ok(()));
+    /// assert!(matches!(deposit(7).validate(), Err(ParseError::InvalidUserId(..))));
+    /// ```
+    pub fn validate(&self) -> Result<(), ParseError> {
+        validate_from_user_id(self.from_user_id, self.transaction_type)?;
+        validate_to_user_id(self.to_user_id, self.transaction_type)?;
+        Ok(())
+    }
+}
+
+/// A record together with data it has no field for, such as the audit columns of
+/// partner files, read with [`crate::CommonParser::iter_read_extended`] and written with
+/// [`crate::CommonParser::write_ext_to`].
+///
+/// Extras are CSV columns captured by a [`crate::SchemaMap`], TXT keys other than the
+/// record fields and binary extension entries, see
+/// [`ParseOptions::preserve_extras`](crate::ParseOptions::preserve_extras).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YPBankRecordExt {
+    pub core: YPBankRecord,
+    /// Extra values by name.
+    pub extras: BTreeMap<String, String>,
+}
+
+impl From<YPBankRecord> for YPBankRecordExt {
+    fn from(core: YPBankRecord) -> Self {
+        Self {
+            core,
+            extras: BTreeMap::new(),
         }
     }
 }
@@ -0,0 +1,147 @@
+use crate::common::{TransactionStatus, TransactionType};
+use crate::record::YPBankRecord;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+/// Selects the fields that identify a record.
+///
+/// Two records are the same under a key when their keys are equal, see [`RecordEq`].
+pub trait RecordKey {
+    type Key: Eq + Hash;
+
+    fn key(&self, record: &YPBankRecord) -> Self::Key;
+}
+
+/// Decides whether two records are the same, e.g. when comparing or merging files.
+///
+/// Implemented for every [`RecordKey`] by comparing the keys of both records.
+pub trait RecordEq {
+    fn same(&self, a: &YPBankRecord, b: &YPBankRecord) -> bool;
+}
+
+impl<K: RecordKey> RecordEq for K {
+    fn same(&self, a: &YPBankRecord, b: &YPBankRecord) -> bool {
+        self.key(a) == self.key(b)
+    }
+}
+
+/// Records are the same when their transaction ids are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ById;
+
+impl RecordKey for ById {
+    type Key = u64;
+
+    fn key(&self, record: &YPBankRecord) -> u64 {
+        record.id
+    }
+}
+
+/// Records are the same when all fields but the description are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Semantic;
+
+impl RecordKey for Semantic {
+    type Key = (u64, TransactionType, u64, u64, i64, u64, TransactionStatus);
+
+    fn key(&self, record: &YPBankRecord) -> Self::Key {
+        (
+            record.id,
+            record.transaction_type,
+            record.from_user_id,
+            record.to_user_id,
+            record.amount,
+            record.ts,
+            record.status,
+        )
+    }
+}
+
+/// Records are the same when all fields are, as with `==`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Exact;
+
+impl RecordKey for Exact {
+    type Key = YPBankRecord;
+
+    fn key(&self, record: &YPBankRecord) -> YPBankRecord {
+        record.clone()
+    }
+}
+
+/// Removes records that are the same under `key` as an earlier record.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ById, Semantic, TransactionStatus, TransactionType, YPBankRecord, dedup};
+///
+/// let record = |id, description| {
+///     YPBankRecord::new(id, TransactionType::Deposit, 0, 2, 100, 10, TransactionStatus::Success, description)
+/// };
+///
+/// let records = vec![record(1, "Salary"), record(1, "Salary "), record(2, "Salary")];
+/// assert_eq!(dedup(records.clone(), &Semantic), vec![record(1, "Salary"), record(2, "Salary")]);
+/// assert_eq!(dedup(records, &ById).len(), 2);
+/// ```
+pub fn dedup<K: RecordKey>(mut records: Vec<YPBankRecord>, key: &K) -> Vec<YPBankRecord> {
+    let mut seen = HashSet::new();
+    records.retain(|record| seen.insert(key.key(record)));
+    records
+}
+
+#[cfg(test)]
+mod record_eq_tests {
+    use super::*;
+
+    fn record(id: u64, amount: i64, description: &str) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Transfer,
+            1,
+            2,
+            amount,
+            1633036860000,
+            TransactionStatus::Success,
+            description,
+        )
+    }
+
+    #[test]
+    fn test_presets() {
+        let a = record(1, 100, "Rent");
+        let trailing_space = record(1, 100, "Rent ");
+        let other_amount = record(1, 200, "Rent");
+
+        assert!(ById.same(&a, &other_amount));
+        assert!(!ById.same(&a, &record(2, 100, "Rent")));
+        assert!(Semantic.same(&a, &trailing_space));
+        assert!(!Semantic.same(&a, &other_amount));
+        assert!(Exact.same(&a, &a.clone()));
+        assert!(!Exact.same(&a, &trailing_space));
+    }
+
+    #[test]
+    fn test_dedup_keeps_first() {
+        let records = vec![
+            record(1, 100, "Rent"),
+            record(2, 100, "Rent"),
+            record(1, 200, "Rent"),
+            record(1, 100, "Rent "),
+        ];
+
+        assert_eq!(dedup(records.clone(), &Exact), records);
+        assert_eq!(
+            dedup(records.clone(), &Semantic),
+            vec![
+                record(1, 100, "Rent"),
+                record(2, 100, "Rent"),
+                record(1, 200, "Rent")
+            ]
+        );
+        assert_eq!(
+            dedup(records, &ById),
+            vec![record(1, 100, "Rent"), record(2, 100, "Rent")]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Which records a retention policy keeps: those with a timestamp at or after a cutoff.
+///
+/// # Examples
+///
+/// ```
+/// use parser::Retention;
+///
+/// // 2021-10-31T00:00:00Z, keeping 30 days of records.
+/// let retention = Retention::window(30, 1635638400000);
+/// assert_eq!(retention.cutoff(), 1633046400000);
+/// assert!(retention.expired(1633046399999));
+/// assert!(!retention.expired(1633046400000));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    cutoff: u64,
+}
+
+impl Retention {
+    /// Drops records with a timestamp before `cutoff`, in milliseconds.
+    pub fn before(cutoff: u64) -> Self {
+        Self { cutoff }
+    }
+
+    /// Keeps the records of the `days` days up to `now`, in milliseconds, e.g. the
+    /// retention period a regulation allows.
+    pub fn window(days: u64, now: u64) -> Self {
+        Self::before(now.saturating_sub(days.saturating_mul(DAY_MS)))
+    }
+
+    pub fn cutoff(&self) -> u64 {
+        self.cutoff
+    }
+
+    pub fn expired(&self, ts: u64) -> bool {
+        ts < self.cutoff
+    }
+}
+
+/// Audit log of records removed by [`prune_file`] and [`crate::RecordStore::prune`].
+///
+/// Every removed record gets a `<tx_id> <timestamp> <cutoff> <source>` line, where the
+/// source is the path of the file or store it was removed from. Lines are only written
+/// once the records are gone, so the log never lists records that are still there.
+pub struct PruneLog<W: Write> {
+    w: W,
+    removed: u64,
+}
+
+impl<W: Write> PruneLog<W> {
+    pub fn new(w: W) -> Self {
+        Self { w, removed: 0 }
+    }
+
+    /// Returns the number of records logged.
+    pub fn removed(&self) -> u64 {
+        self.removed
+    }
+
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    /// Logs records removed from `source` and flushes the log.
+    pub(crate) fn log(
+        &mut self,
+        source: &Path,
+        retention: &Retention,
+        records: &[(u64, u64)],
+    ) -> Result<(), ParseError> {
+        for (id, ts) in records {
+            writeln!(
+                self.w,
+                "{} {} {} {}",
+                id,
+                ts,
+                retention.cutoff,
+                source.display()
+            )?;
+            self.removed += 1;
+        }
+        self.w.flush()?;
+        Ok(())
+    }
+}
+
+/// Removes the records expired under `retention` from the file at `path`, logging them
+/// to `log`, and returns how many were removed.
+///
+/// The file is read once to find expired records and left untouched when there are
+/// none; otherwise the remaining records are written to a temporary file that replaces
+/// it atomically, see [`CommonParser::write_iter_to_path_atomic`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Format, PruneLog, Retention, prune_file};
+/// # parser::fixtures::enter().unwrap();
+///
+/// // Records before 2021-10-01T00:05:00Z.
+/// let retention = Retention::before(1633046700000);
+/// let mut log = PruneLog::new(Vec::new());
+/// assert_eq!(prune_file("records.csv", Format::Csv, &retention, &mut log).unwrap(), 4);
+/// assert_eq!(prune_file("records.csv", Format::Csv, &retention, &mut log).unwrap(), 0);
+/// ```
+pub fn prune_file<W: Write>(
+    path: impl AsRef<Path>,
+    format: Format,
+    retention: &Retention,
+    log: &mut PruneLog<W>,
+) -> Result<u64, ParseError> {
+    let path = path.as_ref();
+    let parser = CommonParser::new(format);
+    let mut expired = vec![];
+    for record in parser.iter_read(File::open(path)?)? {
+        let record = record?;
+        if retention.expired(record.ts) {
+            expired.push((record.id, record.ts));
+        }
+    }
+    if expired.is_empty() {
+        return Ok(0);
+    }
+
+    let kept = parser
+        .iter_read(File::open(path)?)?
+        .filter(|record: &Result<YPBankRecord, ParseError>| {
+            !matches!(record, Ok(record) if retention.expired(record.ts))
+        });
+    parser.write_iter_to_path_atomic(path, kept, None)?;
+    log.log(path, retention, &expired)?;
+    Ok(expired.len() as u64)
+}
+
+#[cfg(test)]
+mod retention_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(id: u64, ts: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            7,
+            100,
+            ts,
+            TransactionStatus::Success,
+            "Salary",
+        )
+    }
+
+    #[test]
+    fn test_prune_file() {
+        let path = std::env::temp_dir().join(format!("prune_{}.csv", std::process::id()));
+        let parser = CommonParser::new(Format::Csv);
+        parser
+            .write_to_path_atomic(
+                &path,
+                &vec![record(1, 10), record(2, 30), record(3, 20)],
+                None,
+            )
+            .expect("Should write records");
+
+        let mut log = PruneLog::new(Vec::new());
+        let removed = prune_file(&path, Format::Csv, &Retention::before(25), &mut log);
+        let unchanged = prune_file(&path, Format::Csv, &Retention::before(25), &mut log);
+        let records = parser.from_read(&mut File::open(&path).expect("Should open file"));
+        std::fs::remove_file(&path).expect("Should remove file");
+
+        assert_eq!((removed, unchanged), (Ok(2), Ok(0)));
+        assert_eq!(records, Ok(vec![record(2, 30)]));
+        assert_eq!(
+            String::from_utf8(log.into_inner()).expect("Should be UTF-8"),
+            format!("1 10 25 {0}\n3 20 25 {0}\n", path.display())
+        );
+    }
+}
@@ -0,0 +1,475 @@
+use crate::common::{TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::pipeline::Stage;
+use crate::query::Field;
+use crate::record::YPBankRecord;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Number(i128),
+    Text(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 11] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")"];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped @ ('"' | '\\'))) => text.push(escaped),
+                        _ => return Err(format!("invalid escape at {}", start)),
+                    },
+                    Some((_, c)) => text.push(c),
+                    None => return Err(format!("unterminated string at {}", start)),
+                }
+            }
+            tokens.push(Token::Text(text));
+        } else if c.is_ascii_digit() || c == '-' {
+            chars.next();
+            let mut end = start + c.len_utf8();
+            while let Some(&(position, c)) = chars.peek()
+                && c.is_ascii_digit()
+            {
+                end = position + 1;
+                chars.next();
+            }
+            let number = s[start..end]
+                .parse()
+                .map_err(|_| format!("invalid number at {}", start))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(position, c)) = chars.peek()
+                && (c.is_alphanumeric() || c == '_')
+            {
+                end = position + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Word(s[start..end].to_string()));
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| s[start..].starts_with(symbol))
+                .ok_or_else(|| format!("unexpected {:?} at {}", c, start))?;
+            for _ in 0..symbol.len() {
+                chars.next();
+            }
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl Op {
+    fn holds<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Contains => false,
+        }
+    }
+}
+
+/// A parsed expression. Chains of `&&` and `||` are kept flat, so that long ones do not nest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    And(Vec<Node>),
+    Or(Vec<Node>),
+    Not(Box<Node>),
+    Number(Field, Op, i128),
+    Type(Op, TransactionType),
+    Status(Op, TransactionStatus),
+    Description(Op, String),
+}
+
+impl Node {
+    fn matches(&self, record: &YPBankRecord) -> bool {
+        match self {
+            Node::And(nodes) => nodes.iter().all(|node| node.matches(record)),
+            Node::Or(nodes) => nodes.iter().any(|node| node.matches(record)),
+            Node::Not(node) => !node.matches(record),
+            Node::Number(field, op, value) => op.holds(field.of(record), *value),
+            // Only `==` and `!=` are parsed for names.
+            Node::Type(op, value) => (*op == Op::Eq) == (record.transaction_type == *value),
+            Node::Status(op, value) => (*op == Op::Eq) == (record.status == *value),
+            Node::Description(Op::Contains, value) => record.description.contains(value.as_str()),
+            Node::Description(op, value) => op.holds(&*record.description, value.as_str()),
+        }
+    }
+}
+
+/// How deep `!` and parentheses may nest before parsing gives up.
+const MAX_DEPTH: usize = 64;
+
+/// Recursive-descent parser of the tokens of an expression.
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("unexpected end")?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![self.and()?];
+        while self.eat("||") {
+            nodes.push(self.and()?);
+        }
+        Ok(Self::chain(nodes, Node::Or))
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![self.unary()?];
+        while self.eat("&&") {
+            nodes.push(self.unary()?);
+        }
+        Ok(Self::chain(nodes, Node::And))
+    }
+
+    fn chain(mut nodes: Vec<Node>, node: fn(Vec<Node>) -> Node) -> Node {
+        if nodes.len() == 1 {
+            return nodes.pop().expect("one node");
+        }
+        node(nodes)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat("!") {
+            return self.nested(|parser| Ok(Node::Not(Box::new(parser.unary()?))));
+        }
+        if self.eat("(") {
+            return self.nested(|parser| {
+                let node = parser.or()?;
+                if !parser.eat(")") {
+                    return Err("missing )".to_string());
+                }
+                Ok(node)
+            });
+        }
+        self.comparison()
+    }
+
+    /// Runs `parse` one level deeper, failing past [`MAX_DEPTH`] instead of overflowing the stack.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<Node, String>,
+    ) -> Result<Node, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested deeper than {} levels", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let Token::Word(field) = self.next()? else {
+            return Err("expected a field".to_string());
+        };
+        let op = match self.next()? {
+            Token::Symbol("==") => Op::Eq,
+            Token::Symbol("!=") => Op::Ne,
+            Token::Symbol("<") => Op::Lt,
+            Token::Symbol("<=") => Op::Le,
+            Token::Symbol(">") => Op::Gt,
+            Token::Symbol(">=") => Op::Ge,
+            Token::Word(word) if word.eq_ignore_ascii_case("contains") => Op::Contains,
+            _ => return Err(format!("expected a comparison after {}", field)),
+        };
+        let value = self.next()?;
+
+        let numeric = match field.to_lowercase().as_str() {
+            "id" | "tx_id" => Field::Id,
+            "from" | "from_user_id" => Field::FromUserId,
+            "to" | "to_user_id" => Field::ToUserId,
+            "amount" => Field::Amount,
+            "ts" | "timestamp" => Field::Ts,
+            "type" | "tx_type" | "status" => {
+                let (Token::Word(value) | Token::Text(value)) = value else {
+                    return Err(format!("expected a name after {}", field));
+                };
+                if !matches!(op, Op::Eq | Op::Ne) {
+                    return Err(format!("{} is only compared with == and !=", field));
+                }
+                let name = |err: ParseError| err.to_string();
+                return if field.to_lowercase().ends_with("type") {
+                    Ok(Node::Type(
+                        op,
+                        TransactionType::from_str(&value).map_err(name)?,
+                    ))
+                } else {
+                    Ok(Node::Status(
+                        op,
+                        TransactionStatus::from_str(&value).map_err(name)?,
+                    ))
+                };
+            }
+            "description" => {
+                let Token::Text(value) = value else {
+                    return Err("expected a quoted text after description".to_string());
+                };
+                if !matches!(op, Op::Eq | Op::Ne | Op::Contains) {
+                    return Err("description is only compared with ==, != and contains".to_string());
+                }
+                return Ok(Node::Description(op, value));
+            }
+            _ => return Err(format!("unknown field {}", field)),
+        };
+        let Token::Number(value) = value else {
+            return Err(format!("expected a number after {}", field));
+        };
+        if op == Op::Contains {
+            return Err(format!("{} is not compared with contains", field));
+        }
+        Ok(Node::Number(numeric, op, value))
+    }
+}
+
+/// A condition on records written as an expression, parsed at run time.
+///
+/// Comparisons of a field with a value are combined with `&&`, `||`, `!` and
+/// parentheses, `&&` binding tighter than `||`:
+///
+/// - `id`, `from_user_id`, `to_user_id`, `amount` and `timestamp` (also `tx_id`,
+///   `from`, `to` and `ts`) compare with integers using `==`, `!=`, `<`, `<=`, `>`, `>=`
+/// - `type` (also `tx_type`) and `status` compare with names using `==` and `!=`
+/// - `description` compares with quoted texts using `==`, `!=` and `contains`
+///
+/// Field names, type and status names are case-insensitive. Expressions are
+/// [`Stage`]s keeping the records they match, refuse records when set in
+/// [`crate::ParseOptions::reject_rules`], and count records in
+/// `count(<expression>) > <limit> in <window>` [`crate::AlertRule`]s.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{RuleExpr, fixtures};
+///
+/// let rule: RuleExpr = "amount >= 1000 && (type == TRANSFER || status == pending)".parse().unwrap();
+/// let matching = fixtures::records().iter().filter(|record| rule.matches(record)).count();
+/// assert_eq!(matching, 6);
+///
+/// assert!("amount > big".parse::<RuleExpr>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleExpr {
+    source: String,
+    node: Node,
+}
+
+impl RuleExpr {
+    pub fn matches(&self, record: &YPBankRecord) -> bool {
+        self.node.matches(record)
+    }
+
+    /// Fails with [`ParseError::RuleViolation`] with code 1 when `record` matches.
+    pub fn reject(&self, record: &YPBankRecord) -> Result<(), ParseError> {
+        if self.matches(record) {
+            return Err(ParseError::RuleViolation {
+                id: record.id,
+                rule: self.source.clone(),
+                code: 1,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RuleExpr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| ParseError::InvalidRawValue {
+            field: "rule expression".to_string(),
+            value: format!("{} ({})", s, reason),
+        };
+        let mut parser = ExprParser {
+            tokens: tokenize(s).map_err(invalid)?,
+            pos: 0,
+            depth: 0,
+        };
+        let node = parser.or().map_err(invalid)?;
+        if parser.pos < parser.tokens.len() {
+            return Err(invalid(format!(
+                "unexpected {:?}",
+                parser.tokens[parser.pos]
+            )));
+        }
+        Ok(Self {
+            source: s.trim().to_string(),
+            node,
+        })
+    }
+}
+
+impl fmt::Display for RuleExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Stage for RuleExpr {
+    fn process(&self, record: YPBankRecord) -> Result<Option<YPBankRecord>, ParseError> {
+        Ok(self.matches(&record).then_some(record))
+    }
+}
+
+#[cfg(test)]
+mod rule_expr_tests {
+    use super::*;
+
+    fn record(amount: i64, status: TransactionStatus, description: &str) -> YPBankRecord {
+        YPBankRecord::new(
+            7,
+            TransactionType::Transfer,
+            1,
+            2,
+            amount,
+            1633046460000,
+            status,
+            description,
+        )
+    }
+
+    #[test]
+    fn test_matches() {
+        let pending = record(150000, TransactionStatus::Pending, "Rent \"May\"");
+        let success = record(150000, TransactionStatus::Success, "Rent");
+        let small = record(-5, TransactionStatus::Pending, "");
+        let cases = [
+            (
+                "amount > 100000 && type == TRANSFER && status == PENDING",
+                [true, false, false],
+            ),
+            ("status == SUCCESS || amount < 0", [false, true, true]),
+            ("!(status == success) && amount != -5", [true, false, false]),
+            (r#"description contains "\"May\"""#, [true, false, false]),
+            (r#"DESCRIPTION == "" || tx_id == 8"#, [false, false, true]),
+            (
+                "from_user_id == 1 && to >= 2 && ts <= 1633046460000",
+                [true, true, true],
+            ),
+        ];
+        for (source, expected) in cases {
+            let rule: RuleExpr = source.parse().expect("Should parse rule");
+            let matched = [&pending, &success, &small].map(|record| rule.matches(record));
+            assert_eq!(matched, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_precedence_and_errors() {
+        let rule: RuleExpr = "amount < 0 || amount > 10 && status == FAILURE"
+            .parse()
+            .expect("Should parse rule");
+        assert!(rule.matches(&record(-1, TransactionStatus::Success, "")));
+        assert!(!rule.matches(&record(20, TransactionStatus::Success, "")));
+        assert_eq!(
+            rule.reject(&record(-1, TransactionStatus::Success, "")),
+            Err(ParseError::RuleViolation {
+                id: 7,
+                rule: "amount < 0 || amount > 10 && status == FAILURE".to_string(),
+                code: 1
+            })
+        );
+
+        for invalid in [
+            "",
+            "amount >",
+            "amount > 1 &&",
+            "(amount > 1",
+            "amount > 1)",
+            "status > PENDING",
+            "status == LATE",
+            "type == 1",
+            "description contains Rent",
+            "amount contains 1",
+            "balance > 1",
+            "a || b && c",
+            "amount = 1",
+            "description == \"open",
+        ] {
+            assert!(invalid.parse::<RuleExpr>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| {
+            format!(
+                "{}{}amount > 1{}",
+                "!".repeat(depth),
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+        let rule: RuleExpr = nested(MAX_DEPTH / 2).parse().expect("Should parse rule");
+        assert!(rule.matches(&record(2, TransactionStatus::Success, "")));
+
+        for depth in [MAX_DEPTH, 100_000] {
+            let Err(ParseError::InvalidRawValue { value, .. }) = nested(depth).parse::<RuleExpr>()
+            else {
+                panic!("Should reject {} levels", depth);
+            };
+            assert!(value.ends_with("(nested deeper than 64 levels)"));
+        }
+    }
+
+    #[test]
+    fn test_long_chains() {
+        let terms = 200_000;
+        let all = vec!["amount > 0"; terms].join(" && ");
+        let any = vec!["amount < 0"; terms].join(" || ");
+
+        let all: RuleExpr = all.parse().expect("Should parse rule");
+        let any: RuleExpr = any.parse().expect("Should parse rule");
+        assert!(all.matches(&record(1, TransactionStatus::Success, "")));
+        assert!(!any.matches(&record(1, TransactionStatus::Success, "")));
+    }
+}
@@ -0,0 +1,332 @@
+use crate::error::ParseError;
+use crate::toml_format::YPBankTomlRecordParser;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Field names of CSV headers, in column order of YPBank files.
+pub(crate) const CSV_FIELDS: [&str; 8] = [
+    "TX_ID",
+    "TX_TYPE",
+    "FROM_USER_ID",
+    "TO_USER_ID",
+    "AMOUNT",
+    "TIMESTAMP",
+    "STATUS",
+    "DESCRIPTION",
+];
+
+const COLUMNS_TABLE: &str = "columns";
+const COLUMN_NAME: &str = "column name";
+const VALUES_TABLE_PREFIX: &str = "values.";
+const HEADER_TABLE: &str = "header";
+const UNKNOWN_COLUMNS: &str = "unknown_columns";
+
+/// How a [`SchemaMap`] treats header columns that are neither mapped nor named like a
+/// YPBank field, e.g. audit columns of partner files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownColumns {
+    /// Fails with [`ParseError::InvalidCsvHeader`].
+    #[default]
+    Reject,
+    /// Leaves their values out.
+    Ignore,
+    /// Keeps their values by column name, read with
+    /// [`crate::CommonParser::iter_read_extended`].
+    Capture,
+}
+
+impl UnknownColumns {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnknownColumns::Reject => "reject",
+            UnknownColumns::Ignore => "ignore",
+            UnknownColumns::Capture => "capture",
+        }
+    }
+}
+
+impl FromStr for UnknownColumns {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" => Ok(UnknownColumns::Reject),
+            "ignore" => Ok(UnknownColumns::Ignore),
+            "capture" => Ok(UnknownColumns::Capture),
+            _ => Err(ParseError::InvalidRawValue {
+                field: UNKNOWN_COLUMNS.to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Maps the columns and values of foreign CSV files to YPBank fields.
+///
+/// With a schema map set in [`crate::ParseOptions::csv_schema`], CSV headers are matched
+/// by name instead of requiring the YPBank header, so columns can come in any order.
+/// Columns without a mapping must already be named like YPBank fields, unless unknown
+/// columns are ignored or captured ([`UnknownColumns`]).
+///
+/// Maps are usually loaded from a TOML file:
+///
+/// ```toml
+/// [columns]
+/// id = "TX_ID"
+/// kind = "TX_TYPE"
+/// debit_account = "FROM_USER_ID"
+/// credit_account = "TO_USER_ID"
+///
+/// [values.STATUS]
+/// OK = "SUCCESS"
+/// ERR = "FAILURE"
+///
+/// [header]
+/// unknown_columns = "ignore"
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use parser::SchemaMap;
+///
+/// let schema = SchemaMap::new()
+///     .with_column("debit_account", "FROM_USER_ID")
+///     .unwrap()
+///     .with_value("STATUS", "OK", "SUCCESS")
+///     .unwrap();
+///
+/// let toml = "[columns]\ndebit_account = \"FROM_USER_ID\"\n[values.STATUS]\nOK = \"SUCCESS\"\n";
+/// assert_eq!(SchemaMap::from_toml(toml).unwrap(), schema);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaMap {
+    columns: HashMap<String, usize>,
+    values: [HashMap<String, String>; 8],
+    unknown_columns: UnknownColumns,
+}
+
+impl SchemaMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the foreign `column` to the YPBank `field`, e.g. `debit_account` to `FROM_USER_ID`.
+    pub fn with_column(mut self, column: &str, field: &str) -> Result<Self, ParseError> {
+        self.columns
+            .insert(column.trim().to_string(), Self::field_index(field)?);
+        Ok(self)
+    }
+
+    /// Replaces the foreign value `from` of `field` with `to`, e.g. `OK` with `SUCCESS`.
+    pub fn with_value(mut self, field: &str, from: &str, to: &str) -> Result<Self, ParseError> {
+        self.values[Self::field_index(field)?].insert(from.to_string(), to.to_string());
+        Ok(self)
+    }
+
+    pub fn with_unknown_columns(mut self, unknown_columns: UnknownColumns) -> Self {
+        self.unknown_columns = unknown_columns;
+        self
+    }
+
+    pub fn unknown_columns(&self) -> UnknownColumns {
+        self.unknown_columns
+    }
+
+    /// Reads a map from TOML with a `[columns]` table, `[values.<FIELD>]` tables and a
+    /// `[header]` table setting `unknown_columns`.
+    pub fn from_toml(s: &str) -> Result<Self, ParseError> {
+        let mut schema = Self::new();
+        let mut table = None;
+
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let Some((name, _)) = name.split_once(']') else {
+                    return Err(ParseError::InvalidRow(line.to_string()));
+                };
+                table = Some(name.trim().to_string());
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(ParseError::InvalidRow(line.to_string()));
+            };
+            let key = match key.trim() {
+                key if key.starts_with(['"', '\'']) => {
+                    YPBankTomlRecordParser::parse_raw_value(COLUMN_NAME, key)?
+                }
+                key => key.to_string(),
+            };
+            let value = value.trim();
+            if !value.starts_with(['"', '\'']) {
+                return Err(ParseError::InvalidRawValue {
+                    field: key,
+                    value: value.to_string(),
+                });
+            }
+            let value = YPBankTomlRecordParser::parse_raw_value(&key, value)?;
+
+            schema = match table.as_deref() {
+                Some(COLUMNS_TABLE) => schema.with_column(&key, &value)?,
+                Some(HEADER_TABLE) if key == UNKNOWN_COLUMNS => {
+                    schema.with_unknown_columns(UnknownColumns::from_str(&value)?)
+                }
+                Some(table) if table.starts_with(VALUES_TABLE_PREFIX) => {
+                    schema.with_value(&table[VALUES_TABLE_PREFIX.len()..], &key, &value)?
+                }
+                _ => return Err(ParseError::InvalidRow(line.to_string())),
+            };
+        }
+
+        Ok(schema)
+    }
+
+    /// Reads a TOML map from a file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns, for each column of `header`, the index of the field it holds, or `None`
+    /// for unknown columns that are not rejected.
+    pub(crate) fn map_header(&self, header: &[&str]) -> Result<Vec<Option<usize>>, ParseError> {
+        let mut fields = Vec::with_capacity(header.len());
+        for column in header {
+            let field = match self.columns.get(column.trim()) {
+                Some(field) => *field,
+                None => match Self::field_index(column) {
+                    Ok(field) => field,
+                    Err(_) if self.unknown_columns != UnknownColumns::Reject => {
+                        fields.push(None);
+                        continue;
+                    }
+                    Err(_) => {
+                        return Err(ParseError::InvalidCsvHeader(format!(
+                            "unknown column {}",
+                            column
+                        )));
+                    }
+                },
+            };
+            if fields.contains(&Some(field)) {
+                return Err(ParseError::InvalidCsvHeader(format!(
+                    "duplicate column for {}",
+                    CSV_FIELDS[field]
+                )));
+            }
+            fields.push(Some(field));
+        }
+
+        match CSV_FIELDS
+            .iter()
+            .enumerate()
+            .find(|(index, _)| !fields.contains(&Some(*index)))
+        {
+            Some((_, field)) => Err(ParseError::FieldNotFound(field.to_string())),
+            None => Ok(fields),
+        }
+    }
+
+    /// Returns the YPBank form of a value of the field at `field`.
+    pub(crate) fn map_value<'a>(&'a self, field: usize, value: &'a str) -> &'a str {
+        self.values[field].get(value).map_or(value, String::as_str)
+    }
+
+    fn field_index(field: &str) -> Result<usize, ParseError> {
+        let field = field.trim();
+        CSV_FIELDS
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(field))
+            .ok_or_else(|| ParseError::FieldNotFound(field.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod schema_map_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let schema = SchemaMap::from_toml(
+            "# Partner export\n[columns]\ndebit_account = \"FROM_USER_ID\"\n\"credit account\" = 'to_user_id' # lowercase works\n\n[values.STATUS]\nOK = \"SUCCESS\"\n",
+        )
+        .expect("Should parse");
+
+        let expected = SchemaMap::new()
+            .with_column("debit_account", "FROM_USER_ID")
+            .and_then(|schema| schema.with_column("credit account", "TO_USER_ID"))
+            .and_then(|schema| schema.with_value("STATUS", "OK", "SUCCESS"))
+            .expect("Should build");
+        assert_eq!(schema, expected);
+        assert_eq!(schema.map_value(6, "OK"), "SUCCESS");
+        assert_eq!(schema.map_value(6, "PENDING"), "PENDING");
+    }
+
+    #[test]
+    fn test_from_toml_unknown_field() {
+        assert_eq!(
+            SchemaMap::from_toml("[columns]\naccount = \"ACCOUNT\"\n"),
+            Err(ParseError::FieldNotFound("ACCOUNT".to_string()))
+        );
+        assert_eq!(
+            SchemaMap::from_toml("account = \"TX_ID\"\n"),
+            Err(ParseError::InvalidRow("account = \"TX_ID\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_map_header() {
+        let schema = SchemaMap::new()
+            .with_column("debit", "FROM_USER_ID")
+            .expect("Should build");
+
+        let header = [
+            "DESCRIPTION",
+            "TX_ID",
+            "debit",
+            "TO_USER_ID",
+            "AMOUNT",
+            "TIMESTAMP",
+            "STATUS",
+            "TX_TYPE",
+        ];
+        assert_eq!(
+            schema.map_header(&header),
+            Ok([7, 0, 2, 3, 4, 5, 6, 1].map(Some).to_vec())
+        );
+        assert_eq!(
+            schema.map_header(&header[..7]),
+            Err(ParseError::FieldNotFound("TX_TYPE".to_string()))
+        );
+        assert!(matches!(
+            schema.map_header(&["TX_ID", "extra"]),
+            Err(ParseError::InvalidCsvHeader(_))
+        ));
+
+        let mut with_extra = header.to_vec();
+        with_extra.insert(1, "audited_by");
+        let schema = SchemaMap::from_toml(
+            "[columns]\ndebit = \"FROM_USER_ID\"\n[header]\nunknown_columns = \"ignore\"\n",
+        )
+        .expect("Should parse");
+        assert_eq!(schema.unknown_columns(), UnknownColumns::Ignore);
+        assert_eq!(
+            schema.map_header(&with_extra),
+            Ok(vec![
+                Some(7),
+                None,
+                Some(0),
+                Some(2),
+                Some(3),
+                Some(4),
+                Some(5),
+                Some(6),
+                Some(1)
+            ])
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use crate::bloom::IdBloom;
+use crate::common::read_u64_from_bytes;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Spilled runs kept before they are merged into one.
+const MAX_RUNS: usize = 16;
+/// False positive rate of the bloom filters of spilled runs.
+const RUN_FALSE_POSITIVE_RATE: f64 = 0.01;
+const ID_SIZE: u64 = 8;
+
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A set of transaction ids that keeps at most `max_in_memory` ids in memory and spills
+/// the others to sorted run files, for deduplicating more ids than fit in memory.
+///
+/// Every spilled run has an [`IdBloom`], so a new id is looked up in a run file for only
+/// about 1% of the runs; an id the filter lets through is verified by a binary search in
+/// the run file. The filters take about 10 bits per spilled id. Runs are merged once there
+/// are more than 16 of them, and the run files are removed when the set is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use parser::SeenIds;
+///
+/// let mut seen = SeenIds::new(std::env::temp_dir(), 2);
+/// assert_eq!(seen.insert(1), Ok(true));
+/// assert_eq!(seen.insert(2), Ok(true));
+/// assert_eq!(seen.insert(3), Ok(true));
+/// assert_eq!(seen.insert(1), Ok(false));
+/// assert_eq!(seen.len(), 3);
+/// ```
+pub struct SeenIds {
+    dir: PathBuf,
+    max_in_memory: usize,
+    memory: HashSet<u64>,
+    runs: Vec<IdRun>,
+}
+
+impl SeenIds {
+    /// Creates an empty set spilling to files in `temp_dir`.
+    pub fn new(temp_dir: impl AsRef<Path>, max_in_memory: usize) -> Self {
+        Self {
+            dir: temp_dir.as_ref().to_path_buf(),
+            max_in_memory: max_in_memory.max(1),
+            memory: HashSet::new(),
+            runs: vec![],
+        }
+    }
+
+    /// Adds `id`, returning whether it was not in the set yet.
+    pub fn insert(&mut self, id: u64) -> Result<bool, ParseError> {
+        if self.memory.contains(&id) {
+            return Ok(false);
+        }
+        for run in &mut self.runs {
+            if run.contains(id)? {
+                return Ok(false);
+            }
+        }
+
+        self.memory.insert(id);
+        if self.memory.len() >= self.max_in_memory {
+            self.spill()?;
+        }
+        Ok(true)
+    }
+
+    /// Keeps only the first record of every id in `records`, and errors.
+    pub fn dedup<'a, I>(
+        &'a mut self,
+        records: I,
+    ) -> impl Iterator<Item = Result<YPBankRecord, ParseError>> + 'a
+    where
+        I: IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        I::IntoIter: 'a,
+    {
+        records.into_iter().filter_map(|record| match record {
+            Ok(record) => match self.insert(record.id) {
+                Ok(true) => Some(Ok(record)),
+                Ok(false) => None,
+                Err(err) => Some(Err(err)),
+            },
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.memory.len() as u64 + self.runs.iter().map(|run| run.len).sum::<u64>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the ids held in memory to a new run, merging all runs once there are too many.
+    fn spill(&mut self) -> Result<(), ParseError> {
+        let mut ids: Vec<u64> = self.memory.drain().collect();
+        ids.sort_unstable();
+        self.runs
+            .push(IdRun::write(&self.dir, ids.into_iter().map(Ok))?);
+
+        if self.runs.len() > MAX_RUNS {
+            let runs = std::mem::take(&mut self.runs);
+            self.runs.push(IdRun::merge(&self.dir, runs)?);
+        }
+        Ok(())
+    }
+}
+
+/// A file of sorted ids, big-endian, removed on drop.
+struct IdRun {
+    path: PathBuf,
+    file: File,
+    len: u64,
+    bloom: IdBloom,
+}
+
+impl IdRun {
+    fn write(
+        dir: &Path,
+        ids: impl IntoIterator<Item = Result<u64, ParseError>>,
+    ) -> Result<Self, ParseError> {
+        let path = dir.join(format!(
+            "ypbank-seen-{}-{}.ids",
+            std::process::id(),
+            RUN_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        let mut w = BufWriter::new(file);
+        let mut len = 0;
+        for id in ids {
+            w.write_all(&id?.to_be_bytes())?;
+            len += 1;
+        }
+        let file = w.into_inner().map_err(|err| err.into_error())?;
+
+        let mut run = Self {
+            path,
+            file,
+            len,
+            bloom: IdBloom::new(len as usize, RUN_FALSE_POSITIVE_RATE),
+        };
+        run.file.rewind()?;
+        let mut r = BufReader::new(&run.file);
+        for _ in 0..len {
+            run.bloom.insert(read_u64_from_bytes(&mut r)?);
+        }
+        Ok(run)
+    }
+
+    /// Merges `runs` into one run, removing their files.
+    fn merge(dir: &Path, runs: Vec<IdRun>) -> Result<Self, ParseError> {
+        let mut readers = vec![];
+        for run in &runs {
+            readers.push((BufReader::new(File::open(&run.path)?), run.len));
+        }
+
+        let mut heap = BinaryHeap::new();
+        for (index, (reader, remaining)) in readers.iter_mut().enumerate() {
+            if *remaining > 0 {
+                heap.push(Reverse((read_u64_from_bytes(reader)?, index)));
+                *remaining -= 1;
+            }
+        }
+        // The merged ids go straight to the new run file, never all into memory.
+        let merged = std::iter::from_fn(|| {
+            let Reverse((id, index)) = heap.pop()?;
+            let (reader, remaining) = &mut readers[index];
+            if *remaining > 0 {
+                match read_u64_from_bytes(reader) {
+                    Ok(next) => heap.push(Reverse((next, index))),
+                    Err(err) => return Some(Err(err)),
+                }
+                *remaining -= 1;
+            }
+            Some(Ok(id))
+        });
+        Self::write(dir, merged)
+    }
+
+    fn contains(&mut self, id: u64) -> Result<bool, ParseError> {
+        if !self.bloom.may_contain(id) {
+            return Ok(false);
+        }
+
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            self.file.seek(SeekFrom::Start(middle * ID_SIZE))?;
+            let mut value = [0; ID_SIZE as usize];
+            self.file.read_exact(&mut value)?;
+            match u64::from_be_bytes(value).cmp(&id) {
+                std::cmp::Ordering::Equal => return Ok(true),
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for IdRun {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod seen_ids_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ypbank-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create temp dir");
+        dir
+    }
+
+    #[test]
+    fn test_matches_in_memory_set() {
+        let dir = temp_dir("seen-ids");
+        let mut seen = SeenIds::new(&dir, 7);
+        let mut expected = HashSet::new();
+        for index in 0..2000u64 {
+            let id = (index * 7919) % 1013;
+            assert_eq!(seen.insert(id), Ok(expected.insert(id)), "{}", id);
+        }
+
+        assert_eq!(seen.len(), expected.len() as u64);
+        assert!(seen.runs.len() <= MAX_RUNS);
+        assert!(std::fs::read_dir(&dir).unwrap().count() > 0);
+        drop(seen);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+    }
+
+    #[test]
+    fn test_merges_runs_on_disk() {
+        let dir = temp_dir("seen-ids-merge");
+        let mut seen = SeenIds::new(&dir, 2);
+        // 2 ids per run: a merge for every 17 runs, several times over.
+        let ids: Vec<u64> = (0..200u64).map(|index| (index * 7919) % 211).collect();
+        for &id in &ids {
+            assert_eq!(seen.insert(id), Ok(true));
+        }
+
+        assert!(seen.runs.len() <= MAX_RUNS);
+        let mut spilled = vec![];
+        for run in &seen.runs {
+            let data = std::fs::read(&run.path).expect("Should read run");
+            let run_ids: Vec<u64> = data
+                .chunks(ID_SIZE as usize)
+                .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()))
+                .collect();
+            assert!(run_ids.is_sorted());
+            assert_eq!(run_ids.len() as u64, run.len);
+            spilled.extend(run_ids);
+        }
+        assert!(seen.runs[0].len > 2 * MAX_RUNS as u64);
+        spilled.extend(seen.memory.iter());
+        spilled.sort_unstable();
+        let mut expected = ids.clone();
+        expected.sort_unstable();
+        assert_eq!(spilled, expected);
+        assert_eq!(seen.insert(ids[0]), Ok(false));
+
+        drop(seen);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+    }
+
+    #[test]
+    fn test_dedup() {
+        let record = |id: u64, description: &str| {
+            Ok(YPBankRecord::new(
+                id,
+                TransactionType::Deposit,
+                0,
+                1,
+                100,
+                1633036860000,
+                TransactionStatus::Success,
+                description,
+            ))
+        };
+        let records = vec![
+            record(1, "first"),
+            record(2, "first"),
+            record(1, "second"),
+            Err(ParseError::UnexpectedEOF),
+            record(3, "first"),
+            record(2, "second"),
+        ];
+
+        let dir = temp_dir("seen-ids-dedup");
+        let mut seen = SeenIds::new(&dir, 2);
+        let deduped: Vec<_> = seen.dedup(records).collect();
+        drop(seen);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+
+        assert_eq!(
+            deduped,
+            vec![
+                record(1, "first"),
+                record(2, "first"),
+                Err(ParseError::UnexpectedEOF),
+                record(3, "first"),
+            ]
+        );
+    }
+}
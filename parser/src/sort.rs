@@ -0,0 +1,425 @@
+use crate::CommonParser;
+use crate::common::Format;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Maximum number of runs merged at once by [`sort_file`].
+const MAX_FAN_IN: usize = 64;
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A record field records can be ordered by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Id,
+    Ts,
+    Amount,
+}
+
+impl SortKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortKey::Id => "id",
+            SortKey::Ts => "ts",
+            SortKey::Amount => "amount",
+        }
+    }
+
+    /// Returns the value of the key field, widened so that all fields compare alike.
+    pub fn of(&self, record: &YPBankRecord) -> i128 {
+        match self {
+            SortKey::Id => record.id as i128,
+            SortKey::Ts => record.ts as i128,
+            SortKey::Amount => record.amount as i128,
+        }
+    }
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "id" => Ok(SortKey::Id),
+            "ts" => Ok(SortKey::Ts),
+            "amount" => Ok(SortKey::Amount),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "sort key".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Merges inputs that are each sorted by `key` into one sorted stream.
+///
+/// Only one record per input is held in memory at a time. Records with equal keys are
+/// returned in input order, so the merge is stable. An input that is not sorted by `key`
+/// produces an [`ParseError::InconsistentRecord`] error.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{SortKey, TransactionStatus, TransactionType, YPBankRecord, merge_sorted};
+///
+/// let record = |ts| {
+///     Ok(YPBankRecord::new(ts, TransactionType::Deposit, 0, 1, 1, ts, TransactionStatus::Success, ""))
+/// };
+///
+/// let merged = merge_sorted(vec![vec![record(1), record(4)], vec![record(2), record(3)]], SortKey::Ts)
+///     .map(|record| record.unwrap().ts)
+///     .collect::<Vec<_>>();
+/// assert_eq!(merged, vec![1, 2, 3, 4]);
+/// ```
+pub fn merge_sorted<I>(inputs: Vec<I>, key: SortKey) -> MergeSorted<I::IntoIter>
+where
+    I: IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+{
+    let heads = inputs.iter().map(|_| None).collect();
+    MergeSorted {
+        inputs: inputs.into_iter().map(IntoIterator::into_iter).collect(),
+        key,
+        heads,
+        heap: BinaryHeap::new(),
+        pending_error: None,
+        started: false,
+        done: false,
+    }
+}
+
+/// The iterator returned by [`merge_sorted`].
+pub struct MergeSorted<I> {
+    inputs: Vec<I>,
+    key: SortKey,
+    heads: Vec<Option<YPBankRecord>>,
+    heap: BinaryHeap<Reverse<(i128, usize)>>,
+    pending_error: Option<ParseError>,
+    started: bool,
+    done: bool,
+}
+
+impl<I: Iterator<Item = Result<YPBankRecord, ParseError>>> MergeSorted<I> {
+    /// Pulls the next record of input `index` into the heap.
+    fn advance(&mut self, index: usize, previous: Option<i128>) -> Result<(), ParseError> {
+        let Some(record) = self.inputs[index].next().transpose()? else {
+            return Ok(());
+        };
+
+        let value = self.key.of(&record);
+        if previous.is_some_and(|previous| value < previous) {
+            return Err(ParseError::InconsistentRecord(format!(
+                "input {} is not sorted by {} at record {}",
+                index,
+                self.key.as_str(),
+                record.id
+            )));
+        }
+
+        self.heads[index] = Some(record);
+        self.heap.push(Reverse((value, index)));
+        Ok(())
+    }
+
+    fn fail(&mut self, err: ParseError) -> Option<Result<YPBankRecord, ParseError>> {
+        self.done = true;
+        Some(Err(err))
+    }
+}
+
+impl<I: Iterator<Item = Result<YPBankRecord, ParseError>>> Iterator for MergeSorted<I> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(err) = self.pending_error.take() {
+            return self.fail(err);
+        }
+        if !self.started {
+            self.started = true;
+            for index in 0..self.inputs.len() {
+                if let Err(err) = self.advance(index, None) {
+                    return self.fail(err);
+                }
+            }
+        }
+
+        let Reverse((value, index)) = self.heap.pop()?;
+        let record = self.heads[index].take().expect("heap entries have a head");
+        if let Err(err) = self.advance(index, Some(value)) {
+            self.pending_error = Some(err);
+        }
+
+        Some(Ok(record))
+    }
+}
+
+/// Sorts records by `key` using at most about `mem_budget` bytes of record data in memory.
+///
+/// Records are read in chunks that fit the budget; each chunk is sorted and spilled to a
+/// temporary binary file in `temp_dir`, and the spill files are then merged into `output`.
+/// The sort is stable and the spill files are removed when it finishes, also on errors.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Format, SortKey, sort_file};
+/// use std::fs::File;
+/// # parser::fixtures::enter().unwrap();
+///
+/// let input = File::open("archive.bin").unwrap();
+/// let mut output = File::create("sorted.bin").unwrap();
+/// let temp_dir = std::env::temp_dir();
+/// sort_file(input, &mut output, Format::Bin, SortKey::Ts, &temp_dir, 512 * 1024 * 1024).unwrap();
+/// ```
+pub fn sort_file<R: std::io::Read, W: std::io::Write>(
+    input: R,
+    output: &mut W,
+    format: Format,
+    key: SortKey,
+    temp_dir: &Path,
+    mem_budget: usize,
+) -> Result<(), ParseError> {
+    let parser = CommonParser::new(format);
+    let mut spill = SpillFiles::new(temp_dir);
+
+    let mut chunk = vec![];
+    let mut chunk_size = 0;
+    for record in parser.iter_read(input)? {
+        let record = record?;
+        chunk_size += record_size(&record);
+        chunk.push(record);
+        if chunk_size >= mem_budget {
+            spill.write_run(sort_chunk(chunk, key).into_iter().map(Ok))?;
+            chunk = vec![];
+            chunk_size = 0;
+        }
+    }
+
+    if spill.runs.is_empty() {
+        return parser.write_to(output, &sort_chunk(chunk, key));
+    }
+    if !chunk.is_empty() {
+        spill.write_run(sort_chunk(chunk, key).into_iter().map(Ok))?;
+    }
+
+    while spill.runs.len() > MAX_FAN_IN {
+        let runs = std::mem::take(&mut spill.runs);
+        let merged = runs
+            .chunks(MAX_FAN_IN)
+            .try_for_each(|group| spill.write_run(merge_sorted(SpillFiles::open(group)?, key)));
+        SpillFiles::remove(&runs);
+        merged?;
+    }
+
+    parser.write_iter(output, merge_sorted(SpillFiles::open(&spill.runs)?, key))
+}
+
+fn record_size(record: &YPBankRecord) -> usize {
+    std::mem::size_of::<YPBankRecord>() + record.description.len()
+}
+
+fn sort_chunk(mut chunk: Vec<YPBankRecord>, key: SortKey) -> Vec<YPBankRecord> {
+    chunk.sort_by_key(|record| key.of(record));
+    chunk
+}
+
+/// Temporary run files, removed on drop.
+struct SpillFiles {
+    dir: PathBuf,
+    runs: Vec<PathBuf>,
+}
+
+impl SpillFiles {
+    fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            runs: vec![],
+        }
+    }
+
+    fn write_run(
+        &mut self,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<(), ParseError> {
+        let path = self.dir.join(format!(
+            "ypbank-sort-{}-{}.bin",
+            std::process::id(),
+            SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.runs.push(path.clone());
+
+        let mut w = std::io::BufWriter::new(File::create(&path)?);
+        CommonParser::new(Format::Bin).write_iter(&mut w, records)?;
+        std::io::Write::flush(&mut w)?;
+        Ok(())
+    }
+
+    fn open(runs: &[PathBuf]) -> Result<Vec<crate::RecordStream<'static>>, ParseError> {
+        let parser = CommonParser::new(Format::Bin);
+        runs.iter()
+            .map(|path| parser.iter_read(File::open(path)?))
+            .collect()
+    }
+
+    fn remove(runs: &[PathBuf]) {
+        for path in runs {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for SpillFiles {
+    fn drop(&mut self) {
+        Self::remove(&self.runs);
+    }
+}
+
+#[cfg(test)]
+mod merge_sorted_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(id: u64, ts: u64) -> Result<YPBankRecord, ParseError> {
+        Ok(YPBankRecord::new(
+            id,
+            TransactionType::Deposit,
+            0,
+            1,
+            100,
+            ts,
+            TransactionStatus::Success,
+            "",
+        ))
+    }
+
+    fn ids(merged: impl Iterator<Item = Result<YPBankRecord, ParseError>>) -> Vec<u64> {
+        merged
+            .map(|record| record.expect("Should merge").id)
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_is_stable() {
+        let inputs = vec![
+            vec![record(1, 10), record(2, 20), record(3, 20)],
+            vec![],
+            vec![record(4, 5), record(5, 20), record(6, 30)],
+        ];
+
+        assert_eq!(
+            ids(merge_sorted(inputs, SortKey::Ts)),
+            vec![4, 1, 2, 3, 5, 6]
+        );
+    }
+
+    #[test]
+    fn test_merge_unsorted_input() {
+        let inputs = vec![vec![record(1, 10), record(2, 5)], vec![record(3, 7)]];
+
+        let merged = merge_sorted(inputs, SortKey::Ts).collect::<Vec<_>>();
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged[0], record(3, 7));
+        assert_eq!(merged[1], record(1, 10));
+        assert_eq!(
+            merged[2],
+            Err(ParseError::InconsistentRecord(
+                "input 0 is not sorted by ts at record 2".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sort_key_from_str() {
+        assert_eq!("TS".parse(), Ok(SortKey::Ts));
+        assert_eq!(
+            "date".parse::<SortKey>(),
+            Err(ParseError::InvalidRawValue {
+                field: "sort key".to_string(),
+                value: "date".to_string()
+            })
+        );
+    }
+}
+
+#[cfg(test)]
+mod sort_file_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn records(count: u64) -> Vec<YPBankRecord> {
+        (0..count)
+            .map(|index| {
+                YPBankRecord::new(
+                    index,
+                    TransactionType::Transfer,
+                    1,
+                    2,
+                    index as i64,
+                    (index * 7919) % 101,
+                    TransactionStatus::Success,
+                    format!("Record {}", index),
+                )
+            })
+            .collect()
+    }
+
+    fn sort(records: &Vec<YPBankRecord>, temp_dir: &Path, mem_budget: usize) -> Vec<YPBankRecord> {
+        let parser = CommonParser::new(Format::Csv);
+        let mut input = vec![];
+        parser
+            .write_to(&mut input, records)
+            .expect("Should write input");
+
+        let mut output = vec![];
+        sort_file(
+            std::io::Cursor::new(input),
+            &mut output,
+            Format::Csv,
+            SortKey::Ts,
+            temp_dir,
+            mem_budget,
+        )
+        .expect("Should sort");
+
+        parser
+            .from_read(&mut std::io::Cursor::new(output))
+            .expect("Should read output")
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ypbank-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).expect("Should create temp dir");
+        dir
+    }
+
+    #[test]
+    fn test_sort_file_with_spills_is_stable() {
+        let dir = temp_dir("sort-spills");
+        let mut expected = records(500);
+        expected.sort_by_key(|record| record.ts);
+
+        // A budget of a few records produces more runs than can be merged at once.
+        let sorted = sort(&records(500), &dir, 3 * record_size(&expected[0]));
+
+        assert_eq!(sorted, expected);
+        assert_eq!(std::fs::read_dir(&dir).expect("Should list").count(), 0);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+    }
+
+    #[test]
+    fn test_sort_file_in_memory() {
+        let dir = temp_dir("sort-memory");
+        let mut expected = records(20);
+        expected.sort_by_key(|record| record.ts);
+
+        assert_eq!(sort(&records(20), &dir, usize::MAX), expected);
+        std::fs::remove_dir(&dir).expect("Should remove temp dir");
+    }
+}
@@ -0,0 +1,453 @@
+//! Machine-readable description of the binary format, the authoritative definition for
+//! implementations in other languages.
+//!
+//! [`binary`] describes the layout of a record field by field, and renders it as JSON or
+//! Markdown. The tools print it with `<tool> spec [json|markdown]`.
+
+use crate::common::{TransactionStatus, TransactionType};
+use crate::constant::{AMOUNT, DESCRIPTION, FROM_USER_ID, STATUS, TIMESTAMP, TO_USER_ID, TX_ID};
+use crate::error::ParseError;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const TX_TYPE: &str = "TX_TYPE";
+
+/// Encoding of a field value. Numbers are big-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Bytes,
+    U8,
+    U32,
+    U64,
+    I64,
+    /// UTF-8 text, without a terminator.
+    Utf8,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Bytes => "bytes",
+            FieldType::U8 => "u8",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I64 => "i64",
+            FieldType::Utf8 => "utf8",
+        }
+    }
+}
+
+/// A field of a record or of an extension entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    /// Offset in bytes from the start of the record or entry; `None` after a field of
+    /// variable size.
+    pub offset: Option<u32>,
+    /// Size in bytes; `None` when given by an earlier field or the end of the entry.
+    pub size: Option<u32>,
+    pub field_type: FieldType,
+    pub description: &'static str,
+}
+
+/// Values of a one-byte enumerated field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumSpec {
+    pub field: &'static str,
+    pub values: Vec<(u8, &'static str)>,
+}
+
+/// A change of the layout. Files carry no version number: readers honoring
+/// `RECORD_SIZE` read every revision.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    pub revision: u32,
+    pub changes: &'static str,
+}
+
+/// Layout of the binary format, see [`binary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinarySpec {
+    pub magic: [u8; 4],
+    /// Byte order of every number, `big`.
+    pub endianness: &'static str,
+    /// Fields of a record, in order. Records follow each other without padding.
+    pub record: Vec<FieldSpec>,
+    pub enums: Vec<EnumSpec>,
+    /// Fields of an extension entry; entries fill the record after the description.
+    pub extension_entry: Vec<FieldSpec>,
+    /// Fields of the `DATA` of an extension entry with tag 1, an extra of the record.
+    pub extra_entry: Vec<FieldSpec>,
+    pub revisions: Vec<Revision>,
+}
+
+/// Format of a rendered spec.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpecFormat {
+    #[default]
+    Json,
+    Markdown,
+}
+
+impl FromStr for SpecFormat {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(SpecFormat::Json),
+            "markdown" | "md" => Ok(SpecFormat::Markdown),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "spec format".to_string(),
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Lays out fields one after the other from offset 0, until the first of variable size.
+fn lay_out(fields: &[(&'static str, Option<u32>, FieldType, &'static str)]) -> Vec<FieldSpec> {
+    let mut offset = Some(0);
+    fields
+        .iter()
+        .map(|&(name, size, field_type, description)| {
+            let field = FieldSpec {
+                name,
+                offset,
+                size,
+                field_type,
+                description,
+            };
+            offset = offset.zip(size).map(|(offset, size)| offset + size);
+            field
+        })
+        .collect()
+}
+
+/// Returns the layout of the binary format, as read and written by
+/// [`crate::CommonParser`] with [`crate::Format::Bin`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::spec;
+///
+/// let spec = spec::binary();
+/// assert_eq!(&spec.magic, b"YPBN");
+/// let amount = spec.record.iter().find(|field| field.name == "AMOUNT").unwrap();
+/// assert_eq!((amount.offset, amount.size), (Some(33), Some(8)));
+/// ```
+pub fn binary() -> BinarySpec {
+    use FieldType::*;
+
+    let record = lay_out(&[
+        ("MAGIC", Some(4), Bytes, "`YPBN`, starting every record"),
+        (
+            "RECORD_SIZE",
+            Some(4),
+            U32,
+            "Bytes of the record after this field; 0 ends the records",
+        ),
+        (TX_ID, Some(8), U64, "Transaction id"),
+        (TX_TYPE, Some(1), U8, "Transaction type, see the enums"),
+        (
+            FROM_USER_ID,
+            Some(8),
+            U64,
+            "Sending user; 0 only for deposits",
+        ),
+        (
+            TO_USER_ID,
+            Some(8),
+            U64,
+            "Receiving user; 0 only for withdrawals",
+        ),
+        (AMOUNT, Some(8), I64, "Amount in minor units"),
+        (TIMESTAMP, Some(8), U64, "Milliseconds since the Unix epoch"),
+        (STATUS, Some(1), U8, "Status, see the enums"),
+        ("DESCRIPTION_LEN", Some(4), U32, "Bytes of the description"),
+        (DESCRIPTION, None, Utf8, "Description"),
+        (
+            "EXTENSION",
+            None,
+            Bytes,
+            "Extension entries up to RECORD_SIZE, since revision 2",
+        ),
+    ]);
+    let extension_entry = lay_out(&[
+        (
+            "TAG",
+            Some(1),
+            U8,
+            "Kind of entry; readers skip unknown tags",
+        ),
+        ("LENGTH", Some(4), U32, "Bytes of the entry data"),
+        ("DATA", None, Bytes, "Entry data"),
+    ]);
+    let extra_entry = lay_out(&[
+        ("NAME_LEN", Some(4), U32, "Bytes of the name"),
+        ("NAME", None, Utf8, "Name of the extra"),
+        (
+            "VALUE",
+            None,
+            Utf8,
+            "Value of the extra, up to the end of the data",
+        ),
+    ]);
+
+    BinarySpec {
+        magic: *b"YPBN",
+        endianness: "big",
+        record,
+        enums: vec![
+            EnumSpec {
+                field: TX_TYPE,
+                values: (0..=u8::MAX)
+                    .filter_map(|value| {
+                        let kind = TransactionType::from_int(value).ok()?;
+                        Some((value, kind.as_str()))
+                    })
+                    .collect(),
+            },
+            EnumSpec {
+                field: STATUS,
+                values: (0..=u8::MAX)
+                    .filter_map(|value| {
+                        let status = TransactionStatus::from_int(value).ok()?;
+                        Some((value, status.as_str()))
+                    })
+                    .collect(),
+            },
+        ],
+        extension_entry,
+        extra_entry,
+        revisions: vec![
+            Revision {
+                revision: 1,
+                changes: "Fixed fields and description; RECORD_SIZE is 46 plus DESCRIPTION_LEN",
+            },
+            Revision {
+                revision: 2,
+                changes: "Extension entries after the description, counted in RECORD_SIZE",
+            },
+        ],
+    }
+}
+
+/// Writes `s` as a JSON string.
+pub(crate) fn push_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if ch.is_control() => {
+                let _ = write!(out, "\\u{:04x}", ch as u32);
+            }
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn push_json_fields(out: &mut String, fields: &[FieldSpec]) {
+    let number = |value: Option<u32>| value.map_or("null".to_string(), |value| value.to_string());
+    out.push('[');
+    for (index, field) in fields.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        push_json_str(out, field.name);
+        let _ = write!(
+            out,
+            ",\"offset\":{},\"size\":{},\"type\":\"{}\",\"description\":",
+            number(field.offset),
+            number(field.size),
+            field.field_type.as_str()
+        );
+        push_json_str(out, field.description);
+        out.push('}');
+    }
+    out.push(']');
+}
+
+fn push_markdown_fields(out: &mut String, fields: &[FieldSpec]) {
+    let cell = |value: Option<u32>| value.map_or("-".to_string(), |value| value.to_string());
+    out.push_str("| Field | Offset | Size | Type | Description |\n");
+    out.push_str("|-------|--------|------|------|-------------|\n");
+    for field in fields {
+        let _ = writeln!(
+            out,
+            "| `{}` | {} | {} | {} | {} |",
+            field.name,
+            cell(field.offset),
+            cell(field.size),
+            field.field_type.as_str(),
+            field.description
+        );
+    }
+}
+
+impl BinarySpec {
+    /// Renders the spec as one JSON object.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"format\":\"binary\",\"magic\":");
+        push_json_str(&mut out, &String::from_utf8_lossy(&self.magic));
+        out.push_str(",\"endianness\":");
+        push_json_str(&mut out, self.endianness);
+        out.push_str(",\"record\":");
+        push_json_fields(&mut out, &self.record);
+        out.push_str(",\"enums\":{");
+        for (index, spec) in self.enums.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            push_json_str(&mut out, spec.field);
+            out.push_str(":{");
+            for (index, (value, name)) in spec.values.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                push_json_str(&mut out, name);
+                let _ = write!(out, ":{}", value);
+            }
+            out.push('}');
+        }
+        out.push_str("},\"extension_entry\":");
+        push_json_fields(&mut out, &self.extension_entry);
+        out.push_str(",\"extra_entry\":");
+        push_json_fields(&mut out, &self.extra_entry);
+        out.push_str(",\"revisions\":[");
+        for (index, revision) in self.revisions.iter().enumerate() {
+            if index > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"revision\":{},\"changes\":", revision.revision);
+            push_json_str(&mut out, revision.changes);
+            out.push('}');
+        }
+        out.push_str("]}\n");
+        out
+    }
+
+    /// Renders the spec as a Markdown document with a table per structure.
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::from("# YPBank Binary Format\n\n");
+        let _ = writeln!(
+            out,
+            "Records start with the magic `{}` and follow each other without padding. \
+             Numbers are {}-endian.\n",
+            String::from_utf8_lossy(&self.magic),
+            self.endianness
+        );
+        out.push_str("## Record\n\n");
+        push_markdown_fields(&mut out, &self.record);
+        for spec in &self.enums {
+            let _ = writeln!(out, "\n## `{}` Values\n", spec.field);
+            out.push_str("| Value | Name |\n|-------|------|\n");
+            for (value, name) in &spec.values {
+                let _ = writeln!(out, "| {} | `{}` |", value, name);
+            }
+        }
+        out.push_str("\n## Extension Entry\n\n");
+        push_markdown_fields(&mut out, &self.extension_entry);
+        out.push_str("\n## Extra Entry (tag 1)\n\n");
+        push_markdown_fields(&mut out, &self.extra_entry);
+        out.push_str("\n## Revisions\n\n");
+        for revision in &self.revisions {
+            let _ = writeln!(out, "{}. {}", revision.revision, revision.changes);
+        }
+        out
+    }
+
+    pub fn render(&self, format: SpecFormat) -> String {
+        match format {
+            SpecFormat::Json => self.to_json(),
+            SpecFormat::Markdown => self.to_markdown(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod spec_tests {
+    use super::*;
+    use crate::bin_format::encode_bin_record;
+    use crate::fixtures;
+
+    fn field<'a>(fields: &'a [FieldSpec], name: &str) -> &'a FieldSpec {
+        fields
+            .iter()
+            .find(|field| field.name == name)
+            .expect("Should have field")
+    }
+
+    /// Reads the bytes of a fixed field of `encoded` at the offset of the spec.
+    fn bytes<'a>(spec: &BinarySpec, encoded: &'a [u8], name: &str) -> &'a [u8] {
+        let field = field(&spec.record, name);
+        let offset = field.offset.expect("Should be fixed") as usize;
+        &encoded[offset..offset + field.size.expect("Should be fixed") as usize]
+    }
+
+    #[test]
+    fn test_layout_matches_encoding() {
+        let spec = binary();
+        let record = &fixtures::records()[1];
+        let mut encoded = Vec::new();
+        encode_bin_record(record, &mut encoded);
+
+        assert_eq!(bytes(&spec, &encoded, "MAGIC"), spec.magic);
+        assert_eq!(bytes(&spec, &encoded, TX_ID), record.id.to_be_bytes());
+        assert_eq!(
+            bytes(&spec, &encoded, TX_TYPE),
+            [record.transaction_type.as_int()]
+        );
+        assert_eq!(
+            bytes(&spec, &encoded, FROM_USER_ID),
+            record.from_user_id.to_be_bytes()
+        );
+        assert_eq!(
+            bytes(&spec, &encoded, TO_USER_ID),
+            record.to_user_id.to_be_bytes()
+        );
+        assert_eq!(bytes(&spec, &encoded, AMOUNT), record.amount.to_be_bytes());
+        assert_eq!(bytes(&spec, &encoded, TIMESTAMP), record.ts.to_be_bytes());
+        assert_eq!(bytes(&spec, &encoded, STATUS), [record.status.as_int()]);
+
+        let description_len = record.description.len() as u32;
+        assert_eq!(
+            bytes(&spec, &encoded, "DESCRIPTION_LEN"),
+            description_len.to_be_bytes()
+        );
+        let description = field(&spec.record, DESCRIPTION)
+            .offset
+            .expect("Should follow fixed fields") as usize;
+        assert_eq!(&encoded[description..], record.description.as_bytes());
+        assert_eq!(
+            bytes(&spec, &encoded, "RECORD_SIZE"),
+            (description as u32 - 8 + description_len).to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let spec = binary();
+        assert_eq!(
+            spec.enums[0].values,
+            vec![(0, "DEPOSIT"), (1, "TRANSFER"), (2, "WITHDRAWAL")]
+        );
+
+        let json = spec.render(SpecFormat::Json);
+        assert!(json.starts_with("{\"format\":\"binary\",\"magic\":\"YPBN\",\"endianness\":\"big\",\"record\":[{\"name\":\"MAGIC\",\"offset\":0,\"size\":4,"));
+        assert!(
+            json.contains(
+                "{\"name\":\"DESCRIPTION\",\"offset\":54,\"size\":null,\"type\":\"utf8\""
+            )
+        );
+        assert!(json.contains("\"STATUS\":{\"SUCCESS\":0,\"FAILURE\":1,\"PENDING\":2}"));
+
+        let markdown = spec.render(SpecFormat::from_str("md").expect("Should parse"));
+        assert!(markdown.contains("| `AMOUNT` | 33 | 8 | i64 | Amount in minor units |\n"));
+        assert!(markdown.contains("| `VALUE` | - | - | utf8 |"));
+        assert!(SpecFormat::from_str("yaml").is_err());
+    }
+}
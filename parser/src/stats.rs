@@ -0,0 +1,698 @@
+use crate::business_day::{BusinessCalendar, DayKind};
+use crate::calendar::format_utc;
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::totals::Totals;
+#[cfg(feature = "tz")]
+use crate::tz::TimeZone;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+/// Counts of amounts falling into buckets between increasing bounds.
+///
+/// Bounds `[0, 100]` make the buckets `..0`, `0..100` and `100..`; each bucket includes
+/// its lower bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmountHistogram {
+    bounds: Vec<i64>,
+    counts: Vec<u64>,
+}
+
+impl AmountHistogram {
+    pub fn new(bounds: Vec<i64>) -> Result<Self, ParseError> {
+        if bounds.is_empty() || bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(ParseError::InvalidRawValue {
+                field: "histogram bounds".to_string(),
+                value: format!("{:?}", bounds),
+            });
+        }
+        let counts = vec![0; bounds.len() + 1];
+        Ok(Self { bounds, counts })
+    }
+
+    pub fn add(&mut self, amount: i64) {
+        self.counts[self.bounds.partition_point(|bound| *bound <= amount)] += 1;
+    }
+
+    /// Returns each bucket as `(from, to, count)`; the outer buckets are open-ended.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<i64>, Option<i64>, u64)> + '_ {
+        self.counts.iter().enumerate().map(|(index, count)| {
+            let from = index.checked_sub(1).map(|index| self.bounds[index]);
+            (from, self.bounds.get(index).copied(), *count)
+        })
+    }
+}
+
+/// A period records are counted by, in UTC or with the `tz` feature in a [`TimeZone`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Hour,
+    Day,
+}
+
+impl Period {
+    pub fn millis(&self) -> u64 {
+        match self {
+            Period::Hour => 3_600_000,
+            Period::Day => 86_400_000,
+        }
+    }
+
+    /// Renders the period starting at `start` as `YYYY-MM-DDTHH:MM` or `YYYY-MM-DD`.
+    pub fn label(&self, start: u64) -> String {
+        format_utc(start, *self == Period::Hour)
+    }
+
+    /// Renders the period starting at `start` like [`Period::label`], in local time of
+    /// `time_zone`.
+    #[cfg(feature = "tz")]
+    pub fn label_in(&self, start: u64, time_zone: &TimeZone) -> String {
+        format_utc(local(start, time_zone), *self == Period::Hour)
+    }
+}
+
+/// Shifts `ts` by the UTC offset of `time_zone` at that time.
+#[cfg(feature = "tz")]
+fn local(ts: u64, time_zone: &TimeZone) -> u64 {
+    let offset = time_zone.offset_at((ts / 1000) as i64) as i64;
+    ts.saturating_add_signed(offset * 1000)
+}
+
+/// Records a user took part in and the sum of their absolute amounts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UserVolume {
+    pub user_id: u64,
+    pub count: u64,
+    pub volume: u128,
+}
+
+/// Distributions of a set of records: totals, an amount histogram, record counts per
+/// hour and per day, and volumes per user. With a [`BusinessCalendar`], totals are also
+/// grouped by [`DayKind`].
+///
+/// # Examples
+///
+/// ```
+/// use parser::{AmountHistogram, Period, Stats, TransactionStatus, TransactionType, YPBankRecord};
+///
+/// let record = |to, amount, ts| {
+///     YPBankRecord::new(1, TransactionType::Deposit, 0, to, amount, ts, TransactionStatus::Success, "")
+/// };
+///
+/// let mut stats = Stats::new(AmountHistogram::new(vec![0, 100]).unwrap());
+/// for record in [record(7, 50, 0), record(8, 500, 3_600_000), record(7, 70, 3_700_000)] {
+///     stats.add(&record).unwrap();
+/// }
+///
+/// let buckets: Vec<_> = stats.amounts().buckets().map(|(_, _, count)| count).collect();
+/// assert_eq!(buckets, vec![0, 2, 1]);
+/// assert_eq!(stats.counts_per(Period::Hour).get(&3_600_000), Some(&2));
+/// assert_eq!(stats.top_users(1)[0].user_id, 8);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub totals: Totals,
+    amounts: AmountHistogram,
+    per_hour: BTreeMap<u64, u64>,
+    per_day: BTreeMap<u64, u64>,
+    users: HashMap<u64, UserVolume>,
+    day_kinds: Option<(BusinessCalendar, [Totals; 3])>,
+    #[cfg(feature = "tz")]
+    time_zone: Option<TimeZone>,
+}
+
+impl Stats {
+    pub fn new(amounts: AmountHistogram) -> Self {
+        Self {
+            totals: Totals::new(),
+            amounts,
+            per_hour: BTreeMap::new(),
+            per_day: BTreeMap::new(),
+            users: HashMap::new(),
+            day_kinds: None,
+            #[cfg(feature = "tz")]
+            time_zone: None,
+        }
+    }
+
+    /// Groups totals by the [`DayKind`] of records in `calendar`.
+    pub fn with_calendar(mut self, calendar: BusinessCalendar) -> Self {
+        self.day_kinds = Some((calendar, [Totals::new(); 3]));
+        self
+    }
+
+    /// Counts records per hour and per day of `time_zone` instead of UTC.
+    #[cfg(feature = "tz")]
+    pub fn with_time_zone(mut self, time_zone: TimeZone) -> Self {
+        self.time_zone = Some(time_zone);
+        self
+    }
+
+    /// Adds a record to the statistics.
+    ///
+    /// User `0`, the bank side of deposits and withdrawals, is not counted as a user.
+    pub fn add(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        self.totals.add(record)?;
+        self.amounts.add(record.amount);
+        if let Some((calendar, totals)) = &mut self.day_kinds {
+            totals[calendar.day_kind(record.ts) as usize].add(record)?;
+        }
+        #[cfg(feature = "tz")]
+        let ts = match &self.time_zone {
+            Some(time_zone) => local(record.ts, time_zone),
+            None => record.ts,
+        };
+        #[cfg(not(feature = "tz"))]
+        let ts = record.ts;
+        for (period, counts) in [
+            (Period::Hour, &mut self.per_hour),
+            (Period::Day, &mut self.per_day),
+        ] {
+            *counts
+                .entry(record.ts.saturating_sub(ts % period.millis()))
+                .or_default() += 1;
+        }
+
+        for user_id in [record.from_user_id, record.to_user_id] {
+            if user_id == 0 {
+                continue;
+            }
+            let user = self.users.entry(user_id).or_insert(UserVolume {
+                user_id,
+                ..UserVolume::default()
+            });
+            user.count += 1;
+            user.volume += record.amount.unsigned_abs() as u128;
+        }
+        Ok(())
+    }
+
+    pub fn amounts(&self) -> &AmountHistogram {
+        &self.amounts
+    }
+
+    /// Renders the period starting at `start`, in the time zone of the statistics.
+    pub fn label(&self, period: Period, start: u64) -> String {
+        #[cfg(feature = "tz")]
+        if let Some(time_zone) = &self.time_zone {
+            return period.label_in(start, time_zone);
+        }
+        period.label(start)
+    }
+
+    /// Returns record counts keyed by the start of each period with records, in order.
+    pub fn counts_per(&self, period: Period) -> &BTreeMap<u64, u64> {
+        match period {
+            Period::Hour => &self.per_hour,
+            Period::Day => &self.per_day,
+        }
+    }
+
+    /// Returns the totals of records on days of `kind`, or `None` without a calendar.
+    pub fn for_day_kind(&self, kind: DayKind) -> Option<&Totals> {
+        self.day_kinds
+            .as_ref()
+            .map(|(_, totals)| &totals[kind as usize])
+    }
+
+    /// Returns the `n` users with the highest volume; equal volumes are ordered by user id.
+    pub fn top_users(&self, n: usize) -> Vec<UserVolume> {
+        let mut users: Vec<UserVolume> = self.users.values().copied().collect();
+        users.sort_by(|a, b| b.volume.cmp(&a.volume).then(a.user_id.cmp(&b.user_id)));
+        users.truncate(n);
+        users
+    }
+}
+
+/// Length of the tumbling windows of a [`WindowAggregator`], a whole number of minutes.
+///
+/// Parsed from `<n>m`, `<n>h` or `<n>d`, e.g. `15m` or `1h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    millis: u64,
+}
+
+impl Window {
+    pub fn minutes(minutes: u64) -> Result<Self, ParseError> {
+        match minutes.checked_mul(60_000) {
+            Some(millis) if millis > 0 => Ok(Self { millis }),
+            _ => Err(ParseError::InvalidRawValue {
+                field: "window".to_string(),
+                value: format!("{}m", minutes),
+            }),
+        }
+    }
+
+    pub fn millis(&self) -> u64 {
+        self.millis
+    }
+
+    /// Returns the start of the window holding `ts`; windows are aligned to the epoch.
+    pub fn start(&self, ts: u64) -> u64 {
+        ts - ts % self.millis
+    }
+}
+
+impl fmt::Display for Window {
+    /// Renders the window in the largest unit it is a whole number of, e.g. `90m` or `2h`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.millis / 60_000;
+        match minutes {
+            _ if minutes.is_multiple_of(1440) => write!(f, "{}d", minutes / 1440),
+            _ if minutes.is_multiple_of(60) => write!(f, "{}h", minutes / 60),
+            _ => write!(f, "{}m", minutes),
+        }
+    }
+}
+
+impl FromStr for Window {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: "window".to_string(),
+            value: s.to_string(),
+        };
+        let s = s.trim();
+        let (count, minutes) = match s.char_indices().last().ok_or_else(invalid)? {
+            (index, 'm') => (&s[..index], 1),
+            (index, 'h') => (&s[..index], 60),
+            (index, 'd') => (&s[..index], 1440),
+            _ => return Err(invalid()),
+        };
+        let count: u64 = count.parse().map_err(|_| invalid())?;
+        count
+            .checked_mul(minutes)
+            .ok_or_else(invalid)
+            .and_then(Self::minutes)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Totals of the records of one tumbling window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowSummary {
+    /// Start of the window, inclusive.
+    pub start: u64,
+    /// End of the window, exclusive.
+    pub end: u64,
+    pub totals: Totals,
+    /// Records that belonged to an earlier window, which had been emitted when they
+    /// arrived; they are not part of `totals`.
+    pub late: u64,
+}
+
+impl WindowSummary {
+    /// Renders the start of the window as `YYYY-MM-DDTHH:MM`.
+    pub fn label(&self) -> String {
+        format_utc(self.start, true)
+    }
+
+    /// Renders the start of the window like [`WindowSummary::label`], in local time of
+    /// `time_zone`.
+    #[cfg(feature = "tz")]
+    pub fn label_in(&self, time_zone: &TimeZone) -> String {
+        format_utc(local(self.start, time_zone), true)
+    }
+}
+
+/// Sums records into tumbling windows by timestamp, emitting each window once a record of
+/// a later window arrives.
+///
+/// Only the open window is held, so streams of any length are aggregated in constant
+/// memory. Records are expected roughly in timestamp order: a record of an already
+/// emitted window is counted as late in the open one. Windows without records are not
+/// emitted.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{TransactionStatus, TransactionType, Window, WindowAggregator, YPBankRecord};
+///
+/// let record = |amount, ts| {
+///     YPBankRecord::new(1, TransactionType::Deposit, 0, 2, amount, ts, TransactionStatus::Success, "")
+/// };
+///
+/// let mut aggregator = WindowAggregator::new("1m".parse::<Window>().unwrap());
+/// assert_eq!(aggregator.push(&record(100, 1_000)).unwrap(), None);
+/// assert_eq!(aggregator.push(&record(50, 59_999)).unwrap(), None);
+///
+/// let first = aggregator.push(&record(10, 60_000)).unwrap().unwrap();
+/// assert_eq!((first.start, first.totals.all.count, first.totals.all.amount), (0, 2, 150));
+/// assert_eq!(aggregator.finish().unwrap().totals.all.amount, 10);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WindowAggregator {
+    window: Window,
+    open: Option<WindowSummary>,
+    /// End of the last emitted window; records before it are late.
+    emitted_until: u64,
+}
+
+impl WindowAggregator {
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            open: None,
+            emitted_until: 0,
+        }
+    }
+
+    /// Adds a record, returning the previous window when the record starts a later one.
+    pub fn push(&mut self, record: &YPBankRecord) -> Result<Option<WindowSummary>, ParseError> {
+        let start = self.window.start(record.ts);
+        let mut open = match &mut self.open {
+            Some(open) if start == open.start => return open.totals.add(record).map(|_| None),
+            // Its window was emitted already, or passed while another one was open.
+            Some(open) if start < open.start => {
+                open.late += 1;
+                return Ok(None);
+            }
+            None if record.ts < self.emitted_until => {
+                let mut open = self.empty(self.emitted_until);
+                open.late += 1;
+                self.open = Some(open);
+                return Ok(None);
+            }
+            _ => self.empty(start),
+        };
+
+        open.totals.add(record)?;
+        let closed = self.open.replace(open);
+        if let Some(closed) = &closed {
+            self.emitted_until = closed.end;
+        }
+        Ok(closed)
+    }
+
+    /// Returns the open window, to be called when the stream ends.
+    pub fn finish(&mut self) -> Option<WindowSummary> {
+        let closed = self.open.take();
+        if let Some(closed) = &closed {
+            self.emitted_until = closed.end;
+        }
+        closed
+    }
+
+    fn empty(&self, start: u64) -> WindowSummary {
+        WindowSummary {
+            start,
+            end: start.saturating_add(self.window.millis()),
+            totals: Totals::new(),
+            late: 0,
+        }
+    }
+}
+
+/// Iterator over the [`WindowSummary`] values of a record stream, see [`windows`].
+pub struct Windows<I> {
+    records: I,
+    aggregator: WindowAggregator,
+    done: bool,
+}
+
+impl<I: Iterator<Item = Result<YPBankRecord, ParseError>>> Iterator for Windows<I> {
+    type Item = Result<WindowSummary, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        for record in self.records.by_ref() {
+            match record.and_then(|record| self.aggregator.push(&record)) {
+                Ok(Some(window)) => return Some(Ok(window)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        self.done = true;
+        self.aggregator.finish().map(Ok)
+    }
+}
+
+/// Aggregates `records` into tumbling windows, yielding each window as soon as it completes.
+///
+/// Stops after the first error.
+pub fn windows<I: IntoIterator<Item = Result<YPBankRecord, ParseError>>>(
+    records: I,
+    window: Window,
+) -> Windows<I::IntoIter> {
+    Windows {
+        records: records.into_iter(),
+        aggregator: WindowAggregator::new(window),
+        done: false,
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(from: u64, to: u64, amount: i64, ts: u64) -> YPBankRecord {
+        YPBankRecord::new(
+            1,
+            TransactionType::Transfer,
+            from,
+            to,
+            amount,
+            ts,
+            TransactionStatus::Success,
+            "",
+        )
+    }
+
+    #[test]
+    fn test_day_kinds() {
+        let calendar = BusinessCalendar::new()
+            .with_holiday("2021-10-04")
+            .expect("Should parse holiday");
+        let mut stats = Stats::new(AmountHistogram::new(vec![0]).expect("Should build"))
+            .with_calendar(calendar);
+        // Friday, Saturday and the Monday holiday of 2021-10-01 to 2021-10-04.
+        for (amount, day) in [(10, 0), (20, 0), (30, 1), (40, 3)] {
+            stats
+                .add(&record(1, 2, amount, 1633046400000 + day * 86_400_000))
+                .expect("Should add");
+        }
+
+        let totals = |kind| stats.for_day_kind(kind).expect("Should have calendar").all;
+        assert_eq!(
+            (
+                totals(DayKind::Business).count,
+                totals(DayKind::Business).amount
+            ),
+            (2, 30)
+        );
+        assert_eq!(totals(DayKind::Weekend).amount, 30);
+        assert_eq!(totals(DayKind::Holiday).amount, 40);
+        assert_eq!(
+            Stats::new(AmountHistogram::new(vec![0]).expect("Should build"))
+                .for_day_kind(DayKind::Business),
+            None
+        );
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut histogram = AmountHistogram::new(vec![-100, 0, 100]).expect("Should build");
+        for amount in [i64::MIN, -100, -1, 0, 99, 100, i64::MAX] {
+            histogram.add(amount);
+        }
+
+        assert_eq!(
+            histogram.buckets().collect::<Vec<_>>(),
+            vec![
+                (None, Some(-100), 1),
+                (Some(-100), Some(0), 2),
+                (Some(0), Some(100), 2),
+                (Some(100), None, 2),
+            ]
+        );
+        assert!(AmountHistogram::new(vec![10, 10]).is_err());
+        assert!(AmountHistogram::new(vec![]).is_err());
+    }
+
+    #[test]
+    fn test_periods_and_users() {
+        let mut stats = Stats::new(AmountHistogram::new(vec![0]).expect("Should build"));
+        for record in [
+            record(1, 2, 100, 1633036860000),
+            record(2, 3, -300, 1633040460000),
+            record(3, 1, 50, 1633125600000),
+        ] {
+            stats.add(&record).expect("Should add");
+        }
+
+        let days: Vec<_> = stats
+            .counts_per(Period::Day)
+            .iter()
+            .map(|(start, count)| (Period::Day.label(*start), *count))
+            .collect();
+        assert_eq!(
+            days,
+            vec![("2021-09-30".to_string(), 2), ("2021-10-01".to_string(), 1)]
+        );
+        assert_eq!(stats.counts_per(Period::Hour).len(), 3);
+        assert_eq!(Period::Hour.label(1633039200000), "2021-09-30T22:00");
+
+        let top = stats.top_users(2);
+        assert_eq!(
+            top.iter()
+                .map(|user| (user.user_id, user.count, user.volume))
+                .collect::<Vec<_>>(),
+            vec![(2, 2, 400), (3, 2, 350)]
+        );
+        assert_eq!(stats.totals.all.count, 3);
+    }
+
+    #[cfg(feature = "tz")]
+    #[test]
+    fn test_periods_in_time_zone() {
+        let moscow = TimeZone::from_posix("MSK-3").expect("Should parse");
+        let mut stats = Stats::new(AmountHistogram::new(vec![0]).expect("Should build"))
+            .with_time_zone(moscow.clone());
+        for record in [
+            record(1, 2, 100, 1633036860000),
+            record(2, 3, -300, 1633040460000),
+            record(3, 1, 50, 1633125600000),
+        ] {
+            stats.add(&record).expect("Should add");
+        }
+
+        let labels = |period| {
+            stats
+                .counts_per(period)
+                .iter()
+                .map(|(start, count)| (stats.label(period, *start), *count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            labels(Period::Day),
+            vec![("2021-10-01".to_string(), 2), ("2021-10-02".to_string(), 1)]
+        );
+        assert_eq!(
+            labels(Period::Hour),
+            vec![
+                ("2021-10-01T00:00".to_string(), 1),
+                ("2021-10-01T01:00".to_string(), 1),
+                ("2021-10-02T01:00".to_string(), 1)
+            ]
+        );
+
+        let summary = WindowSummary {
+            start: 1633035600000,
+            end: 1633039200000,
+            totals: Totals::new(),
+            late: 0,
+        };
+        assert_eq!(summary.label(), "2021-09-30T21:00");
+        assert_eq!(summary.label_in(&moscow), "2021-10-01T00:00");
+    }
+
+    #[test]
+    fn test_extreme_amounts() {
+        let mut stats = Stats::new(AmountHistogram::new(vec![0]).expect("Should build"));
+        for amount in [i64::MIN, i64::MIN, i64::MAX] {
+            stats.add(&record(1, 2, amount, 0)).expect("Should add");
+        }
+
+        assert_eq!(
+            stats.totals.all.amount,
+            2 * i64::MIN as i128 + i64::MAX as i128
+        );
+        assert!(stats.totals.all.amount_i64().is_err());
+        assert_eq!(
+            stats.top_users(1)[0].volume,
+            2 * (1u128 << 63) + i64::MAX as u128
+        );
+        assert_eq!(
+            stats.amounts().buckets().collect::<Vec<_>>(),
+            vec![(None, Some(0), 2), (Some(0), None, 1)]
+        );
+    }
+
+    #[test]
+    fn test_window_from_str() {
+        assert_eq!("1h".parse(), Window::minutes(60));
+        assert_eq!(
+            " 15m".parse::<Window>().map(|window| window.millis()),
+            Ok(900_000)
+        );
+        assert_eq!("2d".parse(), Window::minutes(2880));
+        assert_eq!(
+            ["90m", "120m", "24h"].map(|s| s.parse::<Window>().map(|window| window.to_string())),
+            [
+                Ok("90m".to_string()),
+                Ok("2h".to_string()),
+                Ok("1d".to_string())
+            ]
+        );
+        for invalid in ["", "h", "0m", "1s", "1.5h", "-1h", "99999999999999999d"] {
+            assert!(invalid.parse::<Window>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let window = Window::minutes(1).expect("Should build");
+        let records = [
+            record(1, 2, 100, 0),
+            record(1, 2, 50, 59_999),
+            record(1, 2, 7, 180_000),
+            // Late: its window was emitted.
+            record(1, 2, 1, 30_000),
+            record(1, 2, 20, 200_000),
+        ];
+
+        let summaries: Vec<_> = windows(records.iter().cloned().map(Ok), window)
+            .map(|summary| {
+                summary.map(|summary| {
+                    (
+                        summary.label(),
+                        summary.end - summary.start,
+                        summary.totals.all.count,
+                        summary.totals.for_type(TransactionType::Transfer).amount,
+                        summary.late,
+                    )
+                })
+            })
+            .collect();
+        assert_eq!(
+            summaries,
+            vec![
+                Ok(("1970-01-01T00:00".to_string(), 60_000, 2, 150, 0)),
+                Ok(("1970-01-01T00:03".to_string(), 60_000, 2, 27, 1)),
+            ]
+        );
+
+        let failing = vec![
+            Ok(record(1, 2, 100, 0)),
+            Err(ParseError::UnexpectedEOF),
+            Ok(record(1, 2, 100, 60_000)),
+        ];
+        let summaries: Vec<_> = windows(failing, window).collect();
+        assert_eq!(summaries, vec![Err(ParseError::UnexpectedEOF)]);
+    }
+
+    #[test]
+    fn test_late_record_without_open_window() {
+        let mut aggregator = WindowAggregator::new(Window::minutes(1).expect("Should build"));
+        aggregator.push(&record(1, 2, 5, 0)).expect("Should add");
+        assert_eq!(
+            aggregator.finish().map(|summary| summary.totals.all.amount),
+            Some(5)
+        );
+
+        aggregator.push(&record(1, 2, 5, 10)).expect("Should add");
+        let summary = aggregator.finish().expect("Should hold a window");
+        assert_eq!(
+            (summary.start, summary.totals.all.count, summary.late),
+            (60_000, 0, 1)
+        );
+    }
+}
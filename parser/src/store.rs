@@ -0,0 +1,1087 @@
+use crate::atomic;
+use crate::bin_format::encode_bin_record;
+use crate::common::{Format, read_u64_from_bytes};
+use crate::digest::Sha256;
+use crate::erasure::{ErasureReport, UserErasure};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use crate::retention::{PruneLog, Retention};
+use crate::tombstone::Tombstones;
+use crate::{CommonParser, RecordStream};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const MANIFEST: &str = "MANIFEST";
+const MANIFEST_HEADER: &str = "YPBank record store 1";
+const SOURCE_PREFIX: &str = "source ";
+/// Records sorted in memory at a time while ingesting; each chunk becomes a segment.
+const SEGMENT_RECORDS: usize = 1 << 20;
+/// An index entry: a big-endian key and the offset of its record in the segment.
+const INDEX_ENTRY_SIZE: u64 = 16;
+
+/// An embedded store of records on disk, indexed by id and timestamp.
+///
+/// Every ingest adds immutable segments: binary files of records sorted by id, with an
+/// index of their ids and one of their timestamps. A record replaces the record with the
+/// same id of earlier ingests; within an ingest, the last record of an id is kept.
+/// [`RecordStore::compact`] merges all segments into one, dropping replaced records.
+///
+/// Source files are applied with [`RecordStore::apply_snapshot`], which replaces all
+/// records, and [`RecordStore::apply_increment`], which upserts records and deletes the
+/// ids of tombstones. Applied sources are recorded with the digest of their content, so
+/// applying a file again does nothing, see [`RecordStore::sources`].
+///
+/// The list of segments is replaced atomically, so an ingest or compaction that fails
+/// or is interrupted leaves the store as it was.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{Format, RecordStore, fixtures};
+/// # let dir = std::env::temp_dir().join(format!("store-doc-{}", std::process::id()));
+///
+/// let mut store = RecordStore::create(&dir).unwrap();
+/// store.ingest(fixtures::records().into_iter().map(Ok)).unwrap();
+///
+/// let record = store.get(1000000000000000).unwrap();
+/// let october = store.range_ts(1633036800000, 1635724799999).unwrap();
+/// let mut csv = Vec::new();
+/// store.export(&mut csv, Format::Csv).unwrap();
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub struct RecordStore {
+    dir: PathBuf,
+    segments: Vec<Segment>,
+    sources: Vec<AppliedSource>,
+    next_segment: u64,
+}
+
+/// A source file applied to a [`RecordStore`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AppliedSource {
+    /// File name of the source, without its directory.
+    pub name: String,
+    /// SHA-256 of the content of the file and the ids of its tombstones.
+    pub sha256: String,
+    /// Whether the source was applied as a snapshot rather than an increment.
+    pub snapshot: bool,
+    /// Records read from the file.
+    pub records: u64,
+    /// Ids deleted by the tombstones of the file.
+    pub deleted: u64,
+}
+
+/// A segment of a store, with the ids its ingest deleted from earlier segments.
+#[derive(Debug, Clone)]
+struct Segment {
+    name: String,
+    /// Sorted.
+    deleted: Arc<[u64]>,
+}
+
+impl RecordStore {
+    /// Creates an empty store in `dir`, creating the directory if needed.
+    pub fn create(dir: impl AsRef<Path>) -> Result<Self, ParseError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        if dir.join(MANIFEST).exists() {
+            return Err(ParseError::IOError(format!(
+                "{} already holds a record store",
+                dir.display()
+            )));
+        }
+
+        let store = Self {
+            dir,
+            segments: vec![],
+            sources: vec![],
+            next_segment: 0,
+        };
+        store.save_manifest(&store.segments, &store.sources)?;
+        Ok(store)
+    }
+
+    /// Returns whether `dir` holds a record store.
+    pub fn exists(dir: impl AsRef<Path>) -> bool {
+        dir.as_ref().join(MANIFEST).is_file()
+    }
+
+    /// Opens the store in `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, ParseError> {
+        let dir = dir.as_ref().to_path_buf();
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST))?;
+        let mut lines = manifest.lines();
+        let header = lines.next().unwrap_or_default();
+        if header != MANIFEST_HEADER {
+            return Err(ParseError::InvalidMagic(header.to_string()));
+        }
+
+        let mut segments = vec![];
+        let mut sources = vec![];
+        let mut next_segment = 0;
+        for line in lines {
+            if let Some(source) = line.strip_prefix(SOURCE_PREFIX) {
+                sources.push(AppliedSource::parse(source)?);
+                continue;
+            }
+            let number = line
+                .strip_prefix("segment-")
+                .and_then(|number| number.parse::<u64>().ok())
+                .ok_or_else(|| {
+                    ParseError::InconsistentRecord(format!("invalid manifest line {}", line))
+                })?;
+            next_segment = next_segment.max(number + 1);
+            let deleted = match std::fs::read(dir.join(format!("{}.del", line))) {
+                Ok(data) => data
+                    .chunks_exact(8)
+                    .map(|id| u64::from_be_bytes(id.try_into().expect("8 bytes")))
+                    .collect(),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Arc::from([]),
+                Err(err) => return Err(err.into()),
+            };
+            segments.push(Segment {
+                name: line.to_string(),
+                deleted,
+            });
+        }
+        Ok(Self {
+            dir,
+            segments,
+            sources,
+            next_segment,
+        })
+    }
+
+    /// Adds `records`, returning how many were read.
+    ///
+    /// Records are sorted in chunks of about a million, so memory stays bounded for inputs
+    /// of any size. Records that are not valid, see [`YPBankRecord::validate`], fail the
+    /// ingest with [`ParseError::InvalidRecord`].
+    pub fn ingest(
+        &mut self,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+    ) -> Result<u64, ParseError> {
+        self.commit(records, &Tombstones::default(), false, None)
+    }
+
+    /// Adds the records of the file at `path`, see [`RecordStore::ingest`].
+    pub fn ingest_file(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: Format,
+    ) -> Result<u64, ParseError> {
+        let records = CommonParser::new(format).iter_read(File::open(path)?)?;
+        self.ingest(records)
+    }
+
+    /// Replaces all records with those of the snapshot file at `path`, unless the file
+    /// was applied before. Returns whether it was applied now.
+    ///
+    /// A file applied before under the same name with different content fails with
+    /// [`ParseError::InconsistentRecord`].
+    pub fn apply_snapshot(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: Format,
+    ) -> Result<bool, ParseError> {
+        self.apply(path.as_ref(), format, &Tombstones::default(), true)
+    }
+
+    /// Upserts the records of the incremental file at `path` and deletes the ids of
+    /// `tombstones`, unless the file was applied before with the same tombstones. Returns
+    /// whether it was applied now.
+    ///
+    /// Tombstones also delete records of the file itself. A file applied before under the
+    /// same name with different content or tombstones fails with
+    /// [`ParseError::InconsistentRecord`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{Format, RecordStore, Tombstones};
+    /// # parser::fixtures::enter().unwrap();
+    /// # let dir = std::env::temp_dir().join(format!("store-increment-doc-{}", std::process::id()));
+    ///
+    /// let mut store = RecordStore::create(&dir).unwrap();
+    /// let tombstones = Tombstones::new([1000000000000001]);
+    /// assert!(store.apply_increment("records.bin", Format::Bin, &tombstones).unwrap());
+    /// assert!(!store.apply_increment("records.bin", Format::Bin, &tombstones).unwrap());
+    /// assert_eq!(store.get(1000000000000001).unwrap(), None);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn apply_increment(
+        &mut self,
+        path: impl AsRef<Path>,
+        format: Format,
+        tombstones: &Tombstones,
+    ) -> Result<bool, ParseError> {
+        self.apply(path.as_ref(), format, tombstones, false)
+    }
+
+    /// Returns the applied source files, in the order they were applied.
+    pub fn sources(&self) -> &[AppliedSource] {
+        &self.sources
+    }
+
+    fn apply(
+        &mut self,
+        path: &Path,
+        format: Format,
+        tombstones: &Tombstones,
+        snapshot: bool,
+    ) -> Result<bool, ParseError> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| ParseError::IOError(format!("{} is not a file", path.display())))?
+            .to_string_lossy()
+            .to_string();
+        let mut digest = Sha256::new();
+        digest.update_from_read(&mut File::open(path)?)?;
+        let mut ids: Vec<u64> = tombstones.ids().collect();
+        ids.sort_unstable();
+        for id in ids {
+            digest.update(&id.to_be_bytes());
+        }
+        let sha256 = digest.finish_hex();
+
+        if let Some(applied) = self.sources.iter().find(|source| source.name == name) {
+            if applied.sha256 == sha256 && applied.snapshot == snapshot {
+                return Ok(false);
+            }
+            return Err(ParseError::InconsistentRecord(format!(
+                "{} was applied before with different content",
+                name
+            )));
+        }
+
+        let source = AppliedSource {
+            name,
+            sha256,
+            snapshot,
+            records: 0,
+            deleted: tombstones.len() as u64,
+        };
+        let records = CommonParser::new(format).iter_read(File::open(path)?)?;
+        self.commit(records, tombstones, snapshot, Some(source))?;
+        Ok(true)
+    }
+
+    /// Writes the segments of an ingest and adds them to the manifest, together with
+    /// `source`. A snapshot replaces all earlier segments.
+    fn commit(
+        &mut self,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        tombstones: &Tombstones,
+        snapshot: bool,
+        source: Option<AppliedSource>,
+    ) -> Result<u64, ParseError> {
+        let mut written = vec![];
+        let result = self
+            .write_segments(records, tombstones, &mut written)
+            .and_then(|count| {
+                let mut segments = match snapshot {
+                    true => vec![],
+                    false => self.segments.clone(),
+                };
+                segments.extend(written.iter().cloned());
+                let mut sources = self.sources.clone();
+                sources.extend(source.map(|source| AppliedSource {
+                    records: count,
+                    ..source
+                }));
+                self.save_manifest(&segments, &sources)?;
+                self.sources = sources;
+                for replaced in std::mem::replace(&mut self.segments, segments) {
+                    if snapshot {
+                        self.remove_segment(&replaced.name);
+                    }
+                }
+                Ok(count)
+            });
+        if result.is_err() {
+            for segment in &written {
+                self.remove_segment(&segment.name);
+            }
+        }
+        result
+    }
+
+    /// Writes `records` without the ids of `tombstones` to new segments, the last of which
+    /// deletes those ids from earlier segments.
+    fn write_segments(
+        &mut self,
+        records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+        tombstones: &Tombstones,
+        written: &mut Vec<Segment>,
+    ) -> Result<u64, ParseError> {
+        let mut count = 0;
+        let mut chunk = Vec::with_capacity(SEGMENT_RECORDS.min(1024));
+        let mut records = records.into_iter();
+        loop {
+            let record = records.next().transpose()?;
+            let done = record.is_none();
+            if let Some(record) = record {
+                if let Err(err) = record.validate() {
+                    return Err(ParseError::InvalidRecord {
+                        index: count,
+                        reason: err.to_string(),
+                    });
+                }
+                count += 1;
+                if !tombstones.contains(record.id) {
+                    chunk.push(record);
+                }
+                if chunk.len() < SEGMENT_RECORDS {
+                    continue;
+                }
+            }
+            if chunk.is_empty() {
+                break;
+            }
+
+            // The sort is stable, so the last record of an id is the latest one.
+            chunk.sort_by_key(|record| record.id);
+            let mut latest: Vec<YPBankRecord> = Vec::with_capacity(chunk.len());
+            for record in chunk.drain(..) {
+                match latest.last_mut() {
+                    Some(last) if last.id == record.id => *last = record,
+                    _ => latest.push(record),
+                }
+            }
+            let name = self.new_segment_name();
+            written.push(Segment {
+                name: name.clone(),
+                deleted: Arc::from([]),
+            });
+            write_segment(&self.dir, &name, latest.into_iter().map(Ok))?;
+
+            if done {
+                break;
+            }
+        }
+
+        if !tombstones.is_empty() {
+            if written.is_empty() {
+                let name = self.new_segment_name();
+                written.push(Segment {
+                    name: name.clone(),
+                    deleted: Arc::from([]),
+                });
+                write_segment(&self.dir, &name, std::iter::empty())?;
+            }
+            let mut deleted: Vec<u64> = tombstones.ids().collect();
+            deleted.sort_unstable();
+            let last = written.last_mut().expect("written above");
+            let mut w = BufWriter::new(File::create(self.path(&last.name, "del"))?);
+            for id in &deleted {
+                w.write_all(&id.to_be_bytes())?;
+            }
+            w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+            last.deleted = deleted.into();
+        }
+        Ok(count)
+    }
+
+    /// Returns the record with `id`.
+    pub fn get(&self, id: u64) -> Result<Option<YPBankRecord>, ParseError> {
+        for segment in self.segments.iter().rev() {
+            let mut index = File::open(self.path(&segment.name, "id"))?;
+            if let Some(offset) = find(&mut index, id)? {
+                return self.read_at(&segment.name, offset).map(Some);
+            }
+            if segment.deleted.binary_search(&id).is_ok() {
+                return Ok(None);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the records with `from <= id <= to`, in id order.
+    pub fn range_id(&self, from: u64, to: u64) -> Result<Vec<YPBankRecord>, ParseError> {
+        self.merged(from, to)?.collect()
+    }
+
+    /// Returns the records with `from <= ts <= to`, in timestamp order.
+    pub fn range_ts(&self, from: u64, to: u64) -> Result<Vec<YPBankRecord>, ParseError> {
+        let mut records = vec![];
+        for (age, segment) in self.segments.iter().enumerate() {
+            let mut index = File::open(self.path(&segment.name, "ts"))?;
+            let entries = index.metadata()?.len() / INDEX_ENTRY_SIZE;
+            let start = lower_bound(&mut index, entries, from)?;
+            index.seek(SeekFrom::Start(start * INDEX_ENTRY_SIZE))?;
+            let mut index = BufReader::new(index);
+            for _ in start..entries {
+                let ts = read_u64_from_bytes(&mut index)?;
+                let offset = read_u64_from_bytes(&mut index)?;
+                if ts > to {
+                    break;
+                }
+                let record = self.read_at(&segment.name, offset)?;
+                if !self.replaced(age, record.id)? {
+                    records.push(record);
+                }
+            }
+        }
+        records.sort_by_key(|record| (record.ts, record.id));
+        Ok(records)
+    }
+
+    /// Merges all segments into one, dropping records replaced or deleted by later
+    /// ingests.
+    pub fn compact(&mut self) -> Result<(), ParseError> {
+        if self.segments.len() < 2 && self.segments.iter().all(|s| s.deleted.is_empty()) {
+            return Ok(());
+        }
+        self.rewrite(Some)
+    }
+
+    /// Removes the records expired under `retention`, logging them to `log`, and returns
+    /// how many were removed.
+    ///
+    /// The remaining records are merged into one segment as by [`RecordStore::compact`],
+    /// so removed records are gone from disk rather than hidden. The store is left
+    /// untouched when no record has expired.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parser::{PruneLog, RecordStore, Retention, fixtures};
+    /// # let dir = std::env::temp_dir().join(format!("store-prune-doc-{}", std::process::id()));
+    ///
+    /// let mut store = RecordStore::create(&dir).unwrap();
+    /// store.ingest(fixtures::records().into_iter().map(Ok)).unwrap();
+    ///
+    /// // Records before 2021-10-01T00:05:00Z.
+    /// let mut log = PruneLog::new(Vec::new());
+    /// assert_eq!(store.prune(&Retention::before(1633046700000), &mut log).unwrap(), 4);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn prune<W: Write>(
+        &mut self,
+        retention: &Retention,
+        log: &mut PruneLog<W>,
+    ) -> Result<u64, ParseError> {
+        let expired: Vec<(u64, u64)> = match retention.cutoff() {
+            0 => vec![],
+            cutoff => self
+                .range_ts(0, cutoff - 1)?
+                .iter()
+                .map(|record| (record.id, record.ts))
+                .collect(),
+        };
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        self.rewrite(|record| (!retention.expired(record.ts)).then_some(record))?;
+        log.log(&self.dir, retention, &expired)?;
+        Ok(expired.len() as u64)
+    }
+
+    /// Erases the records of a user, adding them to `report`, and returns how many were
+    /// affected.
+    ///
+    /// The records are rewritten into one segment as by [`RecordStore::compact`], so
+    /// earlier versions of them are gone from disk as well. The store is left untouched
+    /// when the user has no records. Source files applied to the store keep their
+    /// content; erase the user from them with [`crate::erase_user_in_file`].
+    pub fn erase_user(
+        &mut self,
+        erasure: &UserErasure,
+        report: &mut ErasureReport,
+    ) -> Result<u64, ParseError> {
+        let mut affected = vec![];
+        for record in self.merged(0, u64::MAX)? {
+            let record = record?;
+            if erasure.touches(&record) {
+                affected.push(record.id);
+            }
+        }
+        if affected.is_empty() {
+            return Ok(0);
+        }
+
+        self.rewrite(|record| erasure.apply(record))?;
+        let count = affected.len() as u64;
+        report.add(&self.dir, affected);
+        Ok(count)
+    }
+
+    /// Replaces all segments with one holding the current records as returned by `f`,
+    /// which drops those it returns `None` for and must keep ids unchanged.
+    fn rewrite(
+        &mut self,
+        mut f: impl FnMut(YPBankRecord) -> Option<YPBankRecord>,
+    ) -> Result<(), ParseError> {
+        let segment = Segment {
+            name: self.new_segment_name(),
+            deleted: Arc::from([]),
+        };
+        let records = self.merged(0, u64::MAX)?.filter_map(|record| match record {
+            Ok(record) => f(record).map(Ok),
+            Err(err) => Some(Err(err)),
+        });
+        let result = write_segment(&self.dir, &segment.name, records)
+            .and_then(|_| self.save_manifest(std::slice::from_ref(&segment), &self.sources));
+        if let Err(err) = result {
+            self.remove_segment(&segment.name);
+            return Err(err);
+        }
+
+        for segment in std::mem::replace(&mut self.segments, vec![segment]) {
+            self.remove_segment(&segment.name);
+        }
+        Ok(())
+    }
+
+    /// Writes all records in id order to `w` in `format`.
+    pub fn export<W: Write>(&self, w: &mut W, format: Format) -> Result<(), ParseError> {
+        CommonParser::new(format).write_iter(w, self.merged(0, u64::MAX)?)
+    }
+
+    /// Returns the number of segments, which [`RecordStore::compact`] brings down to one.
+    pub fn segments(&self) -> usize {
+        self.segments.len()
+    }
+
+    fn path(&self, segment: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", segment, extension))
+    }
+
+    fn new_segment_name(&mut self) -> String {
+        self.next_segment += 1;
+        format!("segment-{:06}", self.next_segment - 1)
+    }
+
+    fn remove_segment(&self, segment: &str) {
+        for extension in ["bin", "id", "ts", "del"] {
+            let _ = std::fs::remove_file(self.path(segment, extension));
+        }
+    }
+
+    fn save_manifest(
+        &self,
+        segments: &[Segment],
+        sources: &[AppliedSource],
+    ) -> Result<(), ParseError> {
+        atomic::write_atomic(&self.dir.join(MANIFEST), None, |w| {
+            writeln!(w, "{}", MANIFEST_HEADER)?;
+            for segment in segments {
+                writeln!(w, "{}", segment.name)?;
+            }
+            for source in sources {
+                writeln!(w, "{}{}", SOURCE_PREFIX, source)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn read_at(&self, segment: &str, offset: u64) -> Result<YPBankRecord, ParseError> {
+        let file = File::open(self.path(segment, "bin"))?;
+        CommonParser::new(Format::Bin)
+            .iter_read_at(file, offset)?
+            .next()
+            .unwrap_or(Err(ParseError::UnexpectedEOF))
+    }
+
+    /// Returns whether a segment newer than the one at `age` holds or deletes `id`.
+    fn replaced(&self, age: usize, id: u64) -> Result<bool, ParseError> {
+        for segment in &self.segments[age + 1..] {
+            if segment.deleted.binary_search(&id).is_ok()
+                || find(&mut File::open(self.path(&segment.name, "id"))?, id)?.is_some()
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the records with `from <= id <= to` of all segments in id order, taking
+    /// every id from the newest segment holding it unless a newer one deletes it.
+    fn merged(&self, from: u64, to: u64) -> Result<Merged<'static>, ParseError> {
+        let mut merged = Merged {
+            streams: vec![],
+            heads: vec![],
+            heap: BinaryHeap::new(),
+            deleted: self
+                .segments
+                .iter()
+                .map(|segment| segment.deleted.clone())
+                .collect(),
+            to,
+            pending_error: None,
+        };
+        for segment in &self.segments {
+            let mut index = File::open(self.path(&segment.name, "id"))?;
+            let entries = index.metadata()?.len() / INDEX_ENTRY_SIZE;
+            let start = lower_bound(&mut index, entries, from)?;
+            let offset = match start < entries {
+                true => read_entry(&mut index, start)?.1,
+                false => File::open(self.path(&segment.name, "bin"))?
+                    .metadata()?
+                    .len(),
+            };
+            let file = File::open(self.path(&segment.name, "bin"))?;
+            merged
+                .streams
+                .push(CommonParser::new(Format::Bin).iter_read_at(file, offset)?);
+            merged.heads.push(None);
+        }
+        for age in 0..merged.streams.len() {
+            merged.advance(age)?;
+        }
+        Ok(merged)
+    }
+}
+
+impl AppliedSource {
+    /// Parses a manifest line written by the `Display` implementation.
+    fn parse(line: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InconsistentRecord(format!("invalid source {}", line));
+        let mut fields = line.splitn(5, ' ');
+        let mut field = || fields.next().ok_or_else(invalid);
+        let snapshot = match field()? {
+            "snapshot" => true,
+            "increment" => false,
+            _ => return Err(invalid()),
+        };
+        let sha256 = field()?.to_string();
+        let records = field()?.parse().map_err(|_| invalid())?;
+        let deleted = field()?.parse().map_err(|_| invalid())?;
+        let name = field()?.to_string();
+        Ok(Self {
+            name,
+            sha256,
+            snapshot,
+            records,
+            deleted,
+        })
+    }
+}
+
+impl std::fmt::Display for AppliedSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            match self.snapshot {
+                true => "snapshot",
+                false => "increment",
+            },
+            self.sha256,
+            self.records,
+            self.deleted,
+            self.name
+        )
+    }
+}
+
+/// Writes a segment of records sorted by id with unique ids, with its indexes.
+fn write_segment(
+    dir: &Path,
+    name: &str,
+    records: impl IntoIterator<Item = Result<YPBankRecord, ParseError>>,
+) -> Result<(), ParseError> {
+    let mut data = BufWriter::new(File::create(dir.join(format!("{}.bin", name)))?);
+    let mut ids = BufWriter::new(File::create(dir.join(format!("{}.id", name)))?);
+    let mut timestamps = vec![];
+    let mut offset = 0u64;
+    let mut buf = vec![];
+    for record in records {
+        let record = record?;
+        buf.clear();
+        encode_bin_record(&record, &mut buf);
+        data.write_all(&buf)?;
+        ids.write_all(&record.id.to_be_bytes())?;
+        ids.write_all(&offset.to_be_bytes())?;
+        timestamps.push((record.ts, offset));
+        offset += buf.len() as u64;
+    }
+
+    timestamps.sort_unstable();
+    let mut ts = BufWriter::new(File::create(dir.join(format!("{}.ts", name)))?);
+    for (timestamp, offset) in timestamps {
+        ts.write_all(&timestamp.to_be_bytes())?;
+        ts.write_all(&offset.to_be_bytes())?;
+    }
+
+    for w in [data, ids, ts] {
+        w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    }
+    Ok(())
+}
+
+fn read_entry(index: &mut File, position: u64) -> Result<(u64, u64), ParseError> {
+    index.seek(SeekFrom::Start(position * INDEX_ENTRY_SIZE))?;
+    let mut entry = [0; INDEX_ENTRY_SIZE as usize];
+    index.read_exact(&mut entry)?;
+    let (key, offset) = entry.split_at(8);
+    Ok((
+        u64::from_be_bytes(key.try_into().expect("8 bytes")),
+        u64::from_be_bytes(offset.try_into().expect("8 bytes")),
+    ))
+}
+
+/// Returns the position of the first entry with a key of at least `key`.
+fn lower_bound(index: &mut File, entries: u64, key: u64) -> Result<u64, ParseError> {
+    let (mut low, mut high) = (0, entries);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if read_entry(index, middle)?.0 < key {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(low)
+}
+
+/// Returns the offset of the record with `id` in an id index.
+fn find(index: &mut File, id: u64) -> Result<Option<u64>, ParseError> {
+    let entries = index.metadata()?.len() / INDEX_ENTRY_SIZE;
+    let position = lower_bound(index, entries, id)?;
+    if position == entries {
+        return Ok(None);
+    }
+    let (key, offset) = read_entry(index, position)?;
+    Ok((key == id).then_some(offset))
+}
+
+/// Segments read in id order up to `to`, merged.
+struct Merged<'a> {
+    streams: Vec<RecordStream<'a>>,
+    heads: Vec<Option<YPBankRecord>>,
+    /// Ids and ages of the heads; of equal ids, the newest segment comes first.
+    heap: BinaryHeap<Reverse<(u64, Reverse<usize>)>>,
+    /// Ids deleted by each segment.
+    deleted: Vec<Arc<[u64]>>,
+    to: u64,
+    pending_error: Option<ParseError>,
+}
+
+impl Merged<'_> {
+    /// Pulls the next record of the segment at `age` into the heap.
+    fn advance(&mut self, age: usize) -> Result<(), ParseError> {
+        let Some(record) = self.streams[age].next().transpose()? else {
+            return Ok(());
+        };
+        if record.id <= self.to {
+            self.heap.push(Reverse((record.id, Reverse(age))));
+            self.heads[age] = Some(record);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Merged<'_> {
+    type Item = Result<YPBankRecord, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(err) = self.pending_error.take() {
+                self.heap.clear();
+                return Some(Err(err));
+            }
+
+            let Reverse((id, Reverse(age))) = self.heap.pop()?;
+            let record = self.heads[age].take().expect("heap entries have a head");
+            let mut result = self.advance(age);
+            while let Some(Reverse((next_id, Reverse(older)))) = self.heap.peek().copied()
+                && next_id == id
+            {
+                self.heap.pop();
+                self.heads[older] = None;
+                result = result.and(self.advance(older));
+            }
+            if let Err(err) = result {
+                self.pending_error = Some(err);
+            }
+            let deleted = self.deleted[age + 1..]
+                .iter()
+                .any(|deleted| deleted.binary_search(&id).is_ok());
+            if !deleted {
+                return Some(Ok(record));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod record_store_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+    use crate::erasure::ErasureMode;
+
+    fn record(id: u64, ts: u64, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            id,
+            TransactionType::Transfer,
+            1,
+            2,
+            amount,
+            ts,
+            TransactionStatus::Success,
+            format!("Record {}", id),
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ypbank-store-{}-{}", name, std::process::id()))
+    }
+
+    fn ids(records: &[YPBankRecord]) -> Vec<(u64, i64)> {
+        records
+            .iter()
+            .map(|record| (record.id, record.amount))
+            .collect()
+    }
+
+    #[test]
+    fn test_lookups_across_segments() {
+        let dir = temp_dir("lookups");
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        let first: Vec<_> = (1..=10).map(|id| record(id, 100 - id, 1)).collect();
+        let second = vec![record(3, 5, 2), record(12, 50, 2), record(3, 7, 3)];
+        assert_eq!(store.ingest(first.into_iter().map(Ok)), Ok(10));
+        assert_eq!(store.ingest(second.into_iter().map(Ok)), Ok(3));
+
+        let store = RecordStore::open(&dir).expect("Should open store");
+        let get = store.get(3);
+        let missing = store.get(11);
+        let by_id = store.range_id(2, 12);
+        let by_ts = store.range_ts(7, 97);
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+
+        assert_eq!(get, Ok(Some(record(3, 7, 3))));
+        assert_eq!(missing, Ok(None));
+        assert_eq!(
+            ids(&by_id.expect("Should range by id")),
+            vec![
+                (2, 1),
+                (3, 3),
+                (4, 1),
+                (5, 1),
+                (6, 1),
+                (7, 1),
+                (8, 1),
+                (9, 1),
+                (10, 1),
+                (12, 2)
+            ]
+        );
+        assert_eq!(
+            ids(&by_ts.expect("Should range by ts")),
+            vec![
+                (3, 3),
+                (12, 2),
+                (10, 1),
+                (9, 1),
+                (8, 1),
+                (7, 1),
+                (6, 1),
+                (5, 1),
+                (4, 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compact_and_export() {
+        let dir = temp_dir("compact");
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        store
+            .ingest([record(1, 10, 1), record(2, 20, 1)].map(Ok))
+            .expect("Should ingest");
+        store
+            .ingest([record(2, 30, 2), record(3, 40, 2)].map(Ok))
+            .expect("Should ingest");
+        let mut before = vec![];
+        store
+            .export(&mut before, Format::Csv)
+            .expect("Should export");
+
+        let compacted = store.compact();
+        let segments = store.segments();
+        let mut after = vec![];
+        store
+            .export(&mut after, Format::Csv)
+            .expect("Should export");
+        let files = std::fs::read_dir(&dir).expect("Should list store").count();
+        let reopened = RecordStore::open(&dir).and_then(|store| store.range_id(0, u64::MAX));
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+
+        assert_eq!(compacted, Ok(()));
+        assert_eq!(segments, 1);
+        assert_eq!(before, after);
+        // The manifest and the data and indexes of one segment.
+        assert_eq!(files, 4);
+        assert_eq!(
+            ids(&reopened.expect("Should reopen")),
+            vec![(1, 1), (2, 2), (3, 2)]
+        );
+    }
+
+    #[test]
+    fn test_failed_ingest_leaves_store_unchanged() {
+        let dir = temp_dir("failed");
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        store
+            .ingest([record(1, 10, 1)].map(Ok))
+            .expect("Should ingest");
+        let mut invalid = record(2, 20, 1);
+        invalid.to_user_id = 0;
+
+        let failed = store.ingest([record(3, 30, 1), invalid].map(Ok));
+        let read_error = store.ingest([Ok(record(4, 40, 1)), Err(ParseError::UnexpectedEOF)]);
+        let recreated = RecordStore::create(&dir);
+        let files = std::fs::read_dir(&dir).expect("Should list store").count();
+        let records = store.range_id(0, u64::MAX);
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+
+        assert!(matches!(
+            failed,
+            Err(ParseError::InvalidRecord { index: 1, .. })
+        ));
+        assert_eq!(read_error, Err(ParseError::UnexpectedEOF));
+        assert!(recreated.is_err());
+        assert_eq!(files, 4);
+        assert_eq!(ids(&records.expect("Should read")), vec![(1, 1)]);
+    }
+
+    #[test]
+    fn test_apply_snapshot_and_increments() {
+        let dir = temp_dir("increments");
+        let sources = temp_dir("increments-sources");
+        std::fs::create_dir_all(&sources).expect("Should create sources");
+        let write = |name: &str, records: &[YPBankRecord]| {
+            let path = sources.join(name);
+            CommonParser::new(Format::Csv)
+                .write_to(
+                    &mut File::create(&path).expect("Should create source"),
+                    &records.to_vec(),
+                )
+                .expect("Should write source");
+            path
+        };
+        let snapshot = write("snapshot.csv", &[record(1, 10, 1), record(2, 20, 1)]);
+        let day1 = write("day1.csv", &[record(2, 30, 2), record(3, 40, 2)]);
+        let day2 = write("day2.csv", &[record(4, 50, 3)]);
+        let resnapshot = write("snapshot-2.csv", &[record(5, 60, 4)]);
+
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        let applied = [
+            store.apply_snapshot(&snapshot, Format::Csv),
+            store.apply_increment(&day1, Format::Csv, &Tombstones::default()),
+            store.apply_increment(&day2, Format::Csv, &Tombstones::new([1, 4])),
+            store.apply_increment(&day1, Format::Csv, &Tombstones::default()),
+        ];
+        let changed = store.apply_increment(&day1, Format::Csv, &Tombstones::new([2]));
+        let reopened = RecordStore::open(&dir).expect("Should reopen");
+        let records = reopened.range_id(0, u64::MAX);
+        let (deleted, by_ts) = (reopened.get(1), reopened.range_ts(0, 100));
+        let mut store = reopened;
+        let compacted = store.compact().and_then(|_| store.range_id(0, u64::MAX));
+        let replaced = store
+            .apply_snapshot(&resnapshot, Format::Csv)
+            .and_then(|_| store.range_id(0, u64::MAX));
+        let sources_applied = store.sources().to_vec();
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+        std::fs::remove_dir_all(&sources).expect("Should remove sources");
+
+        assert_eq!(applied, [Ok(true), Ok(true), Ok(true), Ok(false)]);
+        assert!(matches!(changed, Err(ParseError::InconsistentRecord(_))));
+        assert_eq!(ids(&records.expect("Should read")), vec![(2, 2), (3, 2)]);
+        assert_eq!(deleted, Ok(None));
+        assert_eq!(ids(&by_ts.expect("Should read")), vec![(2, 2), (3, 2)]);
+        assert_eq!(
+            ids(&compacted.expect("Should compact")),
+            vec![(2, 2), (3, 2)]
+        );
+        assert_eq!(ids(&replaced.expect("Should replace")), vec![(5, 4)]);
+        assert_eq!(
+            sources_applied
+                .iter()
+                .map(|source| (
+                    source.name.as_str(),
+                    source.snapshot,
+                    source.records,
+                    source.deleted
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                ("snapshot.csv", true, 2, 0),
+                ("day1.csv", false, 2, 0),
+                ("day2.csv", false, 1, 2),
+                ("snapshot-2.csv", true, 1, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_prune_removes_expired_records() {
+        let dir = temp_dir("prune");
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        store
+            .ingest([record(1, 10, 1), record(2, 20, 1), record(3, 30, 1)].map(Ok))
+            .expect("Should ingest");
+        // A newer version of record 3 that has not expired replaces the expired one.
+        store
+            .ingest([record(3, 40, 2), record(4, 5, 2)].map(Ok))
+            .expect("Should ingest");
+
+        let mut log = PruneLog::new(Vec::new());
+        let pruned = store.prune(&Retention::before(25), &mut log);
+        let unchanged = store.prune(&Retention::before(25), &mut log);
+        let segments = store.segments();
+        let records = RecordStore::open(&dir).and_then(|store| store.range_id(0, u64::MAX));
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+
+        assert_eq!((pruned, unchanged), (Ok(3), Ok(0)));
+        assert_eq!(segments, 1);
+        assert_eq!(ids(&records.expect("Should read")), vec![(3, 2)]);
+        assert_eq!(
+            String::from_utf8(log.into_inner()).expect("Should be UTF-8"),
+            format!("4 5 25 {0}\n1 10 25 {0}\n2 20 25 {0}\n", dir.display())
+        );
+    }
+
+    #[test]
+    fn test_erase_user() {
+        let dir = temp_dir("erase");
+        let mut store = RecordStore::create(&dir).expect("Should create store");
+        let mut other = record(2, 20, 1);
+        (other.from_user_id, other.to_user_id) = (3, 4);
+        store
+            .ingest([record(1, 10, 1), other.clone()].map(Ok))
+            .expect("Should ingest");
+        store
+            .ingest([record(3, 30, 2)].map(Ok))
+            .expect("Should ingest");
+
+        let erasure = UserErasure::new(1, ErasureMode::Remove).expect("Should be a user");
+        let mut report = ErasureReport::new(&erasure);
+        let erased = store.erase_user(&erasure, &mut report);
+        let records = store.range_id(0, u64::MAX);
+        let segment_data = std::fs::read_dir(&dir)
+            .expect("Should list store")
+            .map(|entry| std::fs::read(entry.expect("Should list store").path()))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Should read store");
+        std::fs::remove_dir_all(&dir).expect("Should remove store");
+
+        assert_eq!(erased, Ok(2));
+        assert_eq!(records, Ok(vec![other]));
+        let source = dir.display().to_string();
+        assert_eq!(report.records(), [(1, source.clone()), (3, source)]);
+        // No segment keeps the descriptions of the erased records.
+        assert!(segment_data.iter().all(|data| {
+            !data
+                .windows("Record 1".len())
+                .any(|window| window == b"Record 1")
+        }));
+    }
+}
@@ -0,0 +1,81 @@
+//! Canonical records and the golden files they are written to in every readable format.
+//!
+//! The golden files live in the `testdata/` directory of the crate, so implementations
+//! in other languages can check their output against the same bytes.
+
+use crate::common::{Format, TransactionStatus, TransactionType};
+use crate::record::YPBankRecord;
+
+/// Returns the canonical records, covering extreme ids and amounts, negative amounts,
+/// empty and non-ASCII descriptions and descriptions with quoted separators.
+pub fn records() -> Vec<YPBankRecord> {
+    vec![
+        YPBankRecord::new(
+            u64::MAX,
+            TransactionType::Deposit,
+            0,
+            u64::MAX,
+            i64::MAX,
+            u64::MAX,
+            TransactionStatus::Success,
+            "Max values",
+        ),
+        YPBankRecord::new(
+            0,
+            TransactionType::Withdrawal,
+            1,
+            0,
+            i64::MIN,
+            0,
+            TransactionStatus::Failure,
+            "Min values",
+        ),
+        YPBankRecord::new(
+            1000000000000000,
+            TransactionType::Transfer,
+            9223372036854775807,
+            1,
+            -100,
+            1633036860000,
+            TransactionStatus::Pending,
+            "",
+        ),
+        YPBankRecord::new(
+            1000000000000001,
+            TransactionType::Transfer,
+            2,
+            3,
+            4250,
+            1633036920000,
+            TransactionStatus::Success,
+            "Café ☕ Москва 東京",
+        ),
+        YPBankRecord::new(
+            1000000000000002,
+            TransactionType::Deposit,
+            0,
+            4,
+            1,
+            1633036980000,
+            TransactionStatus::Success,
+            "\"Rent, March\"",
+        ),
+    ]
+}
+
+/// Returns the golden file holding [`records`] in `format`, or `None` for write-only formats.
+pub fn golden(format: Format) -> Option<&'static [u8]> {
+    match format {
+        Format::Csv => Some(include_bytes!("../testdata/canonical.csv")),
+        Format::Txt => Some(include_bytes!("../testdata/canonical.txt")),
+        Format::Bin => Some(include_bytes!("../testdata/canonical.bin")),
+        Format::Toml => Some(include_bytes!("../testdata/canonical.toml")),
+        #[cfg(feature = "msgpack")]
+        Format::MsgPack => Some(include_bytes!("../testdata/canonical.msgpack")),
+        #[cfg(feature = "cbor")]
+        Format::Cbor => Some(include_bytes!("../testdata/canonical.cbor")),
+        Format::Html | Format::PgCopy => None,
+        #[cfg(feature = "xlsx")]
+        Format::Xlsx => None,
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A record read with a timestamp outside the plausible window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImplausibleTimestamp {
+    pub id: u64,
+    /// The timestamp in milliseconds, once converted from the unit of the file.
+    pub ts: u64,
+}
+
+/// Implausible timestamps found while reading, see [`crate::TimestampCheck::Warn`].
+///
+/// Cloning the log is cheap and the clones share the same entries, so the log given to
+/// a parser can be read once the records are.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{CommonParser, Format, ParseOptions, TimestampCheck, TimestampLog};
+/// use parser::TimestampWindow;
+///
+/// let log = TimestampLog::new();
+/// let options = ParseOptions {
+///     timestamp_check: TimestampCheck::Warn(TimestampWindow::plausible(), log.clone()),
+///     ..ParseOptions::default()
+/// };
+/// // Seconds read as milliseconds, on January 19th, 1970.
+/// let data = "TX_ID,TX_TYPE,FROM_USER_ID,TO_USER_ID,AMOUNT,TIMESTAMP,STATUS,DESCRIPTION\n\
+///             1,DEPOSIT,0,2,100,1633036860,SUCCESS,Salary\n";
+/// let records = CommonParser::with_options(Format::Csv, options)
+///     .from_read(&mut data.as_bytes())
+///     .unwrap();
+///
+/// assert_eq!(records.len(), 1);
+/// assert_eq!(log.take()[0].ts, 1633036860);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TimestampLog {
+    entries: Arc<Mutex<Vec<ImplausibleTimestamp>>>,
+}
+
+impl TimestampLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn push(&self, entry: ImplausibleTimestamp) {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(entry);
+    }
+
+    /// Removes and returns the entries logged so far, in the order they were read.
+    pub fn take(&self) -> Vec<ImplausibleTimestamp> {
+        std::mem::take(&mut *self.entries.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Logs are equal when they share the same entries.
+impl PartialEq for TimestampLog {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Eq for TimestampLog {}
@@ -0,0 +1,103 @@
+use crate::constant::TX_ID;
+use crate::error::ParseError;
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+const COMMENT_PREFIX: char = '#';
+
+/// Transaction ids treated as deleted, see [`crate::ParseOptions::tombstones`].
+///
+/// Tombstones are kept next to archives that must not be edited, so that corrections
+/// don't require rewriting them. Cloning is cheap and the clones share the ids.
+///
+/// # Examples
+///
+/// ```
+/// use parser::Tombstones;
+///
+/// let tombstones = Tombstones::from_text(
+///     "# Corrections of 2021-10\n\
+///      1000000000000007  # duplicate of 1000000000000006\n\
+///      1000000000000011\n",
+/// )
+/// .unwrap();
+/// assert!(tombstones.contains(1000000000000007));
+/// assert_eq!(tombstones.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tombstones {
+    ids: Arc<HashSet<u64>>,
+}
+
+impl Tombstones {
+    pub fn new(ids: impl IntoIterator<Item = u64>) -> Self {
+        Self {
+            ids: Arc::new(ids.into_iter().collect()),
+        }
+    }
+
+    /// Reads one id per line. Empty lines and `#` comments, e.g. the reason of a
+    /// deletion after the id, are ignored.
+    pub fn from_text(s: &str) -> Result<Self, ParseError> {
+        let mut ids = HashSet::new();
+        for line in s.lines() {
+            let id = match line.split_once(COMMENT_PREFIX) {
+                Some((id, _)) => id.trim(),
+                None => line.trim(),
+            };
+            if id.is_empty() {
+                continue;
+            }
+
+            ids.insert(id.parse().map_err(|_| ParseError::InvalidRawValue {
+                field: TX_ID.to_string(),
+                value: id.to_string(),
+            })?);
+        }
+
+        Ok(Self { ids: Arc::new(ids) })
+    }
+
+    /// Reads a tombstone file, see [`Tombstones::from_text`].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Self::from_text(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn contains(&self, id: u64) -> bool {
+        self.ids.contains(&id)
+    }
+
+    /// Returns the ids in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ids.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tombstones_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_text() {
+        let tombstones = Tombstones::from_text("\n7\n# reason\n 9 # duplicate\n7\n")
+            .expect("Should read tombstones");
+        assert_eq!(tombstones, Tombstones::new([7, 9]));
+
+        assert_eq!(
+            Tombstones::from_text("7\nabc\n"),
+            Err(ParseError::InvalidRawValue {
+                field: TX_ID.to_string(),
+                value: "abc".to_string(),
+            })
+        );
+    }
+}
@@ -0,0 +1,499 @@
+use crate::common::{TransactionType, ValueParser, parse_value_from_str};
+use crate::constant::{AMOUNT, FROM_USER_ID, STATUS, TIMESTAMP, TO_USER_ID, TX_ID};
+use crate::error::ParseError;
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::YPBankRecord;
+use std::fmt::Write;
+use std::str::FromStr;
+
+const TABLE_HEADER: &str = "[[transaction]]";
+const ASSIGN: char = '=';
+const COMMENT_PREFIX: char = '#';
+const QUOTE: char = '"';
+const LITERAL_QUOTE: char = '\'';
+const ESCAPE: char = '\\';
+
+/// Reads and writes records as TOML arrays of tables.
+///
+/// Each record is a `[[transaction]]` table with one key per field. Only the subset
+/// of TOML needed for records is supported: bare keys, integers and strings.
+/// TOML integers are 64-bit signed, so ids and timestamps above `i64::MAX` are written as
+/// strings, which the reader accepts in place of integers.
+#[derive(Default)]
+pub struct YPBankTomlRecordParser {
+    line: String,
+    raw_values: [Option<String>; 8],
+    /// Whether the last failed record stopped before its end.
+    partial: bool,
+    /// Whether the table header of the next record was read while skipping an invalid one.
+    header_read: bool,
+}
+
+impl YPBankTomlRecordParser {
+    const FIELDS: [&str; 8] = [
+        "tx_id",
+        "tx_type",
+        "from_user_id",
+        "to_user_id",
+        "amount",
+        "timestamp",
+        "status",
+        "description",
+    ];
+
+    /// Counts records by their `[[transaction]]` table headers, without parsing them.
+    pub(crate) fn count_records<R: std::io::BufRead>(r: &mut R) -> Result<u64, ParseError> {
+        let mut line = String::new();
+        let mut count = 0;
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Ok(count);
+            }
+            if Self::strip_comment(&line).trim() == TABLE_HEADER {
+                count += 1;
+            }
+        }
+    }
+
+    fn read_table_header<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        loop {
+            self.line.clear();
+            if r.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+
+            let line = Self::strip_comment(&self.line);
+            if line.is_empty() {
+                continue;
+            }
+
+            if line != TABLE_HEADER {
+                return Err(ParseError::InvalidRow(self.line.clone()));
+            }
+
+            return Ok(true);
+        }
+    }
+
+    fn parse_raw_values<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<(), ParseError> {
+        self.raw_values = Default::default();
+
+        let mut parsed_values = 0;
+        while parsed_values < Self::FIELDS.len() {
+            self.line.clear();
+            if r.read_line(&mut self.line)? == 0 {
+                return Err(ParseError::InconsistentRecord(
+                    "unexpected end of file while parsing".to_string(),
+                ));
+            }
+
+            let line = self.line.trim();
+            if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+                continue;
+            }
+
+            let Some((key, raw_value)) = line.split_once(ASSIGN) else {
+                return Err(ParseError::InvalidRow(self.line.clone()));
+            };
+
+            let key = key.trim();
+            let Some(index) = Self::FIELDS.iter().position(|field| *field == key) else {
+                return Err(ParseError::InvalidRow(self.line.clone()));
+            };
+            if self.raw_values[index].is_some() {
+                return Err(ParseError::InconsistentRecord(format!(
+                    "duplicate key {}",
+                    key
+                )));
+            }
+
+            self.raw_values[index] = Some(Self::parse_raw_value(key, raw_value.trim())?);
+            parsed_values += 1;
+        }
+
+        Ok(())
+    }
+
+    fn from_raw_values(values: &[Option<String>; 8]) -> Result<YPBankRecord, ParseError> {
+        let mut fields: Vec<&str> = vec![];
+        for (field, value) in Self::FIELDS.iter().zip(values) {
+            match value {
+                Some(value) => fields.push(value),
+                None => return Err(ParseError::FieldNotFound(field.to_string())),
+            }
+        }
+
+        let transaction_type = TransactionType::from_str(fields[1])?;
+        let values = ValueParser::default();
+
+        Ok(YPBankRecord::new(
+            values.number(TX_ID, fields[0])?,
+            transaction_type,
+            values.user_id(FROM_USER_ID, fields[2], transaction_type)?,
+            values.user_id(TO_USER_ID, fields[3], transaction_type)?,
+            values.number(AMOUNT, fields[4])?,
+            values.number(TIMESTAMP, fields[5])?,
+            parse_value_from_str(STATUS, fields[6])?,
+            fields[7].to_string(),
+        ))
+    }
+
+    /// Writes `key = value`, quoting values TOML integers cannot hold.
+    fn write_unsigned(line: &mut String, key: &str, value: u64) {
+        if i64::try_from(value).is_ok() {
+            let _ = writeln!(line, "{} = {}", key, value);
+        } else {
+            let _ = writeln!(line, "{} = \"{}\"", key, value);
+        }
+    }
+
+    fn strip_comment(line: &str) -> &str {
+        match line.split_once(COMMENT_PREFIX) {
+            Some((before, _)) => before.trim(),
+            None => line.trim(),
+        }
+    }
+
+    /// Parses the right-hand side of an assignment into its unquoted, unescaped form.
+    pub(crate) fn parse_raw_value(field: &str, raw_value: &str) -> Result<String, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: field.to_uppercase(),
+            value: raw_value.to_string(),
+        };
+        let mut chars = raw_value.char_indices();
+        let (value, rest) = match chars.next() {
+            Some((_, QUOTE)) => Self::parse_basic_string(raw_value).ok_or_else(invalid)?,
+            Some((_, LITERAL_QUOTE)) => match raw_value[1..].split_once(LITERAL_QUOTE) {
+                Some((value, rest)) => (value.to_string(), rest),
+                None => return Err(invalid()),
+            },
+            Some(_) => {
+                let value = Self::strip_comment(raw_value);
+                return Ok(value.replace('_', ""));
+            }
+            None => return Err(invalid()),
+        };
+
+        if !Self::strip_comment(rest).is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(value)
+    }
+
+    fn parse_basic_string(raw_value: &str) -> Option<(String, &str)> {
+        let mut value = String::new();
+        let mut chars = raw_value.char_indices().skip(1);
+        while let Some((pos, ch)) = chars.next() {
+            match ch {
+                QUOTE => return Some((value, &raw_value[pos + 1..])),
+                ESCAPE => {
+                    let (_, escaped) = chars.next()?;
+                    match escaped {
+                        'b' => value.push('\u{8}'),
+                        't' => value.push('\t'),
+                        'n' => value.push('\n'),
+                        'f' => value.push('\u{c}'),
+                        'r' => value.push('\r'),
+                        '"' => value.push('"'),
+                        '\\' => value.push('\\'),
+                        'u' | 'U' => {
+                            let len = if escaped == 'u' { 4 } else { 8 };
+                            let code: String = chars.by_ref().take(len).map(|(_, c)| c).collect();
+                            let ch = u32::from_str_radix(&code, 16)
+                                .ok()
+                                .filter(|_| code.len() == len)
+                                .and_then(char::from_u32)?;
+                            value.push(ch);
+                        }
+                        _ => return None,
+                    }
+                }
+                _ => value.push(ch),
+            }
+        }
+
+        None
+    }
+
+    fn escape_string(value: &str, out: &mut String) {
+        out.push(QUOTE);
+        for ch in value.chars() {
+            match ch {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                ch if ch.is_control() => {
+                    let _ = write!(out, "\\u{:04X}", ch as u32);
+                }
+                ch => out.push(ch),
+            }
+        }
+        out.push(QUOTE);
+    }
+}
+
+impl FromOptions for YPBankTomlRecordParser {}
+
+impl RecordReader for YPBankTomlRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        self.partial = true;
+        if !std::mem::take(&mut self.header_read) && !self.read_table_header(r)? {
+            return Ok(None);
+        }
+
+        self.parse_raw_values(r)?;
+        self.partial = false;
+
+        let record = Self::from_raw_values(&self.raw_values)?;
+        Ok(Some(record))
+    }
+
+    /// Skips the lines of a record that failed before its end, up to the next table header.
+    fn skip_invalid<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        while self.partial {
+            self.line.clear();
+            if r.read_line(&mut self.line)? == 0 {
+                break;
+            }
+            if Self::strip_comment(&self.line) == TABLE_HEADER {
+                self.header_read = true;
+                break;
+            }
+        }
+        self.partial = false;
+        Ok(true)
+    }
+}
+
+impl RecordWriter for YPBankTomlRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        let fields = Self::FIELDS;
+
+        self.line.clear();
+        let _ = writeln!(self.line, "{}", TABLE_HEADER);
+        Self::write_unsigned(&mut self.line, fields[0], record.id);
+        let _ = writeln!(
+            self.line,
+            "{} = \"{}\"",
+            fields[1],
+            record.transaction_type.as_str()
+        );
+        Self::write_unsigned(&mut self.line, fields[2], record.from_user_id);
+        Self::write_unsigned(&mut self.line, fields[3], record.to_user_id);
+        let _ = writeln!(self.line, "{} = {}", fields[4], record.amount);
+        Self::write_unsigned(&mut self.line, fields[5], record.ts);
+        let _ = writeln!(self.line, "{} = \"{}\"", fields[6], record.status.as_str());
+        let _ = write!(self.line, "{} = ", fields[7]);
+        Self::escape_string(&record.description, &mut self.line);
+        self.line.push_str("\n\n");
+
+        w.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+}
+
+pub struct TomlParser {}
+
+impl Parser<YPBankTomlRecordParser> for TomlParser {}
+
+#[cfg(test)]
+mod yp_bank_toml_record_tests {
+    use super::*;
+    use crate::common::TransactionStatus;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_from_read_regular_case() {
+        let raw_data = "# Record 1\n[[transaction]]\ntx_type = \"DEPOSIT\"\nto_user_id = 9223372036854775807\nfrom_user_id = 0\ntimestamp = 1_633_036_860_000\ndescription = \"\\\"Record number 1\\\"\" # quoted\ntx_id = 1000000000000000\namount = -100\nstatus = 'FAILURE'\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let target_record = YPBankRecord::new(
+            1000000000000000,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            -100,
+            1633036860000,
+            TransactionStatus::Failure,
+            "\"Record number 1\"".to_string(),
+        );
+
+        let result = YPBankTomlRecordParser::default().from_read(&mut reader);
+        assert!(result.is_ok(), "Parsing should succeed");
+        let record_opt = result.expect("Should parse successfully");
+        assert_eq!(record_opt.expect("Should have a record"), target_record);
+    }
+
+    #[test]
+    fn test_from_read_missing_field() {
+        let raw_data = "[[transaction]]\ntx_id = 1\ntx_type = \"DEPOSIT\"\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let result = YPBankTomlRecordParser::default().from_read(&mut reader);
+        assert!(matches!(result, Err(ParseError::InconsistentRecord(_))));
+    }
+
+    #[test]
+    fn test_from_read_extreme_amounts() {
+        let raw_data = |amount: &str| {
+            format!(
+                "[[transaction]]\ntx_id = 1\ntx_type = \"DEPOSIT\"\nfrom_user_id = 0\nto_user_id = 2\namount = {}\ntimestamp = 5\nstatus = \"SUCCESS\"\ndescription = \"\"\n",
+                amount
+            )
+        };
+        let read = |amount| {
+            YPBankTomlRecordParser::default()
+                .from_read(&mut Cursor::new(raw_data(amount).into_bytes()))
+                .map(|record| record.map(|record| record.amount))
+        };
+
+        assert_eq!(read("-9_223_372_036_854_775_808"), Ok(Some(i64::MIN)));
+        assert_eq!(read("9223372036854775807"), Ok(Some(i64::MAX)));
+        assert_eq!(
+            read("9223372036854775808"),
+            Err(ParseError::ValueOutOfRange {
+                field: "AMOUNT".to_string(),
+                value: "9223372036854775808".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_read_unknown_key() {
+        let raw_data = "[[transaction]]\nunknown = 1\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let result = YPBankTomlRecordParser::default().from_read(&mut reader);
+        assert!(matches!(result, Err(ParseError::InvalidRow(_))));
+    }
+
+    #[test]
+    fn test_write_to_regular_case() {
+        let record = YPBankRecord::new(
+            1000000000000000,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            100,
+            1633036860000,
+            TransactionStatus::Failure,
+            "\"Record\\number\n1\"".to_string(),
+        );
+        let raw_data = "[[transaction]]\ntx_id = 1000000000000000\ntx_type = \"DEPOSIT\"\nfrom_user_id = 0\nto_user_id = 9223372036854775807\namount = 100\ntimestamp = 1633036860000\nstatus = \"FAILURE\"\ndescription = \"\\\"Record\\\\number\\n1\\\"\"\n\n";
+
+        let mut writer = Cursor::new(Vec::new());
+        let result = YPBankTomlRecordParser::default().write_to(&record, &mut writer);
+        assert!(result.is_ok(), "Writing should succeed");
+
+        let written =
+            String::from_utf8(writer.into_inner()).expect("Written data should be valid UTF-8");
+        assert_eq!(written, raw_data);
+    }
+}
+
+#[cfg(test)]
+mod toml_parser_tests {
+    use super::*;
+    use crate::common::TransactionStatus;
+    use crate::error::ErrorCode;
+    use crate::options::ParseOptions;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_round_trip_multiple_records() {
+        let records = vec![
+            YPBankRecord::new(
+                1000000000000000,
+                TransactionType::Deposit,
+                0,
+                9223372036854775807,
+                100,
+                1633036860000,
+                TransactionStatus::Failure,
+                "\"Record number 1\"".to_string(),
+            ),
+            YPBankRecord::new(
+                1000000000000001,
+                TransactionType::Transfer,
+                9223372036854775807,
+                9223372036854775807,
+                200,
+                1633036920000,
+                TransactionStatus::Pending,
+                "Описание\twith tab".to_string(),
+            ),
+            YPBankRecord::new(
+                u64::MAX,
+                TransactionType::Transfer,
+                u64::MAX,
+                1,
+                300,
+                u64::MAX,
+                TransactionStatus::Success,
+                "",
+            ),
+        ];
+
+        let mut writer = Cursor::new(Vec::new());
+        TomlParser::write_to(&mut writer, &records, &ParseOptions::default())
+            .expect("Should write successfully");
+
+        // Integers above i64::MAX are not valid TOML, so they are written as strings.
+        let written = String::from_utf8(writer.into_inner()).expect("Should be valid UTF-8");
+        assert!(written.contains("\ntx_id = \"18446744073709551615\"\n"));
+        assert!(written.contains("\nfrom_user_id = \"18446744073709551615\"\n"));
+        assert!(written.contains("\nto_user_id = 1\n"));
+        assert!(written.contains("\ntimestamp = \"18446744073709551615\"\n"));
+
+        let mut reader = Cursor::new(written.into_bytes());
+        let read_records = TomlParser::from_read(&mut reader, &ParseOptions::default())
+            .expect("Should parse successfully");
+        assert_eq!(read_records, records);
+    }
+
+    #[test]
+    fn test_iter_read_skips_invalid_records() {
+        let record = |id: u64, amount: &str| {
+            format!(
+                "[[transaction]]\ntx_id = {id}\ntx_type = \"DEPOSIT\"\nfrom_user_id = 0\n\
+                 to_user_id = 2\namount = {amount}\ntimestamp = 1633036860000\n\
+                 status = \"SUCCESS\"\ndescription = \"Salary\"\n\n"
+            )
+        };
+        let data = format!(
+            "{}[[transaction]]\ntx_id = 2\nunknown = 1\ntx_type = \"DEPOSIT\"\n\n{}{}",
+            record(1, "100"),
+            record(3, "abc"),
+            record(4, "400")
+        );
+        let options = ParseOptions {
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+
+        let ids: Vec<_> = TomlParser::iter_read(data.as_bytes(), &options)
+            .expect("Should start reading")
+            .map(|record| record.map(|record| record.id).map_err(|err| err.code()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                Ok(1),
+                Err(ErrorCode::InvalidRow),
+                Err(ErrorCode::InvalidRawValue),
+                Ok(4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use crate::common::{TransactionStatus, TransactionType};
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+
+/// Number of records and sum of their amounts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Total {
+    pub count: u64,
+    pub amount: i128,
+}
+
+impl Total {
+    fn add(&mut self, amount: i64) -> Result<(), ParseError> {
+        self.count = self
+            .count
+            .checked_add(1)
+            .ok_or_else(|| ParseError::Overflow("record count".to_string()))?;
+        self.amount = self
+            .amount
+            .checked_add(amount as i128)
+            .ok_or_else(|| ParseError::Overflow("total amount".to_string()))?;
+        Ok(())
+    }
+
+    /// Returns the amount as `i64`, failing instead of wrapping when it does not fit.
+    pub fn amount_i64(&self) -> Result<i64, ParseError> {
+        i64::try_from(self.amount).map_err(|_| ParseError::Overflow(self.amount.to_string()))
+    }
+}
+
+/// Totals of a set of records, overall and broken down by type and by status.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub all: Total,
+    by_type: [Total; 3],
+    by_status: [Total; 3],
+}
+
+impl Totals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a record to the totals.
+    pub fn add(&mut self, record: &YPBankRecord) -> Result<(), ParseError> {
+        self.all.add(record.amount)?;
+        self.by_type[record.transaction_type.as_int() as usize].add(record.amount)?;
+        self.by_status[record.status.as_int() as usize].add(record.amount)?;
+        Ok(())
+    }
+
+    pub fn for_type(&self, transaction_type: TransactionType) -> Total {
+        self.by_type[transaction_type.as_int() as usize]
+    }
+
+    pub fn for_status(&self, status: TransactionStatus) -> Total {
+        self.by_status[status.as_int() as usize]
+    }
+}
+
+/// Computes totals of `records` with `i128` accumulation.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{TransactionStatus, TransactionType, YPBankRecord, totals};
+///
+/// let record = |amount| {
+///     YPBankRecord::new(1, TransactionType::Deposit, 0, 2, amount, 3, TransactionStatus::Success, "")
+/// };
+///
+/// let totals = totals(&[record(i64::MAX), record(i64::MAX)]).unwrap();
+/// assert_eq!(totals.all.amount, 2 * i64::MAX as i128);
+/// assert!(totals.all.amount_i64().is_err());
+/// assert_eq!(totals.for_type(TransactionType::Deposit).count, 2);
+/// ```
+pub fn totals(records: &[YPBankRecord]) -> Result<Totals, ParseError> {
+    let mut totals = Totals::new();
+    for record in records {
+        totals.add(record)?;
+    }
+    Ok(totals)
+}
+
+#[cfg(test)]
+mod totals_tests {
+    use super::*;
+
+    fn record(
+        transaction_type: TransactionType,
+        amount: i64,
+        status: TransactionStatus,
+    ) -> YPBankRecord {
+        YPBankRecord::new(1, transaction_type, 1, 2, amount, 3, status, "")
+    }
+
+    #[test]
+    fn test_totals_breakdown() {
+        let records = vec![
+            record(TransactionType::Transfer, 100, TransactionStatus::Success),
+            record(TransactionType::Transfer, -30, TransactionStatus::Pending),
+            record(
+                TransactionType::Withdrawal,
+                i64::MIN,
+                TransactionStatus::Success,
+            ),
+        ];
+
+        let totals = totals(&records).expect("Should compute totals");
+
+        assert_eq!(
+            totals.all,
+            Total {
+                count: 3,
+                amount: 70 + i64::MIN as i128
+            }
+        );
+        assert_eq!(
+            totals.for_type(TransactionType::Transfer),
+            Total {
+                count: 2,
+                amount: 70
+            }
+        );
+        assert_eq!(totals.for_type(TransactionType::Deposit), Total::default());
+        assert_eq!(
+            totals.for_status(TransactionStatus::Success),
+            Total {
+                count: 2,
+                amount: 100 + i64::MIN as i128
+            }
+        );
+        assert_eq!(totals.all.amount_i64(), Ok(70 + i64::MIN));
+    }
+
+    #[test]
+    fn test_amount_i64_overflow() {
+        let total = Total {
+            count: 2,
+            amount: i64::MAX as i128 + 1,
+        };
+        assert_eq!(
+            total.amount_i64(),
+            Err(ParseError::Overflow("9223372036854775808".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_total_add_overflow() {
+        let mut total = Total {
+            count: 1,
+            amount: i128::MAX,
+        };
+        assert_eq!(
+            total.add(1),
+            Err(ParseError::Overflow("total amount".to_string()))
+        );
+    }
+}
@@ -1,17 +1,34 @@
-use crate::common::parse_value_from_string;
-use crate::common::{TransactionType, parse_from_user_id, parse_to_user_id};
+use crate::comment::Comment;
+use crate::common::{TransactionType, ValueParser, parse_value_from_str};
+use crate::constant::{FROM_USER_ID, STATUS, TO_USER_ID, TX_ID};
 use crate::error::ParseError;
-use crate::parser::{Parser, YPBankRecordParser};
-use crate::record::YPBankRecord;
-use std::collections::HashMap;
+use crate::options::{ParseOptions, TxtComments};
+use crate::parser::{FromOptions, Parser, RecordReader, RecordWriter};
+use crate::record::{YPBankRecord, YPBankRecordExt};
+use std::collections::{BTreeMap, HashMap};
 use std::iter::zip;
 use std::str::FromStr;
 
 const SEP: char = ':';
 const COMMENT_PREFIX: char = '#';
 const NEW_LINE: char = '\n';
-
-pub struct YPBankTxtRecordParser {}
+const DASHES: &str = "---";
+
+#[derive(Default)]
+pub struct YPBankTxtRecordParser {
+    line: String,
+    raw_values: HashMap<String, String>,
+    key_aliases: HashMap<String, String>,
+    values: ValueParser,
+    comments: TxtComments,
+    /// Whether keys other than the record fields are read into `extras`.
+    preserve_extras: bool,
+    extras: BTreeMap<String, String>,
+    /// Number of the last line read.
+    line_number: u64,
+    /// Whether the last failed record stopped before its end.
+    partial: bool,
+}
 
 impl YPBankTxtRecordParser {
     const FIELDS: [&str; 8] = [
@@ -25,31 +42,61 @@ impl YPBankTxtRecordParser {
         "DESCRIPTION",
     ];
 
-    fn parse_raw_values<R: std::io::BufRead>(
-        r: &mut R,
-    ) -> Result<Option<HashMap<String, String>>, ParseError> {
-        let mut raw_values = HashMap::<String, String>::new();
+    /// Counts records by their key lines, without parsing them. A last record missing
+    /// some of its lines, e.g. of a file still being written, is not counted.
+    pub(crate) fn count_records<R: std::io::BufRead>(r: &mut R) -> Result<u64, ParseError> {
+        let mut line = String::new();
+        let mut key_lines = 0;
+        loop {
+            line.clear();
+            if r.read_line(&mut line)? == 0 {
+                return Ok(key_lines / Self::FIELDS.len() as u64);
+            }
+            if !(line.starts_with(COMMENT_PREFIX)
+                || line.trim().is_empty()
+                || line.trim_end() == DASHES)
+            {
+                key_lines += 1;
+            }
+        }
+    }
+
+    fn parse_raw_values<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        self.raw_values.clear();
+        self.extras.clear();
 
         let mut parsed_values = 0;
         while parsed_values < 8 {
-            let mut line = String::new();
-            let res = r.read_line(&mut line)?;
+            self.line.clear();
+            let res = r.read_line(&mut self.line)?;
 
             if res == 0 {
                 if parsed_values == 0 {
-                    return Ok(None);
+                    return Ok(false);
                 }
 
                 return Err(ParseError::InconsistentRecord(
                     "unexpected end of file while parsing".to_string(),
                 ));
             }
-
-            if line.starts_with(COMMENT_PREFIX) {
+            self.line_number += 1;
+
+            if self.line.starts_with(COMMENT_PREFIX) {
+                match &self.comments {
+                    TxtComments::Skip => {}
+                    TxtComments::Collect(log) => log.push(Comment {
+                        line: self.line_number,
+                        text: self.line[1..].trim().to_string(),
+                    }),
+                    TxtComments::Reject => {
+                        self.partial = parsed_values > 0;
+                        return Err(ParseError::InvalidRow(self.line.trim_end().to_string()));
+                    }
+                }
                 continue;
             }
 
-            if line == NEW_LINE.to_string() {
+            if self.line.len() == 1 && self.line.starts_with(NEW_LINE) {
                 if parsed_values == 0 {
                     continue;
                 }
@@ -59,15 +106,34 @@ impl YPBankTxtRecordParser {
                 ));
             }
 
-            let [key, val] = Self::parse_raw_line(line)?;
-            raw_values.insert(key, val);
+            if self.line.trim_end() == DASHES {
+                if parsed_values == 0 {
+                    continue;
+                }
+
+                return Err(ParseError::InconsistentRecord(
+                    "unexpected separator while parsing".to_string(),
+                ));
+            }
+
+            let [key, val] =
+                Self::parse_raw_line(&self.line).inspect_err(|_| self.partial = true)?;
+            let field = self.canonical_key(key.clone());
+            if self.preserve_extras && !Self::FIELDS.contains(&field.as_str()) {
+                self.extras.insert(key, val);
+                continue;
+            }
+            self.raw_values.insert(field, val);
             parsed_values += 1
         }
 
-        Ok(Some(raw_values))
+        Ok(true)
     }
 
-    fn from_raw_values(values_map: HashMap<String, String>) -> Result<YPBankRecord, ParseError> {
+    fn record_from_raw_values(
+        &self,
+        values_map: &HashMap<String, String>,
+    ) -> Result<YPBankRecord, ParseError> {
         let mut values: Vec<String> = vec![];
         for field in Self::FIELDS {
             match values_map.get(field) {
@@ -81,41 +147,89 @@ impl YPBankTxtRecordParser {
         let tt_parse_result = TransactionType::from_str(&values[1])?;
 
         Ok(YPBankRecord::new(
-            parse_value_from_string(values[0].clone())?,
-            parse_value_from_string(values[1].clone())?,
-            parse_from_user_id(values[2].clone(), tt_parse_result)?,
-            parse_to_user_id(values[3].clone(), tt_parse_result)?,
-            parse_value_from_string(values[4].clone())?,
-            parse_value_from_string(values[5].clone())?,
-            parse_value_from_string(values[6].clone())?,
+            self.values.number(TX_ID, &values[0])?,
+            tt_parse_result,
+            self.values
+                .user_id(FROM_USER_ID, &values[2], tt_parse_result)?,
+            self.values
+                .user_id(TO_USER_ID, &values[3], tt_parse_result)?,
+            self.values.amount(&values[4])?,
+            self.values.timestamp(&values[5])?,
+            parse_value_from_str(STATUS, &values[6])?,
             values[7].clone(),
         ))
     }
 
-    fn parse_raw_line(line: String) -> Result<[String; 2], ParseError> {
-        let parts = line.split(SEP).collect::<Vec<&str>>();
-        if parts.len() != 2 {
-            return Err(ParseError::InvalidRow(line));
+    fn canonical_key(&self, key: String) -> String {
+        let key = key.to_uppercase();
+        match self.key_aliases.get(&key) {
+            Some(field) => field.clone(),
+            None => key,
         }
+    }
+
+    fn parse_raw_line(line: &str) -> Result<[String; 2], ParseError> {
+        match line.split_once(SEP) {
+            Some((key, val)) => Ok([key.trim().to_string(), val.trim().to_string()]),
+            None => Err(ParseError::InvalidRow(line.to_string())),
+        }
+    }
+}
 
-        Ok([parts[0].trim().to_string(), parts[1].trim().to_string()])
+impl FromOptions for YPBankTxtRecordParser {
+    fn with_options(options: &ParseOptions) -> Self {
+        Self {
+            key_aliases: options
+                .txt_key_aliases
+                .iter()
+                .map(|(alias, field)| (alias.to_uppercase(), field.to_uppercase()))
+                .collect(),
+            values: ValueParser::new(options),
+            comments: options.txt_comments.clone(),
+            preserve_extras: options.preserve_extras,
+            ..Self::default()
+        }
     }
 }
 
-impl YPBankRecordParser for YPBankTxtRecordParser {
-    fn from_read<R: std::io::BufRead>(r: &mut R) -> Result<Option<YPBankRecord>, ParseError> {
-        let raw_values_opt = Self::parse_raw_values(r)?;
+impl RecordReader for YPBankTxtRecordParser {
+    fn from_read<R: std::io::BufRead>(
+        &mut self,
+        r: &mut R,
+    ) -> Result<Option<YPBankRecord>, ParseError> {
+        if !self.parse_raw_values(r)? {
+            return Ok(None);
+        }
+
+        let record = self.record_from_raw_values(&self.raw_values)?;
+        Ok(Some(record))
+    }
 
-        match raw_values_opt {
-            Some(raw_values) => {
-                let record = Self::from_raw_values(raw_values)?;
-                Ok(Some(record))
+    fn take_extras(&mut self) -> BTreeMap<String, String> {
+        std::mem::take(&mut self.extras)
+    }
+
+    /// Skips the lines of a record that failed before its end, up to the next separator.
+    fn skip_invalid<R: std::io::BufRead>(&mut self, r: &mut R) -> Result<bool, ParseError> {
+        while self.partial {
+            self.line.clear();
+            if r.read_line(&mut self.line)? == 0 {
+                break;
             }
-            None => Ok(None),
+            self.line_number += 1;
+            self.partial = !(self.line.trim().is_empty() || self.line.trim_end() == DASHES);
         }
+        self.partial = false;
+        Ok(true)
     }
+}
 
-    fn write_to<W: std::io::Write>(record: &YPBankRecord, w: &mut W) -> Result<(), ParseError> {
+impl RecordWriter for YPBankTxtRecordParser {
+    fn write_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecord,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
         let record_values = [
             record.id.to_string(),
             record.transaction_type.as_str().to_string(),
@@ -127,23 +241,68 @@ impl YPBankRecordParser for YPBankTxtRecordParser {
             record.description.to_string(),
         ];
 
-        let mut raw_values: Vec<String> = vec![];
+        self.line.clear();
         for (key, val) in zip(Self::FIELDS.iter(), &record_values) {
-            raw_values.push(format!("{}: {}", key, val));
+            self.line.push_str(key);
+            self.line.push(SEP);
+            self.line.push(' ');
+            self.line.push_str(val);
+            self.line.push(NEW_LINE);
         }
-        raw_values.push(NEW_LINE.to_string());
 
-        let result = raw_values.join(NEW_LINE.to_string().as_str());
-
-        w.write_all(result.as_bytes())?;
+        w.write_all(self.line.as_bytes())?;
 
         Ok(())
     }
+
+    /// Writes the extras as keys before the record fields, so that readers tell them
+    /// from the keys of the next record. Extras named like a field, or holding a line
+    /// break, fail.
+    fn write_ext_to<W: std::io::Write>(
+        &mut self,
+        record: &YPBankRecordExt,
+        w: &mut W,
+    ) -> Result<(), ParseError> {
+        for (key, val) in &record.extras {
+            let is_field = Self::FIELDS.contains(&self.canonical_key(key.clone()).as_str());
+            if is_field
+                || key.is_empty()
+                || key.starts_with(COMMENT_PREFIX)
+                || key.contains([SEP, NEW_LINE, '\r'])
+                || key.trim() != key
+                || val.contains([NEW_LINE, '\r'])
+            {
+                return Err(ParseError::InvalidRawValue {
+                    field: key.clone(),
+                    value: val.clone(),
+                });
+            }
+        }
+
+        let mut extras = String::new();
+        for (key, val) in &record.extras {
+            extras.push_str(key);
+            extras.push(SEP);
+            extras.push(' ');
+            extras.push_str(val);
+            extras.push(NEW_LINE);
+        }
+        w.write_all(extras.as_bytes())?;
+        self.write_to(&record.core, w)
+    }
 }
 
 pub struct TxtParser {}
 
-impl Parser<YPBankTxtRecordParser> for TxtParser {}
+impl Parser<YPBankTxtRecordParser> for TxtParser {
+    fn write_separator<Writer: std::io::Write>(
+        w: &mut Writer,
+        options: &ParseOptions,
+    ) -> Result<(), ParseError> {
+        w.write_all(options.txt_separator.as_str().as_bytes())?;
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod yp_bank_txt_record_tests {
@@ -167,13 +326,85 @@ mod yp_bank_txt_record_tests {
             "\"Record number 1\"".to_string(),
         );
 
-        let result = YPBankTxtRecordParser::from_read(&mut reader);
+        let result = YPBankTxtRecordParser::default().from_read(&mut reader);
         assert!(result.is_ok(), "Parsing should succeed");
         let record_opt = result.expect("Should parse successfully");
         assert!(record_opt.is_some(), "Should return Some(record)");
         assert_eq!(record_opt.expect("Should have a record"), target_record);
     }
 
+    #[test]
+    fn test_from_read_case_insensitive_and_aliased_keys() {
+        let raw_data = "tx_type: DEPOSIT\nTo: 9223372036854775807\nFrom_User_Id: 0\nTimestamp: 1633036860000\ndescription: \"Record number 1\"\nTxId: 1000000000000000\nAMOUNT: 100\nStatus: FAILURE\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let target_record = YPBankRecord::new(
+            1000000000000000,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            100,
+            1633036860000,
+            TransactionStatus::Failure,
+            "\"Record number 1\"".to_string(),
+        );
+
+        let result = YPBankTxtRecordParser::with_options(&ParseOptions::default())
+            .from_read(&mut reader)
+            .expect("Should parse successfully");
+        assert_eq!(result.expect("Should have a record"), target_record);
+    }
+
+    #[test]
+    fn test_from_read_custom_alias() {
+        let raw_data = "TX_ID: 1000000000000000\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nreceiver: 9223372036854775807\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let mut options = ParseOptions::default();
+        options
+            .txt_key_aliases
+            .insert("Receiver".to_string(), "TO_USER_ID".to_string());
+
+        let result = YPBankTxtRecordParser::with_options(&options)
+            .from_read(&mut reader)
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        assert_eq!(result.to_user_id, 9223372036854775807);
+    }
+
+    #[test]
+    fn test_from_read_amount_out_of_range() {
+        let raw_data = "TX_ID: 1\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 2\nAMOUNT: -9223372036854775809\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let result = YPBankTxtRecordParser::default().from_read(&mut reader);
+        assert_eq!(
+            result,
+            Err(ParseError::ValueOutOfRange {
+                field: "AMOUNT".to_string(),
+                value: "-9223372036854775809".to_string()
+            })
+        );
+    }
+
+    #[cfg(feature = "tz")]
+    #[test]
+    fn test_from_read_local_timestamp() {
+        let raw_data = "TX_ID: 1\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 2\nAMOUNT: 100\nTIMESTAMP: 2021-10-01 00:21:00\nSTATUS: SUCCESS\nDESCRIPTION: \"Record number 1\"\n";
+        let mut reader = Cursor::new(raw_data.as_bytes());
+
+        let options = ParseOptions {
+            timestamp_tz: Some(crate::tz::TimeZone::from_posix("MSK-3").expect("Should parse")),
+            ..ParseOptions::default()
+        };
+
+        let result = YPBankTxtRecordParser::with_options(&options)
+            .from_read(&mut reader)
+            .expect("Should parse successfully")
+            .expect("Should have a record");
+        assert_eq!(result.ts, 1633036860000);
+    }
+
     #[test]
     fn test_write_to_regular_case() {
         let record = YPBankRecord::new(
@@ -186,10 +417,10 @@ mod yp_bank_txt_record_tests {
             TransactionStatus::Failure,
             "\"Record number 1\"".to_string(),
         );
-        let raw_data = "TX_ID: 1000000000000000\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 9223372036854775807\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\n\n";
+        let raw_data = "TX_ID: 1000000000000000\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 9223372036854775807\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\n";
 
         let mut writer = Cursor::new(Vec::new());
-        let result = YPBankTxtRecordParser::write_to(&record, &mut writer);
+        let result = YPBankTxtRecordParser::default().write_to(&record, &mut writer);
         assert!(result.is_ok(), "Writing should succeed");
 
         let written =
@@ -201,7 +432,10 @@ mod yp_bank_txt_record_tests {
 #[cfg(test)]
 mod txt_parser_tests {
     use super::*;
+    use crate::comment::CommentLog;
     use crate::common::TransactionStatus;
+    use crate::error::ErrorCode;
+    use crate::options::TxtSeparator;
     use std::io::Cursor;
 
     #[test]
@@ -232,7 +466,7 @@ mod txt_parser_tests {
             ),
         ];
 
-        let result = TxtParser::from_read(&mut reader);
+        let result = TxtParser::from_read(&mut reader, &ParseOptions::default());
         assert!(result.is_ok(), "Parsing should succeed");
         let records = result.expect("Should parse successfully");
         assert_eq!(records.len(), 2);
@@ -264,14 +498,189 @@ mod txt_parser_tests {
             ),
         ];
 
-        let raw_data = "TX_ID: 1000000000000000\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 9223372036854775807\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\nTX_ID: 1000000000000001\nTX_TYPE: TRANSFER\nFROM_USER_ID: 9223372036854775807\nTO_USER_ID: 9223372036854775807\nAMOUNT: 200\nTIMESTAMP: 1633036920000\nSTATUS: PENDING\nDESCRIPTION: \"Record number 2\"\n";
+        let raw_data = "TX_ID: 1000000000000000\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 9223372036854775807\nAMOUNT: 100\nTIMESTAMP: 1633036860000\nSTATUS: FAILURE\nDESCRIPTION: \"Record number 1\"\n\nTX_ID: 1000000000000001\nTX_TYPE: TRANSFER\nFROM_USER_ID: 9223372036854775807\nTO_USER_ID: 9223372036854775807\nAMOUNT: 200\nTIMESTAMP: 1633036920000\nSTATUS: PENDING\nDESCRIPTION: \"Record number 2\"\n\n";
 
         let mut writer = Cursor::new(Vec::new());
-        let result = TxtParser::write_to(&mut writer, &records);
+        let result = TxtParser::write_to(&mut writer, &records, &ParseOptions::default());
         assert!(result.is_ok(), "Writing should succeed");
 
         let written =
             String::from_utf8(writer.into_inner()).expect("Written data should be valid UTF-8");
         assert_eq!(written, raw_data);
     }
+
+    #[test]
+    fn test_separators_round_trip() {
+        let records: Vec<_> = (1..=3)
+            .map(|id| {
+                YPBankRecord::new(
+                    id,
+                    TransactionType::Deposit,
+                    0,
+                    2,
+                    100,
+                    1633036860000,
+                    TransactionStatus::Success,
+                    format!("Record number {}", id),
+                )
+            })
+            .collect();
+
+        for (separator, between) in [
+            (TxtSeparator::BlankLine, "1\n\nTX_ID: 2\n"),
+            (TxtSeparator::Dashes, "1\n---\nTX_ID: 2\n"),
+            (TxtSeparator::None, "1\nTX_ID: 2\n"),
+        ] {
+            let options = ParseOptions {
+                txt_separator: separator,
+                ..ParseOptions::default()
+            };
+            let mut data = Vec::new();
+            TxtParser::write_to(&mut data, &records, &options).expect("Should write records");
+            TxtParser::append_iter(&mut data, records.iter().cloned().map(Ok), &options)
+                .expect("Should append records");
+
+            let written = String::from_utf8(data).expect("Written data should be valid UTF-8");
+            assert!(written.contains(between), "{:?}", separator);
+            assert_eq!(
+                TxtParser::from_read(&mut written.as_bytes(), &ParseOptions::default()),
+                Ok([records.clone(), records.clone()].concat()),
+                "{:?}",
+                separator
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_read_separator_inside_record() {
+        let raw_data = "TX_ID: 1\nTX_TYPE: DEPOSIT\n---\nFROM_USER_ID: 0\n";
+        assert!(matches!(
+            TxtParser::from_read(&mut raw_data.as_bytes(), &ParseOptions::default()),
+            Err(ParseError::InconsistentRecord(msg)) if msg == "unexpected separator while parsing"
+        ));
+    }
+
+    #[test]
+    fn test_iter_read_skips_invalid_records() {
+        let record = |id: u64, amount: &str| {
+            format!(
+                "TX_ID: {id}\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 2\nAMOUNT: {amount}\n\
+                 TIMESTAMP: 1633036860000\nSTATUS: SUCCESS\nDESCRIPTION: Salary\n\n"
+            )
+        };
+        let raw_data = format!(
+            "{}TX_ID: 2\nbroken line\nTX_TYPE: DEPOSIT\n\n{}{}",
+            record(1, "100"),
+            record(3, "abc"),
+            record(4, "400")
+        );
+        let options = ParseOptions {
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+
+        let ids: Vec<_> = TxtParser::iter_read(raw_data.as_bytes(), &options)
+            .expect("Should start reading")
+            .map(|record| record.map(|record| record.id).map_err(|err| err.code()))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                Ok(1),
+                Err(ErrorCode::InvalidRow),
+                Err(ErrorCode::InvalidRawValue),
+                Ok(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_are_collected_or_rejected() {
+        let record = |id: u64| {
+            format!(
+                "TX_ID: {id}\nTX_TYPE: DEPOSIT\nFROM_USER_ID: 0\nTO_USER_ID: 2\nAMOUNT: 100\n\
+                 TIMESTAMP: 1633036860000\nSTATUS: SUCCESS\nDESCRIPTION: Salary\n\n"
+            )
+        };
+        let raw_data = format!(
+            "# Exported 2021-10-01\n{}# Checked\n#\n{}",
+            record(1),
+            record(2)
+        );
+        let read = |comments: TxtComments| {
+            let options = ParseOptions {
+                txt_comments: comments,
+                ..ParseOptions::default()
+            };
+            TxtParser::from_read(&mut raw_data.as_bytes(), &options).map(|records| records.len())
+        };
+
+        let log = CommentLog::new();
+        assert_eq!(read(TxtComments::Skip), Ok(2));
+        assert_eq!(read(TxtComments::Collect(log.clone())), Ok(2));
+        assert_eq!(
+            log.take(),
+            vec![
+                Comment {
+                    line: 1,
+                    text: "Exported 2021-10-01".to_string()
+                },
+                Comment {
+                    line: 11,
+                    text: "Checked".to_string()
+                },
+                Comment {
+                    line: 12,
+                    text: String::new()
+                },
+            ]
+        );
+        assert_eq!(
+            read(TxtComments::Reject),
+            Err(ParseError::InvalidRow("# Exported 2021-10-01".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_extras_round_trip() {
+        let mut record = YPBankRecordExt::from(YPBankRecord::new(
+            1,
+            TransactionType::Deposit,
+            0,
+            2,
+            100,
+            1633036860000,
+            TransactionStatus::Success,
+            "\"Salary\"",
+        ));
+        record
+            .extras
+            .insert("Audited_By".to_string(), "jdoe".to_string());
+        let options = ParseOptions {
+            preserve_extras: true,
+            ..ParseOptions::default()
+        };
+
+        let mut data = Vec::new();
+        let records = vec![record.clone(), record.core.clone().into()];
+        TxtParser::write_ext_to(&mut data, &records, &options).expect("Should write successfully");
+        assert!(data.starts_with(b"Audited_By: jdoe\nTX_ID: 1\n"));
+
+        let read = TxtParser::iter_read(Cursor::new(&data), &options)
+            .expect("Should read successfully")
+            .with_extras()
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(read, Ok(records));
+
+        let mut data = Vec::new();
+        TxtParser::write_ext_to(&mut data, &[record.clone()], &ParseOptions::default())
+            .expect("Should write successfully");
+        assert!(data.starts_with(b"TX_ID: 1\n"));
+
+        record.extras.insert("amount".to_string(), "1".to_string());
+        assert!(matches!(
+            TxtParser::write_ext_to(&mut Vec::new(), &[record], &options),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+    }
 }
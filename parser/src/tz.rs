@@ -0,0 +1,605 @@
+use crate::calendar::{SECONDS_PER_DAY, civil_from_days, days_from_civil, days_in_month};
+use crate::constant::TIMESTAMP;
+use crate::error::ParseError;
+use std::path::PathBuf;
+
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const TIMEZONE: &str = "timezone";
+
+/// A timezone used to render timestamps and to parse local datetimes.
+///
+/// Zones are loaded from the system timezone database (`$TZDIR` or
+/// `/usr/share/zoneinfo`), including the POSIX rule used after the last listed transition.
+///
+/// # Examples
+///
+/// ```
+/// use parser::TimeZone;
+///
+/// let moscow = TimeZone::from_posix("MSK-3").unwrap();
+/// assert_eq!(moscow.format_ts(1633036860000), "2021-10-01T00:21:00.000+03:00");
+/// assert_eq!(moscow.parse_local("2021-10-01 00:21"), Ok(1633036860000));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeZone {
+    name: String,
+    /// UTC seconds at which the offset changes, with the index into `offsets`.
+    transitions: Vec<(i64, usize)>,
+    /// UTC offsets in seconds.
+    offsets: Vec<i32>,
+    rule: Option<PosixRule>,
+}
+
+impl TimeZone {
+    pub fn utc() -> Self {
+        Self {
+            name: "UTC".to_string(),
+            transitions: vec![],
+            offsets: vec![0],
+            rule: None,
+        }
+    }
+
+    /// Loads a zone such as `Europe/Moscow` from the system timezone database.
+    pub fn load(name: &str) -> Result<Self, ParseError> {
+        if name.eq_ignore_ascii_case("UTC") {
+            return Ok(Self::utc());
+        }
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return Err(ParseError::InvalidRawValue {
+                field: TIMEZONE.to_string(),
+                value: name.to_string(),
+            });
+        }
+
+        let dir = std::env::var_os("TZDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(ZONEINFO_DIR));
+        let data = std::fs::read(dir.join(name))
+            .map_err(|err| ParseError::IOError(format!("timezone {}: {}", name, err)))?;
+        Self::from_tzif(name, &data)
+    }
+
+    /// Builds a zone from a POSIX TZ string such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+    pub fn from_posix(rule: &str) -> Result<Self, ParseError> {
+        let rule = PosixRule::parse(rule)?;
+        Ok(Self {
+            name: rule.source.clone(),
+            transitions: vec![],
+            offsets: vec![rule.std_offset],
+            rule: Some(rule),
+        })
+    }
+
+    /// Parses a TZif file (RFC 8536).
+    pub fn from_tzif(name: &str, data: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = TzifReader { data, pos: 0 };
+        let header = reader.header()?;
+        let (header, time_size) = if header.version >= b'2' {
+            reader.skip(reader.data_len(&header, 4)?)?;
+            (reader.header()?, 8)
+        } else {
+            (header, 4)
+        };
+        let data_len = reader.data_len(&header, time_size)?;
+
+        let mut times = Vec::with_capacity(header.time_count);
+        for _ in 0..header.time_count {
+            times.push(reader.int(time_size)?);
+        }
+        let mut indexes = Vec::with_capacity(header.time_count);
+        for _ in 0..header.time_count {
+            indexes.push(reader.int(1)? as usize);
+        }
+        let mut offsets = Vec::with_capacity(header.type_count);
+        for _ in 0..header.type_count {
+            offsets.push(reader.int(4)? as i32);
+            reader.skip(2)?;
+        }
+        if offsets.is_empty() || indexes.iter().any(|index| *index >= offsets.len()) {
+            return Err(ParseError::InvalidRawValue {
+                field: TIMEZONE.to_string(),
+                value: name.to_string(),
+            });
+        }
+        reader.skip(data_len - header.time_count * (time_size + 1) - header.type_count * 6)?;
+
+        let rule = if time_size == 8 {
+            let footer = String::from_utf8_lossy(&data[reader.pos..]);
+            let footer = footer.trim_matches('\n');
+            (!footer.is_empty())
+                .then(|| PosixRule::parse(footer))
+                .transpose()?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            transitions: times.into_iter().zip(indexes).collect(),
+            offsets,
+            rule,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the UTC offset in seconds at the given UTC time.
+    pub fn offset_at(&self, utc_secs: i64) -> i32 {
+        let after_last = self
+            .transitions
+            .last()
+            .is_none_or(|(time, _)| utc_secs >= *time);
+        if let (true, Some(rule)) = (after_last, &self.rule) {
+            return rule.offset_at(utc_secs);
+        }
+
+        match self
+            .transitions
+            .partition_point(|(time, _)| *time <= utc_secs)
+        {
+            0 => self.offsets[0],
+            index => self.offsets[self.transitions[index - 1].1],
+        }
+    }
+
+    /// Renders a timestamp in milliseconds as `YYYY-MM-DDTHH:MM:SS.mmm+HH:MM`.
+    pub fn format_ts(&self, ts: u64) -> String {
+        let secs = (ts / 1000) as i64;
+        let offset = self.offset_at(secs);
+        let local = secs + offset as i64;
+        let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let time = local.rem_euclid(SECONDS_PER_DAY);
+
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{}{:02}:{:02}",
+            year,
+            month,
+            day,
+            time / 3600,
+            time / 60 % 60,
+            time % 60,
+            ts % 1000,
+            if offset < 0 { '-' } else { '+' },
+            offset.abs() / 3600,
+            offset.abs() / 60 % 60,
+        )
+    }
+
+    /// Parses `YYYY-MM-DD[( |T)HH:MM[:SS[.mmm]]]` in this zone into a timestamp in milliseconds.
+    /// Years range from 1 to 9999.
+    ///
+    /// An explicit `Z` or `±HH:MM` suffix takes precedence over the zone. Local times that
+    /// occur twice or not at all around DST changes resolve to the offset in effect before
+    /// the change.
+    pub fn parse_local(&self, s: &str) -> Result<u64, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: TIMESTAMP.to_string(),
+            value: s.to_string(),
+        };
+        let (local_ms, explicit_offset) = parse_datetime(s.trim()).ok_or_else(invalid)?;
+
+        let local_secs = local_ms.div_euclid(1000);
+        let offset = match explicit_offset {
+            Some(offset) => offset,
+            None => {
+                let guess = self.offset_at(local_secs);
+                self.offset_at(local_secs - guess as i64)
+            }
+        };
+
+        u64::try_from(local_ms - offset as i64 * 1000).map_err(|_| invalid())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PosixRule {
+    source: String,
+    std_offset: i32,
+    dst: Option<DstRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DstRule {
+    offset: i32,
+    start: (u32, u32, u32, i64),
+    end: (u32, u32, u32, i64),
+}
+
+impl PosixRule {
+    fn parse(source: &str) -> Result<Self, ParseError> {
+        let invalid = || ParseError::InvalidRawValue {
+            field: TIMEZONE.to_string(),
+            value: source.to_string(),
+        };
+        let mut rest = source;
+
+        skip_name(&mut rest).ok_or_else(invalid)?;
+        // POSIX offsets count hours west of Greenwich.
+        let std_offset = -parse_signed_time(&mut rest).ok_or_else(invalid)? as i32;
+        if rest.is_empty() {
+            return Ok(Self {
+                source: source.to_string(),
+                std_offset,
+                dst: None,
+            });
+        }
+
+        skip_name(&mut rest).ok_or_else(invalid)?;
+        let offset = if rest.starts_with(',') {
+            std_offset + 3600
+        } else {
+            -parse_signed_time(&mut rest).ok_or_else(invalid)? as i32
+        };
+        let start = parse_transition_rule(&mut rest).ok_or_else(invalid)?;
+        let end = parse_transition_rule(&mut rest).ok_or_else(invalid)?;
+        if !rest.is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            source: source.to_string(),
+            std_offset,
+            dst: Some(DstRule { offset, start, end }),
+        })
+    }
+
+    fn offset_at(&self, utc_secs: i64) -> i32 {
+        let Some(dst) = &self.dst else {
+            return self.std_offset;
+        };
+
+        let local = utc_secs + self.std_offset as i64;
+        let (year, _, _) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let start = transition_time(year, dst.start) - self.std_offset as i64;
+        let end = transition_time(year, dst.end) - dst.offset as i64;
+
+        let is_dst = if start < end {
+            start <= utc_secs && utc_secs < end
+        } else {
+            utc_secs < end || start <= utc_secs
+        };
+        if is_dst { dst.offset } else { self.std_offset }
+    }
+}
+
+/// Skips a zone abbreviation, either alphabetic or quoted in `<...>`.
+fn skip_name(rest: &mut &str) -> Option<()> {
+    let len = if let Some(quoted) = rest.strip_prefix('<') {
+        quoted.find('>')? + 2
+    } else {
+        rest.find(|ch: char| !ch.is_ascii_alphabetic())
+            .unwrap_or(rest.len())
+    };
+    if len < 3 {
+        return None;
+    }
+    *rest = &rest[len..];
+    Some(())
+}
+
+/// Parses `[+-]hh[:mm[:ss]]` into seconds.
+fn parse_signed_time(rest: &mut &str) -> Option<i64> {
+    let sign = match rest.chars().next()? {
+        '-' => -1,
+        _ => 1,
+    };
+    let unsigned = rest.trim_start_matches(['+', '-']);
+    let len = unsigned
+        .find(|ch: char| !ch.is_ascii_digit() && ch != ':')
+        .unwrap_or(unsigned.len());
+
+    let mut secs = 0;
+    for (index, part) in unsigned[..len].split(':').enumerate() {
+        if index > 2 || part.is_empty() {
+            return None;
+        }
+        secs += part.parse::<i64>().ok()? * [3600, 60, 1][index];
+    }
+    *rest = &unsigned[len..];
+    Some(sign * secs)
+}
+
+/// Parses `,Mm.w.d[/time]` into (month, week, weekday, seconds after local midnight).
+fn parse_transition_rule(rest: &mut &str) -> Option<(u32, u32, u32, i64)> {
+    let spec = rest.strip_prefix(",M")?;
+    let len = spec
+        .find(|ch: char| !ch.is_ascii_digit() && ch != '.')
+        .unwrap_or(spec.len());
+    let mut parts = spec[..len].split('.').map(|part| part.parse::<u32>().ok());
+    let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+    if parts.next().is_some()
+        || !(1..=12).contains(&month)
+        || !(1..=5).contains(&week)
+        || weekday > 6
+    {
+        return None;
+    }
+
+    *rest = &spec[len..];
+    let time = match rest.strip_prefix('/') {
+        Some(time) => {
+            *rest = time;
+            parse_signed_time(rest)?
+        }
+        None => 2 * 3600,
+    };
+    Some((month, week, weekday, time))
+}
+
+/// Returns local seconds since the epoch of a `Mm.w.d/time` rule in `year`.
+fn transition_time(year: i64, (month, week, weekday, time): (u32, u32, u32, i64)) -> i64 {
+    let first = days_from_civil(year, month, 1);
+    let first_weekday = (first + 4).rem_euclid(7) as u32;
+    let mut day = first + ((weekday + 7 - first_weekday) % 7) as i64 + (week as i64 - 1) * 7;
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    while day >= days_from_civil(next_year, next_month, 1) {
+        day -= 7;
+    }
+    day * SECONDS_PER_DAY + time
+}
+
+/// Parses a datetime into local milliseconds since the epoch and an optional offset.
+fn parse_datetime(s: &str) -> Option<(i64, Option<i32>)> {
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, time.trim_start()),
+        None => (s, "00:00"),
+    };
+
+    let mut date_parts = date.split('-');
+    let year = date_parts.next()?.parse::<i64>().ok()?;
+    let month = date_parts.next()?.parse::<u32>().ok()?;
+    let day = date_parts.next()?.parse::<u32>().ok()?;
+    if date_parts.next().is_some()
+        || !(1..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+    {
+        return None;
+    }
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, Some(0))
+    } else if let Some(pos) = time.rfind(['+', '-']) {
+        let mut offset = &time[pos..];
+        let secs = parse_signed_time(&mut offset)?;
+        if !offset.is_empty() {
+            return None;
+        }
+        (&time[..pos], Some(secs as i32))
+    } else {
+        (time, None)
+    };
+
+    let (time, millis) = match time.split_once('.') {
+        Some((time, fraction)) if (1..=3).contains(&fraction.len()) => {
+            let millis = fraction.parse::<i64>().ok()? * 10i64.pow(3 - fraction.len() as u32);
+            (time, millis)
+        }
+        Some(_) => return None,
+        None => (time, 0),
+    };
+    let mut parts = time.split(':').map(|part| part.parse::<i64>().ok());
+    let hours = parts.next()??;
+    let minutes = parts.next()??;
+    let seconds = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() || hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+
+    let secs =
+        days_from_civil(year, month, day) * SECONDS_PER_DAY + hours * 3600 + minutes * 60 + seconds;
+    Some((secs * 1000 + millis, offset))
+}
+
+struct TzifHeader {
+    version: u8,
+    is_ut_count: usize,
+    is_std_count: usize,
+    leap_count: usize,
+    time_count: usize,
+    type_count: usize,
+    char_count: usize,
+}
+
+impl TzifHeader {
+    /// Length of the data block following the header for the given time size, or `None`
+    /// when it does not fit in a `usize`.
+    fn data_len(&self, time_size: usize) -> Option<usize> {
+        self.time_count
+            .checked_mul(time_size + 1)?
+            .checked_add(self.type_count.checked_mul(6)?)?
+            .checked_add(self.char_count)?
+            .checked_add(self.leap_count.checked_mul(time_size + 4)?)?
+            .checked_add(self.is_std_count)?
+            .checked_add(self.is_ut_count)
+    }
+}
+
+struct TzifReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl TzifReader<'_> {
+    fn header(&mut self) -> Result<TzifHeader, ParseError> {
+        if self.data.get(self.pos..self.pos + 4) != Some(b"TZif") {
+            return Err(ParseError::InvalidMagic("not a TZif file".to_string()));
+        }
+        self.skip(4)?;
+        let version = self.int(1)? as u8;
+        self.skip(15)?;
+
+        Ok(TzifHeader {
+            version,
+            is_ut_count: self.count()?,
+            is_std_count: self.count()?,
+            leap_count: self.count()?,
+            time_count: self.count()?,
+            type_count: self.count()?,
+            char_count: self.count()?,
+        })
+    }
+
+    /// Reads a big-endian unsigned 32-bit count of a header.
+    fn count(&mut self) -> Result<usize, ParseError> {
+        Ok(self.int(4)? as u32 as usize)
+    }
+
+    /// Length of the data block described by `header`, failing when the data is shorter,
+    /// so that counts are checked before anything is allocated for them.
+    fn data_len(&self, header: &TzifHeader, time_size: usize) -> Result<usize, ParseError> {
+        header
+            .data_len(time_size)
+            .filter(|len| *len <= self.data.len() - self.pos)
+            .ok_or(ParseError::UnexpectedEOF)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ParseError> {
+        if self.data.len() - self.pos < len {
+            return Err(ParseError::UnexpectedEOF);
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    /// Reads a big-endian signed integer of 1, 4 or 8 bytes.
+    fn int(&mut self, len: usize) -> Result<i64, ParseError> {
+        let start = self.pos;
+        self.skip(len)?;
+        let bytes = &self.data[start..self.pos];
+        Ok(match len {
+            1 => bytes[0] as i64,
+            4 => i32::from_be_bytes(bytes.try_into().expect("4 bytes")) as i64,
+            _ => i64::from_be_bytes(bytes.try_into().expect("8 bytes")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod time_zone_tests {
+    use super::*;
+
+    /// Builds a TZif v2 file with one transition from UTC to UTC+3 at `at`.
+    fn tzif(at: i64, footer: &str) -> Vec<u8> {
+        let header = |version: u8| {
+            let mut header = b"TZif".to_vec();
+            header.push(version);
+            header.extend_from_slice(&[0; 15]);
+            for count in [0u32, 0, 0, 1, 2, 4] {
+                header.extend_from_slice(&count.to_be_bytes());
+            }
+            header
+        };
+        let types = [0u8, 0, 0, 0, 0, 0, 0, 0, 0x2a, 0x30, 0, 0];
+
+        let mut data = header(b'2');
+        data.extend_from_slice(&(at as i32).to_be_bytes());
+        data.push(1);
+        data.extend_from_slice(&types);
+        data.extend_from_slice(b"UTC\0");
+        data.extend_from_slice(&header(b'2'));
+        data.extend_from_slice(&at.to_be_bytes());
+        data.push(1);
+        data.extend_from_slice(&types);
+        data.extend_from_slice(b"UTC\0");
+        data.extend_from_slice(format!("\n{}\n", footer).as_bytes());
+        data
+    }
+
+    #[test]
+    fn test_from_tzif() {
+        let zone = TimeZone::from_tzif("Test/Zone", &tzif(1000, "MSK-3")).expect("Should parse");
+
+        assert_eq!(zone.offset_at(999), 0);
+        assert_eq!(zone.offset_at(1000), 3 * 3600);
+        assert_eq!(zone.offset_at(2_000_000_000), 3 * 3600);
+        assert_eq!(zone.format_ts(0), "1970-01-01T00:00:00.000+00:00");
+    }
+
+    #[test]
+    fn test_from_tzif_checks_counts() {
+        for version in [0, b'2'] {
+            let mut data = b"TZif".to_vec();
+            data.push(version);
+            data.extend_from_slice(&[0; 15]);
+            for count in [0u32, 0, 0, u32::MAX, 1, 4] {
+                data.extend_from_slice(&count.to_be_bytes());
+            }
+            assert_eq!(
+                TimeZone::from_tzif("Test/Zone", &data),
+                Err(ParseError::UnexpectedEOF)
+            );
+        }
+    }
+
+    #[test]
+    fn test_posix_dst_rule() {
+        let berlin = TimeZone::from_posix("CET-1CEST,M3.5.0,M10.5.0/3").expect("Should parse");
+
+        // 2021-03-28 01:00 UTC starts summer time, 2021-10-31 01:00 UTC ends it.
+        assert_eq!(berlin.offset_at(1616893199), 3600);
+        assert_eq!(berlin.offset_at(1616893200), 7200);
+        assert_eq!(berlin.offset_at(1635641999), 7200);
+        assert_eq!(berlin.offset_at(1635642000), 3600);
+        assert_eq!(
+            berlin.format_ts(1625097600123),
+            "2021-07-01T02:00:00.123+02:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_local() {
+        let berlin = TimeZone::from_posix("CET-1CEST,M3.5.0,M10.5.0/3").expect("Should parse");
+
+        assert_eq!(
+            berlin.parse_local("2021-07-01T02:00:00.123"),
+            Ok(1625097600123)
+        );
+        assert_eq!(berlin.parse_local("2021-01-01 01:00"), Ok(1609459200000));
+        assert_eq!(
+            berlin.parse_local("2021-01-01T00:00:00Z"),
+            Ok(1609459200000)
+        );
+        assert_eq!(
+            berlin.parse_local("2021-01-01 03:00+03:00"),
+            Ok(1609459200000)
+        );
+        assert_eq!(
+            berlin.parse_local("2021-02-30"),
+            Err(ParseError::InvalidRawValue {
+                field: "TIMESTAMP".to_string(),
+                value: "2021-02-30".to_string()
+            })
+        );
+        for oversized in [
+            "10000-01-01",
+            "99999999999999999-01-01",
+            "-99999999999999999-01-01",
+        ] {
+            assert_eq!(
+                berlin.parse_local(oversized),
+                Err(ParseError::InvalidRawValue {
+                    field: "TIMESTAMP".to_string(),
+                    value: oversized.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_rejects_paths() {
+        assert!(matches!(
+            TimeZone::load("../etc/passwd"),
+            Err(ParseError::InvalidRawValue { .. })
+        ));
+        assert_eq!(TimeZone::load("utc"), Ok(TimeZone::utc()));
+    }
+}
@@ -0,0 +1,939 @@
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8] = b"\0asm\x01\0\0\0";
+const CHECK_EXPORT: &str = "check";
+const I32: u8 = 0x7f;
+const I64: u8 = 0x7e;
+/// Parameters of `check`: id, type, from user id, to user id, amount, timestamp, status.
+const CHECK_PARAMS: [u8; 7] = [I64, I32, I64, I64, I64, I64, I32];
+/// Instructions a rule may run per record, so that a rule stuck in a loop fails the
+/// record instead of hanging the read.
+const FUEL: u64 = 1_000_000;
+const MAX_CALL_DEPTH: usize = 64;
+const MAX_LOCALS: usize = 50_000;
+
+fn invalid(reason: impl std::fmt::Display) -> ParseError {
+    ParseError::InvalidFormat(format!("wasm rule: {}", reason))
+}
+
+/// Instructions of the supported subset, with the ends of blocks resolved when loading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instr {
+    Unreachable,
+    Nop,
+    Block {
+        arity: usize,
+        end: usize,
+    },
+    Loop,
+    If {
+        arity: usize,
+        els: Option<usize>,
+        end: usize,
+    },
+    Else {
+        end: usize,
+    },
+    End,
+    Br(usize),
+    BrIf(usize),
+    Return,
+    Call(usize),
+    Drop,
+    Select,
+    LocalGet(usize),
+    LocalSet(usize),
+    LocalTee(usize),
+    GlobalGet(usize),
+    GlobalSet(usize),
+    I32Const(i32),
+    I64Const(i64),
+    /// Comparisons, arithmetic and conversions of `i32` and `i64`, by opcode.
+    Numeric(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FuncType {
+    params: Vec<u8>,
+    results: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Func {
+    ty: usize,
+    /// Parameters and declared locals.
+    locals: usize,
+    body: Vec<Instr>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Module {
+    types: Vec<FuncType>,
+    funcs: Vec<Func>,
+    /// Initial values of the globals; every evaluation starts from them.
+    globals: Vec<u64>,
+    check: usize,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    fn done(&self) -> bool {
+        self.pos == self.bytes.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], ParseError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or(ParseError::UnexpectedEOF)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, ParseError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let mut value = 0u64;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return u32::try_from(value).map_err(|_| invalid("integer out of range"));
+            }
+        }
+        Err(invalid("integer too long"))
+    }
+
+    fn index(&mut self) -> Result<usize, ParseError> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn signed(&mut self, bits: u32) -> Result<i64, ParseError> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= bits.div_ceil(7) * 7 {
+                return Err(invalid("integer too long"));
+            }
+            value |= ((byte & 0x7f) as i64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn value_type(&mut self) -> Result<u8, ParseError> {
+        match self.byte()? {
+            value_type @ (I32 | I64) => Ok(value_type),
+            other => Err(invalid(format!(
+                "value type 0x{:02x} is not supported",
+                other
+            ))),
+        }
+    }
+
+    fn value_types(&mut self) -> Result<Vec<u8>, ParseError> {
+        (0..self.u32()?).map(|_| self.value_type()).collect()
+    }
+}
+
+/// Reads the instructions of a function body up to its final `end`.
+fn read_body(r: &mut Reader) -> Result<Vec<Instr>, ParseError> {
+    let mut body = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    loop {
+        let op = r.byte()?;
+        let arity = |r: &mut Reader| match r.byte()? {
+            0x40 => Ok(0),
+            I32 | I64 => Ok(1),
+            other => Err(invalid(format!(
+                "block type 0x{:02x} is not supported",
+                other
+            ))),
+        };
+        let instr = match op {
+            0x00 => Instr::Unreachable,
+            0x01 => Instr::Nop,
+            0x02..=0x04 => {
+                let arity = arity(r)?;
+                open.push(body.len());
+                match op {
+                    0x02 => Instr::Block { arity, end: 0 },
+                    0x03 => Instr::Loop,
+                    _ => Instr::If {
+                        arity,
+                        els: None,
+                        end: 0,
+                    },
+                }
+            }
+            0x05 => {
+                let position = body.len();
+                match open.last().map(|start| &mut body[*start]) {
+                    Some(Instr::If {
+                        els: els @ None, ..
+                    }) => *els = Some(position),
+                    _ => return Err(invalid("else outside of an if")),
+                }
+                Instr::Else { end: 0 }
+            }
+            0x0b => {
+                let Some(start) = open.pop() else {
+                    body.push(Instr::End);
+                    return Ok(body);
+                };
+                let position = body.len();
+                match &mut body[start] {
+                    Instr::Block { end, .. } => *end = position,
+                    Instr::If { els, end, .. } => {
+                        *end = position;
+                        if let Some(els) = *els {
+                            body[els] = Instr::Else { end: position };
+                        }
+                    }
+                    _ => {}
+                }
+                Instr::End
+            }
+            0x0c | 0x0d => {
+                let depth = r.index()?;
+                if depth > open.len() {
+                    return Err(invalid(format!("branch depth {} is too deep", depth)));
+                }
+                if op == 0x0c {
+                    Instr::Br(depth)
+                } else {
+                    Instr::BrIf(depth)
+                }
+            }
+            0x0f => Instr::Return,
+            0x10 => Instr::Call(r.index()?),
+            0x1a => Instr::Drop,
+            0x1b => Instr::Select,
+            0x20 => Instr::LocalGet(r.index()?),
+            0x21 => Instr::LocalSet(r.index()?),
+            0x22 => Instr::LocalTee(r.index()?),
+            0x23 => Instr::GlobalGet(r.index()?),
+            0x24 => Instr::GlobalSet(r.index()?),
+            0x41 => Instr::I32Const(r.signed(32)? as i32),
+            0x42 => Instr::I64Const(r.signed(64)?),
+            0x45..=0x5a | 0x6a..=0x73 | 0x7c..=0x85 | 0xa7 | 0xac | 0xad => Instr::Numeric(op),
+            _ => {
+                return Err(invalid(format!(
+                    "instruction 0x{:02x} is not supported",
+                    op
+                )));
+            }
+        };
+        body.push(instr);
+    }
+}
+
+/// Reads a constant initializer of a global.
+fn read_const(r: &mut Reader) -> Result<u64, ParseError> {
+    let value = match r.byte()? {
+        0x41 => r.signed(32)? as i32 as u32 as u64,
+        0x42 => r.signed(64)? as u64,
+        _ => return Err(invalid("globals must be initialized with constants")),
+    };
+    if r.byte()? != 0x0b {
+        return Err(invalid("globals must be initialized with constants"));
+    }
+    Ok(value)
+}
+
+impl Module {
+    fn parse(bytes: &[u8]) -> Result<Self, ParseError> {
+        let mut r = Reader::new(bytes);
+        if r.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+            return Err(ParseError::InvalidMagic(
+                "not a WebAssembly 1 module".to_string(),
+            ));
+        }
+
+        let mut types = Vec::new();
+        let mut func_types = Vec::new();
+        let mut globals = Vec::new();
+        let mut check = None;
+        let mut bodies = Vec::new();
+        while !r.done() {
+            let id = r.byte()?;
+            let len = r.index()?;
+            let mut s = Reader::new(r.bytes(len)?);
+            match id {
+                // Custom sections, memories and their data are left alone, as compilers emit
+                // them by default; rules using memory fail at its instructions.
+                0 | 5 | 11 | 12 => continue,
+                1 => {
+                    for _ in 0..s.u32()? {
+                        if s.byte()? != 0x60 {
+                            return Err(invalid("invalid function type"));
+                        }
+                        let params = s.value_types()?;
+                        let results = s.value_types()?;
+                        if results.len() > 1 {
+                            return Err(invalid("functions return at most one value"));
+                        }
+                        types.push(FuncType { params, results });
+                    }
+                }
+                2 => return Err(invalid("imports are not supported, rules run on their own")),
+                3 => {
+                    for _ in 0..s.u32()? {
+                        func_types.push(s.index()?);
+                    }
+                }
+                6 => {
+                    for _ in 0..s.u32()? {
+                        s.value_type()?;
+                        s.byte()?;
+                        globals.push(read_const(&mut s)?);
+                    }
+                }
+                7 => {
+                    for _ in 0..s.u32()? {
+                        let len = s.index()?;
+                        let name = s.bytes(len)?;
+                        let kind = s.byte()?;
+                        let index = s.index()?;
+                        if name == CHECK_EXPORT.as_bytes() && kind == 0 {
+                            check = Some(index);
+                        }
+                    }
+                }
+                10 => {
+                    for _ in 0..s.u32()? {
+                        let len = s.index()?;
+                        let mut f = Reader::new(s.bytes(len)?);
+                        let mut locals = 0usize;
+                        for _ in 0..f.u32()? {
+                            locals = locals.saturating_add(f.index()?);
+                            f.value_type()?;
+                        }
+                        let body = read_body(&mut f)?;
+                        if !f.done() {
+                            return Err(invalid("instructions after the end of a function"));
+                        }
+                        bodies.push((locals, body));
+                    }
+                }
+                _ => return Err(invalid(format!("section {} is not supported", id))),
+            }
+            if !s.done() {
+                return Err(invalid(format!(
+                    "section {} is longer than its content",
+                    id
+                )));
+            }
+        }
+
+        if func_types.len() != bodies.len() {
+            return Err(invalid("functions and bodies do not match"));
+        }
+        let funcs = func_types
+            .into_iter()
+            .zip(bodies)
+            .map(|(ty, (locals, body))| {
+                let params = types
+                    .get(ty)
+                    .ok_or_else(|| invalid("unknown type"))?
+                    .params
+                    .len();
+                Ok(Func {
+                    ty,
+                    locals: params.saturating_add(locals),
+                    body,
+                })
+            })
+            .collect::<Result<Vec<_>, ParseError>>()?;
+        for func in &funcs {
+            if func.locals > MAX_LOCALS {
+                return Err(invalid("too many locals"));
+            }
+            let valid = |instr: &Instr| match *instr {
+                Instr::Call(index) => index < funcs.len(),
+                Instr::LocalGet(index) | Instr::LocalSet(index) | Instr::LocalTee(index) => {
+                    index < func.locals
+                }
+                Instr::GlobalGet(index) | Instr::GlobalSet(index) => index < globals.len(),
+                _ => true,
+            };
+            if !func.body.iter().all(valid) {
+                return Err(invalid("unknown function, local or global"));
+            }
+        }
+
+        let check = check.ok_or_else(|| invalid("no exported check function"))?;
+        let signature = funcs.get(check).map(|func| &types[func.ty]);
+        if signature.is_none_or(|ty| ty.params != CHECK_PARAMS || ty.results != [I32]) {
+            return Err(invalid(
+                "check must take (i64, i32, i64, i64, i64, i64, i32) and return i32",
+            ));
+        }
+        Ok(Self {
+            types,
+            funcs,
+            globals,
+            check,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    /// Stack height when the block was entered.
+    height: usize,
+    /// Values a branch to the label carries.
+    arity: usize,
+    /// Position of the block instruction for loops, of its `end` otherwise.
+    target: usize,
+    is_loop: bool,
+}
+
+/// Values are kept as `u64`, `i32` values zero-extended.
+struct Machine<'a> {
+    module: &'a Module,
+    globals: Vec<u64>,
+    fuel: u64,
+    depth: usize,
+}
+
+fn pop(stack: &mut Vec<u64>) -> Result<u64, String> {
+    stack.pop().ok_or_else(|| "stack underflow".to_string())
+}
+
+/// Moves the top `arity` values down to `height`.
+fn unwind(stack: &mut Vec<u64>, height: usize, arity: usize) -> Result<(), String> {
+    if stack.len() < height + arity {
+        return Err("stack underflow".to_string());
+    }
+    stack.drain(height..stack.len() - arity);
+    Ok(())
+}
+
+/// Runs a numeric instruction on the values on top of `stack`.
+fn numeric(op: u8, stack: &mut Vec<u64>) -> Result<(), String> {
+    let value = match op {
+        0x45 => (pop(stack)? as u32 == 0) as u64,
+        0x50 => (pop(stack)? == 0) as u64,
+        0xa7 => pop(stack)? as u32 as u64,
+        0xac => pop(stack)? as u32 as i32 as i64 as u64,
+        0xad => pop(stack)? as u32 as u64,
+        0x46..=0x4f => {
+            let (b, a) = (pop(stack)? as u32, pop(stack)? as u32);
+            let (sb, sa) = (b as i32, a as i32);
+            (match op {
+                0x46 => a == b,
+                0x47 => a != b,
+                0x48 => sa < sb,
+                0x49 => a < b,
+                0x4a => sa > sb,
+                0x4b => a > b,
+                0x4c => sa <= sb,
+                0x4d => a <= b,
+                0x4e => sa >= sb,
+                _ => a >= b,
+            }) as u64
+        }
+        0x51..=0x5a => {
+            let (b, a) = (pop(stack)?, pop(stack)?);
+            let (sb, sa) = (b as i64, a as i64);
+            (match op {
+                0x51 => a == b,
+                0x52 => a != b,
+                0x53 => sa < sb,
+                0x54 => a < b,
+                0x55 => sa > sb,
+                0x56 => a > b,
+                0x57 => sa <= sb,
+                0x58 => a <= b,
+                0x59 => sa >= sb,
+                _ => a >= b,
+            }) as u64
+        }
+        0x6a..=0x73 => {
+            let (b, a) = (pop(stack)? as u32, pop(stack)? as u32);
+            let (sb, sa) = (b as i32, a as i32);
+            if matches!(op, 0x6d..=0x70) && b == 0 {
+                return Err("integer divide by zero".to_string());
+            }
+            (match op {
+                0x6a => a.wrapping_add(b),
+                0x6b => a.wrapping_sub(b),
+                0x6c => a.wrapping_mul(b),
+                0x6d => sa
+                    .checked_div(sb)
+                    .ok_or_else(|| "integer overflow".to_string())? as u32,
+                0x6e => a / b,
+                0x6f => sa.wrapping_rem(sb) as u32,
+                0x70 => a % b,
+                0x71 => a & b,
+                0x72 => a | b,
+                _ => a ^ b,
+            }) as u64
+        }
+        _ => {
+            let (b, a) = (pop(stack)?, pop(stack)?);
+            let (sb, sa) = (b as i64, a as i64);
+            if matches!(op, 0x7f..=0x82) && b == 0 {
+                return Err("integer divide by zero".to_string());
+            }
+            match op {
+                0x7c => a.wrapping_add(b),
+                0x7d => a.wrapping_sub(b),
+                0x7e => a.wrapping_mul(b),
+                0x7f => sa
+                    .checked_div(sb)
+                    .ok_or_else(|| "integer overflow".to_string())? as u64,
+                0x80 => a / b,
+                0x81 => sa.wrapping_rem(sb) as u64,
+                0x82 => a % b,
+                0x83 => a & b,
+                0x84 => a | b,
+                _ => a ^ b,
+            }
+        }
+    };
+    stack.push(value);
+    Ok(())
+}
+
+impl Machine<'_> {
+    fn call(&mut self, index: usize, mut locals: Vec<u64>) -> Result<Vec<u64>, String> {
+        if self.depth == MAX_CALL_DEPTH {
+            return Err("call stack exhausted".to_string());
+        }
+        self.depth += 1;
+        let module = self.module;
+        let func = &module.funcs[index];
+        let results = module.types[func.ty].results.len();
+        locals.resize(func.locals, 0);
+        let mut stack = Vec::new();
+        let mut labels: Vec<Label> = Vec::new();
+        let mut pc = 0;
+        loop {
+            self.fuel = self.fuel.checked_sub(1).ok_or("out of fuel")?;
+            // Branches to depth `labels.len()` leave the function.
+            let mut branch = None;
+            match func.body[pc] {
+                Instr::Unreachable => return Err("unreachable executed".to_string()),
+                Instr::Nop => {}
+                Instr::Block { arity, end } => labels.push(Label {
+                    height: stack.len(),
+                    arity,
+                    target: end,
+                    is_loop: false,
+                }),
+                Instr::Loop => labels.push(Label {
+                    height: stack.len(),
+                    arity: 0,
+                    target: pc,
+                    is_loop: true,
+                }),
+                Instr::If { arity, els, end } => {
+                    let condition = pop(&mut stack)? as u32;
+                    labels.push(Label {
+                        height: stack.len(),
+                        arity,
+                        target: end,
+                        is_loop: false,
+                    });
+                    if condition == 0 {
+                        // Lands on the `end` when there is no `else`, leaving the block.
+                        pc = els.map_or(end, |els| els + 1);
+                        continue;
+                    }
+                }
+                Instr::Else { end } => {
+                    pc = end;
+                    continue;
+                }
+                Instr::End => {
+                    if labels.pop().is_none() {
+                        break;
+                    }
+                }
+                Instr::Br(depth) => branch = Some(depth),
+                Instr::BrIf(depth) => {
+                    if pop(&mut stack)? as u32 != 0 {
+                        branch = Some(depth);
+                    }
+                }
+                Instr::Return => branch = Some(labels.len()),
+                Instr::Call(callee) => {
+                    let params = module.types[module.funcs[callee].ty].params.len();
+                    if stack.len() < params {
+                        return Err("stack underflow".to_string());
+                    }
+                    let args = stack.split_off(stack.len() - params);
+                    stack.extend(self.call(callee, args)?);
+                }
+                Instr::Drop => {
+                    pop(&mut stack)?;
+                }
+                Instr::Select => {
+                    let condition = pop(&mut stack)? as u32;
+                    let (b, a) = (pop(&mut stack)?, pop(&mut stack)?);
+                    stack.push(if condition != 0 { a } else { b });
+                }
+                Instr::LocalGet(index) => stack.push(locals[index]),
+                Instr::LocalSet(index) => locals[index] = pop(&mut stack)?,
+                Instr::LocalTee(index) => {
+                    locals[index] = *stack.last().ok_or("stack underflow")?;
+                }
+                Instr::GlobalGet(index) => stack.push(self.globals[index]),
+                Instr::GlobalSet(index) => self.globals[index] = pop(&mut stack)?,
+                Instr::I32Const(value) => stack.push(value as u32 as u64),
+                Instr::I64Const(value) => stack.push(value as u64),
+                Instr::Numeric(op) => numeric(op, &mut stack)?,
+            }
+
+            if let Some(depth) = branch {
+                if depth == labels.len() {
+                    break;
+                }
+                let label = labels[labels.len() - 1 - depth];
+                unwind(&mut stack, label.height, label.arity)?;
+                if label.is_loop {
+                    labels.truncate(labels.len() - depth);
+                } else {
+                    labels.truncate(labels.len() - 1 - depth);
+                }
+                pc = label.target + 1;
+                continue;
+            }
+            pc += 1;
+        }
+
+        self.depth -= 1;
+        unwind(&mut stack, 0, results)?;
+        Ok(stack)
+    }
+}
+
+/// A validation rule shipped as a WebAssembly module, checked against every record read
+/// when set in [`crate::ParseOptions::wasm_rules`].
+///
+/// The module exports a function `check` taking the fields of a record and returning 0
+/// when the record passes, and any other value as the code of the violation:
+///
+/// ```text
+/// (func (export "check")
+///   (param $id i64) (param $type i32) (param $from i64) (param $to i64)
+///   (param $amount i64) (param $ts i64) (param $status i32) (result i32) ...)
+/// ```
+///
+/// Types and statuses are passed as [`crate::TransactionType::as_int`] and
+/// [`crate::TransactionStatus::as_int`]; descriptions are not passed. Only integer
+/// instructions, locals, globals, control flow and calls are supported; modules with
+/// imports or using memory, floats or tables are refused. Every evaluation starts from
+/// the initial globals and runs at most a million instructions.
+///
+/// # Examples
+///
+/// ```
+/// use parser::{ParseError, TransactionStatus, TransactionType, WasmRule, YPBankRecord};
+///
+/// // (func (export "check") (param i64 i32 i64 i64 i64 i64 i32) (result i32)
+/// //   (i64.gt_s (local.get 4) (i64.const 100000)))
+/// let module = [
+///     0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+///     0x01, 0x0c, 0x01, 0x60, 0x07, 0x7e, 0x7f, 0x7e, 0x7e, 0x7e, 0x7e, 0x7f, 0x01, 0x7f,
+///     0x03, 0x02, 0x01, 0x00, // function 0 of type 0
+///     0x07, 0x09, 0x01, 0x05, b'c', b'h', b'e', b'c', b'k', 0x00, 0x00,
+///     0x0a, 0x0b, 0x01, 0x09, 0x00, 0x20, 0x04, 0x42, 0xa0, 0x8d, 0x06, 0x55, 0x0b,
+/// ];
+/// let rule = WasmRule::from_bytes("large_amount", &module).unwrap();
+/// let record = |amount| {
+///     YPBankRecord::new(1, TransactionType::Deposit, 0, 7, amount, 10, TransactionStatus::Success, "")
+/// };
+///
+/// assert_eq!(rule.check(&record(100000)), Ok(()));
+/// assert!(matches!(
+///     rule.check(&record(100001)),
+///     Err(ParseError::RuleViolation { id: 1, code: 1, .. })
+/// ));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasmRule {
+    name: String,
+    module: Arc<Module>,
+}
+
+impl WasmRule {
+    /// Loads a rule from the bytes of a WebAssembly module, failing with
+    /// [`ParseError::InvalidFormat`] when it uses anything unsupported.
+    pub fn from_bytes(name: impl Into<String>, bytes: &[u8]) -> Result<Self, ParseError> {
+        Ok(Self {
+            name: name.into(),
+            module: Arc::new(Module::parse(bytes)?),
+        })
+    }
+
+    /// Loads the `.wasm` file at `path`, naming the rule after the file.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        let name = path.file_stem().map_or_else(
+            || path.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        );
+        Self::from_bytes(name, &std::fs::read(path)?)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns what `check` returns for `record`, failing with
+    /// [`ParseError::InvalidFormat`] when the rule traps.
+    pub fn evaluate(&self, record: &YPBankRecord) -> Result<i32, ParseError> {
+        let module = &self.module;
+        let mut machine = Machine {
+            module,
+            globals: module.globals.clone(),
+            fuel: FUEL,
+            depth: 0,
+        };
+        let args = vec![
+            record.id,
+            record.transaction_type.as_int() as u64,
+            record.from_user_id,
+            record.to_user_id,
+            record.amount as u64,
+            record.ts,
+            record.status.as_int() as u64,
+        ];
+        let results = machine.call(module.check, args).map_err(|trap| {
+            invalid(format!(
+                "{} trapped on record {}: {}",
+                self.name, record.id, trap
+            ))
+        })?;
+        Ok(results.first().copied().unwrap_or_default() as u32 as i32)
+    }
+
+    /// Fails with [`ParseError::RuleViolation`] when `check` returns anything but 0.
+    pub fn check(&self, record: &YPBankRecord) -> Result<(), ParseError> {
+        match self.evaluate(record)? {
+            0 => Ok(()),
+            code => Err(ParseError::RuleViolation {
+                id: record.id,
+                rule: self.name.clone(),
+                code,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod wasm_rule_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    /// A function as its type, its locals as `(count, type)` and its body up to its
+    /// final `end`.
+    type TestFunc<'a> = (u8, &'a [(u8, u8)], &'a [u8]);
+
+    /// Builds a module of the given types and functions, exporting function `check` as
+    /// `check`.
+    fn module(types: &[(&[u8], &[u8])], funcs: &[TestFunc], check: u8) -> Vec<u8> {
+        fn section(id: u8, count: usize, content: Vec<u8>, module: &mut Vec<u8>) {
+            let mut body = vec![count as u8];
+            body.extend(content);
+            module.push(id);
+            module.push(body.len() as u8);
+            module.extend(body);
+        }
+        let mut bytes = MAGIC.to_vec();
+        let mut content = Vec::new();
+        for (params, results) in types {
+            content.push(0x60);
+            content.push(params.len() as u8);
+            content.extend(*params);
+            content.push(results.len() as u8);
+            content.extend(*results);
+        }
+        section(1, types.len(), content, &mut bytes);
+        section(
+            3,
+            funcs.len(),
+            funcs.iter().map(|func| func.0).collect(),
+            &mut bytes,
+        );
+        section(
+            7,
+            1,
+            [&[5][..], &b"check"[..], &[0, check][..]].concat(),
+            &mut bytes,
+        );
+        let mut content = Vec::new();
+        for (_, locals, body) in funcs {
+            let mut code = vec![locals.len() as u8];
+            for (count, value_type) in *locals {
+                code.extend([*count, *value_type]);
+            }
+            code.extend(*body);
+            content.push(code.len() as u8);
+            content.extend(code);
+        }
+        section(10, funcs.len(), content, &mut bytes);
+        bytes
+    }
+
+    const CHECK: (&[u8], &[u8]) = (&CHECK_PARAMS, &[I32]);
+
+    fn record(transaction_type: TransactionType, amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            9,
+            transaction_type,
+            if transaction_type == TransactionType::Deposit {
+                0
+            } else {
+                7
+            },
+            8,
+            amount,
+            1633046460000,
+            TransactionStatus::Pending,
+            "",
+        )
+    }
+
+    #[test]
+    fn test_blocks_and_calls() {
+        // check: 3 for transfers with more than 5 digits(amount), 0 otherwise, where
+        // digits (function 1) counts decimal digits in a loop.
+        let check: &[u8] = &[
+            0x20, 0x01, 0x41, 0x01, 0x46, // type == 1
+            0x04, 0x7f, // if (result i32)
+            0x20, 0x04, 0x10, 0x01, 0x41, 0x05, 0x4a, // digits(amount) > 5
+            0x04, 0x7f, 0x41, 0x03, 0x05, 0x41, 0x00, 0x0b, // if 3 else 0
+            0x05, 0x41, 0x00, // else 0
+            0x0b, 0x0b,
+        ];
+        let digits: &[u8] = &[
+            0x02, 0x40, 0x03, 0x40, // block loop
+            0x20, 0x01, 0x41, 0x01, 0x6a, 0x21, 0x01, // count += 1
+            0x20, 0x00, 0x42, 0x0a, 0x7f, 0x22, 0x00, // n /= 10
+            0x50, 0x0d, 0x01, // br_if 1 when n == 0
+            0x0c, 0x00, // br 0
+            0x0b, 0x0b, 0x20, 0x01, 0x0b,
+        ];
+        let bytes = module(
+            &[CHECK, (&[I64], &[I32])],
+            &[(0, &[], check), (1, &[(1, I32)], digits)],
+            0,
+        );
+        let rule = WasmRule::from_bytes("digits", &bytes).expect("Should load rule");
+
+        assert_eq!(
+            rule.evaluate(&record(TransactionType::Transfer, 123456)),
+            Ok(3)
+        );
+        assert_eq!(
+            rule.evaluate(&record(TransactionType::Transfer, 12345)),
+            Ok(0)
+        );
+        assert_eq!(
+            rule.evaluate(&record(TransactionType::Deposit, 123456)),
+            Ok(0)
+        );
+        assert_eq!(
+            rule.check(&record(TransactionType::Transfer, 999999)),
+            Err(ParseError::RuleViolation {
+                id: 9,
+                rule: "digits".to_string(),
+                code: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_traps_and_invalid_modules() {
+        let endless = module(
+            &[CHECK],
+            &[(0, &[], &[0x03, 0x40, 0x0c, 0x00, 0x0b, 0x00, 0x0b])],
+            0,
+        );
+        let divide = module(
+            &[CHECK],
+            &[(0, &[], &[0x41, 0x01, 0x41, 0x00, 0x6d, 0x0b])],
+            0,
+        );
+        let memory = module(
+            &[CHECK],
+            &[(0, &[], &[0x41, 0x00, 0x28, 0x02, 0x00, 0x0b])],
+            0,
+        );
+        let signature = module(&[(&[I64], &[I32])], &[(0, &[], &[0x41, 0x00, 0x0b])], 0);
+
+        let record = record(TransactionType::Transfer, 100);
+        for (bytes, trap) in [(endless, "out of fuel"), (divide, "integer divide by zero")] {
+            let rule = WasmRule::from_bytes("trap", &bytes).expect("Should load rule");
+            assert!(matches!(
+                rule.evaluate(&record),
+                Err(ParseError::InvalidFormat(reason)) if reason.ends_with(trap)
+            ));
+        }
+        assert!(WasmRule::from_bytes("memory", &memory).is_err());
+        assert!(WasmRule::from_bytes("signature", &signature).is_err());
+        assert!(matches!(
+            WasmRule::from_bytes("text", b"(module)"),
+            Err(ParseError::InvalidMagic(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_with_rules() {
+        use crate::{CommonParser, Format, ParseOptions};
+
+        // Refuses pending records with code 7.
+        let pending = module(
+            &[CHECK],
+            &[(
+                0,
+                &[],
+                &[0x20, 0x06, 0x41, 0x02, 0x46, 0x41, 0x07, 0x6c, 0x0b],
+            )],
+            0,
+        );
+        let options = ParseOptions {
+            wasm_rules: vec![WasmRule::from_bytes("pending", &pending).expect("Should load rule")],
+            skip_invalid_records: true,
+            ..ParseOptions::default()
+        };
+        let mut data = Vec::new();
+        CommonParser::new(Format::Csv)
+            .write_to(&mut data, &crate::fixtures::records())
+            .expect("Should write records");
+
+        let read: Vec<_> = CommonParser::with_options(Format::Csv, options)
+            .iter_read(data.as_slice())
+            .expect("Should read header")
+            .collect();
+        let refused = read.iter().filter(|record| record.is_err()).count();
+        assert_eq!((read.len(), refused), (20, 5));
+        assert!(
+            read.iter()
+                .any(|record| matches!(record, Err(ParseError::RuleViolation { code: 7, .. })))
+        );
+    }
+}
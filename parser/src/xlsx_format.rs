@@ -0,0 +1,348 @@
+use crate::error::ParseError;
+use crate::record::YPBankRecord;
+use std::fmt::Write;
+
+const HEADER: [&str; 8] = [
+    "TX_ID",
+    "TX_TYPE",
+    "FROM_USER_ID",
+    "TO_USER_ID",
+    "AMOUNT",
+    "TIMESTAMP",
+    "STATUS",
+    "DESCRIPTION",
+];
+const COLUMNS: [char; 8] = ['A', 'B', 'C', 'D', 'E', 'F', 'G', 'H'];
+
+/// Largest integer a spreadsheet can store as a number without losing precision (2^53).
+const MAX_EXACT_NUMBER: u64 = 1 << 53;
+/// Rows of an Excel sheet, the header row included.
+const MAX_ROWS: usize = 1_048_576;
+
+const CONTENT_TYPES: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+    r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+    r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+    r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+    r#"<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+    r#"</Types>"#,
+);
+const ROOT_RELS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>"#,
+    r#"</Relationships>"#,
+);
+const WORKBOOK: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+    r#"<sheets><sheet name="Transactions" sheetId="1" r:id="rId1"/></sheets>"#,
+    r#"</workbook>"#,
+);
+const WORKBOOK_RELS: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>"#,
+    r#"</Relationships>"#,
+);
+const SHEET_START: &str = concat!(
+    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+    r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#,
+    r#"<sheetViews><sheetView workbookViewId="0">"#,
+    r#"<pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/>"#,
+    r#"</sheetView></sheetViews>"#,
+    r#"<sheetData>"#,
+);
+const SHEET_END: &str = "</sheetData></worksheet>";
+
+/// Writes records as a single-sheet XLSX workbook.
+///
+/// The sheet has a frozen header row. Ids are stored as text so that spreadsheet tools
+/// cannot round them to floating point; amounts and timestamps are stored as numbers
+/// unless they are too large to be represented exactly.
+///
+/// A sheet holds at most 1,048,575 records below its header; more fail with
+/// [`ParseError::InconsistentRecord`] before anything is written. Control characters
+/// other than tab and line breaks cannot be stored in XLSX and are dropped from
+/// descriptions.
+pub struct XlsxWriter {}
+
+impl XlsxWriter {
+    pub fn write_to<W: std::io::Write>(
+        w: &mut W,
+        records: &[YPBankRecord],
+    ) -> Result<(), ParseError> {
+        if records.len() >= MAX_ROWS {
+            return Err(ParseError::InconsistentRecord(format!(
+                "{} records do not fit in a sheet of {} rows",
+                records.len(),
+                MAX_ROWS
+            )));
+        }
+
+        let mut zip = StoredZipWriter::new(w);
+        zip.add_file("[Content_Types].xml", CONTENT_TYPES.as_bytes())?;
+        zip.add_file("_rels/.rels", ROOT_RELS.as_bytes())?;
+        zip.add_file("xl/workbook.xml", WORKBOOK.as_bytes())?;
+        zip.add_file("xl/_rels/workbook.xml.rels", WORKBOOK_RELS.as_bytes())?;
+        zip.add_file("xl/worksheets/sheet1.xml", Self::sheet(records).as_bytes())?;
+        zip.finish()
+    }
+
+    fn sheet(records: &[YPBankRecord]) -> String {
+        let mut sheet = String::from(SHEET_START);
+
+        sheet.push_str(r#"<row r="1">"#);
+        for (column, name) in COLUMNS.iter().zip(HEADER) {
+            Self::push_text_cell(&mut sheet, *column, 1, name);
+        }
+        sheet.push_str("</row>");
+
+        for (index, record) in records.iter().enumerate() {
+            let row = index + 2;
+            let _ = write!(sheet, r#"<row r="{}">"#, row);
+            Self::push_text_cell(&mut sheet, 'A', row, &record.id.to_string());
+            Self::push_text_cell(&mut sheet, 'B', row, record.transaction_type.as_str());
+            Self::push_text_cell(&mut sheet, 'C', row, &record.from_user_id.to_string());
+            Self::push_text_cell(&mut sheet, 'D', row, &record.to_user_id.to_string());
+            Self::push_number_cell(
+                &mut sheet,
+                'E',
+                row,
+                record.amount.unsigned_abs(),
+                &record.amount.to_string(),
+            );
+            Self::push_number_cell(&mut sheet, 'F', row, record.ts, &record.ts.to_string());
+            Self::push_text_cell(&mut sheet, 'G', row, record.status.as_str());
+            Self::push_text_cell(&mut sheet, 'H', row, &record.description);
+            sheet.push_str("</row>");
+        }
+
+        sheet.push_str(SHEET_END);
+        sheet
+    }
+
+    fn push_text_cell(sheet: &mut String, column: char, row: usize, value: &str) {
+        let _ = write!(
+            sheet,
+            r#"<c r="{}{}" t="inlineStr"><is><t xml:space="preserve">"#,
+            column, row
+        );
+        escape_xml(value, sheet);
+        sheet.push_str("</t></is></c>");
+    }
+
+    fn push_number_cell(sheet: &mut String, column: char, row: usize, magnitude: u64, value: &str) {
+        if magnitude > MAX_EXACT_NUMBER {
+            Self::push_text_cell(sheet, column, row, value);
+            return;
+        }
+
+        let _ = write!(sheet, r#"<c r="{}{}"><v>{}</v></c>"#, column, row, value);
+    }
+}
+
+/// Escapes text for XML content, dropping characters XML 1.0 cannot represent.
+fn escape_xml(value: &str, out: &mut String) {
+    for ch in value.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(ch),
+            ch if ch.is_control() => {}
+            ch => out.push(ch),
+        }
+    }
+}
+
+/// Computes the CRC-32 (IEEE) checksum used by ZIP archives.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// A minimal ZIP archive writer storing entries without compression.
+struct StoredZipWriter<'a, W: std::io::Write> {
+    w: &'a mut W,
+    offset: u32,
+    central_directory: Vec<u8>,
+    entries: u16,
+}
+
+impl<'a, W: std::io::Write> StoredZipWriter<'a, W> {
+    const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+    const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+    const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+    const VERSION: u16 = 20;
+    /// Bit 11: file names are UTF-8.
+    const FLAGS: u16 = 1 << 11;
+
+    fn new(w: &'a mut W) -> Self {
+        Self {
+            w,
+            offset: 0,
+            central_directory: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    fn add_file(&mut self, name: &str, data: &[u8]) -> Result<(), ParseError> {
+        let too_large = || ParseError::InconsistentRecord("workbook is too large".to_string());
+        let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+        let crc = crc32(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&Self::LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        Self::push_entry_fields(&mut header, name, crc, size);
+        header.extend_from_slice(name.as_bytes());
+
+        let mut central = Vec::with_capacity(46 + name.len());
+        central.extend_from_slice(&Self::CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        central.extend_from_slice(&Self::VERSION.to_le_bytes());
+        Self::push_entry_fields(&mut central, name, crc, size);
+        central.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        central.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&self.offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+
+        self.w.write_all(&header)?;
+        self.w.write_all(data)?;
+
+        self.offset = self
+            .offset
+            .checked_add(header.len() as u32)
+            .and_then(|offset| offset.checked_add(size))
+            .ok_or_else(too_large)?;
+        self.central_directory.extend_from_slice(&central);
+        self.entries += 1;
+
+        Ok(())
+    }
+
+    /// Fields shared by local and central headers, from "version needed" to "extra length".
+    fn push_entry_fields(buf: &mut Vec<u8>, name: &str, crc: u32, size: u32) {
+        buf.extend_from_slice(&Self::VERSION.to_le_bytes());
+        buf.extend_from_slice(&Self::FLAGS.to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes()); // stored
+        buf.extend_from_slice(&0u16.to_le_bytes()); // modification time
+        buf.extend_from_slice(&0x0021u16.to_le_bytes()); // modification date: 1980-01-01
+        buf.extend_from_slice(&crc.to_le_bytes());
+        buf.extend_from_slice(&size.to_le_bytes()); // compressed size
+        buf.extend_from_slice(&size.to_le_bytes()); // uncompressed size
+        buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        buf.extend_from_slice(&0u16.to_le_bytes()); // extra length
+    }
+
+    fn finish(self) -> Result<(), ParseError> {
+        let mut end = Vec::with_capacity(22);
+        end.extend_from_slice(&Self::END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        end.extend_from_slice(&0u16.to_le_bytes()); // disk with central directory
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&self.entries.to_le_bytes());
+        end.extend_from_slice(&(self.central_directory.len() as u32).to_le_bytes());
+        end.extend_from_slice(&self.offset.to_le_bytes());
+        end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+
+        self.w.write_all(&self.central_directory)?;
+        self.w.write_all(&end)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod xlsx_writer_tests {
+    use super::*;
+    use crate::common::{TransactionStatus, TransactionType};
+
+    fn record(amount: i64) -> YPBankRecord {
+        YPBankRecord::new(
+            1000000000000000,
+            TransactionType::Deposit,
+            0,
+            9223372036854775807,
+            amount,
+            1633036860000,
+            TransactionStatus::Success,
+            "<Tom & Jerry>".to_string(),
+        )
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_sheet_typed_cells() {
+        let sheet = XlsxWriter::sheet(&[record(-100), record(i64::MAX)]);
+
+        assert!(sheet.contains(
+            r#"<pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/>"#
+        ));
+        assert!(
+            sheet.contains(
+                r#"<c r="A1" t="inlineStr"><is><t xml:space="preserve">TX_ID</t></is></c>"#
+            )
+        );
+        assert!(sheet.contains(
+            r#"<c r="A2" t="inlineStr"><is><t xml:space="preserve">1000000000000000</t></is></c>"#
+        ));
+        assert!(sheet.contains(r#"<c r="E2"><v>-100</v></c>"#));
+        assert!(sheet.contains(r#"<c r="F2"><v>1633036860000</v></c>"#));
+        assert!(sheet.contains(r#"<c r="E3" t="inlineStr"><is><t xml:space="preserve">9223372036854775807</t></is></c>"#));
+        assert!(sheet.contains("&lt;Tom &amp; Jerry&gt;"));
+    }
+
+    #[test]
+    fn test_write_to_zip_structure() {
+        let mut written = Vec::new();
+        XlsxWriter::write_to(&mut written, &[record(100)]).expect("Should write");
+
+        assert_eq!(&written[0..4], &[0x50, 0x4b, 0x03, 0x04]);
+
+        let end = &written[written.len() - 22..];
+        assert_eq!(&end[0..4], &[0x50, 0x4b, 0x05, 0x06]);
+        assert_eq!(u16::from_le_bytes([end[10], end[11]]), 5);
+
+        let directory_offset = u32::from_le_bytes([end[16], end[17], end[18], end[19]]) as usize;
+        assert_eq!(
+            &written[directory_offset..directory_offset + 4],
+            &[0x50, 0x4b, 0x01, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_write_to_too_many_rows() {
+        let mut written = Vec::new();
+        let result = XlsxWriter::write_to(&mut written, &vec![record(100); MAX_ROWS]);
+
+        assert_eq!(
+            result,
+            Err(ParseError::InconsistentRecord(
+                "1048576 records do not fit in a sheet of 1048576 rows".to_string()
+            ))
+        );
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn test_sheet_drops_control_characters() {
+        let mut record = record(1);
+        record.description = "a\u{1}b\tc".into();
+
+        assert!(XlsxWriter::sheet(&[record]).contains(">ab\tc<"));
+    }
+}